@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 i_uv;
+
+layout(location = 0) out vec4 o_color;
+
+layout(set = 0, binding = 1) uniform sampler u_sampler;
+layout(set = 0, binding = 2) uniform texture2D u_texture;
+
+void main() {
+    o_color = texture(sampler2D(u_texture, u_sampler), i_uv);
+    // Don't hide what is behind the transparent pixels in the depth buffer
+    if(o_color.a == 0.0) {
+        discard;
+    }
+}
@@ -0,0 +1,16 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Transform {
+    mat4 u_transform;
+};
+
+layout(location = 0) in vec3 i_position;
+layout(location = 1) in vec2 i_uv;
+
+layout(location = 0) out vec2 o_uv;
+
+void main() {
+    gl_Position = u_transform * vec4(i_position, 1.0);
+
+    o_uv = i_uv;
+}
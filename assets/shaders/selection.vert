@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec3 a_Pos;
+layout(location = 1) in vec4 a_Color;
+
+layout(location = 0) out vec4 v_Color;
+
+layout(set = 0, binding = 0) uniform Temp1 { mat4 u_ViewProj; };
+
+void main() {
+    v_Color = a_Color;
+    gl_Position = u_ViewProj * vec4(a_Pos, 1.0);
+}
@@ -0,0 +1,17 @@
+#version 450
+
+layout(location = 0) in vec2 i_uv;
+
+layout(location = 0) out vec4 o_color;
+
+layout(set = 0, binding = 1) uniform sampler u_sampler;
+layout(set = 0, binding = 2) uniform texture2D u_texture;
+
+void main() {
+    vec4 color = texture(sampler2D(u_texture, u_sampler), i_uv);
+    // only the text is drawn, the face of the sign is visible around it
+    if (color.a < 0.5) {
+        discard;
+    }
+    o_color = vec4(color.rgb, 1.0);
+}
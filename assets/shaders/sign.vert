@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec3 i_position;
+layout(location = 1) in vec2 i_uv;
+
+layout(set = 0, binding = 0) uniform Transform {
+    mat4 u_view_proj;
+};
+
+layout(location = 0) out vec2 o_uv;
+
+void main() {
+    o_uv = i_uv;
+    gl_Position = u_view_proj * vec4(i_position, 1.0);
+}
@@ -6,21 +6,51 @@ layout(location = 2) flat in vec2 i_texture_top_left;
 layout(location = 3) flat in vec2 i_texture_size;
 layout(location = 4) flat in vec2 i_texture_max_uv;
 layout(location = 5) in vec2 i_texture_uv;
-layout(location = 6) flat in float i_light_level;
+layout(location = 6) in float i_light_level;
+layout(location = 7) flat in vec3 i_tint;
+layout(location = 8) in vec3 i_position;
+layout(location = 9) flat in uint i_portal;
+layout(location = 10) flat in uint i_flicker;
 
 layout(location = 0) out vec4 o_color;
 
+layout(set = 0, binding = 0) uniform Transform {
+    mat4 u_view_proj;
+    // position of the light held by the player, and its level
+    vec4 u_hand_light;
+    // seconds since the start of the rendering in x, brightness of the flickering glow in y
+    vec4 u_time;
+};
 layout(set = 0, binding = 1) uniform sampler u_sampler;
 layout(set = 0, binding = 2) uniform texture2D u_texture_atlas;
+// the light emitted by the textures, at the same place as the textures
+layout(set = 0, binding = 3) uniform texture2D u_emissive_atlas;
 
 const vec3 SUN_DIRECTION = normalize(vec3(0, 1, 0.5));
 const float SUN_FRACTION = 0.1;
 const vec2 EPSILON = vec2(1e-7, 1e-7);
+// turns per second of the swirl of the portals, at their center
+const float PORTAL_SWIRL_SPEED = 0.25;
+
+// rotate the uv around the center of the texture, faster close to the center
+vec2 swirl(vec2 uv) {
+    vec2 center = i_texture_size * 0.5;
+    vec2 offset = uv - center;
+    float strength = 1.0 - clamp(length(offset / i_texture_size) * 2.0, 0.0, 1.0);
+    float angle = 6.2831853 * PORTAL_SWIRL_SPEED * u_time.x * strength;
+    float c = cos(angle);
+    float s = sin(angle);
+    return center + mat2(c, s, -s, c) * offset;
+}
 
 void main() {
     /* TEXTURE ACCESS */
     // avoid going out of bounds when multisampling is enabled
     vec2 corrected_uv = clamp(i_texture_uv, EPSILON, i_texture_max_uv - EPSILON);
+    // the portals swirl within their texture
+    if (i_portal == 1u) {
+        corrected_uv = clamp(swirl(corrected_uv), EPSILON, i_texture_max_uv - EPSILON);
+    }
     // compute the texture gradients before texture wrapping
     vec2 x_derivative = dFdx(corrected_uv);
     vec2 y_derivative = dFdy(corrected_uv);
@@ -28,12 +58,32 @@ void main() {
     vec2 actual_uv = i_texture_top_left + mod(corrected_uv, i_texture_size);
     // get texture value
     vec4 tex_color = textureGrad(sampler2D(u_texture_atlas, u_sampler), actual_uv, x_derivative, y_derivative);
+    // the transparent pixels of the textures, for example between the rungs of the ladders
+    if (tex_color.a < 0.5) {
+        discard;
+    }
+    vec4 emissive = textureGrad(sampler2D(u_emissive_atlas, u_sampler), actual_uv, x_derivative, y_derivative);
 
     /* VARIOUS BRIGHTNESS FACTORS */
-    float light_factor = pow(0.8, 15.0 - i_light_level);
+    // the held light decreases by one level per block, like the light of the blocks
+    float hand_light_level = u_hand_light.w - distance(i_position, u_hand_light.xyz);
+    float light_level = max(i_light_level, hand_light_level);
+    // the portals glow
+    if (i_portal == 1u) {
+        light_level = 15.0;
+    }
+    float light_factor = pow(0.8, 15.0 - light_level);
     float normal_factor = 1.0 - SUN_FRACTION + SUN_FRACTION * dot(i_norm, SUN_DIRECTION);
     float total_factor = light_factor * i_occl * normal_factor;
 
+    /* GLOW */
+    // the glowing pixels are added after the lighting, so that they stay bright in the dark
+    float glow_factor = emissive.a;
+    if (i_flicker == 1u) {
+        glow_factor *= u_time.y;
+    }
+
     /* OUTPUT */
-    o_color = vec4(total_factor, total_factor, total_factor, 1.0) * tex_color;
+    o_color = vec4(total_factor * i_tint, 1.0) * tex_color;
+    o_color.rgb = min(o_color.rgb + emissive.rgb * glow_factor, vec3(1.0));
 }
@@ -10,9 +10,17 @@ layout(location = 5) in uint i_occl_and_face;
 // light: 4 bits
 // occl: 2 bits
 // face: 3 bits
+// portal: 1 bit, above the light
+// flicker: 1 bit, above the portal
+// biome color, red in the lowest byte
+layout(location = 6) in uint i_tint;
 
 layout(set = 0, binding = 0) uniform Transform {
     mat4 u_view_proj;
+    // position of the light held by the player, and its level
+    vec4 u_hand_light;
+    // seconds since the start of the rendering in x, brightness of the flickering glow in y
+    vec4 u_time;
 };
 
 layout(location = 0) flat out vec3 o_norm;
@@ -21,7 +29,12 @@ layout(location = 2) flat out vec2 o_texture_top_left;
 layout(location = 3) flat out vec2 o_texture_size;
 layout(location = 4) flat out vec2 o_texture_max_uv;
 layout(location = 5) out vec2 o_texture_uv;
-layout(location = 6) flat out float o_light_level;
+// the light is smoothed across the faces, like the ambient occlusion
+layout(location = 6) out float o_light_level;
+layout(location = 7) flat out vec3 o_tint;
+layout(location = 8) out vec3 o_position;
+layout(location = 9) flat out uint o_portal;
+layout(location = 10) flat out uint o_flicker;
 
 vec3 get_normal(uint id) {
     if(id == 0u) {
@@ -56,6 +69,8 @@ void main() {
     uint light_level = (i_occl_and_face & 0x000001E0u) >> 5;
     uint occl_code = (i_occl_and_face & 0x00000018u) >> 3;
     uint face_index = (i_occl_and_face & 0x00000007u) >> 0;
+    o_portal = (i_occl_and_face & 0x00000200u) >> 9;
+    o_flicker = (i_occl_and_face & 0x00000400u) >> 10;
 
     o_norm = get_normal(face_index);
     o_occl = get_occl(occl_code);
@@ -64,6 +79,8 @@ void main() {
     o_texture_max_uv = i_texture_max_uv;
     o_texture_uv = i_texture_uv;
     o_light_level = float(light_level);
+    o_tint = vec3(i_tint & 0xFFu, (i_tint >> 8) & 0xFFu, (i_tint >> 16) & 0xFFu) / 255.0;
+    o_position = i_position;
 
     gl_Position = u_view_proj * vec4(i_position, 1.0);
 }
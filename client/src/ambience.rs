@@ -0,0 +1,290 @@
+//! The ambience: looping environmental sounds chosen from the surroundings of the camera, that crossfade
+//! when the surroundings change, and random one-shot sounds over them like the water dripping in the caves.
+//! The surroundings are sampled from the loaded world a few times per second, with a few hundred block queries.
+//!
+//! There is no audio output yet: the volumes of the beds are only reported in the debug info.
+
+use crate::world::World;
+use nalgebra::Point3;
+use std::time::{SystemTime, UNIX_EPOCH};
+use voxel_rs_common::{
+    debug::send_debug_info, physics::BlockContainer, world::BlockPos, worldgen::biome::Biome,
+};
+
+/// Seconds between two samples of the surroundings of the camera
+const SAMPLE_INTERVAL: f32 = 0.25;
+/// Seconds for a bed to fade from silent to full volume
+const CROSSFADE_DURATION: f32 = 3.0;
+/// The beds that fade below this volume are stopped
+const SILENT_VOLUME: f32 = 0.01;
+/// Horizontal offsets of the columns above the camera that are checked for a ceiling
+const CEILING_OFFSETS: [(i64, i64); 5] = [(0, 0), (4, 0), (-4, 0), (0, 4), (0, -4)];
+/// Number of blocks above the camera that are checked for a ceiling
+const CEILING_HEIGHT: i64 = 32;
+/// Minimum number of full blocks above a column for it to be covered, so that the trees are not caves
+const CEILING_MIN_BLOCKS: usize = 4;
+/// Distance and spacing of the blocks around the camera that are checked for water
+const WATER_RADIUS: i64 = 6;
+const WATER_STEP: usize = 2;
+/// Number of sampled fluid blocks for the water to play at full volume
+const WATER_FULL_COUNT: usize = 12;
+/// Altitudes where the wind starts to blow and where it plays at full volume
+const WIND_MIN_ALTITUDE: f64 = 48.0;
+const WIND_MAX_ALTITUDE: f64 = 112.0;
+
+/// A looping environmental sound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmbienceBed {
+    Cave,
+    Water,
+    Wind,
+    Biome(Biome),
+}
+
+impl AmbienceBed {
+    pub fn sound(self) -> &'static str {
+        match self {
+            Self::Cave => "ambience.cave",
+            Self::Water => "ambience.water",
+            Self::Wind => "ambience.wind",
+            Self::Biome(Biome::Plains) => "ambience.plains",
+            Self::Biome(Biome::Forest) => "ambience.forest",
+            Self::Biome(Biome::Swamp) => "ambience.swamp",
+            Self::Biome(Biome::Savanna) => "ambience.savanna",
+            Self::Biome(Biome::Taiga) => "ambience.taiga",
+        }
+    }
+}
+
+/// A one-shot sound played at random times over a bed, while the bed is loud enough
+struct Stinger {
+    sound: &'static str,
+    bed: AmbienceBed,
+    min_volume: f32,
+    /// Average number of seconds between two plays
+    mean_interval: f32,
+}
+
+const STINGERS: [Stinger; 2] = [
+    Stinger {
+        sound: "ambience.cave_drip",
+        bed: AmbienceBed::Cave,
+        min_volume: 0.5,
+        mean_interval: 6.0,
+    },
+    Stinger {
+        sound: "ambience.wind_gust",
+        bed: AmbienceBed::Wind,
+        min_volume: 0.5,
+        mean_interval: 15.0,
+    },
+];
+
+/// The surroundings of the camera that choose the beds, with weights between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AmbienceContext {
+    /// How covered the camera is
+    pub underground: f32,
+    /// How much water there is around the camera
+    pub water: f32,
+    /// How high the camera is
+    pub altitude: f32,
+    /// The biome of the camera, if its biome map is loaded
+    pub biome: Option<Biome>,
+}
+
+impl AmbienceContext {
+    /// Sample the loaded world around the camera
+    pub fn sample(camera: Point3<f64>, world: &World) -> Self {
+        let camera_block = BlockPos::from(camera);
+        let around = |dx: i64, dy: i64, dz: i64| {
+            BlockPos::from((
+                camera_block.px + dx,
+                camera_block.py + dy,
+                camera_block.pz + dz,
+            ))
+        };
+        let covered_columns = CEILING_OFFSETS
+            .iter()
+            .filter(|&&(dx, dz)| {
+                (1..=CEILING_HEIGHT)
+                    .filter(|&dy| world.is_block_full(around(dx, dy, dz)))
+                    .count()
+                    >= CEILING_MIN_BLOCKS
+            })
+            .count();
+        let mut water_blocks = 0;
+        for dx in (-WATER_RADIUS..=WATER_RADIUS).step_by(WATER_STEP) {
+            for dy in (-WATER_RADIUS..=WATER_RADIUS).step_by(WATER_STEP) {
+                for dz in (-WATER_RADIUS..=WATER_RADIUS).step_by(WATER_STEP) {
+                    if world.is_block_fluid(around(dx, dy, dz)) {
+                        water_blocks += 1;
+                    }
+                }
+            }
+        }
+        let altitude = (camera.y - WIND_MIN_ALTITUDE) / (WIND_MAX_ALTITUDE - WIND_MIN_ALTITUDE);
+        Self {
+            underground: covered_columns as f32 / CEILING_OFFSETS.len() as f32,
+            water: (water_blocks as f32 / WATER_FULL_COUNT as f32).min(1.0),
+            altitude: altitude.max(0.0).min(1.0) as f32,
+            biome: world.get_biome(camera_block),
+        }
+    }
+
+    /// The volume that every bed fades to. The wind and the biome are muffled underground.
+    fn target_volumes(&self) -> Vec<(AmbienceBed, f32)> {
+        let outside = 1.0 - self.underground;
+        let mut volumes = vec![
+            (AmbienceBed::Cave, self.underground),
+            (AmbienceBed::Water, self.water),
+            (AmbienceBed::Wind, self.altitude * outside),
+        ];
+        if let Some(biome) = self.biome {
+            volumes.push((AmbienceBed::Biome(biome), (1.0 - self.altitude) * outside));
+        }
+        volumes
+    }
+}
+
+/// A xorshift generator for the times of the stingers, which don't need a good randomness
+struct XorShift(u64);
+
+impl XorShift {
+    /// A random number in `]0, 1]`
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        ((self.0 >> 40) + 1) as f32 / (1u64 << 24) as f32
+    }
+}
+
+pub struct Ambience {
+    /// The beds that are playing, with their volume
+    beds: Vec<(AmbienceBed, f32)>,
+    context: AmbienceContext,
+    /// Seconds until the next sample of the surroundings
+    next_sample: f32,
+    /// Seconds until the next play of every stinger, counted down while its bed is loud enough
+    stinger_delays: [f32; STINGERS.len()],
+    rng: XorShift,
+}
+
+impl Ambience {
+    pub fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        let mut rng = XorShift(seed | 1);
+        let mut stinger_delays = [0.0; STINGERS.len()];
+        for (delay, stinger) in stinger_delays.iter_mut().zip(STINGERS.iter()) {
+            *delay = random_interval(&mut rng, stinger.mean_interval);
+        }
+        Self {
+            beds: Vec::new(),
+            context: AmbienceContext::default(),
+            next_sample: 0.0,
+            stinger_delays,
+            rng,
+        }
+    }
+
+    /// Advance the ambience by `dt` seconds. Return the stingers to play.
+    pub fn update(&mut self, camera: Point3<f64>, world: &World, dt: f32) -> Vec<&'static str> {
+        self.next_sample -= dt;
+        if self.next_sample <= 0.0 {
+            self.next_sample = SAMPLE_INTERVAL;
+            self.context = AmbienceContext::sample(camera, world);
+        }
+        let targets = self.context.target_volumes();
+        self.fade_beds(&targets, dt);
+
+        let mut stingers = Vec::new();
+        for (i, stinger) in STINGERS.iter().enumerate() {
+            if self.volume(stinger.bed) < stinger.min_volume {
+                continue;
+            }
+            self.stinger_delays[i] -= dt;
+            if self.stinger_delays[i] <= 0.0 {
+                self.stinger_delays[i] = random_interval(&mut self.rng, stinger.mean_interval);
+                stingers.push(stinger.sound);
+            }
+        }
+
+        let playing: Vec<_> = self
+            .beds
+            .iter()
+            .map(|(bed, volume)| format!("{} {:.2}", bed.sound(), volume))
+            .collect();
+        send_debug_info(
+            "Audio",
+            "ambience",
+            format!("Ambience: {}", playing.join(", ")),
+        );
+        stingers
+    }
+
+    /// Current volume of a bed, 0 if it is not playing
+    pub fn volume(&self, bed: AmbienceBed) -> f32 {
+        self.beds
+            .iter()
+            .find(|(playing, _)| *playing == bed)
+            .map_or(0.0, |(_, volume)| *volume)
+    }
+
+    /// Move the volume of every bed towards its target, the beds without a target fading out
+    fn fade_beds(&mut self, targets: &[(AmbienceBed, f32)], dt: f32) {
+        for &(bed, _) in targets.iter() {
+            if !self.beds.iter().any(|(playing, _)| *playing == bed) {
+                self.beds.push((bed, 0.0));
+            }
+        }
+        let step = dt / CROSSFADE_DURATION;
+        for (bed, volume) in self.beds.iter_mut() {
+            let target = targets
+                .iter()
+                .find(|(target_bed, _)| target_bed == bed)
+                .map_or(0.0, |(_, target)| *target);
+            *volume = if *volume < target {
+                (*volume + step).min(target)
+            } else {
+                (*volume - step).max(target)
+            };
+        }
+        self.beds.retain(|(_, volume)| *volume >= SILENT_VOLUME);
+    }
+}
+
+/// A random number of seconds until the next play of a stinger, exponentially distributed
+fn random_interval(rng: &mut XorShift, mean_interval: f32) -> f32 {
+    -mean_interval * rng.next_f32().ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossfade() {
+        let mut ambience = Ambience::new();
+        let plains = AmbienceBed::Biome(Biome::Plains);
+        let outside = [(plains, 1.0), (AmbienceBed::Cave, 0.0)];
+        let cave = [(AmbienceBed::Cave, 1.0)];
+
+        ambience.fade_beds(&outside, CROSSFADE_DURATION / 2.0);
+        assert_eq!(ambience.volume(plains), 0.5);
+        ambience.fade_beds(&outside, CROSSFADE_DURATION);
+        assert_eq!(ambience.volume(plains), 1.0);
+        // The silent beds are not kept
+        assert_eq!(ambience.beds.len(), 1);
+
+        // Entering a cave fades the biome out while the cave fades in
+        ambience.fade_beds(&cave, CROSSFADE_DURATION / 4.0);
+        assert_eq!(ambience.volume(plains), 0.75);
+        assert_eq!(ambience.volume(AmbienceBed::Cave), 0.25);
+        ambience.fade_beds(&cave, CROSSFADE_DURATION);
+        assert_eq!(ambience.volume(plains), 0.0);
+        assert_eq!(ambience.beds, vec![(AmbienceBed::Cave, 1.0)]);
+    }
+}
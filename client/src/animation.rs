@@ -0,0 +1,89 @@
+//! Animation of the entity models, according to what the server says the entities are doing
+
+use nalgebra::{Translation3, UnitQuaternion, Vector3};
+use std::collections::HashMap;
+use std::time::Instant;
+use voxel_rs_common::{
+    animation::{AnimatedModel, EntityActivity},
+    player::PlayerId,
+};
+
+use crate::render::Model;
+
+/// The animation that an entity is playing, and when it started
+struct EntityAnimation {
+    activity: EntityActivity,
+    since: Instant,
+}
+
+pub struct Animations {
+    entities: HashMap<PlayerId, EntityAnimation>,
+}
+
+impl Animations {
+    pub fn new() -> Self {
+        Self {
+            entities: HashMap::new(),
+        }
+    }
+
+    /// Update the activities of the entities, restarting the animation of the entities whose activity changed
+    pub fn update_activities(&mut self, activities: HashMap<PlayerId, EntityActivity>) {
+        self.entities.retain(|id, _| activities.contains_key(id));
+        let now = Instant::now();
+        for (id, activity) in activities {
+            let animation = self.entities.entry(id).or_insert(EntityAnimation {
+                activity,
+                since: now,
+            });
+            if animation.activity != activity {
+                *animation = EntityAnimation {
+                    activity,
+                    since: now,
+                };
+            }
+        }
+    }
+
+    /// Get the models of the parts of an entity, posed by its current animation.
+    /// `model` is the position, scale and rotation of the whole entity.
+    pub fn pose_parts(
+        &self,
+        id: PlayerId,
+        animated_model: &AnimatedModel,
+        model: &Model,
+    ) -> Vec<Model> {
+        let (animation, time) = match self.entities.get(&id) {
+            Some(entity) => (
+                animated_model
+                    .animations
+                    .get(entity.activity.animation_name()),
+                entity.since.elapsed().as_secs_f32(),
+            ),
+            None => (None, 0.0),
+        };
+        animated_model
+            .parts
+            .iter()
+            .map(|part| {
+                let transform = animation
+                    .map(|animation| animation.sample(&part.name, time))
+                    .unwrap_or_default();
+                let [rx, ry, rz] = transform.rotation;
+                let rotation = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), rz.to_radians())
+                    * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), ry.to_radians())
+                    * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), rx.to_radians());
+                let pivot = Vector3::from(part.pivot);
+                // Move the part to its place in the model, then rotate it around its pivot
+                let pose = Translation3::from(pivot + Vector3::from(transform.translation))
+                    * rotation
+                    * Translation3::from(Vector3::from(part.offset) - pivot);
+                Model {
+                    mesh_id: part.mesh_id,
+                    pose: model.pose * pose,
+                    ..*model
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,156 @@
+//! The sounds of the game: footsteps on the terrain, and blocks being broken and placed.
+//! The sounds are chosen from the sound material of the blocks.
+//!
+//! The ambience around the camera is managed by the `Ambience`.
+//!
+//! There is no audio output yet: the played sounds are only reported in the debug info.
+
+use std::time::{Duration, Instant};
+use voxel_rs_common::{
+    block::{
+        sound::{SoundMaterial, SoundSet},
+        Block, BlockId,
+    },
+    debug::send_debug_info,
+    physics::player::PhysicsPlayer,
+    registry::Registry,
+    world::BlockPos,
+};
+
+use crate::ambience::Ambience;
+use crate::world::World;
+use nalgebra::Point3;
+
+/// Horizontal distance between two footsteps
+const STEP_DISTANCE: f64 = 1.8;
+/// How long to wait for the server to change a watched block
+const WATCH_DURATION: Duration = Duration::from_secs(10);
+
+/// The kinds of sounds that a block makes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockSound {
+    Footstep,
+    Break,
+    Place,
+}
+
+/// A block that the player is breaking or placing, with the block that was there before
+struct WatchedBlock {
+    pos: BlockPos,
+    previous: BlockId,
+    since: Instant,
+}
+
+pub struct Audio {
+    /// Horizontal distance walked since the last footstep
+    walked_distance: f64,
+    last_position: Option<(f64, f64)>,
+    watched_blocks: Vec<WatchedBlock>,
+    ambience: Ambience,
+    /// The sounds of the sound materials of the blocks
+    sound_materials: Registry<SoundSet>,
+}
+
+impl Audio {
+    pub fn new(sound_materials: Registry<SoundSet>) -> Self {
+        Self {
+            walked_distance: 0.0,
+            last_position: None,
+            watched_blocks: Vec::new(),
+            ambience: Ambience::new(),
+            sound_materials,
+        }
+    }
+
+    /// Play the footsteps of the player, with the sound of the block below their feet
+    pub fn update_footsteps(
+        &mut self,
+        player: &PhysicsPlayer,
+        world: &World,
+        block_registry: &Registry<Block>,
+    ) {
+        let position = player.position();
+        let horizontal = (position.x, position.z);
+        let moved = match self.last_position.replace(horizontal) {
+            Some((x, z)) => ((horizontal.0 - x).powi(2) + (horizontal.1 - z).powi(2)).sqrt(),
+            None => 0.0,
+        };
+        if !player.is_on_ground(world) || player.is_in_fluid(world) {
+            self.walked_distance = 0.0;
+            return;
+        }
+        self.walked_distance += moved;
+        if self.walked_distance >= STEP_DISTANCE {
+            self.walked_distance -= STEP_DISTANCE;
+            let below = BlockPos::from(position - nalgebra::Vector3::new(0.0, 0.1, 0.0));
+            let block = world.get_block(below);
+            if block != 0 {
+                self.play_block_sound(BlockSound::Footstep, block, block_registry);
+            }
+        }
+    }
+
+    /// Wait for the server to break a block or to place a block at `pos`, to play the matching sound
+    pub fn watch_block(&mut self, pos: BlockPos, world: &World) {
+        self.watched_blocks.retain(|watched| watched.pos != pos);
+        self.watched_blocks.push(WatchedBlock {
+            pos,
+            previous: world.get_block(pos),
+            since: Instant::now(),
+        });
+    }
+
+    /// Play the sounds of the watched blocks that were changed by the server
+    pub fn update_watched_blocks(&mut self, world: &World, block_registry: &Registry<Block>) {
+        let mut changes = Vec::new();
+        self.watched_blocks.retain(|watched| {
+            let block = world.get_block(watched.pos);
+            if block != watched.previous {
+                changes.push((watched.previous, block));
+                false
+            } else {
+                watched.since.elapsed() < WATCH_DURATION
+            }
+        });
+        for (previous, block) in changes {
+            if block == 0 {
+                self.play_block_sound(BlockSound::Break, previous, block_registry);
+            } else {
+                self.play_block_sound(BlockSound::Place, block, block_registry);
+            }
+        }
+    }
+
+    /// Crossfade the ambience to the surroundings of the camera, and play its stingers
+    pub fn update_ambience(&mut self, camera: Point3<f64>, world: &World, dt: f32) {
+        for stinger in self.ambience.update(camera, world, dt) {
+            self.play(stinger);
+        }
+    }
+
+    fn play_block_sound(
+        &self,
+        sound: BlockSound,
+        block: BlockId,
+        block_registry: &Registry<Block>,
+    ) {
+        let material = block_registry
+            .get_value_by_id(block as u32)
+            .map_or(SoundMaterial::FALLBACK, |block| block.sound_material);
+        let sound_set = match self.sound_materials.get_value_by_id(material.0) {
+            Some(sound_set) => sound_set,
+            None => return,
+        };
+        let name = match sound {
+            BlockSound::Footstep => &sound_set.footstep,
+            BlockSound::Break => &sound_set.break_block,
+            BlockSound::Place => &sound_set.place_block,
+        };
+        self.play(name);
+    }
+
+    fn play(&self, name: &str) {
+        log::debug!("Playing sound {}", name);
+        send_debug_info("Audio", "lastsound", format!("Last sound: {}", name));
+    }
+}
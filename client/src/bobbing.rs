@@ -0,0 +1,60 @@
+//! The view bobbing: the camera sways up and down with the steps of the player
+use crate::settings::AccessibilityConfig;
+use crate::world::World;
+use nalgebra::Vector3;
+use voxel_rs_common::physics::player::PhysicsPlayer;
+
+/// Walked distance of a full sway, two steps
+const CYCLE_DISTANCE: f64 = 3.2;
+/// Vertical amplitude of the sway in blocks
+const AMPLITUDE: f64 = 0.05;
+/// How fast the sway fades in and out when the player starts or stops walking, per second
+const FADE_SPEED: f64 = 6.0;
+
+#[derive(Default)]
+pub struct ViewBobbing {
+    /// Position in the cycle, in radians
+    phase: f64,
+    /// Current strength of the sway, between 0 and 1
+    strength: f64,
+    last_position: Option<(f64, f64)>,
+}
+
+impl ViewBobbing {
+    /// Advance the sway with the horizontal movement of the player on the ground
+    pub fn update(
+        &mut self,
+        player: &PhysicsPlayer,
+        world: &World,
+        config: &AccessibilityConfig,
+        seconds_delta: f64,
+    ) {
+        let position = player.position();
+        let horizontal = (position.x, position.z);
+        let moved = match self.last_position.replace(horizontal) {
+            Some((x, z)) => ((horizontal.0 - x).powi(2) + (horizontal.1 - z).powi(2)).sqrt(),
+            None => 0.0,
+        };
+        let walking = config.view_bobbing
+            && moved > 1e-3
+            && player.is_on_ground(world)
+            && !player.is_in_fluid(world);
+        let target = if walking { 1.0 } else { 0.0 };
+        let max_change = FADE_SPEED * seconds_delta;
+        self.strength += (target - self.strength).max(-max_change).min(max_change);
+        if walking {
+            self.phase = (self.phase + moved / CYCLE_DISTANCE * std::f64::consts::TAU)
+                % std::f64::consts::TAU;
+        }
+        if self.strength == 0.0 {
+            self.phase = 0.0;
+        }
+    }
+
+    /// Offset of the camera from the eyes of the player
+    pub fn offset(&self) -> Vector3<f64> {
+        // Two bounces per cycle, one per step
+        let height = -(self.phase * 2.0).cos() * 0.5 + 0.5;
+        Vector3::new(0.0, -AMPLITUDE * self.strength * height, 0.0)
+    }
+}
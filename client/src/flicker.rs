@@ -0,0 +1,62 @@
+//! The flicker of the flames: the glow of the flame blocks follows a smooth noise over time, the same for all the
+//! flames so that it can be sent to the shader once per frame.
+use crate::settings::AccessibilityConfig;
+
+/// Changes of the flicker per second
+const FREQUENCY: f64 = 8.0;
+/// Highest change of the brightness of the glow, as a fraction of the full glow
+const AMPLITUDE: f64 = 0.25;
+/// The frequency and the amplitude when the flicker is limited, see `AccessibilityConfig::limit_flicker`
+const LIMITED_FREQUENCY: f64 = 3.0;
+const LIMITED_AMPLITUDE: f64 = 0.08;
+
+/// Random value between 0 and 1 for the integer `n`
+fn hash(n: i64) -> f64 {
+    let mut x = n as u64;
+    x = (x ^ (x >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x = (x ^ (x >> 33)).wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^= x >> 33;
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Value noise between 0 and 1, smoothly interpolated between random values at the integers
+fn value_noise(t: f64) -> f64 {
+    let i = t.floor();
+    let f = t - i;
+    let smooth = f * f * (3.0 - 2.0 * f);
+    let (a, b) = (hash(i as i64), hash(i as i64 + 1));
+    a + (b - a) * smooth
+}
+
+/// Brightness factor of the glow of the flames at `seconds` since the start of the rendering
+pub fn flame_flicker(seconds: f64, config: &AccessibilityConfig) -> f32 {
+    let (frequency, amplitude) = if config.limit_flicker {
+        (LIMITED_FREQUENCY, LIMITED_AMPLITUDE)
+    } else {
+        (FREQUENCY, AMPLITUDE)
+    };
+    (1.0 - amplitude * value_noise(seconds * frequency)) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flame_flicker() {
+        let config = AccessibilityConfig::default();
+        let limited = AccessibilityConfig {
+            limit_flicker: true,
+            ..AccessibilityConfig::default()
+        };
+        for i in 0..1000 {
+            let seconds = i as f64 * 0.013;
+            let flicker = flame_flicker(seconds, &config) as f64;
+            assert!(flicker <= 1.0 && flicker >= 1.0 - AMPLITUDE - 1e-6);
+            let flicker = flame_flicker(seconds, &limited) as f64;
+            assert!(flicker <= 1.0 && flicker >= 1.0 - LIMITED_AMPLITUDE - 1e-6);
+        }
+        // The noise is continuous
+        assert!((value_noise(2.999_999) - value_noise(3.0)).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use voxel_rs_common::boss_bar::{BossBar, BossBarStyle};
+
+const BAR_WIDTH: i32 = 400;
+const BAR_HEIGHT: i32 = 8;
+const TEXT_HEIGHT: i32 = 20;
+/// Vertical distance between the tops of two bars
+const BAR_SPACING: i32 = TEXT_HEIGHT + BAR_HEIGHT + 8;
+/// Distance between the first bar and the top of the screen
+const TOP_MARGIN: i32 = 10;
+/// Width of the gaps between the segments of a notched bar
+const NOTCH_WIDTH: i32 = 2;
+/// How fast the shown progress follows the progress sent by the server, per second
+const PROGRESS_SPEED: f64 = 8.0;
+
+/// A bar sent by the server, with the progress that is shown while it moves to the new value
+#[derive(Debug, Clone)]
+pub struct DisplayedBossBar {
+    pub bar: BossBar,
+    shown_progress: f32,
+}
+
+impl DisplayedBossBar {
+    /// A new bar is shown full and then goes down to its progress
+    pub fn new(bar: BossBar) -> Self {
+        Self {
+            bar,
+            shown_progress: 1.0,
+        }
+    }
+
+    /// Move the shown progress towards the progress of the bar
+    pub fn update(&mut self, seconds_delta: f64) {
+        let step = 1.0 - (-seconds_delta * PROGRESS_SPEED).exp();
+        self.shown_progress += (self.bar.progress - self.shown_progress) * step as f32;
+    }
+}
+
+/// Draw the bars at the top of the screen, one below the other in the order of their ids
+pub fn render_boss_bars(
+    gui: &mut super::Gui,
+    width: i32,
+    bars: &BTreeMap<String, DisplayedBossBar>,
+) {
+    let x = (width - BAR_WIDTH) / 2;
+    for (i, displayed) in bars.values().enumerate() {
+        let y = TOP_MARGIN + i as i32 * BAR_SPACING;
+        gui.text(
+            x,
+            y,
+            TEXT_HEIGHT,
+            displayed.bar.title.clone(),
+            gui.theme.text,
+            0.01,
+        );
+        let bar_y = y + TEXT_HEIGHT + 2;
+        gui.primitives.draw_rect(
+            x,
+            bar_y,
+            BAR_WIDTH,
+            BAR_HEIGHT,
+            gui.theme.bar_background,
+            0.02,
+        );
+        let filled = (BAR_WIDTH as f32 * displayed.shown_progress.min(1.0).max(0.0)) as i32;
+        let color = displayed.bar.color.rgba();
+        match displayed.bar.style {
+            BossBarStyle::Solid => {
+                gui.primitives
+                    .draw_rect(x, bar_y, filled, BAR_HEIGHT, color, 0.01);
+            }
+            BossBarStyle::Notched(segments) => {
+                let segments = segments.max(1) as i32;
+                for segment in 0..segments {
+                    let start = segment * BAR_WIDTH / segments;
+                    let end = ((segment + 1) * BAR_WIDTH / segments - NOTCH_WIDTH).min(filled);
+                    if end > start {
+                        gui.primitives.draw_rect(
+                            x + start,
+                            bar_y,
+                            end - start,
+                            BAR_HEIGHT,
+                            color,
+                            0.01,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_animation() {
+        let mut bar = BossBar::new("Boss".to_owned());
+        bar.progress = 0.2;
+        let mut displayed = DisplayedBossBar::new(bar);
+        displayed.update(0.05);
+        assert!(displayed.shown_progress < 1.0 && displayed.shown_progress > 0.2);
+        for _ in 0..100 {
+            displayed.update(0.05);
+        }
+        assert!((displayed.shown_progress - 0.2).abs() < 1e-3);
+    }
+}
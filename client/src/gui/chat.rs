@@ -0,0 +1,52 @@
+const TEXT_HEIGHT: i32 = 20;
+const CHAT_WIDTH: i32 = 600;
+const CHAT_MARGIN: i32 = 10;
+
+/// Draw the chat messages in the bottom-left corner of the screen, the most recent at the bottom,
+/// with the line being typed below them if the chat is open
+pub fn render_chat<'a>(
+    gui: &mut super::Gui,
+    height: i32,
+    messages: impl DoubleEndedIterator<Item = &'a str>,
+    input: Option<&str>,
+) {
+    let mut y = height - CHAT_MARGIN - TEXT_HEIGHT;
+    if let Some(input) = input {
+        gui.primitives.draw_rect(
+            CHAT_MARGIN,
+            y,
+            CHAT_WIDTH,
+            TEXT_HEIGHT,
+            gui.theme.panel,
+            0.02,
+        );
+        gui.text(
+            CHAT_MARGIN + 4,
+            y,
+            TEXT_HEIGHT,
+            format!("{}_", input),
+            gui.theme.text,
+            0.01,
+        );
+        y -= TEXT_HEIGHT + 4;
+    }
+    for message in messages.rev() {
+        gui.primitives.draw_rect(
+            CHAT_MARGIN,
+            y,
+            CHAT_WIDTH,
+            TEXT_HEIGHT,
+            gui.theme.panel,
+            0.02,
+        );
+        gui.text(
+            CHAT_MARGIN + 4,
+            y,
+            TEXT_HEIGHT,
+            message.to_owned(),
+            gui.theme.text,
+            0.01,
+        );
+        y -= TEXT_HEIGHT;
+    }
+}
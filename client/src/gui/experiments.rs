@@ -5,13 +5,14 @@ const ELEMENT_OFFSET: i32 = 25;
 
 pub fn render_debug_info(gui: &mut super::Gui, debug_info: &mut DebugInfo) {
     let debug_info = debug_info.get_debug_info();
+    let text_color = gui.theme.debug_text;
     let x = 4;
     let mut y = 4;
     for (section, (displayed, id, messages)) in debug_info {
         let section_text = format!("{} debug info", section.to_uppercase());
         if gui
             .button(*id, x, y, 400, ELEMENT_HEIGHT)
-            .text(section_text, [1.0, 1.0, 1.0, 1.0])
+            .text(section_text, text_color)
             .build()
         {
             *displayed = !*displayed;
@@ -22,14 +23,7 @@ pub fn render_debug_info(gui: &mut super::Gui, debug_info: &mut DebugInfo) {
                 match part {
                     DebugInfoPart::Message(message) => {
                         for line in message.lines() {
-                            gui.text(
-                                x + 10,
-                                y,
-                                ELEMENT_HEIGHT,
-                                line.to_owned(),
-                                [1.0, 1.0, 1.0, 1.0],
-                                0.02,
-                            );
+                            gui.text(x + 10, y, ELEMENT_HEIGHT, line.to_owned(), text_color, 0.02);
                             y += ELEMENT_HEIGHT;
                         }
                     }
@@ -42,7 +36,7 @@ pub fn render_debug_info(gui: &mut super::Gui, debug_info: &mut DebugInfo) {
                             perf.efficiency * 100.0,
                             perf.pending,
                         );
-                        gui.text(x + 10, y, ELEMENT_HEIGHT, text, [1.0, 1.0, 1.0, 1.0], 0.02);
+                        gui.text(x + 10, y, ELEMENT_HEIGHT, text, text_color, 0.02);
                         y += ELEMENT_HEIGHT;
                     }
                     DebugInfoPart::PerfBreakdown(name, breakdown) => {
@@ -51,13 +45,13 @@ pub fn render_debug_info(gui: &mut super::Gui, debug_info: &mut DebugInfo) {
                             y,
                             ELEMENT_HEIGHT,
                             format!("{} performance breakdown", name),
-                            [1.0, 1.0, 1.0, 1.0],
+                            text_color,
                             0.02,
                         );
                         y += ELEMENT_HEIGHT;
                         for (text, percents) in breakdown {
                             let text = format!("{:3.0}% of time: {}", *percents * 100.0, text);
-                            gui.text(x + 20, y, ELEMENT_HEIGHT, text, [1.0, 1.0, 1.0, 1.0], 0.02);
+                            gui.text(x + 20, y, ELEMENT_HEIGHT, text, text_color, 0.02);
                             y += ELEMENT_HEIGHT;
                         }
                     }
@@ -0,0 +1,93 @@
+//! Heatmap of a statistic of the loaded chunks seen from above, to find the parts of the world that cause
+//! performance problems
+use std::collections::{HashMap, HashSet};
+use voxel_rs_common::debug::ChunkMetric;
+use voxel_rs_common::world::ChunkPosXZ;
+
+const TEXT_HEIGHT: i32 = 20;
+const BORDER_WIDTH: i32 = 2;
+/// Color of the loaded columns that don't have a value
+const NO_VALUE_COLOR: [f32; 4] = [0.15, 0.15, 0.15, 0.8];
+const PLAYER_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Color of a value between 0 and 1: blue for the lowest values, then green, then red for the highest values
+fn heat_color(t: f32) -> [f32; 4] {
+    let t = t.max(0.0).min(1.0);
+    if t < 0.5 {
+        let u = 2.0 * t;
+        [0.0, u, 1.0 - u, 0.8]
+    } else {
+        let u = 2.0 * t - 1.0;
+        [u, 1.0 - u, 0.0, 0.8]
+    }
+}
+
+/// Draw the heatmap of `metric` in the square whose top-left corner is at `(x, y)`.
+/// It shows the chunk columns at most `radius` columns away from `center`, colored relatively to the highest
+/// value of the visible loaded columns.
+pub fn render_chunk_heatmap(
+    gui: &mut super::Gui,
+    x: i32,
+    y: i32,
+    size: i32,
+    metric: ChunkMetric,
+    values: &HashMap<ChunkPosXZ, f64>,
+    loaded_columns: &HashSet<ChunkPosXZ>,
+    center: ChunkPosXZ,
+    radius: i64,
+) {
+    let columns = 2 * radius + 1;
+    let cell_size = (size / columns as i32).max(1);
+    let size = cell_size * columns as i32;
+    gui.primitives.draw_rect(
+        x - BORDER_WIDTH,
+        y - BORDER_WIDTH,
+        size + 2 * BORDER_WIDTH,
+        size + 2 * BORDER_WIDTH,
+        gui.theme.panel_border,
+        0.6,
+    );
+    gui.primitives
+        .draw_rect(x, y, size, size, gui.theme.panel, 0.55);
+
+    let visible = |column: &ChunkPosXZ| {
+        (column.px - center.px).abs() <= radius && (column.pz - center.pz).abs() <= radius
+    };
+    let max_value = loaded_columns
+        .iter()
+        .filter(|column| visible(column))
+        .filter_map(|column| values.get(column))
+        .fold(0.0f64, |max, &value| max.max(value));
+    for column in loaded_columns.iter().filter(|column| visible(column)) {
+        let color = match values.get(column) {
+            Some(&value) if max_value > 0.0 => heat_color((value / max_value) as f32),
+            _ => NO_VALUE_COLOR,
+        };
+        let cell_x = x + (column.px - center.px + radius) as i32 * cell_size;
+        let cell_y = y + (column.pz - center.pz + radius) as i32 * cell_size;
+        gui.primitives
+            .draw_rect(cell_x, cell_y, cell_size, cell_size, color, 0.5);
+    }
+
+    // The column of the player is outlined
+    let center_x = x + radius as i32 * cell_size;
+    let center_y = y + radius as i32 * cell_size;
+    gui.primitives.draw_rect(
+        center_x - 1,
+        center_y - 1,
+        cell_size + 2,
+        cell_size + 2,
+        PLAYER_COLOR,
+        0.52,
+    );
+
+    let text_color = gui.theme.debug_text;
+    gui.text(
+        x,
+        y + size + BORDER_WIDTH,
+        TEXT_HEIGHT,
+        format!("{}, max {:.1}", metric.name(), max_value),
+        text_color,
+        0.02,
+    );
+}
@@ -0,0 +1,99 @@
+use crate::screen_effects::ScreenEffects;
+
+const BAR_WIDTH: i32 = 60;
+const BAR_HEIGHT: i32 = 6;
+/// Distance between the center of the screen and the progress bar
+const BAR_OFFSET: i32 = 20;
+const PROMPT_WIDTH: i32 = 400;
+const PROMPT_TEXT_HEIGHT: i32 = 20;
+/// Number of frames of the underground vignette, and width of the vignette in percent of the screen size
+const VIGNETTE_FRAMES: i32 = 8;
+const VIGNETTE_SIZE_PERCENT: i32 = 30;
+
+/// Draw the progress of the block being broken below the crosshair. `fraction` is between 0 and 1.
+pub fn render_break_progress(gui: &mut super::Gui, width: i32, height: i32, fraction: f32) {
+    let x = (width - BAR_WIDTH) / 2;
+    let y = height / 2 + BAR_OFFSET;
+    gui.primitives
+        .draw_rect(x, y, BAR_WIDTH, BAR_HEIGHT, gui.theme.bar_background, 0.02);
+    let filled = (BAR_WIDTH as f32 * fraction.min(1.0).max(0.0)) as i32;
+    gui.primitives
+        .draw_rect(x, y, filled, BAR_HEIGHT, gui.theme.bar_fill, 0.01);
+}
+
+/// Cover the screen with the color of the teleportation, `fraction` going from 0 when the player was just
+/// teleported to 1 when the flash is over
+pub fn render_teleport_flash(gui: &mut super::Gui, width: i32, height: i32, fraction: f32) {
+    let mut color = gui.theme.teleport_flash;
+    color[3] *= 1.0 - fraction.min(1.0).max(0.0);
+    gui.primitives.draw_rect(0, 0, width, height, color, 0.1);
+}
+
+/// Draw the effects of the surroundings of the camera over the whole screen, under the rest of the GUI
+pub fn render_screen_effects(
+    gui: &mut super::Gui,
+    width: i32,
+    height: i32,
+    effects: &ScreenEffects,
+) {
+    let overlays = [
+        (gui.theme.underwater_tint, effects.underwater()),
+        (gui.theme.damage_flash, effects.damage()),
+        (gui.theme.darkness, effects.suffocation()),
+    ];
+    for &(mut color, intensity) in overlays.iter() {
+        if intensity > 0.0 {
+            color[3] *= intensity;
+            gui.primitives.draw_rect(0, 0, width, height, color, 0.15);
+        }
+    }
+    // The vignette is a stack of frames, each one narrower than the previous one, so that the darkness grows
+    // towards the edges of the screen
+    if effects.underground() > 0.0 {
+        let mut color = gui.theme.darkness;
+        color[3] *= effects.underground() / VIGNETTE_FRAMES as f32;
+        let step = width.min(height) * VIGNETTE_SIZE_PERCENT / 100 / VIGNETTE_FRAMES;
+        for frame in 1..=VIGNETTE_FRAMES {
+            let border = frame * step;
+            let rects = [
+                (0, 0, width, border),
+                (0, height - border, width, border),
+                (0, border, border, height - 2 * border),
+                (width - border, border, border, height - 2 * border),
+            ];
+            for &(x, y, w, h) in rects.iter() {
+                gui.primitives.draw_rect(x, y, w, h, color, 0.15);
+            }
+        }
+    }
+}
+
+/// Draw a text prompt in the middle of the screen, with the text being typed below `label`
+pub fn render_text_prompt(gui: &mut super::Gui, width: i32, height: i32, label: &str, text: &str) {
+    let x = (width - PROMPT_WIDTH) / 2;
+    let y = height / 2 - PROMPT_TEXT_HEIGHT;
+    gui.primitives.draw_rect(
+        x - 10,
+        y - 10,
+        PROMPT_WIDTH + 20,
+        2 * PROMPT_TEXT_HEIGHT + 20,
+        gui.theme.panel,
+        0.01,
+    );
+    gui.text(
+        x,
+        y,
+        PROMPT_TEXT_HEIGHT,
+        format!("{} (Enter to confirm, Esc to cancel):", label),
+        gui.theme.text,
+        0.005,
+    );
+    gui.text(
+        x,
+        y + PROMPT_TEXT_HEIGHT,
+        PROMPT_TEXT_HEIGHT,
+        format!("{}_", text),
+        gui.theme.text,
+        0.005,
+    );
+}
@@ -0,0 +1,332 @@
+use voxel_rs_common::block::entity::{
+    FurnaceProgress, FURNACE_FUEL_SLOT, FURNACE_INPUT_SLOT, FURNACE_OUTPUT_SLOT,
+};
+use voxel_rs_common::inventory::{Inventory, ItemStack, HOTBAR_SIZE};
+use voxel_rs_common::item::Item;
+use voxel_rs_common::registry::Registry;
+
+pub const SLOT_SIZE: i32 = 40;
+pub const SLOT_SPACING: i32 = 4;
+pub const SLOTS_PER_ROW: usize = 9;
+
+/// Space between the border of a slot and the icon of its item
+const ICON_MARGIN: i32 = 4;
+/// Width of the remaining fuel bar of the furnace
+const FUEL_BAR_WIDTH: i32 = 8;
+/// Height of the remaining durability bar of the worn tools, at the bottom of their slot
+const DURABILITY_BAR_HEIGHT: i32 = 3;
+/// Dimensions of the smelting arrow of the furnace
+const ARROW_LENGTH: i32 = 2 * SLOT_SIZE;
+const ARROW_THICKNESS: i32 = 8;
+const ARROW_HEAD_LENGTH: i32 = ARROW_THICKNESS;
+
+/// Total width of an inventory grid
+pub fn inventory_width() -> i32 {
+    SLOTS_PER_ROW as i32 * (SLOT_SIZE + SLOT_SPACING) - SLOT_SPACING
+}
+
+/// Total height of the grid of some inventory
+pub fn inventory_height(inventory: &Inventory) -> i32 {
+    let rows = (inventory.size() + SLOTS_PER_ROW - 1) / SLOTS_PER_ROW;
+    rows as i32 * (SLOT_SIZE + SLOT_SPACING) - SLOT_SPACING
+}
+
+/// Draw an inventory as a grid of slots whose top-left corner is at `(x, y)`.
+/// Return the slot that the mouse is hovering, if any.
+pub fn render_inventory(
+    gui: &mut super::Gui,
+    x: i32,
+    y: i32,
+    inventory: &Inventory,
+    item_registry: &Registry<Item>,
+) -> Option<usize> {
+    let mut hovered_slot = None;
+    for (i, stack) in inventory.slots().enumerate() {
+        let slot_x = x + (i % SLOTS_PER_ROW) as i32 * (SLOT_SIZE + SLOT_SPACING);
+        let slot_y = y + (i / SLOTS_PER_ROW) as i32 * (SLOT_SIZE + SLOT_SPACING);
+        if render_slot(gui, slot_x, slot_y, stack, item_registry) {
+            hovered_slot = Some(i);
+        }
+    }
+    hovered_slot
+}
+
+/// Draw the hotbar, the first slots of the player inventory, at the bottom center of the screen
+pub fn render_hotbar(
+    gui: &mut super::Gui,
+    width: i32,
+    height: i32,
+    inventory: &Inventory,
+    selected_slot: usize,
+    item_registry: &Registry<Item>,
+) {
+    let hotbar_width = HOTBAR_SIZE as i32 * (SLOT_SIZE + SLOT_SPACING) - SLOT_SPACING;
+    let x = (width - hotbar_width) / 2;
+    let y = height - SLOT_SIZE - 2 * SLOT_SPACING;
+    for (i, stack) in inventory.slots().take(HOTBAR_SIZE).enumerate() {
+        let slot_x = x + i as i32 * (SLOT_SIZE + SLOT_SPACING);
+        if i == selected_slot {
+            gui.primitives.draw_rect(
+                slot_x - 2,
+                y - 2,
+                SLOT_SIZE + 4,
+                SLOT_SIZE + 4,
+                gui.theme.slot_selected,
+                0.02,
+            );
+        }
+        render_slot(gui, slot_x, y, stack, item_registry);
+    }
+}
+
+/// Create the creative palette, an inventory containing one of every item
+pub fn creative_palette(item_registry: &Registry<Item>) -> Inventory {
+    let mut palette = Inventory::new(item_registry.get_number_of_ids() as usize);
+    for item in 0..item_registry.get_number_of_ids() {
+        palette.set(item as usize, Some(ItemStack::new(item, 1)));
+    }
+    palette
+}
+
+/// Total height of the furnace layout
+pub fn furnace_height() -> i32 {
+    2 * SLOT_SIZE + SLOT_SPACING
+}
+
+/// Draw a furnace whose layout is `inventory_width()` wide and whose top-left corner is at `(x, y)`.
+/// The input slot is above the fuel slot, and an arrow shows the smelting progress towards the output slot.
+/// Return the slot that the mouse is hovering, if any.
+pub fn render_furnace(
+    gui: &mut super::Gui,
+    x: i32,
+    y: i32,
+    inventory: &Inventory,
+    progress: FurnaceProgress,
+    item_registry: &Registry<Item>,
+) -> Option<usize> {
+    let arrow_length = ARROW_LENGTH + ARROW_HEAD_LENGTH;
+    let total_width = SLOT_SIZE
+        + SLOT_SPACING
+        + FUEL_BAR_WIDTH
+        + 2 * SLOT_SPACING
+        + arrow_length
+        + SLOT_SPACING
+        + SLOT_SIZE;
+    let input_x = x + (inventory_width() - total_width) / 2;
+    let fuel_y = y + SLOT_SIZE + SLOT_SPACING;
+    let output_x = x + (inventory_width() + total_width) / 2 - SLOT_SIZE;
+    let output_y = y + (furnace_height() - SLOT_SIZE) / 2;
+
+    let mut hovered_slot = None;
+    let slots = [
+        (FURNACE_INPUT_SLOT, input_x, y),
+        (FURNACE_FUEL_SLOT, input_x, fuel_y),
+        (FURNACE_OUTPUT_SLOT, output_x, output_y),
+    ];
+    for &(slot, slot_x, slot_y) in slots.iter() {
+        if render_slot(gui, slot_x, slot_y, &inventory.get(slot), item_registry) {
+            hovered_slot = Some(slot);
+        }
+    }
+
+    // Remaining fuel, filled from the bottom
+    let fuel_x = input_x + SLOT_SIZE + SLOT_SPACING;
+    let fuel_height = (SLOT_SIZE as f32 * progress.fuel_fraction()) as i32;
+    gui.primitives.draw_rect(
+        fuel_x,
+        fuel_y,
+        FUEL_BAR_WIDTH,
+        SLOT_SIZE,
+        gui.theme.bar_background,
+        0.01,
+    );
+    gui.primitives.draw_rect(
+        fuel_x,
+        fuel_y + SLOT_SIZE - fuel_height,
+        FUEL_BAR_WIDTH,
+        fuel_height,
+        gui.theme.bar_fuel,
+        0.009,
+    );
+
+    // Smelting arrow, filled from the left
+    let arrow_x = fuel_x + FUEL_BAR_WIDTH + 2 * SLOT_SPACING;
+    let arrow_center_y = y + furnace_height() / 2;
+    let filled_length = (arrow_length as f32 * progress.smelting_fraction()) as i32;
+    for i in 0..arrow_length {
+        // The head of the arrow is twice as thick as its body, and narrows to a point
+        let half_thickness = if i < ARROW_LENGTH {
+            ARROW_THICKNESS / 2
+        } else {
+            arrow_length - i
+        };
+        let color = if i < filled_length {
+            gui.theme.bar_fill
+        } else {
+            gui.theme.bar_background
+        };
+        gui.primitives.draw_rect(
+            arrow_x + i,
+            arrow_center_y - half_thickness,
+            1,
+            2 * half_thickness,
+            color,
+            0.01,
+        );
+    }
+
+    hovered_slot
+}
+
+/// Draw a slot whose top-left corner is at `(x, y)`. Return true if the mouse is hovering it.
+fn render_slot(
+    gui: &mut super::Gui,
+    slot_x: i32,
+    slot_y: i32,
+    stack: &Option<ItemStack>,
+    item_registry: &Registry<Item>,
+) -> bool {
+    let hovered = gui.is_mouse_inside(slot_x, slot_y, SLOT_SIZE, SLOT_SIZE);
+    if hovered && stack.is_some() {
+        gui.set_cursor_style(super::CursorStyle::Hand);
+    }
+    gui.primitives.draw_rect(
+        slot_x,
+        slot_y,
+        SLOT_SIZE,
+        SLOT_SIZE,
+        if hovered {
+            gui.theme.slot_hovered
+        } else {
+            gui.theme.slot
+        },
+        0.01,
+    );
+    if let Some(stack) = stack {
+        render_stack(gui, slot_x, slot_y, stack, item_registry, 0.005);
+    }
+    hovered
+}
+
+/// Draw the tooltip of the item in the hovered slot, if the slot is not empty
+pub fn render_slot_tooltip(
+    gui: &mut super::Gui,
+    inventory: &Inventory,
+    slot: usize,
+    item_registry: &Registry<Item>,
+) {
+    if let Some(stack) = inventory.get(slot) {
+        let mut lines = vec![(item_name(item_registry, stack.item), gui.theme.text)];
+        if let Some(item) = item_registry.get_value_by_id(stack.item) {
+            for line in item.description().lines() {
+                lines.push((line.to_owned(), gui.theme.text_dim));
+            }
+        }
+        lines.push((format!("Count: {}", stack.count), gui.theme.text_highlight));
+        let durability = item_registry
+            .get_value_by_id(stack.item)
+            .map_or(0, Item::durability);
+        if durability > 0 {
+            lines.push((
+                format!("Durability: {}/{}", durability - stack.damage, durability),
+                gui.theme.text_highlight,
+            ));
+        }
+        gui.tooltip(lines);
+    }
+}
+
+fn item_name(item_registry: &Registry<Item>, item: u32) -> String {
+    item_registry
+        .get_value_by_id(item)
+        .map(|item| item.name.clone())
+        .unwrap_or_else(|| format!("unknown item #{}", item))
+}
+
+/// Draw an item stack in a slot whose top-left corner is at `(x, y)`, at depth `z`
+fn render_stack(
+    gui: &mut super::Gui,
+    slot_x: i32,
+    slot_y: i32,
+    stack: &ItemStack,
+    item_registry: &Registry<Item>,
+    z: f32,
+) {
+    match gui.item_icons.get(&stack.item).copied() {
+        Some(uv) => gui.primitives.draw_texture(
+            slot_x + ICON_MARGIN,
+            slot_y + ICON_MARGIN,
+            SLOT_SIZE - 2 * ICON_MARGIN,
+            SLOT_SIZE - 2 * ICON_MARGIN,
+            uv,
+            z,
+        ),
+        // The icons are not baked yet, draw the initial of the item instead
+        None => {
+            let name = item_name(item_registry, stack.item);
+            let initial = name
+                .chars()
+                .next()
+                .unwrap_or('?')
+                .to_uppercase()
+                .to_string();
+            gui.text(
+                slot_x + 4,
+                slot_y,
+                SLOT_SIZE / 2,
+                initial,
+                gui.theme.text,
+                z,
+            );
+        }
+    }
+    // Remaining durability of the worn tool on top of the stack, filled from the left
+    let durability = item_registry
+        .get_value_by_id(stack.item)
+        .map_or(0, Item::durability);
+    if durability > 0 && stack.damage > 0 {
+        let bar_x = slot_x + ICON_MARGIN;
+        let bar_y = slot_y + SLOT_SIZE - ICON_MARGIN - DURABILITY_BAR_HEIGHT;
+        let bar_width = SLOT_SIZE - 2 * ICON_MARGIN;
+        let remaining = 1.0 - stack.damage as f32 / durability as f32;
+        gui.primitives.draw_rect(
+            bar_x,
+            bar_y,
+            bar_width,
+            DURABILITY_BAR_HEIGHT,
+            gui.theme.bar_background,
+            z - 0.0005,
+        );
+        gui.primitives.draw_rect(
+            bar_x,
+            bar_y,
+            (bar_width as f32 * remaining) as i32,
+            DURABILITY_BAR_HEIGHT,
+            gui.theme.bar_durability,
+            z - 0.001,
+        );
+    }
+    if stack.count > 1 {
+        gui.text(
+            slot_x + SLOT_SIZE / 2,
+            slot_y + SLOT_SIZE / 2,
+            SLOT_SIZE / 2,
+            stack.count.to_string(),
+            gui.theme.text_highlight,
+            z,
+        );
+    }
+}
+
+/// Draw the stack held by the cursor next to the mouse
+pub fn render_cursor_stack(
+    gui: &mut super::Gui,
+    stack: &ItemStack,
+    item_registry: &Registry<Item>,
+) {
+    let (x, y) = (gui.mouse_x - SLOT_SIZE / 2, gui.mouse_y - SLOT_SIZE / 2);
+    gui.set_cursor_style(super::CursorStyle::Grab);
+    gui.primitives
+        .draw_rect(x, y, SLOT_SIZE, SLOT_SIZE, gui.theme.slot_hovered, 0.003);
+    render_stack(gui, x, y, stack, item_registry, 0.002);
+}
@@ -0,0 +1,89 @@
+const BAR_WIDTH: i32 = 400;
+const BAR_HEIGHT: i32 = 16;
+const TEXT_HEIGHT: i32 = 20;
+/// Distance between the text and the progress bar
+const TEXT_OFFSET: i32 = 10;
+const ERROR_MARGIN: i32 = 40;
+const ERROR_TEXT_HEIGHT: i32 = 16;
+const ERROR_LINE_SPACING: i32 = 4;
+
+/// Draw the loading screen: `text` above a progress bar in the middle of the screen. `fraction` is between 0 and 1.
+pub fn render_loading_screen(
+    gui: &mut super::Gui,
+    width: i32,
+    height: i32,
+    text: String,
+    fraction: f32,
+) {
+    let x = (width - BAR_WIDTH) / 2;
+    let y = (height - BAR_HEIGHT) / 2;
+    gui.text(
+        x,
+        y - TEXT_OFFSET - TEXT_HEIGHT,
+        TEXT_HEIGHT,
+        text,
+        gui.theme.text,
+        0.01,
+    );
+    gui.primitives
+        .draw_rect(x, y, BAR_WIDTH, BAR_HEIGHT, gui.theme.bar_background, 0.02);
+    let filled = (BAR_WIDTH as f32 * fraction.min(1.0).max(0.0)) as i32;
+    gui.primitives
+        .draw_rect(x, y, filled, BAR_HEIGHT, gui.theme.bar_fill, 0.01);
+}
+
+/// Draw the error screen shown when the data can't be loaded: `title` above one line per error.
+/// The lines that don't fit on the screen are replaced by a count of the remaining errors.
+pub fn render_error_screen(
+    gui: &mut super::Gui,
+    width: i32,
+    height: i32,
+    title: String,
+    lines: &[String],
+) {
+    gui.primitives.draw_rect(
+        ERROR_MARGIN / 2,
+        ERROR_MARGIN / 2,
+        width - ERROR_MARGIN,
+        height - ERROR_MARGIN,
+        gui.theme.panel,
+        0.02,
+    );
+    gui.text(
+        ERROR_MARGIN,
+        ERROR_MARGIN,
+        TEXT_HEIGHT,
+        title,
+        gui.theme.text_highlight,
+        0.01,
+    );
+
+    let line_height = ERROR_TEXT_HEIGHT + ERROR_LINE_SPACING;
+    let first_line_y = ERROR_MARGIN + TEXT_HEIGHT + TEXT_OFFSET;
+    let max_lines = ((height - ERROR_MARGIN - first_line_y) / line_height).max(1) as usize;
+    let shown_lines = if lines.len() > max_lines {
+        max_lines - 1
+    } else {
+        lines.len()
+    };
+    for (i, line) in lines.iter().take(shown_lines).enumerate() {
+        gui.text(
+            ERROR_MARGIN,
+            first_line_y + i as i32 * line_height,
+            ERROR_TEXT_HEIGHT,
+            line.clone(),
+            gui.theme.text,
+            0.01,
+        );
+    }
+    if shown_lines < lines.len() {
+        gui.text(
+            ERROR_MARGIN,
+            first_line_y + shown_lines as i32 * line_height,
+            ERROR_TEXT_HEIGHT,
+            format!("... and {} more, see the log", lines.len() - shown_lines),
+            gui.theme.text_dim,
+            0.01,
+        );
+    }
+}
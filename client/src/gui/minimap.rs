@@ -0,0 +1,78 @@
+use voxel_rs_common::player::Waypoint;
+
+const BORDER_WIDTH: i32 = 2;
+/// Distance between the center and the tip of the player arrow
+const ARROW_SIZE: f32 = 8.0;
+const WAYPOINT_SIZE: i32 = 6;
+
+/// Draw the border, the waypoints and the player arrow of a map showing `blocks` x `blocks` blocks around
+/// `center_block` in the square whose top-left corner is at `(x, y)`.
+/// `player_position` is the `(x, z)` position of the player and `yaw` is its yaw in degrees.
+pub fn render_map_overlay(
+    gui: &mut super::Gui,
+    x: i32,
+    y: i32,
+    size: i32,
+    blocks: u32,
+    center_block: (i64, i64),
+    player_position: (f64, f64),
+    yaw: f64,
+    waypoints: &[Waypoint],
+) {
+    // Border, behind the map
+    gui.primitives.draw_rect(
+        x - BORDER_WIDTH,
+        y - BORDER_WIDTH,
+        size + 2 * BORDER_WIDTH,
+        size + 2 * BORDER_WIDTH,
+        gui.theme.panel_border,
+        0.6,
+    );
+
+    // Convert world positions to screen positions
+    let scale = size as f64 / blocks as f64;
+    let (x0, z0) = (
+        center_block.0 - blocks as i64 / 2,
+        center_block.1 - blocks as i64 / 2,
+    );
+    let to_screen = |wx: f64, wz: f64| {
+        (
+            (x as f64 + (wx - x0 as f64) * scale) as f32,
+            (y as f64 + (wz - z0 as f64) * scale) as f32,
+        )
+    };
+
+    for waypoint in waypoints {
+        let (wx, wy) = to_screen(waypoint.x as f64 + 0.5, waypoint.z as f64 + 0.5);
+        let (wx, wy) = (wx as i32, wy as i32);
+        if x <= wx && wx < x + size && y <= wy && wy < y + size {
+            gui.primitives.draw_rect(
+                wx - WAYPOINT_SIZE / 2,
+                wy - WAYPOINT_SIZE / 2,
+                WAYPOINT_SIZE,
+                WAYPOINT_SIZE,
+                waypoint.color,
+                0.005,
+            );
+        }
+    }
+
+    // Player arrow, pointing in the direction the player is looking
+    let (px, py) = to_screen(player_position.0, player_position.1);
+    let yaw = yaw.to_radians() as f32;
+    let (dx, dy) = (-yaw.sin(), -yaw.cos());
+    let (nx, ny) = (-dy, dx);
+    let tip = [px + dx * ARROW_SIZE, py + dy * ARROW_SIZE, 0.004];
+    let left = [
+        px + (-dx + nx) * 0.6 * ARROW_SIZE,
+        py + (-dy + ny) * 0.6 * ARROW_SIZE,
+        0.004,
+    ];
+    let right = [
+        px + (-dx - nx) * 0.6 * ARROW_SIZE,
+        py + (-dy - ny) * 0.6 * ARROW_SIZE,
+        0.004,
+    ];
+    gui.primitives
+        .draw_triangles(vec![tip, left, right], vec![0, 1, 2], gui.theme.map_player);
+}
@@ -1,6 +1,39 @@
-use crate::ui::PrimitiveBuffer;
+use crate::ui::{PrimitiveBuffer, TextPart};
+use std::collections::HashMap;
+use theme::Theme;
+use wgpu_glyph::ab_glyph::PxScale;
 
+pub mod boss_bars;
+pub mod chat;
 pub mod experiments;
+pub mod heatmap;
+pub mod hud;
+pub mod inventory;
+pub mod loading;
+pub mod minimap;
+pub mod players;
+pub mod scoreboard;
+pub mod stats;
+pub mod theme;
+pub mod titles;
+pub mod waypoints;
+
+/// Shape of the cursor drawn over the GUI, in the order of the cells of the cursor atlas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorStyle {
+    Arrow,
+    /// Over something that can be clicked
+    Hand,
+    /// Dragging something, like a stack of items
+    Grab,
+}
+
+impl CursorStyle {
+    /// Index of the cell of the cursor atlas, and of the hotspot in `CursorTheme::hotspots`
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
 
 /// Immediate-mode GUI
 pub struct Gui {
@@ -13,29 +46,62 @@ pub struct Gui {
     /// 0 means "no item but can be assigned"
     /// 1 means "no item and cannot be assigned"
     pub(self) active_item: u32,
+    /// Style of the cursor, set while drawing the frame
+    pub(self) cursor_style: CursorStyle,
 
     pub(self) primitives: PrimitiveBuffer,
+    pub(self) theme: Theme,
+    /// The rectangle of the icon of every item in the icon atlas of the `UiRenderer`
+    pub(self) item_icons: HashMap<u32, [f32; 4]>,
 }
 
 impl Gui {
-    /// Create a new Gui
-    pub fn new() -> Self {
+    /// Create a new Gui drawn with the colors of `theme`
+    pub fn new(theme: Theme) -> Self {
         Self {
             mouse_x: 0,
             mouse_y: 0,
             mouse_down: false,
             hot_item: 0,
             active_item: 0,
+            cursor_style: CursorStyle::Arrow,
             primitives: Default::default(),
+            theme,
+            item_icons: HashMap::new(),
         }
     }
 
+    /// Set the rectangles of the item icons in the icon atlas
+    pub fn set_item_icons(&mut self, item_icons: HashMap<u32, [f32; 4]>) {
+        self.item_icons = item_icons;
+    }
+
+    /// Get the colors of the Gui
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
     /// Update the mouse position
     pub fn update_mouse_position(&mut self, new_x: i32, new_y: i32) {
         self.mouse_x = new_x;
         self.mouse_y = new_y;
     }
 
+    /// Get the mouse position, in GUI units
+    pub fn mouse_position(&self) -> (i32, i32) {
+        (self.mouse_x, self.mouse_y)
+    }
+
+    /// Get the style of the cursor for the last drawn frame
+    pub fn cursor_style(&self) -> CursorStyle {
+        self.cursor_style
+    }
+
+    /// Set the style of the cursor for the current frame
+    pub fn set_cursor_style(&mut self, style: CursorStyle) {
+        self.cursor_style = style;
+    }
+
     /// Update the state of the mouse button
     pub fn update_mouse_button(&mut self, is_down: bool) {
         self.mouse_down = is_down;
@@ -49,6 +115,7 @@ impl Gui {
     /// Prepare for frame drawing
     pub fn prepare(&mut self) {
         self.hot_item = 0;
+        self.cursor_style = CursorStyle::Arrow;
     }
 
     /// Finish the frame
@@ -88,6 +155,22 @@ impl Gui {
     pub fn text(&mut self, x: i32, y: i32, h: i32, text: String, color: [f32; 4], z: f32) {
         self.primitives.draw_text_simple(x, y, h, text, color, z);
     }
+
+    /// Draw a tooltip next to the mouse, with one line per `(text, color)` pair.
+    /// Tooltips are rendered on top of everything else.
+    pub fn tooltip(&mut self, lines: Vec<(String, [f32; 4])>) {
+        let lines = lines
+            .into_iter()
+            .map(|(text, color)| TextPart {
+                text,
+                font_size: PxScale::from(20.0),
+                color,
+                font: None,
+            })
+            .collect();
+        self.primitives
+            .draw_tooltip(self.mouse_x, self.mouse_y, lines);
+    }
 }
 
 // TODO: fix depth
@@ -121,6 +204,7 @@ impl<'a> ButtonBuilder<'a> {
         if gui.is_mouse_inside(x, y, w, h) {
             // Then the button is hot
             gui.hot_item = id;
+            gui.cursor_style = CursorStyle::Hand;
             // Maybe the button should also be active
             if gui.active_item == 0 && gui.mouse_down {
                 gui.active_item = id;
@@ -128,7 +212,7 @@ impl<'a> ButtonBuilder<'a> {
         }
         // Draw the shadow
         gui.primitives
-            .draw_rect(x + 3, y + 3, w, h, [0.0, 0.0, 0.0, 1.0], 0.02);
+            .draw_rect(x + 3, y + 3, w, h, gui.theme.button_shadow, 0.02);
         // Draw the button
         let draw_pos;
         let button_color;
@@ -136,16 +220,16 @@ impl<'a> ButtonBuilder<'a> {
             if gui.active_item == id {
                 // Hot and active
                 draw_pos = (x + 2, y + 2);
-                button_color = [0.4, 0.4, 0.5, 1.0];
+                button_color = gui.theme.button_pressed;
             } else {
                 // Just hot
                 draw_pos = (x, y);
-                button_color = [0.5, 0.5, 0.6, 1.0];
+                button_color = gui.theme.button_hovered;
             }
         } else {
             // Not hot but might be active
             draw_pos = (x, y);
-            button_color = [0.6, 0.6, 0.7, 1.0];
+            button_color = gui.theme.button;
         }
         gui.primitives
             .draw_rect(draw_pos.0, draw_pos.1, w, h, button_color, 0.01);
@@ -165,3 +249,26 @@ impl<'a> ButtonBuilder<'a> {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_style() {
+        let mut gui = Gui::new(Theme::default());
+        gui.update_mouse_position(15, 15);
+        gui.prepare();
+        gui.button(0, 0, 0, 10, 10).build();
+        assert_eq!(gui.cursor_style(), CursorStyle::Arrow);
+        gui.button(1, 10, 10, 10, 10).build();
+        assert_eq!(gui.cursor_style(), CursorStyle::Hand);
+        gui.finish();
+
+        // The style is reset for every frame
+        gui.update_mouse_position(50, 50);
+        gui.prepare();
+        gui.button(1, 10, 10, 10, 10).build();
+        assert_eq!(gui.cursor_style(), CursorStyle::Arrow);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::render::Frustum;
+use crate::ui::{TextPart, TextPrimitive};
+use nalgebra::{Vector3, Vector4};
+use voxel_rs_common::player::{PlayerId, PlayerListEntry};
+use wgpu_glyph::ab_glyph::PxScale;
+
+/// Height of the name tags of the close players, in pixels
+const NAME_TAG_HEIGHT: f32 = 22.0;
+/// Height of the name tags of the far players, in pixels
+const MIN_NAME_TAG_HEIGHT: f32 = 12.0;
+/// The name tags are shrinking beyond this distance, in blocks
+const NAME_TAG_SHRINK_DISTANCE: f64 = 8.0;
+/// The name tags are fading out between these distances, in blocks
+const NAME_TAG_FADE_START: f64 = 24.0;
+const NAME_TAG_FADE_END: f64 = 48.0;
+/// Opacity of the name tags of the players behind blocks
+const OCCLUDED_ALPHA: f32 = 0.35;
+const TEXT_HEIGHT: i32 = 20;
+const LIST_WIDTH: i32 = 300;
+const LIST_MARGIN: i32 = 40;
+/// Indentation of the players under the name of their team in the player list
+const TEAM_INDENT: i32 = 15;
+
+/// The name tag of another player
+pub struct NameTag {
+    pub name: String,
+    /// Color of the name, the color of the team of the player if they are in one
+    pub color: Option<[f32; 4]>,
+    /// The score of the player for the objective shown below the names, with the title of the objective
+    pub below_name: Option<String>,
+    /// Position of the bottom of the tag, above the head of the player
+    pub position: Vector3<f64>,
+    /// Whether there are blocks between the camera and the tag
+    pub occluded: bool,
+}
+
+/// Draw the name tags facing the camera: they shrink and fade out with the distance, and are dimmed
+/// when the player is behind blocks
+pub fn render_name_tags(
+    gui: &mut super::Gui,
+    frustum: &Frustum,
+    width: f32,
+    height: f32,
+    name_tags: &[NameTag],
+) {
+    let view_projection = frustum.get_view_projection(width as f64 / height as f64);
+    for tag in name_tags {
+        let distance = (tag.position - frustum.position).norm();
+        let fade = (NAME_TAG_FADE_END - distance) / (NAME_TAG_FADE_END - NAME_TAG_FADE_START);
+        let mut alpha = fade.max(0.0).min(1.0) as f32;
+        if tag.occluded {
+            alpha *= OCCLUDED_ALPHA;
+        }
+        let p = tag.position;
+        let clip = view_projection * Vector4::new(p.x, p.y, p.z, 1.0);
+        if alpha <= 0.0 || clip.w <= 0.0 || clip.x.abs() > clip.w || clip.y.abs() > clip.w {
+            continue;
+        }
+        let x = ((clip.x / clip.w + 1.0) / 2.0 * width as f64) as i32;
+        let y = ((1.0 - clip.y / clip.w) / 2.0 * height as f64) as i32;
+        let text_height = (NAME_TAG_HEIGHT * (NAME_TAG_SHRINK_DISTANCE / distance) as f32)
+            .max(MIN_NAME_TAG_HEIGHT)
+            .min(NAME_TAG_HEIGHT);
+        let text_height = text_height as i32;
+        let faded = |mut color: [f32; 4]| {
+            color[3] *= alpha;
+            color
+        };
+        let color = faded(gui.theme.text);
+        // The score line is smaller, between the name and the head
+        let score_height = text_height * 3 / 4;
+        let name_y = match &tag.below_name {
+            Some(below_name) => {
+                draw_name_tag_line(gui, x, y, score_height, below_name, color, alpha);
+                y - score_height
+            }
+            None => y,
+        };
+        let name_color = faded(tag.color.unwrap_or(gui.theme.text));
+        draw_name_tag_line(gui, x, name_y, text_height, &tag.name, name_color, alpha);
+    }
+}
+
+/// Draw a line of a name tag centered on `x`, with its bottom at `y`
+fn draw_name_tag_line(
+    gui: &mut super::Gui,
+    x: i32,
+    y: i32,
+    text_height: i32,
+    text: &str,
+    color: [f32; 4],
+    alpha: f32,
+) {
+    // Approximate width of the text
+    let text_width = text.chars().count() as i32 * text_height / 2;
+    let mut background = gui.theme.panel;
+    background[3] *= alpha;
+    gui.primitives.draw_rect(
+        x - text_width / 2 - 4,
+        y - text_height,
+        text_width + 8,
+        text_height,
+        background,
+        0.2,
+    );
+    gui.primitives.text.push(TextPrimitive {
+        x: x - text_width / 2 - 4,
+        y: y - text_height,
+        w: Some(text_width + 8),
+        h: Some(text_height),
+        parts: vec![TextPart {
+            text: text.to_owned(),
+            font_size: PxScale::from(text_height as f32),
+            color,
+            font: None,
+        }],
+        z: 0.19,
+        center_horizontally: true,
+        center_vertically: true,
+    });
+}
+
+/// Draw the list of the connected players at the top of the screen, with their ping.
+/// The server sends the players of a team next to each other, they are shown under the name of their team.
+pub fn render_player_list(
+    gui: &mut super::Gui,
+    width: i32,
+    entries: &[PlayerListEntry],
+    own_id: PlayerId,
+) {
+    let has_teams = entries.iter().any(|entry| entry.team.is_some());
+    // The team headers and the players, with the ping of the players
+    let mut rows: Vec<(i32, String, [f32; 4], Option<String>)> = Vec::new();
+    let mut current_team = None;
+    for (i, entry) in entries.iter().enumerate() {
+        let team = entry.team.as_ref();
+        if has_teams && (i == 0 || team.map(|team| &team.name) != current_team) {
+            rows.push(match team {
+                Some(team) => (0, team.name.clone(), team.color, None),
+                None => (0, "No team".to_owned(), gui.theme.text_dim, None),
+            });
+        }
+        current_team = team.map(|team| &team.name);
+        let color = match team {
+            Some(team) => team.color,
+            None if entry.id == own_id => gui.theme.text_highlight,
+            None => gui.theme.text,
+        };
+        let ping = match entry.ping {
+            Some(ping) => format!("{} ms", ping),
+            None => "? ms".to_owned(),
+        };
+        let indent = if has_teams { TEAM_INDENT } else { 0 };
+        rows.push((indent, entry.name.clone(), color, Some(ping)));
+    }
+
+    let x = (width - LIST_WIDTH) / 2;
+    let y = LIST_MARGIN;
+    gui.primitives.draw_rect(
+        x - 10,
+        y - 10,
+        LIST_WIDTH + 20,
+        (rows.len() as i32 + 1) * TEXT_HEIGHT + 20,
+        gui.theme.panel,
+        0.02,
+    );
+    gui.text(
+        x,
+        y,
+        TEXT_HEIGHT,
+        format!("Players ({})", entries.len()),
+        gui.theme.text_highlight,
+        0.01,
+    );
+    for (i, (indent, text, color, ping)) in rows.into_iter().enumerate() {
+        let line_y = y + (i as i32 + 1) * TEXT_HEIGHT;
+        gui.text(x + indent, line_y, TEXT_HEIGHT, text, color, 0.01);
+        if let Some(ping) = ping {
+            gui.text(
+                x + LIST_WIDTH - 80,
+                line_y,
+                TEXT_HEIGHT,
+                ping,
+                gui.theme.text_dim,
+                0.01,
+            );
+        }
+    }
+}
+
+/// Show who is being watched at the top of the screen, with the keys to switch players
+pub fn render_spectating(gui: &mut super::Gui, width: i32, name: &str) {
+    let x = (width - LIST_WIDTH) / 2;
+    let y = LIST_MARGIN / 2;
+    gui.primitives.draw_rect(
+        x - 10,
+        y - 5,
+        LIST_WIDTH + 20,
+        2 * TEXT_HEIGHT + 10,
+        gui.theme.panel,
+        0.03,
+    );
+    gui.text(
+        x,
+        y,
+        TEXT_HEIGHT,
+        format!("Spectating {}", name),
+        gui.theme.text_highlight,
+        0.025,
+    );
+    gui.text(
+        x,
+        y + TEXT_HEIGHT,
+        TEXT_HEIGHT,
+        "< and > to switch, Shift to stop".to_owned(),
+        gui.theme.text_dim,
+        0.025,
+    );
+}
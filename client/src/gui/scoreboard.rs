@@ -0,0 +1,49 @@
+use voxel_rs_common::scoreboard::DisplayedObjective;
+
+const TEXT_HEIGHT: i32 = 20;
+const SIDEBAR_WIDTH: i32 = 220;
+/// Distance between the sidebar and the right of the screen
+const SIDEBAR_MARGIN: i32 = 10;
+/// Horizontal position of the scores, relative to the right of the sidebar
+const SCORE_COLUMN: i32 = 60;
+
+/// Draw the scores of an objective on the right of the screen, from the highest to the lowest
+pub fn render_sidebar(
+    gui: &mut super::Gui,
+    width: i32,
+    height: i32,
+    objective: &DisplayedObjective,
+) {
+    let sidebar_height = (objective.scores.len() as i32 + 1) * TEXT_HEIGHT + 10;
+    let x = width - SIDEBAR_WIDTH - SIDEBAR_MARGIN;
+    let y = (height - sidebar_height) / 2;
+    gui.primitives
+        .draw_rect(x, y, SIDEBAR_WIDTH, sidebar_height, gui.theme.panel, 0.02);
+    gui.text(
+        x + 5,
+        y + 5,
+        TEXT_HEIGHT,
+        objective.title.clone(),
+        gui.theme.text_highlight,
+        0.01,
+    );
+    for (i, (name, score)) in objective.scores.iter().enumerate() {
+        let line_y = y + 5 + (i as i32 + 1) * TEXT_HEIGHT;
+        gui.text(
+            x + 5,
+            line_y,
+            TEXT_HEIGHT,
+            name.clone(),
+            gui.theme.text,
+            0.01,
+        );
+        gui.text(
+            x + SIDEBAR_WIDTH - SCORE_COLUMN,
+            line_y,
+            TEXT_HEIGHT,
+            score.to_string(),
+            gui.theme.text_highlight,
+            0.01,
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::{BTreeMap, BTreeSet};
+use voxel_rs_common::stats::PlayerStats;
+
+const TEXT_HEIGHT: i32 = 20;
+const SCREEN_WIDTH: i32 = 500;
+/// Horizontal position of the columns of the block table, relative to the left of the screen
+const BROKEN_COLUMN: i32 = 260;
+const PLACED_COLUMN: i32 = 380;
+
+/// Format a duration in seconds as hours, minutes and seconds
+fn format_playtime(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!(
+        "{}h {:02}m {:02}s",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Draw the statistics screen in the middle of the screen.
+/// `stats` is `None` while the statistics have not been received from the server yet.
+pub fn render_stats(gui: &mut super::Gui, width: i32, height: i32, stats: Option<&PlayerStats>) {
+    let (title_color, text_color) = (gui.theme.text_highlight, gui.theme.text);
+    let mut lines: Vec<(i32, String, [f32; 4])> = Vec::new();
+    match stats {
+        None => lines.push((0, "Loading statistics...".to_owned(), text_color)),
+        Some(stats) => {
+            lines.push((0, "General".to_owned(), title_color));
+            lines.push((
+                0,
+                format!("Time played: {}", format_playtime(stats.playtime)),
+                text_color,
+            ));
+            lines.push((
+                0,
+                format!("Distance walked: {:.0} blocks", stats.distance_walked),
+                text_color,
+            ));
+            lines.push((0, format!("Deaths: {}", stats.deaths), text_color));
+            lines.push((0, String::new(), text_color));
+            lines.push((0, "Blocks".to_owned(), title_color));
+            lines.push((BROKEN_COLUMN, "Broken".to_owned(), title_color));
+            lines.push((PLACED_COLUMN, "Placed".to_owned(), title_color));
+            let blocks = stats
+                .blocks_broken
+                .keys()
+                .chain(stats.blocks_placed.keys())
+                .collect::<BTreeSet<_>>();
+            for block in blocks {
+                let count =
+                    |map: &BTreeMap<String, u64>| map.get(block).cloned().unwrap_or(0).to_string();
+                lines.push((0, block.clone(), text_color));
+                lines.push((BROKEN_COLUMN, count(&stats.blocks_broken), text_color));
+                lines.push((PLACED_COLUMN, count(&stats.blocks_placed), text_color));
+            }
+        }
+    }
+
+    // The lines that are not in the first column are on the same row as the previous line
+    let rows = lines.iter().filter(|(column, _, _)| *column == 0).count() as i32;
+    let screen_height = (rows + 2) * TEXT_HEIGHT;
+    let x = (width - SCREEN_WIDTH) / 2;
+    let mut y = (height - screen_height) / 2;
+    gui.primitives
+        .draw_rect(x, y, SCREEN_WIDTH, screen_height, gui.theme.panel, 0.02);
+    gui.text(
+        x + TEXT_HEIGHT,
+        y,
+        TEXT_HEIGHT,
+        "Statistics".to_owned(),
+        title_color,
+        0.01,
+    );
+    for (column, text, color) in lines {
+        if column == 0 {
+            y += TEXT_HEIGHT;
+        }
+        gui.text(x + TEXT_HEIGHT + column, y, TEXT_HEIGHT, text, color, 0.01);
+    }
+}
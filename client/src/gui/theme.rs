@@ -0,0 +1,173 @@
+//! Colors of the GUI. The themes are defined in `assets/themes/<name>.toml`, and the theme is selected in the settings.
+//! The colors missing from a theme file are taken from the default dark theme.
+
+use anyhow::{Context, Result};
+use quint::{Color, Style};
+use serde::Deserialize;
+
+/// Folder containing the theme files
+const THEMES_FOLDER: &str = "assets/themes";
+/// Folder containing the cursor atlases
+const CURSORS_FOLDER: &str = "assets/cursors";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Background of the panels: chat, statistics, prompts and labels
+    pub panel: Color,
+    /// Border of the maps
+    pub panel_border: Color,
+    pub text: Color,
+    /// Secondary text, like the descriptions of the items
+    pub text_dim: Color,
+    /// Titles and item counts
+    pub text_highlight: Color,
+    pub slot: Color,
+    pub slot_hovered: Color,
+    /// Frame around the selected slot of the hotbar
+    pub slot_selected: Color,
+    pub button: Color,
+    pub button_hovered: Color,
+    pub button_pressed: Color,
+    pub button_shadow: Color,
+    /// Empty part of the progress bars
+    pub bar_background: Color,
+    /// Filled part of the progress bars: block breaking and smelting
+    pub bar_fill: Color,
+    /// Remaining fuel of the furnaces
+    pub bar_fuel: Color,
+    /// Remaining durability of the tools
+    pub bar_durability: Color,
+    pub tooltip_background: Color,
+    pub tooltip_border: Color,
+    pub crosshair: Color,
+    /// Arrow of the player on the maps
+    pub map_player: Color,
+    /// Text of the debug overlay
+    pub debug_text: Color,
+    /// Flash of the screen when the player goes through a portal, fading out
+    pub teleport_flash: Color,
+    /// Tint of the screen when the camera is under water
+    pub underwater_tint: Color,
+    /// Flash of the screen when the player is hurt, fading out
+    pub damage_flash: Color,
+    /// Vignette deep underground and overlay when the camera is inside a block
+    pub darkness: Color,
+    /// Colors of the waypoints, in the order they are added
+    pub waypoints: Vec<Color>,
+    /// The cursor drawn in the menus
+    pub cursor: CursorTheme,
+}
+
+/// The cursor drawn by the GUI instead of the system cursor
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CursorTheme {
+    /// Name of the atlas, loaded from `assets/cursors/<atlas>.png`. It is 64x64 pixels with a 32x32 cell
+    /// per `CursorStyle`, from left to right and then from top to bottom.
+    pub atlas: String,
+    /// Point of each style that is at the mouse position, in pixels from the top-left corner of its cell
+    pub hotspots: [[f32; 2]; 3],
+}
+
+impl Default for CursorTheme {
+    fn default() -> Self {
+        Self {
+            atlas: "default".to_owned(),
+            hotspots: [[1.0, 1.0], [11.5, 2.0], [16.0, 12.0]],
+        }
+    }
+}
+
+impl CursorTheme {
+    /// Load the cursor atlas
+    pub fn load_atlas(&self) -> Result<image::RgbaImage> {
+        let path = format!("{}/{}.png", CURSORS_FOLDER, self.atlas);
+        let atlas = image::open(&path)
+            .with_context(|| format!("Failed to load cursor atlas {}", path))?
+            .to_rgba();
+        anyhow::ensure!(
+            atlas.dimensions() == (64, 64),
+            "The cursor atlas {} must be 64x64 pixels",
+            path
+        );
+        Ok(atlas)
+    }
+}
+
+impl Theme {
+    /// Style of the buttons of the widget-based menus
+    pub fn button_style(&self) -> Style {
+        Style::default()
+            .background(self.button)
+            .hovered_background(self.button_hovered)
+            .text_color(self.text)
+    }
+
+    /// Color of the `index`-th waypoint
+    pub fn waypoint_color(&self, index: usize) -> Color {
+        if self.waypoints.is_empty() {
+            self.text_highlight
+        } else {
+            self.waypoints[index % self.waypoints.len()]
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            panel: [0.0, 0.0, 0.0, 0.6],
+            panel_border: [0.1, 0.1, 0.1, 0.9],
+            text: [1.0, 1.0, 1.0, 1.0],
+            text_dim: [0.7, 0.7, 0.75, 1.0],
+            text_highlight: [1.0, 1.0, 0.6, 1.0],
+            slot: [0.25, 0.25, 0.3, 0.9],
+            slot_hovered: [0.45, 0.45, 0.55, 0.9],
+            slot_selected: [1.0, 1.0, 1.0, 0.9],
+            button: [0.6, 0.6, 0.7, 1.0],
+            button_hovered: [0.5, 0.5, 0.6, 1.0],
+            button_pressed: [0.4, 0.4, 0.5, 1.0],
+            button_shadow: [0.0, 0.0, 0.0, 1.0],
+            bar_background: [0.15, 0.15, 0.2, 0.9],
+            bar_fill: [0.9, 0.9, 0.9, 1.0],
+            bar_fuel: [1.0, 0.55, 0.1, 1.0],
+            bar_durability: [0.3, 0.85, 0.3, 1.0],
+            tooltip_background: [0.1, 0.05, 0.15, 0.92],
+            tooltip_border: [0.35, 0.2, 0.6, 1.0],
+            crosshair: [1.0, 1.0, 1.0, 0.5],
+            map_player: [1.0, 1.0, 1.0, 1.0],
+            debug_text: [1.0, 1.0, 1.0, 1.0],
+            teleport_flash: [0.45, 0.2, 0.75, 1.0],
+            underwater_tint: [0.1, 0.25, 0.6, 0.45],
+            damage_flash: [0.8, 0.05, 0.05, 0.4],
+            darkness: [0.0, 0.0, 0.0, 0.85],
+            waypoints: vec![
+                [1.0, 0.2, 0.2, 1.0],
+                [0.2, 0.6, 1.0, 1.0],
+                [1.0, 0.9, 0.2, 1.0],
+                [0.7, 0.3, 1.0, 1.0],
+            ],
+            cursor: CursorTheme::default(),
+        }
+    }
+}
+
+fn read_theme(name: &str) -> Result<Theme> {
+    let path = format!("{}/{}.toml", THEMES_FOLDER, name);
+    let theme = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read theme file {}", path))?;
+    toml::de::from_str(&theme).with_context(|| format!("Failed to parse theme file {}", path))
+}
+
+/// Load the theme with some name, or the default theme if it can't be loaded
+pub fn load_theme(name: &str) -> Theme {
+    log::info!("Loading GUI theme {}", name);
+    match read_theme(name) {
+        Ok(theme) => theme,
+        Err(e) => {
+            log::warn!("{:?}\nUsing the default theme instead", e);
+            Theme::default()
+        }
+    }
+}
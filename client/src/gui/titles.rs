@@ -0,0 +1,176 @@
+use super::inventory::{SLOT_SIZE, SLOT_SPACING};
+use crate::ui::{TextPart, TextPrimitive};
+use wgpu_glyph::ab_glyph::PxScale;
+
+const TITLE_HEIGHT: i32 = 64;
+const SUBTITLE_HEIGHT: i32 = 28;
+const ACTION_BAR_HEIGHT: i32 = 20;
+/// Distance between the action bar text and the top of the hotbar
+const ACTION_BAR_MARGIN: i32 = 30;
+/// How long the action bar text is shown, and how long it fades out at the end, in seconds
+const ACTION_BAR_TIME: f64 = 3.0;
+const ACTION_BAR_FADE_OUT: f64 = 1.0;
+
+/// A title sent by the server, shown in the middle of the screen
+#[derive(Debug, Clone)]
+pub struct ShownTitle {
+    pub title: String,
+    pub subtitle: String,
+    fade_in: f64,
+    stay: f64,
+    fade_out: f64,
+    /// Seconds since the title was received
+    elapsed: f64,
+}
+
+impl ShownTitle {
+    pub fn new(title: String, subtitle: String, fade_in: f32, stay: f32, fade_out: f32) -> Self {
+        Self {
+            title,
+            subtitle,
+            fade_in: fade_in.max(0.0) as f64,
+            stay: stay.max(0.0) as f64,
+            fade_out: fade_out.max(0.0) as f64,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, seconds_delta: f64) {
+        self.elapsed += seconds_delta;
+    }
+
+    /// Whether the title faded out completely
+    pub fn is_over(&self) -> bool {
+        self.elapsed >= self.fade_in + self.stay + self.fade_out
+    }
+
+    /// Opacity of the title, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        let alpha = if self.elapsed < self.fade_in {
+            self.elapsed / self.fade_in
+        } else if self.elapsed < self.fade_in + self.stay {
+            1.0
+        } else {
+            1.0 - (self.elapsed - self.fade_in - self.stay) / self.fade_out
+        };
+        alpha.min(1.0).max(0.0) as f32
+    }
+}
+
+/// A text sent by the server, shown above the hotbar
+#[derive(Debug, Clone)]
+pub struct ActionBarText {
+    pub text: String,
+    /// Seconds since the text was received
+    elapsed: f64,
+}
+
+impl ActionBarText {
+    pub fn new(text: String) -> Self {
+        Self { text, elapsed: 0.0 }
+    }
+
+    pub fn update(&mut self, seconds_delta: f64) {
+        self.elapsed += seconds_delta;
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.elapsed >= ACTION_BAR_TIME
+    }
+
+    /// Opacity of the text, that fades out at the end
+    pub fn alpha(&self) -> f32 {
+        ((ACTION_BAR_TIME - self.elapsed) / ACTION_BAR_FADE_OUT)
+            .min(1.0)
+            .max(0.0) as f32
+    }
+}
+
+/// Draw a line of text centered horizontally on the screen
+fn centered_text(
+    gui: &mut super::Gui,
+    width: i32,
+    y: i32,
+    text_height: i32,
+    text: &str,
+    color: [f32; 4],
+) {
+    gui.primitives.text.push(TextPrimitive {
+        x: 0,
+        y,
+        w: Some(width),
+        h: Some(text_height),
+        parts: vec![TextPart {
+            text: text.to_owned(),
+            font_size: PxScale::from(text_height as f32),
+            color,
+            font: None,
+        }],
+        z: 0.05,
+        center_horizontally: true,
+        center_vertically: true,
+    });
+}
+
+/// Draw the title and its subtitle in the middle of the screen
+pub fn render_title(gui: &mut super::Gui, width: i32, height: i32, title: &ShownTitle) {
+    let alpha = title.alpha();
+    let mut title_color = gui.theme.text_highlight;
+    title_color[3] *= alpha;
+    let mut subtitle_color = gui.theme.text;
+    subtitle_color[3] *= alpha;
+    let y = height / 3;
+    centered_text(gui, width, y, TITLE_HEIGHT, &title.title, title_color);
+    centered_text(
+        gui,
+        width,
+        y + TITLE_HEIGHT,
+        SUBTITLE_HEIGHT,
+        &title.subtitle,
+        subtitle_color,
+    );
+}
+
+/// Draw the action bar text above the hotbar
+pub fn render_action_bar(
+    gui: &mut super::Gui,
+    width: i32,
+    height: i32,
+    action_bar: &ActionBarText,
+) {
+    let mut color = gui.theme.text;
+    color[3] *= action_bar.alpha();
+    let hotbar_top = height - SLOT_SIZE - 2 * SLOT_SPACING;
+    centered_text(
+        gui,
+        width,
+        hotbar_top - ACTION_BAR_MARGIN - ACTION_BAR_HEIGHT,
+        ACTION_BAR_HEIGHT,
+        &action_bar.text,
+        color,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_fading() {
+        let mut title = ShownTitle::new("Wave 2".to_owned(), String::new(), 1.0, 2.0, 1.0);
+        assert_eq!(title.alpha(), 0.0);
+        title.update(0.5);
+        assert_eq!(title.alpha(), 0.5);
+        title.update(2.0);
+        assert_eq!(title.alpha(), 1.0);
+        title.update(1.0);
+        assert_eq!(title.alpha(), 0.5);
+        assert!(!title.is_over());
+        title.update(0.5);
+        assert!(title.is_over());
+
+        // Without fading, the title is shown at once
+        let title = ShownTitle::new("Wave 3".to_owned(), String::new(), 0.0, 1.0, 0.0);
+        assert_eq!(title.alpha(), 1.0);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::render::Frustum;
+use nalgebra::{Vector3, Vector4};
+use voxel_rs_common::player::Waypoint;
+
+const TEXT_HEIGHT: i32 = 20;
+/// Height of the waypoint beams, in blocks
+const BEAM_HEIGHT: f64 = 48.0;
+/// Width of the waypoint beams, in pixels
+const BEAM_WIDTH: f32 = 4.0;
+/// Opacity of the waypoint beams
+const BEAM_ALPHA: f32 = 0.4;
+/// Distance between the labels of the waypoints that are not on the screen and the border of the screen
+const LABEL_MARGIN: f32 = 30.0;
+const LIST_WIDTH: i32 = 260;
+/// Ids of the remove buttons of the waypoint list, after the ids of the debug info buttons
+const REMOVE_BUTTON_ID: u32 = 1 << 16;
+
+/// Draw the list of the waypoints with their positions, starting at `(x, y)`.
+/// Return the index of the waypoint whose remove button was clicked, if any.
+pub fn render_waypoint_list(
+    gui: &mut super::Gui,
+    x: i32,
+    y: i32,
+    waypoints: &[Waypoint],
+) -> Option<usize> {
+    let mut removed = None;
+    for (i, waypoint) in waypoints.iter().enumerate() {
+        let line_y = y + i as i32 * (TEXT_HEIGHT + 6);
+        gui.primitives
+            .draw_rect(x, line_y, 4, TEXT_HEIGHT, waypoint.color, 0.01);
+        gui.text(
+            x + 8,
+            line_y,
+            TEXT_HEIGHT,
+            format!(
+                "{} ({}, {}, {})",
+                waypoint.name, waypoint.x, waypoint.y, waypoint.z
+            ),
+            gui.theme.text,
+            0.005,
+        );
+        if gui
+            .button(
+                REMOVE_BUTTON_ID + i as u32,
+                x + LIST_WIDTH - TEXT_HEIGHT,
+                line_y,
+                TEXT_HEIGHT,
+                TEXT_HEIGHT,
+            )
+            .text(" x".to_owned(), gui.theme.text)
+            .build()
+        {
+            removed = Some(i);
+        }
+    }
+    removed
+}
+
+/// Draw a beam and a label with the name and the distance of every waypoint closer than `max_distance` blocks.
+/// The labels of the waypoints that are not on the screen are drawn on the border of the screen,
+/// in the direction of the waypoint.
+pub fn render_waypoint_labels(
+    gui: &mut super::Gui,
+    frustum: &Frustum,
+    width: f32,
+    height: f32,
+    waypoints: &[Waypoint],
+    max_distance: f64,
+) {
+    let view = frustum.get_view_matrix();
+    let view_projection = frustum.get_view_projection(width as f64 / height as f64);
+    let to_screen = |ndc_x: f64, ndc_y: f64| {
+        (
+            ((ndc_x + 1.0) / 2.0 * width as f64) as f32,
+            ((1.0 - ndc_y) / 2.0 * height as f64) as f32,
+        )
+    };
+
+    for waypoint in waypoints {
+        let base = Vector3::new(
+            waypoint.x as f64 + 0.5,
+            waypoint.y as f64,
+            waypoint.z as f64 + 0.5,
+        );
+        let distance = (base - frustum.position).norm();
+        if distance > max_distance {
+            continue;
+        }
+        let label = format!("{} ({:.0} m)", waypoint.name, distance);
+
+        let clip = view_projection * Vector4::new(base.x, base.y, base.z, 1.0);
+        let on_screen = clip.w > 0.0 && clip.x.abs() <= clip.w && clip.y.abs() <= clip.w;
+        if on_screen {
+            let (x, y) = to_screen(clip.x / clip.w, clip.y / clip.w);
+            // Beam going up from the waypoint
+            let top = base + Vector3::new(0.0, BEAM_HEIGHT, 0.0);
+            let top_clip = view_projection * Vector4::new(top.x, top.y, top.z, 1.0);
+            if top_clip.w > 0.0 {
+                let (tx, ty) = to_screen(top_clip.x / top_clip.w, top_clip.y / top_clip.w);
+                let half = BEAM_WIDTH / 2.0;
+                let mut color = waypoint.color;
+                color[3] = BEAM_ALPHA;
+                gui.primitives.draw_triangles(
+                    vec![
+                        [x - half, y, 0.9],
+                        [x + half, y, 0.9],
+                        [tx - half, ty, 0.9],
+                        [tx + half, ty, 0.9],
+                    ],
+                    vec![0, 1, 2, 1, 3, 2],
+                    color,
+                );
+            }
+            render_label(gui, x, y, label, waypoint.color);
+        } else {
+            // Use the direction of the waypoint in view space, which is also correct behind the camera
+            let view_pos = view * Vector4::new(base.x, base.y, base.z, 1.0);
+            let (mut dx, mut dy) = (view_pos.x, view_pos.y);
+            if dx == 0.0 && dy == 0.0 {
+                dy = -1.0;
+            }
+            let scale = f64::max(dx.abs(), dy.abs());
+            dx /= scale;
+            dy /= scale;
+            let (x, y) = to_screen(dx, dy);
+            let x = x.max(LABEL_MARGIN).min(width - LABEL_MARGIN);
+            let y = y.max(LABEL_MARGIN).min(height - LABEL_MARGIN);
+            render_label(gui, x, y, label, waypoint.color);
+        }
+    }
+}
+
+/// Draw a label centered horizontally on `x`, just above `y`
+fn render_label(gui: &mut super::Gui, x: f32, y: f32, label: String, color: [f32; 4]) {
+    // Approximate width of the text
+    let text_width = label.chars().count() as i32 * TEXT_HEIGHT / 2;
+    let (x, y) = (x as i32 - text_width / 2, y as i32 - TEXT_HEIGHT);
+    gui.primitives
+        .draw_rect(x - 4, y, text_width + 8, TEXT_HEIGHT, gui.theme.panel, 0.1);
+    gui.primitives
+        .draw_rect(x - 4, y + TEXT_HEIGHT - 2, text_width + 8, 2, color, 0.09);
+    gui.text(x, y, TEXT_HEIGHT, label, gui.theme.text, 0.08);
+}
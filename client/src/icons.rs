@@ -0,0 +1,322 @@
+//! Icons of the items, baked from their voxel models with a fixed isometric camera.
+//! The icons are packed into an atlas that the GUI draws from, and every icon is cached in `cache/icons`
+//! under a hash of its model, so that the models are only rendered again when they change.
+
+use image::{ImageBuffer, Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use voxel_rs_common::{
+    data::vox::VoxelModel,
+    item::{Item, ItemMesh},
+    registry::Registry,
+};
+
+/// Size of an icon in pixels. It must be a power of two so that the atlas is one as well.
+pub const ICON_SIZE: u32 = 64;
+/// The icons are rendered this many times larger, then downsampled to smooth their edges
+const SUPERSAMPLING: u32 = 4;
+/// Empty border around the model in an icon, in pixels
+const ICON_PADDING: f32 = 3.0;
+/// Brightness of the top, right (+x) and left (+z) faces of the voxels
+const FACE_SHADES: [f32; 3] = [1.0, 0.8, 0.6];
+/// Folder containing the cached icons
+const CACHE_FOLDER: &str = "cache/icons";
+/// Changing the way the icons are rendered must change this version, to invalidate the cached icons
+const BAKE_VERSION: u64 = 1;
+
+/// The icons of the items, packed into a square atlas
+pub struct ItemIcons {
+    pub atlas: RgbaImage,
+    /// The rectangle `[x, y, width, height]` of the icon of every item in the atlas, in texture coordinates
+    pub uvs: HashMap<u32, [f32; 4]>,
+}
+
+impl ItemIcons {
+    /// Bake the icons of all the items, reusing the cached icons when their model didn't change
+    pub fn bake(
+        items: &Registry<Item>,
+        item_meshes: &[ItemMesh],
+        models: &Registry<VoxelModel>,
+    ) -> Self {
+        let item_count = items.get_number_of_ids();
+        let icons_per_row = ((item_count as f64).sqrt().ceil() as u32).max(1);
+        let atlas_size = (icons_per_row * ICON_SIZE).next_power_of_two();
+        let mut atlas = ImageBuffer::new(atlas_size, atlas_size);
+        let mut uvs = HashMap::new();
+        let (mut baked, mut cached) = (0, 0);
+
+        for item in 0..item_count {
+            let model = match item_meshes.get(item as usize) {
+                Some(ItemMesh::SimpleMesh { mesh_id, .. }) => models.get_value_by_id(*mesh_id),
+                None => None,
+            };
+            let model = match model {
+                Some(model) => model,
+                None => continue,
+            };
+            let cache_path = cache_path(model);
+            let icon = match load_cached_icon(&cache_path) {
+                Some(icon) => {
+                    cached += 1;
+                    icon
+                }
+                None => {
+                    baked += 1;
+                    let icon = render_icon(model);
+                    save_cached_icon(&cache_path, &icon);
+                    icon
+                }
+            };
+
+            let (x, y) = (
+                (item % icons_per_row) * ICON_SIZE,
+                (item / icons_per_row) * ICON_SIZE,
+            );
+            for (px, py, pixel) in icon.enumerate_pixels() {
+                atlas.put_pixel(x + px, y + py, *pixel);
+            }
+            let size = ICON_SIZE as f32 / atlas_size as f32;
+            uvs.insert(
+                item,
+                [
+                    x as f32 / atlas_size as f32,
+                    y as f32 / atlas_size as f32,
+                    size,
+                    size,
+                ],
+            );
+        }
+
+        log::info!(
+            "Item icons ready: {} baked, {} loaded from the cache",
+            baked,
+            cached
+        );
+        Self { atlas, uvs }
+    }
+}
+
+/// Hash the contents of a model with FNV-1a, which unlike the std hasher is stable between builds
+fn model_hash(model: &VoxelModel) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut write = |value: u64| {
+        for byte in value.to_le_bytes().iter() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    write(BAKE_VERSION);
+    write(ICON_SIZE as u64);
+    write(model.size_x as u64);
+    write(model.size_y as u64);
+    write(model.size_z as u64);
+    for (&voxel, &full) in model.voxels.iter().zip(model.full.iter()) {
+        write(if full { voxel as u64 | 1 << 32 } else { 0 });
+    }
+    hash
+}
+
+fn cache_path(model: &VoxelModel) -> PathBuf {
+    Path::new(CACHE_FOLDER).join(format!("{:016x}.png", model_hash(model)))
+}
+
+fn load_cached_icon(path: &Path) -> Option<RgbaImage> {
+    if !path.exists() {
+        return None;
+    }
+    match image::open(path) {
+        Ok(icon) => {
+            let icon = icon.to_rgba();
+            if icon.dimensions() == (ICON_SIZE, ICON_SIZE) {
+                Some(icon)
+            } else {
+                log::warn!(
+                    "Ignoring cached icon {} with the wrong size",
+                    path.display()
+                );
+                None
+            }
+        }
+        Err(e) => {
+            log::warn!("Couldn't read cached icon {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn save_cached_icon(path: &Path, icon: &RgbaImage) {
+    if let Err(e) = std::fs::create_dir_all(CACHE_FOLDER) {
+        log::warn!(
+            "Couldn't create the icon cache folder {}: {}",
+            CACHE_FOLDER,
+            e
+        );
+        return;
+    }
+    if let Err(e) = icon.save(path) {
+        log::warn!("Couldn't cache icon {}: {}", path.display(), e);
+    }
+}
+
+/// Project a point of the model on the icon, before scaling.
+/// The camera looks at the model from the `(+x, +y, +z)` direction, and the y axis points up.
+fn project(x: f32, y: f32, z: f32) -> (f32, f32) {
+    const COS_30: f32 = 0.866_025_4;
+    ((x - z) * COS_30, (x + z) * 0.5 - y)
+}
+
+/// Render a model to an icon, drawing the visible faces of its voxels from back to front
+fn render_icon(model: &VoxelModel) -> RgbaImage {
+    let (size_x, size_y, size_z) = (model.size_x, model.size_y, model.size_z);
+    let index = |x: usize, y: usize, z: usize| x * size_y * size_z + y * size_z + z;
+    let is_full = |x: usize, y: usize, z: usize| {
+        x < size_x && y < size_y && z < size_z && model.full[index(x, y, z)]
+    };
+
+    // Fit the projection of the bounding box of the model in the icon
+    let (mut min, mut max) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+    for &x in [0, size_x].iter() {
+        for &y in [0, size_y].iter() {
+            for &z in [0, size_z].iter() {
+                let (u, v) = project(x as f32, y as f32, z as f32);
+                min = (min.0.min(u), min.1.min(v));
+                max = (max.0.max(u), max.1.max(v));
+            }
+        }
+    }
+    let render_size = (ICON_SIZE * SUPERSAMPLING) as usize;
+    let padding = ICON_PADDING * SUPERSAMPLING as f32;
+    let scale = (render_size as f32 - 2.0 * padding) / (max.0 - min.0).max(max.1 - min.1).max(1.0);
+    let offset = (
+        (render_size as f32 - (max.0 - min.0) * scale) / 2.0 - min.0 * scale,
+        (render_size as f32 - (max.1 - min.1) * scale) / 2.0 - min.1 * scale,
+    );
+    let to_pixels = |x: f32, y: f32, z: f32| {
+        let (u, v) = project(x, y, z);
+        (u * scale + offset.0, v * scale + offset.1)
+    };
+
+    // The voxels further from the camera are drawn first
+    let mut voxels = Vec::new();
+    for x in 0..size_x {
+        for y in 0..size_y {
+            for z in 0..size_z {
+                if model.full[index(x, y, z)] {
+                    voxels.push((x, y, z));
+                }
+            }
+        }
+    }
+    voxels.sort_by_key(|&(x, y, z)| x + y + z);
+
+    let mut pixels = vec![[0.0f32; 4]; render_size * render_size];
+    for (x, y, z) in voxels {
+        let color = model.voxels[index(x, y, z)];
+        let rgb = [
+            (color & 0xFF) as f32 / 255.0,
+            ((color >> 8) & 0xFF) as f32 / 255.0,
+            ((color >> 16) & 0xFF) as f32 / 255.0,
+        ];
+        let (fx, fy, fz) = (x as f32, y as f32, z as f32);
+        // Each visible face as a corner and its two edges
+        let faces = [
+            (
+                !is_full(x, y + 1, z),
+                (fx, fy + 1.0, fz),
+                (1.0, 0.0, 0.0),
+                (0.0, 0.0, 1.0),
+            ),
+            (
+                !is_full(x + 1, y, z),
+                (fx + 1.0, fy, fz),
+                (0.0, 1.0, 0.0),
+                (0.0, 0.0, 1.0),
+            ),
+            (
+                !is_full(x, y, z + 1),
+                (fx, fy, fz + 1.0),
+                (1.0, 0.0, 0.0),
+                (0.0, 1.0, 0.0),
+            ),
+        ];
+        for (face, &(visible, corner, edge1, edge2)) in faces.iter().enumerate() {
+            if !visible {
+                continue;
+            }
+            let shade = FACE_SHADES[face];
+            let color = [rgb[0] * shade, rgb[1] * shade, rgb[2] * shade, 1.0];
+            let origin = to_pixels(corner.0, corner.1, corner.2);
+            let a = to_pixels(corner.0 + edge1.0, corner.1 + edge1.1, corner.2 + edge1.2);
+            let b = to_pixels(corner.0 + edge2.0, corner.1 + edge2.1, corner.2 + edge2.2);
+            fill_parallelogram(
+                &mut pixels,
+                render_size,
+                origin,
+                (a.0 - origin.0, a.1 - origin.1),
+                (b.0 - origin.0, b.1 - origin.1),
+                color,
+            );
+        }
+    }
+
+    // Downsample, averaging the colors of the covered samples
+    ImageBuffer::from_fn(ICON_SIZE, ICON_SIZE, |px, py| {
+        let mut sum = [0.0; 4];
+        for sy in 0..SUPERSAMPLING {
+            for sx in 0..SUPERSAMPLING {
+                let sample = pixels[(py * SUPERSAMPLING + sy) as usize * render_size
+                    + (px * SUPERSAMPLING + sx) as usize];
+                for c in 0..3 {
+                    sum[c] += sample[c] * sample[3];
+                }
+                sum[3] += sample[3];
+            }
+        }
+        if sum[3] == 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let samples = (SUPERSAMPLING * SUPERSAMPLING) as f32;
+        Rgba([
+            (sum[0] / sum[3] * 255.0).round() as u8,
+            (sum[1] / sum[3] * 255.0).round() as u8,
+            (sum[2] / sum[3] * 255.0).round() as u8,
+            (sum[3] / samples * 255.0).round() as u8,
+        ])
+    })
+}
+
+/// Fill the pixels whose center is inside the parallelogram `origin + s * a + t * b` for `s` and `t` in `[0, 1]`
+fn fill_parallelogram(
+    pixels: &mut [[f32; 4]],
+    size: usize,
+    origin: (f32, f32),
+    a: (f32, f32),
+    b: (f32, f32),
+    color: [f32; 4],
+) {
+    let det = a.0 * b.1 - a.1 * b.0;
+    if det.abs() < 1e-6 {
+        return;
+    }
+    let corners = [
+        origin,
+        (origin.0 + a.0, origin.1 + a.1),
+        (origin.0 + b.0, origin.1 + b.1),
+        (origin.0 + a.0 + b.0, origin.1 + a.1 + b.1),
+    ];
+    let clamp = |v: f32| (v.max(0.0) as usize).min(size);
+    let min_x = clamp(corners.iter().map(|c| c.0).fold(f32::MAX, f32::min).floor());
+    let max_x = clamp(corners.iter().map(|c| c.0).fold(f32::MIN, f32::max).ceil());
+    let min_y = clamp(corners.iter().map(|c| c.1).fold(f32::MAX, f32::min).floor());
+    let max_y = clamp(corners.iter().map(|c| c.1).fold(f32::MIN, f32::max).ceil());
+    for py in min_y..max_y {
+        for px in min_x..max_x {
+            let (dx, dy) = (px as f32 + 0.5 - origin.0, py as f32 + 0.5 - origin.1);
+            let s = (dx * b.1 - dy * b.0) / det;
+            let t = (a.0 * dy - a.1 * dx) / det;
+            if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) {
+                pixels[py * size + px] = color;
+            }
+        }
+    }
+}
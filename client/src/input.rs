@@ -1,3 +1,4 @@
+use crate::settings::MouseSettings;
 use std::collections::HashMap;
 use voxel_rs_common::debug::send_debug_info;
 use voxel_rs_common::player::PlayerInput;
@@ -11,12 +12,10 @@ pub struct YawPitch {
 }
 
 impl YawPitch {
-    // TODO: Allow mouse inverting
-    pub fn update_cursor(&mut self, dx: f64, dy: f64) {
-        // TODO: don't hardcode this
-        let mouse_speed: f64 = 0.2;
-        self.yaw -= mouse_speed * (dx as f64);
-        self.pitch -= mouse_speed * (dy as f64);
+    /// Turn the camera by some angles in degrees, the positive angles turn it to the right and down
+    pub fn rotate(&mut self, dx: f64, dy: f64) {
+        self.yaw -= dx;
+        self.pitch -= dy;
 
         // Ensure the yaw stays within [-180; 180]
         if self.yaw < -180.0 {
@@ -45,6 +44,54 @@ impl Default for YawPitch {
     }
 }
 
+/// Turns the mouse movements into camera rotations, with the sensitivity, the acceleration and the smoothing
+/// of the mouse settings
+#[derive(Debug, Default)]
+pub struct MouseFilter {
+    /// The movement received since the last frame, in mouse counts
+    received: (f64, f64),
+    /// The rotation in degrees that the smoothing didn't apply yet
+    pending: (f64, f64),
+}
+
+impl MouseFilter {
+    pub fn add_movement(&mut self, dx: f64, dy: f64) {
+        self.received.0 += dx;
+        self.received.1 += dy;
+    }
+
+    /// Get the rotation in degrees to apply this frame
+    pub fn rotation(
+        &mut self,
+        settings: &MouseSettings,
+        invert_y: bool,
+        seconds_delta: f64,
+    ) -> (f64, f64) {
+        let (dx, dy) = std::mem::take(&mut self.received);
+        // The faster the mouse moves, the more sensitive it is
+        let speed = (dx * dx + dy * dy).sqrt() / seconds_delta.max(1e-3);
+        let acceleration = 1.0 + settings.acceleration * speed / 1000.0;
+        let invert = if invert_y { -1.0 } else { 1.0 };
+        self.pending.0 += dx * settings.sensitivity_x * acceleration;
+        self.pending.1 += dy * settings.sensitivity_y * acceleration * invert;
+        // The smoothing applies the rotation exponentially over time, it always applies all of it eventually
+        let applied = if settings.smoothing > 0.0 {
+            1.0 - (-seconds_delta / settings.smoothing).exp()
+        } else {
+            1.0
+        };
+        let rotation = (self.pending.0 * applied, self.pending.1 * applied);
+        self.pending.0 -= rotation.0;
+        self.pending.1 -= rotation.1;
+        rotation
+    }
+
+    /// Forget the movements, for example when the camera stops following the mouse
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
 /// The state of the keyboard and mouse buttons.
 pub struct InputState {
     keys: HashMap<u32, ElementState>,
@@ -133,6 +180,7 @@ impl InputState {
             key_move_right: allow_movement && self.is_key_pressed(MOVE_RIGHT),
             key_move_up: allow_movement && self.is_key_pressed(MOVE_UP),
             key_move_down: allow_movement && self.is_key_pressed(MOVE_DOWN),
+            key_sprint: allow_movement && self.is_key_pressed(SPRINT),
             yaw: yaw_pitch.yaw,
             pitch: yaw_pitch.pitch,
             flying: self.flying,
@@ -146,5 +194,31 @@ pub const MOVE_BACKWARD: u32 = 31;
 pub const MOVE_RIGHT: u32 = 32;
 pub const MOVE_UP: u32 = 57;
 pub const MOVE_DOWN: u32 = 42;
+pub const SPRINT: u32 = 29;
 pub const TOGGLE_FLIGHT: u32 = 33;
 pub const TOGGLE_CULLING: u32 = 46;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mouse_smoothing() {
+        let settings = MouseSettings {
+            smoothing: 0.05,
+            ..MouseSettings::default()
+        };
+        let mut filter = MouseFilter::default();
+        filter.add_movement(100.0, -20.0);
+        let mut total = filter.rotation(&settings, true, 1.0 / 60.0);
+        // The smoothing spreads the rotation over the next frames
+        assert!(total.0 > 0.0 && total.0 < 100.0 * settings.sensitivity_x);
+        for _ in 0..120 {
+            let (dx, dy) = filter.rotation(&settings, true, 1.0 / 60.0);
+            total.0 += dx;
+            total.1 += dy;
+        }
+        assert!((total.0 - 100.0 * settings.sensitivity_x).abs() < 1e-6);
+        assert!((total.1 - 20.0 * settings.sensitivity_y).abs() < 1e-6);
+    }
+}
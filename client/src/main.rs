@@ -1,12 +1,25 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use std::path::Path;
+use std::time::Duration;
+use voxel_rs_common::network::emulation::NetworkConditions;
 
+mod ambience;
+mod animation;
+mod audio;
+mod bobbing;
+mod flicker;
 mod fps;
 mod gui;
+mod icons;
 mod input;
+mod minimap;
 mod render;
+mod screen_effects;
 mod settings;
 mod singleplayer;
+mod skin;
+mod spectate;
+mod step_smoothing;
 mod texture;
 mod ui;
 mod window;
@@ -18,12 +31,52 @@ fn main() -> Result<()> {
     log::info!("Starting up...");
     let config_folder = Path::new("config");
     let config_file = Path::new("config/settings.toml");
-    let settings = settings::load_settings(&config_folder, &config_file)?;
+    let mut settings = settings::load_settings(&config_folder, &config_file)?;
+    settings.net_emulation = parse_net_emulation(std::env::args().skip(1))?;
     log::info!("Current settings: {:?}", settings);
 
     window::open_window(
         settings,
         // Box::new(singleplayer::SinglePlayer::new_factory(Box::new(client))),
-        ui::mainmenu::MainMenu::new_factory(),
+        ui::loading::LoadingScreen::new_factory(),
     )
 }
+
+/// Parse the `--net-lag <ms>`, `--net-jitter <ms>` and `--net-loss <percent>` options of the network emulation.
+/// The network is only emulated if at least one of them is given.
+fn parse_net_emulation(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Option<NetworkConditions>> {
+    let mut conditions = NetworkConditions {
+        lag: Duration::from_millis(0),
+        jitter: Duration::from_millis(0),
+        loss: 0.0,
+    };
+    let mut emulate = false;
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--net-lag" | "--net-jitter" | "--net-loss" => args
+                .next()
+                .with_context(|| format!("Missing value after {}", arg))?,
+            _ => bail!("Unknown command line option {}", arg),
+        };
+        let number: f64 = value
+            .parse()
+            .with_context(|| format!("Invalid value {} for {}", value, arg))?;
+        if !number.is_finite() || number < 0.0 {
+            bail!("The value of {} can't be negative", arg);
+        }
+        match arg.as_str() {
+            "--net-lag" => conditions.lag = Duration::from_secs_f64(number / 1000.0),
+            "--net-jitter" => conditions.jitter = Duration::from_secs_f64(number / 1000.0),
+            _ => {
+                if number > 100.0 {
+                    bail!("The value of --net-loss is a percentage, it can't be above 100");
+                }
+                conditions.loss = number / 100.0;
+            }
+        }
+        emulate = true;
+    }
+    Ok(if emulate { Some(conditions) } else { None })
+}
@@ -0,0 +1,159 @@
+//! Top-down map of the explored chunks
+use image::{ImageBuffer, Rgba};
+use std::collections::HashMap;
+use voxel_rs_common::{
+    block::{BlockId, BlockMesh},
+    data::compute_block_colors,
+    world::{Chunk, ChunkPosXZ, CHUNK_SIZE},
+};
+
+/// Color of the parts of the map that were not explored yet
+const UNEXPLORED_COLOR: [u8; 4] = [0, 0, 0, 160];
+/// Brightness change of a block that is higher or lower than the block to its north
+const RELIEF_SHADING: f32 = 0.15;
+
+/// The highest visible block of a column of blocks
+#[derive(Debug, Clone, Copy)]
+struct BlockTop {
+    y: i64,
+    block: BlockId,
+}
+
+const NO_BLOCK: BlockTop = BlockTop {
+    y: i64::MIN,
+    block: 0,
+};
+
+/// The map of a chunk column
+struct MapColumn {
+    /// The highest visible block of each explored chunk of the column, by chunk height
+    chunk_tops: HashMap<i64, Vec<BlockTop>>,
+    /// The color of each `(x, z)` position of the column
+    colors: Vec<[u8; 4]>,
+}
+
+/// Top-down color map built from the highest visible block of the explored chunks.
+/// It is kept when the chunks are unloaded.
+pub struct Minimap {
+    /// The map color of each block
+    block_colors: Vec<[u8; 4]>,
+    columns: HashMap<ChunkPosXZ, MapColumn>,
+    /// Incremented every time the map changes
+    version: u64,
+}
+
+impl Minimap {
+    /// Create an empty map, using the average color of the top texture of each block
+    pub fn new(block_meshes: &[BlockMesh], texture_atlas: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Self {
+        let block_colors = compute_block_colors(block_meshes, texture_atlas);
+        Self {
+            block_colors,
+            columns: HashMap::new(),
+            version: 0,
+        }
+    }
+
+    /// Update the map with a new or modified chunk
+    pub fn update_chunk(&mut self, chunk: &Chunk) {
+        let mut tops = vec![NO_BLOCK; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        for i in 0..CHUNK_SIZE {
+            for k in 0..CHUNK_SIZE {
+                for j in (0..CHUNK_SIZE).rev() {
+                    let block = chunk.get_block_at((i, j, k));
+                    if block != 0 {
+                        tops[(i * CHUNK_SIZE + k) as usize] = BlockTop {
+                            y: chunk.pos.py * CHUNK_SIZE as i64 + j as i64,
+                            block,
+                        };
+                        break;
+                    }
+                }
+            }
+        }
+
+        let column = self
+            .columns
+            .entry(chunk.pos.into())
+            .or_insert_with(|| MapColumn {
+                chunk_tops: HashMap::new(),
+                colors: vec![UNEXPLORED_COLOR; (CHUNK_SIZE * CHUNK_SIZE) as usize],
+            });
+        column.chunk_tops.insert(chunk.pos.py, tops);
+
+        // Merge the chunks of the column
+        let mut column_tops = vec![NO_BLOCK; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        for chunk_tops in column.chunk_tops.values() {
+            for (column_top, chunk_top) in column_tops.iter_mut().zip(chunk_tops.iter()) {
+                if chunk_top.y > column_top.y {
+                    *column_top = *chunk_top;
+                }
+            }
+        }
+
+        // Compute the colors, shading the slopes
+        for i in 0..CHUNK_SIZE as usize {
+            for k in 0..CHUNK_SIZE as usize {
+                let top = column_tops[i * CHUNK_SIZE as usize + k];
+                column.colors[i * CHUNK_SIZE as usize + k] = if top.y == i64::MIN {
+                    UNEXPLORED_COLOR
+                } else {
+                    let color = self.block_colors[top.block as usize];
+                    let shading = if k == 0 {
+                        1.0
+                    } else {
+                        let north = column_tops[i * CHUNK_SIZE as usize + k - 1].y;
+                        if north == i64::MIN || north == top.y {
+                            1.0
+                        } else if north < top.y {
+                            1.0 + RELIEF_SHADING
+                        } else {
+                            1.0 - RELIEF_SHADING
+                        }
+                    };
+                    let shade = |c: u8| (c as f32 * shading).min(255.0) as u8;
+                    [shade(color[0]), shade(color[1]), shade(color[2]), color[3]]
+                };
+            }
+        }
+
+        self.version += 1;
+    }
+
+    /// Incremented every time the map changes
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Render the `size` x `size` blocks around `(center_x, center_z)` to an RGBA image.
+    /// The x axis goes to the right of the image, and the z axis goes down.
+    pub fn render_image(&self, center_x: i64, center_z: i64, size: u32) -> Vec<u8> {
+        let mut image = vec![0; (size * size * 4) as usize];
+        let (x0, z0) = (center_x - size as i64 / 2, center_z - size as i64 / 2);
+        let chunk_size = CHUNK_SIZE as i64;
+        // Copy the map column by column
+        for cx in x0.div_euclid(chunk_size)..=(x0 + size as i64 - 1).div_euclid(chunk_size) {
+            for cz in z0.div_euclid(chunk_size)..=(z0 + size as i64 - 1).div_euclid(chunk_size) {
+                let column = self.columns.get(&ChunkPosXZ::from((cx, cz)));
+                for i in 0..chunk_size {
+                    let x = cx * chunk_size + i;
+                    if x < x0 || x >= x0 + size as i64 {
+                        continue;
+                    }
+                    for k in 0..chunk_size {
+                        let z = cz * chunk_size + k;
+                        if z < z0 || z >= z0 + size as i64 {
+                            continue;
+                        }
+                        let color = match column {
+                            Some(column) => column.colors[(i * chunk_size + k) as usize],
+                            None => UNEXPLORED_COLOR,
+                        };
+                        let pixel = (((z - z0) * size as i64 + (x - x0)) * 4) as usize;
+                        image[pixel..pixel + 4].copy_from_slice(&color);
+                    }
+                }
+            }
+        }
+        image
+    }
+}
@@ -3,12 +3,14 @@
 use std::collections::HashMap;
 use std::hash::Hash;
 
-use super::{buffer_from_slice, to_u8_slice};
+use super::memory::{self, MemoryCategory, TrackedBuffer};
+use super::UploadBelt;
 
 /// A buffer that will automatically resize itself when necessary
 pub struct DynamicBuffer<T: Copy> {
-    buffer: wgpu::Buffer,
+    buffer: TrackedBuffer,
     usage: wgpu::BufferUsage,
+    category: MemoryCategory,
     capacity: usize,
     len: usize,
     phantom: std::marker::PhantomData<T>,
@@ -20,16 +22,22 @@ impl<T: Copy + 'static> DynamicBuffer<T> {
         device: &wgpu::Device,
         initial_capacity: usize,
         mut usage: wgpu::BufferUsage,
+        category: MemoryCategory,
     ) -> Self {
         usage |= wgpu::BufferUsage::COPY_DST;
         Self {
-            buffer: device.create_buffer(&wgpu::BufferDescriptor {
-                mapped_at_creation: false,
-                label: None,
-                size: (initial_capacity * std::mem::size_of::<T>()) as u64,
-                usage,
-            }),
+            buffer: memory::create_buffer(
+                device,
+                category,
+                &wgpu::BufferDescriptor {
+                    mapped_at_creation: false,
+                    label: None,
+                    size: (initial_capacity * std::mem::size_of::<T>()) as u64,
+                    usage,
+                },
+            ),
             usage,
+            category,
             capacity: initial_capacity,
             len: 0,
             phantom: std::marker::PhantomData,
@@ -41,6 +49,7 @@ impl<T: Copy + 'static> DynamicBuffer<T> {
         &mut self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
         data: &[T],
     ) {
         if data.is_empty() {
@@ -49,24 +58,20 @@ impl<T: Copy + 'static> DynamicBuffer<T> {
         }
 
         if data.len() > self.capacity {
-            self.buffer = device.create_buffer(&wgpu::BufferDescriptor {
-                mapped_at_creation: false,
-                label: None,
-                size: (data.len() * std::mem::size_of::<T>()) as u64,
-                usage: self.usage,
-            });
+            self.buffer = memory::create_buffer(
+                device,
+                self.category,
+                &wgpu::BufferDescriptor {
+                    mapped_at_creation: false,
+                    label: None,
+                    size: (data.len() * std::mem::size_of::<T>()) as u64,
+                    usage: self.usage,
+                },
+            );
             self.capacity = data.len();
         }
 
-        let src_buffer = buffer_from_slice(device, wgpu::BufferUsage::COPY_SRC, to_u8_slice(data));
-
-        encoder.copy_buffer_to_buffer(
-            &src_buffer,
-            0,
-            &self.buffer,
-            0,
-            (data.len() * std::mem::size_of::<T>()) as u64,
-        );
+        belt.upload(device, encoder, &self.buffer, 0, data);
         self.len = data.len();
     }
 
@@ -83,8 +88,9 @@ impl<T: Copy + 'static> DynamicBuffer<T> {
 
 /// A buffer that can contain multiple objects. Every object is of type `T` and can be accessed by a key of type `K`.
 pub struct MultiBuffer<K: Hash + Eq + Clone, T: Copy + 'static> {
-    buffer: wgpu::Buffer,
+    buffer: TrackedBuffer,
     usage: wgpu::BufferUsage,
+    category: MemoryCategory,
     objects: HashMap<K, usize>,
     segments: Vec<MultiBufferSegment>,
     len: usize,
@@ -99,17 +105,22 @@ impl<K: Hash + Eq + Clone + std::fmt::Debug, T: Copy + std::fmt::Debug + 'static
         device: &wgpu::Device,
         initial_capacity: usize,
         mut usage: wgpu::BufferUsage,
+        category: MemoryCategory,
     ) -> Self {
         // We crash on Vulkan if buffer capacity is 0
         assert!(initial_capacity > 0);
 
         usage |= wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::COPY_SRC;
-        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            mapped_at_creation: false,
-            size: (initial_capacity * std::mem::size_of::<T>()) as u64,
-            usage,
-        });
+        let buffer = memory::create_buffer(
+            device,
+            category,
+            &wgpu::BufferDescriptor {
+                label: None,
+                mapped_at_creation: false,
+                size: (initial_capacity * std::mem::size_of::<T>()) as u64,
+                usage,
+            },
+        );
         let segments = vec![MultiBufferSegment {
             free: true,
             pos: 0,
@@ -119,6 +130,7 @@ impl<K: Hash + Eq + Clone + std::fmt::Debug, T: Copy + std::fmt::Debug + 'static
         Self {
             buffer,
             usage,
+            category,
             objects: HashMap::new(),
             segments,
             len: initial_capacity,
@@ -163,6 +175,7 @@ impl<K: Hash + Eq + Clone + std::fmt::Debug, T: Copy + std::fmt::Debug + 'static
         &mut self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
         object: K,
         data: &[T],
     ) {
@@ -180,13 +193,12 @@ impl<K: Hash + Eq + Clone + std::fmt::Debug, T: Copy + std::fmt::Debug + 'static
             self.segments.len() - 1
         });
         // Copy data into the buffer
-        let src_buffer = buffer_from_slice(device, wgpu::BufferUsage::COPY_SRC, to_u8_slice(data));
-        encoder.copy_buffer_to_buffer(
-            &src_buffer,
-            0,
+        belt.upload(
+            device,
+            encoder,
             &self.buffer,
             (self.segments[insert_position].pos * std::mem::size_of::<T>()) as u64,
-            (data.len() * std::mem::size_of::<T>()) as u64,
+            data,
         );
         // Update current segment
         self.segments[insert_position].free = false;
@@ -228,12 +240,7 @@ impl<K: Hash + Eq + Clone + std::fmt::Debug, T: Copy + std::fmt::Debug + 'static
             new_len
         );
         // Create new buffer and copy data
-        let new_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            mapped_at_creation: false,
-            size: (new_len * std::mem::size_of::<T>()) as u64,
-            usage: self.usage,
-        });
+        let new_buffer = self.create_buffer(device, new_len);
         encoder.copy_buffer_to_buffer(
             &self.buffer,
             0,
@@ -256,6 +263,76 @@ impl<K: Hash + Eq + Clone + std::fmt::Debug, T: Copy + std::fmt::Debug + 'static
         self.len = new_len;
     }
 
+    fn create_buffer(&self, device: &wgpu::Device, len: usize) -> TrackedBuffer {
+        memory::create_buffer(
+            device,
+            self.category,
+            &wgpu::BufferDescriptor {
+                label: None,
+                mapped_at_creation: false,
+                size: (len * std::mem::size_of::<T>()) as u64,
+                usage: self.usage,
+            },
+        )
+    }
+
+    /// Move the objects to the start of a new buffer just large enough for them, releasing the free space
+    pub fn shrink_to_fit(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let used_len = self
+            .segments
+            .iter()
+            .filter(|seg| !seg.free)
+            .map(|seg| seg.len)
+            .sum::<usize>();
+        // The buffer can't be empty, see `with_capacity`
+        let new_len = used_len.max(1);
+        if new_len == self.len {
+            return;
+        }
+        log::trace!(
+            "Shrinking MultiBuffer<{}, {}> from length {} to length {}",
+            std::any::type_name::<K>(),
+            std::any::type_name::<T>(),
+            self.len,
+            new_len
+        );
+        let new_buffer = self.create_buffer(device, new_len);
+        let mut objects_by_pos: HashMap<usize, K> = self
+            .objects
+            .drain()
+            .map(|(object, pos)| (pos, object))
+            .collect();
+        let mut segments = Vec::new();
+        let mut pos = 0;
+        for seg in self.segments.iter().filter(|seg| !seg.free) {
+            encoder.copy_buffer_to_buffer(
+                &self.buffer,
+                (seg.pos * std::mem::size_of::<T>()) as u64,
+                &new_buffer,
+                (pos * std::mem::size_of::<T>()) as u64,
+                (seg.len * std::mem::size_of::<T>()) as u64,
+            );
+            let object = objects_by_pos.remove(&seg.pos).expect("logic error!");
+            self.objects.insert(object, pos);
+            segments.push(MultiBufferSegment {
+                free: false,
+                pos,
+                len: seg.len,
+            });
+            pos += seg.len;
+        }
+        if pos < new_len {
+            segments.push(MultiBufferSegment {
+                free: true,
+                pos,
+                len: new_len - pos,
+            });
+        }
+        self.buffer = new_buffer;
+        self.segments = segments;
+        self.len = new_len;
+    }
+
     fn _assert_invariants(&self) {
         assert_eq!(self.segments.first().unwrap().pos, 0);
         assert_eq!(
@@ -349,37 +426,47 @@ mod tests {
         ))
         .expect("Failed to request device.");
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+        let mut belt = UploadBelt::new();
 
         // Create initial buffer
-        let mut multi_buffer = MultiBuffer::with_capacity(&device, 10, BufferUsage::empty());
+        let mut multi_buffer =
+            MultiBuffer::with_capacity(&device, 10, BufferUsage::empty(), MemoryCategory::Other);
 
         let seg1 = [2u16, 3u16, 4u16];
         let seg2 = [5u16, 6u16, 7u16, 8u16];
         let seg3 = [9u16];
 
         // Single insert
-        multi_buffer.update(&device, &mut encoder, 0u16, &seg1);
+        multi_buffer.update(&device, &mut encoder, &mut belt, 0u16, &seg1);
         multi_buffer.remove(&0u16);
         assert_eq!(multi_buffer.get_pos_len(&0), None);
 
         // Double insert
-        multi_buffer.update(&device, &mut encoder, 1u16, &seg2);
+        multi_buffer.update(&device, &mut encoder, &mut belt, 1u16, &seg2);
         assert_eq!(multi_buffer.get_pos_len(&1), Some((0, 4)));
-        multi_buffer.update(&device, &mut encoder, 2u16, &seg2);
+        multi_buffer.update(&device, &mut encoder, &mut belt, 2u16, &seg2);
         assert_eq!(multi_buffer.get_pos_len(&2), Some((4, 4)));
         multi_buffer.remove(&1u16);
         assert_eq!(multi_buffer.get_pos_len(&1), None);
         assert_eq!(multi_buffer.get_pos_len(&2), Some((4, 4)));
 
         // Triple insert
-        multi_buffer.update(&device, &mut encoder, 0u16, &seg1);
+        multi_buffer.update(&device, &mut encoder, &mut belt, 0u16, &seg1);
         assert_eq!(multi_buffer.get_pos_len(&0), Some((0, 3)));
-        multi_buffer.update(&device, &mut encoder, 1u16, &seg3);
+        multi_buffer.update(&device, &mut encoder, &mut belt, 1u16, &seg3);
         assert_eq!(multi_buffer.get_pos_len(&1), Some((3, 1)));
         // Now we have 8 items
 
         // Reallocate
-        multi_buffer.update(&device, &mut encoder, 3u16, &seg2);
+        multi_buffer.update(&device, &mut encoder, &mut belt, 3u16, &seg2);
         assert_eq!(multi_buffer.get_pos_len(&3), Some((8, 4)));
+
+        // Shrink
+        multi_buffer.remove(&0u16);
+        multi_buffer.remove(&1u16);
+        multi_buffer.shrink_to_fit(&device, &mut encoder);
+        multi_buffer._assert_invariants();
+        assert_eq!(multi_buffer.get_pos_len(&2), Some((0, 4)));
+        assert_eq!(multi_buffer.get_pos_len(&3), Some((4, 4)));
     }
 }
@@ -0,0 +1,135 @@
+//! Culling of the entities and the props drawn as voxel models: the models that are too far or outside of
+//! the frustum are not drawn, and the far animated models are drawn in one piece instead of part by part.
+use super::frustum::{Frustum, Plane};
+use super::world::Model;
+use nalgebra::{Matrix4, Vector3};
+use voxel_rs_common::debug::send_debug_info;
+
+/// Beyond this distance in blocks, the models are drawn with less detail
+const LOD_DISTANCE: f64 = 32.0;
+
+/// How much detail a model is drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelDetail {
+    /// Every part of the model, posed by its animation
+    Full,
+    /// The whole model as a single mesh, without animation
+    Low,
+}
+
+/// Decides which models are drawn during a frame, and counts them for the debug overlay
+pub struct EntityCulling {
+    planes: [[Plane; 2]; 3],
+    view_matrix: Matrix4<f64>,
+    camera: Vector3<f64>,
+    /// The models farther than this distance in blocks are not drawn
+    max_distance: f64,
+    /// If false, all the models are drawn with full detail
+    enabled: bool,
+    drawn: usize,
+    culled: usize,
+}
+
+impl EntityCulling {
+    pub fn new(frustum: &Frustum, aspect_ratio: f64, max_distance: f64, enabled: bool) -> Self {
+        Self {
+            planes: frustum.get_planes(aspect_ratio),
+            view_matrix: frustum.get_view_matrix(),
+            camera: frustum.position,
+            max_distance,
+            enabled,
+            drawn: 0,
+            culled: 0,
+        }
+    }
+
+    /// Decide how to draw the entity whose whole model is `model`, with a mesh of `size` voxels,
+    /// or return `None` if it should not be drawn
+    pub fn check(&mut self, model: &Model, size: [usize; 3]) -> Option<ModelDetail> {
+        if !self.enabled {
+            self.drawn += 1;
+            return Some(ModelDetail::Full);
+        }
+        let (min, max) = model.bounding_box(size);
+        let (min, max) = (nalgebra::convert(min), nalgebra::convert(max));
+        // Distance from the camera to the closest point of the box
+        let closest: Vector3<f64> = self.camera.sup(&min).inf(&max);
+        let distance = (closest - self.camera).norm();
+        if distance > self.max_distance
+            || !Frustum::contains_box(&self.planes, &self.view_matrix, min, max)
+        {
+            self.culled += 1;
+            return None;
+        }
+        self.drawn += 1;
+        if distance > LOD_DISTANCE {
+            Some(ModelDetail::Low)
+        } else {
+            Some(ModelDetail::Full)
+        }
+    }
+
+    /// Show the number of drawn and culled entities in the debug overlay
+    pub fn send_debug_info(&self) {
+        send_debug_info(
+            "Render",
+            "renderedentities",
+            format!(
+                "{} entities were rendered, {} were culled",
+                self.drawn, self.culled
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::YawPitch;
+    use nalgebra::Isometry3;
+
+    fn model_at(x: f32, y: f32, z: f32) -> Model {
+        Model {
+            mesh_id: 0,
+            pos_x: x,
+            pos_y: y,
+            pos_z: z,
+            scale: 0.1,
+            rot_y: 0.0,
+            rot_offset: [0.0, 0.0, 0.0],
+            pose: Isometry3::identity(),
+            skin: None,
+        }
+    }
+
+    #[test]
+    fn test_entity_culling() {
+        // Looking towards -z
+        let frustum = Frustum::new(
+            Vector3::zeros(),
+            YawPitch {
+                yaw: 0.0,
+                pitch: 0.0,
+            },
+        );
+        let size = [10, 20, 10];
+        let mut culling = EntityCulling::new(&frustum, 1.0, 100.0, true);
+        assert_eq!(
+            culling.check(&model_at(0.0, 0.0, -5.0), size),
+            Some(ModelDetail::Full)
+        );
+        assert_eq!(
+            culling.check(&model_at(0.0, 0.0, -50.0), size),
+            Some(ModelDetail::Low)
+        );
+        assert_eq!(culling.check(&model_at(0.0, 0.0, -150.0), size), None);
+        assert_eq!(culling.check(&model_at(0.0, 10.0, 50.0), size), None);
+        assert_eq!((culling.drawn, culling.culled), (2, 2));
+
+        let mut culling = EntityCulling::new(&frustum, 1.0, 100.0, false);
+        assert_eq!(
+            culling.check(&model_at(0.0, 0.0, 50.0), size),
+            Some(ModelDetail::Full)
+        );
+    }
+}
@@ -112,22 +112,47 @@ impl Frustum {
         fn to_chunk_center(chunk_pos: i64) -> f64 {
             (chunk_pos * CHUNK_SIZE as i64 + CHUNK_SIZE as i64 / 2) as f64
         }
-        #[inline(always)]
-        fn to_vec3(v: Vector4<f64>) -> Vector3<f64> {
-            Vector3::new(v.x / v.w, v.y / v.w, v.z / v.w)
-        }
-        let chunk_center = Vector4::new(
+        let chunk_center = Vector3::new(
             to_chunk_center(chunk_pos.px),
             to_chunk_center(chunk_pos.py),
             to_chunk_center(chunk_pos.pz),
-            1.0,
         );
-        let chunk_center = to_vec3(view_matrix * chunk_center);
         let radius = CHUNK_SIZE as f64 * 3.0f64.sqrt() / 2.0;
+        Self::contains_sphere(planes, view_matrix, chunk_center, radius)
+    }
+
+    /// Checks whether the frustum contains the axis-aligned box between `min` and `max`.
+    /// This function may return false positives.
+    pub fn contains_box(
+        planes: &[[Plane; 2]; 3],
+        view_matrix: &Matrix4<f64>,
+        min: Vector3<f64>,
+        max: Vector3<f64>,
+    ) -> bool {
+        Self::contains_sphere(
+            planes,
+            view_matrix,
+            (min + max) / 2.0,
+            (max - min).norm() / 2.0,
+        )
+    }
+
+    fn contains_sphere(
+        planes: &[[Plane; 2]; 3],
+        view_matrix: &Matrix4<f64>,
+        center: Vector3<f64>,
+        radius: f64,
+    ) -> bool {
+        let center = view_matrix * Vector4::new(center.x, center.y, center.z, 1.0);
+        let center = Vector3::new(
+            center.x / center.w,
+            center.y / center.w,
+            center.z / center.w,
+        );
         let mut keep = false;
         for [plane1, plane2] in planes.iter() {
-            let d1 = plane1.dist(chunk_center);
-            let d2 = plane2.dist(chunk_center);
+            let d1 = plane1.dist(center);
+            let d2 = plane2.dist(center);
             if d1 > 0.0 && d2 > 0.0 {
                 // inside both
                 keep = true;
@@ -7,7 +7,9 @@ use iced_winit::Debug;
 use wgpu::Device;
 use winit::{dpi::LogicalPosition, event::ModifiersState};
 
-use crate::window::{WindowBuffers, WindowData, COLOR_FORMAT, PRESENT_MODE};
+use super::UploadBelt;
+use crate::gui::CursorStyle;
+use crate::window::{WindowBuffers, WindowData, COLOR_FORMAT};
 
 fn viewport_from_window_data(window_data: &WindowData) -> Viewport {
     Viewport::with_physical_size(
@@ -15,7 +17,7 @@ fn viewport_from_window_data(window_data: &WindowData) -> Viewport {
             window_data.physical_window_size.width,
             window_data.physical_window_size.height,
         ),
-        window_data.scale_factor,
+        window_data.gui_scale_factor(),
     )
 }
 
@@ -51,7 +53,7 @@ where
             device,
             iced_wgpu::Settings {
                 format: COLOR_FORMAT,
-                present_mode: PRESENT_MODE,
+                present_mode: crate::window::present_mode(false),
                 default_font: Some(include_bytes!(
                     "../../../assets/fonts/IBMPlexMono-SemiBold.ttf"
                 )),
@@ -110,6 +112,16 @@ where
         }
     }
 
+    /// Style of the cursor over the widget under the mouse
+    pub fn cursor_style(&self) -> CursorStyle {
+        use iced_native::mouse::Interaction;
+        match self.state.primitive().1 {
+            Interaction::Pointer => CursorStyle::Hand,
+            Interaction::Grab | Interaction::Grabbing => CursorStyle::Grab,
+            _ => CursorStyle::Arrow,
+        }
+    }
+
     pub fn handle_cursor_movement(&mut self, logical_position: winit::dpi::LogicalPosition<f64>) {
         self.cursor_position = logical_position;
     }
@@ -119,19 +131,17 @@ where
         device: &mut Device,
         buffers: WindowBuffers<'a>,
         mut encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
         debug_info: Option<Vec<String>>,
     ) {
-        let mut staging_belt = wgpu::util::StagingBelt::new(128);
         self.renderer.backend_mut().draw(
             device,
-            &mut staging_belt,
+            belt.staging_belt(),
             &mut encoder,
             buffers.texture_buffer,
             &self.viewport,
             self.state.primitive(),
             &debug_info.unwrap_or(vec![]),
         );
-
-        staging_belt.finish();
     }
 }
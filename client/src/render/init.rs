@@ -1,6 +1,33 @@
 //! Helpers for pipeline creation and initialization
 use std::path::Path;
 
+/// An error while creating the renderers, from the files in `assets`
+#[derive(Debug, thiserror::Error)]
+pub enum RenderInitError {
+    /// A shader file couldn't be read
+    #[error("couldn't read the shader {path}")]
+    ReadShader {
+        path: String,
+        #[source]
+        error: std::io::Error,
+    },
+    /// A shader couldn't be compiled to SPIR-V
+    #[error("couldn't compile the shader {path}: {message}")]
+    CompileShader { path: String, message: String },
+    /// A font list couldn't be read or parsed
+    #[error("invalid font list {path}: {message}")]
+    FontList { path: String, message: String },
+    /// A font file couldn't be read or parsed
+    #[error("couldn't load the font {path}: {message}")]
+    Font { path: String, message: String },
+    /// No GPU adapter of the backend can draw to the window
+    #[error("no GPU adapter of the {backend} backend can draw to the window")]
+    NoAdapter { backend: String },
+    /// The device of the adapter couldn't be created
+    #[error("couldn't create a device on the adapter {adapter}")]
+    RequestDevice { adapter: String },
+}
+
 /// Shader stage
 pub enum ShaderStage {
     Vertex,
@@ -8,21 +35,33 @@ pub enum ShaderStage {
 }
 
 /// Load a GLSL shader from a file and compile it to SPIR-V
-pub fn load_glsl_shader<'a, P: AsRef<Path>>(stage: ShaderStage, path: P) -> Vec<u8> {
+pub fn load_glsl_shader<'a, P: AsRef<Path>>(
+    stage: ShaderStage,
+    path: P,
+) -> Result<Vec<u8>, RenderInitError> {
     let ty = match stage {
         ShaderStage::Vertex => shaderc::ShaderKind::Vertex,
         ShaderStage::Fragment => shaderc::ShaderKind::Fragment,
     };
     let path_display = path.as_ref().display().to_string();
     log::info!("Loading GLSL shader from {}", path_display);
-    let glsl_source = std::fs::read_to_string(path).expect("Couldn't read shader from file");
+    let glsl_source =
+        std::fs::read_to_string(path).map_err(|error| RenderInitError::ReadShader {
+            path: path_display.clone(),
+            error,
+        })?;
 
-    let mut compiler = shaderc::Compiler::new().unwrap();
-    compiler
+    let mut compiler = shaderc::Compiler::new().ok_or_else(|| RenderInitError::CompileShader {
+        path: path_display.clone(),
+        message: "the shader compiler couldn't be created".to_owned(),
+    })?;
+    let spirv = compiler
         .compile_into_spirv(&glsl_source, ty, &path_display, "main", None)
-        .expect("Couldn't compile shader.")
-        .as_binary_u8()
-        .to_vec()
+        .map_err(|e| RenderInitError::CompileShader {
+            path: path_display.clone(),
+            message: e.to_string(),
+        })?;
+    Ok(spirv.as_binary_u8().to_vec())
 }
 
 /// Default `RasterizationStateDescriptor` with no backface culling
@@ -74,6 +113,13 @@ pub const DEFAULT_DEPTH_STENCIL_STATE_DESCRIPTOR: wgpu::DepthStencilStateDescrip
         },
     };
 
+/// `DepthStencilStateDescriptor` that tests the depth without writing it
+pub const NO_DEPTH_WRITE_DEPTH_STENCIL_STATE_DESCRIPTOR: wgpu::DepthStencilStateDescriptor =
+    wgpu::DepthStencilStateDescriptor {
+        depth_write_enabled: false,
+        ..DEFAULT_DEPTH_STENCIL_STATE_DESCRIPTOR
+    };
+
 /// Create a default pipeline
 pub fn create_default_pipeline(
     device: &wgpu::Device,
@@ -83,6 +129,54 @@ pub fn create_default_pipeline(
     primitive_topology: wgpu::PrimitiveTopology,
     vertex_buffer_descriptor: wgpu::VertexBufferDescriptor,
     cull_back_faces: bool,
+) -> wgpu::RenderPipeline {
+    create_pipeline(
+        device,
+        uniform_layout,
+        vertex_shader,
+        fragment_shader,
+        primitive_topology,
+        vertex_buffer_descriptor,
+        if cull_back_faces {
+            RASTERIZER_WITH_CULLING
+        } else {
+            RASTERIZER_NO_CULLING
+        },
+        DEFAULT_DEPTH_STENCIL_STATE_DESCRIPTOR,
+    )
+}
+
+/// Create a pipeline for the translucent geometry drawn over the world, without backface culling.
+/// It is hidden by the terrain in front of it, but doesn't hide what is behind it.
+pub fn create_translucent_pipeline(
+    device: &wgpu::Device,
+    uniform_layout: &wgpu::BindGroupLayout,
+    vertex_shader: wgpu::ShaderModuleSource,
+    fragment_shader: wgpu::ShaderModuleSource,
+    primitive_topology: wgpu::PrimitiveTopology,
+    vertex_buffer_descriptor: wgpu::VertexBufferDescriptor,
+) -> wgpu::RenderPipeline {
+    create_pipeline(
+        device,
+        uniform_layout,
+        vertex_shader,
+        fragment_shader,
+        primitive_topology,
+        vertex_buffer_descriptor,
+        RASTERIZER_NO_CULLING,
+        NO_DEPTH_WRITE_DEPTH_STENCIL_STATE_DESCRIPTOR,
+    )
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    uniform_layout: &wgpu::BindGroupLayout,
+    vertex_shader: wgpu::ShaderModuleSource,
+    fragment_shader: wgpu::ShaderModuleSource,
+    primitive_topology: wgpu::PrimitiveTopology,
+    vertex_buffer_descriptor: wgpu::VertexBufferDescriptor,
+    rasterization_state: wgpu::RasterizationStateDescriptor,
+    depth_stencil_state: wgpu::DepthStencilStateDescriptor,
 ) -> wgpu::RenderPipeline {
     // Shaders
     let vertex_shader_module = device.create_shader_module(vertex_shader);
@@ -112,16 +206,57 @@ pub fn create_default_pipeline(
             index_format: wgpu::IndexFormat::Uint32,
             vertex_buffers: &[vertex_buffer_descriptor],
         },
-        rasterization_state: Some(if cull_back_faces {
-            RASTERIZER_WITH_CULLING
-        } else {
-            RASTERIZER_NO_CULLING
-        }),
+        rasterization_state: Some(rasterization_state),
         primitive_topology,
         color_states: &DEFAULT_COLOR_STATE_DESCRIPTOR,
-        depth_stencil_state: Some(DEFAULT_DEPTH_STENCIL_STATE_DESCRIPTOR),
+        depth_stencil_state: Some(depth_stencil_state),
         sample_count: crate::window::SAMPLE_COUNT,
         sample_mask: 0xFFFFFFFF,
         alpha_to_coverage_enabled: false,
     })
 }
+
+/// Create a pipeline that draws directly to the (non-multisampled) window frame buffer, without depth testing.
+/// This is used for overlays that must be drawn after the multisampled frame buffer was resolved.
+pub fn create_overlay_pipeline(
+    device: &wgpu::Device,
+    uniform_layout: &wgpu::BindGroupLayout,
+    vertex_shader: wgpu::ShaderModuleSource,
+    fragment_shader: wgpu::ShaderModuleSource,
+    vertex_buffer_descriptor: wgpu::VertexBufferDescriptor,
+) -> wgpu::RenderPipeline {
+    let vertex_shader_module = device.create_shader_module(vertex_shader);
+    let fragment_shader_module = device.create_shader_module(fragment_shader);
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[uniform_layout],
+        push_constant_ranges: &[],
+    });
+
+    log::trace!("Creating overlay render pipeline.");
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vertex_shader_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fragment_shader_module,
+            entry_point: "main",
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[vertex_buffer_descriptor],
+        },
+        rasterization_state: Some(RASTERIZER_NO_CULLING),
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        color_states: &DEFAULT_COLOR_STATE_DESCRIPTOR,
+        depth_stencil_state: None,
+        sample_count: 1,
+        sample_mask: 0xFFFFFFFF,
+        alpha_to_coverage_enabled: false,
+    })
+}
@@ -0,0 +1,200 @@
+//! Tracking of the GPU memory allocated by the renderer.
+//! The buffers and the textures are created through `create_buffer`, `create_buffer_init` and `create_texture`,
+//! which count their size in their `MemoryCategory` until they are dropped.
+
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use wgpu::util::DeviceExt;
+
+/// What the GPU memory is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    /// The vertices and the indices of the chunks and of the far terrain
+    ChunkMeshes,
+    /// The vertices and the indices of the models, the skins and the signs
+    ModelMeshes,
+    /// The texture atlases, the sign texts and the minimap
+    Textures,
+    /// The buffers and the textures of the user interface
+    Ui,
+    /// The uniforms, the render targets and the temporary upload buffers
+    Other,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 5] = [
+        MemoryCategory::ChunkMeshes,
+        MemoryCategory::ModelMeshes,
+        MemoryCategory::Textures,
+        MemoryCategory::Ui,
+        MemoryCategory::Other,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryCategory::ChunkMeshes => "chunk meshes",
+            MemoryCategory::ModelMeshes => "model meshes",
+            MemoryCategory::Textures => "textures",
+            MemoryCategory::Ui => "ui",
+            MemoryCategory::Other => "other",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Bytes allocated in every category, in the order of `MemoryCategory::ALL`
+static ALLOCATED_BYTES: [AtomicU64; 5] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// Get the number of bytes currently allocated in `category`
+pub fn allocated_bytes(category: MemoryCategory) -> u64 {
+    ALLOCATED_BYTES[category.index()].load(Ordering::Relaxed)
+}
+
+/// Get the number of bytes currently allocated in all the categories
+pub fn total_allocated_bytes() -> u64 {
+    MemoryCategory::ALL
+        .iter()
+        .map(|&category| allocated_bytes(category))
+        .sum()
+}
+
+/// A buffer or a texture whose size is counted in its category until it is dropped
+pub struct Tracked<T> {
+    inner: T,
+    category: MemoryCategory,
+    size: u64,
+}
+
+impl<T> Tracked<T> {
+    fn new(inner: T, category: MemoryCategory, size: u64) -> Self {
+        ALLOCATED_BYTES[category.index()].fetch_add(size, Ordering::Relaxed);
+        Self {
+            inner,
+            category,
+            size,
+        }
+    }
+
+    /// Size of the allocation in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        ALLOCATED_BYTES[self.category.index()].fetch_sub(self.size, Ordering::Relaxed);
+    }
+}
+
+pub type TrackedBuffer = Tracked<wgpu::Buffer>;
+pub type TrackedTexture = Tracked<wgpu::Texture>;
+
+pub fn create_buffer(
+    device: &wgpu::Device,
+    category: MemoryCategory,
+    descriptor: &wgpu::BufferDescriptor,
+) -> TrackedBuffer {
+    Tracked::new(device.create_buffer(descriptor), category, descriptor.size)
+}
+
+pub fn create_buffer_init(
+    device: &wgpu::Device,
+    category: MemoryCategory,
+    descriptor: &wgpu::util::BufferInitDescriptor,
+) -> TrackedBuffer {
+    Tracked::new(
+        device.create_buffer_init(descriptor),
+        category,
+        descriptor.contents.len() as u64,
+    )
+}
+
+pub fn create_texture(
+    device: &wgpu::Device,
+    category: MemoryCategory,
+    descriptor: &wgpu::TextureDescriptor,
+) -> TrackedTexture {
+    Tracked::new(
+        device.create_texture(descriptor),
+        category,
+        texture_size(descriptor),
+    )
+}
+
+/// Approximate size of a texture in bytes, with all its mipmap levels and samples
+fn texture_size(descriptor: &wgpu::TextureDescriptor) -> u64 {
+    let texel_size = match descriptor.format {
+        wgpu::TextureFormat::R8Unorm | wgpu::TextureFormat::R8Uint => 1,
+        wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba16Uint => 8,
+        wgpu::TextureFormat::Rgba32Float | wgpu::TextureFormat::Rgba32Uint => 16,
+        // The formats used by the renderer: the 8-bit colors and the 32-bit depth
+        _ => 4,
+    };
+    let wgpu::Extent3d {
+        width,
+        height,
+        depth,
+    } = descriptor.size;
+    (0..descriptor.mip_level_count)
+        .map(|level| {
+            let level_width = (width >> level).max(1) as u64;
+            let level_height = (height >> level).max(1) as u64;
+            level_width * level_height * depth as u64
+        })
+        .sum::<u64>()
+        * texel_size
+        * descriptor.sample_count as u64
+}
+
+/// Format a number of bytes in megabytes, for the debug overlay
+pub fn format_megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_texture_size() {
+        let descriptor = wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: 16,
+                height: 16,
+                depth: 1,
+            },
+            mip_level_count: 3,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsage::SAMPLED,
+        };
+        assert_eq!(texture_size(&descriptor), (256 + 64 + 16) * 4);
+        let multisampled = wgpu::TextureDescriptor {
+            mip_level_count: 1,
+            sample_count: 4,
+            format: wgpu::TextureFormat::Depth32Float,
+            ..descriptor
+        };
+        assert_eq!(texture_size(&multisampled), 256 * 4 * 4);
+    }
+}
@@ -0,0 +1,243 @@
+//! Minimap rendering
+
+use super::buffer_from_slice;
+use super::buffers::DynamicBuffer;
+use super::init::{load_glsl_shader, RenderInitError, ShaderStage};
+use super::memory::{self, MemoryCategory, TrackedTexture};
+use super::UploadBelt;
+use crate::minimap::Minimap;
+use crate::window::{WindowBuffers, WindowData};
+
+/// Depth of the map in normalized device coordinates, behind the rest of the ui
+const MAP_DEPTH: f32 = 0.75;
+
+pub struct MinimapRenderer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    vertex_buffer: DynamicBuffer<MinimapVertex>,
+    index_buffer: DynamicBuffer<u32>,
+    /// The map texture, its size and its bind group
+    texture: Option<(TrackedTexture, u32, wgpu::BindGroup)>,
+    /// The center, size and version of the map currently in the texture, so that it is only uploaded when it changes
+    uploaded_map: Option<(i64, i64, u32, u64)>,
+}
+
+impl MinimapRenderer {
+    pub fn new(device: &mut wgpu::Device) -> Result<Self, RenderInitError> {
+        let bind_group_layout = device.create_bind_group_layout(&MINIMAP_BIND_GROUP_LAYOUT);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 0.0,
+            compare: None,
+            anisotropy_clamp: None,
+        });
+
+        let vertex_shader_bytes =
+            load_glsl_shader(ShaderStage::Vertex, "assets/shaders/minimap.vert")?;
+        let fragment_shader_bytes =
+            load_glsl_shader(ShaderStage::Fragment, "assets/shaders/minimap.frag")?;
+        let pipeline = super::init::create_default_pipeline(
+            device,
+            &bind_group_layout,
+            wgpu::util::make_spirv(&vertex_shader_bytes),
+            wgpu::util::make_spirv(&fragment_shader_bytes),
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<MinimapVertex>() as u64,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: &MINIMAP_VERTEX_ATTRIBUTES,
+            },
+            false,
+        );
+
+        Ok(Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            vertex_buffer: DynamicBuffer::with_capacity(
+                device,
+                4,
+                wgpu::BufferUsage::VERTEX,
+                MemoryCategory::Ui,
+            ),
+            index_buffer: DynamicBuffer::with_capacity(
+                device,
+                6,
+                wgpu::BufferUsage::INDEX,
+                MemoryCategory::Ui,
+            ),
+            texture: None,
+            uploaded_map: None,
+        })
+    }
+
+    /// Draw the `size` x `size` blocks of the map around `center` in the logical screen rectangle `[x, y, width, height]`.
+    /// `size` must be a multiple of 64.
+    pub fn render(
+        &mut self,
+        buffers: WindowBuffers,
+        device: &mut wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
+        window_data: &WindowData,
+        minimap: &Minimap,
+        (center_x, center_z): (i64, i64),
+        size: u32,
+        [x, y, width, height]: [f32; 4],
+    ) {
+        // The rows of the texture must be aligned to 256 bytes
+        assert_eq!(size % 64, 0, "the size of the map must be a multiple of 64");
+
+        // Create the texture if necessary
+        if self.texture.as_ref().map(|(_, s, _)| *s) != Some(size) {
+            let texture = memory::create_texture(
+                device,
+                MemoryCategory::Ui,
+                &wgpu::TextureDescriptor {
+                    label: Some("minimap_texture"),
+                    size: wgpu::Extent3d {
+                        width: size,
+                        height: size,
+                        depth: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    usage: wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::SAMPLED,
+                },
+            );
+            let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&texture_view),
+                    },
+                ],
+            });
+            self.texture = Some((texture, size, bind_group));
+            self.uploaded_map = None;
+        }
+        let (texture, _, bind_group) = self.texture.as_ref().unwrap();
+
+        // Upload the map if it changed
+        let map = (center_x, center_z, size, minimap.version());
+        if self.uploaded_map != Some(map) {
+            let image = minimap.render_image(center_x, center_z, size);
+            let src_buffer = buffer_from_slice(device, wgpu::BufferUsage::COPY_SRC, &image);
+            encoder.copy_buffer_to_texture(
+                wgpu::BufferCopyView {
+                    buffer: &src_buffer,
+                    layout: wgpu::TextureDataLayout {
+                        offset: 0,
+                        bytes_per_row: 4 * size,
+                        rows_per_image: size,
+                    },
+                },
+                wgpu::TextureCopyView {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                },
+                wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth: 1,
+                },
+            );
+            self.uploaded_map = Some(map);
+        }
+
+        // Convert the rectangle to normalized device coordinates
+        let (win_w, win_h) = (
+            window_data.gui_size().width as f32,
+            window_data.gui_size().height as f32,
+        );
+        let to_ndc = |px: f32, py: f32| [px / win_w * 2.0 - 1.0, 1.0 - py / win_h * 2.0, MAP_DEPTH];
+        let vertices = [
+            MinimapVertex {
+                position: to_ndc(x, y),
+                uv: [0.0, 0.0],
+            },
+            MinimapVertex {
+                position: to_ndc(x + width, y),
+                uv: [1.0, 0.0],
+            },
+            MinimapVertex {
+                position: to_ndc(x, y + height),
+                uv: [0.0, 1.0],
+            },
+            MinimapVertex {
+                position: to_ndc(x + width, y + height),
+                uv: [1.0, 1.0],
+            },
+        ];
+        self.vertex_buffer.upload(device, encoder, belt, &vertices);
+        self.index_buffer
+            .upload(device, encoder, belt, &[1, 0, 2, 1, 2, 3]);
+
+        let mut rpass = super::render::create_default_render_pass(encoder, buffers);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.get_buffer().slice(..));
+        rpass.set_index_buffer(self.index_buffer.get_buffer().slice(..));
+        rpass.draw_indexed(0..(self.index_buffer.len() as u32), 0, 0..1);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MinimapVertex {
+    position: [f32; 3],
+    uv: [f32; 2],
+}
+
+const MINIMAP_VERTEX_ATTRIBUTES: [wgpu::VertexAttributeDescriptor; 2] = [
+    wgpu::VertexAttributeDescriptor {
+        shader_location: 0,
+        format: wgpu::VertexFormat::Float3,
+        offset: 0,
+    },
+    wgpu::VertexAttributeDescriptor {
+        shader_location: 1,
+        format: wgpu::VertexFormat::Float2,
+        offset: 12,
+    },
+];
+
+const MINIMAP_BIND_GROUP_LAYOUT: wgpu::BindGroupLayoutDescriptor<'static> =
+    wgpu::BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler { comparison: false },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    component_type: wgpu::TextureComponentType::Float,
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+        ],
+    };
@@ -3,19 +3,27 @@
 /* WebGPU HELPER MODULES */
 mod buffers;
 mod init;
+pub mod memory;
 mod render;
+mod upload;
 pub use self::buffers::MultiBuffer;
+pub use self::init::RenderInitError;
 pub use self::render::{
     buffer_from_slice, clear_color_and_depth, clear_depth, encode_resolve_render_pass, to_u8_slice,
 };
+pub use self::upload::UploadBelt;
 
 /* OTHER HELPER MODULES */
+mod entity_culling;
 mod frustum;
+pub use self::entity_culling::{EntityCulling, ModelDetail};
 pub use self::frustum::Frustum;
 
 /* RENDERING-RESPONSIBLE MODULES */
 pub mod iced;
+mod minimap;
 mod ui;
 pub mod world;
+pub use self::minimap::MinimapRenderer;
 pub use self::ui::UiRenderer;
 pub use self::world::{ChunkVertex, Model, WorldRenderer};
@@ -1,7 +1,8 @@
 //! Helpers for renderer passes
 
+use super::memory::{self, MemoryCategory, TrackedBuffer};
 use crate::window::WindowBuffers;
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::util::BufferInitDescriptor;
 
 /// Create an attachment for the depth buffer that doesn't clear it.
 pub fn create_default_depth_stencil_attachment(
@@ -40,6 +41,24 @@ pub fn create_default_render_pass<'a>(
     })
 }
 
+/// Create a render pass that renders directly to the window frame buffer, without depth buffer and without clearing.
+pub fn create_overlay_render_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    buffers: WindowBuffers<'a>,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+            attachment: buffers.texture_buffer,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    })
+}
+
 /// Encode a render pass to resolve the multisampled frame buffer to the window frame buffer
 pub fn encode_resolve_render_pass<'a>(encoder: &mut wgpu::CommandEncoder, buffers: WindowBuffers) {
     let _rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -112,10 +131,14 @@ pub fn buffer_from_slice(
     device: &wgpu::Device,
     usage: wgpu::BufferUsage,
     data: &[u8],
-) -> wgpu::Buffer {
-    device.create_buffer_init(&BufferInitDescriptor {
-        label: None,
-        usage,
-        contents: &data,
-    })
+) -> TrackedBuffer {
+    memory::create_buffer_init(
+        device,
+        MemoryCategory::Other,
+        &BufferInitDescriptor {
+            label: None,
+            usage,
+            contents: &data,
+        },
+    )
 }
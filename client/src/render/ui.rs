@@ -1,60 +1,182 @@
 //! Ui rendering
 
 use super::buffers::DynamicBuffer;
-use super::init::{load_glsl_shader, ShaderStage};
-use super::{buffer_from_slice, to_u8_slice};
+use super::init::{load_glsl_shader, RenderInitError, ShaderStage};
+use super::memory::{self, MemoryCategory, TrackedBuffer, TrackedTexture};
+use super::{buffer_from_slice, UploadBelt};
+use crate::gui::{theme::CursorTheme, CursorStyle};
 use crate::window::{WindowBuffers, WindowData};
+use serde::Deserialize;
 use std::collections::{BTreeMap, HashMap};
-use wgpu_glyph::{ab_glyph::FontVec, FontId};
+use wgpu_glyph::{
+    ab_glyph::{Font, FontVec},
+    FontId, GlyphCruncher,
+};
+
+/// Padding between the border of a tooltip and its text, in logical pixels
+const TOOLTIP_PADDING: f32 = 6.0;
+/// Offset between the anchor of a tooltip and its top-left corner, in logical pixels
+const TOOLTIP_OFFSET: f32 = 12.0;
+/// Size of the cells of the cursor atlas, in pixels
+const CURSOR_CELL_SIZE: f32 = 32.0;
+/// Size of the drawn cursor, in logical pixels
+const CURSOR_SIZE: f32 = 24.0;
+
+#[derive(Deserialize)]
+struct FallbackFontList {
+    fallback: Vec<String>,
+}
+
+/// Read and parse a font list of `assets/fonts`
+fn load_font_list<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, RenderInitError> {
+    let font_list_error = |message: String| RenderInitError::FontList {
+        path: path.to_owned(),
+        message,
+    };
+    let font_list = std::fs::read_to_string(path).map_err(|e| font_list_error(e.to_string()))?;
+    toml::de::from_str(&font_list).map_err(|e| font_list_error(e.to_string()))
+}
+
+/// Load the fallback fonts listed in `assets/fonts/fallback.toml`, skipping the missing ones
+fn load_fallback_fonts() -> Result<Vec<FontVec>, RenderInitError> {
+    log::info!("Loading fallback fonts from assets/fonts/fallback.toml");
+    let font_list: FallbackFontList = load_font_list("assets/fonts/fallback.toml")?;
+    let mut fonts = Vec::new();
+    for font_file in font_list.fallback {
+        match std::fs::read(&font_file) {
+            Ok(font_bytes) => match FontVec::try_from_vec(font_bytes) {
+                Ok(font) => {
+                    log::info!("Loaded fallback font {}", font_file);
+                    fonts.push(font);
+                }
+                Err(e) => log::warn!("Couldn't parse fallback font {}: {}", font_file, e),
+            },
+            Err(e) => log::warn!("Couldn't read fallback font {}: {}", font_file, e),
+        }
+    }
+    Ok(fonts)
+}
+
+/// Split `text` into runs that are each drawn with a single font. Every character is drawn with `font`
+/// if it has a glyph for it, otherwise with the first of the `fallback` fonts that has one.
+/// Whitespace stays in the current run, and the characters that no font covers are drawn with `font`.
+fn split_font_runs<'t>(
+    fonts: &[FontVec],
+    fallback: &[FontId],
+    text: &'t str,
+    font: FontId,
+) -> Vec<(&'t str, FontId)> {
+    let has_glyph = |id: FontId, c: char| {
+        fonts
+            .get(id.0)
+            .map_or(false, |font_data| font_data.glyph_id(c).0 != 0)
+    };
+    let mut runs: Vec<(&'t str, FontId)> = Vec::new();
+    let mut run_start = 0;
+    let mut run_font = font;
+    for (i, c) in text.char_indices() {
+        let char_font = if c.is_whitespace() || c.is_control() {
+            run_font
+        } else if has_glyph(font, c) {
+            font
+        } else {
+            fallback
+                .iter()
+                .cloned()
+                .find(|&id| has_glyph(id, c))
+                .unwrap_or(font)
+        };
+        if char_font != run_font {
+            if i > run_start {
+                runs.push((&text[run_start..i], run_font));
+            }
+            run_start = i;
+            run_font = char_font;
+        }
+    }
+    if run_start < text.len() {
+        runs.push((&text[run_start..], run_font));
+    }
+    runs
+}
 
 pub struct UiRenderer {
     // Glyph rendering
     glyph_brush: wgpu_glyph::GlyphBrush<(), FontVec>,
     fonts: HashMap<String, FontId>,
+    /// Fonts used for the characters that are missing from the font of a text, in order
+    fallback_fonts: Vec<FontId>,
     // Rectangle rendering
-    transform_buffer: wgpu::Buffer,
+    transform_buffer: TrackedBuffer,
     uniforms_bind_group: wgpu::BindGroup,
     pipeline: wgpu::RenderPipeline,
     vertex_buffer: DynamicBuffer<UiVertex>,
     index_buffer: DynamicBuffer<u32>,
+    // Overlay rendering, drawn on top of the text
+    overlay_pipeline: wgpu::RenderPipeline,
+    overlay_vertex_buffer: DynamicBuffer<UiVertex>,
+    overlay_index_buffer: DynamicBuffer<u32>,
+    // Texture rendering, from the icon atlas
+    texture_pipeline: wgpu::RenderPipeline,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    texture_sampler: wgpu::Sampler,
+    texture_vertex_buffer: DynamicBuffer<UiTextureVertex>,
+    texture_index_buffer: DynamicBuffer<u32>,
+    /// The icon atlas and its bind group
+    icon_atlas: Option<(TrackedTexture, wgpu::BindGroup)>,
+    // Cursor rendering, drawn on top of everything else
+    cursor_pipeline: wgpu::RenderPipeline,
+    cursor_vertex_buffer: DynamicBuffer<UiTextureVertex>,
+    cursor_index_buffer: DynamicBuffer<u32>,
+    /// The cursor atlas, its bind group and the hotspots of the styles
+    cursor_atlas: Option<(TrackedTexture, wgpu::BindGroup, [[f32; 2]; 3])>,
 }
 
 impl<'a> UiRenderer {
-    pub fn new(device: &mut wgpu::Device) -> Self {
+    pub fn new(device: &mut wgpu::Device) -> Result<Self, RenderInitError> {
         // Load fonts
         let default_font = FontVec::try_from_vec(
             include_bytes!("../../../assets/fonts/IBMPlexMono-Regular.ttf").to_vec(),
         )
-        .expect("Failed to load default font.");
+        .map_err(|e| RenderInitError::Font {
+            path: "assets/fonts/IBMPlexMono-Regular.ttf".to_owned(),
+            message: e.to_string(),
+        })?;
         let mut glyph_brush_builder = wgpu_glyph::GlyphBrushBuilder::using_font(default_font);
         log::info!("Loading fonts from assets/fonts/list.toml");
         let mut fonts = HashMap::new();
-        let font_list = std::fs::read_to_string("assets/fonts/list.toml")
-            .expect("Couldn't read font list file");
-        let font_files: BTreeMap<String, String> =
-            toml::de::from_str(&font_list).expect("Couldn't parse font list file");
+        let font_files: BTreeMap<String, String> = load_font_list("assets/fonts/list.toml")?;
         for (font_name, font_file) in font_files.into_iter() {
-            use std::io::Read;
             log::info!("Loading font {} from file {}", font_name, font_file);
-            let mut font_bytes = vec![];
-            let mut file = std::fs::File::open(font_file).expect("Couldn't open font file");
-            file.read_to_end(&mut font_bytes)
-                .expect("Couldn't read font file");
-            let font = FontVec::try_from_vec(font_bytes).expect("Couldn't read font file");
+            let font_error = |message: String| RenderInitError::Font {
+                path: font_file.clone(),
+                message,
+            };
+            let font_bytes = std::fs::read(&font_file).map_err(|e| font_error(e.to_string()))?;
+            let font = FontVec::try_from_vec(font_bytes).map_err(|e| font_error(e.to_string()))?;
             fonts.insert(font_name, glyph_brush_builder.add_font(font));
         }
+        // The default font is the first fallback, for the fonts that only cover a few characters
+        let mut fallback_fonts = vec![FontId::default()];
+        for font in load_fallback_fonts()? {
+            fallback_fonts.push(glyph_brush_builder.add_font(font));
+        }
         log::info!("Fonts successfully loaded");
         let glyph_brush = glyph_brush_builder
             //.depth_stencil_state(DEFAULT_DEPTH_STENCIL_STATE_DESCRIPTOR)
             .build(device, crate::window::COLOR_FORMAT);
 
         // Create uniform buffer
-        let transform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("ui_transform_buffer"),
-            mapped_at_creation: false,
-            size: 64,
-            usage: (wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST),
-        });
+        let transform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Ui,
+            &wgpu::BufferDescriptor {
+                label: Some("ui_transform_buffer"),
+                mapped_at_creation: false,
+                size: 64,
+                usage: (wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST),
+            },
+        );
 
         // Create bind group layout
         let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -82,10 +204,10 @@ impl<'a> UiRenderer {
 
         // Create shader modules
         let vertex_shader_bytes =
-            load_glsl_shader(ShaderStage::Vertex, "assets/shaders/gui-rect.vert");
+            load_glsl_shader(ShaderStage::Vertex, "assets/shaders/gui-rect.vert")?;
         let vertex_shader = wgpu::util::make_spirv(&vertex_shader_bytes);
         let fragment_shader_bytes =
-            load_glsl_shader(ShaderStage::Fragment, "assets/shaders/gui-rect.frag");
+            load_glsl_shader(ShaderStage::Fragment, "assets/shaders/gui-rect.frag")?;
         let fragment_shader = wgpu::util::make_spirv(&fragment_shader_bytes);
 
         log::trace!("Creating pipeline.");
@@ -104,17 +226,236 @@ impl<'a> UiRenderer {
             false,
         );
 
+        let overlay_pipeline = super::init::create_overlay_pipeline(
+            device,
+            &uniform_layout,
+            wgpu::util::make_spirv(&vertex_shader_bytes),
+            wgpu::util::make_spirv(&fragment_shader_bytes),
+            wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<UiVertex>() as u64,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: &UI_VERTEX_ATTRIBUTES,
+            },
+        );
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&UI_TEXTURE_BIND_GROUP_LAYOUT);
+        let texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 0.0,
+            compare: None,
+            anisotropy_clamp: None,
+        });
+        let texture_vertex_shader_bytes =
+            load_glsl_shader(ShaderStage::Vertex, "assets/shaders/gui-texture.vert")?;
+        let texture_fragment_shader_bytes =
+            load_glsl_shader(ShaderStage::Fragment, "assets/shaders/gui-texture.frag")?;
+        let texture_pipeline = super::init::create_default_pipeline(
+            device,
+            &texture_bind_group_layout,
+            wgpu::util::make_spirv(&texture_vertex_shader_bytes),
+            wgpu::util::make_spirv(&texture_fragment_shader_bytes),
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<UiTextureVertex>() as u64,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: &UI_TEXTURE_VERTEX_ATTRIBUTES,
+            },
+            false,
+        );
+        let cursor_pipeline = super::init::create_overlay_pipeline(
+            device,
+            &texture_bind_group_layout,
+            wgpu::util::make_spirv(&texture_vertex_shader_bytes),
+            wgpu::util::make_spirv(&texture_fragment_shader_bytes),
+            wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<UiTextureVertex>() as u64,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: &UI_TEXTURE_VERTEX_ATTRIBUTES,
+            },
+        );
+
         log::trace!("Created pipeline.");
 
-        Self {
+        Ok(Self {
             glyph_brush,
             fonts,
+            fallback_fonts,
             transform_buffer,
             uniforms_bind_group,
             pipeline,
-            vertex_buffer: DynamicBuffer::with_capacity(device, 64, wgpu::BufferUsage::VERTEX),
-            index_buffer: DynamicBuffer::with_capacity(device, 64, wgpu::BufferUsage::INDEX),
-        }
+            vertex_buffer: DynamicBuffer::with_capacity(
+                device,
+                64,
+                wgpu::BufferUsage::VERTEX,
+                MemoryCategory::Ui,
+            ),
+            index_buffer: DynamicBuffer::with_capacity(
+                device,
+                64,
+                wgpu::BufferUsage::INDEX,
+                MemoryCategory::Ui,
+            ),
+            overlay_pipeline,
+            overlay_vertex_buffer: DynamicBuffer::with_capacity(
+                device,
+                64,
+                wgpu::BufferUsage::VERTEX,
+                MemoryCategory::Ui,
+            ),
+            overlay_index_buffer: DynamicBuffer::with_capacity(
+                device,
+                64,
+                wgpu::BufferUsage::INDEX,
+                MemoryCategory::Ui,
+            ),
+            texture_pipeline,
+            texture_bind_group_layout,
+            texture_sampler,
+            texture_vertex_buffer: DynamicBuffer::with_capacity(
+                device,
+                64,
+                wgpu::BufferUsage::VERTEX,
+                MemoryCategory::Ui,
+            ),
+            texture_index_buffer: DynamicBuffer::with_capacity(
+                device,
+                64,
+                wgpu::BufferUsage::INDEX,
+                MemoryCategory::Ui,
+            ),
+            icon_atlas: None,
+            cursor_pipeline,
+            cursor_vertex_buffer: DynamicBuffer::with_capacity(
+                device,
+                4,
+                wgpu::BufferUsage::VERTEX,
+                MemoryCategory::Ui,
+            ),
+            cursor_index_buffer: DynamicBuffer::with_capacity(
+                device,
+                6,
+                wgpu::BufferUsage::INDEX,
+                MemoryCategory::Ui,
+            ),
+            cursor_atlas: None,
+        })
+    }
+
+    /// Upload the icon atlas that the texture primitives are drawn from.
+    /// The atlas must be square, and its size must be a multiple of 64.
+    pub fn set_icon_atlas(
+        &mut self,
+        device: &mut wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        atlas: &image::RgbaImage,
+    ) {
+        self.icon_atlas = Some(self.create_atlas(device, encoder, atlas, "icon_atlas_texture"));
+    }
+
+    /// Load the cursor atlas of `cursor`. The system cursor is used if it can't be loaded, see `has_cursor`.
+    pub fn load_cursor(
+        &mut self,
+        device: &mut wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        cursor: &CursorTheme,
+    ) {
+        self.cursor_atlas = match cursor.load_atlas() {
+            Ok(atlas) => {
+                let (texture, bind_group) =
+                    self.create_atlas(device, encoder, &atlas, "cursor_atlas_texture");
+                Some((texture, bind_group, cursor.hotspots))
+            }
+            Err(e) => {
+                log::warn!("{:?}\nUsing the system cursor instead", e);
+                None
+            }
+        };
+    }
+
+    /// Whether the cursor atlas is loaded, so that `render_cursor` draws the cursor
+    pub fn has_cursor(&self) -> bool {
+        self.cursor_atlas.is_some()
+    }
+
+    /// Upload a texture atlas and create its bind group for the texture pipelines
+    fn create_atlas(
+        &self,
+        device: &mut wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        atlas: &image::RgbaImage,
+        label: &str,
+    ) -> (TrackedTexture, wgpu::BindGroup) {
+        let size = atlas.width();
+        // The rows of the texture must be aligned to 256 bytes
+        assert_eq!(size, atlas.height(), "the atlas must be square");
+        assert_eq!(
+            size % 64,
+            0,
+            "the size of the atlas must be a multiple of 64"
+        );
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth: 1,
+        };
+        let texture = memory::create_texture(
+            device,
+            MemoryCategory::Ui,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::SAMPLED,
+            },
+        );
+        let src_buffer = buffer_from_slice(device, wgpu::BufferUsage::COPY_SRC, atlas.as_raw());
+        encoder.copy_buffer_to_texture(
+            wgpu::BufferCopyView {
+                buffer: &src_buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * size,
+                    rows_per_image: size,
+                },
+            },
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+            },
+            extent,
+        );
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(self.transform_buffer.slice(0..64)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.texture_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+            ],
+        });
+        (texture, bind_group)
     }
 
     pub fn render(
@@ -122,6 +463,7 @@ impl<'a> UiRenderer {
         buffers: WindowBuffers<'a>,
         device: &mut wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
         window_data: &WindowData,
         gui: &mut crate::gui::Gui,
         draw_crosshair: bool,
@@ -135,7 +477,7 @@ impl<'a> UiRenderer {
         let mut rect_vertices: Vec<UiVertex> = Vec::new();
         let mut rect_indices: Vec<u32> = Vec::new();
 
-        use crate::ui::{RectanglePrimitive, TextPrimitive, TrianglesPrimitive};
+        use crate::ui::{RectanglePrimitive, TextPrimitive, TexturePrimitive, TrianglesPrimitive};
 
         // Rectangles
         for RectanglePrimitive {
@@ -182,6 +524,28 @@ impl<'a> UiRenderer {
             );
             rect_indices.extend(indices.into_iter().map(|id| id + index_offset));
         }
+        // Textures
+        let mut texture_vertices: Vec<UiTextureVertex> = Vec::new();
+        let mut texture_indices: Vec<u32> = Vec::new();
+        for TexturePrimitive {
+            layout: l,
+            uv: [u, v, uw, vh],
+            z,
+        } in primitive_buffer.textures.into_iter()
+        {
+            let index_offset = texture_vertices.len() as u32;
+            texture_vertices.extend(
+                [
+                    ([l.x, l.y, z], [u, v]),
+                    ([l.x + l.width, l.y, z], [u + uw, v]),
+                    ([l.x, l.y + l.height, z], [u, v + vh]),
+                    ([l.x + l.width, l.y + l.height, z], [u + uw, v + vh]),
+                ]
+                .iter()
+                .map(|&(position, uv)| UiTextureVertex { position, uv }),
+            );
+            texture_indices.extend([1, 0, 2, 1, 2, 3].iter().map(|i| i + index_offset));
+        }
         // Text
         for TextPrimitive {
             x,
@@ -194,7 +558,7 @@ impl<'a> UiRenderer {
             ..
         } in primitive_buffer.text.into_iter()
         {
-            let dpi = window_data.scale_factor as f32;
+            let dpi = window_data.gui_scale_factor() as f32;
 
             // Apply DPI to font size
             for p in parts.iter_mut() {
@@ -202,19 +566,28 @@ impl<'a> UiRenderer {
                 p.font_size.y *= dpi;
             }
             // Get font IDs
-            let Self { ref fonts, .. } = &self;
+            let Self {
+                ref fonts,
+                ref fallback_fonts,
+                ref glyph_brush,
+                ..
+            } = &self;
             let parts: Vec<wgpu_glyph::Text> = parts
                 .iter()
-                .map(|part| {
-                    wgpu_glyph::Text::new(&part.text)
-                        .with_scale(part.font_size)
-                        .with_color(part.color)
-                        .with_font_id(
-                            part.font
-                                .clone()
-                                .and_then(|f| fonts.get(&f).cloned())
-                                .unwrap_or_default(),
-                        )
+                .flat_map(|part| {
+                    let font = part
+                        .font
+                        .clone()
+                        .and_then(|f| fonts.get(&f).cloned())
+                        .unwrap_or_default();
+                    split_font_runs(glyph_brush.fonts(), fallback_fonts, &part.text, font)
+                        .into_iter()
+                        .map(move |(run, font)| {
+                            wgpu_glyph::Text::new(run)
+                                .with_scale(part.font_size)
+                                .with_color(part.color)
+                                .with_font_id(font)
+                        })
                 })
                 .collect();
             // Calculate positions
@@ -263,43 +636,43 @@ impl<'a> UiRenderer {
         // Crosshair
         if draw_crosshair {
             let (cx, cy) = (
-                window_data.logical_window_size.width as f32 / 2.0,
-                window_data.logical_window_size.height as f32 / 2.0,
+                window_data.gui_size().width as f32 / 2.0,
+                window_data.gui_size().height as f32 / 2.0,
             );
             const HALF_HEIGHT: f32 = 15.0;
             const HALF_WIDTH: f32 = 2.0;
-            const COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.5];
+            let color = gui.theme().crosshair;
             let v1 = UiVertex {
                 position: [cx - HALF_WIDTH, cy - HALF_HEIGHT, -1.0],
-                color: COLOR,
+                color,
             };
             let v2 = UiVertex {
                 position: [cx + HALF_WIDTH, cy - HALF_HEIGHT, -1.0],
-                color: COLOR,
+                color,
             };
             let v3 = UiVertex {
                 position: [cx - HALF_WIDTH, cy + HALF_HEIGHT, -1.0],
-                color: COLOR,
+                color,
             };
             let v4 = UiVertex {
                 position: [cx + HALF_WIDTH, cy + HALF_HEIGHT, -1.0],
-                color: COLOR,
+                color,
             };
             let v5 = UiVertex {
                 position: [cx - HALF_HEIGHT, cy - HALF_WIDTH, -1.0],
-                color: COLOR,
+                color,
             };
             let v6 = UiVertex {
                 position: [cx + HALF_HEIGHT, cy - HALF_WIDTH, -1.0],
-                color: COLOR,
+                color,
             };
             let v7 = UiVertex {
                 position: [cx - HALF_HEIGHT, cy + HALF_WIDTH, -1.0],
-                color: COLOR,
+                color,
             };
             let v8 = UiVertex {
                 position: [cx + HALF_HEIGHT, cy + HALF_WIDTH, -1.0],
-                color: COLOR,
+                color,
             };
             let voffset = rect_vertices.len() as u32;
             rect_vertices.extend([v1, v2, v3, v4, v5, v6, v7, v8].iter());
@@ -312,39 +685,13 @@ impl<'a> UiRenderer {
 
         // Draw rectangles
         {
-            let (win_w, win_h) = (
-                window_data.logical_window_size.width,
-                window_data.logical_window_size.height,
-            );
-            // Update the uniform buffer to map (w, h) coordinates to [-1, 1]
-            let transformation_matrix = [
-                2.0 / win_w as f32,
-                0.0,
-                0.0,
-                0.0,
-                0.0,
-                -2.0 / win_h as f32,
-                0.0,
-                0.0,
-                0.0,
-                0.0,
-                0.5,
-                0.0,
-                -1.0,
-                1.0,
-                0.5,
-                1.0,
-            ];
-            let src_buffer = buffer_from_slice(
-                device,
-                wgpu::BufferUsage::COPY_SRC,
-                to_u8_slice(&transformation_matrix[..]),
-            );
-            encoder.copy_buffer_to_buffer(&src_buffer, 0, &self.transform_buffer, 0, 16 * 4);
+            self.upload_transform(device, encoder, belt, window_data);
             // Update vertex buffer
-            self.vertex_buffer.upload(device, encoder, &rect_vertices);
+            self.vertex_buffer
+                .upload(device, encoder, belt, &rect_vertices);
             // Update index buffer
-            self.index_buffer.upload(device, encoder, &rect_indices);
+            self.index_buffer
+                .upload(device, encoder, belt, &rect_indices);
             // Draw
             {
                 let mut rpass = super::render::create_default_render_pass(encoder, buffers);
@@ -354,6 +701,21 @@ impl<'a> UiRenderer {
                 rpass.set_index_buffer(self.index_buffer.get_buffer().slice(..));
                 rpass.draw_indexed(0..(self.index_buffer.len() as u32), 0, 0..1);
             }
+            // Draw textures
+            if let Some((_, texture_bind_group)) = self.icon_atlas.as_ref() {
+                if !texture_indices.is_empty() {
+                    self.texture_vertex_buffer
+                        .upload(device, encoder, belt, &texture_vertices);
+                    self.texture_index_buffer
+                        .upload(device, encoder, belt, &texture_indices);
+                    let mut rpass = super::render::create_default_render_pass(encoder, buffers);
+                    rpass.set_pipeline(&self.texture_pipeline);
+                    rpass.set_bind_group(0, texture_bind_group, &[]);
+                    rpass.set_vertex_buffer(0, self.texture_vertex_buffer.get_buffer().slice(..));
+                    rpass.set_index_buffer(self.texture_index_buffer.get_buffer().slice(..));
+                    rpass.draw_indexed(0..(self.texture_index_buffer.len() as u32), 0, 0..1);
+                }
+            }
         }
 
         // Resolve !
@@ -361,11 +723,10 @@ impl<'a> UiRenderer {
 
         // Draw text
         // TODO: use depth buffer
-        let mut staging_belt = wgpu::util::StagingBelt::new(128);
         self.glyph_brush
             .draw_queued(
                 device,
-                &mut staging_belt,
+                belt.staging_belt(),
                 encoder,
                 buffers.texture_buffer,
                 //create_default_depth_stencil_attachment(buffers.depth_buffer),
@@ -373,7 +734,259 @@ impl<'a> UiRenderer {
                 window_data.physical_window_size.height,
             )
             .expect("couldn't draw queued glyphs");
+
+        // Draw tooltips on top of everything else
+        if !primitive_buffer.tooltips.is_empty() {
+            self.render_tooltips(
+                buffers,
+                device,
+                encoder,
+                belt,
+                window_data,
+                primitive_buffer.tooltips,
+                gui.theme(),
+            );
+        }
+    }
+
+    /// Update the uniform buffer to map the GUI coordinates to [-1, 1]
+    fn upload_transform(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
+        window_data: &WindowData,
+    ) {
+        let (win_w, win_h) = (window_data.gui_size().width, window_data.gui_size().height);
+        let transformation_matrix = [
+            2.0 / win_w as f32,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            -2.0 / win_h as f32,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            0.5,
+            0.0,
+            -1.0,
+            1.0,
+            0.5,
+            1.0,
+        ];
+        belt.upload(
+            device,
+            encoder,
+            &self.transform_buffer,
+            0,
+            &transformation_matrix[..],
+        );
+    }
+
+    /// Draw the cursor on top of everything else, with its hotspot at `position` in GUI units.
+    /// Nothing is drawn if the cursor atlas is not loaded.
+    pub fn render_cursor(
+        &mut self,
+        buffers: WindowBuffers<'a>,
+        device: &mut wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
+        window_data: &WindowData,
+        position: (f32, f32),
+        style: CursorStyle,
+    ) {
+        self.upload_transform(device, encoder, belt, window_data);
+        let (_, bind_group, hotspots) = match self.cursor_atlas.as_ref() {
+            Some(cursor_atlas) => cursor_atlas,
+            None => return,
+        };
+        let scale = CURSOR_SIZE / CURSOR_CELL_SIZE;
+        let [hotspot_x, hotspot_y] = hotspots[style.index()];
+        let (x, y) = (
+            position.0 - hotspot_x * scale,
+            position.1 - hotspot_y * scale,
+        );
+        // The cells are 2 by 2 in the atlas
+        let (u, v) = (
+            (style.index() % 2) as f32 * 0.5,
+            (style.index() / 2) as f32 * 0.5,
+        );
+        let vertices = [
+            ([x, y, 0.0], [u, v]),
+            ([x + CURSOR_SIZE, y, 0.0], [u + 0.5, v]),
+            ([x, y + CURSOR_SIZE, 0.0], [u, v + 0.5]),
+            ([x + CURSOR_SIZE, y + CURSOR_SIZE, 0.0], [u + 0.5, v + 0.5]),
+        ]
+        .iter()
+        .map(|&(position, uv)| UiTextureVertex { position, uv })
+        .collect::<Vec<_>>();
+        self.cursor_vertex_buffer
+            .upload(device, encoder, belt, &vertices);
+        self.cursor_index_buffer
+            .upload(device, encoder, belt, &[1, 0, 2, 1, 2, 3]);
+        let mut rpass = super::render::create_overlay_render_pass(encoder, buffers);
+        rpass.set_pipeline(&self.cursor_pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.cursor_vertex_buffer.get_buffer().slice(..));
+        rpass.set_index_buffer(self.cursor_index_buffer.get_buffer().slice(..));
+        rpass.draw_indexed(0..6, 0, 0..1);
     }
+
+    fn render_tooltips(
+        &mut self,
+        buffers: WindowBuffers<'a>,
+        device: &mut wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
+        window_data: &WindowData,
+        tooltips: Vec<crate::ui::TooltipPrimitive>,
+        theme: &crate::gui::theme::Theme,
+    ) {
+        let dpi = window_data.gui_scale_factor() as f32;
+        let screen_width = window_data.gui_size().width as f32;
+        let screen_height = window_data.gui_size().height as f32;
+        let mut vertices: Vec<UiVertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+
+        for tooltip in tooltips.into_iter() {
+            // One line per part
+            let line_count = tooltip.lines.len();
+            let texts = tooltip
+                .lines
+                .iter()
+                .enumerate()
+                .map(|(i, line)| {
+                    if i + 1 < line_count {
+                        format!("{}\n", line.text)
+                    } else {
+                        line.text.clone()
+                    }
+                })
+                .collect::<Vec<_>>();
+            let Self {
+                ref fonts,
+                ref fallback_fonts,
+                ref glyph_brush,
+                ..
+            } = &self;
+            let parts: Vec<wgpu_glyph::Text> = tooltip
+                .lines
+                .iter()
+                .zip(texts.iter())
+                .flat_map(|(line, text)| {
+                    let mut font_size = line.font_size;
+                    font_size.x *= dpi;
+                    font_size.y *= dpi;
+                    let font = line
+                        .font
+                        .clone()
+                        .and_then(|f| fonts.get(&f).cloned())
+                        .unwrap_or_default();
+                    split_font_runs(glyph_brush.fonts(), fallback_fonts, text, font)
+                        .into_iter()
+                        .map(move |(run, font)| {
+                            wgpu_glyph::Text::new(run)
+                                .with_scale(font_size)
+                                .with_color(line.color)
+                                .with_font_id(font)
+                        })
+                })
+                .collect();
+            let section = wgpu_glyph::Section::default().with_text(parts);
+
+            // Measure the text to find the size of the panel
+            let bounds = match self.glyph_brush.glyph_bounds(&section) {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            let width = bounds.width() / dpi + 2.0 * TOOLTIP_PADDING;
+            let height = bounds.height() / dpi + 2.0 * TOOLTIP_PADDING;
+
+            // Place the panel next to its anchor, and keep it inside the screen
+            let mut x = tooltip.x as f32 + TOOLTIP_OFFSET;
+            let mut y = tooltip.y as f32 + TOOLTIP_OFFSET;
+            if x + width > screen_width {
+                // Try the other side of the anchor first
+                x = tooltip.x as f32 - TOOLTIP_OFFSET - width;
+            }
+            if y + height > screen_height {
+                y = screen_height - height;
+            }
+            x = x.min(screen_width - width).max(0.0);
+            y = y.max(0.0);
+
+            push_rectangle(
+                &mut vertices,
+                &mut indices,
+                [x - 1.0, y - 1.0, width + 2.0, height + 2.0],
+                theme.tooltip_border,
+            );
+            push_rectangle(
+                &mut vertices,
+                &mut indices,
+                [x, y, width, height],
+                theme.tooltip_background,
+            );
+
+            let text_position = ((x + TOOLTIP_PADDING) * dpi, (y + TOOLTIP_PADDING) * dpi);
+            // The bounds might not start exactly at the screen position of the section
+            let text_position = (
+                text_position.0 - bounds.min.x,
+                text_position.1 - bounds.min.y,
+            );
+            self.glyph_brush
+                .queue(section.with_screen_position(text_position));
+        }
+
+        // Draw the panels
+        self.overlay_vertex_buffer
+            .upload(device, encoder, belt, &vertices);
+        self.overlay_index_buffer
+            .upload(device, encoder, belt, &indices);
+        {
+            let mut rpass = super::render::create_overlay_render_pass(encoder, buffers);
+            rpass.set_pipeline(&self.overlay_pipeline);
+            rpass.set_bind_group(0, &self.uniforms_bind_group, &[]);
+            rpass.set_vertex_buffer(0, self.overlay_vertex_buffer.get_buffer().slice(..));
+            rpass.set_index_buffer(self.overlay_index_buffer.get_buffer().slice(..));
+            rpass.draw_indexed(0..(self.overlay_index_buffer.len() as u32), 0, 0..1);
+        }
+
+        // Draw the text of the tooltips
+        self.glyph_brush
+            .draw_queued(
+                device,
+                belt.staging_belt(),
+                encoder,
+                buffers.texture_buffer,
+                window_data.physical_window_size.width,
+                window_data.physical_window_size.height,
+            )
+            .expect("couldn't draw queued tooltip glyphs");
+    }
+}
+
+/// Add an axis-aligned rectangle `[x, y, width, height]` to a vertex and index list
+fn push_rectangle(
+    vertices: &mut Vec<UiVertex>,
+    indices: &mut Vec<u32>,
+    [x, y, w, h]: [f32; 4],
+    color: [f32; 4],
+) {
+    let index_offset = vertices.len() as u32;
+    vertices.extend(
+        [
+            [x, y, 0.0],
+            [x + w, y, 0.0],
+            [x, y + h, 0.0],
+            [x + w, y + h, 0.0],
+        ]
+        .iter()
+        .map(|&position| UiVertex { position, color }),
+    );
+    indices.extend([1, 0, 2, 1, 2, 3].iter().map(|i| i + index_offset));
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -394,3 +1007,54 @@ const UI_VERTEX_ATTRIBUTES: [wgpu::VertexAttributeDescriptor; 2] = [
         offset: 12,
     },
 ];
+
+#[derive(Debug, Clone, Copy)]
+struct UiTextureVertex {
+    position: [f32; 3],
+    uv: [f32; 2],
+}
+
+const UI_TEXTURE_VERTEX_ATTRIBUTES: [wgpu::VertexAttributeDescriptor; 2] = [
+    wgpu::VertexAttributeDescriptor {
+        shader_location: 0,
+        format: wgpu::VertexFormat::Float3,
+        offset: 0,
+    },
+    wgpu::VertexAttributeDescriptor {
+        shader_location: 1,
+        format: wgpu::VertexFormat::Float2,
+        offset: 12,
+    },
+];
+
+const UI_TEXTURE_BIND_GROUP_LAYOUT: wgpu::BindGroupLayoutDescriptor<'static> =
+    wgpu::BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX,
+                ty: wgpu::BindingType::UniformBuffer {
+                    dynamic: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler { comparison: false },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    component_type: wgpu::TextureComponentType::Float,
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+        ],
+    };
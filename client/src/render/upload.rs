@@ -0,0 +1,65 @@
+//! Batching of the uploads to the GPU.
+//! All the uploads of a frame are written to a few large staging buffers that are reused from frame to frame,
+//! instead of creating a new staging buffer for every upload.
+
+use futures::executor::LocalPool;
+use futures::task::SpawnExt;
+
+use super::to_u8_slice;
+
+/// Size of a staging buffer. Larger uploads get their own staging buffer, which is reused as well.
+const CHUNK_SIZE: wgpu::BufferAddress = 1 << 20;
+
+/// The staging buffers of the uploads of a state
+pub struct UploadBelt {
+    belt: wgpu::util::StagingBelt,
+    /// Runs the futures that give the staging buffers back to the belt once the GPU is done with them
+    local_pool: LocalPool,
+}
+
+impl UploadBelt {
+    pub fn new() -> Self {
+        Self {
+            belt: wgpu::util::StagingBelt::new(CHUNK_SIZE),
+            local_pool: LocalPool::new(),
+        }
+    }
+
+    /// Copy `data` to `target`, starting at byte `offset`. The copy is recorded in `encoder`.
+    pub fn upload<T: Copy>(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        data: &[T],
+    ) {
+        let bytes = to_u8_slice(data);
+        if let Some(size) = wgpu::BufferSize::new(bytes.len() as u64) {
+            self.belt
+                .write_buffer(encoder, target, offset, size, device)
+                .copy_from_slice(bytes);
+        }
+    }
+
+    /// Get the belt itself, for the text renderers that use one
+    pub fn staging_belt(&mut self) -> &mut wgpu::util::StagingBelt {
+        &mut self.belt
+    }
+
+    /// Close the staging buffers used by the current encoder. Must be called before finishing the encoder.
+    pub fn finish(&mut self) {
+        self.belt.finish();
+    }
+
+    /// Reuse the staging buffers that the GPU is done with. Must be called after the encoder was submitted,
+    /// usually at the beginning of the next frame.
+    pub fn recall(&mut self, device: &wgpu::Device) {
+        device.poll(wgpu::Maintain::Poll);
+        self.local_pool
+            .spawner()
+            .spawn(self.belt.recall())
+            .expect("couldn't recall the staging buffers");
+        self.local_pool.run_until_stalled();
+    }
+}
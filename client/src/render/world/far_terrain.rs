@@ -0,0 +1,61 @@
+use super::RgbVertex;
+use voxel_rs_common::world::{FarTile, FAR_TILE_SAMPLES, FAR_TILE_STEP};
+
+/// Number of blocks by which the far terrain is lowered, so that the real chunks cover it once they are loaded
+const FAR_TERRAIN_SINK: f32 = 2.0;
+/// Brightness change per block of height difference with the previous sample, to show the relief
+const SLOPE_SHADING: f32 = 0.03;
+/// Normal id of the upward normal and ambient occlusion code without occlusion, for the model shader
+const UP_NORMAL_AND_NO_OCCLUSION: u32 = (2 << 24) | (3 << 27);
+
+/// Create the mesh of a far tile: a grid with one vertex per sample, colored with the color of the surface block.
+/// The cells with an air corner are skipped.
+pub fn mesh_far_tile(tile: &FarTile, block_colors: &[[u8; 4]]) -> (Vec<RgbVertex>, Vec<u32>) {
+    let samples = FAR_TILE_SAMPLES + 1;
+    let min = tile.pos.min_block_pos();
+    let index = |i: u32, k: u32| (i * samples + k) as usize;
+
+    let mut vertices = Vec::with_capacity((samples * samples) as usize);
+    for i in 0..samples {
+        for k in 0..samples {
+            let height = tile.heights[index(i, k)];
+            let previous_height = tile.heights[index(i.saturating_sub(1), k)];
+            let shading = (1.0 + (height - previous_height) as f32 * SLOPE_SHADING)
+                .max(0.5)
+                .min(1.5);
+            let color = block_colors[tile.blocks[index(i, k)] as usize];
+            let shade = |c: u8| (c as f32 * shading).min(255.0) as u32;
+            vertices.push(RgbVertex {
+                position: [
+                    (min.px + (i * FAR_TILE_STEP) as i64) as f32,
+                    height as f32 + 1.0 - FAR_TERRAIN_SINK,
+                    (min.pz + (k * FAR_TILE_STEP) as i64) as f32,
+                ],
+                info: shade(color[0])
+                    | (shade(color[1]) << 8)
+                    | (shade(color[2]) << 16)
+                    | UP_NORMAL_AND_NO_OCCLUSION,
+            });
+        }
+    }
+
+    let mut indices = Vec::new();
+    for i in 0..FAR_TILE_SAMPLES {
+        for k in 0..FAR_TILE_SAMPLES {
+            let corners = [
+                index(i, k),
+                index(i, k + 1),
+                index(i + 1, k),
+                index(i + 1, k + 1),
+            ];
+            if corners.iter().any(|&corner| tile.blocks[corner] == 0) {
+                continue;
+            }
+            let [v00, v01, v10, v11] = corners;
+            indices.extend_from_slice(&[
+                v00 as u32, v01 as u32, v10 as u32, v10 as u32, v01 as u32, v11 as u32,
+            ]);
+        }
+    }
+    (vertices, indices)
+}
@@ -3,9 +3,10 @@ use super::ChunkVertex;
 use std::sync::Arc;
 use voxel_rs_common::world::LightChunk;
 use voxel_rs_common::{
-    block::BlockMesh,
+    block::{rail::RailShape, BiomeTint, BlockId, BlockMesh, HorizontalFace, MAX_LAYERS},
     collections::zero_initialized_vec,
     world::{Chunk, CHUNK_SIZE},
+    worldgen::biome::{Biome, BiomeMap, BIOME_COUNT},
 };
 
 #[derive(Clone, Copy, Default)]
@@ -19,6 +20,7 @@ pub struct Quad {
     v4: u32,
     // i = 1 j = 1 => (y, z) = (1, 1)
     block_id: u16,
+    tint: u32,
 }
 
 impl Quad {
@@ -49,26 +51,292 @@ fn ambiant_occl(corners: u32, edge: u32) -> u32 {
     }
 }
 
+/// The tint of the faces that are not tinted: white, with the red in the lowest byte
+const NO_TINT: u32 = 0x00ff_ffff;
+/// Flag of the vertices of the faces whose glow flickers like a flame, above the portal flag
+const FLICKER_FLAG: u32 = 1 << 10;
+/// The biomes of the block columns at most this far away are averaged to tint a face
+const BIOME_BLEND_RADIUS: i32 = 3;
+
+/// Averages the biomes around the block columns of a chunk, so that the tints change smoothly across the
+/// borders of the biomes
+struct BiomeBlender<'a> {
+    biome_maps: &'a [Option<Arc<BiomeMap>>; 9],
+    /// The proportion of every biome around every block column, computed when it is first needed
+    weights: Vec<Option<[f32; BIOME_COUNT]>>,
+}
+
+impl<'a> BiomeBlender<'a> {
+    fn new(biome_maps: &'a [Option<Arc<BiomeMap>>; 9]) -> Self {
+        Self {
+            biome_maps,
+            weights: vec![None; (CHUNK_SIZE * CHUNK_SIZE) as usize],
+        }
+    }
+
+    /// Get the proportion of every biome around the block column `(x, z)` of the chunk.
+    /// The columns whose biome map is not loaded are ignored, and it is only plains if no biome map is loaded.
+    fn weights(&mut self, x: i32, z: i32) -> [f32; BIOME_COUNT] {
+        let index = (x * CHUNK_SIZE as i32 + z) as usize;
+        if let Some(weights) = self.weights[index] {
+            return weights;
+        }
+        let size = CHUNK_SIZE as i32;
+        let mut weights = [0.0; BIOME_COUNT];
+        let mut count = 0;
+        for dx in -BIOME_BLEND_RADIUS..=BIOME_BLEND_RADIUS {
+            for dz in -BIOME_BLEND_RADIUS..=BIOME_BLEND_RADIUS {
+                let (bx, bz) = (x + dx, z + dz);
+                let map_index = (3 * (bx.div_euclid(size) + 1) + bz.div_euclid(size) + 1) as usize;
+                if let Some(map) = &self.biome_maps[map_index] {
+                    let biome = map.get(bx.rem_euclid(size) as u32, bz.rem_euclid(size) as u32);
+                    weights[biome.index()] += 1.0;
+                    count += 1;
+                }
+            }
+        }
+        if count == 0 {
+            weights[Biome::Plains.index()] = 1.0;
+        } else {
+            for weight in weights.iter_mut() {
+                *weight /= count as f32;
+            }
+        }
+        self.weights[index] = Some(weights);
+        weights
+    }
+
+    /// Get the tint of a face of the block column `(x, z)` at altitude `y`, packed like `NO_TINT`
+    fn tint(&mut self, x: i32, z: i32, tint: BiomeTint, y: i64) -> u32 {
+        let weights = self.weights(x, z);
+        let mut color = [0.0; 3];
+        for biome in Biome::ALL.iter() {
+            let weight = weights[biome.index()];
+            if weight > 0.0 {
+                let biome_color = biome.tint(tint, y);
+                for c in 0..3 {
+                    color[c] += weight * biome_color[c];
+                }
+            }
+        }
+        let channel = |c: f32| (c * 255.0).round().max(0.0).min(255.0) as u32;
+        channel(color[0]) | channel(color[1]) << 8 | channel(color[2]) << 16
+    }
+}
+
 /// The chunk-specific data that is needed to mesh it.
-pub struct ChunkMeshData {
+/// Number of blocks along each axis of a chunk with its 1-block border
+const N_SIZE: usize = (CHUNK_SIZE + 2) as usize;
+
+/// Index of the chunk containing the position `(x, y, z)` of the chunk with its border, among the 27 chunks
+/// around the chunk. The chunk at offset `(dx, dy, dz)` is at index `9 * (dx + 1) + 3 * (dy + 1) + (dz + 1)`.
+#[inline(always)]
+fn chunk_index(x: usize, y: usize, z: usize) -> usize {
+    #[inline(always)]
+    fn f(x: usize) -> usize {
+        if x == 0 {
+            0
+        } else if x == N_SIZE - 1 {
+            2
+        } else {
+            1
+        }
+    }
+    9 * f(x) + 3 * f(y) + f(z)
+}
+
+/// The position in its own chunk of the position `(x, y, z)` of the chunk with its border
+#[inline(always)]
+fn outside_position(x: usize, y: usize, z: usize) -> (u32, u32, u32) {
+    #[inline(always)]
+    fn f(x: usize) -> u32 {
+        if x == 0 {
+            CHUNK_SIZE - 1
+        } else if x == N_SIZE - 1 {
+            0
+        } else {
+            x as u32 - 1
+        }
+    }
+    (f(x), f(y), f(z))
+}
+
+/// The positions of the border in the chunk with its border, ordered by x, then y, then z
+fn border_positions() -> impl Iterator<Item = (usize, usize, usize)> {
+    (0..N_SIZE)
+        .flat_map(|i| (0..N_SIZE).flat_map(move |j| (0..N_SIZE).map(move |k| (i, j, k))))
+        .filter(|&(i, j, k)| chunk_index(i, j, k) != 13)
+}
+
+/// What the mesher reads to mesh a chunk: the chunk, and a copy of the 1-block border around it taken from the
+/// adjacent chunks when the meshing was requested.
+/// The copy is small, and the mesh records which adjacent chunks were loaded so that it is only built again
+/// when one of them arrives or when its border changes.
+pub struct ChunkMeshInput {
     /// The chunk to mesh
     pub chunk: Arc<Chunk>,
-    /// The chunks that are adjacent to the current chunk (the value at position 9+3+1, i.e. the current chunk, doesn't matter)
-    pub all_chunks: [Option<Arc<Chunk>>; 27],
     /// The light chunk of the current chunk
     pub light_chunk: Arc<LightChunk>,
-    /// The light chunks that are adjacent to the current light chunk
-    pub all_light_chunks: [Option<Arc<LightChunk>>; 27],
+    /// The blocks and the light levels of the border, in the order of `border_positions`.
+    /// The blocks of the adjacent chunks that are not loaded are air with a light level of 15.
+    border: Vec<(BlockId, u8)>,
+    /// The adjacent chunks that were loaded, the chunk at index `i` (like `chunk_index`) being bit `i`
+    pub neighbours: u32,
+    /// The biome maps of the chunk column and of the adjacent columns, the column `(dx, dz)` being at
+    /// index `3 * (dx + 1) + (dz + 1)`
+    pub biome_maps: [Option<Arc<BiomeMap>>; 9],
+}
+
+impl ChunkMeshInput {
+    /// Copy the border of a chunk from the adjacent chunks. `adjacent` returns the blocks and the light of the
+    /// adjacent chunk at index `i`, like `chunk_index`, if it is loaded.
+    pub fn new<'a>(
+        chunk: Arc<Chunk>,
+        light_chunk: Arc<LightChunk>,
+        adjacent: impl Fn(usize) -> Option<(&'a Chunk, &'a LightChunk)>,
+        biome_maps: [Option<Arc<BiomeMap>>; 9],
+    ) -> Self {
+        let mut all_chunks: [Option<(&Chunk, &LightChunk)>; 27] = Default::default();
+        let mut neighbours = 0;
+        for (i, adjacent_chunk) in all_chunks.iter_mut().enumerate() {
+            if i != 13 {
+                *adjacent_chunk = adjacent(i);
+                if adjacent_chunk.is_some() {
+                    neighbours |= 1 << i;
+                }
+            }
+        }
+        let border = border_positions()
+            .map(|(i, j, k)| match all_chunks[chunk_index(i, j, k)] {
+                Some((c, lc)) => {
+                    let pos = outside_position(i, j, k);
+                    (c.get_block_at(pos), lc.get_light_at(pos))
+                }
+                None => (0, 15),
+            })
+            .collect();
+        Self {
+            chunk,
+            light_chunk,
+            border,
+            neighbours,
+            biome_maps,
+        }
+    }
+}
+
+/// The light levels and the opaque blocks of a chunk and of its 1-block border, read from the snapshot of the
+/// 3x3x3 chunks around the chunk in `ChunkMeshInput`. The faces at the border of the chunk are lit and occluded by
+/// the blocks of the adjacent chunks exactly like by the blocks of the chunk, so there are no seams between chunks.
+/// The positions are relative to the chunk, from -1 to `CHUNK_SIZE` along each axis.
+struct LightView {
+    light_levels: [u8; N_SIZE * N_SIZE * N_SIZE],
+    /// The blocks whose mesh hides the faces behind them and darkens the corners around them
+    opaque: [bool; N_SIZE * N_SIZE * N_SIZE],
+    /// The cells that are lit by the light propagation, only the air.
+    /// The other blocks stop the light, so their light level is always 0.
+    lit: [bool; N_SIZE * N_SIZE * N_SIZE],
+}
+
+impl LightView {
+    fn new(chunk_data: &ChunkMeshInput, meshes: &[BlockMesh]) -> Self {
+        let mut view = Self {
+            light_levels: [15; N_SIZE * N_SIZE * N_SIZE],
+            opaque: [false; N_SIZE * N_SIZE * N_SIZE],
+            lit: [true; N_SIZE * N_SIZE * N_SIZE],
+        };
+        let mut border = chunk_data.border.iter();
+        for i in 0..N_SIZE {
+            for j in 0..N_SIZE {
+                for k in 0..N_SIZE {
+                    let (block, light) = if chunk_index(i, j, k) == 13 {
+                        let pos = (i as u32 - 1, j as u32 - 1, k as u32 - 1);
+                        (
+                            chunk_data.chunk.get_block_at(pos),
+                            chunk_data.light_chunk.get_light_at(pos),
+                        )
+                    } else {
+                        // The border comes in the same order as the loop
+                        *border.next().expect("Incomplete chunk border")
+                    };
+                    let index = Self::index(i as i32 - 1, j as i32 - 1, k as i32 - 1);
+                    view.light_levels[index] = light;
+                    view.opaque[index] = meshes[block as usize].is_opaque();
+                    view.lit[index] = block == 0;
+                }
+            }
+        }
+        view
+    }
+
+    #[inline(always)]
+    fn index(x: i32, y: i32, z: i32) -> usize {
+        let (a, b, c) = ((x + 1) as usize, (y + 1) as usize, (z + 1) as usize);
+        a * N_SIZE * N_SIZE + b * N_SIZE + c
+    }
+
+    #[inline(always)]
+    fn light(&self, x: i32, y: i32, z: i32) -> u8 {
+        self.light_levels[Self::index(x, y, z)]
+    }
+
+    #[inline(always)]
+    fn is_opaque(&self, x: i32, y: i32, z: i32) -> bool {
+        self.opaque[Self::index(x, y, z)]
+    }
+
+    /// Get the ambient occlusion code and the smooth light level of a corner of a face.
+    /// `front` is the cell in front of the face, and the corner is at `a` and `b` (-1 or 1) along `d1` and `d2`.
+    /// The light is the average of the lit cells around the corner in front of the face, the cell in the diagonal
+    /// being hidden if the two cells on the sides are opaque.
+    fn corner(&self, front: [i32; 3], d1: [i32; 3], d2: [i32; 3], a: i32, b: i32) -> (u32, u32) {
+        let cell = |u: i32, v: i32| {
+            [
+                front[0] + d1[0] * u + d2[0] * v,
+                front[1] + d1[1] * u + d2[1] * v,
+                front[2] + d1[2] * u + d2[2] * v,
+            ]
+        };
+        let (side1, side2, diagonal) = (cell(a, 0), cell(0, b), cell(a, b));
+        let is_opaque = |c: [i32; 3]| self.is_opaque(c[0], c[1], c[2]);
+        let edges = is_opaque(side1) as u32 + is_opaque(side2) as u32;
+        let corners = is_opaque(diagonal) as u32;
+
+        let cells = [front, side1, side2, diagonal];
+        let visible_cells = if edges == 2 { 3 } else { 4 };
+        let (mut sum, mut count) = (0, 0);
+        for c in cells[..visible_cells].iter() {
+            let index = Self::index(c[0], c[1], c[2]);
+            if self.lit[index] {
+                sum += self.light_levels[index] as u32;
+                count += 1;
+            }
+        }
+        // The cell in front of a face that is not lit is a light-blocking block like a torch or a ladder
+        let light = if count == 0 {
+            self.light(front[0], front[1], front[2]) as u32
+        } else {
+            (sum + count / 2) / count
+        };
+        (ambiant_occl(corners, edges), light)
+    }
 }
 
 /// Greedy meshing : compressed adjacent quads, return the number of uncompressed and compressed quads
 ///
 /// `quads`: Buffer that is reused every time.
 pub fn greedy_meshing(
-    chunk_data: ChunkMeshData,
+    chunk_data: ChunkMeshInput,
     meshes: &Vec<BlockMesh>,
     quads: &mut Vec<Quad>,
 ) -> (Vec<ChunkVertex>, Vec<u32>, u32, u32) {
+    // A chunk of air has no faces, whatever its border
+    if let Some(block) = chunk_data.chunk.uniform_block() {
+        if let BlockMesh::Empty = meshes[block as usize] {
+            return (Vec::new(), Vec::new(), 0, 0);
+        }
+    }
+
     let chunk_pos = chunk_data.chunk.pos;
     let offset_x = chunk_pos.px as f32 * CHUNK_SIZE as f32;
     let offset_y = chunk_pos.py as f32 * CHUNK_SIZE as f32;
@@ -82,9 +350,9 @@ pub fn greedy_meshing(
 
     let mut n_of_different_vertex = 0;
 
-    const N_SIZE: usize = (CHUNK_SIZE + 2) as usize;
-    let mut chunk_mask = [false; N_SIZE * N_SIZE * N_SIZE];
-    let mut light_levels = [15; N_SIZE * N_SIZE * N_SIZE];
+    let light_view = LightView::new(&chunk_data, meshes);
+    // The portals hide the faces of the adjacent portals, so that a portal of several blocks has no inner faces
+    let mut portal_mask = [false; N_SIZE * N_SIZE * N_SIZE];
 
     #[inline(always)]
     fn ind(x: i32, y: i32, z: i32) -> usize {
@@ -97,40 +365,21 @@ pub fn greedy_meshing(
         (a * N_SIZE * N_SIZE + b * N_SIZE + c) as usize
     }
 
-    #[inline(always)]
-    fn chunk_index(x: usize, y: usize, z: usize) -> usize {
-        #[inline(always)]
-        fn f(x: usize) -> usize {
-            if x == 0 {
-                0
-            } else if x == N_SIZE - 1 {
-                2
-            } else {
-                1
-            }
-        }
-        9 * f(x) + 3 * f(y) + f(z)
-    }
-
-    #[inline(always)]
-    fn outside_position(x: usize, y: usize, z: usize) -> (u32, u32, u32) {
-        #[inline(always)]
-        fn f(x: usize) -> u32 {
-            if x == 0 {
-                CHUNK_SIZE - 1
-            } else if x == N_SIZE - 1 {
-                0
-            } else {
-                x as u32 - 1
-            }
-        }
-        (f(x), f(y), f(z))
-    }
-
     // TODO: for light, we don't need the 8 corners
 
+    let mut biome_blender = BiomeBlender::new(&chunk_data.biome_maps);
+
     let mut opaque_blocks_count = 0;
+    // The ladders, the layers, the crops, the torches, the portals and the rails are not part of the greedy
+    // meshing, they are meshed separately at the end
+    let mut ladders = Vec::new();
+    let mut layer_blocks = Vec::new();
+    let mut crops = Vec::new();
+    let mut torches = Vec::new();
+    let mut portals = Vec::new();
+    let mut rails = Vec::new();
 
+    let mut border = chunk_data.border.iter();
     for i in 0..N_SIZE {
         for j in 0..N_SIZE {
             for k in 0..N_SIZE {
@@ -139,36 +388,78 @@ pub fn greedy_meshing(
                     unsafe {
                         let u_ind = uind(i, j, k);
 
-                        let masked =
-                            (*meshes.get_unchecked(chunk_data.chunk.get_block_at_unsafe((
-                                i as u32 - 1,
-                                j as u32 - 1,
-                                k as u32 - 1,
-                            )) as usize))
-                            .is_opaque();
+                        let mesh = meshes.get_unchecked(chunk_data.chunk.get_block_at_unsafe((
+                            i as u32 - 1,
+                            j as u32 - 1,
+                            k as u32 - 1,
+                        )) as usize);
+                        let masked = mesh.is_opaque();
+                        if let BlockMesh::Ladder {
+                            texture,
+                            attached_to,
+                        } = mesh
+                        {
+                            ladders.push((
+                                i as i32 - 1,
+                                j as i32 - 1,
+                                k as i32 - 1,
+                                *texture,
+                                *attached_to,
+                            ));
+                        }
+                        if let BlockMesh::Layer { texture, layers } = mesh {
+                            layer_blocks.push((
+                                i as i32 - 1,
+                                j as i32 - 1,
+                                k as i32 - 1,
+                                *texture,
+                                *layers,
+                            ));
+                        }
+                        if let BlockMesh::Crop { texture } = mesh {
+                            crops.push((i as i32 - 1, j as i32 - 1, k as i32 - 1, *texture));
+                        }
+                        if let BlockMesh::Torch {
+                            texture,
+                            attached_to,
+                            flickers,
+                        } = mesh
+                        {
+                            torches.push((
+                                i as i32 - 1,
+                                j as i32 - 1,
+                                k as i32 - 1,
+                                *texture,
+                                *attached_to,
+                                *flickers,
+                            ));
+                        }
+                        if let BlockMesh::Portal { texture } = mesh {
+                            portals.push((i as i32 - 1, j as i32 - 1, k as i32 - 1, *texture));
+                            *portal_mask.get_unchecked_mut(u_ind) = true;
+                        }
+                        if let BlockMesh::Rail { texture, shape } = mesh {
+                            rails.push((
+                                i as i32 - 1,
+                                j as i32 - 1,
+                                k as i32 - 1,
+                                *texture,
+                                *shape,
+                            ));
+                        }
                         // 13 = 9 + 3 + 1 is the current chunk
-                        *chunk_mask.get_unchecked_mut(u_ind) = masked;
-
                         if masked {
                             opaque_blocks_count += 1;
                         }
-
-                        *light_levels.get_unchecked_mut(u_ind) = chunk_data
-                            .light_chunk
-                            .get_light_at_unsafe((i as u32 - 1, j as u32 - 1, k as u32 - 1));
                     }
                 } else {
+                    // The border comes in the same order as the loop
+                    let &(block, _) = border.next().expect("Incomplete chunk border");
                     unsafe {
-                        if let Some(c) = &chunk_data.all_chunks[ci] {
-                            *chunk_mask.get_unchecked_mut(uind(i, j, k)) = (*meshes.get_unchecked(
-                                c.get_block_at_unsafe(outside_position(i, j, k)) as usize,
-                            ))
-                            .is_opaque();
-                        }
-                        if let Some(lc) = &chunk_data.all_light_chunks[ci] {
-                            *light_levels.get_unchecked_mut(uind(i, j, k)) =
-                                lc.get_light_at_unsafe(outside_position(i, j, k));
-                        }
+                        *portal_mask.get_unchecked_mut(uind(i, j, k)) = matches!(
+                            meshes.get_unchecked(block as usize),
+                            BlockMesh::Portal { .. }
+                        );
                     }
                 }
             }
@@ -222,84 +513,45 @@ pub fn greedy_meshing(
             for i in 0..(CHUNK_SIZE as i32) {
                 for k in 0..(CHUNK_SIZE as i32) {
                     unsafe {
-                        if *chunk_mask.get_unchecked(ind(i + 1, j + 1, k + 1)) {
+                        if light_view.is_opaque(i, j, k) {
                             opaque_blocks_count_pass -= 1;
                             *to_mesh_faces.get_unchecked_mut(s) += 1;
                             //checking if not void
-                            if !*chunk_mask.get_unchecked(ind(
-                                i + 1 + D[s][0],
-                                j + 1 + D[s][1],
-                                k + 1 + D[s][2],
-                            )) {
-                                let mut coins = [0; 4];
-                                let mut edge = [0; 4];
-
-                                for i2 in -1..=1 {
-                                    for j2 in -1..=1 {
-                                        let dx =
-                                            1 + D[s][0] + D_DELTA1[s][0] * i2 + D_DELTA2[s][0] * j2;
-                                        let dy =
-                                            1 + D[s][1] + D_DELTA1[s][1] * i2 + D_DELTA2[s][1] * j2;
-                                        let dz =
-                                            1 + D[s][2] + D_DELTA1[s][2] * i2 + D_DELTA2[s][2] * j2;
-
-                                        if *chunk_mask.get_unchecked(ind(i + dx, j + dy, k + dz)) {
-                                            match (i2, j2) {
-                                                (-1, -1) => {
-                                                    coins[0] += 1;
-                                                }
-                                                (-1, 1) => {
-                                                    coins[1] += 1;
-                                                }
-                                                (1, -1) => {
-                                                    coins[2] += 1;
-                                                }
-                                                (1, 1) => {
-                                                    coins[3] += 1;
-                                                }
-                                                (-1, 0) => {
-                                                    edge[0] += 1;
-                                                    edge[1] += 1;
-                                                }
-                                                (1, 0) => {
-                                                    edge[2] += 1;
-                                                    edge[3] += 1;
-                                                }
-                                                (0, -1) => {
-                                                    edge[0] += 1;
-                                                    edge[2] += 1;
-                                                }
-                                                (0, 1) => {
-                                                    edge[1] += 1;
-                                                    edge[3] += 1;
-                                                }
-                                                _ => (),
-                                            }
-                                        }
-                                    }
+                            let front = [i + D[s][0], j + D[s][1], k + D[s][2]];
+                            if !light_view.is_opaque(front[0], front[1], front[2]) {
+                                // The corners at (-1, -1), (-1, 1), (1, -1) and (1, 1) along D_DELTA1 and D_DELTA2
+                                let mut v = [0; 4];
+                                for (corner, (a, b)) in v
+                                    .iter_mut()
+                                    .zip([(-1, -1), (-1, 1), (1, -1), (1, 1)].iter())
+                                {
+                                    let (occl, light_level) =
+                                        light_view.corner(front, D_DELTA1[s], D_DELTA2[s], *a, *b);
+                                    *corner = (s as u32) + (occl << 3) + (light_level << 5);
                                 }
 
-                                let light_level = *light_levels.get_unchecked(ind(
-                                    i + 1 + D[s][0],
-                                    j + 1 + D[s][1],
-                                    k + 1 + D[s][2],
-                                ));
+                                let block_id = chunk_data
+                                    .chunk
+                                    .get_block_at((i as u32, j as u32, k as u32));
+                                let tint = match meshes.get_unchecked(block_id as usize) {
+                                    BlockMesh::FullCube { tints, .. } => match tints[s] {
+                                        Some(tint) => biome_blender.tint(
+                                            i,
+                                            k,
+                                            tint,
+                                            chunk_pos.py * CHUNK_SIZE as i64 + j as i64,
+                                        ),
+                                        None => NO_TINT,
+                                    },
+                                    _ => NO_TINT,
+                                };
                                 let quad = Quad {
-                                    v1: (s as u32)
-                                        + (ambiant_occl(coins[0], edge[0]) << 3)
-                                        + ((light_level as u32) << 5),
-                                    v2: (s as u32)
-                                        + (ambiant_occl(coins[1], edge[1]) << 3)
-                                        + ((light_level as u32) << 5),
-                                    v3: (s as u32)
-                                        + (ambiant_occl(coins[2], edge[2]) << 3)
-                                        + ((light_level as u32) << 5),
-                                    v4: (s as u32)
-                                        + (ambiant_occl(coins[3], edge[3]) << 3)
-                                        + ((light_level as u32) << 5),
-                                    block_id: chunk_data
-                                        .chunk
-                                        .get_block_at((i as u32, j as u32, k as u32)),
+                                    v1: v[0],
+                                    v2: v[1],
+                                    v3: v[2],
+                                    v4: v[3],
+                                    block_id,
+                                    tint,
                                 };
                                 *quads.get_unchecked_mut(ind_mesh(s, i, j, k)) = quad;
                                 *to_mesh.get_unchecked_mut(ind_mesh(s, i, j, k)) = true;
@@ -411,6 +663,7 @@ pub fn greedy_meshing(
                                         && next_quad.v1 == next_quad.v3
                                         && next_quad.v2 == next_quad.v4
                                         && current_quad.block_id == next_quad.block_id
+                                        && current_quad.tint == next_quad.tint
                                     {
                                         *to_mesh
                                             .get_unchecked_mut(ind_mesh(s, pos.0, pos.1, pos.2)) =
@@ -434,7 +687,8 @@ pub fn greedy_meshing(
                                                 .get_unchecked(ind_mesh(s, pos.0, pos.1, pos.2))
                                                 && next_quad.is_same()
                                                 && next_quad.v1 == current_quad.v1
-                                                && next_quad.block_id == current_quad.block_id)
+                                                && next_quad.block_id == current_quad.block_id
+                                                && next_quad.tint == current_quad.tint)
                                             {
                                                 break 'wloop;
                                             }
@@ -466,6 +720,7 @@ pub fn greedy_meshing(
                                         && next_quad.v1 == next_quad.v2
                                         && next_quad.v3 == next_quad.v4
                                         && next_quad.block_id == current_quad.block_id
+                                        && next_quad.tint == current_quad.tint
                                     {
                                         *to_mesh
                                             .get_unchecked_mut(ind_mesh(s, pos.0, pos.1, pos.2)) =
@@ -512,9 +767,17 @@ pub fn greedy_meshing(
                                 }
                             }
 
-                            let uv = match meshes[current_quad.block_id as usize] {
-                                BlockMesh::Empty => continue,
-                                BlockMesh::FullCube { textures } => textures[s],
+                            let (uv, flicker_flag) = match meshes[current_quad.block_id as usize] {
+                                BlockMesh::Empty
+                                | BlockMesh::Ladder { .. }
+                                | BlockMesh::Layer { .. }
+                                | BlockMesh::Crop { .. }
+                                | BlockMesh::Torch { .. }
+                                | BlockMesh::Portal { .. }
+                                | BlockMesh::Rail { .. } => continue,
+                                BlockMesh::FullCube {
+                                    textures, flickers, ..
+                                } => (textures[s], if flickers { FLICKER_FLAG } else { 0 }),
                             };
 
                             let texture_top_left = [uv.x, uv.y];
@@ -556,7 +819,8 @@ pub fn greedy_meshing(
                                     texture_uv: uvs[kk],
                                     texture_max_uv,
                                     texture_size,
-                                    occl_and_face: v[kk],
+                                    occl_and_face: v[kk] | flicker_flag,
+                                    tint: current_quad.tint,
                                 });
                             }
 
@@ -583,6 +847,460 @@ pub fn greedy_meshing(
         }
     }
 
+    // A ladder is a single quad slightly in front of the side it is attached to, facing away from it
+    const LADDER_OFFSET: f32 = 1.0 / 16.0;
+    for (x, y, z, uv, attached_to) in ladders {
+        let s = attached_to.opposite().face_index();
+        // The light of the ladder cell is always 0, so use the light of the cell in front of the ladder
+        let light_level = light_view.light(x + D[s][0], y + D[s][1], z + D[s][2]);
+        let v = (s as u32) + (3 << 3) + ((light_level as u32) << 5);
+        let plane_offset = if s % 2 == 0 {
+            LADDER_OFFSET
+        } else {
+            1.0 - LADDER_OFFSET
+        };
+        let corners = [
+            [0, 0, 0],
+            D_DELTA2[s],
+            D_DELTA1[s],
+            [
+                D_DELTA1[s][0] + D_DELTA2[s][0],
+                D_DELTA1[s][1] + D_DELTA2[s][1],
+                D_DELTA1[s][2] + D_DELTA2[s][2],
+            ],
+        ];
+        for kk in 0..4 {
+            let mut pos = [
+                (x + corners[kk][0]) as f32,
+                (y + corners[kk][1]) as f32,
+                (z + corners[kk][2]) as f32,
+            ];
+            let normal_axis = s / 2;
+            pos[normal_axis] = [x, y, z][normal_axis] as f32 + plane_offset;
+            res_vertex.push(ChunkVertex {
+                pos: [pos[0] + offset_x, pos[1] + offset_y, pos[2] + offset_z],
+                texture_top_left: [uv.x, uv.y],
+                texture_uv: [uvs[s][kk][0] * uv.width, uvs[s][kk][1] * uv.height],
+                texture_max_uv: [uv.width, uv.height],
+                texture_size: [uv.width, uv.height],
+                occl_and_face: v,
+                tint: NO_TINT,
+            });
+        }
+        for kk in 0..6 {
+            res_index.push(n_of_different_vertex + order2[s][kk]);
+        }
+        n_of_different_vertex += 4;
+        tot_quad += 1;
+        act_quad += 1;
+    }
+
+    // A layered block is a box at the bottom of its cell, whose faces are hidden by the adjacent opaque blocks
+    for (x, y, z, uv, layers) in layer_blocks {
+        let height = layers as f32 / MAX_LAYERS as f32;
+        for s in 0..6 {
+            let neighbor = (x + D[s][0], y + D[s][1], z + D[s][2]);
+            // The top face is only against the block above if the layers fill the cell
+            if (s != 2 || layers == MAX_LAYERS)
+                && light_view.is_opaque(neighbor.0, neighbor.1, neighbor.2)
+            {
+                continue;
+            }
+            // Like for the ladders, the light of the cell of the layers is 0, so use the light in front of the face
+            let light_level = light_view.light(neighbor.0, neighbor.1, neighbor.2);
+            let v = (s as u32) + (3 << 3) + ((light_level as u32) << 5);
+            let corners = [
+                [0, 0, 0],
+                D_DELTA2[s],
+                D_DELTA1[s],
+                [
+                    D_DELTA1[s][0] + D_DELTA2[s][0],
+                    D_DELTA1[s][1] + D_DELTA2[s][1],
+                    D_DELTA1[s][2] + D_DELTA2[s][2],
+                ],
+            ];
+            let normal_axis = s / 2;
+            for kk in 0..4 {
+                let mut pos = [
+                    (x + corners[kk][0]) as f32,
+                    y as f32 + corners[kk][1] as f32 * height,
+                    (z + corners[kk][2]) as f32,
+                ];
+                if s % 2 == 0 {
+                    pos[normal_axis] += if s == 2 { height } else { 1.0 };
+                }
+                // Only the bottom of the texture is visible on the sides
+                let mut texture_uv = uvs[s][kk];
+                if normal_axis != 1 {
+                    texture_uv[1] = 1.0 - (1.0 - texture_uv[1]) * height;
+                }
+                res_vertex.push(ChunkVertex {
+                    pos: [pos[0] + offset_x, pos[1] + offset_y, pos[2] + offset_z],
+                    texture_top_left: [uv.x, uv.y],
+                    texture_uv: [texture_uv[0] * uv.width, texture_uv[1] * uv.height],
+                    texture_max_uv: [uv.width, uv.height],
+                    texture_size: [uv.width, uv.height],
+                    occl_and_face: v,
+                    tint: NO_TINT,
+                });
+            }
+            for kk in 0..6 {
+                res_index.push(n_of_different_vertex + order2[s][kk]);
+            }
+            n_of_different_vertex += 4;
+            tot_quad += 1;
+            act_quad += 1;
+        }
+    }
+
+    // A crop is made of two vertical planes along x and two along z, visible from both sides
+    const CROP_PLANE_OFFSETS: [f32; 2] = [0.25, 0.75];
+    for (x, y, z, uv) in crops {
+        // Like for the ladders, the light of the crop cell is 0, so use the light of the cell above
+        let light_level = light_view.light(x, y + 1, z);
+        for &s in [0, 1, 4, 5].iter() {
+            let v = (s as u32) + (3 << 3) + ((light_level as u32) << 5);
+            let corners = [
+                [0, 0, 0],
+                D_DELTA2[s],
+                D_DELTA1[s],
+                [
+                    D_DELTA1[s][0] + D_DELTA2[s][0],
+                    D_DELTA1[s][1] + D_DELTA2[s][1],
+                    D_DELTA1[s][2] + D_DELTA2[s][2],
+                ],
+            ];
+            let normal_axis = s / 2;
+            for &plane_offset in CROP_PLANE_OFFSETS.iter() {
+                for kk in 0..4 {
+                    let mut pos = [
+                        (x + corners[kk][0]) as f32,
+                        (y + corners[kk][1]) as f32,
+                        (z + corners[kk][2]) as f32,
+                    ];
+                    pos[normal_axis] = [x, y, z][normal_axis] as f32 + plane_offset;
+                    res_vertex.push(ChunkVertex {
+                        pos: [pos[0] + offset_x, pos[1] + offset_y, pos[2] + offset_z],
+                        texture_top_left: [uv.x, uv.y],
+                        texture_uv: [uvs[s][kk][0] * uv.width, uvs[s][kk][1] * uv.height],
+                        texture_max_uv: [uv.width, uv.height],
+                        texture_size: [uv.width, uv.height],
+                        occl_and_face: v,
+                        tint: NO_TINT,
+                    });
+                }
+                for kk in 0..6 {
+                    res_index.push(n_of_different_vertex + order2[s][kk]);
+                }
+                n_of_different_vertex += 4;
+                tot_quad += 1;
+                act_quad += 1;
+            }
+        }
+    }
+
+    // A torch is made of two vertical planes crossing at the center of the cell, visible from both sides.
+    // The attached torches are moved towards their side and up, so that they hang on the wall.
+    const TORCH_WALL_OFFSET: f32 = 0.3;
+    const TORCH_WALL_RAISE: f32 = 0.2;
+    for (x, y, z, uv, attached_to, flickers) in torches {
+        // Like for the crops, the light of the torch cell is 0, so use the light of the cell above
+        let light_level = light_view.light(x, y + 1, z);
+        let (shift, raise) = match attached_to {
+            Some(side) => {
+                let s = side.face_index();
+                (
+                    [
+                        D[s][0] as f32 * TORCH_WALL_OFFSET,
+                        0.0,
+                        D[s][2] as f32 * TORCH_WALL_OFFSET,
+                    ],
+                    TORCH_WALL_RAISE,
+                )
+            }
+            None => ([0.0; 3], 0.0),
+        };
+        for &s in [0, 1, 4, 5].iter() {
+            let v = (s as u32)
+                + (3 << 3)
+                + ((light_level as u32) << 5)
+                + if flickers { FLICKER_FLAG } else { 0 };
+            let corners = [
+                [0, 0, 0],
+                D_DELTA2[s],
+                D_DELTA1[s],
+                [
+                    D_DELTA1[s][0] + D_DELTA2[s][0],
+                    D_DELTA1[s][1] + D_DELTA2[s][1],
+                    D_DELTA1[s][2] + D_DELTA2[s][2],
+                ],
+            ];
+            let normal_axis = s / 2;
+            for kk in 0..4 {
+                let mut pos = [
+                    (x + corners[kk][0]) as f32 + shift[0],
+                    (y + corners[kk][1]) as f32 + raise,
+                    (z + corners[kk][2]) as f32 + shift[2],
+                ];
+                pos[normal_axis] = [x, y, z][normal_axis] as f32 + 0.5 + shift[normal_axis];
+                res_vertex.push(ChunkVertex {
+                    pos: [pos[0] + offset_x, pos[1] + offset_y, pos[2] + offset_z],
+                    texture_top_left: [uv.x, uv.y],
+                    texture_uv: [uvs[s][kk][0] * uv.width, uvs[s][kk][1] * uv.height],
+                    texture_max_uv: [uv.width, uv.height],
+                    texture_size: [uv.width, uv.height],
+                    occl_and_face: v,
+                    tint: NO_TINT,
+                });
+            }
+            for kk in 0..6 {
+                res_index.push(n_of_different_vertex + order2[s][kk]);
+            }
+            n_of_different_vertex += 4;
+            tot_quad += 1;
+            act_quad += 1;
+        }
+    }
+
+    // A portal is a cube whose faces are hidden by the adjacent opaque blocks and portals.
+    // Its faces are flagged so that the shader animates them.
+    const PORTAL_FLAG: u32 = 1 << 9;
+    for (x, y, z, uv) in portals {
+        for s in 0..6 {
+            let (nx, ny, nz) = (x + D[s][0], y + D[s][1], z + D[s][2]);
+            if light_view.is_opaque(nx, ny, nz) || portal_mask[ind(nx + 1, ny + 1, nz + 1)] {
+                continue;
+            }
+            let light_level = light_view.light(nx, ny, nz);
+            let v = (s as u32) + (3 << 3) + ((light_level as u32) << 5) + PORTAL_FLAG;
+            let corners = [
+                [0, 0, 0],
+                D_DELTA2[s],
+                D_DELTA1[s],
+                [
+                    D_DELTA1[s][0] + D_DELTA2[s][0],
+                    D_DELTA1[s][1] + D_DELTA2[s][1],
+                    D_DELTA1[s][2] + D_DELTA2[s][2],
+                ],
+            ];
+            for kk in 0..4 {
+                let mut pos = [
+                    (x + corners[kk][0]) as f32,
+                    (y + corners[kk][1]) as f32,
+                    (z + corners[kk][2]) as f32,
+                ];
+                if s % 2 == 0 {
+                    pos[s / 2] += 1.0;
+                }
+                res_vertex.push(ChunkVertex {
+                    pos: [pos[0] + offset_x, pos[1] + offset_y, pos[2] + offset_z],
+                    texture_top_left: [uv.x, uv.y],
+                    texture_uv: [uvs[s][kk][0] * uv.width, uvs[s][kk][1] * uv.height],
+                    texture_max_uv: [uv.width, uv.height],
+                    texture_size: [uv.width, uv.height],
+                    occl_and_face: v,
+                    tint: NO_TINT,
+                });
+            }
+            for kk in 0..6 {
+                res_index.push(n_of_different_vertex + order2[s][kk]);
+            }
+            n_of_different_vertex += 4;
+            tot_quad += 1;
+            act_quad += 1;
+        }
+    }
+
+    // A rail is a flat quad just above the bottom of its cell, raised by one block on the up side of the slopes.
+    // The texture goes along z, and the curve texture connects +x and +z: it is turned or mirrored to fit the shape.
+    const RAIL_HEIGHT: f32 = 1.0 / 16.0;
+    for (x, y, z, uv, shape) in rails {
+        // Like for the crops, the light of the rail cell is 0, so use the light of the cell above
+        let light_level = light_view.light(x, y + 1, z);
+        let s = 2;
+        let v = (s as u32) + (3 << 3) + ((light_level as u32) << 5);
+        let corners = [
+            [0, 0, 0],
+            D_DELTA2[s],
+            D_DELTA1[s],
+            [
+                D_DELTA1[s][0] + D_DELTA2[s][0],
+                D_DELTA1[s][1] + D_DELTA2[s][1],
+                D_DELTA1[s][2] + D_DELTA2[s][2],
+            ],
+        ];
+        for kk in 0..4 {
+            let (cx, cz) = (corners[kk][0] as f32, corners[kk][2] as f32);
+            let is_up = |side: HorizontalFace| match side {
+                HorizontalFace::PosX => cx == 1.0,
+                HorizontalFace::NegX => cx == 0.0,
+                HorizontalFace::PosZ => cz == 1.0,
+                HorizontalFace::NegZ => cz == 0.0,
+            };
+            let (rise, texture_uv) = match shape {
+                RailShape::StraightZ => (0.0, [cx, cz]),
+                RailShape::StraightX => (0.0, [cz, cx]),
+                RailShape::Curve(x_side, z_side) => (
+                    0.0,
+                    [
+                        if x_side == HorizontalFace::PosX {
+                            cx
+                        } else {
+                            1.0 - cx
+                        },
+                        if z_side == HorizontalFace::PosZ {
+                            cz
+                        } else {
+                            1.0 - cz
+                        },
+                    ],
+                ),
+                RailShape::Slope(up) => {
+                    let rise = if is_up(up) { 1.0 } else { 0.0 };
+                    match up {
+                        HorizontalFace::PosZ | HorizontalFace::NegZ => (rise, [cx, cz]),
+                        HorizontalFace::PosX | HorizontalFace::NegX => (rise, [cz, cx]),
+                    }
+                }
+            };
+            let pos = [x as f32 + cx, y as f32 + RAIL_HEIGHT + rise, z as f32 + cz];
+            res_vertex.push(ChunkVertex {
+                pos: [pos[0] + offset_x, pos[1] + offset_y, pos[2] + offset_z],
+                texture_top_left: [uv.x, uv.y],
+                texture_uv: [texture_uv[0] * uv.width, texture_uv[1] * uv.height],
+                texture_max_uv: [uv.width, uv.height],
+                texture_size: [uv.width, uv.height],
+                occl_and_face: v,
+                tint: NO_TINT,
+            });
+        }
+        for kk in 0..6 {
+            res_index.push(n_of_different_vertex + order2[s][kk]);
+        }
+        n_of_different_vertex += 4;
+        tot_quad += 1;
+        act_quad += 1;
+    }
+
     let res_index: Vec<u32> = res_index.iter().map(|x| *x as u32).collect();
     (res_vertex, res_index, tot_quad, act_quad)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use voxel_rs_common::world::{ChunkBuilder, ChunkPos, ChunkPosXZ};
+
+    #[test]
+    fn test_biome_blending() {
+        let mut biome_maps: [Option<Arc<BiomeMap>>; 9] = Default::default();
+        biome_maps[4] = Some(Arc::new(BiomeMap::uniform(
+            ChunkPosXZ { px: 0, pz: 0 },
+            Biome::Plains,
+        )));
+        // The column at +x
+        biome_maps[7] = Some(Arc::new(BiomeMap::uniform(
+            ChunkPosXZ { px: 1, pz: 0 },
+            Biome::Swamp,
+        )));
+        let mut blender = BiomeBlender::new(&biome_maps);
+
+        let weights = blender.weights(5, 10);
+        assert_eq!(weights[Biome::Plains.index()], 1.0);
+        let plains = blender.tint(5, 10, BiomeTint::Grass, 0);
+        assert_eq!(plains, NO_TINT);
+
+        // The last column of the chunk sees 3 columns of swamp out of 7
+        let size = CHUNK_SIZE as i32;
+        let weights = blender.weights(size - 1, 10);
+        assert_eq!(weights[Biome::Swamp.index()], 3.0 / 7.0);
+        assert_ne!(blender.tint(size - 1, 10, BiomeTint::Grass, 0), plains);
+
+        // The missing columns at -x are ignored
+        assert_eq!(blender.weights(0, 10)[Biome::Plains.index()], 1.0);
+    }
+
+    #[test]
+    fn test_chunk_border_copy() {
+        let pos = ChunkPos::from((0, 0, 0));
+        let east_pos = pos.offset(1, 0, 0);
+        // A block and a light level on the face of the chunk at +x that touches the chunk
+        let east = ChunkBuilder::new(east_pos).block((0, 5, 7), 3).build();
+        let mut east_light = LightChunk::new(east_pos);
+        east_light.light[(5 * CHUNK_SIZE + 7) as usize] = 4;
+        let input = ChunkMeshInput::new(
+            Arc::new(Chunk::new(pos)),
+            Arc::new(LightChunk::new(pos)),
+            |i| {
+                if i == 22 {
+                    Some((&east, &east_light))
+                } else {
+                    None
+                }
+            },
+            Default::default(),
+        );
+        assert_eq!(input.neighbours, 1 << 22);
+        assert_eq!(
+            input.border.len(),
+            N_SIZE.pow(3) - (CHUNK_SIZE as usize).pow(3)
+        );
+        let border_at = |pos| {
+            border_positions()
+                .zip(input.border.iter())
+                .find(|(border_pos, _)| *border_pos == pos)
+                .map(|(_, &block)| block)
+                .unwrap()
+        };
+        assert_eq!(border_at((N_SIZE - 1, 6, 8)), (3, 4));
+        assert_eq!(border_at((N_SIZE - 1, 6, 9)), (0, 15));
+        // The missing chunks are air, fully lit
+        assert_eq!(border_at((0, 6, 8)), (0, 15));
+    }
+
+    #[test]
+    fn test_light_view_across_chunks() {
+        let pos = ChunkPos::from((0, 0, 0));
+        let east_pos = pos.offset(1, 0, 0);
+        let size = CHUNK_SIZE as i32;
+        // A dark chunk with a block against the chunk at +x, whose light comes from the chunk at +x
+        let chunk = ChunkBuilder::new(pos)
+            .block((CHUNK_SIZE - 1, 4, 7), 1)
+            .build();
+        let mut light = LightChunk::new(pos);
+        light.light.iter_mut().for_each(|level| *level = 0);
+        let east = Chunk::new(east_pos);
+        let mut east_light = LightChunk::new(east_pos);
+        east_light.light.iter_mut().for_each(|level| *level = 0);
+        east_light.light[(5 * CHUNK_SIZE + 7) as usize] = 12;
+        let input = ChunkMeshInput::new(
+            Arc::new(chunk),
+            Arc::new(light),
+            |i| {
+                if i == 22 {
+                    Some((&east, &east_light))
+                } else {
+                    None
+                }
+            },
+            Default::default(),
+        );
+        let meshes = vec![
+            BlockMesh::Empty,
+            BlockMesh::FullCube {
+                textures: Default::default(),
+                tints: [None; 6],
+                flickers: false,
+            },
+        ];
+        let view = LightView::new(&input, &meshes);
+        assert!(view.is_opaque(size - 1, 4, 7));
+        assert_eq!(view.light(size, 5, 7), 12);
+
+        // The top face of the block is lit by the chunk at +x on its +x side only
+        let front = [size - 1, 5, 7];
+        let (d1, d2) = ([1, 0, 0], [0, 0, 1]);
+        assert_eq!(view.corner(front, d1, d2, 1, -1), (3, 3));
+        assert_eq!(view.corner(front, d1, d2, -1, -1), (3, 0));
+    }
+}
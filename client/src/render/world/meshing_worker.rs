@@ -1,12 +1,14 @@
 //! Meshing worker, allowing meshing to be performed in a separate thread
-use super::meshing::{greedy_meshing, ChunkMeshData};
+use super::meshing::{greedy_meshing, ChunkMeshInput};
 use crate::render::world::ChunkVertex;
+use std::time::Instant;
 use voxel_rs_common::block::BlockMesh;
+use voxel_rs_common::debug::{set_chunk_stat, ChunkMetric};
 use voxel_rs_common::worker::{Worker, WorkerState};
 use voxel_rs_common::world::ChunkPos;
 
 pub type ChunkMesh = (ChunkPos, Vec<ChunkVertex>, Vec<u32>);
-pub type MeshingWorker = Worker<ChunkMeshData, ChunkMesh, MeshingState>;
+pub type MeshingWorker = Worker<ChunkMeshInput, ChunkMesh, MeshingState>;
 
 pub fn start_meshing_worker(block_meshes: Vec<BlockMesh>) -> MeshingWorker {
     MeshingWorker::new(
@@ -30,11 +32,14 @@ impl MeshingState {
     }
 }
 
-impl WorkerState<ChunkMeshData, ChunkMesh> for MeshingState {
-    fn compute(&mut self, input: ChunkMeshData) -> ChunkMesh {
+impl WorkerState<ChunkMeshInput, ChunkMesh> for MeshingState {
+    fn compute(&mut self, input: ChunkMeshInput) -> ChunkMesh {
         let pos = input.chunk.pos;
+        let start = Instant::now();
         let (vertices, indices, _, _) =
             greedy_meshing(input, &self.block_meshes, &mut self.quads_reuse);
+        let millis = start.elapsed().as_secs_f64() * 1000.0;
+        set_chunk_stat(ChunkMetric::MeshTime, pos, millis);
         (pos, vertices, indices)
     }
 }
@@ -2,92 +2,144 @@
 
 use super::buffers::MultiBuffer;
 use super::frustum::Frustum;
-use super::init::{create_default_pipeline, load_glsl_shader, ShaderStage};
-use super::{buffer_from_slice, to_u8_slice};
+use super::init::{
+    create_default_pipeline, create_translucent_pipeline, load_glsl_shader, RenderInitError,
+    ShaderStage,
+};
+use super::memory::{self, MemoryCategory, TrackedBuffer, TrackedTexture};
+use super::UploadBelt;
 use crate::texture::load_image;
 use crate::window::WindowBuffers;
 use image::{ImageBuffer, Rgba};
-use nalgebra::{Matrix4, Similarity3, Translation3, UnitQuaternion, Vector3};
+use nalgebra::Matrix4;
+use std::collections::HashMap;
+use std::time::Instant;
+use voxel_rs_common::block::entity::Sign;
 use voxel_rs_common::data::vox::VoxelModel;
 use voxel_rs_common::debug::send_debug_info;
+use voxel_rs_common::player::PlayerId;
 use voxel_rs_common::registry::Registry;
-use voxel_rs_common::world::{BlockPos, ChunkPos};
+use voxel_rs_common::world::{BlockPos, ChunkPos, FarTile, FarTilePos};
 
+mod far_terrain;
 mod meshing;
 mod meshing_worker;
 mod model;
+mod selection;
+mod sign;
 mod skybox;
-pub use self::meshing::ChunkMeshData;
+pub use self::meshing::ChunkMeshInput;
 pub use self::meshing_worker::{start_meshing_worker, ChunkMesh, MeshingWorker};
 pub use self::model::Model;
+pub use self::selection::selection_box;
 
 /// All the state necessary to render the world.
 pub struct WorldRenderer {
-    // View-projection matrix
-    uniform_view_proj: wgpu::Buffer,
+    // View-projection matrix, followed by the position and the level of the light held by the player,
+    // and the time in seconds for the animated blocks
+    uniform_view_proj: TrackedBuffer,
     // Model matrix
-    uniform_model: wgpu::Buffer,
+    uniform_model: TrackedBuffer,
     // Chunk rendering
     chunk_index_buffers: MultiBuffer<ChunkPos, u32>,
     chunk_vertex_buffers: MultiBuffer<ChunkPos, ChunkVertex>,
     chunk_pipeline: wgpu::RenderPipeline,
     chunk_bind_group: wgpu::BindGroup,
+    // The atlases sampled through the chunk bind group
+    _texture_atlas: TrackedTexture,
+    _emissive_atlas: TrackedTexture,
     // Skybox rendering
-    skybox_index_buffer: wgpu::Buffer,
-    skybox_vertex_buffer: wgpu::Buffer,
+    skybox_index_buffer: TrackedBuffer,
+    skybox_vertex_buffer: TrackedBuffer,
     skybox_pipeline: wgpu::RenderPipeline,
     // View-proj and model bind group
     vpm_bind_group: wgpu::BindGroup,
     // Targeted block rendering
-    target_vertex_buffer: wgpu::Buffer,
+    target_vertex_buffer: TrackedBuffer,
     target_pipeline: wgpu::RenderPipeline,
+    // World edit selection rendering, the faces and then the edges in the same buffer
+    selection_vertex_buffer: TrackedBuffer,
+    selection_face_pipeline: wgpu::RenderPipeline,
+    selection_edge_pipeline: wgpu::RenderPipeline,
+    // The cuboid whose vertices are in the buffer
+    selection_box: Option<(BlockPos, BlockPos)>,
     // Model rendering
     model_index_buffers: MultiBuffer<u32, u32>,
     model_vertex_buffers: MultiBuffer<u32, RgbVertex>,
     model_pipeline: wgpu::RenderPipeline,
+    // The models recolored by the skins of the players, by player and model id
+    skin_index_buffers: MultiBuffer<(PlayerId, u32), u32>,
+    skin_vertex_buffers: MultiBuffer<(PlayerId, u32), RgbVertex>,
+    // Far terrain rendering, with the model pipeline
+    far_tile_index_buffers: MultiBuffer<FarTilePos, u32>,
+    far_tile_vertex_buffers: MultiBuffer<FarTilePos, RgbVertex>,
+    // Sign rendering, with the chunk bind group layout without the emissive atlas and a texture per sign
+    sign_bind_group_layout: wgpu::BindGroupLayout,
+    sign_pipeline: wgpu::RenderPipeline,
+    signs: HashMap<BlockPos, self::sign::SignMesh>,
+    // When the renderer was created, the origin of the time of the animations
+    start_time: Instant,
 }
 
 impl WorldRenderer {
     pub fn new(
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
         texture_atlas: ImageBuffer<Rgba<u8>, Vec<u8>>,
+        emissive_atlas: ImageBuffer<Rgba<u8>, Vec<u8>>,
         models: &Registry<VoxelModel>,
-    ) -> Self {
+    ) -> Result<Self, RenderInitError> {
         // Load texture atlas
         let texture_atlas = load_image(device, encoder, texture_atlas);
         let texture_atlas_view = texture_atlas.create_view(&wgpu::TextureViewDescriptor::default());
+        let emissive_atlas = load_image(device, encoder, emissive_atlas);
+        let emissive_atlas_view =
+            emissive_atlas.create_view(&wgpu::TextureViewDescriptor::default());
 
         // Create uniform buffers
-        let uniform_view_proj = device.create_buffer(&wgpu::BufferDescriptor {
-            mapped_at_creation: false,
-            label: None,
-            size: 64,
-            usage: (wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST),
-        });
-        let uniform_model = device.create_buffer(&wgpu::BufferDescriptor {
-            mapped_at_creation: false,
-            label: None,
-            size: 64,
-            usage: (wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST),
-        });
+        let uniform_view_proj = memory::create_buffer(
+            device,
+            MemoryCategory::Other,
+            &wgpu::BufferDescriptor {
+                mapped_at_creation: false,
+                label: None,
+                size: 64 + 16 + 16,
+                usage: (wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST),
+            },
+        );
+        let uniform_model = memory::create_buffer(
+            device,
+            MemoryCategory::Other,
+            &wgpu::BufferDescriptor {
+                mapped_at_creation: false,
+                label: None,
+                size: 64,
+                usage: (wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST),
+            },
+        );
 
         // Create uniform bind group
-        let chunk_bind_group_layout = device.create_bind_group_layout(&CHUNK_BIND_GROUP_LAYOUT);
+        let chunk_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &CHUNK_BIND_GROUP_LAYOUT_ENTRIES,
+            });
         let chunk_bind_group = create_chunk_bind_group(
             device,
             &chunk_bind_group_layout,
             &texture_atlas_view,
+            Some(&emissive_atlas_view),
             &uniform_view_proj,
         );
 
         // Create chunk pipeline
         let chunk_pipeline = {
             let vertex_shader_bytes =
-                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/world.vert");
+                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/world.vert")?;
             let vertex_shader = wgpu::util::make_spirv(&vertex_shader_bytes);
             let fragment_shader_bytes =
-                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/world.frag");
+                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/world.frag")?;
             let fragment_shader = wgpu::util::make_spirv(&fragment_shader_bytes);
 
             create_default_pipeline(
@@ -120,10 +172,10 @@ impl WorldRenderer {
         // Create skybox pipeline
         let skybox_pipeline = {
             let vertex_shader_bytes =
-                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/skybox.vert");
+                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/skybox.vert")?;
             let vertex_shader = wgpu::util::make_spirv(&vertex_shader_bytes);
             let fragment_shader_bytes =
-                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/skybox.frag");
+                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/skybox.frag")?;
             let fragment_shader = wgpu::util::make_spirv(&fragment_shader_bytes);
 
             create_default_pipeline(
@@ -142,18 +194,22 @@ impl WorldRenderer {
         };
 
         // Create target buffer and pipeline
-        let target_vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            mapped_at_creation: false,
-            label: None,
-            size: 8 * std::mem::size_of::<SkyboxVertex>() as u64,
-            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
-        });
+        let target_vertex_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Other,
+            &wgpu::BufferDescriptor {
+                mapped_at_creation: false,
+                label: None,
+                size: 8 * std::mem::size_of::<SkyboxVertex>() as u64,
+                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            },
+        );
         let target_pipeline = {
             let vertex_shader_bytes =
-                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/target.vert");
+                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/target.vert")?;
             let vertex_shader = wgpu::util::make_spirv(&vertex_shader_bytes);
             let fragment_shader_bytes =
-                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/target.frag");
+                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/target.frag")?;
             let fragment_shader = wgpu::util::make_spirv(&fragment_shader_bytes);
 
             create_default_pipeline(
@@ -171,13 +227,50 @@ impl WorldRenderer {
             )
         };
 
+        // Create selection buffer and pipelines
+        let selection_vertex_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Other,
+            &wgpu::BufferDescriptor {
+                mapped_at_creation: false,
+                label: None,
+                size: (self::selection::FACE_VERTICES + self::selection::EDGE_VERTICES) as u64
+                    * std::mem::size_of::<self::selection::SelectionVertex>() as u64,
+                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            },
+        );
+        let create_selection_pipeline = |primitive_topology| {
+            let vertex_shader_bytes =
+                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/selection.vert")?;
+            let vertex_shader = wgpu::util::make_spirv(&vertex_shader_bytes);
+            let fragment_shader_bytes =
+                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/selection.frag")?;
+            let fragment_shader = wgpu::util::make_spirv(&fragment_shader_bytes);
+
+            create_translucent_pipeline(
+                device,
+                &vpm_bind_group_layout,
+                vertex_shader,
+                fragment_shader,
+                primitive_topology,
+                wgpu::VertexBufferDescriptor {
+                    stride: std::mem::size_of::<self::selection::SelectionVertex>() as u64,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &self::selection::SELECTION_VERTEX_ATTRIBUTES,
+                },
+            )
+        };
+        let selection_face_pipeline =
+            create_selection_pipeline(wgpu::PrimitiveTopology::TriangleList);
+        let selection_edge_pipeline = create_selection_pipeline(wgpu::PrimitiveTopology::LineList);
+
         // Create model pipeline
         let model_pipeline = {
             let vertex_shader_bytes =
-                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/model.vert");
+                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/model.vert")?;
             let vertex_shader = wgpu::util::make_spirv(&vertex_shader_bytes);
             let fragment_shader_bytes =
-                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/model.frag");
+                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/model.frag")?;
             let fragment_shader = wgpu::util::make_spirv(&fragment_shader_bytes);
 
             create_default_pipeline(
@@ -195,55 +288,133 @@ impl WorldRenderer {
             )
         };
 
+        // Create sign pipeline
+        let sign_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &CHUNK_BIND_GROUP_LAYOUT_ENTRIES[..3],
+            });
+        let sign_pipeline = {
+            let vertex_shader_bytes =
+                load_glsl_shader(ShaderStage::Vertex, "assets/shaders/sign.vert")?;
+            let vertex_shader = wgpu::util::make_spirv(&vertex_shader_bytes);
+            let fragment_shader_bytes =
+                load_glsl_shader(ShaderStage::Fragment, "assets/shaders/sign.frag")?;
+            let fragment_shader = wgpu::util::make_spirv(&fragment_shader_bytes);
+
+            create_default_pipeline(
+                device,
+                &sign_bind_group_layout,
+                vertex_shader,
+                fragment_shader,
+                wgpu::PrimitiveTopology::TriangleList,
+                wgpu::VertexBufferDescriptor {
+                    stride: std::mem::size_of::<self::sign::SignVertex>() as u64,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                    attributes: &self::sign::SIGN_VERTEX_ATTRIBUTES,
+                },
+                false,
+            )
+        };
+
         // Mesh models
-        let mut model_index_buffers =
-            MultiBuffer::with_capacity(device, 10466, wgpu::BufferUsage::INDEX);
-        let mut model_vertex_buffers =
-            MultiBuffer::with_capacity(device, 87360, wgpu::BufferUsage::VERTEX);
+        let mut model_index_buffers = MultiBuffer::with_capacity(
+            device,
+            10466,
+            wgpu::BufferUsage::INDEX,
+            MemoryCategory::ModelMeshes,
+        );
+        let mut model_vertex_buffers = MultiBuffer::with_capacity(
+            device,
+            87360,
+            wgpu::BufferUsage::VERTEX,
+            MemoryCategory::ModelMeshes,
+        );
         for mesh_id in 0..models.get_number_of_ids() {
             let (vertices, indices) =
                 self::model::mesh_model(models.get_value_by_id(mesh_id).unwrap());
-            model_index_buffers.update(device, encoder, mesh_id, &indices);
-            model_vertex_buffers.update(device, encoder, mesh_id, &vertices);
+            model_index_buffers.update(device, encoder, belt, mesh_id, &indices);
+            model_vertex_buffers.update(device, encoder, belt, mesh_id, &vertices);
         }
 
-        Self {
+        Ok(Self {
             uniform_view_proj,
             uniform_model,
             chunk_index_buffers: MultiBuffer::with_capacity(
                 device,
                 123040,
                 wgpu::BufferUsage::INDEX,
+                MemoryCategory::ChunkMeshes,
             ),
             chunk_vertex_buffers: MultiBuffer::with_capacity(
                 device,
                 43680,
                 wgpu::BufferUsage::VERTEX,
+                MemoryCategory::ChunkMeshes,
             ),
             chunk_pipeline,
             chunk_bind_group,
+            _texture_atlas: texture_atlas,
+            _emissive_atlas: emissive_atlas,
             skybox_vertex_buffer,
             skybox_index_buffer,
             skybox_pipeline,
             vpm_bind_group,
             target_vertex_buffer,
             target_pipeline,
+            selection_vertex_buffer,
+            selection_face_pipeline,
+            selection_edge_pipeline,
+            selection_box: None,
             model_pipeline,
             model_index_buffers,
             model_vertex_buffers,
-        }
+            skin_index_buffers: MultiBuffer::with_capacity(
+                device,
+                10466,
+                wgpu::BufferUsage::INDEX,
+                MemoryCategory::ModelMeshes,
+            ),
+            skin_vertex_buffers: MultiBuffer::with_capacity(
+                device,
+                87360,
+                wgpu::BufferUsage::VERTEX,
+                MemoryCategory::ModelMeshes,
+            ),
+            far_tile_index_buffers: MultiBuffer::with_capacity(
+                device,
+                6 * 1024 * 81,
+                wgpu::BufferUsage::INDEX,
+                MemoryCategory::ChunkMeshes,
+            ),
+            far_tile_vertex_buffers: MultiBuffer::with_capacity(
+                device,
+                33 * 33 * 81,
+                wgpu::BufferUsage::VERTEX,
+                MemoryCategory::ChunkMeshes,
+            ),
+            sign_bind_group_layout,
+            sign_pipeline,
+            signs: HashMap::new(),
+            start_time: Instant::now(),
+        })
     }
 
     pub fn render(
         &mut self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
         buffers: WindowBuffers,
         data: &crate::window::WindowData,
         frustum: &Frustum,
         enable_culling: bool,
         pointed_block: Option<(BlockPos, usize)>,
+        selection: Option<(BlockPos, BlockPos)>,
+        hand_light: u8,
+        flicker: f32,
         models: &[model::Model],
+        far_tiles: &[FarTilePos],
     ) {
         //============= RENDER =============//
         // TODO: what if win_h is 0 ?
@@ -271,9 +442,53 @@ impl WorldRenderer {
         .into();
 
         // Update view_proj matrix
-        let src_buffer =
-            buffer_from_slice(device, wgpu::BufferUsage::COPY_SRC, to_u8_slice(&view_proj));
-        encoder.copy_buffer_to_buffer(&src_buffer, 0, &self.uniform_view_proj, 0, 64);
+        belt.upload(device, encoder, &self.uniform_view_proj, 0, &view_proj);
+        // The held light is at the camera
+        let hand_light_pos = frustum.position;
+        belt.upload(
+            device,
+            encoder,
+            &self.uniform_view_proj,
+            64,
+            &[
+                hand_light_pos.x as f32,
+                hand_light_pos.y as f32,
+                hand_light_pos.z as f32,
+                hand_light as f32,
+            ],
+        );
+        belt.upload(
+            device,
+            encoder,
+            &self.uniform_view_proj,
+            80,
+            &[self.start_time.elapsed().as_secs_f32(), flicker, 0.0, 0.0],
+        );
+
+        // Draw the far terrain, behind the chunks
+        {
+            // Update model buffer
+            let identity: [[f32; 4]; 4] = Matrix4::<f32>::identity().into();
+            belt.upload(device, encoder, &self.uniform_model, 0, &identity);
+            let mut rpass = super::render::create_default_render_pass(encoder, buffers);
+            rpass.set_pipeline(&self.model_pipeline);
+            rpass.set_bind_group(0, &self.vpm_bind_group, &[]);
+            rpass.set_vertex_buffer(0, self.far_tile_vertex_buffers.get_buffer().slice(..));
+            rpass.set_index_buffer(self.far_tile_index_buffers.get_buffer().slice(..));
+            for tile_pos in far_tiles {
+                if let Some((index_pos, index_len)) =
+                    self.far_tile_index_buffers.get_pos_len(tile_pos)
+                {
+                    let (vertex_pos, _) =
+                        self.far_tile_vertex_buffers.get_pos_len(tile_pos).unwrap();
+                    rpass.draw_indexed(
+                        (index_pos as u32)..((index_pos + index_len) as u32),
+                        vertex_pos as i32,
+                        0..1,
+                    );
+                }
+            }
+        }
 
         // Draw all the chunks
         {
@@ -304,13 +519,29 @@ impl WorldRenderer {
             );
         }
 
+        // Draw the text of the signs, in front of their blocks
+        {
+            let mut rpass = super::render::create_default_render_pass(encoder, buffers);
+            rpass.set_pipeline(&self.sign_pipeline);
+            for (pos, sign) in self.signs.iter() {
+                let chunk_pos = pos.containing_chunk_pos();
+                if !enable_culling || Frustum::contains_chunk(&planes, &view_mat, chunk_pos) {
+                    rpass.set_bind_group(0, &sign.bind_group, &[]);
+                    rpass.set_vertex_buffer(0, sign.vertex_buffer.slice(..));
+                    rpass.draw(0..6, 0..1);
+                }
+            }
+        }
+
         // Draw the skybox
         {
             // Update model buffer
-            let src_buffer = buffer_from_slice(
+            belt.upload(
                 device,
-                wgpu::BufferUsage::COPY_SRC,
-                to_u8_slice(&[
+                encoder,
+                &self.uniform_model,
+                0,
+                &[
                     1.0,
                     0.0,
                     0.0,
@@ -327,9 +558,8 @@ impl WorldRenderer {
                     frustum.position.y as f32,
                     frustum.position.z as f32,
                     1.0,
-                ]),
+                ],
             );
-            encoder.copy_buffer_to_buffer(&src_buffer, 0, &self.uniform_model, 0, 64);
             let mut rpass = super::render::create_default_render_pass(encoder, buffers);
             rpass.set_pipeline(&self.skybox_pipeline);
             rpass.set_bind_group(0, &self.vpm_bind_group, &[]);
@@ -342,23 +572,20 @@ impl WorldRenderer {
         if let Some((target_pos, target_face)) = pointed_block {
             // Generate the vertices
             // TODO: maybe check if they changed since last frame
-            let src_buffer = buffer_from_slice(
+            belt.upload(
                 device,
-                wgpu::BufferUsage::COPY_SRC,
-                to_u8_slice(&create_target_vertices(target_face)),
-            );
-            encoder.copy_buffer_to_buffer(
-                &src_buffer,
-                0,
+                encoder,
                 &self.target_vertex_buffer,
                 0,
-                8 * std::mem::size_of::<SkyboxVertex>() as u64,
+                &create_target_vertices(target_face),
             );
             // Update model buffer
-            let src_buffer = buffer_from_slice(
+            belt.upload(
                 device,
-                wgpu::BufferUsage::COPY_SRC,
-                to_u8_slice(&[
+                encoder,
+                &self.uniform_model,
+                0,
+                &[
                     1.0,
                     0.0,
                     0.0,
@@ -375,9 +602,8 @@ impl WorldRenderer {
                     target_pos.py as f32,
                     target_pos.pz as f32,
                     1.0,
-                ]),
+                ],
             );
-            encoder.copy_buffer_to_buffer(&src_buffer, 0, &self.uniform_model, 0, 64);
             let mut rpass = super::render::create_default_render_pass(encoder, buffers);
             rpass.set_pipeline(&self.target_pipeline);
             rpass.set_bind_group(0, &self.vpm_bind_group, &[]);
@@ -387,61 +613,91 @@ impl WorldRenderer {
 
         // Draw the models
         for model in models {
-            // Compute model matrix
-            let mut transform = Similarity3::identity();
-            transform.append_scaling_mut(model.scale);
-            let offset_translation = Translation3::from(-Vector3::from(model.rot_offset));
-            transform.append_translation_mut(&offset_translation);
-            transform.append_rotation_mut(&UnitQuaternion::from_axis_angle(
-                &Vector3::y_axis(),
-                model.rot_y,
-            ));
-            transform.append_translation_mut(&Translation3::from(
-                Vector3::new(model.pos_x, model.pos_y, model.pos_z)
-                    + &Vector3::from(model.rot_offset),
-            ));
-            let transformation_matrix: Matrix4<f32> = nalgebra::convert(transform);
+            let transformation_matrix: Matrix4<f32> = nalgebra::convert(model.transform());
             // Update model buffer
-            let src_buffer = buffer_from_slice(
+            belt.upload(
                 device,
-                wgpu::BufferUsage::COPY_SRC,
-                to_u8_slice(transformation_matrix.as_ref()),
+                encoder,
+                &self.uniform_model,
+                0,
+                transformation_matrix.as_slice(),
             );
-            encoder.copy_buffer_to_buffer(&src_buffer, 0, &self.uniform_model, 0, 64);
-            // Draw model
+            // Draw model, with the mesh recolored by the skin if there is one
             let mut rpass = super::render::create_default_render_pass(encoder, buffers);
             rpass.set_pipeline(&self.model_pipeline);
             rpass.set_bind_group(0, &self.vpm_bind_group, &[]);
-            rpass.set_vertex_buffer(0, self.model_vertex_buffers.get_buffer().slice(..));
-            rpass.set_index_buffer(self.model_index_buffers.get_buffer().slice(..));
-            let (index_pos, index_len) = self
-                .model_index_buffers
-                .get_pos_len(&model.mesh_id)
-                .unwrap();
-            let (vertex_pos, _) = self
-                .model_vertex_buffers
-                .get_pos_len(&model.mesh_id)
-                .unwrap();
+            let skin_key = model.skin.map(|player| (player, model.mesh_id));
+            let skinned = skin_key.and_then(|key| {
+                Some((
+                    self.skin_index_buffers.get_pos_len(&key)?,
+                    self.skin_vertex_buffers.get_pos_len(&key)?,
+                ))
+            });
+            let ((index_pos, index_len), (vertex_pos, _)) = match skinned {
+                Some(pos_lens) => {
+                    rpass.set_vertex_buffer(0, self.skin_vertex_buffers.get_buffer().slice(..));
+                    rpass.set_index_buffer(self.skin_index_buffers.get_buffer().slice(..));
+                    pos_lens
+                }
+                None => {
+                    rpass.set_vertex_buffer(0, self.model_vertex_buffers.get_buffer().slice(..));
+                    rpass.set_index_buffer(self.model_index_buffers.get_buffer().slice(..));
+                    (
+                        self.model_index_buffers
+                            .get_pos_len(&model.mesh_id)
+                            .unwrap(),
+                        self.model_vertex_buffers
+                            .get_pos_len(&model.mesh_id)
+                            .unwrap(),
+                    )
+                }
+            };
             rpass.draw_indexed(
                 (index_pos as u32)..((index_pos + index_len) as u32),
                 vertex_pos as i32,
                 0..1,
             );
         }
+
+        // Draw the world edit selection last, over the terrain and the models
+        if let Some((min, max)) = selection {
+            if self.selection_box != selection {
+                belt.upload(
+                    device,
+                    encoder,
+                    &self.selection_vertex_buffer,
+                    0,
+                    &self::selection::create_selection_vertices(min, max),
+                );
+                self.selection_box = selection;
+            }
+            let mut rpass = super::render::create_default_render_pass(encoder, buffers);
+            rpass.set_bind_group(0, &self.vpm_bind_group, &[]);
+            rpass.set_vertex_buffer(0, self.selection_vertex_buffer.slice(..));
+            rpass.set_pipeline(&self.selection_face_pipeline);
+            rpass.draw(0..self::selection::FACE_VERTICES, 0..1);
+            rpass.set_pipeline(&self.selection_edge_pipeline);
+            let edges_start = self::selection::FACE_VERTICES;
+            rpass.draw(
+                edges_start..edges_start + self::selection::EDGE_VERTICES,
+                0..1,
+            );
+        }
     }
 
     pub fn update_chunk_mesh(
         &mut self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
         chunk_mesh: ChunkMesh,
     ) {
         let (pos, vertices, indices) = chunk_mesh;
         if vertices.len() > 0 && indices.len() > 0 {
             self.chunk_vertex_buffers
-                .update(device, encoder, pos, &vertices[..]);
+                .update(device, encoder, belt, pos, &vertices[..]);
             self.chunk_index_buffers
-                .update(device, encoder, pos, &indices[..]);
+                .update(device, encoder, belt, pos, &indices[..]);
         }
     }
 
@@ -449,6 +705,101 @@ impl WorldRenderer {
         self.chunk_vertex_buffers.remove(&pos);
         self.chunk_index_buffers.remove(&pos);
     }
+
+    pub fn update_far_tile_mesh(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
+        tile: &FarTile,
+        block_colors: &[[u8; 4]],
+    ) {
+        let (vertices, indices) = self::far_terrain::mesh_far_tile(tile, block_colors);
+        if indices.len() > 0 {
+            self.far_tile_vertex_buffers
+                .update(device, encoder, belt, tile.pos, &vertices[..]);
+            self.far_tile_index_buffers
+                .update(device, encoder, belt, tile.pos, &indices[..]);
+        }
+    }
+
+    /// Bake the text of a sign, unless it didn't change
+    pub fn update_sign(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pos: BlockPos,
+        sign: &Sign,
+    ) {
+        if self.signs.get(&pos).map(|mesh| &mesh.sign) == Some(sign) {
+            return;
+        }
+        let mesh = self::sign::SignMesh::new(
+            device,
+            encoder,
+            &self.sign_bind_group_layout,
+            &self.uniform_view_proj,
+            pos,
+            sign,
+        );
+        self.signs.insert(pos, mesh);
+    }
+
+    pub fn remove_sign(&mut self, pos: BlockPos) {
+        self.signs.remove(&pos);
+    }
+
+    /// Mesh the models recolored by the skin of a player, replacing the previous skin of the player
+    pub fn update_player_skin(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
+        player: PlayerId,
+        models: &[(u32, VoxelModel)],
+    ) {
+        self.remove_player_skin(player);
+        for (mesh_id, model) in models {
+            let (vertices, indices) = self::model::mesh_model(model);
+            self.skin_index_buffers
+                .update(device, encoder, belt, (player, *mesh_id), &indices);
+            self.skin_vertex_buffers
+                .update(device, encoder, belt, (player, *mesh_id), &vertices);
+        }
+    }
+
+    pub fn remove_player_skin(&mut self, player: PlayerId) {
+        let keys: Vec<_> = self
+            .skin_index_buffers
+            .keys()
+            .filter(|&(skin_player, _)| skin_player == player)
+            .collect();
+        for key in keys {
+            self.skin_index_buffers.remove(&key);
+            self.skin_vertex_buffers.remove(&key);
+        }
+    }
+
+    /// Shrink the buffers of the meshes to the meshes they contain, to free the GPU memory of the removed meshes
+    pub fn shrink_mesh_buffers(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.chunk_index_buffers.shrink_to_fit(device, encoder);
+        self.chunk_vertex_buffers.shrink_to_fit(device, encoder);
+        self.far_tile_index_buffers.shrink_to_fit(device, encoder);
+        self.far_tile_vertex_buffers.shrink_to_fit(device, encoder);
+        self.skin_index_buffers.shrink_to_fit(device, encoder);
+        self.skin_vertex_buffers.shrink_to_fit(device, encoder);
+        self.model_index_buffers.shrink_to_fit(device, encoder);
+        self.model_vertex_buffers.shrink_to_fit(device, encoder);
+    }
+
+    pub fn remove_far_tile_mesh(&mut self, pos: FarTilePos) {
+        self.far_tile_vertex_buffers.remove(&pos);
+        self.far_tile_index_buffers.remove(&pos);
+    }
 }
 
 /*========== CHUNK RENDERING ==========*/
@@ -461,10 +812,12 @@ pub struct ChunkVertex {
     pub texture_max_uv: [f32; 2],
     pub texture_uv: [f32; 2],
     pub occl_and_face: u32,
+    /// The biome color multiplied into the texture, with the red in the lowest byte
+    pub tint: u32,
 }
 
 /// Chunk vertex attributes
-const CHUNK_VERTEX_ATTRIBUTES: [wgpu::VertexAttributeDescriptor; 6] = [
+const CHUNK_VERTEX_ATTRIBUTES: [wgpu::VertexAttributeDescriptor; 7] = [
     wgpu::VertexAttributeDescriptor {
         shader_location: 0,
         format: wgpu::VertexFormat::Float3,
@@ -495,45 +848,62 @@ const CHUNK_VERTEX_ATTRIBUTES: [wgpu::VertexAttributeDescriptor; 6] = [
         format: wgpu::VertexFormat::Uint,
         offset: 4 * (3 + 2 + 2 + 2 + 2),
     },
+    wgpu::VertexAttributeDescriptor {
+        shader_location: 6,
+        format: wgpu::VertexFormat::Uint,
+        offset: 4 * (3 + 2 + 2 + 2 + 2 + 1),
+    },
 ];
 
-const CHUNK_BIND_GROUP_LAYOUT: wgpu::BindGroupLayoutDescriptor<'static> =
-    wgpu::BindGroupLayoutDescriptor {
-        label: None,
-        entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStage::VERTEX,
-                ty: wgpu::BindingType::UniformBuffer {
-                    dynamic: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStage::FRAGMENT,
-                ty: wgpu::BindingType::Sampler { comparison: true },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 2,
-                visibility: wgpu::ShaderStage::FRAGMENT,
-                ty: wgpu::BindingType::SampledTexture {
-                    component_type: wgpu::TextureComponentType::Uint,
-                    multisampled: false,
-                    dimension: wgpu::TextureViewDimension::D2,
-                },
-                count: None,
-            },
-        ],
-    };
+/// The uniforms, the sampler, the texture atlas and the emissive atlas of the chunks.
+/// The signs use the same entries except the emissive atlas, because they don't glow.
+const CHUNK_BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 4] = [
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        // the fragment shader reads the held light
+        visibility: wgpu::ShaderStage::from_bits_truncate(
+            wgpu::ShaderStage::VERTEX.bits() | wgpu::ShaderStage::FRAGMENT.bits(),
+        ),
+        ty: wgpu::BindingType::UniformBuffer {
+            dynamic: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Sampler { comparison: true },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::SampledTexture {
+            component_type: wgpu::TextureComponentType::Uint,
+            multisampled: false,
+            dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::SampledTexture {
+            component_type: wgpu::TextureComponentType::Uint,
+            multisampled: false,
+            dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    },
+];
 
 /// Create chunk bind group
 fn create_chunk_bind_group(
     device: &wgpu::Device,
     layout: &wgpu::BindGroupLayout,
     texture_atlas_view: &wgpu::TextureView,
+    emissive_atlas_view: Option<&wgpu::TextureView>,
     uniform_view_proj: &wgpu::Buffer,
 ) -> wgpu::BindGroup {
     // Create texture sampler
@@ -551,23 +921,31 @@ fn create_chunk_bind_group(
         anisotropy_clamp: None,
     });
 
+    let mut entries = vec![
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(uniform_view_proj.slice(0..96)),
+        },
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&sampler),
+        },
+        wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::TextureView(texture_atlas_view),
+        },
+    ];
+    if let Some(emissive_atlas_view) = emissive_atlas_view {
+        entries.push(wgpu::BindGroupEntry {
+            binding: 3,
+            resource: wgpu::BindingResource::TextureView(emissive_atlas_view),
+        });
+    }
+
     device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: None,
         layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer(uniform_view_proj.slice(0..64)),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: wgpu::BindingResource::TextureView(texture_atlas_view),
-            },
-        ],
+        entries: &entries,
     })
 }
 
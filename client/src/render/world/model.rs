@@ -1,5 +1,7 @@
 use super::RgbVertex;
+use nalgebra::{Isometry3, Point3, Similarity3, Translation3, UnitQuaternion, Vector3};
 use voxel_rs_common::data::vox::VoxelModel;
+use voxel_rs_common::player::PlayerId;
 
 /// Data structure used to draw a pre-loaded model
 /// Contains the position, scale and its id in the model registry
@@ -15,6 +17,47 @@ pub struct Model {
     pub rot_y: f32,
     /// Offset to apply before rotating the model
     pub rot_offset: [f32; 3],
+    /// Movement of the model before scaling, in voxels. Used to pose the parts of the animated models.
+    pub pose: Isometry3<f32>,
+    /// The player whose skin recolors the model, if any
+    pub skin: Option<PlayerId>,
+}
+
+impl Model {
+    /// The transformation from the voxels of the mesh to the world
+    pub fn transform(&self) -> Similarity3<f32> {
+        let mut transform = Similarity3::from_isometry(self.pose, 1.0);
+        transform.append_scaling_mut(self.scale);
+        let offset_translation = Translation3::from(-Vector3::from(self.rot_offset));
+        transform.append_translation_mut(&offset_translation);
+        transform.append_rotation_mut(&UnitQuaternion::from_axis_angle(
+            &Vector3::y_axis(),
+            self.rot_y,
+        ));
+        transform.append_translation_mut(&Translation3::from(
+            Vector3::new(self.pos_x, self.pos_y, self.pos_z) + Vector3::from(self.rot_offset),
+        ));
+        transform
+    }
+
+    /// The minimum and maximum corners of the axis-aligned box containing the model in the world,
+    /// if its mesh is `size` voxels large
+    pub fn bounding_box(&self, size: [usize; 3]) -> (Vector3<f32>, Vector3<f32>) {
+        let transform = self.transform();
+        let mut min = Vector3::repeat(f32::INFINITY);
+        let mut max = Vector3::repeat(f32::NEG_INFINITY);
+        for corner in 0..8 {
+            let voxel = Point3::new(
+                (corner & 1) as f32 * size[0] as f32,
+                (corner >> 1 & 1) as f32 * size[1] as f32,
+                (corner >> 2 & 1) as f32 * size[2] as f32,
+            );
+            let point = transform * voxel;
+            min = min.inf(&point.coords);
+            max = max.sup(&point.coords);
+        }
+        (min, max)
+    }
 }
 
 const D: [[i32; 3]; 6] = [
@@ -37,6 +80,12 @@ pub struct Quad {
     v4: u32, // i = 1 j = 1 => (y, z) = (1, 1)
 }
 
+impl Quad {
+    fn is_same(&self) -> bool {
+        self.v1 == self.v2 && self.v2 == self.v3 && self.v3 == self.v4
+    }
+}
+
 fn ambiant_occl(corners: u32, edge: u32) -> u32 {
     if edge == 2 {
         return 0;
@@ -66,6 +115,8 @@ const D_DELTA2: [[i32; 3]; 6] = [
     [0, 1, 0],
 ];
 
+/// Mesh a model: only the faces between a full voxel and an empty voxel are meshed, with ambient occlusion,
+/// and the adjacent faces with the same color and ambient occlusion are merged
 pub fn mesh_model(model: &VoxelModel) -> (Vec<RgbVertex>, Vec<u32>) {
     let mut res_vertex: Vec<RgbVertex> = Vec::new();
     let mut res_index: Vec<usize> = Vec::new();
@@ -215,80 +266,148 @@ pub fn mesh_model(model: &VoxelModel) -> (Vec<RgbVertex>, Vec<u32>) {
     ];
     let mut n_of_different_vertex = 0;
 
-    const DX: [[i32; 6]; 3] = [[0, 0, 0, 0, 0, 0], [0, 0, 1, 1, 1, 1], [0, 0, 1, 1, 1, 1]];
-
-    const DY: [[i32; 6]; 3] = [[0, 0, 0, 0, 1, 1], [1, 1, 0, 0, 0, 0], [1, 1, 0, 0, 1, 1]];
-
-    const DZ: [[i32; 6]; 3] = [[1, 1, 1, 1, 0, 0], [0, 0, 0, 0, 0, 0], [1, 1, 1, 1, 0, 0]];
+    // Axes of the faces of every direction: the normal, then the axis from v1 to v3 and the axis from v1 to v2
+    const FACE_AXES: [[usize; 3]; 6] = [
+        [0, 1, 2],
+        [0, 1, 2],
+        [1, 0, 2],
+        [1, 0, 2],
+        [2, 0, 1],
+        [2, 0, 1],
+    ];
+    let sizes = [size_x, size_y, size_z];
+    let mut tot_quad = 0;
+    let mut act_quad = 0;
 
     for s in 0..6 {
         // each direction
+        let [normal_axis, j_axis, k_axis] = FACE_AXES[s];
+        let pos = |i: usize, j: usize, k: usize| -> [usize; 3] {
+            let mut pos = [0; 3];
+            pos[normal_axis] = i;
+            pos[j_axis] = j;
+            pos[k_axis] = k;
+            pos
+        };
+        let face = |i: usize, j: usize, k: usize| -> usize {
+            let [x, y, z] = pos(i, j, k);
+            ind_mesh(s, x, y, z)
+        };
+        let (size_i, size_j, size_k) = (sizes[normal_axis], sizes[j_axis], sizes[k_axis]);
 
-        for i in 0..size_x {
-            // x x y y z z
-            for j in 0..size_y {
-                // y y x x x x
-                for k in 0..size_z {
-                    // z z z z y y
-                    if to_mesh[ind_mesh(s, i, j, k)] {
-                        let current_quad = quads[ind_mesh(s, i, j, k)];
-
-                        let (px, py, pz) = (i as i32, j as i32, k as i32);
-                        let (px2, py2, pz2) = (px + DX[0][s], py + DY[0][s], pz + DZ[0][s]);
-                        let (px3, py3, pz3) = (px + DX[1][s], py + DY[1][s], pz + DZ[1][s]);
-                        let (px4, py4, pz4) = (px + DX[2][s], py + DY[2][s], pz + DZ[2][s]);
-
-                        let mut px_ = [px as f32, px2 as f32, px3 as f32, px4 as f32];
-                        let mut py_ = [py as f32, py2 as f32, py3 as f32, py4 as f32];
-                        let mut pz_ = [pz as f32, pz2 as f32, pz3 as f32, pz4 as f32];
-                        let v = [
-                            current_quad.v1,
-                            current_quad.v2,
-                            current_quad.v3,
-                            current_quad.v4,
-                        ];
+        for i in 0..size_i {
+            for j in 0..size_j {
+                for k in 0..size_k {
+                    if !to_mesh[face(i, j, k)] {
+                        continue;
+                    }
+                    to_mesh[face(i, j, k)] = false;
+                    let current_quad = quads[face(i, j, k)];
+                    let mut j_end = j + 1;
+                    let mut k_end = k + 1;
 
-                        if s == 0 {
-                            // 1x
-                            for kk in 0..4 {
-                                px_[kk] = px_[kk] + 1.0;
+                    // Merge the adjacent faces with the same color and ambient occlusion,
+                    // like the greedy meshing of the chunks
+                    if current_quad.v1 == current_quad.v3 && current_quad.v2 == current_quad.v4 {
+                        // meshing along j
+                        while j_end < size_j && to_mesh[face(i, j_end, k)] {
+                            let next_quad = quads[face(i, j_end, k)];
+                            if next_quad.v1 == current_quad.v1
+                                && next_quad.v2 == current_quad.v2
+                                && next_quad.v1 == next_quad.v3
+                                && next_quad.v2 == next_quad.v4
+                            {
+                                to_mesh[face(i, j_end, k)] = false;
+                                j_end += 1;
+                            } else {
+                                break;
                             }
-                        } else if s == 2 {
-                            // 1y
-                            for kk in 0..4 {
-                                py_[kk] = py_[kk] + 1.0;
+                        }
+
+                        if current_quad.v1 == current_quad.v2 {
+                            // 2D greedy meshing
+                            'wloop: while k_end < size_k {
+                                for j2 in j..j_end {
+                                    let next_quad = quads[face(i, j2, k_end)];
+                                    if !(to_mesh[face(i, j2, k_end)]
+                                        && next_quad.is_same()
+                                        && next_quad.v1 == current_quad.v1)
+                                    {
+                                        break 'wloop;
+                                    }
+                                }
+                                for j2 in j..j_end {
+                                    to_mesh[face(i, j2, k_end)] = false;
+                                }
+                                k_end += 1;
                             }
-                        } else if s == 4 {
-                            // 1z
-                            for kk in 0..4 {
-                                pz_[kk] = pz_[kk] + 1.0;
+                        }
+                    } else if current_quad.v1 == current_quad.v2
+                        && current_quad.v3 == current_quad.v4
+                    {
+                        // meshing along k
+                        while k_end < size_k && to_mesh[face(i, j, k_end)] {
+                            let next_quad = quads[face(i, j, k_end)];
+                            if next_quad.v1 == current_quad.v1
+                                && next_quad.v3 == current_quad.v3
+                                && next_quad.v1 == next_quad.v2
+                                && next_quad.v3 == next_quad.v4
+                            {
+                                to_mesh[face(i, j, k_end)] = false;
+                                k_end += 1;
+                            } else {
+                                break;
                             }
                         }
+                    }
+                    tot_quad += (j_end - j) * (k_end - k);
+                    act_quad += 1;
 
-                        for kk in 0..4 {
-                            res_vertex.push(RgbVertex {
-                                position: [px_[kk], py_[kk], pz_[kk]],
-                                info: v[kk],
-                            });
+                    let corners = [
+                        pos(i, j, k),
+                        pos(i, j, k_end),
+                        pos(i, j_end, k),
+                        pos(i, j_end, k_end),
+                    ];
+                    let v = [
+                        current_quad.v1,
+                        current_quad.v2,
+                        current_quad.v3,
+                        current_quad.v4,
+                    ];
+                    for (corner, &info) in corners.iter().zip(v.iter()) {
+                        let mut position = [corner[0] as f32, corner[1] as f32, corner[2] as f32];
+                        if s % 2 == 0 {
+                            // 1x, 1y or 1z
+                            position[normal_axis] += 1.0;
                         }
-                        let a00 = v[0] >> 27;
-                        let a11 = v[3] >> 27;
-                        let a01 = v[1] >> 27;
-                        let a10 = v[2] >> 27;
+                        res_vertex.push(RgbVertex { position, info });
+                    }
+                    let a00 = v[0] >> 27;
+                    let a11 = v[3] >> 27;
+                    let a01 = v[1] >> 27;
+                    let a10 = v[2] >> 27;
 
-                        for kk in 0..6 {
-                            if a00 + a11 < a01 + a10 {
-                                res_index.push(n_of_different_vertex + order1[s][kk]);
-                            } else {
-                                res_index.push(n_of_different_vertex + order2[s][kk]);
-                            }
+                    for kk in 0..6 {
+                        if a00 + a11 < a01 + a10 {
+                            res_index.push(n_of_different_vertex + order1[s][kk]);
+                        } else {
+                            res_index.push(n_of_different_vertex + order2[s][kk]);
                         }
-                        n_of_different_vertex += 4;
                     }
+                    n_of_different_vertex += 4;
                 }
             }
         }
     }
+    log::debug!(
+        "Meshed a {}x{}x{} model with {} quads instead of {}",
+        size_x,
+        size_y,
+        size_z,
+        act_quad,
+        tot_quad
+    );
     let res_index: Vec<u32> = res_index.iter().map(|x| *x as u32).collect();
     (res_vertex, res_index)
 }
@@ -0,0 +1,126 @@
+//! Rendering of the cuboid selected with the world edit selection tool: translucent faces and opaque edges.
+//! The selection is depth tested but doesn't write the depth, so it stays readable over the terrain it covers.
+
+use voxel_rs_common::world::BlockPos;
+
+/// Color of the faces of the selection
+const FACE_COLOR: [f32; 4] = [0.25, 0.55, 1.0, 0.2];
+/// Color of the edges of the selection
+const EDGE_COLOR: [f32; 4] = [0.25, 0.55, 1.0, 1.0];
+/// Distance between the selection and the faces of the selected blocks, to avoid z-fighting
+const FACE_OFFSET: f32 = 0.005;
+/// Number of vertices of the faces of the selection: 6 faces of 2 triangles
+pub const FACE_VERTICES: u32 = 36;
+/// Number of vertices of the edges of the selection: 12 lines
+pub const EDGE_VERTICES: u32 = 24;
+
+/// Vertex of the selection
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+pub const SELECTION_VERTEX_ATTRIBUTES: [wgpu::VertexAttributeDescriptor; 2] = [
+    wgpu::VertexAttributeDescriptor {
+        shader_location: 0,
+        format: wgpu::VertexFormat::Float3,
+        offset: 0,
+    },
+    wgpu::VertexAttributeDescriptor {
+        shader_location: 1,
+        format: wgpu::VertexFormat::Float4,
+        offset: 4 * 3,
+    },
+];
+
+/// Get the selected cuboid from its corners. While only one corner is set, its block is selected.
+pub fn selection_box(
+    first: Option<BlockPos>,
+    second: Option<BlockPos>,
+) -> Option<(BlockPos, BlockPos)> {
+    let (first, second) = match (first, second) {
+        (None, None) => return None,
+        (Some(corner), None) | (None, Some(corner)) => (corner, corner),
+        (Some(first), Some(second)) => (first, second),
+    };
+    Some((
+        BlockPos::from((
+            first.px.min(second.px),
+            first.py.min(second.py),
+            first.pz.min(second.pz),
+        )),
+        BlockPos::from((
+            first.px.max(second.px),
+            first.py.max(second.py),
+            first.pz.max(second.pz),
+        )),
+    ))
+}
+
+/// Create the vertices of the faces, followed by the vertices of the edges of the cuboid between `min` and `max`, inclusive
+pub fn create_selection_vertices(min: BlockPos, max: BlockPos) -> Vec<SelectionVertex> {
+    let low = [
+        min.px as f32 - FACE_OFFSET,
+        min.py as f32 - FACE_OFFSET,
+        min.pz as f32 - FACE_OFFSET,
+    ];
+    let high = [
+        (max.px + 1) as f32 + FACE_OFFSET,
+        (max.py + 1) as f32 + FACE_OFFSET,
+        (max.pz + 1) as f32 + FACE_OFFSET,
+    ];
+    // The corner `i` takes the high coordinate on the axis `a` if the bit `a` of `i` is set
+    let corner = |i: usize, color: [f32; 4]| SelectionVertex {
+        position: [
+            if i & 1 != 0 { high[0] } else { low[0] },
+            if i & 2 != 0 { high[1] } else { low[1] },
+            if i & 4 != 0 { high[2] } else { low[2] },
+        ],
+        color,
+    };
+    let mut vertices = Vec::with_capacity((FACE_VERTICES + EDGE_VERTICES) as usize);
+    // Faces, drawn without culling so that they are visible from the inside too
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for &side in [0, 1 << axis].iter() {
+            let quad = [
+                side,
+                side | (1 << u),
+                side | (1 << u) | (1 << v),
+                side | (1 << v),
+            ];
+            for &k in [0, 1, 2, 0, 2, 3].iter() {
+                vertices.push(corner(quad[k], FACE_COLOR));
+            }
+        }
+    }
+    // Edges, between the corners that differ on one axis
+    for i in 0..8 {
+        for axis in 0..3 {
+            if i & (1 << axis) == 0 {
+                vertices.push(corner(i, EDGE_COLOR));
+                vertices.push(corner(i | (1 << axis), EDGE_COLOR));
+            }
+        }
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_box() {
+        assert_eq!(selection_box(None, None), None);
+        let corner = BlockPos::from((1, -2, 3));
+        assert_eq!(selection_box(None, Some(corner)), Some((corner, corner)));
+        assert_eq!(
+            selection_box(Some(corner), Some(BlockPos::from((-4, 5, 3)))),
+            Some((BlockPos::from((-4, -2, 3)), BlockPos::from((1, 5, 3))))
+        );
+        let vertices = create_selection_vertices(corner, corner);
+        assert_eq!(vertices.len() as u32, FACE_VERTICES + EDGE_VERTICES);
+    }
+}
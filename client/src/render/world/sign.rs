@@ -0,0 +1,216 @@
+//! Rendering of the text of the signs.
+//! The text of every sign is baked into its own texture once, and drawn on a quad in front of the face of the sign.
+
+use crate::render::memory::{self, MemoryCategory, TrackedBuffer, TrackedTexture};
+use image::{ImageBuffer, Rgba};
+use voxel_rs_common::block::{entity::Sign, HorizontalFace};
+use voxel_rs_common::world::BlockPos;
+use wgpu_glyph::ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+
+/// Size of the texture of a sign, in pixels
+const SIGN_TEXTURE_SIZE: u32 = 256;
+/// Number of lines of text on a sign
+const SIGN_LINES: usize = 4;
+/// Number of characters of a line of text on a sign, with the monospace font
+const SIGN_LINE_LENGTH: usize = 15;
+/// Height of the text, in pixels of the sign texture
+const TEXT_SCALE: f32 = 26.0;
+const TEXT_COLOR: [u8; 3] = [30, 20, 10];
+/// Distance between the quad of the text and the face of the block, to avoid z-fighting
+const FACE_OFFSET: f32 = 0.001;
+
+/// Vertex of the quad of a sign
+#[derive(Debug, Clone, Copy)]
+pub struct SignVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+pub const SIGN_VERTEX_ATTRIBUTES: [wgpu::VertexAttributeDescriptor; 2] = [
+    wgpu::VertexAttributeDescriptor {
+        shader_location: 0,
+        format: wgpu::VertexFormat::Float3,
+        offset: 0,
+    },
+    wgpu::VertexAttributeDescriptor {
+        shader_location: 1,
+        format: wgpu::VertexFormat::Float2,
+        offset: 4 * 3,
+    },
+];
+
+/// The baked text of a sign, ready to be drawn
+pub struct SignMesh {
+    /// The sign that was baked, to only bake it again when it changes
+    pub sign: Sign,
+    pub vertex_buffer: TrackedBuffer,
+    pub bind_group: wgpu::BindGroup,
+    // Kept alive while the bind group uses it
+    _texture: TrackedTexture,
+}
+
+impl SignMesh {
+    pub fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        layout: &wgpu::BindGroupLayout,
+        uniform_view_proj: &wgpu::Buffer,
+        pos: BlockPos,
+        sign: &Sign,
+    ) -> Self {
+        let texture = crate::texture::load_image(device, encoder, bake_sign_text(&sign.text));
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group =
+            super::create_chunk_bind_group(device, layout, &texture_view, None, uniform_view_proj);
+        let vertices = sign_quad(pos, sign.facing);
+        let vertex_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::ModelMeshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                usage: wgpu::BufferUsage::VERTEX,
+                contents: crate::render::to_u8_slice(&vertices),
+            },
+        );
+        Self {
+            sign: sign.clone(),
+            vertex_buffer,
+            bind_group,
+            _texture: texture,
+        }
+    }
+}
+
+/// Split the text of a sign into at most `SIGN_LINES` lines, cutting between the words if possible
+pub fn wrap_sign_text(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        let line_length = line.chars().count();
+        if line_length > 0 && line_length + 1 + word.len() <= SIGN_LINE_LENGTH {
+            line.push(' ');
+            line.extend(word.iter());
+            continue;
+        }
+        if line_length > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        // The words that are longer than a line are cut
+        while word.len() > SIGN_LINE_LENGTH {
+            lines.push(word.drain(..SIGN_LINE_LENGTH).collect());
+        }
+        line.extend(word.iter());
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.truncate(SIGN_LINES);
+    lines
+}
+
+/// Draw the text of a sign on a transparent square image, centered
+fn bake_sign_text(text: &str) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let mut image = ImageBuffer::new(SIGN_TEXTURE_SIZE, SIGN_TEXTURE_SIZE);
+    let font = FontRef::try_from_slice(include_bytes!(
+        "../../../../assets/fonts/IBMPlexMono-Regular.ttf"
+    ))
+    .expect("Failed to load the sign font");
+    let scaled_font = font.as_scaled(PxScale::from(TEXT_SCALE));
+    let line_height = scaled_font.height() + scaled_font.line_gap();
+    let lines = wrap_sign_text(text);
+    let size = SIGN_TEXTURE_SIZE as f32;
+    let top = (size - line_height * lines.len() as f32) / 2.0;
+    for (i, line) in lines.iter().enumerate() {
+        let width = line
+            .chars()
+            .map(|c| scaled_font.h_advance(font.glyph_id(c)))
+            .sum::<f32>();
+        let mut x = (size - width) / 2.0;
+        let baseline = top + i as f32 * line_height + scaled_font.ascent();
+        for c in line.chars() {
+            let glyph = scaled_font.scaled_glyph(c);
+            let advance = scaled_font.h_advance(glyph.id);
+            let glyph = glyph
+                .id
+                .with_scale_and_position(TEXT_SCALE, point(x, baseline));
+            x += advance;
+            if let Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|gx, gy, coverage| {
+                    let px = bounds.min.x as i32 + gx as i32;
+                    let py = bounds.min.y as i32 + gy as i32;
+                    if px >= 0
+                        && py >= 0
+                        && (px as u32) < image.width()
+                        && (py as u32) < image.height()
+                    {
+                        let [r, g, b] = TEXT_COLOR;
+                        let alpha = (coverage.min(1.0) * 255.0) as u8;
+                        let pixel: &mut Rgba<u8> = image.get_pixel_mut(px as u32, py as u32);
+                        if alpha > pixel[3] {
+                            *pixel = Rgba([r, g, b, alpha]);
+                        }
+                    }
+                });
+            }
+        }
+    }
+    image
+}
+
+/// Create the two triangles of the quad of a sign, just in front of its face.
+/// The texture is upright and readable from in front of the face.
+fn sign_quad(pos: BlockPos, facing: HorizontalFace) -> [SignVertex; 6] {
+    let (normal, right) = match facing {
+        HorizontalFace::PosX => ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        HorizontalFace::NegX => ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        HorizontalFace::PosZ => ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        HorizontalFace::NegZ => ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0]),
+    };
+    let center = [
+        pos.px as f32 + 0.5 + normal[0] * (0.5 + FACE_OFFSET),
+        pos.py as f32 + 0.5,
+        pos.pz as f32 + 0.5 + normal[2] * (0.5 + FACE_OFFSET),
+    ];
+    let vertex = |u: f32, v: f32| SignVertex {
+        position: [
+            center[0] + right[0] * (u - 0.5),
+            center[1] + (0.5 - v),
+            center[2] + right[2] * (u - 0.5),
+        ],
+        uv: [u, v],
+    };
+    [
+        vertex(0.0, 0.0),
+        vertex(0.0, 1.0),
+        vertex(1.0, 1.0),
+        vertex(0.0, 0.0),
+        vertex(1.0, 1.0),
+        vertex(1.0, 0.0),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_sign_text() {
+        assert_eq!(wrap_sign_text("  "), Vec::<String>::new());
+        assert_eq!(
+            wrap_sign_text("Welcome to the village of Stone"),
+            vec!["Welcome to the", "village of", "Stone"]
+        );
+        // Long words are cut, and the lines that don't fit are dropped
+        assert_eq!(
+            wrap_sign_text("abcdefghijklmnopqrstuvwxyz a b c d e f g h i j k l m n o p"),
+            vec![
+                "abcdefghijklmno",
+                "pqrstuvwxyz a b",
+                "c d e f g h i j",
+                "k l m n o p"
+            ]
+        );
+    }
+}
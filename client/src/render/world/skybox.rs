@@ -1,7 +1,9 @@
 //! Skybox rendering
 
-use super::{to_u8_slice, SkyboxVertex};
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use super::SkyboxVertex;
+use crate::render::memory::{self, MemoryCategory, TrackedBuffer};
+use crate::render::to_u8_slice;
+use wgpu::util::BufferInitDescriptor;
 
 const FAR: f32 = 900.0;
 
@@ -52,7 +54,7 @@ const NORTH: [[f32; 3]; 4] = [
 const POS: [[[f32; 3]; 4]; 6] = [EAST, WEST, UP, DOWN, SOUTH, NORTH];
 
 /// Create the vertex and the index buffer for the skybox.
-pub fn create_skybox(device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+pub fn create_skybox(device: &wgpu::Device) -> (TrackedBuffer, TrackedBuffer) {
     let mut vertices = Vec::new();
     let mut indices = Vec::new();
 
@@ -70,19 +72,27 @@ pub fn create_skybox(device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
     (
         {
             let vertices_slice = to_u8_slice(&vertices);
-            device.create_buffer_init(&BufferInitDescriptor {
-                label: Some("skybox_vertices"),
-                usage: wgpu::BufferUsage::COPY_SRC | wgpu::BufferUsage::VERTEX,
-                contents: &vertices_slice,
-            })
+            memory::create_buffer_init(
+                device,
+                MemoryCategory::Other,
+                &BufferInitDescriptor {
+                    label: Some("skybox_vertices"),
+                    usage: wgpu::BufferUsage::COPY_SRC | wgpu::BufferUsage::VERTEX,
+                    contents: &vertices_slice,
+                },
+            )
         },
         {
             let indices_slice = to_u8_slice(&indices);
-            device.create_buffer_init(&BufferInitDescriptor {
-                label: Some("skybox_indicies"),
-                usage: wgpu::BufferUsage::COPY_SRC | wgpu::BufferUsage::INDEX,
-                contents: &indices_slice,
-            })
+            memory::create_buffer_init(
+                device,
+                MemoryCategory::Other,
+                &BufferInitDescriptor {
+                    label: Some("skybox_indicies"),
+                    usage: wgpu::BufferUsage::COPY_SRC | wgpu::BufferUsage::INDEX,
+                    contents: &indices_slice,
+                },
+            )
         },
     )
 }
@@ -0,0 +1,133 @@
+//! The full-screen effects of the surroundings of the camera: a tint under water, a dark vignette deep
+//! underground, a dark overlay when the camera is inside a solid block, and a flash when the player is hurt.
+//! Every effect has an intensity between 0 and 1 that fades towards its target, and the GUI draws them over
+//! the world with these intensities.
+use crate::world::World;
+use nalgebra::Point3;
+use voxel_rs_common::{physics::BlockContainer, world::BlockPos};
+
+/// How fast the effects fade in and out when the surroundings change, per second
+const FADE_SPEED: f32 = 3.0;
+/// Seconds for the flash of the damage to fade out
+const DAMAGE_FLASH_DURATION: f32 = 0.5;
+/// Altitudes where the underground vignette starts and where it is the darkest
+const UNDERGROUND_START_ALTITUDE: f64 = 0.0;
+const UNDERGROUND_FULL_ALTITUDE: f64 = -64.0;
+/// Number of blocks above the camera that are checked for a ceiling, without one the camera is not underground
+const CEILING_HEIGHT: i64 = 32;
+
+/// What the effects depend on around the camera
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CameraSurroundings {
+    pub in_fluid: bool,
+    pub in_solid_block: bool,
+    /// How deep underground the camera is, between 0 and 1
+    pub depth: f32,
+}
+
+impl CameraSurroundings {
+    /// Look at the blocks around the camera
+    pub fn sample(camera: Point3<f64>, world: &World) -> Self {
+        let pos = BlockPos::from(camera);
+        let covered = (1..=CEILING_HEIGHT)
+            .any(|dy| world.is_block_full(BlockPos::from((pos.px, pos.py + dy, pos.pz))));
+        let depth = if covered {
+            ((UNDERGROUND_START_ALTITUDE - camera.y)
+                / (UNDERGROUND_START_ALTITUDE - UNDERGROUND_FULL_ALTITUDE))
+                .min(1.0)
+                .max(0.0) as f32
+        } else {
+            0.0
+        };
+        Self {
+            in_fluid: world.is_block_fluid(pos),
+            in_solid_block: world.is_block_full(pos),
+            depth,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScreenEffects {
+    underwater: f32,
+    underground: f32,
+    suffocation: f32,
+    damage: f32,
+}
+
+impl ScreenEffects {
+    /// Fade the effects towards the surroundings of the camera
+    pub fn update(&mut self, surroundings: CameraSurroundings, seconds_delta: f64) {
+        let max_change = FADE_SPEED * seconds_delta as f32;
+        let fade = |intensity: &mut f32, target: f32| {
+            if (target - *intensity).abs() <= max_change {
+                *intensity = target;
+            } else {
+                *intensity += max_change * (target - *intensity).signum();
+            }
+        };
+        fade(&mut self.underwater, surroundings.in_fluid as u8 as f32);
+        fade(&mut self.underground, surroundings.depth);
+        // The camera enters the blocks at once, for example when the player is pushed into a wall
+        if surroundings.in_solid_block {
+            self.suffocation = 1.0;
+        } else {
+            fade(&mut self.suffocation, 0.0);
+        }
+        self.damage = (self.damage - seconds_delta as f32 / DAMAGE_FLASH_DURATION).max(0.0);
+    }
+
+    /// Flash the screen, the player was hurt
+    pub fn hurt(&mut self) {
+        self.damage = 1.0;
+    }
+
+    pub fn underwater(&self) -> f32 {
+        self.underwater
+    }
+
+    pub fn underground(&self) -> f32 {
+        self.underground
+    }
+
+    pub fn suffocation(&self) -> f32 {
+        self.suffocation
+    }
+
+    pub fn damage(&self) -> f32 {
+        self.damage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screen_effects() {
+        let mut effects = ScreenEffects::default();
+        let under_water = CameraSurroundings {
+            in_fluid: true,
+            ..Default::default()
+        };
+        effects.update(under_water, 0.1);
+        assert!(effects.underwater() > 0.0 && effects.underwater() < 1.0);
+        for _ in 0..10 {
+            effects.update(under_water, 0.1);
+        }
+        assert_eq!(effects.underwater(), 1.0);
+
+        let in_wall = CameraSurroundings {
+            in_solid_block: true,
+            ..Default::default()
+        };
+        effects.hurt();
+        effects.update(in_wall, 0.25);
+        assert_eq!(effects.suffocation(), 1.0);
+        assert_eq!(effects.damage(), 0.5);
+        effects.update(CameraSurroundings::default(), 1.0);
+        assert_eq!(effects.suffocation(), 0.0);
+        assert_eq!(effects.underwater(), 0.0);
+        assert_eq!(effects.damage(), 0.0);
+    }
+}
@@ -6,6 +6,8 @@ use std::{
     io::{Read, Write},
     path::Path,
 };
+use voxel_rs_common::network::emulation::NetworkConditions;
+use voxel_rs_common::player::{is_valid_profile, PROFILE_LENGTH};
 
 pub fn load_settings(folder_path: &Path, file_path: &Path) -> Result<Settings> {
     info!(
@@ -13,7 +15,7 @@ pub fn load_settings(folder_path: &Path, file_path: &Path) -> Result<Settings> {
         folder_path.display(),
         file_path.display()
     );
-    let settings = if file_path.is_file() {
+    let mut settings = if file_path.is_file() {
         let mut settings_file = OpenOptions::new()
             .read(true)
             .write(true)
@@ -36,10 +38,17 @@ pub fn load_settings(folder_path: &Path, file_path: &Path) -> Result<Settings> {
         ))?
     } else {
         std::fs::create_dir_all(folder_path)?;
-        let settings = Settings::default();
-        write_settings(file_path, &settings)?;
-        settings
+        Settings::default()
     };
+    // The profile is generated on the first launch, and then kept in the settings file
+    if !is_valid_profile(&settings.player_profile) {
+        settings.player_profile = format!(
+            "{:0width$x}",
+            rand::random::<u128>(),
+            width = PROFILE_LENGTH
+        );
+        write_settings(file_path, &settings)?;
+    }
 
     // TODO: write settings
 
@@ -63,13 +72,146 @@ fn write_settings(path: impl AsRef<Path>, settings: &Settings) -> Result<()> {
     Ok(())
 }
 
+/// Graphics backend used to create the GPU device
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GpuBackend {
+    /// Let wgpu pick among the primary backends (Vulkan, Metal, DX12, Browser WebGPU)
+    Primary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl GpuBackend {
+    pub fn to_backend_bit(self) -> wgpu::BackendBit {
+        match self {
+            Self::Primary => wgpu::BackendBit::PRIMARY,
+            Self::Vulkan => wgpu::BackendBit::VULKAN,
+            Self::Metal => wgpu::BackendBit::METAL,
+            Self::Dx12 => wgpu::BackendBit::DX12,
+            Self::Dx11 => wgpu::BackendBit::DX11,
+            Self::Gl => wgpu::BackendBit::GL,
+        }
+    }
+}
+
+/// Which kind of GPU should be preferred when no adapter is explicitly selected
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GpuPowerPreference {
+    /// Usually an integrated GPU
+    LowPower,
+    /// Usually a discrete GPU
+    HighPerformance,
+}
+
+impl GpuPowerPreference {
+    pub fn to_wgpu(self) -> wgpu::PowerPreference {
+        match self {
+            Self::LowPower => wgpu::PowerPreference::LowPower,
+            Self::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+/// How the mouse turns the camera
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct MouseSettings {
+    /// Use the raw movements of the mouse, without the acceleration of the OS
+    pub raw_input: bool,
+    /// Degrees of horizontal rotation per mouse count
+    pub sensitivity_x: f64,
+    /// Degrees of vertical rotation per mouse count
+    pub sensitivity_y: f64,
+    /// Extra sensitivity for every 1000 counts per second of mouse speed, 0 for a linear response
+    pub acceleration: f64,
+    /// Time in seconds over which the rotation is smoothed, 0 to disable the smoothing
+    pub smoothing: f64,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            raw_input: true,
+            sensitivity_x: 0.2,
+            sensitivity_y: 0.2,
+            acceleration: 0.0,
+            smoothing: 0.0,
+        }
+    }
+}
+
+/// Options reducing the motion and the flashes on screen, for the players sensitive to them.
+/// The camera, particle and post-processing effects check these options before they run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AccessibilityConfig {
+    /// Sway the camera with the steps of the player
+    pub view_bobbing: bool,
+    /// Strength of the camera shake, between 0 (disabled) and 1
+    pub screen_shake: f64,
+    /// Limit the light flicker and the flashes to at most 3 per second, and soften them
+    pub limit_flicker: bool,
+    /// Fraction of the particles that are spawned, between 0 and 1
+    pub particle_density: f64,
+}
+
+impl Default for AccessibilityConfig {
+    fn default() -> Self {
+        Self {
+            view_bobbing: true,
+            screen_shake: 1.0,
+            limit_flicker: false,
+            particle_density: 1.0,
+        }
+    }
+}
+
 /// Settings of the game
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(default)]
 pub struct Settings {
     pub window_size: [u16; 2],
+    /// Move the camera up when the mouse moves down
     pub invert_mouse: bool,
+    pub mouse: MouseSettings,
+    pub accessibility: AccessibilityConfig,
     pub render_distance: (u64, u64, u64, u64, u64, u64),
+    pub gpu_backend: GpuBackend,
+    pub gpu_power_preference: GpuPowerPreference,
+    /// If set, use the first adapter whose name contains this string (case-insensitive)
+    pub gpu_adapter: Option<String>,
+    /// Time in seconds for the camera to catch up with the eyes after a step or a change of the sneaking,
+    /// 0 to follow the eyes exactly
+    pub step_smoothing_time: f64,
+    /// Wait for the vertical blank before presenting a frame
+    pub vsync: bool,
+    /// Maximum number of frames per second when vsync is off, 0 for no limit
+    pub max_fps: u32,
+    /// GPU memory in megabytes above which a warning is logged and the buffers of the meshes are shrunk,
+    /// 0 for no limit. The total is shown in the debug info.
+    pub gpu_memory_budget_mb: u64,
+    /// Scale of the HUD and the menus, independent of the DPI. It is reduced on small windows so that the GUI fits.
+    pub gui_scale: f64,
+    /// Draw the cursor of the GUI theme in the menus instead of the system cursor
+    pub software_cursor: bool,
+    /// Name of the GUI theme, loaded from `assets/themes/<theme>.toml`: dark, light, high_contrast or deuteranopia
+    pub theme: String,
+    /// Name shown to the other players, `Player <id>` if it is empty
+    pub player_name: String,
+    /// Random secret that identifies the player on the servers, generated on the first launch.
+    /// The servers save the player under it, and list it to make the player an operator.
+    pub player_profile: String,
+    /// Pause the game of the integrated server while the pause menu is open or the window is not focused.
+    /// The game never pauses while other players are connected, disable it to never pause a shared world.
+    pub pause_integrated_server: bool,
+    /// Conditions of the emulated network, set with the `--net-*` command line options
+    #[serde(skip)]
+    pub net_emulation: Option<NetworkConditions>,
 }
 
 impl Default for Settings {
@@ -77,7 +219,23 @@ impl Default for Settings {
         Self {
             window_size: [1600, 900],
             invert_mouse: false,
+            mouse: MouseSettings::default(),
+            accessibility: AccessibilityConfig::default(),
             render_distance: (16, 16, 16, 16, 16, 16),
+            gpu_backend: GpuBackend::Primary,
+            gpu_power_preference: GpuPowerPreference::HighPerformance,
+            gpu_adapter: None,
+            step_smoothing_time: 0.15,
+            vsync: false,
+            max_fps: 0,
+            gpu_memory_budget_mb: 1024,
+            gui_scale: 1.0,
+            software_cursor: true,
+            theme: "dark".to_owned(),
+            player_name: String::new(),
+            player_profile: String::new(),
+            pause_integrated_server: true,
+            net_emulation: None,
         }
     }
 }
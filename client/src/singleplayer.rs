@@ -1,57 +1,181 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::info;
 
 use voxel_rs_common::{
-    block::Block,
+    animation::AnimatedModel,
+    block::{
+        block_shapes,
+        entity::{FurnaceProgress, MAX_COMMAND_LENGTH, MAX_SIGN_TEXT_LENGTH},
+        Block, BlockId,
+    },
     network::{messages::ToClient, messages::ToServer, Client, ClientEvent},
-    player::RenderDistance,
+    player::{GameMode, PlayerId, PlayerListEntry, RenderDistance, Waypoint},
     registry::Registry,
-    world::BlockPos,
+    scoreboard::ScoreboardDisplay,
+    stats::PlayerStats,
+    world::{BlockPos, ChunkPosXZ, CHUNK_SIZE},
 };
 
-use crate::input::YawPitch;
+use crate::bobbing::ViewBobbing;
+use crate::input::{MouseFilter, YawPitch};
+use crate::screen_effects::{CameraSurroundings, ScreenEffects};
+use crate::step_smoothing::StepSmoothing;
 //use crate::model::model::Model;
 //use crate::world::meshing::ChunkMeshData;
-use crate::gui::Gui;
-use crate::render::{iced::IcedRenderer, Frustum, UiRenderer, WorldRenderer};
+use crate::gui::{
+    boss_bars::DisplayedBossBar,
+    players::NameTag,
+    theme::load_theme,
+    titles::{ActionBarText, ShownTitle},
+    Gui,
+};
+use crate::icons::ItemIcons;
+use crate::minimap::Minimap;
+use crate::render::{
+    iced::IcedRenderer,
+    memory::{self, MemoryCategory},
+    world::selection_box,
+    EntityCulling, Frustum, MinimapRenderer, ModelDetail, UiRenderer, UploadBelt, WorldRenderer,
+};
+use crate::spectate::SpectatorCamera;
 use crate::window::WindowBuffers;
 use crate::{
-    fps::FpsCounter,
+    animation::Animations,
+    audio::Audio,
     input::InputState,
     settings::Settings,
     ui::pausemenu::{self, PauseMenuControls},
     window::{State, StateTransition, WindowData, WindowFlags},
     world::World,
 };
-use nalgebra::Vector3;
-use std::time::Instant;
-use voxel_rs_common::data::vox::VoxelModel;
-use voxel_rs_common::debug::{send_debug_info, send_perf_breakdown, DebugInfo};
-use voxel_rs_common::item::{Item, ItemMesh};
+use nalgebra::{Isometry3, Point3, Vector3};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use voxel_rs_common::content::DisplayedModel;
+use voxel_rs_common::data::{compute_block_colors, vox::VoxelModel, Data};
+use voxel_rs_common::debug::{send_debug_info, send_perf_breakdown, ChunkMetric, DebugInfo};
+use voxel_rs_common::inventory::{Inventory, InventoryWindow, ItemStack, HOTBAR_SIZE};
+use voxel_rs_common::item::{Item, ItemId, ItemMesh};
+use voxel_rs_common::metrics::set_gauge;
+use voxel_rs_common::network::bandwidth::BandwidthMeter;
+use voxel_rs_common::network::clock::{ClockSync, ServerTime};
 use voxel_rs_common::physics::simulation::{ClientPhysicsSimulation, PhysicsState, ServerState};
+use voxel_rs_common::physics::snapshot::SnapshotDecoder;
+use voxel_rs_common::physics::vehicle::{VehicleId, VehicleKind};
+use voxel_rs_common::skin::PlayerSkin;
 use voxel_rs_common::time::BreakdownCounter;
+use voxel_rs_common::world_view::WorldView;
 use winit::event::{ElementState, ModifiersState, MouseButton};
 
 /// State of a singleplayer world
 pub struct SinglePlayer {
-    fps_counter: FpsCounter,
+    bandwidth_meter: BandwidthMeter,
+    /// Whether the player hosts the integrated server, rather than playing in a world opened to LAN
+    is_host: bool,
+    /// Whether the pause menu lets the player open the world to LAN, until it is opened
+    can_open_to_lan: bool,
+    /// Whether the connection to the server was lost, the game then goes back to the main menu
+    disconnected: bool,
     is_paused: bool,
+    /// Whether the server was asked to pause the game, while the pause menu is open or the window is not focused
+    requested_server_pause: bool,
+    /// Whether the server paused the game. The physics are not predicted meanwhile.
+    is_server_paused: bool,
+    is_inventory_open: bool,
+    inventory: Inventory,
+    cursor_stack: Option<ItemStack>,
+    open_container: Option<(BlockPos, Inventory)>,
+    /// The progress of the open container if it is a furnace
+    furnace_progress: Option<FurnaceProgress>,
+    hovered_slot: Option<(InventoryWindow, usize)>,
+    /// The slot of the creative palette that the mouse is hovering
+    hovered_palette_slot: Option<usize>,
+    /// One of every item, shown instead of the open container in creative mode
+    creative_palette: Inventory,
+    shift_pressed: bool,
+    game_mode: GameMode,
+    /// The block that the player is breaking in survival mode, and when they started breaking it
+    breaking: Option<(BlockPos, Instant)>,
+    /// Whether the break button is held
+    is_break_pressed: bool,
     pause_menu_renderer: IcedRenderer<PauseMenuControls, pausemenu::Message>,
     gui: Gui,
     ui_renderer: UiRenderer,
+    is_map_open: bool,
+    minimap: Minimap,
+    minimap_renderer: MinimapRenderer,
+    /// The staging buffers of all the uploads to the GPU
+    upload_belt: UploadBelt,
+    waypoints: Vec<Waypoint>,
+    /// The text being typed and what it is for, if a text prompt is open
+    text_input: Option<(TextInput, String)>,
+    /// The recent chat messages, with the time they were received
+    chat_messages: VecDeque<(Instant, String)>,
+    is_stats_open: bool,
+    /// The slot of the hotbar whose item the player holds
+    selected_slot: usize,
+    /// The block that the player places, picked with the middle click like on the server.
+    /// If it emits light, the player holds its light.
+    held_block: BlockId,
+    /// The cuboid selected with the world edit selection tool, drawn over the world
+    selection: Option<(BlockPos, BlockPos)>,
+    /// The statistics shown on the statistics screen, once they are received from the server
+    stats: Option<PlayerStats>,
+    /// The objectives of the scoreboard shown in the sidebar and below the names
+    scoreboard: ScoreboardDisplay,
+    /// The bars shown at the top of the HUD, by id
+    boss_bars: BTreeMap<String, DisplayedBossBar>,
+    /// The title shown in the middle of the screen, until it fades out
+    title: Option<ShownTitle>,
+    /// The text shown above the hotbar, until it fades out
+    action_bar: Option<ActionBarText>,
     world: World,
-    #[allow(dead_code)] // TODO: remove this
+    /// The data loaded at startup, given back to the main menu when leaving the game
+    preloaded_data: Arc<Data>,
+    audio: Audio,
     block_registry: Registry<Block>,
     item_registry: Registry<Item>,
     item_meshes: Vec<ItemMesh>,
     model_registry: Registry<VoxelModel>,
+    animated_models: Registry<AnimatedModel>,
+    /// Name of the model of the other players
+    player_model: Option<String>,
+    /// The models drawn in the world, apart from the players
+    displayed_models: Vec<DisplayedModel>,
+    /// The connected players, shown in the player list and in the name tags
+    player_list: Vec<PlayerListEntry>,
+    /// Whether the player list is shown, while its key is held
+    is_player_list_open: bool,
+    /// The camera following another player, while spectating
+    spectating: Option<SpectatorCamera>,
+    /// When the player last went through a portal, to flash the screen
+    last_teleport: Option<Instant>,
+    /// The camera position before the last update, that the rendered camera is interpolated from
+    previous_camera_position: Option<Point3<f64>>,
+    /// The positions and yaws of the vehicles before the last update, that the rendered vehicles are interpolated from
+    previous_vehicle_positions: HashMap<VehicleId, (Point3<f64>, f64)>,
+    animations: Animations,
     client: Box<dyn Client>,
     render_distance: RenderDistance,
     // TODO: put this in the settigs
     physics_simulation: ClientPhysicsSimulation,
+    /// The estimation of the server clock, that the physics prediction is timed with
+    clock_sync: ClockSync,
+    /// The physics snapshots received from the server
+    snapshots: SnapshotDecoder,
     yaw_pitch: YawPitch,
+    mouse_filter: MouseFilter,
+    view_bobbing: ViewBobbing,
+    step_smoothing: StepSmoothing,
+    /// The full-screen effects of the surroundings of the camera
+    screen_effects: ScreenEffects,
     debug_info: DebugInfo,
+    /// The statistic shown on the chunk heatmap, if it is shown
+    heatmap_metric: Option<ChunkMetric>,
     start_time: Instant,
+    /// Whether the GPU memory was above the budget at the last frame
+    over_gpu_memory_budget: bool,
     client_timing: BreakdownCounter,
     looking_at: Option<(BlockPos, usize)>,
 }
@@ -59,14 +183,28 @@ pub struct SinglePlayer {
 impl Drop for SinglePlayer {
     fn drop(&mut self) {
         log::info!("Dropping singleplayer state.");
-        self.client.send(ToServer::StopServer);
+        if self.is_host {
+            self.client.send(ToServer::StopServer);
+        }
     }
 }
 
 impl SinglePlayer {
-    pub fn new_factory(client: Box<dyn Client>) -> crate::window::StateFactory {
+    pub fn new_factory(
+        client: Box<dyn Client>,
+        preloaded_data: Arc<Data>,
+        is_host: bool,
+    ) -> crate::window::StateFactory {
         Box::new(move |device, settings, window_data, modifiers_state| {
-            Self::new(settings, device, window_data, modifiers_state, client)
+            Self::new(
+                settings,
+                device,
+                window_data,
+                modifiers_state,
+                client,
+                preloaded_data,
+                is_host,
+            )
         })
     }
 
@@ -76,6 +214,8 @@ impl SinglePlayer {
         window_data: &WindowData,
         modifiers_state: &ModifiersState,
         mut client: Box<dyn Client>,
+        preloaded_data: Arc<Data>,
+        is_host: bool,
     ) -> Result<(Box<dyn State>, wgpu::CommandBuffer)> {
         info!("Launching singleplayer");
         // Wait for data and player_id from the server
@@ -91,11 +231,20 @@ impl SinglePlayer {
                         data = Some(game_data)
                     }
                     ClientEvent::ServerMessage(ToClient::CurrentId(id)) => player_id = Some(id),
+                    ClientEvent::Disconnected => {
+                        bail!("Disconnected from the server while joining")
+                    }
+                    // The server of a world opened to LAN is still answering
+                    ClientEvent::NoEvent => std::thread::sleep(Duration::from_millis(1)),
                     _ => (),
                 }
             }
         };
         info!("Received game data from the server");
+        client.send(ToServer::Join(
+            settings.player_name.clone(),
+            settings.player_profile.clone(),
+        ));
 
         // Set render distance
         let (x1, x2, y1, y2, z1, z2) = settings.render_distance;
@@ -108,10 +257,19 @@ impl SinglePlayer {
             z_min: z2,
         };
         client.send(ToServer::SetRenderDistance(render_distance));
+        // Send the skin of the player, for the other players to see
+        let player_model = data
+            .player_model
+            .as_ref()
+            .and_then(|name| data.models.get_id_by_name(name))
+            .and_then(|id| data.models.get_value_by_id(id));
+        if let Some(skin) = crate::skin::load_skin(player_model) {
+            client.send(ToServer::SetSkin(skin));
+        }
 
         // Create the UI renderers
         let pause_menu_renderer = IcedRenderer::new(
-            PauseMenuControls::new(),
+            PauseMenuControls::new(is_host),
             device,
             window_data,
             modifiers_state,
@@ -120,19 +278,86 @@ impl SinglePlayer {
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        let world_renderer =
-            WorldRenderer::new(device, &mut encoder, data.texture_atlas, &data.models);
+        let minimap = Minimap::new(&data.meshes, &data.texture_atlas);
+        let block_colors = compute_block_colors(&data.meshes, &data.texture_atlas);
+        let mut upload_belt = UploadBelt::new();
+        let world_renderer = WorldRenderer::new(
+            device,
+            &mut encoder,
+            &mut upload_belt,
+            data.texture_atlas,
+            data.emissive_atlas,
+            &data.models,
+        )?;
+
+        let item_icons = ItemIcons::bake(&data.items, &data.item_meshes, &data.models);
+        let mut gui = Gui::new(load_theme(&settings.theme));
+        gui.set_item_icons(item_icons.uvs);
+        let mut ui_renderer = UiRenderer::new(device)?;
+        ui_renderer.set_icon_atlas(device, &mut encoder, &item_icons.atlas);
+        ui_renderer.load_cursor(device, &mut encoder, &gui.theme().cursor);
+        upload_belt.finish();
 
         Ok((
             Box::new(Self {
-                fps_counter: FpsCounter::new(),
+                bandwidth_meter: BandwidthMeter::new(),
+                is_host,
+                can_open_to_lan: is_host,
+                disconnected: false,
                 is_paused: false,
+                requested_server_pause: false,
+                is_server_paused: false,
+                is_inventory_open: false,
+                inventory: Inventory::new(0),
+                cursor_stack: None,
+                open_container: None,
+                furnace_progress: None,
+                hovered_slot: None,
+                hovered_palette_slot: None,
+                creative_palette: crate::gui::inventory::creative_palette(&data.items),
+                shift_pressed: false,
+                game_mode: GameMode::default(),
+                breaking: None,
+                is_break_pressed: false,
                 pause_menu_renderer,
-                gui: Gui::new(),
-                ui_renderer: UiRenderer::new(device),
-                world: World::new(data.meshes.clone(), world_renderer),
+                gui,
+                ui_renderer,
+                is_map_open: false,
+                minimap,
+                minimap_renderer: MinimapRenderer::new(device)?,
+                upload_belt,
+                waypoints: Vec::new(),
+                text_input: None,
+                chat_messages: VecDeque::new(),
+                is_stats_open: false,
+                selected_slot: 0,
+                held_block: 0,
+                selection: None,
+                stats: None,
+                scoreboard: ScoreboardDisplay::default(),
+                boss_bars: BTreeMap::new(),
+                title: None,
+                action_bar: None,
+                world: World::new(
+                    data.meshes.clone(),
+                    block_colors,
+                    block_shapes(&data.blocks),
+                    world_renderer,
+                ),
+                preloaded_data,
+                audio: Audio::new(data.sound_materials),
                 block_registry: data.blocks,
                 model_registry: data.models,
+                animated_models: data.animated_models,
+                player_model: data.player_model,
+                displayed_models: data.displayed_models,
+                player_list: Vec::new(),
+                is_player_list_open: false,
+                spectating: None,
+                last_teleport: None,
+                previous_camera_position: None,
+                previous_vehicle_positions: HashMap::new(),
+                animations: Animations::new(),
                 item_registry: data.items,
                 item_meshes: data.item_meshes,
                 client,
@@ -140,14 +365,22 @@ impl SinglePlayer {
                 physics_simulation: ClientPhysicsSimulation::new(
                     ServerState {
                         physics_state: PhysicsState::default(),
-                        server_time: Instant::now(),
+                        server_time: ServerTime::default(),
                         input: Default::default(),
                     },
                     player_id,
                 ),
+                clock_sync: ClockSync::new(),
+                snapshots: SnapshotDecoder::new(),
                 yaw_pitch: Default::default(),
+                mouse_filter: Default::default(),
+                view_bobbing: Default::default(),
+                step_smoothing: Default::default(),
+                screen_effects: Default::default(),
                 debug_info: DebugInfo::new_current(),
+                heatmap_metric: None,
                 start_time: Instant::now(),
+                over_gpu_memory_budget: false,
                 client_timing: BreakdownCounter::new(),
                 looking_at: None,
             }),
@@ -155,68 +388,756 @@ impl SinglePlayer {
         ))
     }
 
+    /// Get the position and the orientation of the camera, which follows the watched player while spectating
+    fn camera(&self) -> (Point3<f64>, YawPitch) {
+        match &self.spectating {
+            Some(camera) => (camera.position(), camera.yaw_pitch()),
+            None => (
+                self.physics_simulation.get_camera_position()
+                    + self.step_smoothing.offset()
+                    + self.view_bobbing.offset(),
+                self.yaw_pitch,
+            ),
+        }
+    }
+
+    /// Get the camera of a frame rendered between the last two updates, `interpolation` being the fraction of
+    /// an update since the last one
+    fn interpolated_camera(&self, interpolation: f64) -> (Point3<f64>, YawPitch) {
+        let (position, yaw_pitch) = self.camera();
+        let position = match self.previous_camera_position {
+            Some(previous) => previous + (position - previous) * interpolation,
+            None => position,
+        };
+        (position, yaw_pitch)
+    }
+
+    /// Get the player being watched, if any
+    fn spectate_target(&self) -> Option<PlayerId> {
+        self.spectating.as_ref().map(|camera| camera.target)
+    }
+
+    /// Ask the server to watch the next or the previous player of the player list
+    fn cycle_spectate_target(&mut self, forward: bool) {
+        let own_id = self.physics_simulation.get_player_id();
+        let targets: Vec<_> = self
+            .player_list
+            .iter()
+            .map(|entry| entry.id)
+            .filter(|&id| id != own_id)
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+        let next = match targets
+            .iter()
+            .position(|&id| Some(id) == self.spectate_target())
+        {
+            Some(i) if forward => (i + 1) % targets.len(),
+            Some(i) => (i + targets.len() - 1) % targets.len(),
+            None => 0,
+        };
+        self.client.send(ToServer::Spectate(Some(targets[next])));
+    }
+
+    /// Get the name tags of the other players, above their heads
+    fn name_tags(&self) -> Vec<NameTag> {
+        let (camera, _) = self.camera();
+        self.physics_simulation
+            .other_players()
+            .filter(|&(id, _)| Some(id) != self.spectate_target())
+            .filter_map(|(id, player)| {
+                let entry = self.player_list.iter().find(|entry| entry.id == id)?;
+                let position = player.position() + Vector3::new(0.0, NAME_TAG_HEIGHT, 0.0);
+                let below_name = self.scoreboard.below_name.as_ref().and_then(|objective| {
+                    let score = objective.score(&entry.name)?;
+                    Some(format!("{} {}", score, objective.title))
+                });
+                Some(NameTag {
+                    name: entry.name.clone(),
+                    color: entry.team.as_ref().map(|team| team.color),
+                    below_name,
+                    position: position.coords,
+                    occluded: self.world.is_segment_occluded(
+                        camera,
+                        position,
+                        NAME_TAG_OCCLUSION_STEP,
+                    ),
+                })
+            })
+            .collect()
+    }
+
+    /// Recolor the player model and the meshes of its animated parts with a skin
+    fn skinned_player_models(&self, skin: &PlayerSkin) -> Vec<(u32, VoxelModel)> {
+        let name = match self.player_model.as_ref() {
+            Some(name) => name,
+            None => return Vec::new(),
+        };
+        let mut mesh_ids: Vec<u32> = self
+            .model_registry
+            .get_id_by_name(name)
+            .into_iter()
+            .collect();
+        if let Some(animated_model) = self
+            .animated_models
+            .get_id_by_name(name)
+            .and_then(|id| self.animated_models.get_value_by_id(id))
+        {
+            mesh_ids.extend(animated_model.parts.iter().map(|part| part.mesh_id));
+        }
+        mesh_ids
+            .into_iter()
+            .filter_map(|mesh_id| {
+                let model = self.model_registry.get_value_by_id(mesh_id)?;
+                Some((mesh_id, skin.recolor(model)))
+            })
+            .collect()
+    }
+
+    /// Show the GPU memory in the debug info, and warn and shrink the mesh buffers when it goes above the budget
+    fn check_gpu_memory(
+        &mut self,
+        budget_mb: u64,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        for &category in MemoryCategory::ALL.iter() {
+            send_debug_info(
+                "GPU memory",
+                category.name(),
+                format!(
+                    "{}: {}",
+                    category.name(),
+                    memory::format_megabytes(memory::allocated_bytes(category))
+                ),
+            );
+        }
+        let total = memory::total_allocated_bytes();
+        send_debug_info(
+            "GPU memory",
+            "total",
+            format!("Total: {}", memory::format_megabytes(total)),
+        );
+        let over_budget = budget_mb > 0 && total > budget_mb * 1024 * 1024;
+        // Only when the budget is crossed, the buffers would grow back right away if they were shrunk every frame
+        if over_budget && !self.over_gpu_memory_budget {
+            log::warn!(
+                "GPU memory above the budget: {} used, {} MB allowed. Shrinking the mesh buffers.",
+                memory::format_megabytes(total),
+                budget_mb
+            );
+            self.world.shrink_mesh_buffers(device, encoder);
+        }
+        self.over_gpu_memory_budget = over_budget;
+    }
+
+    /// Show the network traffic in the debug info
+    fn report_bandwidth(&mut self) {
+        /// Number of message types shown
+        const TOP_MESSAGES: usize = 3;
+        if let Some(rates) = self.bandwidth_meter.measure() {
+            send_debug_info(
+                "Network",
+                "bandwidth",
+                format!(
+                    "Bandwidth: {:.1} KB/s down, {:.1} KB/s up",
+                    rates.down / 1000.0,
+                    rates.up / 1000.0
+                ),
+            );
+            let top = rates
+                .by_message
+                .iter()
+                .take(TOP_MESSAGES)
+                .map(|(direction, kind, rate)| {
+                    format!("{} ({}) {:.1} KB/s", kind, direction.name(), rate / 1000.0)
+                })
+                .collect::<Vec<_>>();
+            send_debug_info(
+                "Network",
+                "topmessages",
+                format!("Top messages: {}", top.join(", ")),
+            );
+            if let (Some(offset), Some(round_trip)) =
+                (self.clock_sync.offset(), self.clock_sync.round_trip_time())
+            {
+                send_debug_info(
+                    "Network",
+                    "clock",
+                    format!(
+                        "Server clock: {:+.1} ms, round trip {} ms",
+                        offset * 1000.0,
+                        round_trip.as_millis()
+                    ),
+                );
+            }
+        }
+    }
+
     fn handle_server_messages(&mut self) {
         loop {
             match self.client.receive_event() {
                 ClientEvent::NoEvent => break,
                 ClientEvent::ServerMessage(message) => match message {
                     ToClient::Chunk(chunk, light_chunk) => {
+                        self.minimap.update_chunk(&chunk);
                         self.world.add_chunk(chunk, light_chunk);
                     }
-                    ToClient::UpdatePhysics(server_state) => {
-                        self.physics_simulation.receive_server_update(server_state);
+                    ToClient::FarTile(tile) => {
+                        self.world.add_far_tile(tile);
+                    }
+                    ToClient::BiomeMap(biome_map) => {
+                        self.world.add_biome_map(biome_map);
+                    }
+                    ToClient::LightChunk(light_chunk) => {
+                        self.world.set_light_chunk(light_chunk);
+                    }
+                    ToClient::BlockChanges(changes) => {
+                        for chunk in self.world.apply_block_changes(&changes) {
+                            self.minimap.update_chunk(&chunk);
+                        }
+                    }
+                    ToClient::UpdatePhysics(snapshot) => {
+                        let sequence = snapshot.sequence;
+                        if let Some(server_state) = self.snapshots.decode(snapshot) {
+                            self.client.send(ToServer::AckPhysics(sequence));
+                            self.physics_simulation.receive_server_update(server_state);
+                        }
+                    }
+                    ToClient::UpdateActivities(activities) => {
+                        self.animations.update_activities(activities);
+                    }
+                    ToClient::Ping(number) => {
+                        self.client.send(ToServer::Pong(number));
+                    }
+                    ToClient::TimeResponse(client_time, server_time) => {
+                        self.clock_sync.receive_response(client_time, server_time);
+                    }
+                    ToClient::PlayerListUpdate(entries) => {
+                        self.player_list = entries;
+                    }
+                    ToClient::Spectate(target) => {
+                        // The camera moves smoothly from where it is, also when switching between players
+                        let (position, yaw_pitch) = self.camera();
+                        self.spectating =
+                            target.map(|target| SpectatorCamera::new(target, position, yaw_pitch));
+                    }
+                    ToClient::UpdateSkin(id, skin) => {
+                        let models = skin.map(|skin| self.skinned_player_models(&skin));
+                        self.world.set_player_skin(id, models);
                     }
                     ToClient::GameData(_) => {}
                     ToClient::CurrentId(_) => {}
+                    ToClient::UpdateInventory(inventory, cursor_stack) => {
+                        self.inventory = inventory;
+                        self.cursor_stack = cursor_stack;
+                    }
+                    ToClient::OpenContainer(pos, inventory) => {
+                        self.open_container = Some((pos, inventory));
+                        self.furnace_progress = None;
+                        self.is_inventory_open = true;
+                    }
+                    ToClient::UpdateContainer(pos, inventory) => {
+                        if let Some((open_pos, open_inventory)) = &mut self.open_container {
+                            if *open_pos == pos {
+                                *open_inventory = inventory;
+                            }
+                        }
+                    }
+                    ToClient::UpdateFurnaceProgress(pos, progress) => {
+                        if let Some((open_pos, _)) = &self.open_container {
+                            if *open_pos == pos {
+                                self.furnace_progress = Some(progress);
+                            }
+                        }
+                    }
+                    ToClient::CloseContainer => {
+                        self.open_container = None;
+                        self.furnace_progress = None;
+                        self.is_inventory_open = false;
+                    }
+                    ToClient::OpenSignEditor(pos, text) => {
+                        self.text_input = Some((TextInput::SignText(pos), text));
+                    }
+                    ToClient::OpenCommandBlockEditor(pos, command) => {
+                        self.text_input = Some((TextInput::CommandBlock(pos), command));
+                    }
+                    ToClient::UpdateSign(pos, sign) => {
+                        self.world.set_sign(pos, sign);
+                    }
+                    ToClient::UpdateWaypoints(waypoints) => {
+                        self.waypoints = waypoints;
+                    }
+                    ToClient::UpdateSelection(first, second) => {
+                        self.selection = selection_box(first, second);
+                    }
+                    ToClient::UpdateStats(stats) => {
+                        self.stats = Some(stats);
+                    }
+                    ToClient::ScoreboardUpdate(scoreboard) => {
+                        self.scoreboard = scoreboard;
+                    }
+                    ToClient::BossBar(id, Some(bar)) => match self.boss_bars.get_mut(&id) {
+                        Some(displayed) => displayed.bar = bar,
+                        None => {
+                            self.boss_bars.insert(id, DisplayedBossBar::new(bar));
+                        }
+                    },
+                    ToClient::BossBar(id, None) => {
+                        self.boss_bars.remove(&id);
+                    }
+                    ToClient::Title {
+                        title,
+                        subtitle,
+                        fade_in,
+                        stay,
+                        fade_out,
+                    } => {
+                        self.title =
+                            Some(ShownTitle::new(title, subtitle, fade_in, stay, fade_out));
+                    }
+                    ToClient::ActionBar(text) => {
+                        self.action_bar = Some(ActionBarText::new(text));
+                    }
+                    ToClient::SetGameMode(game_mode) => {
+                        self.game_mode = game_mode;
+                        self.breaking = None;
+                    }
+                    ToClient::Teleported => {
+                        self.last_teleport = Some(Instant::now());
+                    }
+                    ToClient::Hurt => self.screen_effects.hurt(),
+                    ToClient::SetPaused(paused) => {
+                        self.is_server_paused = paused;
+                    }
+                    ToClient::ChatMessage(message) => {
+                        self.chat_messages.push_back((Instant::now(), message));
+                        if self.chat_messages.len() > MAX_CHAT_MESSAGES {
+                            self.chat_messages.pop_front();
+                        }
+                    }
                 },
-                ClientEvent::Disconnected => unimplemented!("server disconnected"),
+                ClientEvent::Disconnected => {
+                    log::warn!("Disconnected from the server");
+                    self.disconnected = true;
+                    break;
+                }
                 ClientEvent::Connected => {}
             }
         }
     }
+
+    /// Whether the player is currently controlling their character, i.e. no menu is open
+    fn is_playing(&self) -> bool {
+        !self.is_paused
+            && !self.is_inventory_open
+            && !self.is_map_open
+            && !self.is_stats_open
+            && self.text_input.is_none()
+    }
+
+    /// Draw the minimap in the top-right corner, or the fullscreen map if it is open
+    fn render_map(
+        &mut self,
+        buffers: WindowBuffers,
+        device: &mut wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        data: &WindowData,
+    ) {
+        let (player, _) = self.camera();
+        let center_block = (player[0].floor() as i64, player[2].floor() as i64);
+        let (width, height) = (data.gui_size().width as i32, data.gui_size().height as i32);
+        let (x, y, size, blocks) = if self.is_map_open {
+            let size = i32::min(width, height) - 2 * MAP_MARGIN;
+            (
+                (width - size) / 2,
+                (height - size) / 2,
+                size,
+                FULLSCREEN_MAP_BLOCKS,
+            )
+        } else {
+            (
+                width - MINIMAP_SIZE - MAP_MARGIN,
+                MAP_MARGIN,
+                MINIMAP_SIZE,
+                MINIMAP_BLOCKS,
+            )
+        };
+        self.minimap_renderer.render(
+            buffers,
+            device,
+            encoder,
+            &mut self.upload_belt,
+            data,
+            &self.minimap,
+            center_block,
+            blocks,
+            [x as f32, y as f32, size as f32, size as f32],
+        );
+        crate::gui::minimap::render_map_overlay(
+            &mut self.gui,
+            x,
+            y,
+            size,
+            blocks,
+            center_block,
+            (player[0], player[2]),
+            self.yaw_pitch.yaw,
+            &self.waypoints,
+        );
+        if self.is_map_open {
+            let removed = crate::gui::waypoints::render_waypoint_list(
+                &mut self.gui,
+                x + size + MAP_MARGIN,
+                y,
+                &self.waypoints,
+            );
+            if let Some(index) = removed {
+                self.client.send(ToServer::RemoveWaypoint(index));
+            }
+        }
+    }
+
+    /// Tell the server to break the pointed block, or to start breaking it in survival mode
+    fn send_break_block(&mut self) {
+        if let Some((pos, _)) = self.looking_at {
+            self.audio.watch_block(pos, &self.world);
+        }
+        let (y, p) = (self.yaw_pitch.yaw, self.yaw_pitch.pitch);
+        let pp = self.physics_simulation.get_player();
+        self.client
+            .send(ToServer::BreakBlock(pp.position().coords, y, p));
+    }
+
+    /// Close the text prompt and use the text that was typed
+    fn confirm_text_input(&mut self) {
+        let (input, text) = match self.text_input.take() {
+            Some(text_input) => text_input,
+            None => return,
+        };
+        let text = text.trim();
+        match input {
+            TextInput::WaypointName => {
+                if text.is_empty() {
+                    return;
+                }
+                let player = self.physics_simulation.get_camera_position();
+                self.client.send(ToServer::AddWaypoint(Waypoint {
+                    name: text.to_owned(),
+                    x: player[0].floor() as i64,
+                    y: player[1].floor() as i64,
+                    z: player[2].floor() as i64,
+                    color: self.gui.theme().waypoint_color(self.waypoints.len()),
+                }));
+            }
+            TextInput::Command => {
+                // TODO: send chat messages to the other players
+                if let Some(command) = text.strip_prefix('/') {
+                    self.client.send(ToServer::Command(command.to_owned()));
+                }
+            }
+            TextInput::SignText(pos) => {
+                self.client
+                    .send(ToServer::SetSignText(pos, text.to_owned()));
+            }
+            TextInput::CommandBlock(pos) => {
+                self.client
+                    .send(ToServer::SetCommandBlock(pos, text.to_owned()));
+            }
+        }
+    }
+
+    /// Close the inventory screen, and the open container if there is one
+    fn close_inventory(&mut self) {
+        self.is_inventory_open = false;
+        self.hovered_slot = None;
+        self.open_container = None;
+        self.furnace_progress = None;
+        self.client.send(ToServer::CloseContainer);
+    }
+
+    /// Draw the player inventory in the middle of the screen, below the open container if there is one
+    fn render_inventory(&mut self, data: &WindowData) {
+        use crate::gui::inventory::{
+            furnace_height, inventory_height, inventory_width, render_cursor_stack, render_furnace,
+            render_inventory, render_slot_tooltip, SLOT_SPACING,
+        };
+
+        let show_palette = self.open_container.is_none() && self.game_mode.has_infinite_items();
+        let container_height = match (&self.open_container, self.furnace_progress) {
+            (Some(_), Some(_)) => furnace_height() + 4 * SLOT_SPACING,
+            (Some((_, container)), None) => inventory_height(container) + 4 * SLOT_SPACING,
+            (None, _) if show_palette => {
+                inventory_height(&self.creative_palette) + 4 * SLOT_SPACING
+            }
+            (None, _) => 0,
+        };
+        let x = (data.gui_size().width as i32 - inventory_width()) / 2;
+        let mut y =
+            (data.gui_size().height as i32 - inventory_height(&self.inventory) - container_height)
+                / 2;
+
+        self.hovered_slot = None;
+        self.hovered_palette_slot = None;
+        if show_palette {
+            self.hovered_palette_slot = render_inventory(
+                &mut self.gui,
+                x,
+                y,
+                &self.creative_palette,
+                &self.item_registry,
+            );
+            y += container_height;
+        }
+        if let Some((_, container)) = &self.open_container {
+            let hovered_slot = match self.furnace_progress {
+                Some(progress) => render_furnace(
+                    &mut self.gui,
+                    x,
+                    y,
+                    container,
+                    progress,
+                    &self.item_registry,
+                ),
+                None => render_inventory(&mut self.gui, x, y, container, &self.item_registry),
+            };
+            if let Some(slot) = hovered_slot {
+                self.hovered_slot = Some((InventoryWindow::Container, slot));
+            }
+            y += container_height;
+        }
+        if let Some(slot) =
+            render_inventory(&mut self.gui, x, y, &self.inventory, &self.item_registry)
+        {
+            self.hovered_slot = Some((InventoryWindow::Player, slot));
+        }
+
+        match &self.cursor_stack {
+            Some(stack) => render_cursor_stack(&mut self.gui, stack, &self.item_registry),
+            None => match self.hovered_slot {
+                Some((InventoryWindow::Player, slot)) => {
+                    render_slot_tooltip(&mut self.gui, &self.inventory, slot, &self.item_registry)
+                }
+                Some((InventoryWindow::Container, slot)) => {
+                    if let Some((_, container)) = &self.open_container {
+                        render_slot_tooltip(&mut self.gui, container, slot, &self.item_registry);
+                    }
+                }
+                None => {
+                    if let Some(slot) = self.hovered_palette_slot {
+                        render_slot_tooltip(
+                            &mut self.gui,
+                            &self.creative_palette,
+                            slot,
+                            &self.item_registry,
+                        );
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// What the text typed in the text prompt is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextInput {
+    WaypointName,
+    Command,
+    /// The new text of the sign at some position
+    SignText(BlockPos),
+    /// The new command of the command block at some position
+    CommandBlock(BlockPos),
 }
 
+/// Scancode of the key that opens the inventory
+const TOGGLE_INVENTORY: u32 = 18;
+/// Scancode of the key that opens the fullscreen map
+const TOGGLE_MAP: u32 = 50;
+/// Scancode of the key that adds a waypoint at the position of the player
+const ADD_WAYPOINT: u32 = 49;
+/// Scancode of the key that opens the statistics screen
+const TOGGLE_STATS: u32 = 37;
+/// Scancode of the key that opens the command prompt
+const OPEN_COMMAND: u32 = 53;
+/// Scancode of the key that shows the player list while it is held
+const SHOW_PLAYER_LIST: u32 = 15;
+/// Scancodes of the keys that switch to the previous and to the next player while spectating
+const SPECTATE_PREVIOUS: u32 = 51;
+const SPECTATE_NEXT: u32 = 52;
+/// Scancode of the F4 key, that shows the chunk heatmap and cycles through its statistics
+const CYCLE_HEATMAP: u32 = 62;
+/// Height of the name tags above the position of the players, in blocks
+const NAME_TAG_HEIGHT: f64 = 2.2;
+/// Distance between the samples of the segments checked for the occlusion of the name tags, in blocks
+const NAME_TAG_OCCLUSION_STEP: f64 = 0.25;
+/// Scancode of the 1 key, the next keys select the next slots of the hotbar
+const FIRST_HOTBAR_KEY: u32 = 2;
+/// Maximum length of the text typed in the text prompts
+const MAX_TEXT_INPUT_LENGTH: usize = 100;
+/// Maximum length of the name of a waypoint
+const MAX_WAYPOINT_NAME_LENGTH: usize = 32;
+/// Number of chat messages that are kept
+const MAX_CHAT_MESSAGES: usize = 10;
+/// How long the chat messages are shown when the command prompt is closed
+const CHAT_MESSAGE_DURATION: Duration = Duration::from_secs(10);
+/// Seconds for the flash of the screen to fade out after going through a portal
+const TELEPORT_FLASH_DURATION: f32 = 0.6;
+/// Size of the minimap, and distance between the maps and the border of the screen
+const MINIMAP_SIZE: i32 = 192;
+const MAP_MARGIN: i32 = 10;
+/// Number of blocks shown along each axis of the minimap and of the fullscreen map. Must be multiples of 64.
+const MINIMAP_BLOCKS: u32 = 128;
+const FULLSCREEN_MAP_BLOCKS: u32 = 512;
+/// The direction of the neighbour block through each face, to know where the blocks are placed
+const FACE_DIRECTIONS: [[i64; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+/// Scancodes of the shift keys, used for quick-moving stacks
+const LEFT_SHIFT: u32 = 42;
+const RIGHT_SHIFT: u32 = 54;
+
 impl State for SinglePlayer {
     fn update(
         &mut self,
-        _settings: &mut Settings,
+        settings: &mut Settings,
         input_state: &InputState,
         window_data: &WindowData,
         flags: &mut WindowFlags,
-        _seconds_delta: f64,
+        seconds_delta: f64,
         _device: &mut wgpu::Device,
     ) -> Result<StateTransition> {
-        send_debug_info("Player", "fps", format!("fps = {}", self.fps_counter.fps()));
-
+        self.previous_camera_position = Some(self.camera().0);
+        self.previous_vehicle_positions = self
+            .physics_simulation
+            .vehicles()
+            .map(|(id, vehicle)| (id, (vehicle.position(), vehicle.yaw)))
+            .collect();
         self.client_timing.start_frame();
         self.pause_menu_renderer.update(window_data);
 
         // Handle server messages
         self.handle_server_messages();
+        self.report_bandwidth();
         self.client_timing.record_part("Network events");
 
         // Collect input
-        let frame_input = input_state.get_physics_input(self.yaw_pitch, !self.is_paused);
+        if self.is_playing() && self.spectating.is_none() {
+            let (dx, dy) =
+                self.mouse_filter
+                    .rotation(&settings.mouse, settings.invert_mouse, seconds_delta);
+            self.yaw_pitch.rotate(dx, dy);
+        } else {
+            self.mouse_filter.clear();
+        }
+        // The player doesn't move while watching another player
+        let mut frame_input = input_state.get_physics_input(
+            self.yaw_pitch,
+            self.is_playing() && self.spectating.is_none(),
+        );
+        // The server doesn't let the player fly in survival mode
+        frame_input.flying &= self.game_mode.can_fly();
 
         // Send input to server
         self.client.send(ToServer::UpdateInput(frame_input));
+        let wants_pause =
+            settings.pause_integrated_server && (self.is_paused || !window_data.focused);
+        if wants_pause != self.requested_server_pause {
+            self.requested_server_pause = wants_pause;
+            self.client.send(ToServer::SetPaused(wants_pause));
+        }
+        if let Some(client_time) = self.clock_sync.poll_request() {
+            self.client.send(ToServer::TimeRequest(client_time));
+        }
         self.client_timing.record_part("Collect and send input");
 
-        // Update physics
-        self.physics_simulation
-            .step_simulation(frame_input, Instant::now(), &self.world);
+        // Update physics, unless the game is paused
+        if !self.is_server_paused {
+            let server_time = self.clock_sync.server_now();
+            let world_view = WorldView::new(&self.world);
+            self.physics_simulation
+                .step_simulation(frame_input, server_time, &world_view);
+            let (accesses, lookups) = world_view.lookup_stats();
+            send_debug_info(
+                "Physics",
+                "chunklookups",
+                format!(
+                    "Physics chunk lookups: {} for {} block reads",
+                    lookups, accesses
+                ),
+            );
+        }
         self.client_timing.record_part("Update physics");
 
-        let p = self.physics_simulation.get_camera_position();
+        // Play the sounds of the footsteps, of the blocks changed by the server and of the ambience
+        self.audio.update_footsteps(
+            self.physics_simulation.get_player(),
+            &self.world,
+            &self.block_registry,
+        );
+        self.audio
+            .update_watched_blocks(&self.world, &self.block_registry);
+        let (camera, _) = self.camera();
+        self.audio
+            .update_ambience(camera, &self.world, seconds_delta as f32);
+        self.client_timing.record_part("Update audio");
+
+        self.view_bobbing.update(
+            self.physics_simulation.get_player(),
+            &self.world,
+            &settings.accessibility,
+            seconds_delta,
+        );
+        self.step_smoothing.update(
+            self.physics_simulation.get_player(),
+            &self.world,
+            settings.step_smoothing_time,
+            seconds_delta,
+        );
+        let surroundings = CameraSurroundings::sample(self.camera().0, &self.world);
+        self.screen_effects.update(surroundings, seconds_delta);
+        for bar in self.boss_bars.values_mut() {
+            bar.update(seconds_delta);
+        }
+        if let Some(title) = &mut self.title {
+            title.update(seconds_delta);
+            if title.is_over() {
+                self.title = None;
+            }
+        }
+        if let Some(action_bar) = &mut self.action_bar {
+            action_bar.update(seconds_delta);
+            if action_bar.is_over() {
+                self.action_bar = None;
+            }
+        }
+
+        // Follow the watched player
+        if let Some(camera) = &mut self.spectating {
+            if let Some((position, yaw, pitch)) =
+                self.physics_simulation.get_other_player_view(camera.target)
+            {
+                camera.update(position, yaw, pitch);
+            }
+        }
+
+        let (p, _) = self.camera();
         let player_chunk = BlockPos::from(p).containing_chunk_pos();
 
-        // Apply raytracing to get the pointed at block.
+        // Apply raytracing to get the pointed at block. Nothing is pointed at while spectating.
         let pp = self.physics_simulation.get_player();
-        self.looking_at = {
+        self.looking_at = if self.spectating.is_some() {
+            None
+        } else {
             let y = self.yaw_pitch.yaw.to_radians();
             let p = self.yaw_pitch.pitch.to_radians();
             let dir = Vector3::new(-y.sin() * p.cos(), p.sin(), -y.cos() * p.cos());
-            pp.get_pointed_at(dir, 10.0, &self.world)
+            pp.get_pointed_at(dir, 10.0, &WorldView::new(&self.world))
         };
         if let Some((x, face)) = self.looking_at {
             send_debug_info(
@@ -232,6 +1153,24 @@ impl State for SinglePlayer {
         }
         self.client_timing.record_part("Raytrace");
 
+        // Keep breaking the pointed block while the break button is held in survival mode
+        let pointed_block = self
+            .looking_at
+            .map(|(pos, _)| pos)
+            .filter(|_| self.is_playing());
+        if pointed_block != self.breaking.map(|(pos, _)| pos) {
+            if self.breaking.take().is_some() {
+                self.client.send(ToServer::StopBreaking);
+            }
+            if self.is_break_pressed && !self.game_mode.breaks_instantly() {
+                if let Some(pos) = pointed_block {
+                    self.send_break_block();
+                    self.breaking = Some((pos, Instant::now()));
+                }
+            }
+        }
+        self.client_timing.record_part("Update block breaking");
+
         // Debug current player position, yaw and pitch
         send_debug_info(
             "Player",
@@ -265,17 +1204,33 @@ impl State for SinglePlayer {
             "clientloaded",
             format!("Client loaded {} chunks", self.world.num_loaded_chunks()),
         );
+        set_gauge(
+            "voxel_client_mesh_queue_depth",
+            "Chunks waiting to be meshed by the integrated client",
+            self.world.num_chunks_in_meshing_queue() as f64,
+        );
 
-        flags.grab_cursor = !self.is_paused;
+        flags.grab_cursor = self.is_playing();
+        flags.software_cursor = settings.software_cursor && self.ui_renderer.has_cursor();
 
-        if self.pause_menu_renderer.state.program().should_exit {
-            self.pause_menu_renderer.reset(PauseMenuControls::new());
+        if self.pause_menu_renderer.state.program().should_exit || self.disconnected {
+            self.pause_menu_renderer
+                .reset(PauseMenuControls::new(self.can_open_to_lan));
             Ok(StateTransition::ReplaceCurrent(
-                crate::ui::mainmenu::MainMenu::new_factory(),
+                crate::ui::mainmenu::MainMenu::new_factory(self.preloaded_data.clone()),
             ))
         } else if self.pause_menu_renderer.state.program().should_resume {
             self.is_paused = false;
-            self.pause_menu_renderer.reset(PauseMenuControls::new());
+            self.pause_menu_renderer
+                .reset(PauseMenuControls::new(self.can_open_to_lan));
+            Ok(StateTransition::KeepCurrent)
+        } else if self.pause_menu_renderer.state.program().should_open_to_lan {
+            // The server answers in the chat with the port, or with the error
+            self.client.send(ToServer::OpenToLan);
+            self.can_open_to_lan = false;
+            self.is_paused = false;
+            self.pause_menu_renderer
+                .reset(PauseMenuControls::new(self.can_open_to_lan));
             Ok(StateTransition::KeepCurrent)
         } else {
             Ok(StateTransition::KeepCurrent)
@@ -284,62 +1239,171 @@ impl State for SinglePlayer {
 
     fn render<'a>(
         &mut self,
-        _settings: &Settings,
+        settings: &Settings,
         buffers: WindowBuffers<'a>,
         device: &mut wgpu::Device,
         data: &WindowData,
         input_state: &InputState,
     ) -> Result<(StateTransition, wgpu::CommandBuffer)> {
-        self.fps_counter.add_frame();
-
-        let frustum = Frustum::new(
-            self.physics_simulation.get_camera_position().coords,
-            self.yaw_pitch,
-        );
+        let (camera_position, camera_yaw_pitch) =
+            self.interpolated_camera(data.update_interpolation);
+        let frustum = Frustum::new(camera_position.coords, camera_yaw_pitch);
 
-        // Begin rendering
+        // Begin rendering, the uploads of the previous frame are done
+        self.upload_belt.recall(device);
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
         crate::render::clear_color_and_depth(&mut encoder, buffers);
 
+        // The entities farther than the render distance or outside of the frustum are not drawn
+        let aspect_ratio = {
+            let size = data.physical_window_size;
+            size.width as f64 / size.height as f64
+        };
+        let max_entity_distance = (self.render_distance.x_max * CHUNK_SIZE as u64) as f64;
+        let mut culling = EntityCulling::new(
+            &frustum,
+            aspect_ratio,
+            max_entity_distance,
+            input_state.enable_culling,
+        );
+        let model_registry = &self.model_registry;
+        let model_size = |mesh_id: u32| {
+            model_registry
+                .get_value_by_id(mesh_id)
+                .map_or([0; 3], |model| [model.size_x, model.size_y, model.size_z])
+        };
         let mut models_to_draw = Vec::new();
-        models_to_draw.push(crate::render::Model {
-            mesh_id: self
-                .model_registry
-                .get_id_by_name(&"knight".to_owned())
-                .unwrap(),
-            pos_x: 0.0,
-            pos_y: 55.0,
-            pos_z: 0.0,
-            scale: 0.3,
-            rot_offset: [0.0, 0.0, 0.0],
-            rot_y: 0.0,
-        });
-        let item_rotation = (Instant::now() - self.start_time).as_secs_f32(); // TODO: use f64
-        models_to_draw.push(crate::render::Model {
-            mesh_id: self
-                .model_registry
-                .get_id_by_name(&"item:ingot_iron".to_owned())
-                .unwrap(),
-            pos_x: 30.0,
-            pos_y: 55.0,
-            pos_z: 30.0,
-            scale: 1.0 / 32.0,
-            rot_offset: [0.5, 0.5, 1.0 / 64.0],
-            rot_y: item_rotation,
-        });
+        let model_rotation = (Instant::now() - self.start_time).as_secs_f32(); // TODO: use f64
+        for displayed_model in self.displayed_models.iter() {
+            let mesh_id = match self.model_registry.get_id_by_name(&displayed_model.model) {
+                Some(mesh_id) => mesh_id,
+                None => continue,
+            };
+            let [pos_x, pos_y, pos_z] = displayed_model.position;
+            let model = crate::render::Model {
+                mesh_id,
+                pos_x,
+                pos_y,
+                pos_z,
+                scale: displayed_model.scale,
+                rot_offset: displayed_model.rot_offset,
+                rot_y: if displayed_model.spinning {
+                    model_rotation
+                } else {
+                    0.0
+                },
+                pose: Isometry3::identity(),
+                skin: None,
+            };
+            if culling.check(&model, model_size(mesh_id)).is_some() {
+                models_to_draw.push(model);
+            }
+        }
+        // Draw the vehicles turned towards their yaw, between their last two positions.
+        // The boat model is 14 voxels wide and the cart model 9 voxels wide, centered on the vehicle.
+        for (id, vehicle) in self.physics_simulation.vehicles() {
+            let (model, half_width) = match vehicle.kind {
+                VehicleKind::Boat => ("boat", 0.7),
+                VehicleKind::Cart => ("cart", 0.45),
+            };
+            let mesh_id = match self.model_registry.get_id_by_name(&model.to_owned()) {
+                Some(mesh_id) => mesh_id,
+                None => continue,
+            };
+            let (mut position, mut yaw) = (vehicle.position(), vehicle.yaw);
+            if let Some(&(previous_position, previous_yaw)) =
+                self.previous_vehicle_positions.get(&id)
+            {
+                position =
+                    previous_position + (position - previous_position) * data.update_interpolation;
+                // Turn the shortest way
+                let turn = (yaw - previous_yaw + 540.0).rem_euclid(360.0) - 180.0;
+                yaw = previous_yaw + turn * data.update_interpolation;
+            }
+            let model = crate::render::Model {
+                mesh_id,
+                pos_x: position.x as f32 - half_width,
+                pos_y: position.y as f32,
+                pos_z: position.z as f32 - half_width,
+                scale: 0.1,
+                rot_offset: [half_width, 0.0, half_width],
+                rot_y: yaw.to_radians() as f32,
+                pose: Isometry3::identity(),
+                skin: None,
+            };
+            if culling.check(&model, model_size(mesh_id)).is_some() {
+                models_to_draw.push(model);
+            }
+        }
+        // Draw the other players, a bit lower when they are sneaking, and animated if their model has parts
+        // and they are close enough
+        let player_mesh_id = self
+            .player_model
+            .as_ref()
+            .and_then(|name| self.model_registry.get_id_by_name(name));
+        let player_animated_model = self
+            .player_model
+            .as_ref()
+            .and_then(|name| self.animated_models.get_id_by_name(name))
+            .and_then(|id| self.animated_models.get_value_by_id(id));
+        if let Some(player_mesh_id) = player_mesh_id {
+            // The camera is inside the watched player
+            let spectate_target = self.spectate_target();
+            for (id, player) in self.physics_simulation.other_players() {
+                if Some(id) == spectate_target {
+                    continue;
+                }
+                let position = player.position();
+                let sneaking_offset = if player.sneaking { 0.15 } else { 0.0 };
+                let model = crate::render::Model {
+                    mesh_id: player_mesh_id,
+                    pos_x: position.x as f32,
+                    pos_y: (position.y - sneaking_offset) as f32,
+                    pos_z: position.z as f32,
+                    scale: 0.3,
+                    rot_offset: [0.0, 0.0, 0.0],
+                    rot_y: 0.0,
+                    pose: Isometry3::identity(),
+                    skin: Some(id),
+                };
+                let detail = match culling.check(&model, model_size(player_mesh_id)) {
+                    Some(detail) => detail,
+                    None => continue,
+                };
+                match (player_animated_model, detail) {
+                    (Some(animated_model), ModelDetail::Full) => models_to_draw
+                        .extend(self.animations.pose_parts(id, animated_model, &model)),
+                    _ => models_to_draw.push(model),
+                }
+            }
+        }
+        culling.send_debug_info();
         // Draw chunks
+        let hand_light = self
+            .block_registry
+            .get_value_by_id(self.held_block as u32)
+            .map_or(0, |block| block.light_level());
+        let flicker = crate::flicker::flame_flicker(
+            self.start_time.elapsed().as_secs_f64(),
+            &settings.accessibility,
+        );
         self.world.render_chunks(
             device,
             &mut encoder,
+            &mut self.upload_belt,
             buffers,
             data,
             &frustum,
             input_state.enable_culling,
             self.looking_at,
+            self.selection,
+            hand_light,
+            flicker,
             &models_to_draw,
         );
+        self.check_gpu_memory(settings.gpu_memory_budget_mb, device, &mut encoder);
         self.client_timing.record_part("Render chunks");
 
         crate::render::clear_depth(&mut encoder, buffers);
@@ -349,18 +1413,204 @@ impl State for SinglePlayer {
         // crate::render::encode_resolve_render_pass(&mut encoder, buffers);
         self.gui.prepare();
         crate::gui::experiments::render_debug_info(&mut self.gui, &mut self.debug_info);
+        crate::gui::inventory::render_hotbar(
+            &mut self.gui,
+            data.gui_size().width as i32,
+            data.gui_size().height as i32,
+            &self.inventory,
+            self.selected_slot,
+            &self.item_registry,
+        );
+        if self.is_inventory_open {
+            self.render_inventory(data);
+        }
+        self.render_map(buffers, device, &mut encoder, data);
+        if let (Some(metric), false) = (self.heatmap_metric, self.is_map_open) {
+            let (player, _) = self.camera();
+            let player_column = ChunkPosXZ::from(BlockPos::from(player).containing_chunk_pos());
+            crate::gui::heatmap::render_chunk_heatmap(
+                &mut self.gui,
+                data.gui_size().width as i32 - MINIMAP_SIZE - MAP_MARGIN,
+                2 * MAP_MARGIN + MINIMAP_SIZE,
+                MINIMAP_SIZE,
+                metric,
+                &self.debug_info.column_stats(metric),
+                &self.world.loaded_columns(),
+                player_column,
+                self.render_distance.x_max as i64,
+            );
+        }
+        let max_waypoint_distance = (self.render_distance.x_max * CHUNK_SIZE as u64) as f64;
+        crate::gui::waypoints::render_waypoint_labels(
+            &mut self.gui,
+            &frustum,
+            data.gui_size().width as f32,
+            data.gui_size().height as f32,
+            &self.waypoints,
+            max_waypoint_distance,
+        );
+        crate::gui::players::render_name_tags(
+            &mut self.gui,
+            &frustum,
+            data.gui_size().width as f32,
+            data.gui_size().height as f32,
+            &self.name_tags(),
+        );
+        crate::gui::boss_bars::render_boss_bars(
+            &mut self.gui,
+            data.gui_size().width as i32,
+            &self.boss_bars,
+        );
+        if let Some(title) = &self.title {
+            crate::gui::titles::render_title(
+                &mut self.gui,
+                data.gui_size().width as i32,
+                data.gui_size().height as i32,
+                title,
+            );
+        }
+        if let Some(action_bar) = &self.action_bar {
+            crate::gui::titles::render_action_bar(
+                &mut self.gui,
+                data.gui_size().width as i32,
+                data.gui_size().height as i32,
+                action_bar,
+            );
+        }
+        if let Some(objective) = &self.scoreboard.sidebar {
+            crate::gui::scoreboard::render_sidebar(
+                &mut self.gui,
+                data.gui_size().width as i32,
+                data.gui_size().height as i32,
+                objective,
+            );
+        }
+        if let Some(target) = self.spectate_target() {
+            let name = self
+                .player_list
+                .iter()
+                .find(|entry| entry.id == target)
+                .map_or("another player", |entry| entry.name.as_str());
+            crate::gui::players::render_spectating(
+                &mut self.gui,
+                data.gui_size().width as i32,
+                name,
+            );
+        }
+        if self.is_player_list_open {
+            crate::gui::players::render_player_list(
+                &mut self.gui,
+                data.gui_size().width as i32,
+                &self.player_list,
+                self.physics_simulation.get_player_id(),
+            );
+        }
+        if self.is_stats_open {
+            crate::gui::stats::render_stats(
+                &mut self.gui,
+                data.gui_size().width as i32,
+                data.gui_size().height as i32,
+                self.stats.as_ref(),
+            );
+        }
+        let prompt_label = match &self.text_input {
+            Some((TextInput::WaypointName, _)) => Some("Waypoint name"),
+            Some((TextInput::SignText(_), _)) => Some("Sign text"),
+            Some((TextInput::CommandBlock(_), _)) => Some("Command block command"),
+            _ => None,
+        };
+        if let (Some(label), Some((_, text))) = (prompt_label, &self.text_input) {
+            crate::gui::hud::render_text_prompt(
+                &mut self.gui,
+                data.gui_size().width as i32,
+                data.gui_size().height as i32,
+                label,
+                text,
+            );
+        }
+        // Show all the recent messages while typing a command
+        let command = match &self.text_input {
+            Some((TextInput::Command, command)) => Some(command.as_str()),
+            _ => None,
+        };
+        let now = Instant::now();
+        crate::gui::chat::render_chat(
+            &mut self.gui,
+            data.gui_size().height as i32,
+            self.chat_messages
+                .iter()
+                .filter(|(time, _)| command.is_some() || now - *time < CHAT_MESSAGE_DURATION)
+                .map(|(_, message)| message.as_str()),
+            command,
+        );
+        crate::gui::hud::render_screen_effects(
+            &mut self.gui,
+            data.gui_size().width as i32,
+            data.gui_size().height as i32,
+            &self.screen_effects,
+        );
+        if let Some(last_teleport) = self.last_teleport {
+            let fraction = (now - last_teleport).as_secs_f32() / TELEPORT_FLASH_DURATION;
+            if fraction < 1.0 {
+                crate::gui::hud::render_teleport_flash(
+                    &mut self.gui,
+                    data.gui_size().width as i32,
+                    data.gui_size().height as i32,
+                    fraction,
+                );
+            }
+        }
+        if let Some((pos, start)) = self.breaking {
+            let break_time = self
+                .block_registry
+                .get_value_by_id(self.world.get_block(pos) as u32)
+                .map(Block::break_time)
+                .unwrap_or(0.0);
+            if break_time > 0.0 {
+                crate::gui::hud::render_break_progress(
+                    &mut self.gui,
+                    data.gui_size().width as i32,
+                    data.gui_size().height as i32,
+                    (now - start).as_secs_f32() / break_time,
+                );
+            }
+        }
         self.gui.finish();
+        let draw_crosshair = self.is_playing();
         self.ui_renderer.render(
             buffers,
             device,
             &mut encoder,
+            &mut self.upload_belt,
             &data,
             &mut self.gui,
-            !self.is_paused,
+            draw_crosshair,
         );
         if self.is_paused {
-            self.pause_menu_renderer
-                .render(device, buffers, &mut encoder, None);
+            self.pause_menu_renderer.render(
+                device,
+                buffers,
+                &mut encoder,
+                &mut self.upload_belt,
+                None,
+            );
+        }
+        if settings.software_cursor && !self.is_playing() {
+            let (x, y) = self.gui.mouse_position();
+            let style = if self.is_paused {
+                self.pause_menu_renderer.cursor_style()
+            } else {
+                self.gui.cursor_style()
+            };
+            self.ui_renderer.render_cursor(
+                buffers,
+                device,
+                &mut encoder,
+                &mut self.upload_belt,
+                &data,
+                (x as f32, y as f32),
+                style,
+            );
         }
 
         self.client_timing.record_part("Render UI");
@@ -372,16 +1622,36 @@ impl State for SinglePlayer {
             self.client_timing.extract_part_averages(),
         );
 
+        self.upload_belt.finish();
         Ok((StateTransition::KeepCurrent, encoder.finish()))
     }
 
     fn handle_window_event(&mut self, event: winit::event::WindowEvent, _: &InputState) {
+        // Type in the open text prompt
+        if let winit::event::WindowEvent::ReceivedCharacter(c) = event {
+            if let Some((input, text)) = &mut self.text_input {
+                let max_length = match input {
+                    TextInput::WaypointName => MAX_WAYPOINT_NAME_LENGTH,
+                    TextInput::Command => MAX_TEXT_INPUT_LENGTH,
+                    TextInput::SignText(_) => MAX_SIGN_TEXT_LENGTH,
+                    TextInput::CommandBlock(_) => MAX_COMMAND_LENGTH,
+                };
+                match c {
+                    '\r' | '\n' => self.confirm_text_input(),
+                    '\u{8}' => {
+                        text.pop();
+                    }
+                    c if !c.is_control() && text.chars().count() < max_length => text.push(c),
+                    _ => {}
+                }
+            }
+        }
         self.pause_menu_renderer.handle_window_event(event)
     }
 
     fn handle_mouse_motion(&mut self, _settings: &Settings, delta: (f64, f64)) {
-        if !self.is_paused {
-            self.yaw_pitch.update_cursor(delta.0, delta.1);
+        if self.is_playing() && self.spectating.is_none() {
+            self.mouse_filter.add_movement(delta.0, delta.1);
         }
     }
 
@@ -396,21 +1666,35 @@ impl State for SinglePlayer {
         &mut self,
         changes: Vec<(winit::event::MouseButton, winit::event::ElementState)>,
     ) {
-        if self.is_paused {
+        if !self.is_playing() {
             for (button, state) in changes.iter() {
                 match *button {
                     MouseButton::Left => match *state {
                         ElementState::Pressed => {
                             self.gui.update_mouse_button(true);
+                            if self.is_inventory_open {
+                                if let Some((window, slot)) = self.hovered_slot {
+                                    self.client.send(ToServer::ClickSlot(
+                                        window,
+                                        slot,
+                                        self.shift_pressed,
+                                    ));
+                                }
+                                if let Some(slot) = self.hovered_palette_slot {
+                                    self.client
+                                        .send(ToServer::ClickCreativePalette(slot as ItemId));
+                                }
+                            }
                         }
                         ElementState::Released => {
                             self.gui.update_mouse_button(false);
+                            self.is_break_pressed = false;
                         }
                     },
                     _ => {}
                 }
             }
-        } else {
+        } else if self.spectating.is_none() {
             for (button, state) in changes.iter() {
                 let pp = self.physics_simulation.get_player();
                 let y = self.yaw_pitch.yaw;
@@ -418,20 +1702,41 @@ impl State for SinglePlayer {
                 match *button {
                     MouseButton::Left => match *state {
                         ElementState::Pressed => {
-                            self.client
-                                .send(ToServer::BreakBlock(pp.position().coords, y, p));
+                            self.is_break_pressed = true;
+                            // In survival mode, the block is broken in `update` while the button is held
+                            if self.game_mode.breaks_instantly() {
+                                self.client
+                                    .send(ToServer::BreakBlock(pp.position().coords, y, p));
+                                if let Some((pos, _)) = self.looking_at {
+                                    self.audio.watch_block(pos, &self.world);
+                                }
+                            }
+                        }
+                        ElementState::Released => {
+                            self.is_break_pressed = false;
+                            if self.breaking.take().is_some() {
+                                self.client.send(ToServer::StopBreaking);
+                            }
                         }
-                        _ => {}
                     },
                     MouseButton::Right => match *state {
                         ElementState::Pressed => {
                             self.client
                                 .send(ToServer::PlaceBlock(pp.position().coords, y, p));
+                            if let Some((mut pos, face)) = self.looking_at {
+                                pos.px += FACE_DIRECTIONS[face][0];
+                                pos.py += FACE_DIRECTIONS[face][1];
+                                pos.pz += FACE_DIRECTIONS[face][2];
+                                self.audio.watch_block(pos, &self.world);
+                            }
                         }
                         _ => {}
                     },
                     MouseButton::Middle => match *state {
                         ElementState::Pressed => {
+                            if let Some((pos, _)) = self.looking_at {
+                                self.held_block = self.world.get_block(pos);
+                            }
                             self.client
                                 .send(ToServer::SelectBlock(pp.position().coords, y, p));
                         }
@@ -445,10 +1750,111 @@ impl State for SinglePlayer {
 
     fn handle_key_state_changes(&mut self, changes: Vec<(u32, winit::event::ElementState)>) {
         for (key, state) in changes.into_iter() {
-            // Escape key
-            if key == 1 {
-                if let winit::event::ElementState::Pressed = state {
-                    self.is_paused = !self.is_paused;
+            // Shift keys
+            if key == LEFT_SHIFT || key == RIGHT_SHIFT {
+                self.shift_pressed = state == winit::event::ElementState::Pressed;
+            }
+            // Tab key
+            if key == SHOW_PLAYER_LIST {
+                self.is_player_list_open = state == winit::event::ElementState::Pressed;
+            }
+            if let winit::event::ElementState::Pressed = state {
+                // The other keys are used to type in the text prompt
+                if self.text_input.is_some() {
+                    if key == 1 {
+                        self.text_input = None;
+                    }
+                    continue;
+                }
+                // Escape key
+                if key == 1 {
+                    if self.is_inventory_open {
+                        self.close_inventory();
+                    } else if self.is_map_open {
+                        self.is_map_open = false;
+                    } else if self.is_stats_open {
+                        self.is_stats_open = false;
+                    } else {
+                        self.is_paused = !self.is_paused;
+                    }
+                }
+                // E key
+                if key == TOGGLE_INVENTORY
+                    && !self.is_paused
+                    && !self.is_map_open
+                    && !self.is_stats_open
+                {
+                    if self.is_inventory_open {
+                        self.close_inventory();
+                    } else {
+                        self.is_inventory_open = true;
+                    }
+                }
+                // M key
+                if key == TOGGLE_MAP
+                    && !self.is_paused
+                    && !self.is_inventory_open
+                    && !self.is_stats_open
+                {
+                    self.is_map_open = !self.is_map_open;
+                }
+                // N key
+                if key == ADD_WAYPOINT
+                    && !self.is_paused
+                    && !self.is_inventory_open
+                    && !self.is_stats_open
+                {
+                    self.text_input = Some((TextInput::WaypointName, String::new()));
+                }
+                // / key
+                if key == OPEN_COMMAND
+                    && !self.is_paused
+                    && !self.is_inventory_open
+                    && !self.is_stats_open
+                {
+                    // The `/` character was received before the prompt was opened
+                    self.text_input = Some((TextInput::Command, "/".to_owned()));
+                }
+                // Spectating keys: comma and period switch between the players, shift goes back to the own camera
+                if self.spectating.is_some() && self.is_playing() {
+                    if key == SPECTATE_PREVIOUS || key == SPECTATE_NEXT {
+                        self.cycle_spectate_target(key == SPECTATE_NEXT);
+                    }
+                    if key == LEFT_SHIFT || key == RIGHT_SHIFT {
+                        self.client.send(ToServer::Spectate(None));
+                    }
+                }
+                // Number keys
+                if (FIRST_HOTBAR_KEY..FIRST_HOTBAR_KEY + HOTBAR_SIZE as u32).contains(&key)
+                    && self.is_playing()
+                {
+                    self.selected_slot = (key - FIRST_HOTBAR_KEY) as usize;
+                    self.client
+                        .send(ToServer::SelectHotbarSlot(self.selected_slot));
+                }
+                // K key
+                if key == TOGGLE_STATS
+                    && !self.is_paused
+                    && !self.is_inventory_open
+                    && !self.is_map_open
+                {
+                    self.is_stats_open = !self.is_stats_open;
+                    if self.is_stats_open {
+                        self.stats = None;
+                        self.client.send(ToServer::RequestStats);
+                    }
+                }
+                // F4 key
+                if key == CYCLE_HEATMAP {
+                    let metrics = ChunkMetric::ALL;
+                    self.heatmap_metric = match self.heatmap_metric {
+                        None => Some(metrics[0]),
+                        Some(metric) => metrics
+                            .iter()
+                            .position(|&m| m == metric)
+                            .and_then(|i| metrics.get(i + 1))
+                            .copied(),
+                    };
                 }
             }
         }
@@ -0,0 +1,52 @@
+//! Loading of the skin of the player from the config folder
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::path::Path;
+use voxel_rs_common::data::vox::{load_voxel_model, VoxelModel};
+use voxel_rs_common::skin::PlayerSkin;
+
+const SKIN_IMAGE_PATH: &str = "config/skin.png";
+const SKIN_MODEL_PATH: &str = "config/skin.vox";
+
+/// Load the skin of the player: `config/skin.png`, a palette of the colors to replace,
+/// or else `config/skin.vox`, a recolored copy of the player model.
+/// Returns `None` if there is no skin or if it can't be loaded.
+pub fn load_skin(player_model: Option<&VoxelModel>) -> Option<PlayerSkin> {
+    let skin = if Path::new(SKIN_IMAGE_PATH).is_file() {
+        load_skin_image()
+    } else if Path::new(SKIN_MODEL_PATH).is_file() {
+        match player_model {
+            Some(player_model) => load_skin_model(player_model),
+            None => {
+                warn!("Ignoring {}, there is no player model", SKIN_MODEL_PATH);
+                return None;
+            }
+        }
+    } else {
+        return None;
+    };
+    match skin {
+        Ok(skin) => {
+            info!("Loaded a skin replacing {} colors", skin.colors.len());
+            Some(skin)
+        }
+        Err(e) => {
+            warn!("Failed to load the skin: {:?}", e);
+            None
+        }
+    }
+}
+
+fn load_skin_image() -> Result<PlayerSkin> {
+    let image = image::open(SKIN_IMAGE_PATH)
+        .with_context(|| format!("Failed to open {}", SKIN_IMAGE_PATH))?
+        .to_rgba();
+    PlayerSkin::from_image(&image).with_context(|| format!("Invalid skin {}", SKIN_IMAGE_PATH))
+}
+
+fn load_skin_model(player_model: &VoxelModel) -> Result<PlayerSkin> {
+    let model = load_voxel_model(SKIN_MODEL_PATH)
+        .with_context(|| format!("Failed to load {}", SKIN_MODEL_PATH))?;
+    PlayerSkin::from_models(player_model, &model)
+        .with_context(|| format!("Invalid skin {}", SKIN_MODEL_PATH))
+}
@@ -0,0 +1,68 @@
+//! The camera that follows the viewpoint of another player
+use crate::input::YawPitch;
+use nalgebra::Point3;
+use std::time::Instant;
+use voxel_rs_common::player::PlayerId;
+
+/// How fast the camera catches up with the viewpoint of the target: the remaining distance is divided by e
+/// every `1 / SMOOTHING` seconds
+const SMOOTHING: f64 = 12.0;
+
+pub struct SpectatorCamera {
+    /// The player being watched
+    pub target: PlayerId,
+    position: Point3<f64>,
+    yaw_pitch: YawPitch,
+    last_update: Instant,
+}
+
+impl SpectatorCamera {
+    /// Start watching `target` from the current camera
+    pub fn new(target: PlayerId, position: Point3<f64>, yaw_pitch: YawPitch) -> Self {
+        Self {
+            target,
+            position,
+            yaw_pitch,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Move the camera towards the viewpoint of the target
+    pub fn update(&mut self, target_position: Point3<f64>, target_yaw: f64, target_pitch: f64) {
+        let now = Instant::now();
+        let dt = (now - self.last_update).as_secs_f64();
+        self.last_update = now;
+        let t = 1.0 - (-SMOOTHING * dt).exp();
+        self.position += (target_position - self.position) * t;
+        self.yaw_pitch.yaw = lerp_angle(self.yaw_pitch.yaw, target_yaw, t);
+        self.yaw_pitch.pitch += (target_pitch - self.yaw_pitch.pitch) * t;
+    }
+
+    pub fn position(&self) -> Point3<f64> {
+        self.position
+    }
+
+    pub fn yaw_pitch(&self) -> YawPitch {
+        self.yaw_pitch
+    }
+}
+
+/// Interpolate between two angles in degrees, the short way around. The result is in [-180; 180].
+fn lerp_angle(from: f64, to: f64, t: f64) -> f64 {
+    let delta = (to - from + 180.0).rem_euclid(360.0) - 180.0;
+    (from + delta * t + 180.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lerp_angle() {
+        assert_eq!(lerp_angle(10.0, 30.0, 0.5), 20.0);
+        // Going through 180 rather than through 0
+        assert_eq!(lerp_angle(170.0, -170.0, 0.5), -180.0);
+        assert_eq!(lerp_angle(-170.0, 170.0, 0.25), -175.0);
+        assert_eq!(lerp_angle(0.0, 90.0, 1.0), 90.0);
+    }
+}
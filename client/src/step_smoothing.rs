@@ -0,0 +1,109 @@
+//! The smoothing of the camera height: when the player walks up a step or starts or stops sneaking, the camera
+//! catches up with the eyes over a short time instead of snapping to them. Only the rendered camera is smoothed,
+//! the pointed block and the reach checks of the server still use the true position of the eyes.
+use crate::world::World;
+use nalgebra::Vector3;
+use voxel_rs_common::physics::player::PhysicsPlayer;
+
+/// Highest rise of the feet in one update that is smoothed, a bit more than the highest step
+const MAX_SMOOTHED_RISE: f64 = 0.6;
+
+#[derive(Default)]
+pub struct StepSmoothing {
+    /// Vertical offset of the camera from the eyes, that shrinks back to 0
+    offset: f64,
+    /// The height of the feet and the height of the eyes above the feet at the last update
+    last_heights: Option<(f64, f64)>,
+}
+
+impl StepSmoothing {
+    /// Follow the eyes of the player, `smoothing_time` being the time in seconds to catch up with them
+    pub fn update(
+        &mut self,
+        player: &PhysicsPlayer,
+        world: &World,
+        smoothing_time: f64,
+        seconds_delta: f64,
+    ) {
+        let feet = player.position().y;
+        let eye_height = player.get_camera_position().y - feet;
+        self.advance(
+            feet,
+            eye_height,
+            player.is_on_ground(world),
+            smoothing_time,
+            seconds_delta,
+        );
+    }
+
+    fn advance(
+        &mut self,
+        feet: f64,
+        eye_height: f64,
+        on_ground: bool,
+        smoothing_time: f64,
+        seconds_delta: f64,
+    ) {
+        if let Some((last_feet, last_eye_height)) = self.last_heights.replace((feet, eye_height)) {
+            // Rising while staying on the ground is walking up a step, jumping leaves the ground
+            let rise = feet - last_feet;
+            if on_ground && rise > 0.0 && rise <= MAX_SMOOTHED_RISE {
+                self.offset -= rise;
+            }
+            self.offset += last_eye_height - eye_height;
+        }
+        // 98% of the offset is gone after the smoothing time
+        self.offset = if smoothing_time > 0.0 {
+            let decay = (-4.0 * seconds_delta / smoothing_time).exp();
+            (self.offset * decay)
+                .max(-MAX_SMOOTHED_RISE)
+                .min(MAX_SMOOTHED_RISE)
+        } else {
+            0.0
+        };
+    }
+
+    /// Offset of the camera from the eyes of the player
+    pub fn offset(&self) -> Vector3<f64> {
+        Vector3::new(0.0, self.offset, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    #[test]
+    fn test_step_smoothing() {
+        let mut smoothing = StepSmoothing::default();
+        smoothing.advance(10.0, 1.6, true, 0.2, DT);
+        assert_eq!(smoothing.offset, 0.0);
+
+        // Walking up a step: the camera starts below the eyes and catches up
+        smoothing.advance(10.5, 1.6, true, 0.2, DT);
+        let after_step = smoothing.offset;
+        assert!(after_step < -0.3 && after_step > -0.5);
+        for _ in 0..12 {
+            smoothing.advance(10.5, 1.6, true, 0.2, DT);
+        }
+        assert!(smoothing.offset.abs() < 0.01);
+
+        // Sneaking lowers the eyes, the camera follows them down
+        smoothing.advance(10.5, 1.45, true, 0.2, DT);
+        assert!(smoothing.offset > 0.05);
+
+        // Jumping is not smoothed
+        let mut smoothing = StepSmoothing::default();
+        smoothing.advance(10.0, 1.6, true, 0.2, DT);
+        smoothing.advance(10.1, 1.6, false, 0.2, DT);
+        assert_eq!(smoothing.offset, 0.0);
+
+        // Without smoothing, the camera stays at the eyes
+        let mut smoothing = StepSmoothing::default();
+        smoothing.advance(10.0, 1.6, true, 0.0, DT);
+        smoothing.advance(10.5, 1.6, true, 0.0, DT);
+        assert_eq!(smoothing.offset, 0.0);
+    }
+}
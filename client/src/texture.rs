@@ -1,6 +1,7 @@
+use crate::render::memory::{self, MemoryCategory, TrackedTexture};
 use image::{ImageBuffer, Rgba};
 use log::info;
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::util::BufferInitDescriptor;
 
 const MIPMAP_LEVELS: u32 = 5;
 
@@ -9,7 +10,7 @@ pub fn load_image(
     device: &wgpu::Device,
     encoder: &mut wgpu::CommandEncoder,
     image: ImageBuffer<Rgba<u8>, Vec<u8>>,
-) -> wgpu::Texture {
+) -> TrackedTexture {
     info!("Loading image...");
     // Only squared images are allowed
     // TODO: check for power of two
@@ -63,17 +64,21 @@ pub fn load_image(
         format: wgpu::TextureFormat::Rgba8Unorm,
         usage: wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::SAMPLED,
     };
-    let texture = device.create_texture(&texture_descriptor);
+    let texture = memory::create_texture(device, MemoryCategory::Textures, &texture_descriptor);
     // Send texture to GPU
 
     for level in 0..MIPMAP_LEVELS {
         info!("Copying mipmap level {mipmap_level}", mipmap_level = level);
         let current_size = image_size >> level;
-        let src_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: None,
-            usage: wgpu::BufferUsage::COPY_SRC,
-            contents: &mipmaps[level as usize],
-        });
+        let src_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Other,
+            &BufferInitDescriptor {
+                label: None,
+                usage: wgpu::BufferUsage::COPY_SRC,
+                contents: &mipmaps[level as usize],
+            },
+        );
         let buffer_view = wgpu::BufferCopyView {
             layout: wgpu::TextureDataLayout {
                 offset: 0,
@@ -0,0 +1,133 @@
+use anyhow::Result;
+
+use crate::{
+    gui::{theme::load_theme, Gui},
+    input::InputState,
+    render::{UiRenderer, UploadBelt},
+    settings::Settings,
+    window::{State, StateTransition, WindowBuffers, WindowData, WindowFlags},
+};
+
+/// State of the error screen, shown instead of a state that couldn't be created, with the chain of causes
+pub struct ErrorScreen {
+    title: String,
+    lines: Vec<String>,
+    gui: Gui,
+    ui_renderer: UiRenderer,
+    upload_belt: UploadBelt,
+}
+
+impl ErrorScreen {
+    pub fn new_factory(title: String, error: anyhow::Error) -> crate::window::StateFactory {
+        let lines = error.chain().map(|cause| cause.to_string()).collect();
+        Box::new(move |device, settings, _window_data, _modifiers_state| {
+            Self::new(device, settings, title, lines)
+        })
+    }
+
+    pub fn new(
+        device: &mut wgpu::Device,
+        settings: &mut Settings,
+        title: String,
+        lines: Vec<String>,
+    ) -> Result<(Box<dyn State>, wgpu::CommandBuffer)> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("error_screen_encoder"),
+        });
+        let gui = Gui::new(load_theme(&settings.theme));
+        let mut ui_renderer = UiRenderer::new(device)?;
+        ui_renderer.load_cursor(device, &mut encoder, &gui.theme().cursor);
+        Ok((
+            Box::new(Self {
+                title,
+                lines,
+                gui,
+                ui_renderer,
+                upload_belt: UploadBelt::new(),
+            }),
+            encoder.finish(),
+        ))
+    }
+}
+
+impl State for ErrorScreen {
+    fn update(
+        &mut self,
+        settings: &mut Settings,
+        _input_state: &InputState,
+        _data: &WindowData,
+        flags: &mut WindowFlags,
+        _seconds_delta: f64,
+        _device: &mut wgpu::Device,
+    ) -> Result<StateTransition> {
+        flags.grab_cursor = false;
+        flags.software_cursor = settings.software_cursor && self.ui_renderer.has_cursor();
+        Ok(StateTransition::KeepCurrent)
+    }
+
+    fn render<'a>(
+        &mut self,
+        settings: &Settings,
+        buffers: WindowBuffers<'a>,
+        device: &mut wgpu::Device,
+        window_data: &WindowData,
+        _input_state: &InputState,
+    ) -> Result<(StateTransition, wgpu::CommandBuffer)> {
+        self.upload_belt.recall(device);
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        crate::render::clear_color_and_depth(&mut encoder, buffers);
+
+        self.gui.prepare();
+        let gui_size = window_data.gui_size();
+        crate::gui::loading::render_error_screen(
+            &mut self.gui,
+            gui_size.width as i32,
+            gui_size.height as i32,
+            self.title.clone(),
+            &self.lines,
+        );
+        self.gui.finish();
+        self.ui_renderer.render(
+            buffers,
+            device,
+            &mut encoder,
+            &mut self.upload_belt,
+            window_data,
+            &mut self.gui,
+            false,
+        );
+        if settings.software_cursor {
+            let (x, y) = self.gui.mouse_position();
+            self.ui_renderer.render_cursor(
+                buffers,
+                device,
+                &mut encoder,
+                &mut self.upload_belt,
+                window_data,
+                (x as f32, y as f32),
+                self.gui.cursor_style(),
+            );
+        }
+
+        self.upload_belt.finish();
+        Ok((StateTransition::KeepCurrent, encoder.finish()))
+    }
+
+    fn handle_window_event(&mut self, _: winit::event::WindowEvent, _: &InputState) {}
+
+    fn handle_mouse_motion(&mut self, _: &Settings, _: (f64, f64)) {}
+
+    fn handle_cursor_movement(&mut self, logical_position: winit::dpi::LogicalPosition<f64>) {
+        let (x, y) = logical_position.into();
+        self.gui.update_mouse_position(x, y);
+    }
+
+    fn handle_mouse_state_changes(
+        &mut self,
+        _: Vec<(winit::event::MouseButton, winit::event::ElementState)>,
+    ) {
+    }
+
+    fn handle_key_state_changes(&mut self, _: Vec<(u32, winit::event::ElementState)>) {}
+}
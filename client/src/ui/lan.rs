@@ -0,0 +1,281 @@
+use anyhow::Result;
+use iced_wgpu::{button, Renderer};
+use iced_winit::{program, Align, Column, Command, Element, HorizontalAlignment, Length, Text};
+use winit::event::ModifiersState;
+
+use crate::{
+    fps::FpsCounter,
+    gui::theme::load_theme,
+    input::InputState,
+    render::{iced::IcedRenderer, UiRenderer, UploadBelt},
+    settings::Settings,
+    singleplayer::SinglePlayer,
+    ui::{error::ErrorScreen, mainmenu::MainMenu},
+    window::{State, StateTransition, WindowBuffers, WindowData, WindowFlags},
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use voxel_rs_common::{data::Data, network::lan::LanClient};
+use voxel_rs_network::LanListener;
+
+/// State of the list of the worlds opened to LAN, that the player can join
+pub struct LanMenu {
+    fps_counter: FpsCounter,
+    ui_renderer: IcedRenderer<LanMenuControls, Message>,
+    /// Draws the cursor over the menu
+    cursor_renderer: UiRenderer,
+    upload_belt: UploadBelt,
+    /// The data loaded by the loading screen, used to play in the joined world
+    data: Arc<Data>,
+}
+
+impl LanMenu {
+    pub fn new_factory(data: Arc<Data>) -> crate::window::StateFactory {
+        Box::new(move |device, settings, window_data, modifiers_state| {
+            Self::new(device, settings, window_data, modifiers_state, data)
+        })
+    }
+
+    pub fn new(
+        device: &mut wgpu::Device,
+        settings: &Settings,
+        window_data: &WindowData,
+        modifiers_state: &ModifiersState,
+        data: Arc<Data>,
+    ) -> Result<(Box<dyn State>, wgpu::CommandBuffer)> {
+        log::info!("Initializing LAN menu");
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("lan_menu_encoder"),
+        });
+        let ui_renderer =
+            IcedRenderer::new(LanMenuControls::new(), device, window_data, modifiers_state);
+        let mut cursor_renderer = UiRenderer::new(device)?;
+        cursor_renderer.load_cursor(device, &mut encoder, &load_theme(&settings.theme).cursor);
+
+        Ok((
+            Box::new(Self {
+                fps_counter: FpsCounter::new(),
+                ui_renderer,
+                cursor_renderer,
+                upload_belt: UploadBelt::new(),
+                data,
+            }),
+            encoder.finish(),
+        ))
+    }
+
+    fn join(&self, address: SocketAddr) -> crate::window::StateFactory {
+        log::info!("Joining the world at {}", address);
+        match LanClient::connect(address, (*self.data).clone()) {
+            Ok(client) => SinglePlayer::new_factory(Box::new(client), self.data.clone(), false),
+            Err(e) => ErrorScreen::new_factory("Couldn't join the world".to_owned(), e),
+        }
+    }
+}
+
+impl State for LanMenu {
+    fn update(
+        &mut self,
+        settings: &mut Settings,
+        _input_state: &InputState,
+        _data: &WindowData,
+        flags: &mut WindowFlags,
+        _seconds_delta: f64,
+        _device: &mut wgpu::Device,
+    ) -> Result<StateTransition> {
+        flags.grab_cursor = false;
+        flags.software_cursor = settings.software_cursor && self.cursor_renderer.has_cursor();
+
+        // Listen to the announcements of the worlds
+        self.ui_renderer.state.queue_message(Message::Refresh);
+
+        let program = self.ui_renderer.state.program();
+        if program.should_go_back {
+            Ok(StateTransition::ReplaceCurrent(MainMenu::new_factory(
+                self.data.clone(),
+            )))
+        } else if let Some(address) = program.world_to_join {
+            Ok(StateTransition::ReplaceCurrent(self.join(address)))
+        } else {
+            Ok(StateTransition::KeepCurrent)
+        }
+    }
+
+    fn render<'a>(
+        &mut self,
+        settings: &Settings,
+        buffers: WindowBuffers<'a>,
+        device: &mut wgpu::Device,
+        window_data: &WindowData,
+        _input_state: &InputState,
+    ) -> Result<(StateTransition, wgpu::CommandBuffer)> {
+        self.fps_counter.add_frame();
+        self.ui_renderer.update(window_data);
+
+        // Initialize encoder and clear buffers.
+        self.upload_belt.recall(device);
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        crate::render::clear_color_and_depth(&mut encoder, buffers);
+
+        // Render Iced UI
+        self.ui_renderer
+            .render(device, buffers, &mut encoder, &mut self.upload_belt, None);
+        if settings.software_cursor {
+            let position = self.ui_renderer.cursor_position;
+            self.cursor_renderer.render_cursor(
+                buffers,
+                device,
+                &mut encoder,
+                &mut self.upload_belt,
+                window_data,
+                (position.x as f32, position.y as f32),
+                self.ui_renderer.cursor_style(),
+            );
+        }
+        self.upload_belt.finish();
+
+        Ok((StateTransition::KeepCurrent, encoder.finish()))
+    }
+
+    fn handle_window_event(&mut self, event: winit::event::WindowEvent, _input_state: &InputState) {
+        self.ui_renderer.handle_window_event(event);
+    }
+
+    fn handle_cursor_movement(&mut self, logical_position: winit::dpi::LogicalPosition<f64>) {
+        self.ui_renderer.handle_cursor_movement(logical_position);
+    }
+
+    fn handle_mouse_motion(&mut self, _: &Settings, _: (f64, f64)) {}
+
+    fn handle_mouse_state_changes(
+        &mut self,
+        _: Vec<(winit::event::MouseButton, winit::event::ElementState)>,
+    ) {
+    }
+
+    fn handle_key_state_changes(&mut self, _: Vec<(u32, winit::event::ElementState)>) {}
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Message {
+    /// Receive the announcements and update the list of the worlds
+    Refresh,
+    /// Join the world at some index of the list
+    Join(usize),
+    Back,
+}
+
+struct LanMenuControls {
+    /// Receives the announcements of the worlds, or the error if the discovery port couldn't be bound
+    listener: Result<LanListener, String>,
+    /// The announced worlds sorted by name, with the state of their button
+    worlds: Vec<(SocketAddr, String, button::State)>,
+    back_button_state: button::State,
+    pub(self) should_go_back: bool,
+    pub(self) world_to_join: Option<SocketAddr>,
+}
+
+impl LanMenuControls {
+    pub fn new() -> Self {
+        let listener = LanListener::new().map_err(|e| {
+            log::warn!("Failed to listen to the local network: {:?}", e);
+            format!("Couldn't listen to the local network: {}", e)
+        });
+        LanMenuControls {
+            listener,
+            worlds: Vec::new(),
+            back_button_state: button::State::new(),
+            should_go_back: false,
+            world_to_join: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let listener = match &mut self.listener {
+            Ok(listener) => listener,
+            Err(_) => return,
+        };
+        listener.poll();
+        let mut announced: Vec<_> = listener
+            .worlds()
+            .map(|(address, announcement)| (announcement.name.clone(), address))
+            .collect();
+        announced.sort();
+        // Keep the state of the buttons of the worlds that are still announced
+        let mut previous = std::mem::take(&mut self.worlds);
+        self.worlds = announced
+            .into_iter()
+            .map(|(name, address)| {
+                let state = match previous.iter().position(|world| world.0 == address) {
+                    Some(i) => previous.swap_remove(i).2,
+                    None => button::State::new(),
+                };
+                (address, name, state)
+            })
+            .collect();
+    }
+}
+
+impl program::Program for LanMenuControls {
+    type Renderer = iced_wgpu::Renderer;
+    type Message = Message;
+
+    fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Refresh => self.refresh(),
+            Message::Join(i) => {
+                log::debug!("Received UI message: {:?}", message);
+                self.world_to_join = self.worlds.get(i).map(|world| world.0);
+            }
+            Message::Back => {
+                log::debug!("Received UI message: {:?}", message);
+                self.should_go_back = true;
+            }
+        }
+
+        Command::none()
+    }
+
+    fn view(&mut self) -> Element<Message, Renderer> {
+        let mut column = Column::new()
+            .padding(60)
+            .width(Length::Fill)
+            .align_items(Align::Center)
+            .spacing(20)
+            .push(Text::new("LAN Worlds").size(40));
+        match &self.listener {
+            Err(error) => column = column.push(Text::new(error.clone()).size(20)),
+            Ok(_) if self.worlds.is_empty() => {
+                column =
+                    column.push(Text::new("Looking for worlds on the local network...").size(20))
+            }
+            Ok(_) => {}
+        }
+        for (i, (address, name, state)) in self.worlds.iter_mut().enumerate() {
+            column = column.push(
+                button::Button::new(
+                    state,
+                    Text::new(format!("{} ({})", name, address))
+                        .size(30)
+                        .horizontal_alignment(HorizontalAlignment::Center),
+                )
+                .width(Length::Units(600))
+                .on_press(Message::Join(i)),
+            );
+        }
+        column
+            .push(
+                button::Button::new(
+                    &mut self.back_button_state,
+                    Text::new("Back")
+                        .size(30)
+                        .horizontal_alignment(HorizontalAlignment::Center),
+                )
+                .width(Length::Units(300))
+                .on_press(Message::Back),
+            )
+            .into()
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Result};
+use std::sync::{
+    mpsc::{self, Receiver, TryRecvError},
+    Arc,
+};
+
+use crate::{
+    gui::{theme::load_theme, Gui},
+    input::InputState,
+    render::{UiRenderer, UploadBelt},
+    settings::Settings,
+    ui::mainmenu::MainMenu,
+    window::{State, StateTransition, WindowBuffers, WindowData, WindowFlags},
+};
+use voxel_rs_common::{
+    content::DefaultContent,
+    data::{load_data_with_progress, Data, DataErrors, LoadingProgress},
+};
+
+/// State of the loading screen, shown while the data is loaded on a background thread before the main menu.
+/// If the data can't be loaded, it shows every error instead.
+pub struct LoadingScreen {
+    progress: Arc<LoadingProgress>,
+    loaded_data: Receiver<Result<Data>>,
+    /// The lines of the error screen, if the data couldn't be loaded
+    errors: Option<Vec<String>>,
+    gui: Gui,
+    ui_renderer: UiRenderer,
+    upload_belt: UploadBelt,
+}
+
+impl LoadingScreen {
+    pub fn new_factory() -> crate::window::StateFactory {
+        Box::new(move |device, settings, _window_data, _modifiers_state| {
+            Self::new(device, settings)
+        })
+    }
+
+    pub fn new(
+        device: &mut wgpu::Device,
+        settings: &mut Settings,
+    ) -> Result<(Box<dyn State>, wgpu::CommandBuffer)> {
+        log::info!("Loading data in the background");
+        let progress = Arc::new(LoadingProgress::new());
+        let (sender, loaded_data) = mpsc::channel();
+        {
+            let progress = progress.clone();
+            std::thread::spawn(move || {
+                // The loading screen may already be closed
+                let _ = sender.send(load_data_with_progress(
+                    "data".into(),
+                    &DefaultContent,
+                    &progress,
+                ));
+            });
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("loading_screen_encoder"),
+        });
+        let gui = Gui::new(load_theme(&settings.theme));
+        let mut ui_renderer = UiRenderer::new(device)?;
+        ui_renderer.load_cursor(device, &mut encoder, &gui.theme().cursor);
+        Ok((
+            Box::new(Self {
+                progress,
+                loaded_data,
+                errors: None,
+                gui,
+                ui_renderer,
+                upload_belt: UploadBelt::new(),
+            }),
+            encoder.finish(),
+        ))
+    }
+}
+
+impl State for LoadingScreen {
+    fn update(
+        &mut self,
+        settings: &mut Settings,
+        _input_state: &InputState,
+        _data: &WindowData,
+        flags: &mut WindowFlags,
+        _seconds_delta: f64,
+        _device: &mut wgpu::Device,
+    ) -> Result<StateTransition> {
+        flags.grab_cursor = false;
+        flags.software_cursor = settings.software_cursor && self.ui_renderer.has_cursor();
+        if self.errors.is_some() {
+            return Ok(StateTransition::KeepCurrent);
+        }
+
+        match self.loaded_data.try_recv() {
+            Ok(Ok(data)) => {
+                log::info!("Data loaded, opening the main menu");
+                Ok(StateTransition::ReplaceCurrent(MainMenu::new_factory(
+                    Arc::new(data),
+                )))
+            }
+            Ok(Err(e)) => {
+                log::error!("Failed to load the data: {:?}", e);
+                let lines = match e.downcast_ref::<DataErrors>() {
+                    Some(data_errors) => data_errors
+                        .errors
+                        .iter()
+                        .map(|error| format!("{}: {}", error.file.display(), error.message))
+                        .collect(),
+                    None => e.chain().map(|cause| cause.to_string()).collect(),
+                };
+                self.errors = Some(lines);
+                Ok(StateTransition::KeepCurrent)
+            }
+            Err(TryRecvError::Empty) => Ok(StateTransition::KeepCurrent),
+            Err(TryRecvError::Disconnected) => Err(anyhow!("The data loading thread panicked")),
+        }
+    }
+
+    fn render<'a>(
+        &mut self,
+        settings: &Settings,
+        buffers: WindowBuffers<'a>,
+        device: &mut wgpu::Device,
+        window_data: &WindowData,
+        _input_state: &InputState,
+    ) -> Result<(StateTransition, wgpu::CommandBuffer)> {
+        self.upload_belt.recall(device);
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        crate::render::clear_color_and_depth(&mut encoder, buffers);
+
+        self.gui.prepare();
+        let gui_size = window_data.gui_size();
+        match self.errors.as_ref() {
+            Some(errors) => crate::gui::loading::render_error_screen(
+                &mut self.gui,
+                gui_size.width as i32,
+                gui_size.height as i32,
+                format!("The data couldn't be loaded: {} error(s)", errors.len()),
+                errors,
+            ),
+            None => crate::gui::loading::render_loading_screen(
+                &mut self.gui,
+                gui_size.width as i32,
+                gui_size.height as i32,
+                format!(
+                    "Loading data: {} / {} files",
+                    self.progress.files_processed(),
+                    self.progress.files_discovered()
+                ),
+                self.progress.fraction(),
+            ),
+        }
+        self.gui.finish();
+        self.ui_renderer.render(
+            buffers,
+            device,
+            &mut encoder,
+            &mut self.upload_belt,
+            window_data,
+            &mut self.gui,
+            false,
+        );
+        if settings.software_cursor {
+            let (x, y) = self.gui.mouse_position();
+            self.ui_renderer.render_cursor(
+                buffers,
+                device,
+                &mut encoder,
+                &mut self.upload_belt,
+                window_data,
+                (x as f32, y as f32),
+                self.gui.cursor_style(),
+            );
+        }
+
+        self.upload_belt.finish();
+        Ok((StateTransition::KeepCurrent, encoder.finish()))
+    }
+
+    fn handle_window_event(&mut self, _: winit::event::WindowEvent, _: &InputState) {}
+
+    fn handle_mouse_motion(&mut self, _: &Settings, _: (f64, f64)) {}
+
+    fn handle_cursor_movement(&mut self, logical_position: winit::dpi::LogicalPosition<f64>) {
+        let (x, y) = logical_position.into();
+        self.gui.update_mouse_position(x, y);
+    }
+
+    fn handle_mouse_state_changes(
+        &mut self,
+        _: Vec<(winit::event::MouseButton, winit::event::ElementState)>,
+    ) {
+    }
+
+    fn handle_key_state_changes(&mut self, _: Vec<(u32, winit::event::ElementState)>) {}
+}
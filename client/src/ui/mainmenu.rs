@@ -5,37 +5,50 @@ use winit::event::ModifiersState;
 
 use crate::{
     fps::FpsCounter,
+    gui::theme::load_theme,
     input::InputState,
-    render::iced::IcedRenderer,
+    render::{iced::IcedRenderer, UiRenderer, UploadBelt},
     settings::Settings,
     singleplayer::SinglePlayer,
+    ui::lan::LanMenu,
     window::{State, StateFactory, StateTransition, WindowBuffers, WindowData, WindowFlags},
 };
-use voxel_rs_common::network::dummy;
-use voxel_rs_server::launch_server;
+use std::sync::Arc;
+use voxel_rs_common::{
+    data::Data,
+    network::{dummy, emulation::EmulatedClient, lan::IntegratedServer, Client},
+};
+use voxel_rs_server::launch_server_with_data;
 
 /// State of the main menu
 pub struct MainMenu {
     fps_counter: FpsCounter,
     ui_renderer: IcedRenderer<MainMenuControls, Message>,
+    /// Draws the cursor over the menu
+    cursor_renderer: UiRenderer,
+    upload_belt: UploadBelt,
+    /// The data loaded by the loading screen, given to the integrated server
+    data: Arc<Data>,
 }
 
 impl MainMenu {
-    pub fn new_factory() -> crate::window::StateFactory {
-        Box::new(move |device, _settings, window_data, modifiers_state| {
-            Self::new(device, window_data, modifiers_state)
+    pub fn new_factory(data: Arc<Data>) -> crate::window::StateFactory {
+        Box::new(move |device, settings, window_data, modifiers_state| {
+            Self::new(device, settings, window_data, modifiers_state, data)
         })
     }
 
     pub fn new(
         device: &mut wgpu::Device,
+        settings: &Settings,
         window_data: &WindowData,
         modifiers_state: &ModifiersState,
+        data: Arc<Data>,
     ) -> Result<(Box<dyn State>, wgpu::CommandBuffer)> {
         log::info!("Initializing main menu");
 
         // Create the renderers
-        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("main_menu_encoder"),
         });
         let ui_renderer = IcedRenderer::new(
@@ -44,21 +57,32 @@ impl MainMenu {
             window_data,
             modifiers_state,
         );
+        let mut cursor_renderer = UiRenderer::new(device)?;
+        cursor_renderer.load_cursor(device, &mut encoder, &load_theme(&settings.theme).cursor);
 
         Ok((
             Box::new(Self {
                 fps_counter: FpsCounter::new(),
                 ui_renderer,
+                cursor_renderer,
+                upload_belt: UploadBelt::new(),
+                data,
             }),
             encoder.finish(),
         ))
     }
 
-    fn start_single_player(&mut self) -> Box<StateFactory> {
+    fn start_single_player(&mut self, settings: &Settings) -> Box<StateFactory> {
         let (client, server) = dummy::new();
+        let client: Box<dyn Client> = match settings.net_emulation {
+            Some(conditions) => Box::new(EmulatedClient::new(Box::new(client), conditions)),
+            None => Box::new(client),
+        };
 
+        let game_data = (*self.data).clone();
         std::thread::spawn(move || {
-            if let Err(e) = launch_server(Box::new(server)) {
+            let server = IntegratedServer::new(server);
+            if let Err(e) = launch_server_with_data(Box::new(server), game_data) {
                 // TODO: rewrite this error reporting
                 log::error!(
                     "Error happened in the server code: {}\nPrinting chain:\n{}",
@@ -72,14 +96,14 @@ impl MainMenu {
             }
         });
 
-        Box::new(SinglePlayer::new_factory(Box::new(client)))
+        Box::new(SinglePlayer::new_factory(client, self.data.clone(), true))
     }
 }
 
 impl State for MainMenu {
     fn update(
         &mut self,
-        _settings: &mut Settings,
+        settings: &mut Settings,
         _input_state: &InputState,
         _data: &WindowData,
         flags: &mut WindowFlags,
@@ -87,11 +111,18 @@ impl State for MainMenu {
         _device: &mut wgpu::Device,
     ) -> Result<StateTransition> {
         flags.grab_cursor = false;
+        flags.software_cursor = settings.software_cursor && self.cursor_renderer.has_cursor();
 
         if self.ui_renderer.state.program().should_exit {
             Ok(StateTransition::CloseWindow)
         } else if self.ui_renderer.state.program().should_start_single_player {
-            Ok(StateTransition::ReplaceCurrent(self.start_single_player()))
+            Ok(StateTransition::ReplaceCurrent(
+                self.start_single_player(settings),
+            ))
+        } else if self.ui_renderer.state.program().should_show_lan_worlds {
+            Ok(StateTransition::ReplaceCurrent(LanMenu::new_factory(
+                self.data.clone(),
+            )))
         } else {
             Ok(StateTransition::KeepCurrent)
         }
@@ -99,7 +130,7 @@ impl State for MainMenu {
 
     fn render<'a>(
         &mut self,
-        _settings: &Settings,
+        settings: &Settings,
         buffers: WindowBuffers<'a>,
         device: &mut wgpu::Device,
         window_data: &WindowData,
@@ -109,12 +140,27 @@ impl State for MainMenu {
         self.ui_renderer.update(window_data);
 
         // Initialize encoder and clear buffers.
+        self.upload_belt.recall(device);
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
         crate::render::clear_color_and_depth(&mut encoder, buffers);
 
         // Render Iced UI
-        self.ui_renderer.render(device, buffers, &mut encoder, None);
+        self.ui_renderer
+            .render(device, buffers, &mut encoder, &mut self.upload_belt, None);
+        if settings.software_cursor {
+            let position = self.ui_renderer.cursor_position;
+            self.cursor_renderer.render_cursor(
+                buffers,
+                device,
+                &mut encoder,
+                &mut self.upload_belt,
+                window_data,
+                (position.x as f32, position.y as f32),
+                self.ui_renderer.cursor_style(),
+            );
+        }
+        self.upload_belt.finish();
 
         Ok((StateTransition::KeepCurrent, encoder.finish()))
     }
@@ -141,6 +187,7 @@ impl State for MainMenu {
 #[derive(Debug, Clone, Copy)]
 enum Message {
     StartSinglePlayer,
+    ShowLanWorlds,
     ExitGame,
 }
 
@@ -150,6 +197,8 @@ struct MainMenuControls {
     pub(self) should_exit: bool,
     start_single_player_button_state: button::State,
     pub(self) should_start_single_player: bool,
+    lan_worlds_button_state: button::State,
+    pub(self) should_show_lan_worlds: bool,
 }
 
 impl MainMenuControls {
@@ -159,6 +208,8 @@ impl MainMenuControls {
             should_exit: false,
             start_single_player_button_state: button::State::new(),
             should_start_single_player: false,
+            lan_worlds_button_state: button::State::new(),
+            should_show_lan_worlds: false,
         }
     }
 }
@@ -171,6 +222,7 @@ impl program::Program for MainMenuControls {
         log::debug!("Received UI message: {:?}", message);
         match message {
             Message::StartSinglePlayer => self.should_start_single_player = true,
+            Message::ShowLanWorlds => self.should_show_lan_worlds = true,
             Message::ExitGame => self.should_exit = true,
         }
 
@@ -193,6 +245,16 @@ impl program::Program for MainMenuControls {
                 .width(Length::Units(300))
                 .on_press(Message::StartSinglePlayer),
             )
+            .push(
+                button::Button::new(
+                    &mut self.lan_worlds_button_state,
+                    Text::new("LAN Worlds")
+                        .size(30)
+                        .horizontal_alignment(HorizontalAlignment::Center),
+                )
+                .width(Length::Units(300))
+                .on_press(Message::ShowLanWorlds),
+            )
             .push(
                 button::Button::new(
                     &mut self.exit_button_state,
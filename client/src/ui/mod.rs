@@ -1,5 +1,8 @@
 use wgpu_glyph::ab_glyph::PxScale;
 
+pub mod error;
+pub mod lan;
+pub mod loading;
 pub mod mainmenu;
 pub mod pausemenu;
 pub mod widgets;
@@ -30,6 +33,25 @@ pub struct TrianglesPrimitive {
     pub color: [f32; 4],
 }
 
+/// A rectangle of the icon atlas, drawn in the rectangle `layout` of the screen
+#[derive(Debug, Clone)]
+pub struct TexturePrimitive {
+    pub layout: quint::Layout,
+    /// The rectangle `[x, y, width, height]` of the atlas, in texture coordinates
+    pub uv: [f32; 4],
+    pub z: f32,
+}
+
+/// A floating panel drawn on top of everything else.
+/// Its size is measured from the text at render time, and it is clamped to the screen edges.
+#[derive(Debug, Clone)]
+pub struct TooltipPrimitive {
+    /// Anchor of the tooltip, usually the mouse position
+    pub x: i32,
+    pub y: i32,
+    pub lines: Vec<TextPart>,
+}
+
 #[derive(Debug, Clone)]
 pub struct TextPart {
     pub text: String,
@@ -43,6 +65,8 @@ pub struct PrimitiveBuffer {
     pub rectangle: Vec<RectanglePrimitive>,
     pub text: Vec<TextPrimitive>,
     pub triangles: Vec<TrianglesPrimitive>,
+    pub textures: Vec<TexturePrimitive>,
+    pub tooltips: Vec<TooltipPrimitive>,
 }
 
 impl PrimitiveBuffer {
@@ -104,6 +128,23 @@ impl PrimitiveBuffer {
         });
     }
 
+    pub fn draw_texture(&mut self, x: i32, y: i32, w: i32, h: i32, uv: [f32; 4], z: f32) {
+        self.textures.push(TexturePrimitive {
+            layout: quint::Layout {
+                x: x as f32,
+                y: y as f32,
+                width: w as f32,
+                height: h as f32,
+            },
+            uv,
+            z,
+        });
+    }
+
+    pub fn draw_tooltip(&mut self, x: i32, y: i32, lines: Vec<TextPart>) {
+        self.tooltips.push(TooltipPrimitive { x, y, lines });
+    }
+
     pub fn draw_triangles(&mut self, vertices: Vec<[f32; 3]>, indices: Vec<u32>, color: [f32; 4]) {
         self.triangles.push(TrianglesPrimitive {
             vertices,
@@ -6,6 +6,7 @@ use iced_winit::{
 #[derive(Debug, Clone, Copy)]
 pub enum Message {
     ResumeGame,
+    OpenToLan,
     ExitGame,
 }
 
@@ -15,15 +16,22 @@ pub struct PauseMenuControls {
     pub should_exit: bool,
     resume_button_state: button::State,
     pub should_resume: bool,
+    /// Whether the button to open the world to LAN is shown, only to the host of a world that is not open yet
+    can_open_to_lan: bool,
+    open_to_lan_button_state: button::State,
+    pub should_open_to_lan: bool,
 }
 
 impl PauseMenuControls {
-    pub fn new() -> Self {
+    pub fn new(can_open_to_lan: bool) -> Self {
         PauseMenuControls {
             exit_button_state: button::State::new(),
             should_exit: false,
             resume_button_state: button::State::new(),
             should_resume: false,
+            can_open_to_lan,
+            open_to_lan_button_state: button::State::new(),
+            should_open_to_lan: false,
         }
     }
 }
@@ -36,6 +44,7 @@ impl Program for PauseMenuControls {
         log::debug!("Received UI message: {:?}", message);
         match message {
             Message::ResumeGame => self.should_resume = true,
+            Message::OpenToLan => self.should_open_to_lan = true,
             Message::ExitGame => self.should_exit = true,
         }
 
@@ -43,7 +52,7 @@ impl Program for PauseMenuControls {
     }
 
     fn view(&mut self) -> Element<Message, Renderer> {
-        Column::new()
+        let mut column = Column::new()
             .padding(60)
             .width(Length::Fill)
             .align_items(Align::Center)
@@ -57,7 +66,20 @@ impl Program for PauseMenuControls {
                 )
                 .width(Length::Units(300))
                 .on_press(Message::ResumeGame),
-            )
+            );
+        if self.can_open_to_lan {
+            column = column.push(
+                button::Button::new(
+                    &mut self.open_to_lan_button_state,
+                    Text::new("Open to LAN")
+                        .size(30)
+                        .horizontal_alignment(HorizontalAlignment::Center),
+                )
+                .width(Length::Units(300))
+                .on_press(Message::OpenToLan),
+            );
+        }
+        column
             .push(
                 button::Button::new(
                     &mut self.exit_button_state,
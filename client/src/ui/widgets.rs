@@ -1,5 +1,18 @@
 use super::{PrimitiveBuffer, TextPart};
-use quint::{Event, Layout, Position, Style, Widget};
+use quint::{AnimatedStyle, Color, Event, Layout, Position, Style, Widget};
+
+/// Color of the buttons whose style doesn't set a background
+const DEFAULT_BUTTON_COLOR: Color = [0.8, 0.2, 0.2, 1.0];
+
+/// Multiply the RGB components of a color by `factor`, to get a darker or lighter shade
+fn shade(color: Color, factor: f32) -> Color {
+    [
+        (color[0] * factor).min(1.0),
+        (color[1] * factor).min(1.0),
+        (color[2] * factor).min(1.0),
+        color[3],
+    ]
+}
 
 pub struct Text {
     pub text: Vec<TextPart>,
@@ -23,7 +36,13 @@ impl<T> Widget<PrimitiveBuffer, T> for Text {
         Style::default().percent_size(1.0, 1.0)
     }
 
-    fn render(&self, buffer: &mut PrimitiveBuffer, _cursor_position: Position, layout: Layout) {
+    fn render(
+        &self,
+        buffer: &mut PrimitiveBuffer,
+        _cursor_position: Position,
+        layout: Layout,
+        _animated: &AnimatedStyle,
+    ) {
         //buffer.draw_text(self.text.clone(), layout, 0.0, false);
     }
 }
@@ -42,9 +61,15 @@ where
         self.style.clone()
     }
 
-    fn render(&self, buffer: &mut PrimitiveBuffer, cursor_position: Position, mut l: Layout) {
+    fn render(
+        &self,
+        buffer: &mut PrimitiveBuffer,
+        _cursor_position: Position,
+        mut l: Layout,
+        animated: &AnimatedStyle,
+    ) {
         log::info!("Rendering button at {:?}", l);
-        let hovering = l.is_position_inside(cursor_position);
+        let hovering = animated.hovered;
         // Padded Layout
         let mut pl = l.with_padding(6.0);
         if hovering {
@@ -52,21 +77,10 @@ where
             pl.y += 2.0;
         }
 
-        let main_color = if hovering {
-            [0.75, 0.22, 0.22, 1.0]
-        } else {
-            [0.8, 0.2, 0.2, 1.0]
-        };
-        let dark_shade = if hovering {
-            [0.55, 0.12, 0.12, 1.0]
-        } else {
-            [0.6, 0.1, 0.1, 1.0]
-        };
-        let light_shade = if hovering {
-            [0.95, 0.32, 0.32, 1.0]
-        } else {
-            [1.0, 0.3, 0.3, 1.0]
-        };
+        let mut main_color = animated.background.unwrap_or(DEFAULT_BUTTON_COLOR);
+        main_color[3] *= animated.opacity;
+        let dark_shade = shade(main_color, 0.75);
+        let light_shade = shade(main_color, 1.25);
 
         // Top-left lighter shade
         buffer.draw_triangles(
@@ -1,8 +1,12 @@
-use crate::{input::InputState, settings::Settings};
-use anyhow::Result;
+use crate::render::memory::{self, MemoryCategory};
+use crate::render::RenderInitError;
+use crate::settings::{GpuPowerPreference, Settings};
+use crate::{fps::FpsCounter, input::InputState};
+use anyhow::{anyhow, Context, Result};
 use futures::executor::block_on;
 use log::{info, warn};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use voxel_rs_common::debug::send_debug_info;
 use wgpu::Device;
 use winit::dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize};
 use winit::event::{ElementState, ModifiersState, MouseButton};
@@ -39,8 +43,48 @@ pub struct WindowData {
     pub physical_window_size: PhysicalSize<u32>,
     /// Scale factor of the window (DPI).
     pub scale_factor: f64,
+    /// Scale of the GUI on top of the DPI: the one from the settings, reduced if the window is too small.
+    pub gui_scale: f64,
     /// `true` if the window is currently focused
     pub focused: bool,
+    /// Fraction of a fixed update elapsed since the last update, between 0 and 1. The states render their
+    /// objects at this fraction of the way between their last two updated positions.
+    pub update_interpolation: f64,
+}
+
+/// Smallest size of the window in GUI units. The GUI is scaled down on smaller windows,
+/// so that the widgets never overlap or go off-screen.
+const MIN_GUI_SIZE: (f64, f64) = (640.0, 480.0);
+/// The GUI is never scaled down further, even in a tiny or minimized window
+const MIN_GUI_SCALE: f64 = 0.25;
+
+impl WindowData {
+    /// Size of the window in GUI units, the coordinates used to lay out the HUD and the menus.
+    pub fn gui_size(&self) -> LogicalSize<f64> {
+        LogicalSize::new(
+            self.logical_window_size.width / self.gui_scale,
+            self.logical_window_size.height / self.gui_scale,
+        )
+    }
+
+    /// Number of physical pixels per GUI unit
+    pub fn gui_scale_factor(&self) -> f64 {
+        self.scale_factor * self.gui_scale
+    }
+
+    /// Apply the GUI scale from the settings, clamped so that the GUI fits in the window
+    fn update_gui_scale(&mut self, requested_scale: f64) {
+        let requested_scale = if requested_scale.is_finite() && requested_scale > 0.0 {
+            requested_scale
+        } else {
+            1.0
+        };
+        let max_scale = f64::min(
+            self.logical_window_size.width / MIN_GUI_SIZE.0,
+            self.logical_window_size.height / MIN_GUI_SIZE.1,
+        );
+        self.gui_scale = requested_scale.min(max_scale).max(MIN_GUI_SCALE);
+    }
 }
 
 /// Read-write data of the window that the states can modify.
@@ -48,13 +92,15 @@ pub struct WindowData {
 pub struct WindowFlags {
     /// `true` if the cursor should be hidden and centered.
     pub grab_cursor: bool,
+    /// `true` if the state draws the cursor when it is not grabbed. The system cursor is then hidden.
+    pub software_cursor: bool,
     /// Window title
     pub window_title: String,
 }
 
 /// A window state. It has full control over the rendered content.
 pub trait State {
-    /// Update using the given time delta.
+    /// Update using the given time delta. The updates run at a fixed rate, independently of the frame rate.
     fn update(
         &mut self,
         settings: &mut Settings,
@@ -79,7 +125,7 @@ pub trait State {
     fn handle_window_event(&mut self, event: winit::event::WindowEvent, input_state: &InputState);
     /// Mouse motion
     fn handle_mouse_motion(&mut self, settings: &Settings, delta: (f64, f64));
-    /// Cursor moved
+    /// Cursor moved. The position is in GUI units, see `WindowData::gui_size`.
     fn handle_cursor_movement(&mut self, logical_position: LogicalPosition<f64>);
     /// Mouse clicked
     fn handle_mouse_state_changes(&mut self, changes: Vec<(MouseButton, ElementState)>);
@@ -91,29 +137,165 @@ pub trait State {
 pub const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8Unorm;
 /// Format of the window's depth buffer
 pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
-pub const PRESENT_MODE: wgpu::PresentMode = wgpu::PresentMode::Immediate;
+/// Number of fixed updates of the state per second
+const UPDATE_RATE: f64 = 60.0;
+/// Maximum number of updates before rendering a frame. After a longer hitch, the missed updates are dropped
+/// instead of slowing down the next frames to catch up.
+const MAX_UPDATES_PER_FRAME: u32 = 5;
+
+/// Present mode of the swap chain, waiting for the vertical blank if vsync is on
+pub fn present_mode(vsync: bool) -> wgpu::PresentMode {
+    if vsync {
+        wgpu::PresentMode::Fifo
+    } else {
+        wgpu::PresentMode::Immediate
+    }
+}
+/// Number of consecutive lost swap chains before we give up on the device
+const MAX_SWAP_CHAIN_FAILURES: u32 = 10;
+
+/// Check if two adapter infos describe the same adapter
+fn same_adapter(a: &wgpu::AdapterInfo, b: &wgpu::AdapterInfo) -> bool {
+    a.name == b.name && a.vendor == b.vendor && a.device == b.device && a.backend == b.backend
+}
+
+/// Get the adapters that can draw to the surface. wgpu only checks the surface when it picks the adapter itself,
+/// so it is asked for the adapter of each power preference.
+fn compatible_adapters(instance: &wgpu::Instance, surface: &wgpu::Surface) -> Vec<wgpu::Adapter> {
+    let mut adapters: Vec<wgpu::Adapter> = Vec::new();
+    for &preference in [
+        GpuPowerPreference::LowPower,
+        GpuPowerPreference::HighPerformance,
+    ]
+    .iter()
+    {
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: preference.to_wgpu(),
+            compatible_surface: Some(surface),
+        }));
+        if let Some(adapter) = adapter {
+            if !adapters
+                .iter()
+                .any(|other| same_adapter(&other.get_info(), &adapter.get_info()))
+            {
+                adapters.push(adapter);
+            }
+        }
+    }
+    adapters
+}
+
+/// Log the available adapters and pick one according to the settings, among the adapters that can draw to the
+/// surface. If the adapter configured in the settings can't be found, fall back to the power preference.
+fn select_adapter(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+    settings: &Settings,
+) -> Result<wgpu::Adapter, RenderInitError> {
+    let backends = settings.gpu_backend.to_backend_bit();
+    let adapters = instance.enumerate_adapters(backends).collect::<Vec<_>>();
+    let mut compatible = compatible_adapters(instance, surface);
+    info!("Found {} GPU adapter(s):", adapters.len());
+    for adapter in adapters.iter() {
+        let adapter_info = adapter.get_info();
+        let is_compatible = compatible
+            .iter()
+            .any(|other| same_adapter(&other.get_info(), &adapter_info));
+        info!(
+            "- {} ({:?}, {:?} backend){}",
+            adapter_info.name,
+            adapter_info.device_type,
+            adapter_info.backend,
+            if is_compatible {
+                ""
+            } else {
+                ", can't draw to the window"
+            }
+        );
+    }
+
+    if let Some(requested_name) = &settings.gpu_adapter {
+        let requested_name = requested_name.to_lowercase();
+        let matches_name = |adapter: &wgpu::Adapter| {
+            adapter
+                .get_info()
+                .name
+                .to_lowercase()
+                .contains(&requested_name)
+        };
+        if let Some(index) = compatible.iter().position(matches_name) {
+            let adapter = compatible.swap_remove(index);
+            info!(
+                "Using adapter {} from the settings",
+                adapter.get_info().name
+            );
+            return Ok(adapter);
+        } else if adapters.iter().any(matches_name) {
+            warn!(
+                "The adapter {:?} from the settings can't draw to the window, falling back to the {:?} preference",
+                requested_name, settings.gpu_power_preference
+            );
+        } else {
+            warn!(
+                "Couldn't find the adapter {:?} from the settings, falling back to the {:?} preference",
+                requested_name, settings.gpu_power_preference
+            );
+        }
+    }
 
-/// Open a new window with the given settings and the given initial state
-pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
+    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: settings.gpu_power_preference.to_wgpu(),
+        compatible_surface: Some(surface),
+    }))
+    .ok_or_else(|| RenderInitError::NoAdapter {
+        backend: format!("{:?}", settings.gpu_backend),
+    })?;
+    info!("Using adapter {}", adapter.get_info().name);
+    Ok(adapter)
+}
+
+/// Create a state with its factory. If it fails, the error is shown on an error screen instead.
+/// Only the error screen itself failing stops the game.
+fn create_state(
+    factory: StateFactory,
+    device: &mut Device,
+    settings: &mut Settings,
+    window_data: &WindowData,
+    modifiers_state: &ModifiersState,
+) -> (Box<dyn State>, wgpu::CommandBuffer) {
+    match factory(device, settings, window_data, modifiers_state) {
+        Ok(state) => state,
+        Err(e) => {
+            log::error!("Failed to create the next window state: {:?}", e);
+            let error_screen = crate::ui::error::ErrorScreen::new_factory(
+                "The game couldn't continue".to_owned(),
+                e,
+            );
+            error_screen(device, settings, window_data, modifiers_state)
+                .expect("Failed to create the error screen")
+        }
+    }
+}
+
+/// Open a new window with the given settings and the given initial state.
+/// Only returns if the window or the GPU device couldn't be created.
+pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> Result<()> {
     info!("Opening new window...");
     // Create the window
     let window_title = "voxel-rs".to_owned();
     let event_loop = winit::event_loop::EventLoop::new();
-    let window = Window::new(&event_loop).expect("Failed to create window");
+    let window = Window::new(&event_loop).context("Failed to create the window")?;
     window.set_title(&window_title);
 
     // Create the Surface, i.e. the render target of the program
     let physical_window_size = window.inner_size();
     info!("Creating the swap chain");
-    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    let backends = settings.gpu_backend.to_backend_bit();
+    let instance = wgpu::Instance::new(backends);
     let surface = unsafe { instance.create_surface(&window) };
 
     // Get the Device and the render Queue
-    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance, // TODO: configure this?
-        compatible_surface: Some(&surface),
-    }))
-    .expect("Failed to create adapter");
+    let adapter = select_adapter(&instance, &surface, &settings)?;
     // TODO: device should be immutable
     let (mut device, queue) = block_on(adapter.request_device(
         &wgpu::DeviceDescriptor {
@@ -123,14 +305,16 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
         },
         None,
     ))
-    .expect("Failed to request device");
+    .map_err(|_| RenderInitError::RequestDevice {
+        adapter: adapter.get_info().name,
+    })?;
     // Create the SwapChain
     let mut sc_desc = wgpu::SwapChainDescriptor {
         usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
         format: COLOR_FORMAT,
         width: physical_window_size.width,
         height: physical_window_size.height,
-        present_mode: PRESENT_MODE,
+        present_mode: present_mode(settings.vsync),
     };
     let mut swap_chain = device.create_swap_chain(&surface, &sc_desc);
 
@@ -149,7 +333,8 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
         format: sc_desc.format,
         usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
     };
-    let mut msaa_texture = device.create_texture(&msaa_texture_descriptor);
+    let mut msaa_texture =
+        memory::create_texture(&device, MemoryCategory::Other, &msaa_texture_descriptor);
     let mut msaa_texture_view = msaa_texture.create_view(&texture_view_descriptor);
 
     info!("Creating the depth buffer");
@@ -166,44 +351,64 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
         format: DEPTH_FORMAT,
         usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
     };
-    let mut depth_texture = device.create_texture(&depth_texture_descriptor);
+    let mut depth_texture =
+        memory::create_texture(&device, MemoryCategory::Other, &depth_texture_descriptor);
     let mut depth_texture_view = depth_texture.create_view(&texture_view_descriptor);
 
     let mut window_data = {
         let physical_window_size = window.inner_size();
         let scale_factor = window.scale_factor();
         let logical_window_size = physical_window_size.to_logical(scale_factor);
-        WindowData {
+        let mut window_data = WindowData {
             logical_window_size,
             physical_window_size,
             scale_factor,
+            gui_scale: 1.0,
             focused: true,
-        }
+            update_interpolation: 0.0,
+        };
+        window_data.update_gui_scale(settings.gui_scale);
+        window_data
     };
 
     let mut input_state = InputState::new();
 
     let mut window_flags = WindowFlags {
         grab_cursor: false,
+        software_cursor: false,
         window_title,
     };
 
     info!("Done initializing the window. Moving on to the first state...");
 
-    let (mut state, cmd) = initial_state(
+    let (mut state, cmd) = create_state(
+        initial_state,
         &mut device,
         &mut settings,
         &window_data,
         &input_state._get_modifiers_state(),
-    )
-    .expect("Failed to create initial window state");
+    );
     queue.submit(vec![cmd]);
 
-    let mut previous_time = std::time::Instant::now();
+    let update_step = 1.0 / UPDATE_RATE;
+    let mut previous_time = Instant::now();
+    // Time that the updates are behind, starting with a full step so that the state is updated before the first frame
+    let mut update_lag = update_step;
+    let mut update_counter = FpsCounter::new();
+    let mut frame_counter = FpsCounter::new();
 
     let mut window_resized = false;
+    let mut swap_chain_failures = 0;
+    // True once a GPU failure replaced the state with the error screen. If the GPU fails again, the window closes.
+    let mut showing_gpu_error = false;
     let mut mouse_state_changes = Vec::new();
     let mut key_state_changes = Vec::new();
+    // The position of the cursor after the last movement or after it was centered, to compute the movements
+    // of the cursor when the raw mouse input is disabled
+    let mut last_cursor_position: Option<PhysicalPosition<f64>> = None;
+    // Whether the cursor is grabbed and whether the system cursor is hidden for the software cursor, as last
+    // applied to the window, so that the window is only changed when the state asks for another cursor mode
+    let mut cursor_mode: Option<(bool, bool)> = None;
 
     // Main loop
     event_loop.run(move |event, _, control_flow| {
@@ -233,7 +438,16 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
                         }
                     }
                     CursorMoved { position, .. } => {
-                        state.handle_cursor_movement(position.to_logical(window_data.scale_factor))
+                        if !settings.mouse.raw_input && window_flags.grab_cursor {
+                            if let Some(last) = last_cursor_position {
+                                let delta = (position.x - last.x, position.y - last.y);
+                                state.handle_mouse_motion(&settings, delta);
+                            }
+                        }
+                        last_cursor_position = Some(position);
+                        state.handle_cursor_movement(
+                            position.to_logical(window_data.gui_scale_factor()),
+                        )
                     }
                     CursorEntered { .. } | CursorLeft { .. } | MouseWheel { .. } => (),
                     MouseInput {
@@ -260,7 +474,9 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
                 }
                 use winit::event::DeviceEvent::*;
                 match event {
-                    MouseMotion { delta } => state.handle_mouse_motion(&settings, delta),
+                    MouseMotion { delta } if settings.mouse.raw_input => {
+                        state.handle_mouse_motion(&settings, delta)
+                    }
                     _ => (),
                 }
             }
@@ -282,59 +498,91 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
                     // Update depth buffer
                     depth_texture_descriptor.size.width = sc_desc.width;
                     depth_texture_descriptor.size.height = sc_desc.height;
-                    depth_texture = device.create_texture(&depth_texture_descriptor);
+                    depth_texture = memory::create_texture(
+                        &device,
+                        MemoryCategory::Other,
+                        &depth_texture_descriptor,
+                    );
                     depth_texture_view = depth_texture.create_view(&texture_view_descriptor);
                     // Udate MSAA frame buffer
                     msaa_texture_descriptor.size.width = sc_desc.width;
                     msaa_texture_descriptor.size.height = sc_desc.height;
-                    msaa_texture = device.create_texture(&msaa_texture_descriptor);
+                    msaa_texture = memory::create_texture(
+                        &device,
+                        MemoryCategory::Other,
+                        &msaa_texture_descriptor,
+                    );
                     msaa_texture_view = msaa_texture.create_view(&texture_view_descriptor);
                 }
                 window_resized = false;
+                // The settings can change at any time
+                window_data.update_gui_scale(settings.gui_scale);
+                if sc_desc.present_mode != present_mode(settings.vsync) {
+                    sc_desc.present_mode = present_mode(settings.vsync);
+                    swap_chain = device.create_swap_chain(&surface, &sc_desc);
+                }
 
                 // Update state
                 let (v1, v2) = (Vec::new(), Vec::new()); // TODO: clean up
                 state.handle_mouse_state_changes(std::mem::replace(&mut mouse_state_changes, v1));
                 state.handle_key_state_changes(std::mem::replace(&mut key_state_changes, v2));
-                let seconds_delta = {
-                    let current_time = Instant::now();
-                    let delta = current_time - previous_time;
-                    previous_time = current_time;
-                    delta.as_secs() as f64 + delta.subsec_nanos() as f64 / 1e9
-                };
-                let state_transition = state
-                    .update(
-                        &mut settings,
-                        &input_state,
-                        &window_data,
-                        &mut window_flags,
-                        seconds_delta,
-                        &mut device,
-                    )
-                    .expect("Failed to `update` the current window state"); // TODO: remove this
+                let frame_start = Instant::now();
+                update_lag += (frame_start - previous_time).as_secs_f64();
+                previous_time = frame_start;
+                let mut state_transition = StateTransition::KeepCurrent;
+                let mut updates = 0;
+                while update_lag >= update_step && updates < MAX_UPDATES_PER_FRAME {
+                    state_transition = state
+                        .update(
+                            &mut settings,
+                            &input_state,
+                            &window_data,
+                            &mut window_flags,
+                            update_step,
+                            &mut device,
+                        )
+                        .expect("Failed to `update` the current window state"); // TODO: remove this
+                    update_lag -= update_step;
+                    updates += 1;
+                    update_counter.add_frame();
+                    if !matches!(state_transition, StateTransition::KeepCurrent) {
+                        break;
+                    }
+                }
+                update_lag = update_lag.min(update_step);
+                window_data.update_interpolation = update_lag / update_step;
 
                 // Update window flags
                 window.set_title(&window_flags.window_title);
-                if window_flags.grab_cursor && window_data.focused {
-                    window.set_cursor_visible(false);
-                    let PhysicalSize { width, height } = window_data.physical_window_size;
-                    match window.set_cursor_grab(true) {
-                        Err(err) => log::warn!("Failed to grab cursor ({:?})", err),
+                let grab_cursor = window_flags.grab_cursor && window_data.focused;
+                let new_cursor_mode = (grab_cursor, window_flags.software_cursor);
+                let PhysicalSize { width, height } = window_data.physical_window_size;
+                let center_pos = PhysicalPosition {
+                    x: width / 2,
+                    y: height / 2,
+                };
+                if cursor_mode != Some(new_cursor_mode) {
+                    let was_grabbed = cursor_mode.map_or(false, |(grabbed, _)| grabbed);
+                    cursor_mode = Some(new_cursor_mode);
+                    window.set_cursor_visible(!grab_cursor && !window_flags.software_cursor);
+                    match window.set_cursor_grab(grab_cursor) {
+                        Err(err) if grab_cursor => warn!("Failed to grab cursor ({:?})", err),
+                        Err(err) => warn!("Failed to ungrab cursor ({:?})", err),
                         _ => (),
                     }
-                    let center_pos = PhysicalPosition {
-                        x: width / 2,
-                        y: height / 2,
-                    };
+                    if was_grabbed && !grab_cursor {
+                        // The released cursor starts at the crosshair, even before the mouse moves
+                        let center_pos = center_pos.cast::<f64>();
+                        last_cursor_position = Some(center_pos);
+                        state.handle_cursor_movement(
+                            center_pos.to_logical(window_data.gui_scale_factor()),
+                        );
+                    }
+                }
+                if grab_cursor {
                     match window.set_cursor_position(center_pos) {
                         Err(err) => log::trace!("Failed to center cursor ({:?})", err),
-                        _ => (),
-                    }
-                } else {
-                    window.set_cursor_visible(true);
-                    match window.set_cursor_grab(false) {
-                        Err(err) => warn!("Failed to ungrab cursor ({:?})", err),
-                        _ => (),
+                        _ => last_cursor_position = Some(center_pos.cast()),
                     }
                 }
 
@@ -344,14 +592,20 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
                     StateTransition::ReplaceCurrent(new_state) => {
                         info!("Transitioning to a new window state...");
                         window_resized = true; // TODO: Fix
-                        let (new_state, cmd) = new_state(
+                        let (new_state, cmd) = create_state(
+                            new_state,
                             &mut device,
                             &mut settings,
                             &window_data,
                             &input_state._get_modifiers_state(),
-                        )
-                        .expect("Failed to create next window state");
+                        );
                         state = new_state;
+                        // The new state draws its cursor where the mouse is, even before it moves
+                        if let Some(position) = last_cursor_position {
+                            state.handle_cursor_movement(
+                                position.to_logical(window_data.gui_scale_factor()),
+                            );
+                        }
                         queue.submit(vec![cmd]);
                         return;
                     }
@@ -361,8 +615,10 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
                 }
 
                 // Render frame
+                let mut gpu_error = None;
                 match swap_chain.get_current_frame() {
                     Ok(swap_chain_output) => {
+                        swap_chain_failures = 0;
                         let (state_transition, commands) = state
                             .render(
                                 &settings,
@@ -380,14 +636,20 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
                         match state_transition {
                             StateTransition::KeepCurrent => (),
                             StateTransition::ReplaceCurrent(new_state) => {
-                                let (new_state, cmd) = new_state(
+                                let (new_state, cmd) = create_state(
+                                    new_state,
                                     &mut device,
                                     &mut settings,
                                     &window_data,
                                     &input_state._get_modifiers_state(),
-                                )
-                                .expect("Failed to create next window state");
+                                );
                                 state = new_state;
+                                // The new state draws its cursor where the mouse is, even before it moves
+                                if let Some(position) = last_cursor_position {
+                                    state.handle_cursor_movement(
+                                        position.to_logical(window_data.gui_scale_factor()),
+                                    );
+                                }
                                 queue.submit(vec![cmd]);
                             }
                             StateTransition::CloseWindow => {
@@ -397,9 +659,75 @@ pub fn open_window(mut settings: Settings, initial_state: StateFactory) -> ! {
                     }
                     Err(e) => match e {
                         wgpu::SwapChainError::Outdated => window_resized = true,
-                        e => log::error!("Couldn't get current frame from swapchain: {:?}", e),
+                        wgpu::SwapChainError::Lost => {
+                            // The surface was lost, for example because the GPU was reset or the
+                            // window moved to another display. Recreating the swap chain is enough.
+                            swap_chain_failures += 1;
+                            warn!(
+                                "Swap chain lost ({} consecutive failures), recreating it",
+                                swap_chain_failures
+                            );
+                            window_resized = true;
+                        }
+                        wgpu::SwapChainError::Timeout => {
+                            log::debug!("Timed out while acquiring the next swap chain frame")
+                        }
+                        wgpu::SwapChainError::OutOfMemory => {
+                            gpu_error = Some(anyhow!("The GPU ran out of memory"));
+                        }
                     },
                 }
+                frame_counter.add_frame();
+                send_debug_info(
+                    "Window",
+                    "rates",
+                    format!(
+                        "updates/s = {}, frames/s = {}",
+                        update_counter.fps(),
+                        frame_counter.fps()
+                    ),
+                );
+
+                // Sleep until the next frame is due if the frame rate is capped. With vsync, presenting the frame
+                // already waits for the display.
+                if !settings.vsync && settings.max_fps > 0 {
+                    let frame_duration = Duration::from_secs_f64(1.0 / settings.max_fps as f64);
+                    let elapsed = frame_start.elapsed();
+                    if elapsed < frame_duration {
+                        std::thread::sleep(frame_duration - elapsed);
+                    }
+                }
+
+                if swap_chain_failures > MAX_SWAP_CHAIN_FAILURES {
+                    gpu_error = Some(anyhow!(
+                        "The GPU device was lost and couldn't be recovered after {} attempts",
+                        MAX_SWAP_CHAIN_FAILURES
+                    ));
+                }
+                // Show the GPU failures on the error screen. If the error screen can't be drawn either, give up.
+                if let Some(error) = gpu_error {
+                    if showing_gpu_error {
+                        log::error!("{}, closing the window.", error);
+                        *control_flow = ControlFlow::Exit;
+                    } else {
+                        log::error!("{}, showing the error screen.", error);
+                        showing_gpu_error = true;
+                        swap_chain_failures = 0;
+                        window_resized = true;
+                        let (new_state, cmd) = create_state(
+                            crate::ui::error::ErrorScreen::new_factory(
+                                "The GPU stopped working".to_owned(),
+                                error,
+                            ),
+                            &mut device,
+                            &mut settings,
+                            &window_data,
+                            &input_state._get_modifiers_state(),
+                        );
+                        state = new_state;
+                        queue.submit(vec![cmd]);
+                    }
+                }
             }
             RedrawRequested(_) => (), // TODO: handle this
             LoopDestroyed => {
@@ -1,12 +1,19 @@
-use crate::render::world::{start_meshing_worker, ChunkMeshData, MeshingWorker};
-use crate::render::WorldRenderer;
-use std::collections::HashMap;
+use crate::render::world::{start_meshing_worker, ChunkMeshInput, MeshingWorker};
+use crate::render::{UploadBelt, WorldRenderer};
+use nalgebra::Point3;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use voxel_rs_common::{
-    block::BlockMesh,
+    block::{entity::Sign, rail::RailShape, BlockId, BlockMesh, BlockShape, HorizontalFace},
+    data::vox::VoxelModel,
     physics::BlockContainer,
-    player::{CloseChunks, RenderDistance},
-    world::{BlockPos, Chunk, ChunkPos, LightChunk},
+    player::{CloseChunks, PlayerId, RenderDistance},
+    world::{
+        BlockPos, Chunk, ChunkPos, ChunkPosXZ, FarTile, FarTilePos, LightChunk, CHUNK_SIZE,
+        FAR_TILE_CHUNKS,
+    },
+    world_view::ChunkSource,
+    worldgen::biome::{Biome, BiomeMap},
 };
 
 /// Client-side world.
@@ -16,12 +23,30 @@ use voxel_rs_common::{
 pub struct World {
     /// The chunks
     chunks: HashMap<ChunkPos, ClientChunk>,
+    /// The biomes of the chunk columns, to tint the grass and the foliage
+    biome_maps: HashMap<ChunkPosXZ, Arc<BiomeMap>>,
     /// The meshing worker
     meshing_worker: MeshingWorker,
     /// The chunks the player can see
     close_chunks: CloseChunks,
     /// The renderer
     renderer: WorldRenderer,
+    /// The far tiles, to render the terrain beyond the render distance
+    far_tiles: HashMap<FarTilePos, Arc<FarTile>>,
+    /// The far tiles that were received but not meshed yet
+    new_far_tiles: Vec<Arc<FarTile>>,
+    /// The far tiles that are not entirely covered by the render distance
+    visible_far_tiles: Vec<FarTilePos>,
+    /// The color of every block, for the far terrain
+    block_colors: Vec<[u8; 4]>,
+    /// The shape of every block, for the physics
+    block_shapes: Vec<BlockShape>,
+    /// The signs of the loaded chunks
+    signs: HashMap<BlockPos, Sign>,
+    /// The signs that were received but whose text was not baked yet
+    new_signs: Vec<BlockPos>,
+    /// The models recolored by the skins of the players that were received but not meshed yet
+    new_skins: Vec<(PlayerId, Vec<(u32, VoxelModel)>)>,
 }
 
 impl Drop for World {
@@ -30,34 +55,150 @@ impl Drop for World {
 
 impl World {
     /// Create a new empty world using the provided chunks
-    pub fn new(block_meshes: Vec<BlockMesh>, renderer: WorldRenderer) -> Self {
+    pub fn new(
+        block_meshes: Vec<BlockMesh>,
+        block_colors: Vec<[u8; 4]>,
+        block_shapes: Vec<BlockShape>,
+        renderer: WorldRenderer,
+    ) -> Self {
         Self {
             chunks: HashMap::new(),
+            biome_maps: HashMap::new(),
             meshing_worker: start_meshing_worker(block_meshes),
             close_chunks: CloseChunks::new(&RenderDistance::default()),
             renderer,
+            far_tiles: HashMap::new(),
+            new_far_tiles: Vec::new(),
+            visible_far_tiles: Vec::new(),
+            block_colors,
+            block_shapes,
+            signs: HashMap::new(),
+            new_signs: Vec::new(),
+            new_skins: Vec::new(),
         }
     }
 
     /// Receive a new chunk from the server
     pub fn add_chunk(&mut self, chunk: Arc<Chunk>, light_chunk: Arc<LightChunk>) {
-        // TODO: make sure this only happens once
         let chunk_pos = chunk.pos;
-        self.chunks.insert(
+        let previous = self.chunks.insert(
             chunk_pos,
             ClientChunk {
-                chunk,
-                light_chunk,
+                chunk: chunk.clone(),
+                light_chunk: light_chunk.clone(),
                 is_in_meshing_queue: false,
                 needs_remesh: true,
+                meshed_neighbours: 0,
             },
         );
-        // Queue adjacent chunks for meshing
-        for i in -1..=1 {
-            for j in -1..=1 {
-                for k in -1..=1 {
-                    let adjacent_chunk_pos = chunk_pos.offset(i, j, k);
-                    if let Some(client_chunk) = self.chunks.get_mut(&adjacent_chunk_pos) {
+        match previous {
+            // The chunk was sent again, only its changed border is meshed again in the adjacent chunks
+            Some(previous) => {
+                for pos in border_blocks() {
+                    if previous.chunk.get_block_at(pos) != chunk.get_block_at(pos)
+                        || previous.light_chunk.get_light_at(pos) != light_chunk.get_light_at(pos)
+                    {
+                        self.queue_chunks_reading_block(chunk_pos, pos);
+                    }
+                }
+            }
+            None => self.queue_chunks_missing_neighbour(chunk_pos),
+        }
+    }
+
+    /// Apply block changes received from the server to the loaded chunks.
+    /// Return the chunks that were modified.
+    pub fn apply_block_changes(&mut self, changes: &[(BlockPos, BlockId)]) -> Vec<Arc<Chunk>> {
+        let mut modified_chunks = HashMap::new();
+        for &(pos, block) in changes {
+            let chunk_pos = pos.containing_chunk_pos();
+            if let Some(client_chunk) = self.chunks.get(&chunk_pos) {
+                modified_chunks
+                    .entry(chunk_pos)
+                    .or_insert_with(|| (*client_chunk.chunk).clone())
+                    .set_block_at(pos.pos_in_containing_chunk(), block);
+            }
+        }
+        let mut updated_chunks = Vec::with_capacity(modified_chunks.len());
+        for (chunk_pos, chunk) in modified_chunks {
+            let chunk = Arc::new(chunk);
+            self.chunks.get_mut(&chunk_pos).unwrap().chunk = chunk.clone();
+            updated_chunks.push(chunk);
+        }
+        for &(pos, _) in changes {
+            let chunk_pos = pos.containing_chunk_pos();
+            if self.chunks.contains_key(&chunk_pos) {
+                self.queue_chunks_reading_block(chunk_pos, pos.pos_in_containing_chunk());
+            }
+        }
+        updated_chunks
+    }
+
+    /// Receive the biomes of a chunk column from the server, before its first chunk
+    pub fn add_biome_map(&mut self, biome_map: Arc<BiomeMap>) {
+        self.biome_maps.insert(biome_map.pos, biome_map);
+    }
+
+    /// Receive a new far tile from the server
+    pub fn add_far_tile(&mut self, tile: Arc<FarTile>) {
+        self.far_tiles.insert(tile.pos, tile.clone());
+        self.new_far_tiles.push(tile);
+    }
+
+    /// Receive a new sign from the server, or the removal of a sign
+    pub fn set_sign(&mut self, pos: BlockPos, sign: Option<Sign>) {
+        match sign {
+            Some(sign) => {
+                self.signs.insert(pos, sign);
+                self.new_signs.push(pos);
+            }
+            None => {
+                self.signs.remove(&pos);
+                self.renderer.remove_sign(pos);
+            }
+        }
+    }
+
+    /// Receive the models recolored by the skin of a player, or the removal of the skin
+    pub fn set_player_skin(&mut self, player: PlayerId, models: Option<Vec<(u32, VoxelModel)>>) {
+        self.new_skins
+            .retain(|(skin_player, _)| *skin_player != player);
+        match models {
+            Some(models) => self.new_skins.push((player, models)),
+            None => self.renderer.remove_player_skin(player),
+        }
+    }
+
+    /// Receive new light for a loaded chunk from the server
+    pub fn set_light_chunk(&mut self, light_chunk: Arc<LightChunk>) {
+        let chunk_pos = light_chunk.pos;
+        if let Some(client_chunk) = self.chunks.get_mut(&chunk_pos) {
+            let previous = std::mem::replace(&mut client_chunk.light_chunk, light_chunk.clone());
+            client_chunk.needs_remesh = true;
+            for pos in border_blocks() {
+                if previous.get_light_at(pos) != light_chunk.get_light_at(pos) {
+                    self.queue_chunks_reading_block(chunk_pos, pos);
+                }
+            }
+        }
+    }
+
+    /// Queue for meshing the chunk at `chunk_pos` and the adjacent chunks that have the block at position `pos`
+    /// of the chunk in their border
+    fn queue_chunks_reading_block(&mut self, chunk_pos: ChunkPos, pos: (u32, u32, u32)) {
+        let offsets = |coordinate: u32| -> &'static [i64] {
+            if coordinate == 0 {
+                &[0, -1]
+            } else if coordinate == CHUNK_SIZE - 1 {
+                &[0, 1]
+            } else {
+                &[0]
+            }
+        };
+        for &dx in offsets(pos.0) {
+            for &dy in offsets(pos.1) {
+                for &dz in offsets(pos.2) {
+                    if let Some(client_chunk) = self.chunks.get_mut(&chunk_pos.offset(dx, dy, dz)) {
                         client_chunk.needs_remesh = true;
                     }
                 }
@@ -65,18 +206,52 @@ impl World {
         }
     }
 
+    /// Queue for meshing the adjacent chunks that were meshed while the chunk at `chunk_pos` was not loaded
+    fn queue_chunks_missing_neighbour(&mut self, chunk_pos: ChunkPos) {
+        for (dx, dy, dz) in adjacent_offsets() {
+            if let Some(client_chunk) = self.chunks.get_mut(&chunk_pos.offset(dx, dy, dz)) {
+                if client_chunk.meshed_neighbours & (1 << neighbour_index(-dx, -dy, -dz)) == 0 {
+                    client_chunk.needs_remesh = true;
+                }
+            }
+        }
+    }
+
     /// Fetch the new chunk meshes from the meshing worker
     pub fn get_new_chunk_meshes(
         &mut self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
     ) {
         while let Some(mesh) = self.meshing_worker.get_result() {
             if let Some(client_chunk) = self.chunks.get_mut(&mesh.0) {
                 client_chunk.is_in_meshing_queue = false;
-                self.renderer.update_chunk_mesh(device, encoder, mesh);
+                self.renderer.update_chunk_mesh(device, encoder, belt, mesh);
+            }
+        }
+        // The far tiles are small enough to be meshed immediately
+        for tile in self.new_far_tiles.drain(..) {
+            if self.far_tiles.contains_key(&tile.pos) {
+                self.renderer.update_far_tile_mesh(
+                    device,
+                    encoder,
+                    belt,
+                    &tile,
+                    &self.block_colors,
+                );
             }
         }
+        // The text of the signs is baked once, and again when it changes
+        for pos in self.new_signs.drain(..) {
+            if let Some(sign) = self.signs.get(&pos) {
+                self.renderer.update_sign(device, encoder, pos, sign);
+            }
+        }
+        for (player, models) in self.new_skins.drain(..) {
+            self.renderer
+                .update_player_skin(device, encoder, belt, player, &models);
+        }
     }
 
     /// Remove chunks that are too far for the player
@@ -84,16 +259,80 @@ impl World {
         let Self {
             ref mut chunks,
             ref mut renderer,
+            ref mut far_tiles,
+            ref mut signs,
+            ref mut biome_maps,
             ..
         } = self;
+        let mut removed_chunks = Vec::new();
         chunks.retain(|chunk_pos, _| {
             if render_distance.is_chunk_visible(player_chunk, *chunk_pos) {
                 true
             } else {
                 renderer.remove_chunk_mesh(*chunk_pos);
+                removed_chunks.push(*chunk_pos);
                 false
             }
-        })
+        });
+        // The adjacent chunks are meshed again if the chunk comes back, since it may have changed in the meantime
+        for chunk_pos in removed_chunks {
+            for (dx, dy, dz) in adjacent_offsets() {
+                if let Some(client_chunk) = chunks.get_mut(&chunk_pos.offset(dx, dy, dz)) {
+                    client_chunk.meshed_neighbours &= !(1 << neighbour_index(-dx, -dy, -dz));
+                }
+            }
+        }
+        biome_maps.retain(|column, _| render_distance.is_column_visible(player_chunk, *column));
+        signs.retain(|pos, _| {
+            if chunks.contains_key(&pos.containing_chunk_pos()) {
+                true
+            } else {
+                renderer.remove_sign(*pos);
+                false
+            }
+        });
+
+        // Remove the far tiles that are too far, and hide the far tiles that are entirely covered by chunks
+        let player_tile = FarTilePos::from(ChunkPosXZ::from(player_chunk));
+        far_tiles.retain(|tile_pos, _| {
+            if tile_pos.is_close_to(player_tile) {
+                true
+            } else {
+                renderer.remove_far_tile_mesh(*tile_pos);
+                false
+            }
+        });
+        let tile_chunks = FAR_TILE_CHUNKS as i64;
+        let is_covered = |min: i64, player: i64, distance_min: u64, distance_max: u64| {
+            min >= player - distance_min as i64
+                && min + tile_chunks - 1 <= player + distance_max as i64
+        };
+        self.visible_far_tiles = self
+            .far_tiles
+            .keys()
+            .filter(|tile_pos| {
+                !(is_covered(
+                    tile_pos.px * tile_chunks,
+                    player_chunk.px,
+                    render_distance.x_min,
+                    render_distance.x_max,
+                ) && is_covered(
+                    tile_pos.pz * tile_chunks,
+                    player_chunk.pz,
+                    render_distance.z_min,
+                    render_distance.z_max,
+                ))
+            })
+            .cloned()
+            .collect();
+    }
+
+    /// Number of chunks waiting in the meshing queue
+    pub fn num_chunks_in_meshing_queue(&self) -> usize {
+        self.chunks
+            .values()
+            .filter(|client_chunk| client_chunk.is_in_meshing_queue)
+            .count()
     }
 
     /// Start the meshing of a few chunks
@@ -107,15 +346,16 @@ impl World {
             let pos = pos.offset_by_pos(player_chunk);
             if let Some(client_chunk) = self.chunks.get(&pos) {
                 if client_chunk.needs_remesh && !client_chunk.is_in_meshing_queue {
-                    let res = self
-                        .meshing_worker
-                        .enqueue(self.create_chunk_mesh_data(pos));
+                    let input = self.create_chunk_mesh_input(pos);
+                    let neighbours = input.neighbours;
+                    let res = self.meshing_worker.enqueue(input);
                     match res {
                         // If the meshing queue is not full, update chunk status
                         Ok(()) => {
                             let client_chunk = self.chunks.get_mut(&pos).expect("Logic error");
                             client_chunk.needs_remesh = false;
                             client_chunk.is_in_meshing_queue = true;
+                            client_chunk.meshed_neighbours = neighbours;
                         }
                         // If the meshing queue is full, stop
                         Err(_) => break,
@@ -125,32 +365,39 @@ impl World {
         }
     }
 
-    /// Create a `ChunkMeshData` for a loaded chunk
-    fn create_chunk_mesh_data(&self, pos: ChunkPos) -> ChunkMeshData {
+    /// Create a `ChunkMeshInput` for a loaded chunk, copying the border from the adjacent chunks
+    fn create_chunk_mesh_input(&self, pos: ChunkPos) -> ChunkMeshInput {
         let client_chunk = self
             .chunks
             .get(&pos)
-            .expect("no chunk at current position to create ChunkMeshData");
-        let mut all_chunks: [Option<Arc<Chunk>>; 27] = Default::default();
-        let mut all_light_chunks: [Option<Arc<LightChunk>>; 27] = Default::default();
+            .expect("no chunk at current position to create ChunkMeshInput");
+        let mut biome_maps: [Option<Arc<BiomeMap>>; 9] = Default::default();
         for i in 0..3 {
-            for j in 0..3 {
-                for k in 0..3 {
-                    let np = pos.offset(i - 1, j - 1, k - 1);
-                    let idx = (i * 9 + j * 3 + k) as usize;
-                    let adj_client_chunk = self.chunks.get(&np);
-                    all_chunks[idx] = adj_client_chunk.map(|c| c.chunk.clone());
-                    all_light_chunks[idx] = adj_client_chunk.map(|c| c.light_chunk.clone());
-                }
+            for k in 0..3 {
+                let column = ChunkPosXZ::from(pos).offset(i - 1, k - 1);
+                biome_maps[(i * 3 + k) as usize] = self.biome_maps.get(&column).cloned();
             }
         }
+        let adjacent = |index: usize| {
+            let index = index as i64;
+            self.get_loaded_chunk(pos.offset(index / 9 - 1, index / 3 % 3 - 1, index % 3 - 1))
+        };
 
-        ChunkMeshData {
-            chunk: client_chunk.chunk.clone(),
-            light_chunk: client_chunk.light_chunk.clone(),
-            all_chunks,
-            all_light_chunks,
-        }
+        ChunkMeshInput::new(
+            client_chunk.chunk.clone(),
+            client_chunk.light_chunk.clone(),
+            adjacent,
+            biome_maps,
+        )
+    }
+
+    /// Free the GPU memory of the meshes that were removed, see `WorldRenderer::shrink_mesh_buffers`
+    pub fn shrink_mesh_buffers(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.renderer.shrink_mesh_buffers(device, encoder);
     }
 
     /// Render the chunks
@@ -158,43 +405,128 @@ impl World {
         &mut self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
+        belt: &mut UploadBelt,
         buffers: crate::window::WindowBuffers,
         data: &crate::window::WindowData,
         frustum: &crate::render::Frustum,
         enable_culling: bool,
         pointed_block: Option<(BlockPos, usize)>,
+        selection: Option<(BlockPos, BlockPos)>,
+        hand_light: u8,
+        flicker: f32,
         models: &[crate::render::world::Model],
     ) {
         // TODO: remove some of the parameters and calculate them here instead
-        self.get_new_chunk_meshes(device, encoder);
+        self.get_new_chunk_meshes(device, encoder, belt);
         self.renderer.render(
             device,
             encoder,
+            belt,
             buffers,
             data,
             frustum,
             enable_culling,
             pointed_block,
+            selection,
+            hand_light,
+            flicker,
             models,
+            &self.visible_far_tiles,
         );
     }
 
+    /// Get the block at some position, or air if its chunk is not loaded
+    pub fn get_block(&self, pos: BlockPos) -> BlockId {
+        match self.chunks.get(&pos.containing_chunk_pos()) {
+            None => 0,
+            Some(chunk) => chunk.chunk.get_block_at(pos.pos_in_containing_chunk()),
+        }
+    }
+
+    /// Check whether there is a full block on the segment between two points, sampled every `step` blocks
+    pub fn is_segment_occluded(&self, from: Point3<f64>, to: Point3<f64>, step: f64) -> bool {
+        let length = (to - from).norm();
+        let steps = (length / step).ceil() as usize;
+        (1..steps).any(|i| {
+            let point = from + (to - from) * (i as f64 / steps as f64);
+            self.is_block_full(BlockPos::from(point))
+        })
+    }
+
+    /// Get the biome of the column of a block, if the biome map of its chunk column was received
+    pub fn get_biome(&self, pos: BlockPos) -> Option<Biome> {
+        let column = ChunkPosXZ::from(pos.containing_chunk_pos());
+        let (x, _, z) = pos.pos_in_containing_chunk();
+        self.biome_maps
+            .get(&column)
+            .map(|biome_map| biome_map.get(x, z))
+    }
+
     /// Number of loaded chunks
     pub fn num_loaded_chunks(&self) -> usize {
         self.chunks.len()
     }
+
+    /// The columns that contain at least one loaded chunk
+    pub fn loaded_columns(&self) -> HashSet<ChunkPosXZ> {
+        self.chunks
+            .keys()
+            .map(|&pos| ChunkPosXZ::from(pos))
+            .collect()
+    }
 }
 
 impl BlockContainer for World {
     fn is_block_full(&self, pos: BlockPos) -> bool {
-        // TODO: use BlockRegistry
-        match self.chunks.get(&pos.containing_chunk_pos()) {
-            None => false,
-            Some(chunk) => chunk.chunk.get_block_at(pos.pos_in_containing_chunk()) != 0,
+        self.block_shapes[self.get_block(pos) as usize] == BlockShape::Full
+    }
+
+    fn get_ladder(&self, pos: BlockPos) -> Option<HorizontalFace> {
+        match self.block_shapes[self.get_block(pos) as usize] {
+            BlockShape::Ladder(attached_to) => Some(attached_to),
+            _ => None,
+        }
+    }
+
+    fn is_block_fluid(&self, pos: BlockPos) -> bool {
+        self.block_shapes[self.get_block(pos) as usize] == BlockShape::Fluid
+    }
+
+    fn get_layers(&self, pos: BlockPos) -> Option<u8> {
+        match self.block_shapes[self.get_block(pos) as usize] {
+            BlockShape::Layer(layers) => Some(layers),
+            _ => None,
+        }
+    }
+
+    fn is_block_plant(&self, pos: BlockPos) -> bool {
+        self.block_shapes[self.get_block(pos) as usize] == BlockShape::Plant
+    }
+
+    fn is_block_portal(&self, pos: BlockPos) -> bool {
+        self.block_shapes[self.get_block(pos) as usize] == BlockShape::Portal
+    }
+
+    fn get_rail(&self, pos: BlockPos) -> Option<RailShape> {
+        match self.block_shapes[self.get_block(pos) as usize] {
+            BlockShape::Rail(shape) => Some(shape),
+            _ => None,
         }
     }
 }
 
+impl ChunkSource for World {
+    fn get_loaded_chunk(&self, pos: ChunkPos) -> Option<(&Chunk, &LightChunk)> {
+        self.chunks
+            .get(&pos)
+            .map(|client_chunk| (&*client_chunk.chunk, &*client_chunk.light_chunk))
+    }
+
+    fn block_shapes(&self) -> &[BlockShape] {
+        &self.block_shapes
+    }
+}
+
 /// The data for each chunk stored by the client
 struct ClientChunk {
     /// The chunk itself
@@ -205,4 +537,26 @@ struct ClientChunk {
     pub is_in_meshing_queue: bool,
     /// True if the chunk needs to be meshed, for example before it never was meshed or because it changed.
     pub needs_remesh: bool,
+    /// The adjacent chunks that were loaded when the last mesh was requested, like `ChunkMeshInput::neighbours`
+    pub meshed_neighbours: u32,
+}
+
+/// The offsets of the 26 adjacent chunks
+fn adjacent_offsets() -> impl Iterator<Item = (i64, i64, i64)> {
+    (-1..=1)
+        .flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (dx, dy, dz))))
+        .filter(|&offset| offset != (0, 0, 0))
+}
+
+/// The bit of the adjacent chunk at offset `(dx, dy, dz)` in `ChunkMeshInput::neighbours`
+fn neighbour_index(dx: i64, dy: i64, dz: i64) -> usize {
+    (9 * (dx + 1) + 3 * (dy + 1) + (dz + 1)) as usize
+}
+
+/// The positions of the blocks of a chunk that are in the border of an adjacent chunk
+fn border_blocks() -> impl Iterator<Item = (u32, u32, u32)> {
+    let last = CHUNK_SIZE - 1;
+    (0..CHUNK_SIZE)
+        .flat_map(|x| (0..CHUNK_SIZE).flat_map(move |y| (0..CHUNK_SIZE).map(move |z| (x, y, z))))
+        .filter(move |&(x, y, z)| x == 0 || y == 0 || z == 0 || x == last || y == last || z == last)
 }
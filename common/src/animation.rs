@@ -0,0 +1,220 @@
+//! Keyframe animations of the entity models. An animated model is split into named parts,
+//! and its animations move the parts between keyframes.
+//! The parts and the animations of a model are defined in `data/animations/<model>.ron`.
+
+use crate::data::vox::VoxelModel;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What an entity is doing, which decides the animation that it plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntityActivity {
+    Idle,
+    Walking,
+    Attacking,
+}
+
+impl EntityActivity {
+    /// Name of the animation played during this activity
+    pub fn animation_name(self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Walking => "walk",
+            Self::Attacking => "attack",
+        }
+    }
+}
+
+impl Default for EntityActivity {
+    fn default() -> Self {
+        Self::Idle
+    }
+}
+
+/// A part of an animated model, as defined in the data. The positions are in voxels.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PartData {
+    pub name: String,
+    /// Minimum corner of the part in the model, included
+    pub min: [usize; 3],
+    /// Maximum corner of the part in the model, excluded
+    pub max: [usize; 3],
+    /// Point of the model around which the part rotates
+    pub pivot: [f32; 3],
+}
+
+/// Movement of a part relative to its rest position: a rotation in degrees around the x, y and z axes,
+/// applied in that order around the pivot of the part, then a translation in voxels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PartTransform {
+    pub rotation: [f32; 3],
+    pub translation: [f32; 3],
+}
+
+impl PartTransform {
+    /// Linear interpolation between `self` and `other`, `t` is between 0 and 1
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mut res = *self;
+        for i in 0..3 {
+            res.rotation[i] += (other.rotation[i] - self.rotation[i]) * t;
+            res.translation[i] += (other.translation[i] - self.translation[i]) * t;
+        }
+        res
+    }
+}
+
+/// The transforms of some parts at some time of an animation. The missing parts are at rest.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Keyframe {
+    /// Time of the keyframe in seconds since the start of the animation
+    pub time: f32,
+    #[serde(default)]
+    pub parts: HashMap<String, PartTransform>,
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Animation {
+    /// Duration of the animation in seconds
+    pub duration: f32,
+    /// Whether the animation starts again when it ends. Otherwise, the last keyframe is held.
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+    /// The keyframes, sorted by time
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Animation {
+    /// Get the transform of a part `time` seconds after the start of the animation,
+    /// interpolated between the surrounding keyframes
+    pub fn sample(&self, part: &str, time: f32) -> PartTransform {
+        let transform = |keyframe: &Keyframe| keyframe.parts.get(part).copied().unwrap_or_default();
+        let (first, last) = match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return PartTransform::default(),
+        };
+        let time = if self.looping && self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            time.min(self.duration)
+        };
+
+        // The keyframes before and after `time`, with their times
+        let ((previous, previous_time), (next, next_time)) = match self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > time)
+        {
+            Some(0) if self.looping => ((last, last.time - self.duration), (first, first.time)),
+            Some(0) => return transform(first),
+            Some(i) => {
+                let (previous, next) = (&self.keyframes[i - 1], &self.keyframes[i]);
+                ((previous, previous.time), (next, next.time))
+            }
+            None if self.looping => ((last, last.time), (first, first.time + self.duration)),
+            None => return transform(last),
+        };
+        if next_time <= previous_time {
+            return transform(previous);
+        }
+        let t = (time - previous_time) / (next_time - previous_time);
+        transform(previous).lerp(&transform(next), t)
+    }
+}
+
+/// The parts and the animations of a model, as defined in the data
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnimatedModelData {
+    pub parts: Vec<PartData>,
+    pub animations: HashMap<String, Animation>,
+}
+
+/// A part of an animated model, with its own mesh
+#[derive(Debug, Clone)]
+pub struct ModelPart {
+    pub name: String,
+    /// Id of the mesh of the part in the model registry
+    pub mesh_id: u32,
+    /// Position of the mesh of the part in the model
+    pub offset: [f32; 3],
+    pub pivot: [f32; 3],
+}
+
+/// A model split into parts that can be animated
+#[derive(Debug, Clone)]
+pub struct AnimatedModel {
+    pub parts: Vec<ModelPart>,
+    pub animations: HashMap<String, Animation>,
+}
+
+/// Get the voxels of a model between `min` (included) and `max` (excluded).
+/// The box must be inside the model.
+pub fn split_model(model: &VoxelModel, min: [usize; 3], max: [usize; 3]) -> VoxelModel {
+    let (size_x, size_y, size_z) = (max[0] - min[0], max[1] - min[1], max[2] - min[2]);
+    let mut part = VoxelModel {
+        size_x,
+        size_y,
+        size_z,
+        voxels: Vec::with_capacity(size_x * size_y * size_z),
+        full: Vec::with_capacity(size_x * size_y * size_z),
+        materials: Vec::with_capacity(size_x * size_y * size_z),
+    };
+    for x in min[0]..max[0] {
+        for y in min[1]..max[1] {
+            for z in min[2]..max[2] {
+                let s = x * model.size_y * model.size_z + y * model.size_z + z;
+                part.voxels.push(model.voxels[s]);
+                part.full.push(model.full[s]);
+                part.materials.push(model.materials[s]);
+            }
+        }
+    }
+    part
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f32, rotation_x: f32) -> Keyframe {
+        let mut parts = HashMap::new();
+        parts.insert(
+            "arm".to_owned(),
+            PartTransform {
+                rotation: [rotation_x, 0.0, 0.0],
+                translation: [0.0; 3],
+            },
+        );
+        Keyframe { time, parts }
+    }
+
+    #[test]
+    fn sample_interpolates_keyframes() {
+        let animation = Animation {
+            duration: 2.0,
+            looping: false,
+            keyframes: vec![keyframe(0.0, 0.0), keyframe(1.0, 90.0)],
+        };
+        assert_eq!(animation.sample("arm", 0.5).rotation[0], 45.0);
+        // The last keyframe is held
+        assert_eq!(animation.sample("arm", 5.0).rotation[0], 90.0);
+        // The other parts are at rest
+        assert_eq!(animation.sample("leg", 0.5), PartTransform::default());
+    }
+
+    #[test]
+    fn sample_wraps_looping_animations() {
+        let animation = Animation {
+            duration: 2.0,
+            looping: true,
+            keyframes: vec![keyframe(0.0, 0.0), keyframe(1.0, 90.0)],
+        };
+        // Between the last keyframe and the first keyframe of the next loop
+        assert_eq!(animation.sample("arm", 1.5).rotation[0], 45.0);
+        assert_eq!(animation.sample("arm", 2.5).rotation[0], 45.0);
+    }
+}
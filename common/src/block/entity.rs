@@ -0,0 +1,373 @@
+//! Block entities, i.e. the additional state attached to some blocks in the world
+use crate::block::HorizontalFace;
+use crate::inventory::{Inventory, ItemStack, MAX_STACK_SIZE};
+use crate::item::Item;
+use crate::registry::Registry;
+use crate::smelting::SmeltingRecipe;
+use crate::world::BlockPos;
+use serde::{Deserialize, Serialize};
+
+/// The kind of block entity that a block has. This is part of the block data.
+#[derive(Debug, Clone, Deserialize)]
+pub enum BlockEntityKind {
+    /// A block that stores items, for example a chest
+    Container { slots: usize },
+    /// A block that smelts items using fuel. The block is replaced by `active_block` while it is burning fuel,
+    /// and by `inactive_block` when it stops.
+    Furnace {
+        inactive_block: String,
+        active_block: String,
+    },
+    /// A block with a text written by the players on one of its faces
+    Sign,
+    /// A block that runs a server command every `interval` seconds
+    CommandBlock { interval: f32 },
+    /// A portal, linked to another portal
+    Portal,
+}
+
+impl BlockEntityKind {
+    /// Create the default block entity for a newly placed block
+    pub fn create(&self) -> BlockEntity {
+        match self {
+            Self::Container { slots } => BlockEntity::Container(Inventory::new(*slots)),
+            Self::Furnace { .. } => BlockEntity::Furnace(Furnace::new()),
+            Self::Sign => BlockEntity::Sign(Sign::new()),
+            Self::CommandBlock { interval } => {
+                BlockEntity::CommandBlock(CommandBlock::new(*interval))
+            }
+            Self::Portal => BlockEntity::Portal(Portal { destination: None }),
+        }
+    }
+}
+
+/// The state of a block entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BlockEntity {
+    Container(Inventory),
+    Furnace(Furnace),
+    Sign(Sign),
+    CommandBlock(CommandBlock),
+    Portal(Portal),
+}
+
+impl BlockEntity {
+    /// Get the inventory of this block entity, if it has one
+    pub fn inventory(&self) -> Option<&Inventory> {
+        match self {
+            Self::Container(inventory) => Some(inventory),
+            Self::Furnace(furnace) => Some(&furnace.inventory),
+            Self::Sign(_) | Self::CommandBlock(_) | Self::Portal(_) => None,
+        }
+    }
+
+    /// Get the inventory of this block entity, if it has one
+    pub fn inventory_mut(&mut self) -> Option<&mut Inventory> {
+        match self {
+            Self::Container(inventory) => Some(inventory),
+            Self::Furnace(furnace) => Some(&mut furnace.inventory),
+            Self::Sign(_) | Self::CommandBlock(_) | Self::Portal(_) => None,
+        }
+    }
+}
+
+/// Maximum number of characters of the text of a sign
+pub const MAX_SIGN_TEXT_LENGTH: usize = 60;
+
+/// The state of a sign
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sign {
+    pub text: String,
+    /// The face of the block the text is written on
+    pub facing: HorizontalFace,
+}
+
+impl Sign {
+    pub fn new() -> Self {
+        Self {
+            text: String::new(),
+            facing: HorizontalFace::PosZ,
+        }
+    }
+
+    /// Set the text of the sign, keeping only the first `MAX_SIGN_TEXT_LENGTH` printable characters
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(MAX_SIGN_TEXT_LENGTH)
+            .collect();
+    }
+}
+
+/// Maximum number of characters of the command of a command block
+pub const MAX_COMMAND_LENGTH: usize = 100;
+
+/// The state of a command block
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandBlock {
+    /// The command, without the leading `/`. Nothing runs if it is empty.
+    pub command: String,
+    /// Seconds between two runs of the command
+    interval: f32,
+}
+
+impl CommandBlock {
+    pub fn new(interval: f32) -> Self {
+        Self {
+            command: String::new(),
+            interval,
+        }
+    }
+
+    /// Seconds between two runs of the command. The server schedules the runs when the command is set.
+    pub fn interval(&self) -> f32 {
+        self.interval
+    }
+
+    /// Whether the command must run every `interval` seconds
+    pub fn is_active(&self) -> bool {
+        !self.command.is_empty() && self.interval > 0.0
+    }
+
+    /// Set the command, keeping only the first `MAX_COMMAND_LENGTH` printable characters
+    pub fn set_command(&mut self, command: &str) {
+        let command = command.trim();
+        self.command = command
+            .strip_prefix('/')
+            .unwrap_or(command)
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(MAX_COMMAND_LENGTH)
+            .collect();
+    }
+}
+
+/// The state of a portal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Portal {
+    /// The portal that the players are teleported to, `None` until the portal is linked
+    pub destination: Option<BlockPos>,
+}
+
+/// Slot of the furnace inventory containing the items to smelt
+pub const FURNACE_INPUT_SLOT: usize = 0;
+/// Slot of the furnace inventory containing the fuel
+pub const FURNACE_FUEL_SLOT: usize = 1;
+/// Slot of the furnace inventory containing the smelted items
+pub const FURNACE_OUTPUT_SLOT: usize = 2;
+/// Number of steps of the progress bars of the furnace GUI
+pub const FURNACE_PROGRESS_STEPS: u8 = 24;
+
+/// The progress of a furnace, as shown in its GUI.
+/// It is quantized so that the server only has to send it to the clients when it visibly changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FurnaceProgress {
+    /// Remaining fuel, out of `FURNACE_PROGRESS_STEPS`
+    pub fuel: u8,
+    /// Smelting progress of the current item, out of `FURNACE_PROGRESS_STEPS`
+    pub smelting: u8,
+}
+
+impl FurnaceProgress {
+    /// Fraction of the fuel that remains, between 0 and 1
+    pub fn fuel_fraction(&self) -> f32 {
+        self.fuel as f32 / FURNACE_PROGRESS_STEPS as f32
+    }
+
+    /// Fraction of the current item that was smelted, between 0 and 1
+    pub fn smelting_fraction(&self) -> f32 {
+        self.smelting as f32 / FURNACE_PROGRESS_STEPS as f32
+    }
+}
+
+/// The state of a furnace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Furnace {
+    pub inventory: Inventory,
+    /// Seconds until the current fuel is consumed
+    burn_time_left: f32,
+    /// Total burn time of the current fuel
+    burn_time_total: f32,
+    /// Seconds spent smelting the current item
+    smelting_time: f32,
+    /// Seconds needed to smelt the current item
+    smelting_time_total: f32,
+}
+
+impl Furnace {
+    pub fn new() -> Self {
+        Self {
+            inventory: Inventory::new(3),
+            burn_time_left: 0.0,
+            burn_time_total: 0.0,
+            smelting_time: 0.0,
+            smelting_time_total: 0.0,
+        }
+    }
+
+    /// Whether the furnace is currently burning fuel
+    pub fn is_active(&self) -> bool {
+        self.burn_time_left > 0.0
+    }
+
+    /// Get the progress shown in the furnace GUI
+    pub fn progress(&self) -> FurnaceProgress {
+        let steps = FURNACE_PROGRESS_STEPS as f32;
+        let fraction = |x: f32, total: f32| {
+            if total > 0.0 {
+                (x / total * steps).ceil().min(steps).max(0.0) as u8
+            } else {
+                0
+            }
+        };
+        FurnaceProgress {
+            fuel: fraction(self.burn_time_left, self.burn_time_total),
+            smelting: fraction(self.smelting_time, self.smelting_time_total),
+        }
+    }
+
+    /// Whether ticking the furnace would not change it until its inventory changes: it is not burning fuel,
+    /// has no smelting progress, and either has nothing to smelt or no fuel to smelt it with
+    pub fn is_idle(&self, recipes: &[SmeltingRecipe], item_registry: &Registry<Item>) -> bool {
+        !self.is_active()
+            && self.smelting_time_total == 0.0
+            && (self.current_recipe(recipes).is_none() || self.fuel_burn_time(item_registry) == 0.0)
+    }
+
+    /// Get the burn time of the item in the fuel slot, 0 if there is none or if it is not a fuel
+    fn fuel_burn_time(&self, item_registry: &Registry<Item>) -> f32 {
+        self.inventory
+            .get(FURNACE_FUEL_SLOT)
+            .and_then(|fuel| item_registry.get_value_by_id(fuel.item))
+            .map_or(0.0, Item::burn_time)
+    }
+
+    /// Get the recipe matching the input slot, if the result fits in the output slot
+    fn current_recipe<'a>(&self, recipes: &'a [SmeltingRecipe]) -> Option<&'a SmeltingRecipe> {
+        let input = self.inventory.get(FURNACE_INPUT_SLOT)?;
+        let output = self.inventory.get(FURNACE_OUTPUT_SLOT);
+        recipes.iter().find(|recipe| {
+            recipe.input == input.item
+                && match output {
+                    None => true,
+                    Some(output) => {
+                        output.item == recipe.output
+                            && output.count + recipe.output_count <= MAX_STACK_SIZE
+                    }
+                }
+        })
+    }
+
+    /// Advance the furnace by `dt` seconds. Return true if the inventory changed.
+    pub fn tick(
+        &mut self,
+        dt: f32,
+        recipes: &[SmeltingRecipe],
+        item_registry: &Registry<Item>,
+    ) -> bool {
+        let mut inventory_changed = false;
+        let recipe = self.current_recipe(recipes).cloned();
+
+        // Only start burning new fuel if there is something to smelt
+        if !self.is_active() && recipe.is_some() {
+            if let Some(mut fuel) = self.inventory.get(FURNACE_FUEL_SLOT) {
+                let burn_time = self.fuel_burn_time(item_registry);
+                if burn_time > 0.0 {
+                    fuel.count -= 1;
+                    self.inventory.set(FURNACE_FUEL_SLOT, Some(fuel));
+                    self.burn_time_left = burn_time;
+                    self.burn_time_total = burn_time;
+                    inventory_changed = true;
+                }
+            }
+        }
+
+        match recipe {
+            Some(recipe) if self.is_active() => {
+                self.smelting_time += dt;
+                self.smelting_time_total = recipe.time;
+                if self.smelting_time >= recipe.time {
+                    self.smelting_time = 0.0;
+                    let mut input = self.inventory.get(FURNACE_INPUT_SLOT).unwrap();
+                    input.count -= 1;
+                    self.inventory.set(FURNACE_INPUT_SLOT, Some(input));
+                    let output = match self.inventory.get(FURNACE_OUTPUT_SLOT) {
+                        Some(output) => {
+                            ItemStack::new(output.item, output.count + recipe.output_count)
+                        }
+                        None => ItemStack::new(recipe.output, recipe.output_count),
+                    };
+                    self.inventory.set(FURNACE_OUTPUT_SLOT, Some(output));
+                    inventory_changed = true;
+                }
+            }
+            // The smelting progress is lost if the input is removed or if the fuel runs out
+            _ => {
+                self.smelting_time = 0.0;
+                self.smelting_time_total = 0.0;
+            }
+        }
+
+        self.burn_time_left = f32::max(self.burn_time_left - dt, 0.0);
+        inventory_changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_block_command() {
+        let mut command_block = CommandBlock::new(2.0);
+        assert!(!command_block.is_active());
+        command_block.set_command("/say  hello ");
+        assert_eq!(command_block.command, "say  hello");
+        assert!(command_block.is_active());
+        command_block.set_command(" ");
+        assert!(!command_block.is_active());
+        assert!(!CommandBlock::new(0.0).is_active());
+    }
+
+    #[test]
+    fn test_furnace_idle() {
+        let mut items = Registry::default();
+        for (name, burn_time) in [("ore", 0.0), ("ingot", 0.0), ("coal", 1.5)].iter() {
+            let item = Item {
+                name: (*name).to_owned(),
+                ty: ron::de::from_str(&format!(
+                    "NormalItem(texture: \"\", burn_time: {:?})",
+                    burn_time
+                ))
+                .unwrap(),
+            };
+            items.register((*name).to_owned(), item).unwrap();
+        }
+        let recipes = [SmeltingRecipe {
+            input: 0,
+            output: 1,
+            output_count: 1,
+            time: 1.0,
+        }];
+        let mut furnace = Furnace::new();
+        assert!(furnace.is_idle(&recipes, &items));
+        furnace
+            .inventory
+            .set(FURNACE_INPUT_SLOT, Some(ItemStack::new(0, 1)));
+        assert!(furnace.is_idle(&recipes, &items));
+        furnace
+            .inventory
+            .set(FURNACE_FUEL_SLOT, Some(ItemStack::new(2, 1)));
+        assert!(!furnace.is_idle(&recipes, &items));
+        // The ore is smelted, and the fuel burns out
+        for _ in 0..20 {
+            furnace.tick(0.1, &recipes, &items);
+        }
+        assert_eq!(
+            furnace.inventory.get(FURNACE_OUTPUT_SLOT),
+            Some(ItemStack::new(1, 1))
+        );
+        assert!(furnace.is_idle(&recipes, &items));
+    }
+}
@@ -1,15 +1,271 @@
 use crate::data::TextureRect;
-use serde::Deserialize;
+use crate::item::ToolType;
+use crate::registry::Registry;
+use serde::{Deserialize, Serialize};
+
+pub mod entity;
+pub mod rail;
+pub mod sound;
+use self::entity::BlockEntityKind;
+use self::rail::RailShape;
+use self::sound::SoundMaterial;
 
 pub type BlockId = u16;
 
+/// Maximum number of layers of a layered block, which then fills its cell
+pub const MAX_LAYERS: u8 = 8;
+
+/// The block entity of the portals, which is not part of their block data
+static PORTAL_BLOCK_ENTITY: BlockEntityKind = BlockEntityKind::Portal;
+
 /// The type of a block. It contains the behavior and the mesh of the block.
 /// This is the data provided by the creator of the block.
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename = "Block")]
 pub enum BlockType {
     Air, // TODO: skip when deserializing
-    NormalCube { face_textures: Vec<String> },
+    NormalCube {
+        face_textures: Vec<String>,
+        /// The block entity that is created when the block is placed
+        #[serde(default)]
+        block_entity: Option<BlockEntityKind>,
+        /// The level of the light emitted by the block, 0 if it doesn't emit light
+        #[serde(default)]
+        light_level: u8,
+        /// True if the block is lit by a flame: the glow of its faces flickers
+        #[serde(default)]
+        flickers: bool,
+        /// Number of seconds needed to break the block in survival mode
+        #[serde(default = "default_break_time")]
+        break_time: f32,
+        /// True if the block is a fluid: it is drawn like a cube, but the players swim through it
+        #[serde(default)]
+        fluid: bool,
+        /// Name of the sound material of the block, see `SoundMaterial`
+        #[serde(default)]
+        sound_material: Option<String>,
+        /// The biome color multiplied into every face, in the order of `face_textures`.
+        /// Empty if no face is tinted.
+        #[serde(default)]
+        face_tints: Vec<Option<BiomeTint>>,
+        /// True if the block disappears when no block that sustains it is close enough, like leaves
+        #[serde(default)]
+        decays: bool,
+        /// True if the block keeps the decaying blocks around it, like the wood of the trees
+        #[serde(default)]
+        sustains_decaying: bool,
+        /// Name of the block that replaces this block when it is tilled with a hoe, like farmland for dirt
+        #[serde(default)]
+        tilled_into: Option<String>,
+        /// True if the crops can be planted and grow on top of the block, like farmland
+        #[serde(default)]
+        fertile: bool,
+        /// The kind of tool that breaks the block faster, if any
+        #[serde(default)]
+        preferred_tool: Option<ToolType>,
+        /// The tier of the preferred tool needed to break the block at the normal speed, 0 if the bare hand is enough
+        #[serde(default)]
+        min_tier: u8,
+    },
+    /// A thin climbable block, attached to one side of its cell.
+    /// There is one block per side: the side is part of the block, like the state of the furnace.
+    Ladder {
+        texture: String,
+        /// The side of the cell the ladder is attached to
+        attached_to: HorizontalFace,
+        #[serde(default = "default_break_time")]
+        break_time: f32,
+        /// Name of the sound material of the block, see `SoundMaterial`
+        #[serde(default)]
+        sound_material: Option<String>,
+    },
+    /// A thin block covering the bottom of its cell, like snow or a carpet.
+    /// There is one block per number of layers: the number of layers is part of the block, like the side of the ladders.
+    Layer {
+        /// The texture of every face
+        texture: String,
+        /// The number of layers, from 1 to `MAX_LAYERS`. A layer is `1 / MAX_LAYERS` block high.
+        layers: u8,
+        #[serde(default = "default_break_time")]
+        break_time: f32,
+        /// Name of the sound material of the block, see `SoundMaterial`
+        #[serde(default)]
+        sound_material: Option<String>,
+        /// True if the layers pile up in the cold biomes and melt elsewhere, like snow
+        #[serde(default)]
+        accumulates: bool,
+    },
+    /// A plant growing on a fertile block, that the players walk through.
+    /// There is one block per growth stage: the stage is part of the block, like the number of layers.
+    Crop {
+        /// The name of the plant, shared by all its stages
+        crop: String,
+        /// The growth stage, from 0. The plant is ripe at the last stage.
+        stage: u8,
+        texture: String,
+        /// Minimum light level above the plant for it to grow
+        #[serde(default = "default_crop_min_light")]
+        min_light: u8,
+        #[serde(default = "default_break_time")]
+        break_time: f32,
+        /// Name of the sound material of the block, see `SoundMaterial`
+        #[serde(default)]
+        sound_material: Option<String>,
+    },
+    /// A small light source that the players walk through, standing on a block or attached to the side of its cell.
+    /// There is one block per side like for the ladders, and one standing block.
+    Torch {
+        texture: String,
+        /// The side of the cell the torch is attached to, `None` if it stands on the block below
+        #[serde(default)]
+        attached_to: Option<HorizontalFace>,
+        /// The level of the light emitted by the torch
+        #[serde(default = "default_torch_light_level")]
+        light_level: u8,
+        /// True if the torch is a flame: the glow of its quads flickers
+        #[serde(default)]
+        flickers: bool,
+        #[serde(default = "default_torch_break_time")]
+        break_time: f32,
+        /// Name of the sound material of the block, see `SoundMaterial`
+        #[serde(default)]
+        sound_material: Option<String>,
+    },
+    /// A glowing block that the players walk through, linked to another portal block.
+    /// The players that stay inside it are teleported to the other portal.
+    Portal {
+        texture: String,
+        /// The level of the light emitted by the portal
+        #[serde(default = "default_portal_light_level")]
+        light_level: u8,
+        #[serde(default = "default_break_time")]
+        break_time: f32,
+        /// Name of the sound material of the block, see `SoundMaterial`
+        #[serde(default)]
+        sound_material: Option<String>,
+    },
+    /// A rail lying at the bottom of its cell, that the carts follow and the players walk through.
+    /// There is one block per shape: the shape is part of the block like the growth stage of the crops.
+    Rail {
+        /// The name of the kind of rail, shared by all its shapes
+        rail: String,
+        shape: RailShape,
+        texture: String,
+        #[serde(default = "default_break_time")]
+        break_time: f32,
+        /// Name of the sound material of the block, see `SoundMaterial`
+        #[serde(default)]
+        sound_material: Option<String>,
+    },
+}
+
+/// The kind of biome color that is multiplied into the texture of a face, see `Biome::tint`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BiomeTint {
+    Grass,
+    Foliage,
+}
+
+/// A vertical side of a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HorizontalFace {
+    PosX,
+    NegX,
+    PosZ,
+    NegZ,
+}
+
+impl HorizontalFace {
+    /// Get the side of the block in the direction `(dx, dz)`, if it is a unit horizontal direction
+    pub fn from_direction(dx: i64, dz: i64) -> Option<Self> {
+        match (dx, dz) {
+            (1, 0) => Some(Self::PosX),
+            (-1, 0) => Some(Self::NegX),
+            (0, 1) => Some(Self::PosZ),
+            (0, -1) => Some(Self::NegZ),
+            _ => None,
+        }
+    }
+
+    /// Get the side of the block closest to the horizontal direction `(dx, dz)`
+    pub fn closest_to_direction(dx: f64, dz: f64) -> Self {
+        if dx.abs() > dz.abs() {
+            if dx > 0.0 {
+                Self::PosX
+            } else {
+                Self::NegX
+            }
+        } else if dz > 0.0 {
+            Self::PosZ
+        } else {
+            Self::NegZ
+        }
+    }
+
+    /// Get the index of the face in the x/-x/y/-y/z/-z order of the meshes
+    pub fn face_index(self) -> usize {
+        match self {
+            Self::PosX => 0,
+            Self::NegX => 1,
+            Self::PosZ => 4,
+            Self::NegZ => 5,
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::PosX => Self::NegX,
+            Self::NegX => Self::PosX,
+            Self::PosZ => Self::NegZ,
+            Self::NegZ => Self::PosZ,
+        }
+    }
+}
+
+/// The shape of a block, for the physics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockShape {
+    Empty,
+    Full,
+    /// A fluid that the players can swim in
+    Fluid,
+    /// A ladder attached to one side of the cell
+    Ladder(HorizontalFace),
+    /// Some layers at the bottom of the cell
+    Layer(u8),
+    /// A plant that the players walk through, but can point at
+    Plant,
+    /// A portal that the players walk through, but can point at
+    Portal,
+    /// A rail that the players walk through, but can point at
+    Rail(RailShape),
+}
+
+/// Get the shape of every block id
+pub fn block_shapes(blocks: &Registry<Block>) -> Vec<BlockShape> {
+    (0..blocks.get_number_of_ids())
+        .map(|id| blocks.get_value_by_id(id).unwrap().shape())
+        .collect()
+}
+
+fn default_break_time() -> f32 {
+    0.5
+}
+
+fn default_crop_min_light() -> u8 {
+    9
+}
+
+fn default_torch_light_level() -> u8 {
+    14
+}
+
+fn default_torch_break_time() -> f32 {
+    0.0
+}
+
+fn default_portal_light_level() -> u8 {
+    11
 }
 
 /// A general block in-memory representation.
@@ -17,6 +273,270 @@ pub enum BlockType {
 pub struct Block {
     pub name: String,
     pub block_type: BlockType,
+    /// The material of the block, resolved from the name in the block type when the data is loaded
+    pub sound_material: SoundMaterial,
+}
+
+impl Block {
+    /// Get the kind of block entity of this block, if it has one
+    pub fn block_entity_kind(&self) -> Option<&BlockEntityKind> {
+        match &self.block_type {
+            BlockType::NormalCube { block_entity, .. } => block_entity.as_ref(),
+            BlockType::Portal { .. } => Some(&PORTAL_BLOCK_ENTITY),
+            BlockType::Air
+            | BlockType::Ladder { .. }
+            | BlockType::Layer { .. }
+            | BlockType::Crop { .. }
+            | BlockType::Torch { .. }
+            | BlockType::Rail { .. } => None,
+        }
+    }
+
+    /// Get the level of the light emitted by this block
+    pub fn light_level(&self) -> u8 {
+        match &self.block_type {
+            BlockType::NormalCube { light_level, .. }
+            | BlockType::Torch { light_level, .. }
+            | BlockType::Portal { light_level, .. } => *light_level,
+            BlockType::Air
+            | BlockType::Ladder { .. }
+            | BlockType::Layer { .. }
+            | BlockType::Crop { .. }
+            | BlockType::Rail { .. } => 0,
+        }
+    }
+
+    /// Get the number of seconds needed to break this block in survival mode
+    pub fn break_time(&self) -> f32 {
+        match &self.block_type {
+            BlockType::Air => 0.0,
+            BlockType::NormalCube { break_time, .. }
+            | BlockType::Ladder { break_time, .. }
+            | BlockType::Layer { break_time, .. }
+            | BlockType::Crop { break_time, .. }
+            | BlockType::Torch { break_time, .. }
+            | BlockType::Portal { break_time, .. }
+            | BlockType::Rail { break_time, .. } => *break_time,
+        }
+    }
+
+    /// Get the name of the sound material of this block, if the block data specifies one
+    pub fn sound_material_name(&self) -> Option<&str> {
+        match &self.block_type {
+            BlockType::Air => None,
+            BlockType::NormalCube { sound_material, .. }
+            | BlockType::Ladder { sound_material, .. }
+            | BlockType::Layer { sound_material, .. }
+            | BlockType::Crop { sound_material, .. }
+            | BlockType::Torch { sound_material, .. }
+            | BlockType::Portal { sound_material, .. }
+            | BlockType::Rail { sound_material, .. } => sound_material.as_deref(),
+        }
+    }
+
+    /// Get the shape of this block, for the physics
+    pub fn shape(&self) -> BlockShape {
+        match &self.block_type {
+            BlockType::Air => BlockShape::Empty,
+            BlockType::NormalCube { fluid: true, .. } => BlockShape::Fluid,
+            BlockType::NormalCube { .. } => BlockShape::Full,
+            BlockType::Ladder { attached_to, .. } => BlockShape::Ladder(*attached_to),
+            BlockType::Layer { layers, .. } => BlockShape::Layer(*layers),
+            BlockType::Crop { .. } | BlockType::Torch { .. } => BlockShape::Plant,
+            BlockType::Portal { .. } => BlockShape::Portal,
+            BlockType::Rail { shape, .. } => BlockShape::Rail(*shape),
+        }
+    }
+
+    /// Check if this block is made of layers that pile up in the cold biomes and melt elsewhere
+    pub fn accumulates(&self) -> bool {
+        match &self.block_type {
+            BlockType::Layer { accumulates, .. } => *accumulates,
+            _ => false,
+        }
+    }
+
+    /// Check if this block disappears when no block that sustains it is close enough
+    pub fn decays(&self) -> bool {
+        match &self.block_type {
+            BlockType::NormalCube { decays, .. } => *decays,
+            _ => false,
+        }
+    }
+
+    /// Check if this block keeps the decaying blocks around it
+    pub fn sustains_decaying(&self) -> bool {
+        match &self.block_type {
+            BlockType::NormalCube {
+                sustains_decaying, ..
+            } => *sustains_decaying,
+            _ => false,
+        }
+    }
+
+    /// Get the name of the block that replaces this block when it is tilled with a hoe, if it can be tilled
+    pub fn tilled_into(&self) -> Option<&str> {
+        match &self.block_type {
+            BlockType::NormalCube { tilled_into, .. } => tilled_into.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Check if the crops can be planted and grow on top of this block
+    pub fn is_fertile(&self) -> bool {
+        match &self.block_type {
+            BlockType::NormalCube { fertile, .. } => *fertile,
+            _ => false,
+        }
+    }
+
+    /// Get the kind of tool that breaks this block faster, if any
+    pub fn preferred_tool(&self) -> Option<ToolType> {
+        match &self.block_type {
+            BlockType::NormalCube { preferred_tool, .. } => *preferred_tool,
+            _ => None,
+        }
+    }
+
+    /// Get the tier of the preferred tool needed to break this block at the normal speed
+    pub fn min_tier(&self) -> u8 {
+        match &self.block_type {
+            BlockType::NormalCube { min_tier, .. } => *min_tier,
+            _ => 0,
+        }
+    }
+
+    /// Get the growth stage and the minimum light level of this block, if it is a crop
+    pub fn crop_stage(&self) -> Option<(u8, u8)> {
+        match &self.block_type {
+            BlockType::Crop {
+                stage, min_light, ..
+            } => Some((*stage, *min_light)),
+            _ => None,
+        }
+    }
+
+    /// Get the stage `stage` of this crop, if there is one.
+    /// Returns `None` if this block is not a crop.
+    pub fn variant_with_stage(&self, stage: u8, blocks: &Registry<Block>) -> Option<BlockId> {
+        let crop = match &self.block_type {
+            BlockType::Crop { crop, .. } => crop,
+            _ => return None,
+        };
+        (0..blocks.get_number_of_ids())
+            .find(
+                |&other| match &blocks.get_value_by_id(other).unwrap().block_type {
+                    BlockType::Crop {
+                        crop: other_crop,
+                        stage: other_stage,
+                        ..
+                    } => other_crop == crop && *other_stage == stage,
+                    _ => false,
+                },
+            )
+            .map(|other| other as BlockId)
+    }
+
+    /// Get the name of the kind of rail and the shape of this block, if it is a rail
+    pub fn rail(&self) -> Option<(&str, RailShape)> {
+        match &self.block_type {
+            BlockType::Rail { rail, shape, .. } => Some((rail, *shape)),
+            _ => None,
+        }
+    }
+
+    /// Get the variant of this rail with the shape `shape`, if there is one.
+    /// Returns `None` if this block is not a rail.
+    pub fn variant_with_rail_shape(
+        &self,
+        shape: RailShape,
+        blocks: &Registry<Block>,
+    ) -> Option<BlockId> {
+        let (rail, _) = self.rail()?;
+        (0..blocks.get_number_of_ids())
+            .find(|&other| blocks.get_value_by_id(other).unwrap().rail() == Some((rail, shape)))
+            .map(|other| other as BlockId)
+    }
+
+    /// Check if this block is a torch
+    pub fn is_torch(&self) -> bool {
+        match &self.block_type {
+            BlockType::Torch { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Get the variant of this torch that is attached to the side `side` of its cell, or that stands on the block
+    /// below if `side` is `None`. Returns `None` if this block is not a torch or if there is no such variant.
+    pub fn torch_variant(
+        &self,
+        side: Option<HorizontalFace>,
+        blocks: &Registry<Block>,
+    ) -> Option<BlockId> {
+        let texture = match &self.block_type {
+            BlockType::Torch { texture, .. } => texture,
+            _ => return None,
+        };
+        (0..blocks.get_number_of_ids())
+            .find(
+                |&other| match &blocks.get_value_by_id(other).unwrap().block_type {
+                    BlockType::Torch {
+                        texture: other_texture,
+                        attached_to,
+                        ..
+                    } => other_texture == texture && *attached_to == side,
+                    _ => false,
+                },
+            )
+            .map(|other| other as BlockId)
+    }
+
+    /// Get the variant of this block that is placed against the side `side` of its cell.
+    /// Only ladders have such variants, the other blocks are returned unchanged.
+    pub fn variant_attached_to(
+        &self,
+        id: BlockId,
+        side: HorizontalFace,
+        blocks: &Registry<Block>,
+    ) -> BlockId {
+        let texture = match &self.block_type {
+            BlockType::Ladder { texture, .. } => texture,
+            _ => return id,
+        };
+        (0..blocks.get_number_of_ids())
+            .find(
+                |&other| match &blocks.get_value_by_id(other).unwrap().block_type {
+                    BlockType::Ladder {
+                        texture: other_texture,
+                        attached_to,
+                        ..
+                    } => other_texture == texture && *attached_to == side,
+                    _ => false,
+                },
+            )
+            .map_or(id, |other| other as BlockId)
+    }
+
+    /// Get the variant of this layered block with `layers` layers, if there is one.
+    /// Returns `None` if this block is not made of layers.
+    pub fn variant_with_layers(&self, layers: u8, blocks: &Registry<Block>) -> Option<BlockId> {
+        let texture = match &self.block_type {
+            BlockType::Layer { texture, .. } => texture,
+            _ => return None,
+        };
+        (0..blocks.get_number_of_ids())
+            .find(
+                |&other| match &blocks.get_value_by_id(other).unwrap().block_type {
+                    BlockType::Layer {
+                        texture: other_texture,
+                        layers: other_layers,
+                        ..
+                    } => other_texture == texture && *other_layers == layers,
+                    _ => false,
+                },
+            )
+            .map(|other| other as BlockId)
+    }
 }
 
 /// The mesh of a block.
@@ -25,13 +545,48 @@ pub enum BlockMesh {
     /// No mesh
     Empty,
     /// A usual full cube
-    FullCube { textures: [TextureRect; 6] },
+    FullCube {
+        textures: [TextureRect; 6],
+        /// The biome color multiplied into each face
+        tints: [Option<BiomeTint>; 6],
+        /// True if the glow of the faces flickers like a flame
+        flickers: bool,
+    },
+    /// A single thin quad against one side of the cell
+    Ladder {
+        texture: TextureRect,
+        attached_to: HorizontalFace,
+    },
+    /// A box at the bottom of the cell, `layers / MAX_LAYERS` block high
+    Layer { texture: TextureRect, layers: u8 },
+    /// Four vertical quads crossing the cell, like a `#`
+    Crop { texture: TextureRect },
+    /// Two vertical quads crossing at the center of the cell, moved against the side it is attached to
+    Torch {
+        texture: TextureRect,
+        attached_to: Option<HorizontalFace>,
+        /// True if the glow of the quads flickers like a flame
+        flickers: bool,
+    },
+    /// A cube whose faces against the opaque blocks and the other portals are hidden, with a swirling texture
+    Portal { texture: TextureRect },
+    /// A quad just above the bottom of the cell, or going up to the top of the cell for the slopes
+    Rail {
+        texture: TextureRect,
+        shape: RailShape,
+    },
 }
 
 impl BlockMesh {
     pub fn is_opaque(&self) -> bool {
         match self {
-            Self::Empty => false,
+            Self::Empty
+            | Self::Ladder { .. }
+            | Self::Layer { .. }
+            | Self::Crop { .. }
+            | Self::Torch { .. }
+            | Self::Portal { .. }
+            | Self::Rail { .. } => false,
             Self::FullCube { .. } => true,
         }
     }
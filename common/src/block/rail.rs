@@ -0,0 +1,130 @@
+//! Rails: thin blocks that the carts follow. Every rail connects two sides of its cell, straight, with a quarter
+//! turn or up a slope. The shape is part of the block like the side of the ladders, and the server picks it from
+//! the rails around the block when they change.
+use super::HorizontalFace::{self, NegX, NegZ, PosX, PosZ};
+use serde::{Deserialize, Serialize};
+
+/// The path of a rail through its cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RailShape {
+    /// Straight along the x axis
+    StraightX,
+    /// Straight along the z axis
+    StraightZ,
+    /// A quarter turn connecting a side facing x and a side facing z, in this order
+    Curve(HorizontalFace, HorizontalFace),
+    /// Straight and going up towards this side, to the next cell one block higher
+    Slope(HorizontalFace),
+}
+
+impl RailShape {
+    /// Get the two sides of the cell that the rail connects
+    pub fn connections(self) -> [HorizontalFace; 2] {
+        match self {
+            Self::StraightX => [NegX, PosX],
+            Self::StraightZ => [NegZ, PosZ],
+            Self::Curve(x_side, z_side) => [x_side, z_side],
+            Self::Slope(up) => [up.opposite(), up],
+        }
+    }
+
+    /// Get the ends of the path of the carts on the rail, relative to the bottom corner of the cell, in the order of
+    /// `connections`. The ends are in the middle of the connected sides, and the path between them is straight.
+    pub fn path(self) -> [[f64; 3]; 2] {
+        let end = |side: HorizontalFace| {
+            let height = if self == Self::Slope(side) { 1.0 } else { 0.0 };
+            match side {
+                PosX => [1.0, height, 0.5],
+                NegX => [0.0, height, 0.5],
+                PosZ => [0.5, height, 1.0],
+                NegZ => [0.5, height, 0.0],
+            }
+        };
+        let [a, b] = self.connections();
+        [end(a), end(b)]
+    }
+
+    /// Pick the shape of a rail from the rails around it, `is_rail(side, dy)` telling if there is a rail next to
+    /// the side `side` of the cell, `dy` blocks higher. Two opposite neighbors are connected first, then a neighbor
+    /// facing x with a neighbor facing z, and a rail with one neighbor points towards it. A straight rail goes up
+    /// towards a neighbor one block higher. Returns `None` if there is no rail around.
+    pub fn from_neighbors(is_rail: impl Fn(HorizontalFace, i64) -> bool) -> Option<Self> {
+        let connected = |side: HorizontalFace| (-1..=1).any(|dy| is_rail(side, dy));
+        let x_sides: Vec<_> = [PosX, NegX]
+            .iter()
+            .copied()
+            .filter(|&side| connected(side))
+            .collect();
+        let z_sides: Vec<_> = [PosZ, NegZ]
+            .iter()
+            .copied()
+            .filter(|&side| connected(side))
+            .collect();
+        let shape = match (x_sides.as_slice(), z_sides.as_slice()) {
+            ([_, _], _) => Self::StraightX,
+            (_, [_, _]) => Self::StraightZ,
+            ([x_side], [z_side]) => Self::Curve(*x_side, *z_side),
+            ([_], []) => Self::StraightX,
+            ([], [_]) => Self::StraightZ,
+            _ => return None,
+        };
+        Some(match shape {
+            Self::StraightX | Self::StraightZ => shape
+                .connections()
+                .iter()
+                .find(|&&side| is_rail(side, 1))
+                .map_or(shape, |&side| Self::Slope(side)),
+            _ => shape,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pick the shape of a rail with the rails at `(side, dy)` around it
+    fn shape_with(rails: &[(HorizontalFace, i64)]) -> Option<RailShape> {
+        RailShape::from_neighbors(|side, dy| rails.contains(&(side, dy)))
+    }
+
+    #[test]
+    fn test_shape_from_neighbors() {
+        assert_eq!(shape_with(&[]), None);
+        assert_eq!(shape_with(&[(NegZ, 0)]), Some(RailShape::StraightZ));
+        assert_eq!(
+            shape_with(&[(PosX, 0), (NegX, -1)]),
+            Some(RailShape::StraightX)
+        );
+        // The straight rails are preferred over the turns
+        assert_eq!(
+            shape_with(&[(PosX, 0), (PosZ, 0), (NegZ, 0)]),
+            Some(RailShape::StraightZ)
+        );
+        assert_eq!(
+            shape_with(&[(NegX, 0), (PosZ, -1)]),
+            Some(RailShape::Curve(NegX, PosZ))
+        );
+        // Up towards the higher rail
+        assert_eq!(
+            shape_with(&[(PosX, 1), (NegX, 0)]),
+            Some(RailShape::Slope(PosX))
+        );
+        assert_eq!(shape_with(&[(NegZ, 1)]), Some(RailShape::Slope(NegZ)));
+    }
+
+    #[test]
+    fn test_paths_meet() {
+        // A slope up towards +x ends where the next rail one block higher starts
+        let [low, high] = RailShape::Slope(PosX).path();
+        assert_eq!(low, [0.0, 0.0, 0.5]);
+        assert_eq!(high, [1.0, 1.0, 0.5]);
+        let [start, _] = RailShape::StraightX.path();
+        assert_eq!([start[0] + 1.0, start[1] + 1.0, start[2]], high);
+        // A turn ends in the middle of its sides
+        assert_eq!(
+            RailShape::Curve(PosX, NegZ).path(),
+            [[1.0, 0.0, 0.5], [0.5, 0.0, 0.0]]
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! The sounds of the blocks. Every block has a sound material, that decides the sounds of the footsteps
+//! on the block, and the sounds made when it is broken or placed.
+//! The sound materials are defined by the content pack, see `ContentPack::sound_materials`.
+
+/// What a block sounds like: the id of a sound material in the sound material registry of the data.
+/// It is given by name in the `sound_material` field of the block data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoundMaterial(pub u32);
+
+/// The names of the sounds of a sound material
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoundSet {
+    pub footstep: String,
+    pub break_block: String,
+    pub place_block: String,
+}
+
+impl SoundMaterial {
+    /// The material of the blocks that don't specify one, or that have an unknown material.
+    /// It is the first sound material of the content pack.
+    pub const FALLBACK: Self = Self(0);
+}
+
+impl SoundSet {
+    /// The sounds named `step.<name>`, `break.<name>` and `place.<name>`
+    pub fn named(name: &str) -> Self {
+        Self {
+            footstep: format!("step.{}", name),
+            break_block: format!("break.{}", name),
+            place_block: format!("place.{}", name),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! The bars shown by the server at the top of the HUD, for example the countdown of an event or the health of a boss
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of bars shown at the same time, the server doesn't add more
+pub const MAX_BOSS_BARS: usize = 4;
+
+/// The color of the filled part of a bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BossBarColor {
+    White,
+    Red,
+    Green,
+    Blue,
+    Yellow,
+    Purple,
+}
+
+impl BossBarColor {
+    pub fn rgba(self) -> [f32; 4] {
+        match self {
+            Self::White => [0.9, 0.9, 0.9, 1.0],
+            Self::Red => [0.85, 0.15, 0.15, 1.0],
+            Self::Green => [0.2, 0.8, 0.25, 1.0],
+            Self::Blue => [0.2, 0.45, 0.9, 1.0],
+            Self::Yellow => [0.95, 0.8, 0.15, 1.0],
+            Self::Purple => [0.6, 0.25, 0.85, 1.0],
+        }
+    }
+}
+
+impl std::str::FromStr for BossBarColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "white" => Ok(Self::White),
+            "red" => Ok(Self::Red),
+            "green" => Ok(Self::Green),
+            "blue" => Ok(Self::Blue),
+            "yellow" => Ok(Self::Yellow),
+            "purple" => Ok(Self::Purple),
+            _ => Err(format!(
+                "Unknown color {}, it must be white, red, green, blue, yellow or purple",
+                s
+            )),
+        }
+    }
+}
+
+/// How the bar is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BossBarStyle {
+    /// A single continuous bar
+    Solid,
+    /// A bar divided in some number of segments
+    Notched(u8),
+}
+
+impl std::str::FromStr for BossBarStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "solid" => Ok(Self::Solid),
+            "notched_6" => Ok(Self::Notched(6)),
+            "notched_10" => Ok(Self::Notched(10)),
+            "notched_12" => Ok(Self::Notched(12)),
+            "notched_20" => Ok(Self::Notched(20)),
+            _ => Err(format!(
+                "Unknown style {}, it must be solid, notched_6, notched_10, notched_12 or notched_20",
+                s
+            )),
+        }
+    }
+}
+
+/// A bar as the server sends it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BossBar {
+    pub title: String,
+    /// Filled fraction of the bar, between 0 and 1
+    pub progress: f32,
+    pub color: BossBarColor,
+    pub style: BossBarStyle,
+}
+
+impl BossBar {
+    pub fn new(title: String) -> Self {
+        Self {
+            title,
+            progress: 1.0,
+            color: BossBarColor::White,
+            style: BossBarStyle::Solid,
+        }
+    }
+}
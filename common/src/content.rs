@@ -0,0 +1,163 @@
+//! The content of the game, separated from the engine. The engine only knows the blocks, the items and the models
+//! by their names: a `ContentPack` registers the blocks that the data files don't define, lists the voxel models
+//! to load, and chooses the models that the client draws.
+//! `DefaultContent` is the content of voxel-rs, and another game can reuse the engine with its own content pack.
+
+use crate::block::{
+    sound::{SoundMaterial, SoundSet},
+    Block, BlockMesh, BlockType,
+};
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Where a content pack registers its built-in blocks
+pub trait BlockRegistrar {
+    /// Register a block and its mesh, and return its id
+    fn register_block(&mut self, block: Block, mesh: BlockMesh) -> Result<u32>;
+}
+
+/// A model drawn at a fixed position of the world
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayedModel {
+    /// Name of the model in the model registry
+    pub model: String,
+    pub position: [f32; 3],
+    pub scale: f32,
+    /// Center of the rotation of the model, relative to its size
+    pub rot_offset: [f32; 3],
+    /// Whether the model spins around the vertical axis
+    pub spinning: bool,
+}
+
+/// The blocks placed by the world generator, by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldgenBlocks {
+    /// The block below the surface
+    pub stone: String,
+    /// The surface block above the sea level
+    pub grass: String,
+    /// The block right below the surface block
+    pub dirt_grass: String,
+    /// The blocks below `dirt_grass`, above the stone
+    pub dirt: String,
+    /// The block that fills the terrain below the sea level
+    pub water: String,
+    /// The surface blocks at and below the sea level
+    pub sand: String,
+    /// The leaves of the trees
+    pub leaves: String,
+    /// The trunk of the trees
+    pub wood: String,
+}
+
+/// The content of a game, on top of its data files
+pub trait ContentPack {
+    /// Register the blocks that are not defined by the data files.
+    /// The first block gets the id 0, and must be the empty block that fills the unloaded and empty parts of the world.
+    fn register_builtin_blocks(&self, registrar: &mut dyn BlockRegistrar) -> Result<()>;
+
+    /// The voxel models to load, by name, with their `.vox` file relative to the data directory
+    fn model_files(&self) -> Vec<(String, PathBuf)>;
+
+    /// Name of the model of the players. It is animated if an animated model has the same name.
+    fn player_model(&self) -> Option<String>;
+
+    /// The models drawn in the world, apart from the players
+    fn displayed_models(&self) -> Vec<DisplayedModel>;
+
+    /// The sound materials that the blocks refer to by name, with their sounds.
+    /// The first material is used for the blocks that don't specify one, or that have an unknown material.
+    fn sound_materials(&self) -> Vec<(String, SoundSet)>;
+
+    /// The blocks placed by the world generator
+    fn worldgen_blocks(&self) -> WorldgenBlocks;
+}
+
+/// The content of voxel-rs
+pub struct DefaultContent;
+
+impl ContentPack for DefaultContent {
+    fn register_builtin_blocks(&self, registrar: &mut dyn BlockRegistrar) -> Result<()> {
+        registrar.register_block(
+            Block {
+                name: "air".to_owned(),
+                block_type: BlockType::Air,
+                sound_material: SoundMaterial::FALLBACK,
+            },
+            BlockMesh::Empty,
+        )?;
+        Ok(())
+    }
+
+    fn model_files(&self) -> Vec<(String, PathBuf)> {
+        vec![
+            ("tree".to_owned(), "model/tree.vox".into()),
+            ("knight".to_owned(), "model/chr_knight.vox".into()),
+            ("boat".to_owned(), "model/boat.vox".into()),
+            ("cart".to_owned(), "model/cart.vox".into()),
+        ]
+    }
+
+    fn player_model(&self) -> Option<String> {
+        Some("knight".to_owned())
+    }
+
+    fn displayed_models(&self) -> Vec<DisplayedModel> {
+        vec![
+            DisplayedModel {
+                model: "knight".to_owned(),
+                position: [0.0, 55.0, 0.0],
+                scale: 0.3,
+                rot_offset: [0.0, 0.0, 0.0],
+                spinning: false,
+            },
+            DisplayedModel {
+                model: "item:ingot_iron".to_owned(),
+                position: [30.0, 55.0, 30.0],
+                scale: 1.0 / 32.0,
+                rot_offset: [0.5, 0.5, 1.0 / 64.0],
+                spinning: true,
+            },
+        ]
+    }
+    fn sound_materials(&self) -> Vec<(String, SoundSet)> {
+        let named = |name: &str| (name.to_owned(), SoundSet::named(name));
+        vec![
+            named("generic"),
+            named("stone"),
+            named("wood"),
+            named("grass"),
+            named("dirt"),
+            named("sand"),
+            // Leaves sound like grass when walking on them, but rustle when broken
+            (
+                "leaves".to_owned(),
+                SoundSet {
+                    break_block: "break.leaves".to_owned(),
+                    ..SoundSet::named("grass")
+                },
+            ),
+            named("water"),
+            (
+                "ladder".to_owned(),
+                SoundSet {
+                    footstep: "step.ladder".to_owned(),
+                    ..SoundSet::named("wood")
+                },
+            ),
+        ]
+    }
+
+    fn worldgen_blocks(&self) -> WorldgenBlocks {
+        WorldgenBlocks {
+            stone: "stone".to_owned(),
+            grass: "grass".to_owned(),
+            dirt_grass: "dirt_grass".to_owned(),
+            dirt: "dirt".to_owned(),
+            water: "water".to_owned(),
+            sand: "sand".to_owned(),
+            leaves: "leaves".to_owned(),
+            wood: "wood".to_owned(),
+        }
+    }
+}
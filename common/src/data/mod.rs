@@ -1,18 +1,28 @@
 pub mod vox;
 
 use crate::{
-    block::{Block, BlockMesh, BlockType},
-    registry::Registry,
+    animation::{split_model, AnimatedModel, AnimatedModelData, ModelPart},
+    block::{
+        sound::{SoundMaterial, SoundSet},
+        Block, BlockMesh, BlockType, MAX_LAYERS,
+    },
+    content::{BlockRegistrar, ContentPack, DisplayedModel},
+    registry::{Registry, TAG_PREFIX},
+    tags::{register_tags, TagData},
+    worldgen::WorldgenBlockIds,
 };
 
 use crate::data::vox::{load_voxel_model, VoxelModel};
 use crate::item::{Item, ItemMesh, ItemType};
-use anyhow::{Context, Result};
+use crate::smelting::{SmeltingRecipe, SmeltingRecipeData};
+use anyhow::{bail, Context, Result};
 use image::{ImageBuffer, Rgba};
 use log::info;
+use std::collections::HashMap;
 use std::fs;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use texture_packer::{TexturePacker, TexturePackerConfig};
 
 #[derive(Debug, Clone)]
@@ -20,17 +30,161 @@ pub struct Data {
     pub blocks: Registry<Block>,
     pub meshes: Vec<BlockMesh>,
     pub texture_atlas: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    /// The light emitted by the textures, at the same place as the textures in `texture_atlas`.
+    /// It is transparent where the textures don't glow.
+    pub emissive_atlas: ImageBuffer<Rgba<u8>, Vec<u8>>,
     pub models: Registry<VoxelModel>,
     pub items: Registry<Item>,
     pub item_meshes: Vec<ItemMesh>,
+    pub smelting_recipes: Vec<SmeltingRecipe>,
+    /// The models that are split into animated parts, by model name
+    pub animated_models: Registry<AnimatedModel>,
+    /// Name of the model of the players, chosen by the content pack
+    pub player_model: Option<String>,
+    /// The models drawn in the world, chosen by the content pack
+    pub displayed_models: Vec<DisplayedModel>,
+    /// The sounds of the `SoundMaterial`s of the blocks, defined by the content pack
+    pub sound_materials: Registry<SoundSet>,
+    /// The blocks placed by the world generator, chosen by the content pack
+    pub worldgen_blocks: WorldgenBlockIds,
 }
 
-// TODO: decent error handling
-pub fn load_data(data_directory: PathBuf) -> Result<Data> {
+/// Progress of the data loading, that can be read from another thread while the data is loading
+#[derive(Debug, Default)]
+pub struct LoadingProgress {
+    discovered: AtomicUsize,
+    processed: AtomicUsize,
+}
+
+impl LoadingProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of data files that will be loaded
+    pub fn files_discovered(&self) -> usize {
+        self.discovered.load(Ordering::Relaxed)
+    }
+
+    /// Number of data files that were loaded
+    pub fn files_processed(&self) -> usize {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// Fraction of the data files that were loaded, between 0 and 1
+    pub fn fraction(&self) -> f32 {
+        let discovered = self.files_discovered();
+        if discovered == 0 {
+            0.0
+        } else {
+            (self.files_processed() as f32 / discovered as f32).min(1.0)
+        }
+    }
+
+    fn discover(&self, count: usize) {
+        self.discovered.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn process_file(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// An error in a data file
+#[derive(Debug, Clone)]
+pub struct DataError {
+    pub file: PathBuf,
+    pub message: String,
+}
+
+/// All the errors found in the data files. The data is only loaded if there are none,
+/// so that all the errors can be fixed at once.
+#[derive(Debug, Clone, Default, thiserror::Error)]
+#[error("{} error(s) in the data files:{}", .errors.len(), list_errors(.errors))]
+pub struct DataErrors {
+    pub errors: Vec<DataError>,
+}
+
+impl DataErrors {
+    fn add(&mut self, file: impl Into<PathBuf>, message: impl Into<String>) {
+        let error = DataError {
+            file: file.into(),
+            message: message.into(),
+        };
+        log::error!("Data error in {}: {}", error.file.display(), error.message);
+        self.errors.push(error);
+    }
+}
+
+/// One line per error, each starting with a line break
+fn list_errors(errors: &[DataError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("\n{}: {}", error.file.display(), error.message))
+        .collect()
+}
+
+/// Get the rectangle of a texture in the atlas, or report an error in `file` if the texture doesn't exist
+fn find_texture(
+    texture_registry: &Registry<()>,
+    texture_rects: &[TextureRect],
+    name: &str,
+    file: &Path,
+    errors: &mut DataErrors,
+) -> TextureRect {
+    match texture_registry.get_id_by_name(&name.to_owned()) {
+        Some(id) => texture_rects[id as usize],
+        None => {
+            errors.add(file, format!("unknown texture {}", name));
+            TextureRect::default()
+        }
+    }
+}
+
+/// The folders containing one data file per texture, tag, item, smelting recipe, block or animated model
+const DATA_FOLDERS: [&str; 6] = [
+    "textures",
+    "tags",
+    "items",
+    "smelting",
+    "blocks",
+    "animations",
+];
+
+/// Count the files of the data directory that will be loaded, with the `model_files` of the content pack
+fn count_data_files(data_directory: &Path, model_files: usize) -> usize {
+    let folder_files = DATA_FOLDERS
+        .iter()
+        .map(|folder| {
+            fs::read_dir(data_directory.join(folder))
+                .map(|entries| entries.filter(|entry| entry.is_ok()).count())
+                .unwrap_or(0)
+        })
+        .sum::<usize>();
+    folder_files + model_files
+}
+
+/// Load the data of a content pack. All the errors in the data files are collected into a `DataErrors`.
+pub fn load_data(data_directory: PathBuf, content: &dyn ContentPack) -> Result<Data> {
+    load_data_with_progress(data_directory, content, &LoadingProgress::new())
+}
+
+/// Load the data of a content pack, and report the progress in `progress`.
+/// All the errors in the data files are collected into a `DataErrors`.
+pub fn load_data_with_progress(
+    data_directory: PathBuf,
+    content: &dyn ContentPack,
+    progress: &LoadingProgress,
+) -> Result<Data> {
     info!("Loading data from directory {}", data_directory.display());
+    let model_files = content.model_files();
+    progress.discover(count_data_files(&data_directory, model_files.len()));
+    let mut errors = DataErrors::default();
 
     // Load textures
     let mut textures: Vec<PathBuf> = Vec::new();
+    // The `<name>_e.png` files are the emissive maps of the `<name>` textures, not textures themselves
+    let mut emissive_textures: Vec<(String, PathBuf)> = Vec::new();
     let mut texture_registry: Registry<()> = Default::default();
     let textures_directory = data_directory.join("textures");
     info!(
@@ -45,21 +199,30 @@ pub fn load_data(data_directory: PathBuf) -> Result<Data> {
             .is_file()
         {
             let file_path = dir_entry.path();
+            let stem = file_path
+                .file_stem()
+                .context("failed to get file stem")?
+                .to_string_lossy()
+                .into_owned();
 
-            texture_registry.register(
-                file_path
-                    .file_stem()
-                    .context("failed to get file stem")?
-                    .to_str()
-                    .unwrap()
-                    .to_owned(),
-                (),
-            )?;
-            textures.push(file_path);
+            match stem.strip_suffix(EMISSIVE_SUFFIX) {
+                Some(texture) => emissive_textures.push((texture.to_owned(), file_path)),
+                None => {
+                    texture_registry.register(stem, ())?;
+                    textures.push(file_path);
+                }
+            }
         }
     }
 
-    let (texture_atlas, texture_rects) = load_textures(textures)?;
+    let (texture_atlas, texture_rects) = load_textures(textures, progress, &mut errors)?;
+    let emissive_atlas = load_emissive_textures(
+        emissive_textures,
+        &texture_registry,
+        &texture_rects,
+        progress,
+        &mut errors,
+    );
 
     //Load model
     let mut models = Registry::default();
@@ -90,30 +253,46 @@ pub fn load_data(data_directory: PathBuf) -> Result<Data> {
     };*/
 
     // TODO : load every .vox in the model folder
-    let model_tree =
-        load_voxel_model(data_directory.join("model/tree.vox").to_str().unwrap()).unwrap();
-    models.register("tree".to_owned(), model_tree)?;
-    let model_knight = load_voxel_model(
-        data_directory
-            .join("model/chr_knight.vox")
-            .to_str()
-            .unwrap(),
-    )
-    .unwrap();
-    models.register("knight".to_owned(), model_knight)?;
+    for (name, file) in model_files.into_iter() {
+        let path = data_directory.join(file);
+        match load_voxel_model(&path.to_string_lossy()) {
+            Ok(model) => {
+                models.register(name, model)?;
+            }
+            Err(e) => errors.add(&path, format!("{:#}", e)),
+        }
+        progress.process_file();
+    }
+
+    // Load tags, that are added to the registries once their blocks and items are loaded
+    let tags_directory = data_directory.join("tags");
+    let tag_datas: Vec<(String, TagData)> =
+        load_files_from_folder(&tags_directory, progress, &mut errors);
+    let mut block_tags = HashMap::new();
+    let mut item_tags = HashMap::new();
+    for (name, tag) in tag_datas.into_iter() {
+        block_tags.insert(name.clone(), tag.blocks);
+        item_tags.insert(name, tag.items);
+    }
 
     // Load items
     let items_directory = data_directory.join("items");
-    let item_datas: Vec<(String, ItemType)> = load_files_from_folder(items_directory);
+    let item_datas: Vec<(String, ItemType)> =
+        load_files_from_folder(&items_directory, progress, &mut errors);
     let mut items = Registry::default();
     let mut item_meshes = Vec::new();
 
     // Generate item models
     for (name, ty) in item_datas.into_iter() {
         match &ty {
-            ItemType::NormalItem { texture } => {
-                let texture_rect =
-                    texture_rects[texture_registry.get_id_by_name(texture).unwrap() as usize];
+            ItemType::NormalItem { texture, .. } => {
+                let texture_rect = find_texture(
+                    &texture_registry,
+                    &texture_rects,
+                    texture,
+                    &items_directory.join(format!("{}.ron", name)),
+                    &mut errors,
+                );
                 let model = self::vox::item::generate_item_model(texture_rect, &texture_atlas);
                 let mesh_center = (
                     model.size_x as f32 / 2.0,
@@ -121,12 +300,8 @@ pub fn load_data(data_directory: PathBuf) -> Result<Data> {
                     model.size_z as f32 / 2.0,
                 );
                 let scale = 1.0 / usize::max(model.size_x, model.size_y) as f32;
-                let mesh_id = models
-                    .register(format!("item:{}", name), model)
-                    .expect("Failed to register item model");
-                items
-                    .register(name.clone(), Item { name, ty })
-                    .expect("Failed to register item");
+                let mesh_id = models.register(format!("item:{}", name), model)?;
+                items.register(name.clone(), Item { name, ty })?;
                 item_meshes.push(ItemMesh::SimpleMesh {
                     mesh_id,
                     scale,
@@ -136,61 +311,448 @@ pub fn load_data(data_directory: PathBuf) -> Result<Data> {
         }
     }
 
+    for (tag, message) in register_tags(&mut items, &item_tags) {
+        errors.add(tags_directory.join(format!("{}.ron", tag)), message);
+    }
+
+    // Load animations
+    let animations_directory = data_directory.join("animations");
+    let animation_datas: Vec<(String, AnimatedModelData)> =
+        load_files_from_folder(&animations_directory, progress, &mut errors);
+    let mut animated_models = Registry::default();
+    for (name, animation_data) in animation_datas.into_iter() {
+        let animation_file = animations_directory.join(format!("{}.ron", name));
+        if let Some(animated_model) = load_animated_model(
+            &name,
+            animation_data,
+            &mut models,
+            &animation_file,
+            &mut errors,
+        ) {
+            animated_models.register(name, animated_model)?;
+        }
+    }
+
+    // Load smelting recipes
+    let smelting_directory = data_directory.join("smelting");
+    let recipe_datas: Vec<(String, SmeltingRecipeData)> =
+        load_files_from_folder(&smelting_directory, progress, &mut errors);
+    let mut smelting_recipes = Vec::new();
+    for (name, recipe) in recipe_datas.into_iter() {
+        let recipe_file = smelting_directory.join(format!("{}.ron", name));
+        // A recipe with a tag as input smelts every item of the tag
+        let inputs = items.resolve(&recipe.input);
+        if inputs.is_none() {
+            errors.add(&recipe_file, format!("unknown input item {}", recipe.input));
+        }
+        let output = if recipe.output.starts_with(TAG_PREFIX) {
+            errors.add(
+                &recipe_file,
+                format!("the output {} must be an item, not a tag", recipe.output),
+            );
+            None
+        } else {
+            let output = items.get_id_by_name(&recipe.output);
+            if output.is_none() {
+                errors.add(
+                    &recipe_file,
+                    format!("unknown output item {}", recipe.output),
+                );
+            }
+            output
+        };
+        if let (Some(inputs), Some(output)) = (inputs, output) {
+            for input in inputs {
+                smelting_recipes.push(SmeltingRecipe {
+                    input,
+                    output,
+                    output_count: recipe.output_count,
+                    time: recipe.time,
+                });
+            }
+        }
+    }
+
     // Load blocks
     let blocks_directory = data_directory.join("blocks");
-    let block_datas: Vec<(String, BlockType)> = load_files_from_folder(blocks_directory);
+    let block_datas: Vec<(String, BlockType)> =
+        load_files_from_folder(&blocks_directory, progress, &mut errors);
+
+    let mut sound_materials = Registry::default();
+    for (name, sound_set) in content.sound_materials() {
+        sound_materials.register(name, sound_set)?;
+    }
+    let fallback_material = match sound_materials.get_name_by_id(SoundMaterial::FALLBACK.0) {
+        Some(name) => name.clone(),
+        None => bail!("The content pack must define at least one sound material"),
+    };
 
     info!("Processing collected block and texture data");
     let mut blocks = Registry::default();
     let mut meshes = Vec::new();
-    // Add air
-    blocks
-        .register(
-            "air".to_owned(),
-            Block {
-                name: "air".to_owned(),
-                block_type: BlockType::Air,
-            },
-        )
-        .expect("Couldn't register air in the registry.");
-    meshes.push(BlockMesh::Empty);
+    // Add the built-in blocks, starting with the empty block
+    content.register_builtin_blocks(&mut BlockRegistration {
+        blocks: &mut blocks,
+        meshes: &mut meshes,
+    })?;
+    if !matches!(meshes.first(), Some(BlockMesh::Empty)) {
+        bail!("The first built-in block of the content pack must have an empty mesh");
+    }
 
     for (name, block_type) in block_datas.into_iter() {
-        let block = Block {
+        let block_file = blocks_directory.join(format!("{}.ron", name));
+        let mut block = Block {
             name: name.clone(),
             block_type: block_type.clone(),
+            sound_material: SoundMaterial::FALLBACK,
         };
-        blocks.register(name, block)?;
+        if let Some(material_name) = block.sound_material_name() {
+            match sound_materials.get_id_by_name(&material_name.to_owned()) {
+                Some(material) => block.sound_material = SoundMaterial(material),
+                None => log::warn!(
+                    "Unknown sound material {} in block {}, using {} instead",
+                    material_name,
+                    name,
+                    fallback_material
+                ),
+            }
+        }
+        blocks.register(name.clone(), block)?;
         let mesh = match block_type {
             BlockType::Air => BlockMesh::Empty,
-            // TODO: make sure there are exactly 6 face textures
             BlockType::NormalCube {
                 face_textures: names,
-            } => BlockMesh::FullCube {
-                textures: [
-                    texture_rects[texture_registry.get_id_by_name(&names[0]).unwrap() as usize],
-                    texture_rects[texture_registry.get_id_by_name(&names[1]).unwrap() as usize],
-                    texture_rects[texture_registry.get_id_by_name(&names[2]).unwrap() as usize],
-                    texture_rects[texture_registry.get_id_by_name(&names[3]).unwrap() as usize],
-                    texture_rects[texture_registry.get_id_by_name(&names[4]).unwrap() as usize],
-                    texture_rects[texture_registry.get_id_by_name(&names[5]).unwrap() as usize],
-                ],
+                face_tints,
+                flickers,
+                ..
+            } => {
+                if names.len() != 6 {
+                    errors.add(
+                        &block_file,
+                        format!("expected 6 face textures, found {}", names.len()),
+                    );
+                }
+                if !face_tints.is_empty() && face_tints.len() != 6 {
+                    errors.add(
+                        &block_file,
+                        format!("expected 0 or 6 face tints, found {}", face_tints.len()),
+                    );
+                }
+                let mut tints = [None; 6];
+                for (tint, face_tint) in tints.iter_mut().zip(face_tints.iter()) {
+                    *tint = *face_tint;
+                }
+                let mut textures = [TextureRect::default(); 6];
+                for (texture, name) in textures.iter_mut().zip(names.iter()) {
+                    *texture = find_texture(
+                        &texture_registry,
+                        &texture_rects,
+                        name,
+                        &block_file,
+                        &mut errors,
+                    );
+                }
+                BlockMesh::FullCube {
+                    textures,
+                    tints,
+                    flickers,
+                }
+            }
+            BlockType::Ladder {
+                texture,
+                attached_to,
+                ..
+            } => BlockMesh::Ladder {
+                texture: find_texture(
+                    &texture_registry,
+                    &texture_rects,
+                    &texture,
+                    &block_file,
+                    &mut errors,
+                ),
+                attached_to,
+            },
+            BlockType::Layer {
+                texture, layers, ..
+            } => {
+                if layers == 0 || layers > MAX_LAYERS {
+                    errors.add(
+                        &block_file,
+                        format!("expected 1 to {} layers, found {}", MAX_LAYERS, layers),
+                    );
+                }
+                BlockMesh::Layer {
+                    texture: find_texture(
+                        &texture_registry,
+                        &texture_rects,
+                        &texture,
+                        &block_file,
+                        &mut errors,
+                    ),
+                    layers,
+                }
+            }
+            BlockType::Crop { texture, .. } => BlockMesh::Crop {
+                texture: find_texture(
+                    &texture_registry,
+                    &texture_rects,
+                    &texture,
+                    &block_file,
+                    &mut errors,
+                ),
+            },
+            BlockType::Torch {
+                texture,
+                attached_to,
+                light_level,
+                flickers,
+                ..
+            } => {
+                if light_level > 15 {
+                    errors.add(
+                        &block_file,
+                        format!("expected a light level of 0 to 15, found {}", light_level),
+                    );
+                }
+                BlockMesh::Torch {
+                    texture: find_texture(
+                        &texture_registry,
+                        &texture_rects,
+                        &texture,
+                        &block_file,
+                        &mut errors,
+                    ),
+                    attached_to,
+                    flickers,
+                }
+            }
+            BlockType::Portal {
+                texture,
+                light_level,
+                ..
+            } => {
+                if light_level > 15 {
+                    errors.add(
+                        &block_file,
+                        format!("expected a light level of 0 to 15, found {}", light_level),
+                    );
+                }
+                BlockMesh::Portal {
+                    texture: find_texture(
+                        &texture_registry,
+                        &texture_rects,
+                        &texture,
+                        &block_file,
+                        &mut errors,
+                    ),
+                }
+            }
+            BlockType::Rail { texture, shape, .. } => BlockMesh::Rail {
+                texture: find_texture(
+                    &texture_registry,
+                    &texture_rects,
+                    &texture,
+                    &block_file,
+                    &mut errors,
+                ),
+                shape,
             },
         };
         meshes.push(mesh);
     }
 
+    for (tag, message) in register_tags(&mut blocks, &block_tags) {
+        errors.add(tags_directory.join(format!("{}.ron", tag)), message);
+    }
+    // The blocks that the items are effective on can only be checked once the blocks are loaded
+    for id in 0..items.get_number_of_ids() {
+        let item = items.get_value_by_id(id).unwrap();
+        match &item.ty {
+            ItemType::NormalItem { effective_on, .. } => {
+                for name in effective_on.iter() {
+                    if blocks.resolve(name).is_none() {
+                        errors.add(
+                            items_directory.join(format!("{}.ron", item.name)),
+                            format!("unknown block {}", name),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    if !errors.errors.is_empty() {
+        return Err(errors.into());
+    }
+    let worldgen_blocks = WorldgenBlockIds::resolve(&content.worldgen_blocks(), &blocks)
+        .context("invalid world generation blocks in the content pack")?;
     info!("Data successfully loaded");
     Ok(Data {
         blocks,
         meshes,
         texture_atlas,
+        emissive_atlas,
         models,
         items,
         item_meshes,
+        smelting_recipes,
+        animated_models,
+        player_model: content.player_model(),
+        displayed_models: content.displayed_models(),
+        sound_materials,
+        worldgen_blocks,
+    })
+}
+
+/// Registration of the built-in blocks of a content pack
+struct BlockRegistration<'a> {
+    blocks: &'a mut Registry<Block>,
+    meshes: &'a mut Vec<BlockMesh>,
+}
+
+impl BlockRegistrar for BlockRegistration<'_> {
+    fn register_block(&mut self, block: Block, mesh: BlockMesh) -> Result<u32> {
+        let id = self.blocks.register(block.name.clone(), block)?;
+        self.meshes.push(mesh);
+        Ok(id)
+    }
+}
+
+/// Split a model into the parts of its animation data, and register the parts in the model registry.
+/// The voxels outside of the parts are not drawn.
+fn load_animated_model(
+    name: &str,
+    data: AnimatedModelData,
+    models: &mut Registry<VoxelModel>,
+    file: &Path,
+    errors: &mut DataErrors,
+) -> Option<AnimatedModel> {
+    let model = match models
+        .get_id_by_name(&name.to_owned())
+        .and_then(|id| models.get_value_by_id(id))
+    {
+        Some(model) => model.clone(),
+        None => {
+            errors.add(file, format!("unknown model {}", name));
+            return None;
+        }
+    };
+    let error_count = errors.errors.len();
+    let size = [model.size_x, model.size_y, model.size_z];
+    for part in data.parts.iter() {
+        if (0..3).any(|i| part.min[i] >= part.max[i] || part.max[i] > size[i]) {
+            errors.add(
+                file,
+                format!(
+                    "part {} is not a box inside the {}x{}x{} model",
+                    part.name, size[0], size[1], size[2]
+                ),
+            );
+        }
+    }
+    for (animation_name, animation) in data.animations.iter() {
+        let times = animation.keyframes.iter().map(|keyframe| keyframe.time);
+        if times
+            .clone()
+            .zip(times.skip(1))
+            .any(|(time, next_time)| time > next_time)
+            || animation
+                .keyframes
+                .iter()
+                .any(|keyframe| keyframe.time < 0.0 || keyframe.time > animation.duration)
+        {
+            errors.add(
+                file,
+                format!(
+                    "the keyframes of animation {} are not sorted between 0 and its duration",
+                    animation_name
+                ),
+            );
+        }
+        for keyframe in animation.keyframes.iter() {
+            for part_name in keyframe.parts.keys() {
+                if !data.parts.iter().any(|part| &part.name == part_name) {
+                    errors.add(
+                        file,
+                        format!("unknown part {} in animation {}", part_name, animation_name),
+                    );
+                }
+            }
+        }
+    }
+    if errors.errors.len() > error_count {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    for part in data.parts.into_iter() {
+        let mesh_id = match models.register(
+            format!("{}:{}", name, part.name),
+            split_model(&model, part.min, part.max),
+        ) {
+            Ok(mesh_id) => mesh_id,
+            Err(e) => {
+                errors.add(file, e.to_string());
+                continue;
+            }
+        };
+        parts.push(ModelPart {
+            name: part.name,
+            mesh_id,
+            offset: [part.min[0] as f32, part.min[1] as f32, part.min[2] as f32],
+            pivot: part.pivot,
+        });
+    }
+    Some(AnimatedModel {
+        parts,
+        animations: data.animations,
     })
 }
 
+/// Compute the color of every block: the average color of its top texture, or transparent if it has no mesh
+pub fn compute_block_colors(
+    block_meshes: &[BlockMesh],
+    texture_atlas: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+) -> Vec<[u8; 4]> {
+    block_meshes
+        .iter()
+        .map(|mesh| {
+            let rect = match mesh {
+                BlockMesh::Empty | BlockMesh::Ladder { .. } => return [0, 0, 0, 0],
+                BlockMesh::FullCube { textures, .. } => textures[2],
+                BlockMesh::Layer { texture, .. }
+                | BlockMesh::Crop { texture }
+                | BlockMesh::Torch { texture, .. }
+                | BlockMesh::Portal { texture }
+                | BlockMesh::Rail { texture, .. } => *texture,
+            };
+            let (width, height) = (texture_atlas.width(), texture_atlas.height());
+            let x0 = (rect.x * width as f32) as u32;
+            let y0 = (rect.y * height as f32) as u32;
+            let x1 = ((rect.x + rect.width) * width as f32) as u32;
+            let y1 = ((rect.y + rect.height) * height as f32) as u32;
+            let mut sum = [0u64; 3];
+            let mut count = 0u64;
+            for y in y0..y1.min(height) {
+                for x in x0..x1.min(width) {
+                    let pixel = texture_atlas.get_pixel(x, y);
+                    for (s, &c) in sum.iter_mut().zip(pixel.0.iter()) {
+                        *s += c as u64;
+                    }
+                    count += 1;
+                }
+            }
+            let count = count.max(1);
+            [
+                (sum[0] / count) as u8,
+                (sum[1] / count) as u8,
+                (sum[2] / count) as u8,
+                255,
+            ]
+        })
+        .collect()
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct TextureRect {
     pub x: f32,
@@ -214,108 +776,192 @@ const TEXTURE_PACKER_CONFIG: TexturePackerConfig = TexturePackerConfig {
 /// Load given textures to a unique texture atlas
 fn load_textures(
     textures: Vec<PathBuf>,
+    progress: &LoadingProgress,
+    errors: &mut DataErrors,
 ) -> Result<(ImageBuffer<Rgba<u8>, Vec<u8>>, Vec<TextureRect>)> {
-    use image::GenericImage;
+    use image::{GenericImage, GenericImageView};
     use texture_packer::{exporter::ImageExporter, importer::ImageImporter};
 
     let mut packer = TexturePacker::new_skyline(TEXTURE_PACKER_CONFIG);
     for (i, path) in textures.iter().enumerate() {
-        packer
-            .pack_own(
-                format!("{}", i),
-                ImageImporter::import_from_file(path).expect("Failed to read texture to pack"),
-            )
-            .expect("Failed to pack textures");
+        progress.process_file();
+        let texture = match ImageImporter::import_from_file(path) {
+            Ok(texture) => texture,
+            Err(e) => {
+                errors.add(path, format!("couldn't read the texture: {}", e));
+                continue;
+            }
+        };
+        if texture.width() != texture.height() {
+            errors.add(
+                path,
+                format!(
+                    "the texture is not square ({}x{})",
+                    texture.width(),
+                    texture.height()
+                ),
+            );
+        }
+        if packer.pack_own(format!("{}", i), texture).is_err() {
+            errors.add(path, "the texture doesn't fit in the texture atlas");
+        }
     }
 
     let mut texture_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> =
         ImageBuffer::new(MAX_TEXTURE_SIZE, MAX_TEXTURE_SIZE);
+    let packed_textures = ImageExporter::export(&packer)
+        .map_err(|e| anyhow::anyhow!("failed to export the textures from the packer: {}", e))?;
     texture_buffer
-        .copy_from(
-            &ImageExporter::export(&packer).expect("Failed to export texture from packer"),
-            0,
-            0,
-        )
-        .expect("Failed to copy texture atlas to buffer");
-    texture_buffer
-        .save("atlas.png")
-        .expect("Failed to save texture atlas");
+        .copy_from(&packed_textures, 0, 0)
+        .context("failed to copy the textures to the texture atlas")?;
+    // The atlas is only saved to help debugging the textures
+    if let Err(e) = texture_buffer.save("atlas.png") {
+        log::warn!("Failed to save the texture atlas: {}", e);
+    }
     Ok((
         texture_buffer,
         (0..textures.len())
             .map(|i| {
-                let frame = packer
+                // The textures that couldn't be packed were reported as errors
+                packer
                     .get_frame(&format!("{}", i))
-                    .expect("Texture packer frame key doesn't exist")
-                    .frame;
-                TextureRect {
-                    x: frame.x as f32 / MAX_TEXTURE_SIZE as f32,
-                    y: frame.y as f32 / MAX_TEXTURE_SIZE as f32,
-                    width: frame.w as f32 / MAX_TEXTURE_SIZE as f32,
-                    height: frame.h as f32 / MAX_TEXTURE_SIZE as f32,
-                }
+                    .map(|frame| TextureRect {
+                        x: frame.frame.x as f32 / MAX_TEXTURE_SIZE as f32,
+                        y: frame.frame.y as f32 / MAX_TEXTURE_SIZE as f32,
+                        width: frame.frame.w as f32 / MAX_TEXTURE_SIZE as f32,
+                        height: frame.frame.h as f32 / MAX_TEXTURE_SIZE as f32,
+                    })
+                    .unwrap_or_default()
             })
             .collect(),
     ))
 }
 
+/// Suffix of the name of the emissive map of a texture
+const EMISSIVE_SUFFIX: &str = "_e";
+
+/// Load the emissive maps of the textures to an atlas where each map is at the place of its texture
+fn load_emissive_textures(
+    emissive_textures: Vec<(String, PathBuf)>,
+    texture_registry: &Registry<()>,
+    texture_rects: &[TextureRect],
+    progress: &LoadingProgress,
+    errors: &mut DataErrors,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    use image::GenericImage;
+
+    let mut emissive_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> =
+        ImageBuffer::new(MAX_TEXTURE_SIZE, MAX_TEXTURE_SIZE);
+    for (texture, path) in emissive_textures {
+        progress.process_file();
+        let rect = match texture_registry.get_id_by_name(&texture) {
+            Some(id) => texture_rects[id as usize],
+            None => {
+                errors.add(
+                    &path,
+                    format!("emissive map of unknown texture {}", texture),
+                );
+                continue;
+            }
+        };
+        let emissive = match image::open(&path) {
+            Ok(emissive) => emissive.to_rgba(),
+            Err(e) => {
+                errors.add(&path, format!("couldn't read the emissive map: {}", e));
+                continue;
+            }
+        };
+        let (x, y, width, height) = (
+            (rect.x * MAX_TEXTURE_SIZE as f32) as u32,
+            (rect.y * MAX_TEXTURE_SIZE as f32) as u32,
+            (rect.width * MAX_TEXTURE_SIZE as f32) as u32,
+            (rect.height * MAX_TEXTURE_SIZE as f32) as u32,
+        );
+        if emissive.dimensions() != (width, height) {
+            errors.add(
+                &path,
+                format!(
+                    "the emissive map ({}x{}) doesn't have the size of the texture {} ({}x{})",
+                    emissive.width(),
+                    emissive.height(),
+                    texture,
+                    width,
+                    height
+                ),
+            );
+            continue;
+        }
+        if let Err(e) = emissive_buffer.copy_from(&emissive, x, y) {
+            errors.add(&path, format!("couldn't copy the emissive map: {}", e));
+        }
+    }
+    emissive_buffer
+}
+
 /// Load all <name>.ron files from a given folder and parse them into type `T`.
-fn load_files_from_folder<T: serde::de::DeserializeOwned>(directory: PathBuf) -> Vec<(String, T)> {
+/// The files that can't be read or parsed are reported in `errors`.
+fn load_files_from_folder<T: serde::de::DeserializeOwned>(
+    directory: &Path,
+    progress: &LoadingProgress,
+    errors: &mut DataErrors,
+) -> Vec<(String, T)> {
     let mut result = Vec::new();
     info!(
         "Loading objects of type {} from directory {}",
         std::any::type_name::<T>(),
         directory.display(),
     );
-    for dir_entry in fs::read_dir(directory).expect("Failed to read from directory") {
-        let dir_entry = dir_entry.expect("Failed to read directory entry");
-        if dir_entry
-            .file_type()
-            .expect("Failed to get file type")
-            .is_file()
-        {
-            let file_path = dir_entry.path();
+    let dir_entries = match fs::read_dir(directory) {
+        Ok(dir_entries) => dir_entries,
+        Err(e) => {
+            errors.add(directory, format!("couldn't read the directory: {}", e));
+            return result;
+        }
+    };
+    for dir_entry in dir_entries {
+        progress.process_file();
+        let file_path = match dir_entry {
+            Ok(dir_entry) => dir_entry.path(),
+            Err(e) => {
+                errors.add(directory, format!("couldn't read a directory entry: {}", e));
+                continue;
+            }
+        };
+        if !file_path.is_file() {
+            continue;
+        }
 
-            match file_path.extension() {
-                None => log::warn!(
-                    "No file extension for file {}, skipping...",
-                    file_path.display()
-                ),
-                Some(ext) => {
-                    if ext == "ron" {
-                        log::info!("Attempting to read file {}", file_path.display());
-                        let mut file =
-                            fs::File::open(file_path.clone()).expect("Failed to open file");
-                        let mut buffer = String::new();
-                        file.read_to_string(&mut buffer)
-                            .expect("Failed to read from file");
-                        let file_stem = file_path
-                            .file_stem()
-                            .expect("Failed to get file stem")
-                            .to_str()
-                            .unwrap()
-                            .to_owned();
-
-                        let parsed_file = {
-                            if ext == "ron" {
-                                match ron::de::from_str(&buffer) {
-                                    Ok(x) => x,
-                                    Err(e) => {
-                                        log::error!("Failed to parse RON: {}, skipping...", e);
-                                        continue;
-                                    }
-                                }
-                            } else {
-                                unreachable!("No parser for file format");
-                            }
-                        };
-                        result.push((file_stem, parsed_file));
-                    } else {
-                        log::warn!("Unsupported file extension {:?}, skipping...", ext);
-                        // TODO: display instead of debug
+        match file_path.extension() {
+            None => log::warn!(
+                "No file extension for file {}, skipping...",
+                file_path.display()
+            ),
+            Some(ext) if ext == "ron" => {
+                log::info!("Attempting to read file {}", file_path.display());
+                let mut buffer = String::new();
+                if let Err(e) =
+                    fs::File::open(&file_path).and_then(|mut file| file.read_to_string(&mut buffer))
+                {
+                    errors.add(&file_path, format!("couldn't read the file: {}", e));
+                    continue;
+                }
+                let file_stem = match file_path.file_stem() {
+                    Some(file_stem) => file_stem.to_string_lossy().into_owned(),
+                    None => {
+                        errors.add(&file_path, "the file has no name");
+                        continue;
                     }
+                };
+
+                match ron::de::from_str(&buffer) {
+                    Ok(parsed_file) => result.push((file_stem, parsed_file)),
+                    Err(e) => errors.add(&file_path, format!("invalid RON: {}", e)),
                 }
             }
+            Some(ext) => log::warn!(
+                "Unsupported file extension {}, skipping...",
+                ext.to_string_lossy()
+            ),
         }
     }
     result
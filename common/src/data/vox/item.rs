@@ -1,4 +1,4 @@
-use crate::data::vox::VoxelModel;
+use crate::data::vox::{VoxelMaterial, VoxelModel};
 use crate::data::{TextureRect, MAX_TEXTURE_SIZE};
 use image::{ImageBuffer, Rgba};
 
@@ -33,6 +33,7 @@ pub fn generate_item_model(
         size_x: width as usize,
         size_y: height as usize,
         size_z: 1,
+        materials: vec![VoxelMaterial::default(); voxels.len()],
         voxels,
         full,
     }
@@ -1,3 +1,5 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 use std::str::from_utf8;
@@ -39,6 +41,15 @@ const DEFAULT_PALETTE: [u32; 256] = [
     0xffbbbbbb, 0xffaaaaaa, 0xff888888, 0xff777777, 0xff555555, 0xff444444, 0xff222222, 0xff111111,
 ];
 
+/// Material of a voxel, from the material chunks of the `.vox` file
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VoxelMaterial {
+    /// Intensity of the light emitted by the voxel, 0 if it doesn't emit light
+    pub emission: f32,
+    /// Between 0 for an opaque voxel and 1 for a fully transparent voxel
+    pub transparency: f32,
+}
+
 #[derive(Debug, Clone)]
 pub struct VoxelModel {
     pub size_x: usize,
@@ -46,126 +57,515 @@ pub struct VoxelModel {
     pub size_z: usize,
     pub voxels: Vec<u32>,
     pub full: Vec<bool>,
+    /// The material of every voxel
+    pub materials: Vec<VoxelMaterial>,
 }
 
-pub fn load_voxel_model(path: &str) -> Option<VoxelModel> {
-    let file = File::open(path);
-    match file {
-        Ok(mut f) => {
-            let mut buffer = Vec::new();
-            match f.read_to_end(&mut buffer) {
-                Ok(_) => {
-                    let mut id = str_from_byte(&buffer[0..4]);
-
-                    if id == "VOX " {
-                        let mut big_endian = true;
-                        if four_bytes_to_u32(&buffer[4..8], true) != 150 {
-                            big_endian = false;
-                            assert_eq!(four_bytes_to_u32(&buffer[4..8], false), 150);
-                        }
-                        id = str_from_byte(&buffer[8..12]);
-                        let (size_x, size_y, size_z);
-                        if id == "MAIN" {
-                            let n = four_bytes_to_u32(&buffer[12..16], big_endian);
-                            let m = four_bytes_to_u32(&buffer[16..20], big_endian);
-                            assert_eq!(n, 0);
-                            let mut data = &buffer[20..20 + m as usize];
-                            id = str_from_byte(&data[0..4]);
-                            assert_eq!(id, "SIZE");
-
-                            {
-                                // 24 bytes
-                                let n_size = four_bytes_to_u32(&data[4..8], big_endian);
-                                let m_size = four_bytes_to_u32(&data[8..12], big_endian);
-                                assert_eq!(n_size, 12);
-                                assert_eq!(m_size, 0);
-                                size_x = four_bytes_to_u32(&data[12..16], big_endian);
-                                size_z = four_bytes_to_u32(&data[16..20], big_endian);
-                                size_y = four_bytes_to_u32(&data[20..24], big_endian);
-                            }
-
-                            data = &data[24..];
-                            id = str_from_byte(&data[0..4]);
-                            let n_voxels;
-                            let mut voxel: Vec<(u8, u8, u8, u8)> = Vec::new();
-                            assert_eq!(id, "XYZI");
-                            {
-                                // 16+4*n_voxels
-                                let n_size = four_bytes_to_u32(&data[4..8], big_endian);
-                                let m_size = four_bytes_to_u32(&data[8..12], big_endian);
-                                assert_eq!(m_size, 0);
-
-                                n_voxels = four_bytes_to_u32(&data[12..16], big_endian);
-                                assert_eq!(n_size, n_voxels * 4 + 4);
-                                for i in 0..(n_voxels as usize) {
-                                    let (a, b, c, d) = (
-                                        data[16 + 4 * i],
-                                        data[16 + 4 * i + 1],
-                                        data[16 + 4 * i + 2],
-                                        data[16 + 4 * i + 3],
-                                    );
-                                    voxel.push((a, c, b, d));
-                                }
-                            }
-
-                            let mut palette = [0; 256];
-
-                            if m > 40 + 4 * n_voxels {
-                                data = &data[16 + 4 * n_voxels as usize..];
-                                id = str_from_byte(&data[0..4]);
-                                if id == "RGBA" {
-                                    let n_size = four_bytes_to_u32(&data[4..8], big_endian);
-                                    let m_size = four_bytes_to_u32(&data[8..12], big_endian);
-                                    assert_eq!(m_size, 0);
-                                    assert_eq!(n_size, 4 * 256);
-                                    for i in 0..256 {
-                                        palette[i] = four_bytes_to_u32(
-                                            &data[12 + i * 4..12 + (i + 1) * 4],
-                                            big_endian,
-                                        );
-                                    }
-                                } else {
-                                    palette = DEFAULT_PALETTE;
-                                }
-                            } else {
-                                palette = DEFAULT_PALETTE;
-                            }
-
-                            let mut res = VoxelModel {
-                                size_x: size_x as usize,
-                                size_y: size_y as usize,
-                                size_z: size_z as usize,
-                                voxels: Vec::new(),
-                                full: Vec::new(),
-                            };
-
-                            for _i in 0..(size_x * size_y * size_z) {
-                                res.voxels.push(0);
-                                res.full.push(false);
-                            }
-
-                            for (x, y, z, i) in voxel.iter() {
-                                let s = ((*x as u32) * size_z * size_y
-                                    + (*y as u32) * size_z
-                                    + (*z as u32)) as usize;
-                                res.voxels[s] = palette[(*i as usize)];
-                                res.full[s] = true;
-                            }
-
-                            return Some(res);
-                        } else {
-                            // TODO : add error
-                        }
-                    } else {
-                        // TODO : add error
+/// Maximum size of a model along every axis, after the models of a file are assembled
+const MAX_MODEL_SIZE: i32 = 1024;
+
+/// A model of a `.vox` file, before it is placed in the scene
+struct Shape {
+    size: [i32; 3],
+    /// Position and color index of every voxel
+    voxels: Vec<([i32; 3], u8)>,
+}
+
+/// A rotation and a translation, in the coordinates of the `.vox` file
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Transform {
+    rotation: [[i32; 3]; 3],
+    translation: [i32; 3],
+}
+
+impl Transform {
+    const IDENTITY: Self = Self {
+        rotation: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        translation: [0, 0, 0],
+    };
+
+    fn apply(&self, pos: [i32; 3]) -> [i32; 3] {
+        let mut res = self.translation;
+        for (r, row) in res.iter_mut().zip(self.rotation.iter()) {
+            *r += row[0] * pos[0] + row[1] * pos[1] + row[2] * pos[2];
+        }
+        res
+    }
+
+    /// The transform that applies `child` and then `self`
+    fn then(&self, child: &Self) -> Self {
+        let mut rotation = [[0; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                rotation[i][j] = (0..3)
+                    .map(|k| self.rotation[i][k] * child.rotation[k][j])
+                    .sum();
+            }
+        }
+        Self {
+            rotation,
+            translation: self.apply(child.translation),
+        }
+    }
+}
+
+/// A node of the scene graph
+enum SceneNode {
+    Transform { child: i32, transform: Transform },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<u32> },
+}
+
+/// Reads the values of a `.vox` file, failing instead of reading out of bounds
+struct Reader<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], big_endian: bool) -> Self {
+        Self { bytes, big_endian }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        if count > self.bytes.len() {
+            bail!("unexpected end of file");
+        }
+        let (bytes, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let big_endian = self.big_endian;
+        Ok(four_bytes_to_u32(self.bytes(4)?, big_endian))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length = self.u32()? as usize;
+        Ok(str_from_byte(self.bytes(length)?).to_owned())
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>> {
+        let count = self.u32()?;
+        let mut dict = HashMap::new();
+        for _ in 0..count {
+            let key = self.string()?;
+            let value = self.string()?;
+            dict.insert(key, value);
+        }
+        Ok(dict)
+    }
+}
+
+/// A chunk of a `.vox` file
+struct Chunk<'a> {
+    id: &'a str,
+    content: Reader<'a>,
+    children: &'a [u8],
+}
+
+fn read_chunks<'a>(mut reader: Reader<'a>) -> Result<Vec<Chunk<'a>>> {
+    let mut chunks = Vec::new();
+    while !reader.is_empty() {
+        let id = str_from_byte(reader.bytes(4)?);
+        let content_size = reader.u32()? as usize;
+        let children_size = reader.u32()? as usize;
+        let content = Reader::new(reader.bytes(content_size)?, reader.big_endian);
+        let children = reader.bytes(children_size)?;
+        chunks.push(Chunk {
+            id,
+            content,
+            children,
+        });
+    }
+    Ok(chunks)
+}
+
+/// Parse the `_r` attribute of a transform node: the indices of the non-zero entries of the first two rows,
+/// and the signs of the three rows
+fn parse_rotation(r: &str) -> Result<[[i32; 3]; 3]> {
+    let r: u8 = r.parse().context("invalid rotation")?;
+    let first = (r & 3) as usize;
+    let second = ((r >> 2) & 3) as usize;
+    if first > 2 || second > 2 || first == second {
+        bail!("invalid rotation {}", r);
+    }
+    let third = 3 - first - second;
+    let mut rotation = [[0; 3]; 3];
+    for (row, &column) in [first, second, third].iter().enumerate() {
+        rotation[row][column] = if r & (1 << (4 + row)) != 0 { -1 } else { 1 };
+    }
+    Ok(rotation)
+}
+
+/// Parse the `_t` attribute of a transform node
+fn parse_translation(t: &str) -> Result<[i32; 3]> {
+    let values = t
+        .split_whitespace()
+        .map(|value| value.parse::<i32>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context("invalid translation")?;
+    match values.as_slice() {
+        &[x, y, z] => Ok([x, y, z]),
+        _ => bail!("invalid translation {}", t),
+    }
+}
+
+/// Parse the material of a MATL chunk
+fn parse_material(properties: &HashMap<String, String>) -> Result<VoxelMaterial> {
+    let property = |name: &str| -> Result<f32> {
+        match properties.get(name) {
+            Some(value) => value
+                .parse()
+                .with_context(|| format!("invalid material property {}", name)),
+            None => Ok(0.0),
+        }
+    };
+    let mut material = VoxelMaterial::default();
+    match properties.get("_type").map(|ty| ty.as_str()) {
+        Some("_emit") => material.emission = property("_emit")?,
+        Some("_glass") | Some("_blend") => {
+            material.transparency = property("_alpha")?.max(property("_trans")?)
+        }
+        _ => (),
+    }
+    Ok(material)
+}
+
+/// Place the shapes of the scene graph below `node_id`, with the transform of its parents
+fn place_shapes(
+    nodes: &HashMap<i32, SceneNode>,
+    shapes: &[Shape],
+    node_id: i32,
+    transform: Transform,
+    depth: usize,
+    placed: &mut Vec<(usize, Transform)>,
+) -> Result<()> {
+    if depth > nodes.len() {
+        bail!("the scene graph has a cycle");
+    }
+    match nodes.get(&node_id) {
+        Some(SceneNode::Transform {
+            child,
+            transform: child_transform,
+        }) => place_shapes(
+            nodes,
+            shapes,
+            *child,
+            transform.then(child_transform),
+            depth + 1,
+            placed,
+        ),
+        Some(SceneNode::Group { children }) => {
+            for child in children.iter() {
+                place_shapes(nodes, shapes, *child, transform, depth + 1, placed)?;
+            }
+            Ok(())
+        }
+        Some(SceneNode::Shape { models }) => {
+            for &model in models.iter() {
+                if model as usize >= shapes.len() {
+                    bail!("the scene graph references the unknown model {}", model);
+                }
+                placed.push((model as usize, transform));
+            }
+            Ok(())
+        }
+        None => bail!("the scene graph references the unknown node {}", node_id),
+    }
+}
+
+/// Load a `.vox` file. All the models of the file are placed according to the scene graph and merged into one model.
+pub fn load_voxel_model(path: &str) -> Result<VoxelModel> {
+    let mut buffer = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut buffer))
+        .with_context(|| format!("Failed to read .vox file {}", path))?;
+    parse_voxel_model(&buffer).with_context(|| format!("Failed to parse .vox file {}", path))
+}
+
+fn parse_voxel_model(buffer: &[u8]) -> Result<VoxelModel> {
+    let mut reader = Reader::new(buffer, false);
+    if reader.bytes(4)? != b"VOX " {
+        bail!("not a .vox file");
+    }
+    let version = reader.bytes(4)?;
+    if four_bytes_to_u32(version, true) == 150 {
+        reader.big_endian = true;
+    } else if four_bytes_to_u32(version, false) != 150 {
+        bail!("unsupported version {}", four_bytes_to_u32(version, false));
+    }
+    let big_endian = reader.big_endian;
+    let main = read_chunks(reader)?
+        .into_iter()
+        .find(|chunk| chunk.id == "MAIN")
+        .context("no MAIN chunk")?;
+
+    let mut shapes = Vec::new();
+    let mut size = None;
+    let mut palette = DEFAULT_PALETTE;
+    let mut materials = [VoxelMaterial::default(); 256];
+    let mut nodes = HashMap::new();
+    for mut chunk in read_chunks(Reader::new(main.children, big_endian))? {
+        let content = &mut chunk.content;
+        match chunk.id {
+            "SIZE" => {
+                let (x, y, z) = (content.i32()?, content.i32()?, content.i32()?);
+                if x < 0 || y < 0 || z < 0 {
+                    bail!("invalid model size {}x{}x{}", x, y, z);
+                }
+                size = Some([x, y, z]);
+            }
+            "XYZI" => {
+                let size = size.take().context("XYZI chunk without a SIZE chunk")?;
+                let n_voxels = content.u32()?;
+                let mut voxels = Vec::new();
+                for _ in 0..n_voxels {
+                    let voxel = content.bytes(4)?;
+                    let pos = [voxel[0] as i32, voxel[1] as i32, voxel[2] as i32];
+                    if (0..3).any(|i| pos[i] >= size[i]) {
+                        bail!("voxel outside of its model");
                     }
+                    voxels.push((pos, voxel[3]));
+                }
+                shapes.push(Shape { size, voxels });
+            }
+            "RGBA" => {
+                // The color index 0 is empty, so the palette is shifted by one
+                for i in 0..255 {
+                    palette[i + 1] = content.u32()?;
+                }
+            }
+            "nTRN" => {
+                let node_id = content.i32()?;
+                let _attributes = content.dict()?;
+                let child = content.i32()?;
+                let _reserved = content.i32()?;
+                let _layer = content.i32()?;
+                // Only the first frame of animated models is loaded
+                let frame = match content.u32()? {
+                    0 => HashMap::new(),
+                    _ => content.dict()?,
+                };
+                let transform = Transform {
+                    rotation: match frame.get("_r") {
+                        Some(r) => parse_rotation(r)?,
+                        None => Transform::IDENTITY.rotation,
+                    },
+                    translation: match frame.get("_t") {
+                        Some(t) => parse_translation(t)?,
+                        None => [0, 0, 0],
+                    },
+                };
+                nodes.insert(node_id, SceneNode::Transform { child, transform });
+            }
+            "nGRP" => {
+                let node_id = content.i32()?;
+                let _attributes = content.dict()?;
+                let n_children = content.u32()?;
+                let children = (0..n_children)
+                    .map(|_| content.i32())
+                    .collect::<Result<_>>()?;
+                nodes.insert(node_id, SceneNode::Group { children });
+            }
+            "nSHP" => {
+                let node_id = content.i32()?;
+                let _attributes = content.dict()?;
+                let n_models = content.u32()?;
+                let mut models = Vec::new();
+                for _ in 0..n_models {
+                    models.push(content.u32()?);
+                    let _model_attributes = content.dict()?;
+                }
+                nodes.insert(node_id, SceneNode::Shape { models });
+            }
+            "MATL" => {
+                let id = content.i32()?;
+                let material = parse_material(&content.dict()?)?;
+                if let Some(m) = materials.get_mut(id as usize) {
+                    *m = material;
                 }
-                _ => (),
             }
+            "MATT" => {
+                // Legacy material chunk: 0 is diffuse, 1 is metal, 2 is glass and 3 is emissive
+                let id = content.i32()?;
+                let ty = content.i32()?;
+                let weight = f32::from_bits(content.u32()?);
+                if let Some(m) = materials.get_mut(id as usize) {
+                    match ty {
+                        2 => m.transparency = weight,
+                        3 => m.emission = weight,
+                        _ => (),
+                    }
+                }
+            }
+            // Layers, render settings, cameras and notes are ignored
+            _ => (),
         }
-        _ => (),
+    }
+    if shapes.is_empty() {
+        bail!("the file has no model");
+    }
+
+    // Without a scene graph, all the models are at the origin. With a scene graph, the translations
+    // are the position of the center of the models.
+    let mut placed = Vec::new();
+    if nodes.is_empty() {
+        placed.extend((0..shapes.len()).map(|i| (i, Transform::IDENTITY)));
+    } else {
+        place_shapes(&nodes, &shapes, 0, Transform::IDENTITY, 0, &mut placed)?;
+    }
+    // The model contains the whole box of every placed model, including its empty voxels
+    let mut voxels = Vec::new();
+    let mut min = [i32::MAX; 3];
+    let mut max = [i32::MIN; 3];
+    for (shape, transform) in placed {
+        let shape = &shapes[shape];
+        if shape.size.iter().any(|&size| size == 0) {
+            continue;
+        }
+        let center = if nodes.is_empty() {
+            [0, 0, 0]
+        } else {
+            [shape.size[0] / 2, shape.size[1] / 2, shape.size[2] / 2]
+        };
+        let local = |pos: [i32; 3]| [pos[0] - center[0], pos[1] - center[1], pos[2] - center[2]];
+        let first_corner = transform.apply(local([0, 0, 0]));
+        let last_corner = transform.apply(local([
+            shape.size[0] - 1,
+            shape.size[1] - 1,
+            shape.size[2] - 1,
+        ]));
+        for i in 0..3 {
+            min[i] = min[i].min(first_corner[i]).min(last_corner[i]);
+            max[i] = max[i].max(first_corner[i]).max(last_corner[i]);
+        }
+        for (pos, color) in shape.voxels.iter() {
+            voxels.push((transform.apply(local(*pos)), *color));
+        }
+    }
+    if min[0] > max[0] {
+        min = [0; 3];
+        max = [-1; 3];
+    }
+    let vox_size = [
+        max[0] - min[0] + 1,
+        max[1] - min[1] + 1,
+        max[2] - min[2] + 1,
+    ];
+    if vox_size.iter().any(|&size| size > MAX_MODEL_SIZE) {
+        bail!(
+            "the model is too big: {}x{}x{}, the maximum is {}",
+            vox_size[0],
+            vox_size[1],
+            vox_size[2],
+            MAX_MODEL_SIZE
+        );
+    }
+
+    // The y axis of the .vox files is the z axis of the model, and vice versa
+    let (size_x, size_y, size_z) = (
+        vox_size[0] as usize,
+        vox_size[2] as usize,
+        vox_size[1] as usize,
+    );
+    let mut res = VoxelModel {
+        size_x,
+        size_y,
+        size_z,
+        voxels: vec![0; size_x * size_y * size_z],
+        full: vec![false; size_x * size_y * size_z],
+        materials: vec![VoxelMaterial::default(); size_x * size_y * size_z],
     };
-    return None;
+    for (pos, i) in voxels.iter() {
+        let (x, y, z) = (
+            (pos[0] - min[0]) as usize,
+            (pos[2] - min[2]) as usize,
+            (pos[1] - min[1]) as usize,
+        );
+        let s = x * size_z * size_y + y * size_z + z;
+        res.voxels[s] = palette[*i as usize];
+        res.full[s] = true;
+        res.materials[s] = materials[*i as usize];
+    }
+
+    Ok(res)
+}
+
+/// Save a model to a `.vox` file, with a palette containing the colors of the full voxels.
+/// Fails if the model is bigger than 256 voxels along some axis or has more than 255 colors.
+/// The materials of the voxels are not saved.
+pub fn save_voxel_model(path: &str, model: &VoxelModel) -> Result<()> {
+    if model.size_x > 256 || model.size_y > 256 || model.size_z > 256 {
+        bail!(
+            "The model is too big for a .vox file: {}x{}x{}, the maximum is 256x256x256",
+            model.size_x,
+            model.size_y,
+            model.size_z
+        );
+    }
+
+    // The y axis of the .vox files is the z axis of the model, and vice versa
+    let mut palette: Vec<u32> = Vec::new();
+    let mut xyzi = Vec::new();
+    for x in 0..model.size_x {
+        for y in 0..model.size_y {
+            for z in 0..model.size_z {
+                let s = x * model.size_z * model.size_y + y * model.size_z + z;
+                if !model.full[s] {
+                    continue;
+                }
+                let color = model.voxels[s];
+                let index = match palette.iter().position(|&c| c == color) {
+                    Some(index) => index,
+                    None => {
+                        if palette.len() == 255 {
+                            bail!("The model has more than 255 colors");
+                        }
+                        palette.push(color);
+                        palette.len() - 1
+                    }
+                };
+                xyzi.extend_from_slice(&[x as u8, z as u8, y as u8, index as u8 + 1]);
+            }
+        }
+    }
+    palette.resize(256, 0);
+
+    let mut children = Vec::new();
+    write_chunk_header(&mut children, "SIZE", 12, 0);
+    for size in &[model.size_x, model.size_z, model.size_y] {
+        children.extend_from_slice(&(*size as u32).to_le_bytes());
+    }
+    write_chunk_header(&mut children, "XYZI", 4 + xyzi.len() as u32, 0);
+    children.extend_from_slice(&(xyzi.len() as u32 / 4).to_le_bytes());
+    children.extend_from_slice(&xyzi);
+    write_chunk_header(&mut children, "RGBA", 4 * 256, 0);
+    for color in palette {
+        children.extend_from_slice(&color.to_le_bytes());
+    }
+
+    let mut buffer = Vec::new();
+    buffer.extend_from_slice(b"VOX ");
+    buffer.extend_from_slice(&150u32.to_le_bytes());
+    write_chunk_header(&mut buffer, "MAIN", 0, children.len() as u32);
+    buffer.extend_from_slice(&children);
+    std::fs::write(path, buffer).context(format!("Failed to write .vox file {}", path))
+}
+
+fn write_chunk_header(buffer: &mut Vec<u8>, id: &str, content_size: u32, children_size: u32) {
+    buffer.extend_from_slice(id.as_bytes());
+    buffer.extend_from_slice(&content_size.to_le_bytes());
+    buffer.extend_from_slice(&children_size.to_le_bytes());
 }
 
 fn four_bytes_to_u32(bytes: &[u8], big_endian: bool) -> u32 {
@@ -189,3 +589,134 @@ fn str_from_byte(bytes: &[u8]) -> &str {
         _ => "",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(buffer: &mut Vec<u8>, id: &str, content: &[u8]) {
+        write_chunk_header(buffer, id, content.len() as u32, 0);
+        buffer.extend_from_slice(content);
+    }
+
+    fn int(buffer: &mut Vec<u8>, value: i32) {
+        buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(buffer: &mut Vec<u8>, value: &str) {
+        int(buffer, value.len() as i32);
+        buffer.extend_from_slice(value.as_bytes());
+    }
+
+    fn dict(buffer: &mut Vec<u8>, entries: &[(&str, &str)]) {
+        int(buffer, entries.len() as i32);
+        for (key, value) in entries {
+            string(buffer, key);
+            string(buffer, value);
+        }
+    }
+
+    fn vox_file(children: &[u8]) -> Vec<u8> {
+        let mut buffer = b"VOX ".to_vec();
+        int(&mut buffer, 150);
+        write_chunk_header(&mut buffer, "MAIN", 0, children.len() as u32);
+        buffer.extend_from_slice(children);
+        buffer
+    }
+
+    /// A 2x2x2 model with one voxel of color 1 at the origin
+    fn single_voxel_model(children: &mut Vec<u8>) {
+        let mut size = Vec::new();
+        for _ in 0..3 {
+            int(&mut size, 2);
+        }
+        chunk(children, "SIZE", &size);
+        let mut xyzi = Vec::new();
+        int(&mut xyzi, 1);
+        xyzi.extend_from_slice(&[0, 0, 0, 1]);
+        chunk(children, "XYZI", &xyzi);
+    }
+
+    fn transform_node(children: &mut Vec<u8>, id: i32, child: i32, frame: &[(&str, &str)]) {
+        let mut content = Vec::new();
+        int(&mut content, id);
+        dict(&mut content, &[]);
+        int(&mut content, child);
+        int(&mut content, -1);
+        int(&mut content, 0);
+        int(&mut content, 1);
+        dict(&mut content, frame);
+        chunk(children, "nTRN", &content);
+    }
+
+    fn shape_node(children: &mut Vec<u8>, id: i32, model: i32) {
+        let mut content = Vec::new();
+        int(&mut content, id);
+        dict(&mut content, &[]);
+        int(&mut content, 1);
+        int(&mut content, model);
+        dict(&mut content, &[]);
+        chunk(children, "nSHP", &content);
+    }
+
+    #[test]
+    fn single_model() {
+        let mut children = Vec::new();
+        single_voxel_model(&mut children);
+        let model = parse_voxel_model(&vox_file(&children)).unwrap();
+        assert_eq!((model.size_x, model.size_y, model.size_z), (2, 2, 2));
+        assert_eq!(model.full.iter().filter(|&&full| full).count(), 1);
+        assert!(model.full[0]);
+        assert_eq!(model.voxels[0], DEFAULT_PALETTE[1]);
+    }
+
+    #[test]
+    fn scene_graph_with_materials() {
+        let mut children = Vec::new();
+        single_voxel_model(&mut children);
+        single_voxel_model(&mut children);
+        transform_node(&mut children, 0, 1, &[]);
+        let mut group = Vec::new();
+        int(&mut group, 1);
+        dict(&mut group, &[]);
+        int(&mut group, 2);
+        int(&mut group, 2);
+        int(&mut group, 4);
+        chunk(&mut children, "nGRP", &group);
+        transform_node(&mut children, 2, 3, &[("_t", "1 1 1")]);
+        shape_node(&mut children, 3, 0);
+        transform_node(&mut children, 4, 5, &[("_t", "5 1 1")]);
+        shape_node(&mut children, 5, 1);
+        let mut material = Vec::new();
+        int(&mut material, 1);
+        dict(&mut material, &[("_type", "_emit"), ("_emit", "0.5")]);
+        chunk(&mut children, "MATL", &material);
+
+        let model = parse_voxel_model(&vox_file(&children)).unwrap();
+        // The models are 4 voxels apart along the x axis
+        assert_eq!((model.size_x, model.size_y, model.size_z), (6, 2, 2));
+        assert_eq!(model.full.iter().filter(|&&full| full).count(), 2);
+        let second = 4 * model.size_y * model.size_z;
+        assert!(model.full[0] && model.full[second]);
+        assert_eq!(model.materials[0].emission, 0.5);
+        assert_eq!(model.materials[second].emission, 0.5);
+    }
+
+    #[test]
+    fn rotation() {
+        assert_eq!(parse_rotation("4").unwrap(), Transform::IDENTITY.rotation);
+        assert_eq!(
+            parse_rotation("17").unwrap(),
+            [[0, -1, 0], [1, 0, 0], [0, 0, 1]]
+        );
+        assert!(parse_rotation("0").is_err());
+    }
+
+    #[test]
+    fn truncated_file() {
+        let mut children = Vec::new();
+        single_voxel_model(&mut children);
+        let buffer = vox_file(&children);
+        assert!(parse_voxel_model(&buffer[..buffer.len() - 2]).is_err());
+    }
+}
@@ -1,8 +1,20 @@
+use crate::world::{ChunkPos, ChunkPosXZ};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use lazy_static::lazy_static;
-use std::{collections::BTreeMap, sync::Arc, sync::RwLock};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    sync::RwLock,
+};
 lazy_static! {
-    static ref DEBUG_INFO: Arc<RwLock<Option<Sender<DebugInfoUnit>>>> = Arc::new(RwLock::new(None));
+    static ref DEBUG_INFO: Arc<RwLock<Option<Sender<DebugMessage>>>> = Arc::new(RwLock::new(None));
+}
+
+/// A message to the current `DebugInfo`
+#[derive(Debug, Clone)]
+enum DebugMessage {
+    Info(DebugInfoUnit),
+    ChunkStat(ChunkMetric, ChunkStatUpdate),
 }
 
 #[derive(Debug, Clone)]
@@ -19,12 +31,61 @@ pub enum DebugInfoPart {
     PerfBreakdown(String, Vec<(String, f64)>),
 }
 
+/// A statistic of every chunk, shown as a heatmap of the loaded chunks to find the parts of the world that
+/// cause performance problems
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChunkMetric {
+    /// Milliseconds taken by the last meshing of the chunk
+    MeshTime,
+    /// Number of times the light of the chunk was computed
+    LightUpdates,
+    /// Number of block entities and players in the chunk
+    Entities,
+    /// Bytes of chunk and light data sent to the players
+    BytesSent,
+}
+
+impl ChunkMetric {
+    pub const ALL: [Self; 4] = [
+        Self::MeshTime,
+        Self::LightUpdates,
+        Self::Entities,
+        Self::BytesSent,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::MeshTime => "Last mesh time (ms)",
+            Self::LightUpdates => "Light updates",
+            Self::Entities => "Entities",
+            Self::BytesSent => "Bytes sent",
+        }
+    }
+
+    /// Combine the values of two chunks of the same column: the slowest mesh, or the sum of the counts
+    fn combine(self, a: f64, b: f64) -> f64 {
+        match self {
+            Self::MeshTime => a.max(b),
+            Self::LightUpdates | Self::Entities | Self::BytesSent => a + b,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ChunkStatUpdate {
+    Set(ChunkPos, f64),
+    Add(ChunkPos, f64),
+    /// Replace the values of every chunk, the chunks that are not listed have no value
+    ReplaceAll(Vec<(ChunkPos, f64)>),
+}
+
 /// Helper struct allowing multiple threads to easily show debug info.
 /// There can only be one active `DebugInfo` at any time.
 pub struct DebugInfo {
-    receiver: Receiver<DebugInfoUnit>,
+    receiver: Receiver<DebugMessage>,
     sections: BTreeMap<String, (bool, u32, BTreeMap<String, DebugInfoPart>)>,
     next_id: u32,
+    chunk_stats: HashMap<ChunkMetric, HashMap<ChunkPos, f64>>,
 }
 
 impl Drop for DebugInfo {
@@ -42,40 +103,108 @@ impl DebugInfo {
             receiver,
             sections: BTreeMap::new(),
             next_id: 0,
+            chunk_stats: HashMap::new(),
         }
     }
 
-    /// Get the debug info
-    pub fn get_debug_info(
-        &mut self,
-    ) -> &mut BTreeMap<String, (bool, u32, BTreeMap<String, DebugInfoPart>)> {
+    /// Process the messages sent since the last call
+    fn receive(&mut self) {
         let Self {
             ref mut next_id, ..
         } = self;
-        while let Ok(diu) = self.receiver.try_recv() {
-            let (_, _, inner_map) = self.sections.entry(diu.section).or_insert_with(|| {
-                *next_id += 1;
-                (false, *next_id - 1, BTreeMap::new())
-            });
-            inner_map.insert(diu.id, diu.part);
+        while let Ok(message) = self.receiver.try_recv() {
+            match message {
+                DebugMessage::Info(diu) => {
+                    let (_, _, inner_map) = self.sections.entry(diu.section).or_insert_with(|| {
+                        *next_id += 1;
+                        (false, *next_id - 1, BTreeMap::new())
+                    });
+                    inner_map.insert(diu.id, diu.part);
+                }
+                DebugMessage::ChunkStat(metric, update) => {
+                    let stats = self.chunk_stats.entry(metric).or_default();
+                    match update {
+                        ChunkStatUpdate::Set(pos, value) => {
+                            stats.insert(pos, value);
+                        }
+                        ChunkStatUpdate::Add(pos, value) => {
+                            *stats.entry(pos).or_insert(0.0) += value;
+                        }
+                        ChunkStatUpdate::ReplaceAll(values) => {
+                            *stats = values.into_iter().collect();
+                        }
+                    }
+                }
+            }
         }
+    }
+
+    /// Get the debug info
+    pub fn get_debug_info(
+        &mut self,
+    ) -> &mut BTreeMap<String, (bool, u32, BTreeMap<String, DebugInfoPart>)> {
+        self.receive();
         &mut self.sections
     }
+
+    /// Get the values of a chunk metric, combined by chunk column
+    pub fn column_stats(&mut self, metric: ChunkMetric) -> HashMap<ChunkPosXZ, f64> {
+        self.receive();
+        let mut columns = HashMap::new();
+        if let Some(stats) = self.chunk_stats.get(&metric) {
+            for (&pos, &value) in stats {
+                columns
+                    .entry(ChunkPosXZ::from(pos))
+                    .and_modify(|column_value| *column_value = metric.combine(*column_value, value))
+                    .or_insert(value);
+            }
+        }
+        columns
+    }
 }
 
-/// Send a debug info message to the current `DebugInfo` if there is one
-pub fn send_debug_info(section: impl ToString, id: impl ToString, message: impl ToString) {
+/// Send a message to the current `DebugInfo` if there is one
+fn send(message: DebugMessage) {
     DEBUG_INFO.read().unwrap().as_ref().map(|sender| {
         sender
-            .send(DebugInfoUnit {
-                section: section.to_string(),
-                id: id.to_string(),
-                part: DebugInfoPart::Message(message.to_string()),
-            })
+            .send(message)
             .map_err(|e| log::error!("Error sending debug info: {:?}", e))
     });
 }
 
+/// Send a debug info message to the current `DebugInfo` if there is one
+pub fn send_debug_info(section: impl ToString, id: impl ToString, message: impl ToString) {
+    send(DebugMessage::Info(DebugInfoUnit {
+        section: section.to_string(),
+        id: id.to_string(),
+        part: DebugInfoPart::Message(message.to_string()),
+    }));
+}
+
+/// Set the value of a chunk metric for a chunk
+pub fn set_chunk_stat(metric: ChunkMetric, pos: ChunkPos, value: f64) {
+    send(DebugMessage::ChunkStat(
+        metric,
+        ChunkStatUpdate::Set(pos, value),
+    ));
+}
+
+/// Add to the value of a chunk metric for a chunk, for the metrics that count events
+pub fn add_chunk_stat(metric: ChunkMetric, pos: ChunkPos, value: f64) {
+    send(DebugMessage::ChunkStat(
+        metric,
+        ChunkStatUpdate::Add(pos, value),
+    ));
+}
+
+/// Replace the values of a chunk metric for every chunk, for the metrics that count things in the chunks
+pub fn replace_chunk_stats(metric: ChunkMetric, values: Vec<(ChunkPos, f64)>) {
+    send(DebugMessage::ChunkStat(
+        metric,
+        ChunkStatUpdate::ReplaceAll(values),
+    ));
+}
+
 #[derive(Debug, Clone)]
 pub struct WorkerPerf {
     pub name: String,
@@ -94,21 +223,17 @@ pub fn send_worker_perf(
     iter_per_sec: f32,
     pending: usize,
 ) {
-    DEBUG_INFO.read().unwrap().as_ref().map(|sender| {
-        sender
-            .send(DebugInfoUnit {
-                section: section.to_string(),
-                id: id.to_string(),
-                part: DebugInfoPart::WorkerPerf(WorkerPerf {
-                    name: name.to_string(),
-                    micros_per_iter,
-                    iter_per_sec,
-                    efficiency: micros_per_iter / 1_000_000.0 * iter_per_sec,
-                    pending,
-                }),
-            })
-            .map_err(|e| log::error!("Error sending worker perf debug info: {:?}", e))
-    });
+    send(DebugMessage::Info(DebugInfoUnit {
+        section: section.to_string(),
+        id: id.to_string(),
+        part: DebugInfoPart::WorkerPerf(WorkerPerf {
+            name: name.to_string(),
+            micros_per_iter,
+            iter_per_sec,
+            efficiency: micros_per_iter / 1_000_000.0 * iter_per_sec,
+            pending,
+        }),
+    }));
 }
 
 /// Send a debug info performance breakdown
@@ -118,13 +243,9 @@ pub fn send_perf_breakdown(
     name: impl ToString,
     breakdown: Vec<(String, f64)>,
 ) {
-    DEBUG_INFO.read().unwrap().as_ref().map(|sender| {
-        sender
-            .send(DebugInfoUnit {
-                section: section.to_string(),
-                id: id.to_string(),
-                part: DebugInfoPart::PerfBreakdown(name.to_string(), breakdown),
-            })
-            .map_err(|e| log::error!("Error sending perf breakdown debug info: {:?}", e))
-    });
+    send(DebugMessage::Info(DebugInfoUnit {
+        section: section.to_string(),
+        id: id.to_string(),
+        part: DebugInfoPart::PerfBreakdown(name.to_string(), breakdown),
+    }));
 }
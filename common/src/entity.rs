@@ -0,0 +1,309 @@
+//! Spatial queries on the entities: the entities in a box, around a point or along a ray.
+//! The entities are bucketed by the chunk that contains the center of their box. A query only visits the
+//! buckets of the chunks around the queried region, extended by the size of the largest entity, so that its
+//! cost depends on the number of entities nearby and not on the number of entities in the world.
+use crate::world::{BlockPos, ChunkPos};
+use nalgebra::{Point3, Vector3};
+use ncollide3d::bounding_volume::{BoundingVolume, AABB};
+use std::collections::HashMap;
+
+/// An index of the boxes of the entities, identified by keys
+#[derive(Debug, Clone)]
+pub struct SpatialIndex<K> {
+    buckets: HashMap<ChunkPos, Vec<(K, AABB<f64>)>>,
+    /// The largest half extents of the boxes on every axis: how far a box can go out of the chunk of its center
+    max_half_extents: Vector3<f64>,
+    len: usize,
+}
+
+impl<K: Copy + Ord> SpatialIndex<K> {
+    pub fn new() -> Self {
+        Self {
+            buckets: HashMap::new(),
+            max_half_extents: Vector3::zeros(),
+            len: 0,
+        }
+    }
+
+    pub fn from_entities(entities: impl IntoIterator<Item = (K, AABB<f64>)>) -> Self {
+        let mut index = Self::new();
+        for (key, aabb) in entities {
+            index.insert(key, aabb);
+        }
+        index
+    }
+
+    /// Add an entity. The keys are expected to be unique.
+    pub fn insert(&mut self, key: K, aabb: AABB<f64>) {
+        let chunk = BlockPos::from(aabb.center()).containing_chunk_pos();
+        self.buckets.entry(chunk).or_default().push((key, aabb));
+        self.max_half_extents = self.max_half_extents.sup(&aabb.half_extents());
+        self.len += 1;
+    }
+
+    /// Number of entities
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over all the entities, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &(K, AABB<f64>)> {
+        self.buckets.values().flatten()
+    }
+
+    /// Get the entities of the buckets that can contain boxes intersecting `region`
+    fn candidates(&self, region: &AABB<f64>) -> Vec<&(K, AABB<f64>)> {
+        let min_chunk = BlockPos::from(region.mins - self.max_half_extents).containing_chunk_pos();
+        let max_chunk = BlockPos::from(region.maxs + self.max_half_extents).containing_chunk_pos();
+        let in_range = |chunk: &ChunkPos| {
+            (min_chunk.px..=max_chunk.px).contains(&chunk.px)
+                && (min_chunk.py..=max_chunk.py).contains(&chunk.py)
+                && (min_chunk.pz..=max_chunk.pz).contains(&chunk.pz)
+        };
+        let chunk_count = (max_chunk.px - min_chunk.px + 1) as u64
+            * (max_chunk.py - min_chunk.py + 1) as u64
+            * (max_chunk.pz - min_chunk.pz + 1) as u64;
+        // The large regions are faster to check bucket by bucket than chunk by chunk
+        if chunk_count > self.buckets.len() as u64 {
+            return self
+                .buckets
+                .iter()
+                .filter(|(chunk, _)| in_range(chunk))
+                .flat_map(|(_, entities)| entities.iter())
+                .collect();
+        }
+        let mut candidates = Vec::new();
+        for px in min_chunk.px..=max_chunk.px {
+            for py in min_chunk.py..=max_chunk.py {
+                for pz in min_chunk.pz..=max_chunk.pz {
+                    if let Some(entities) = self.buckets.get(&ChunkPos { px, py, pz }) {
+                        candidates.extend(entities.iter());
+                    }
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Get the entities whose box intersects `region`, sorted by key
+    pub fn entities_in_aabb(&self, region: &AABB<f64>) -> Vec<K> {
+        let mut entities: Vec<_> = self
+            .candidates(region)
+            .into_iter()
+            .filter(|(_, aabb)| aabb.intersects(region))
+            .map(|(key, _)| *key)
+            .collect();
+        entities.sort();
+        entities
+    }
+
+    /// Get the entities whose box is at most `radius` away from `center`, sorted by key
+    pub fn entities_in_radius(&self, center: Point3<f64>, radius: f64) -> Vec<K> {
+        let region = AABB::from_half_extents(center, Vector3::repeat(radius));
+        let mut entities: Vec<_> = self
+            .candidates(&region)
+            .into_iter()
+            .filter(|(_, aabb)| distance_to_point(aabb, center) <= radius)
+            .map(|(key, _)| *key)
+            .collect();
+        entities.sort();
+        entities
+    }
+
+    /// Get the entities whose box is hit by the ray from `origin` towards `direction`, at most `max_distance`
+    /// away, with the distance of the hit. They are sorted by distance, the closest first.
+    /// The entities that contain `origin` are hit at distance 0.
+    pub fn raycast_entities(
+        &self,
+        origin: Point3<f64>,
+        direction: Vector3<f64>,
+        max_distance: f64,
+    ) -> Vec<(K, f64)> {
+        let direction = direction.normalize();
+        let end = origin + direction * max_distance;
+        let region = AABB::new(origin.inf(&end), origin.sup(&end));
+        let mut hits: Vec<_> = self
+            .candidates(&region)
+            .into_iter()
+            .filter_map(|(key, aabb)| {
+                ray_distance(aabb, origin, direction, max_distance).map(|distance| (*key, distance))
+            })
+            .collect();
+        hits.sort_by(|(key1, d1), (key2, d2)| d1.partial_cmp(d2).unwrap().then(key1.cmp(key2)));
+        hits
+    }
+
+    /// Get the pairs of entities whose boxes overlap, not only touch, the smallest key first, sorted by keys
+    pub fn overlapping_pairs(&self) -> Vec<(K, K)> {
+        let mut pairs = Vec::new();
+        for (key, aabb) in self.iter() {
+            for (other_key, other_aabb) in self.candidates(aabb) {
+                if key < other_key && overlaps(aabb, other_aabb) {
+                    pairs.push((*key, *other_key));
+                }
+            }
+        }
+        pairs.sort();
+        pairs
+    }
+}
+
+impl<K: Copy + Ord> Default for SpatialIndex<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check if the intersection of two boxes has a volume
+fn overlaps(a: &AABB<f64>, b: &AABB<f64>) -> bool {
+    (0..3).all(|i| a.mins[i] < b.maxs[i] && b.mins[i] < a.maxs[i])
+}
+
+/// Distance from a point to the closest point of a box, 0 if the box contains the point
+fn distance_to_point(aabb: &AABB<f64>, point: Point3<f64>) -> f64 {
+    let closest = point.sup(&aabb.mins).inf(&aabb.maxs);
+    (point - closest).norm()
+}
+
+/// Distance along the ray from `origin` towards the normalized `direction` where it enters the box, if it
+/// enters it before `max_distance`
+fn ray_distance(
+    aabb: &AABB<f64>,
+    origin: Point3<f64>,
+    direction: Vector3<f64>,
+    max_distance: f64,
+) -> Option<f64> {
+    let mut enter = 0.0f64;
+    let mut exit = max_distance;
+    for i in 0..3 {
+        if direction[i].abs() < 1e-12 {
+            if origin[i] < aabb.mins[i] || origin[i] > aabb.maxs[i] {
+                return None;
+            }
+        } else {
+            let t1 = (aabb.mins[i] - origin[i]) / direction[i];
+            let t2 = (aabb.maxs[i] - origin[i]) / direction[i];
+            enter = enter.max(t1.min(t2));
+            exit = exit.min(t1.max(t2));
+            if enter > exit {
+                return None;
+            }
+        }
+    }
+    Some(enter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn entity_box(x: f64, y: f64, z: f64) -> AABB<f64> {
+        AABB::from_half_extents(Point3::new(x, y, z), Vector3::new(0.4, 0.9, 0.4))
+    }
+
+    /// The entities of a grid centered on the origin with `spacing` blocks between the entities, `side` entities
+    /// per side
+    fn grid(side: u32, spacing: f64) -> Vec<(u32, AABB<f64>)> {
+        (0..side * side)
+            .map(|i| {
+                let x = (i % side) as f64 - (side / 2) as f64;
+                let z = (i / side) as f64 - (side / 2) as f64;
+                (i, entity_box(x * spacing, 50.9, z * spacing))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_queries() {
+        // Entities on both sides of the border of a chunk
+        let index = SpatialIndex::from_entities(vec![
+            (0, entity_box(-0.3, 50.9, 0.0)),
+            (1, entity_box(0.3, 50.9, 0.0)),
+            (2, entity_box(5.0, 50.9, 0.0)),
+            (3, entity_box(0.0, 50.9, 100.0)),
+        ]);
+        assert_eq!(index.len(), 4);
+        let region = AABB::new(Point3::new(-0.2, 50.0, -1.0), Point3::new(0.2, 51.0, 1.0));
+        assert_eq!(index.entities_in_aabb(&region), vec![0, 1]);
+        assert_eq!(
+            index.entities_in_radius(Point3::new(2.5, 50.9, 0.0), 2.2),
+            vec![1, 2]
+        );
+        assert_eq!(index.overlapping_pairs(), vec![(0, 1)]);
+
+        let hits = index.raycast_entities(Point3::new(-10.0, 51.0, 0.0), Vector3::x(), 20.0);
+        let keys: Vec<_> = hits.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, vec![0, 1, 2]);
+        assert!((hits[0].1 - 9.3).abs() < 1e-9);
+        assert!((hits[2].1 - 14.6).abs() < 1e-9);
+        // The ray stops at the max distance, and goes over the short entities
+        assert!(index
+            .raycast_entities(Point3::new(-10.0, 51.0, 0.0), Vector3::x(), 5.0)
+            .is_empty());
+        assert!(index
+            .raycast_entities(Point3::new(-10.0, 52.0, 0.0), Vector3::x(), 20.0)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_queries_match_brute_force() {
+        let entities = grid(20, 0.7);
+        let index = SpatialIndex::from_entities(entities.clone());
+        for &(x, z, radius) in &[(0.0, 0.0, 3.0), (2.0, -3.0, 5.5), (1.0, 1.0, 40.0)] {
+            let center = Point3::new(x, 51.0, z);
+            let expected: Vec<_> = entities
+                .iter()
+                .filter(|(_, aabb)| distance_to_point(aabb, center) <= radius)
+                .map(|(key, _)| *key)
+                .collect();
+            assert_eq!(index.entities_in_radius(center, radius), expected);
+        }
+        let expected_pairs: Vec<_> = entities
+            .iter()
+            .flat_map(|a| entities.iter().map(move |b| (a, b)))
+            .filter(|((a, aabb_a), (b, aabb_b))| a < b && overlaps(aabb_a, aabb_b))
+            .map(|((a, _), (b, _))| (*a, *b))
+            .collect();
+        assert_eq!(index.overlapping_pairs(), expected_pairs);
+    }
+
+    /// Time the queries with thousands of entities, run with `cargo test -- --ignored --nocapture`.
+    /// The time per entity should stay about the same when the number of entities grows.
+    #[test]
+    #[ignore]
+    fn bench_spatial_index() {
+        for &side in &[32, 64, 128] {
+            let entities = grid(side, 3.0);
+            let start = Instant::now();
+            let index = SpatialIndex::from_entities(entities.clone());
+            let build = start.elapsed();
+            let start = Instant::now();
+            let mut found = 0;
+            for (_, aabb) in entities.iter() {
+                found += index.entities_in_radius(aabb.center(), 8.0).len();
+                found += index
+                    .raycast_entities(aabb.center(), Vector3::new(1.0, 0.0, 1.0), 10.0)
+                    .len();
+            }
+            let queries = start.elapsed();
+            let start = Instant::now();
+            let pairs = index.overlapping_pairs().len();
+            let collisions = start.elapsed();
+            let count = entities.len() as u32;
+            println!(
+                "{} entities: build {:?}/entity, radius and ray queries {:?}/entity, pairs {:?}/entity ({} found, {} pairs)",
+                count,
+                build / count,
+                queries / count,
+                collisions / count,
+                found,
+                pairs
+            );
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Inventories and item stacks
+use crate::item::ItemId;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of items in a single stack
+pub const MAX_STACK_SIZE: u32 = 64;
+/// Number of slots of the hotbar, which are the first slots of the player inventory
+pub const HOTBAR_SIZE: usize = 9;
+
+/// Some amount of the same item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub item: ItemId,
+    pub count: u32,
+    /// Number of times the item on top of the stack was used, for the tools that wear out
+    #[serde(default)]
+    pub damage: u32,
+}
+
+impl ItemStack {
+    pub fn new(item: ItemId, count: u32) -> Self {
+        Self {
+            item,
+            count,
+            damage: 0,
+        }
+    }
+
+    /// Check if the items of `other` can be added to this stack: the damaged tools don't stack with the other tools
+    pub fn can_merge(&self, other: &ItemStack) -> bool {
+        self.item == other.item && self.damage == other.damage
+    }
+
+    /// Use the item on top of the stack once, for an item that can be used `durability` times.
+    /// The next item of the stack is used once the top one is used up. Returns what is left of the stack.
+    pub fn wear(self, durability: u32) -> Option<Self> {
+        if self.damage + 1 < durability {
+            Some(Self {
+                damage: self.damage + 1,
+                ..self
+            })
+        } else if self.count > 1 {
+            Some(Self::new(self.item, self.count - 1))
+        } else {
+            None
+        }
+    }
+}
+
+/// The inventories that can be shown on the same inventory screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InventoryWindow {
+    /// The inventory of the player
+    Player,
+    /// The container the player has currently opened
+    Container,
+}
+
+/// A fixed-size list of item slots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+}
+
+impl Inventory {
+    /// Create a new empty inventory with `size` slots
+    pub fn new(size: usize) -> Self {
+        Self {
+            slots: vec![None; size],
+        }
+    }
+
+    /// Number of slots
+    pub fn size(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Get the stack in some slot
+    pub fn get(&self, slot: usize) -> Option<ItemStack> {
+        self.slots.get(slot).cloned().flatten()
+    }
+
+    /// Replace the stack in some slot, returning the previous one
+    pub fn set(&mut self, slot: usize, stack: Option<ItemStack>) -> Option<ItemStack> {
+        std::mem::replace(&mut self.slots[slot], stack.filter(|s| s.count > 0))
+    }
+
+    /// Iterate over the slots
+    pub fn slots(&self) -> impl Iterator<Item = &Option<ItemStack>> {
+        self.slots.iter()
+    }
+
+    /// Insert as much of `stack` as possible, first merging with existing stacks of the same item and then filling empty slots.
+    /// Return the remainder that didn't fit, if any.
+    pub fn insert(&mut self, mut stack: ItemStack) -> Option<ItemStack> {
+        for slot in self.slots.iter_mut() {
+            if let Some(existing) = slot {
+                if existing.can_merge(&stack) && existing.count < MAX_STACK_SIZE {
+                    let moved = u32::min(MAX_STACK_SIZE - existing.count, stack.count);
+                    existing.count += moved;
+                    stack.count -= moved;
+                    if stack.count == 0 {
+                        return None;
+                    }
+                }
+            }
+        }
+        for slot in self.slots.iter_mut() {
+            if slot.is_none() {
+                let moved = u32::min(MAX_STACK_SIZE, stack.count);
+                *slot = Some(ItemStack {
+                    count: moved,
+                    ..stack
+                });
+                stack.count -= moved;
+                if stack.count == 0 {
+                    return None;
+                }
+            }
+        }
+        Some(stack)
+    }
+
+    /// Click `slot` while holding `cursor`: pick up, put down, merge or swap stacks.
+    /// Clicks on slots that don't exist are ignored.
+    pub fn click_slot(&mut self, slot: usize, cursor: &mut Option<ItemStack>) {
+        if slot >= self.slots.len() {
+            return;
+        }
+        let same_item = match (&self.slots[slot], &cursor) {
+            (Some(slot_stack), Some(cursor_stack)) => slot_stack.can_merge(cursor_stack),
+            _ => false,
+        };
+        if same_item {
+            let slot_stack = self.slots[slot].as_mut().unwrap();
+            let cursor_stack = cursor.as_mut().unwrap();
+            let moved = u32::min(MAX_STACK_SIZE - slot_stack.count, cursor_stack.count);
+            slot_stack.count += moved;
+            cursor_stack.count -= moved;
+            if cursor_stack.count == 0 {
+                *cursor = None;
+            }
+        } else {
+            std::mem::swap(&mut self.slots[slot], cursor);
+        }
+    }
+
+    /// Move as much as possible of the stack in `slot` to `other`.
+    pub fn quick_move(&mut self, slot: usize, other: &mut Inventory) {
+        if let Some(stack) = self.slots.get_mut(slot).and_then(Option::take) {
+            self.slots[slot] = other.insert(stack);
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::block::{Block, BlockId};
+use crate::registry::Registry;
 use serde::Deserialize;
 
 pub type ItemId = u32;
@@ -7,7 +9,67 @@ pub type ItemId = u32;
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename = "Item")]
 pub enum ItemType {
-    NormalItem { texture: String },
+    NormalItem {
+        texture: String,
+        /// Text displayed in the item tooltip
+        #[serde(default)]
+        description: String,
+        /// Number of seconds the item burns for when used as furnace fuel, 0 if it is not a fuel
+        #[serde(default)]
+        burn_time: f32,
+        /// What the item does when the player uses it, if it is a tool
+        #[serde(default)]
+        tool: Option<ItemTool>,
+        /// Names or `#tags` of the blocks that the item breaks faster
+        #[serde(default)]
+        effective_on: Vec<String>,
+        /// How many times faster the item breaks the blocks it is effective on
+        #[serde(default = "default_mining_speed")]
+        mining_speed: f32,
+        /// The kind, the tier and the durability of the item, if it is a pickaxe, an axe or a shovel
+        #[serde(default)]
+        mining_tool: Option<MiningTool>,
+    },
+}
+
+fn default_mining_speed() -> f32 {
+    1.0
+}
+
+/// The kinds of tools that break some blocks faster, see `BlockType::NormalCube::preferred_tool`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ToolType {
+    Pickaxe,
+    Axe,
+    Shovel,
+}
+
+/// The mining properties of a pickaxe, an axe or a shovel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct MiningTool {
+    pub tool_type: ToolType,
+    /// The tool breaks the blocks of its type whose minimum tier is at most this tier at the normal speed
+    pub tier: u8,
+    /// Number of blocks the tool can break before it is used up
+    pub durability: u32,
+}
+
+/// Speed factor of breaking a block without a tool of its preferred type and of its minimum tier
+pub const WRONG_TOOL_SPEED: f32 = 0.25;
+
+/// The special behavior of an item when the player breaks or places a block while holding it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ItemTool {
+    /// Select the corners of the cuboid used by the world edit commands instead of breaking and placing blocks
+    Selection,
+    /// Till the clicked block into farmland instead of placing a block
+    Hoe,
+    /// Make the clicked crop grow instead of placing a block, using one item
+    Fertilizer,
+    /// Put a boat on the clicked block instead of placing a block, using one item
+    Boat,
+    /// Put a cart on the clicked rail instead of placing a block, using one item
+    Cart,
 }
 
 /// The mesh of an item
@@ -30,3 +92,134 @@ pub struct Item {
     pub name: String,
     pub ty: ItemType,
 }
+
+impl Item {
+    /// Get the description of the item, possibly empty
+    pub fn description(&self) -> &str {
+        match &self.ty {
+            ItemType::NormalItem { description, .. } => description,
+        }
+    }
+
+    /// Get the number of seconds the item burns for in a furnace, 0 if it is not a fuel
+    pub fn burn_time(&self) -> f32 {
+        match &self.ty {
+            ItemType::NormalItem { burn_time, .. } => *burn_time,
+        }
+    }
+
+    /// Get the tool behavior of the item, if it has one
+    pub fn tool(&self) -> Option<ItemTool> {
+        match &self.ty {
+            ItemType::NormalItem { tool, .. } => *tool,
+        }
+    }
+
+    /// Get the mining properties of the item, if it is a pickaxe, an axe or a shovel
+    pub fn mining_tool(&self) -> Option<MiningTool> {
+        match &self.ty {
+            ItemType::NormalItem { mining_tool, .. } => *mining_tool,
+        }
+    }
+
+    /// Get the number of blocks the item can break before it is used up, 0 if it is never used up
+    pub fn durability(&self) -> u32 {
+        self.mining_tool().map_or(0, |tool| tool.durability)
+    }
+
+    /// Check if the item breaks `block` faster, because it is a tool of the preferred type of the block
+    /// or because the item lists the block
+    fn is_effective_on(&self, block: BlockId, blocks: &Registry<Block>) -> bool {
+        let preferred_tool = blocks
+            .get_value_by_id(block as u32)
+            .and_then(Block::preferred_tool);
+        match &self.ty {
+            ItemType::NormalItem {
+                effective_on,
+                mining_tool,
+                ..
+            } => {
+                (preferred_tool.is_some()
+                    && mining_tool.map(|tool| tool.tool_type) == preferred_tool)
+                    || effective_on
+                        .iter()
+                        .any(|name| blocks.matches(name, block as u32))
+            }
+        }
+    }
+}
+
+/// Get how many times faster `block` breaks than its break time with the item `held`, or with the bare hand.
+/// The effective items break the block faster, but the blocks that need a tool of some tier break slower
+/// without one.
+pub fn mining_speed(held: Option<&Item>, block: BlockId, blocks: &Registry<Block>) -> f32 {
+    let min_tier = blocks
+        .get_value_by_id(block as u32)
+        .map_or(0, Block::min_tier);
+    let mut speed = 1.0;
+    let mut has_tier = min_tier == 0;
+    if let Some(item) = held {
+        if item.is_effective_on(block, blocks) {
+            speed = match &item.ty {
+                ItemType::NormalItem { mining_speed, .. } => *mining_speed,
+            };
+            has_tier |= item
+                .mining_tool()
+                .map_or(false, |tool| tool.tier >= min_tier);
+        }
+    }
+    if has_tier {
+        speed
+    } else {
+        speed * WRONG_TOOL_SPEED
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{sound::SoundMaterial, BlockType};
+
+    #[test]
+    fn test_mining_speed() {
+        let mut blocks = Registry::default();
+        for (name, block_type) in [
+            (
+                "dirt",
+                "NormalCube(face_textures: [], preferred_tool: Some(Shovel))",
+            ),
+            (
+                "stone",
+                "NormalCube(face_textures: [], preferred_tool: Some(Pickaxe), min_tier: 2)",
+            ),
+        ]
+        .iter()
+        {
+            let block = Block {
+                name: (*name).to_owned(),
+                block_type: ron::de::from_str::<BlockType>(block_type).unwrap(),
+                sound_material: SoundMaterial::FALLBACK,
+            };
+            blocks.register((*name).to_owned(), block).unwrap();
+        }
+        let pickaxe = |tier: u8| Item {
+            name: "pickaxe".to_owned(),
+            ty: ron::de::from_str(&format!(
+                "NormalItem(texture: \"\", mining_speed: 4.0, \
+                 mining_tool: Some(MiningTool(tool_type: Pickaxe, tier: {}, durability: 10)))",
+                tier
+            ))
+            .unwrap(),
+        };
+        let (dirt, stone) = (0, 1);
+
+        assert_eq!(mining_speed(None, dirt, &blocks), 1.0);
+        assert_eq!(mining_speed(Some(&pickaxe(2)), dirt, &blocks), 1.0);
+        assert_eq!(mining_speed(Some(&pickaxe(2)), stone, &blocks), 4.0);
+        assert_eq!(
+            mining_speed(Some(&pickaxe(1)), stone, &blocks),
+            4.0 * WRONG_TOOL_SPEED
+        );
+        assert_eq!(mining_speed(None, stone, &blocks), WRONG_TOOL_SPEED);
+    }
+}
@@ -1,13 +1,25 @@
+pub mod animation;
 pub mod block;
+pub mod boss_bar;
 pub mod collections;
+pub mod content;
 pub mod data;
 pub mod debug;
+pub mod entity;
+pub mod inventory;
 pub mod item;
+pub mod metrics;
 pub mod network;
 pub mod physics;
 pub mod player;
 pub mod registry;
+pub mod scoreboard;
+pub mod skin;
+pub mod smelting;
+pub mod stats;
+pub mod tags;
 pub mod time;
 pub mod worker;
 pub mod world;
+pub mod world_view;
 pub mod worldgen;
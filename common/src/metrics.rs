@@ -0,0 +1,179 @@
+//! Global metrics registry, that any thread can update and that can be rendered in the Prometheus text format.
+//! Unlike the debug info, the metrics are always recorded, even if nobody reads them.
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref METRICS: Mutex<BTreeMap<&'static str, Metric>> = Mutex::new(BTreeMap::new());
+}
+
+/// Upper bounds of the buckets of the histograms, in seconds
+const HISTOGRAM_BUCKETS: [f64; 10] = [0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+#[derive(Debug, Clone)]
+struct Metric {
+    help: &'static str,
+    value: MetricValue,
+}
+
+#[derive(Debug, Clone)]
+enum MetricValue {
+    Gauge(f64),
+    Counter(u64),
+    /// Counters with the same name but different labels, by their rendered labels
+    LabeledCounters(BTreeMap<String, u64>),
+    Histogram {
+        /// Number of observations in every bucket, not cumulative
+        buckets: [u64; HISTOGRAM_BUCKETS.len()],
+        count: u64,
+        sum: f64,
+    },
+}
+
+impl MetricValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Gauge(_) => "gauge",
+            Self::Counter(_) | Self::LabeledCounters(_) => "counter",
+            Self::Histogram { .. } => "histogram",
+        }
+    }
+}
+
+fn update_metric(
+    name: &'static str,
+    help: &'static str,
+    default: MetricValue,
+    f: impl FnOnce(&mut MetricValue),
+) {
+    let mut metrics = METRICS.lock().unwrap();
+    let metric = metrics.entry(name).or_insert(Metric {
+        help,
+        value: default,
+    });
+    f(&mut metric.value);
+}
+
+/// Set the value of a gauge
+pub fn set_gauge(name: &'static str, help: &'static str, value: f64) {
+    update_metric(name, help, MetricValue::Gauge(0.0), |metric| {
+        *metric = MetricValue::Gauge(value)
+    });
+}
+
+/// Increase a counter by `count`
+pub fn add_to_counter(name: &'static str, help: &'static str, count: u64) {
+    update_metric(name, help, MetricValue::Counter(0), |metric| {
+        if let MetricValue::Counter(value) = metric {
+            *value += count;
+        }
+    });
+}
+
+/// Increase the counter with some labels by `count`, for example `&[("direction", "up")]`
+pub fn add_to_labeled_counter(
+    name: &'static str,
+    help: &'static str,
+    labels: &[(&str, &str)],
+    count: u64,
+) {
+    let labels = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, value))
+        .collect::<Vec<_>>()
+        .join(",");
+    let default = MetricValue::LabeledCounters(BTreeMap::new());
+    update_metric(name, help, default, |metric| {
+        if let MetricValue::LabeledCounters(counters) = metric {
+            *counters.entry(labels).or_insert(0) += count;
+        }
+    });
+}
+
+/// Add an observation, in seconds, to a histogram
+pub fn observe_histogram(name: &'static str, help: &'static str, seconds: f64) {
+    let default = MetricValue::Histogram {
+        buckets: [0; HISTOGRAM_BUCKETS.len()],
+        count: 0,
+        sum: 0.0,
+    };
+    update_metric(name, help, default, |metric| {
+        if let MetricValue::Histogram {
+            buckets,
+            count,
+            sum,
+        } = metric
+        {
+            if let Some(bucket) = HISTOGRAM_BUCKETS.iter().position(|&le| seconds <= le) {
+                buckets[bucket] += 1;
+            }
+            *count += 1;
+            *sum += seconds;
+        }
+    });
+}
+
+/// Render all the metrics in the Prometheus text exposition format
+pub fn render_prometheus() -> String {
+    let metrics = METRICS.lock().unwrap();
+    let mut out = String::new();
+    for (name, metric) in metrics.iter() {
+        writeln!(out, "# HELP {} {}", name, metric.help).unwrap();
+        writeln!(out, "# TYPE {} {}", name, metric.value.type_name()).unwrap();
+        match &metric.value {
+            MetricValue::Gauge(value) => writeln!(out, "{} {}", name, value).unwrap(),
+            MetricValue::Counter(value) => writeln!(out, "{} {}", name, value).unwrap(),
+            MetricValue::LabeledCounters(counters) => {
+                for (labels, value) in counters.iter() {
+                    writeln!(out, "{}{{{}}} {}", name, labels, value).unwrap();
+                }
+            }
+            MetricValue::Histogram {
+                buckets,
+                count,
+                sum,
+            } => {
+                let mut cumulative = 0;
+                for (le, bucket) in HISTOGRAM_BUCKETS.iter().zip(buckets.iter()) {
+                    cumulative += bucket;
+                    writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative).unwrap();
+                }
+                writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count).unwrap();
+                writeln!(out, "{}_sum {}", name, sum).unwrap();
+                writeln!(out, "{}_count {}", name, count).unwrap();
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_histogram() {
+        observe_histogram("test_tick_seconds", "Test histogram", 0.003);
+        observe_histogram("test_tick_seconds", "Test histogram", 2.0);
+        let rendered = render_prometheus();
+        assert!(rendered.contains("# TYPE test_tick_seconds histogram\n"));
+        assert!(rendered.contains("test_tick_seconds_bucket{le=\"0.002\"} 0\n"));
+        assert!(rendered.contains("test_tick_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(rendered.contains("test_tick_seconds_bucket{le=\"1\"} 1\n"));
+        assert!(rendered.contains("test_tick_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(rendered.contains("test_tick_seconds_count 2\n"));
+    }
+
+    #[test]
+    fn test_render_labeled_counters() {
+        add_to_labeled_counter("test_bytes_total", "Test counter", &[("kind", "a")], 3);
+        add_to_labeled_counter("test_bytes_total", "Test counter", &[("kind", "b")], 1);
+        add_to_labeled_counter("test_bytes_total", "Test counter", &[("kind", "a")], 2);
+        let rendered = render_prometheus();
+        assert!(rendered.contains("# TYPE test_bytes_total counter\n"));
+        assert!(rendered.contains("test_bytes_total{kind=\"a\"} 5\n"));
+        assert!(rendered.contains("test_bytes_total{kind=\"b\"} 1\n"));
+    }
+}
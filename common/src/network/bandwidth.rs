@@ -0,0 +1,111 @@
+//! Accounting of the network traffic, by message type and direction.
+//! The totals are exported to the metrics, and a `BandwidthMeter` turns them into rates for the debug overlay.
+
+use crate::metrics::add_to_labeled_counter;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref TRAFFIC: Mutex<BTreeMap<(Direction, &'static str), u64>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Time over which the rates are averaged
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    /// From the client to the server
+    Up,
+    /// From the server to the client
+    Down,
+}
+
+impl Direction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+        }
+    }
+}
+
+/// Count a message of type `kind` of `bytes` bytes going in some direction
+pub fn record_message(direction: Direction, kind: &'static str, bytes: usize) {
+    *TRAFFIC
+        .lock()
+        .unwrap()
+        .entry((direction, kind))
+        .or_insert(0) += bytes as u64;
+    let labels = [("direction", direction.name()), ("message", kind)];
+    add_to_labeled_counter(
+        "voxel_network_bytes_total",
+        "Bytes sent over the network, by direction and message type. The integrated network doesn't serialize the messages, so their sizes are estimated.",
+        &labels,
+        bytes as u64,
+    );
+    add_to_labeled_counter(
+        "voxel_network_messages_total",
+        "Messages sent over the network, by direction and message type.",
+        &labels,
+        1,
+    );
+}
+
+/// The traffic of the last `RATE_INTERVAL`, in bytes per second
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthRates {
+    pub up: f64,
+    pub down: f64,
+    /// The rate of every message type that was sent, the highest first
+    pub by_message: Vec<(Direction, &'static str, f64)>,
+}
+
+/// Measure the rates of the traffic
+pub struct BandwidthMeter {
+    last_measure: Instant,
+    last_totals: BTreeMap<(Direction, &'static str), u64>,
+}
+
+impl BandwidthMeter {
+    pub fn new() -> Self {
+        Self {
+            last_measure: Instant::now(),
+            last_totals: TRAFFIC.lock().unwrap().clone(),
+        }
+    }
+
+    /// Get the rates since the last measure, if it was at least `RATE_INTERVAL` ago
+    pub fn measure(&mut self) -> Option<BandwidthRates> {
+        let elapsed = self.last_measure.elapsed();
+        if elapsed < RATE_INTERVAL {
+            return None;
+        }
+        let totals = TRAFFIC.lock().unwrap().clone();
+        let mut rates = BandwidthRates::default();
+        for (&(direction, kind), &total) in totals.iter() {
+            let previous = self
+                .last_totals
+                .get(&(direction, kind))
+                .copied()
+                .unwrap_or(0);
+            if total == previous {
+                continue;
+            }
+            let rate = (total - previous) as f64 / elapsed.as_secs_f64();
+            match direction {
+                Direction::Up => rates.up += rate,
+                Direction::Down => rates.down += rate,
+            }
+            rates.by_message.push((direction, kind, rate));
+        }
+        rates
+            .by_message
+            .sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+        self.last_measure = Instant::now();
+        self.last_totals = totals;
+        Some(rates)
+    }
+}
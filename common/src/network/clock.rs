@@ -0,0 +1,287 @@
+//! Synchronization of the client clock with the server clock.
+//! The `Instant`s of two machines can't be compared, so the server timestamps are `ServerTime`s: the time
+//! elapsed since the server started. The client estimates the offset between its clock and the server clock
+//! like NTP: it sends its local time in a `ToServer::TimeRequest`, and the server answers with the same time
+//! and its own time. Assuming the same latency in both directions, the server time was read half a round trip
+//! before the answer arrived. The samples with the shortest round trips are the most precise, so the estimation
+//! uses the best of the recent samples, and it is refined periodically to follow the drift of the clocks.
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::{Add, AddAssign, Sub};
+use std::time::{Duration, Instant};
+
+/// Number of requests sent quickly after connecting, to get a good estimation early
+const INITIAL_REQUESTS: u32 = 5;
+/// Time between two of the initial requests
+const INITIAL_REQUEST_INTERVAL: Duration = Duration::from_millis(100);
+/// Time between two requests once the initial requests were sent
+const REFINE_INTERVAL: Duration = Duration::from_secs(5);
+/// Number of recent samples the best sample is picked from
+const MAX_SAMPLES: usize = 8;
+/// Maximum speed of the correction of the estimated server clock, in seconds per second,
+/// so that the server time seen by the client never jumps or goes back
+const MAX_SLEW_RATE: f64 = 0.05;
+
+/// A time on the server clock: the time elapsed since the server started
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct ServerTime(Duration);
+
+impl ServerTime {
+    pub fn from_duration(duration: Duration) -> Self {
+        Self(duration)
+    }
+
+    pub fn as_duration(self) -> Duration {
+        self.0
+    }
+}
+
+impl Add<Duration> for ServerTime {
+    type Output = Self;
+
+    fn add(self, duration: Duration) -> Self {
+        Self(self.0 + duration)
+    }
+}
+
+impl AddAssign<Duration> for ServerTime {
+    fn add_assign(&mut self, duration: Duration) {
+        self.0 += duration;
+    }
+}
+
+impl Sub for ServerTime {
+    type Output = Duration;
+
+    /// The time between two server times, zero if `other` is later
+    fn sub(self, other: Self) -> Duration {
+        self.0.checked_sub(other.0).unwrap_or_default()
+    }
+}
+
+/// The clock of the server
+pub struct ServerClock {
+    start: Instant,
+}
+
+impl ServerClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+
+    pub fn now(&self) -> ServerTime {
+        ServerTime(self.start.elapsed())
+    }
+}
+
+/// A measure of the clock offset
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    round_trip: f64,
+    /// Server time minus local time, in seconds
+    offset: f64,
+}
+
+/// The client estimation of the server clock
+pub struct ClockSync {
+    /// Origin of the local times sent to the server
+    start: Instant,
+    samples: VecDeque<Sample>,
+    /// Offset of the best recent sample
+    target_offset: f64,
+    /// Offset currently applied, that moves towards `target_offset`, `None` before the first sample
+    offset: Option<f64>,
+    /// Local time of the last correction of `offset`
+    last_slew: Duration,
+    /// Last server time returned, the estimated server clock never goes back
+    last_server_time: ServerTime,
+    requests_sent: u32,
+    last_request: Option<Duration>,
+}
+
+impl ClockSync {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            samples: VecDeque::new(),
+            target_offset: 0.0,
+            offset: None,
+            last_slew: Duration::default(),
+            last_server_time: ServerTime::default(),
+            requests_sent: 0,
+            last_request: None,
+        }
+    }
+
+    fn local_time(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// The local time to send in a `ToServer::TimeRequest`, if a new request should be sent
+    pub fn poll_request(&mut self) -> Option<Duration> {
+        let now = self.local_time();
+        self.poll_request_at(now)
+    }
+
+    fn poll_request_at(&mut self, now: Duration) -> Option<Duration> {
+        let interval = if self.requests_sent < INITIAL_REQUESTS {
+            INITIAL_REQUEST_INTERVAL
+        } else {
+            REFINE_INTERVAL
+        };
+        match self.last_request {
+            Some(last) if now < last + interval => None,
+            _ => {
+                self.requests_sent += 1;
+                self.last_request = Some(now);
+                Some(now)
+            }
+        }
+    }
+
+    /// Process a `ToClient::TimeResponse`
+    pub fn receive_response(&mut self, client_time: Duration, server_time: ServerTime) {
+        let now = self.local_time();
+        self.receive_response_at(client_time, server_time, now);
+    }
+
+    fn receive_response_at(
+        &mut self,
+        client_time: Duration,
+        server_time: ServerTime,
+        now: Duration,
+    ) {
+        if client_time > now {
+            // Not one of our requests
+            return;
+        }
+        let round_trip = (now - client_time).as_secs_f64();
+        let offset = server_time.0.as_secs_f64() + round_trip / 2.0 - now.as_secs_f64();
+        self.samples.push_back(Sample { round_trip, offset });
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        let best = self
+            .samples
+            .iter()
+            .min_by(|a, b| a.round_trip.partial_cmp(&b.round_trip).unwrap())
+            .unwrap();
+        self.target_offset = best.offset;
+        if self.offset.is_none() {
+            self.offset = Some(self.target_offset);
+            self.last_slew = now;
+        }
+    }
+
+    /// Whether at least one answer of the server arrived
+    pub fn is_synchronized(&self) -> bool {
+        self.offset.is_some()
+    }
+
+    /// The round-trip time of the best recent sample
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        self.samples
+            .iter()
+            .map(|sample| sample.round_trip)
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+            .map(Duration::from_secs_f64)
+    }
+
+    /// The estimated difference between the server clock and the client clock, in seconds
+    pub fn offset(&self) -> Option<f64> {
+        self.offset
+    }
+
+    /// The estimated current time of the server clock
+    pub fn server_now(&mut self) -> ServerTime {
+        let now = self.local_time();
+        self.server_time_at(now)
+    }
+
+    fn server_time_at(&mut self, now: Duration) -> ServerTime {
+        let offset = match self.offset.as_mut() {
+            Some(offset) => offset,
+            None => return self.last_server_time,
+        };
+        let max_correction =
+            (now.checked_sub(self.last_slew).unwrap_or_default()).as_secs_f64() * MAX_SLEW_RATE;
+        let correction = (self.target_offset - *offset)
+            .max(-max_correction)
+            .min(max_correction);
+        *offset += correction;
+        self.last_slew = now;
+        let seconds = (now.as_secs_f64() + *offset).max(0.0);
+        let time = ServerTime(Duration::from_secs_f64(seconds));
+        self.last_server_time = self.last_server_time.max(time);
+        self.last_server_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Xorshift generator for the network jitter
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    /// A server whose clock is ahead of the client clock and slightly faster, with asymmetric jittery latencies
+    #[test]
+    fn test_skewed_clock() {
+        let skew = 1000.0;
+        let drift = 1.0001;
+        let server_clock = |local: f64| ServerTime(Duration::from_secs_f64(skew + local * drift));
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut sync = ClockSync::new();
+        let step = Duration::from_millis(10);
+        let mut now = Duration::from_secs(1);
+        // (arrival, client time, server time)
+        let mut in_flight: Vec<(Duration, Duration, ServerTime)> = Vec::new();
+        let mut last_time = ServerTime::default();
+        for _ in 0..6000 {
+            now += step;
+            if let Some(client_time) = sync.poll_request_at(now) {
+                let to_server = Duration::from_millis(40 + rng.below(30));
+                let to_client = Duration::from_millis(60 + rng.below(30));
+                let server_time = server_clock((now + to_server).as_secs_f64());
+                in_flight.push((now + to_server + to_client, client_time, server_time));
+            }
+            for &(_, client_time, server_time) in in_flight.iter().filter(|m| m.0 <= now) {
+                sync.receive_response_at(client_time, server_time, now);
+            }
+            in_flight.retain(|m| m.0 > now);
+
+            let time = sync.server_time_at(now);
+            assert!(time >= last_time, "the server clock went back");
+            last_time = time;
+            if sync.is_synchronized() {
+                let truth = server_clock(now.as_secs_f64()).0.as_secs_f64();
+                let error = (time.0.as_secs_f64() - truth).abs();
+                // The asymmetry of the latencies is at most 50 ms, plus the drift between two refinements
+                assert!(error < 0.03, "error of {} s", error);
+            }
+        }
+        assert!(sync.is_synchronized());
+        assert!(sync.round_trip_time().unwrap() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_server_time_difference_saturates() {
+        let a = ServerTime::from_duration(Duration::from_secs(1));
+        let b = a + Duration::from_millis(500);
+        assert_eq!(b - a, Duration::from_millis(500));
+        assert_eq!(a - b, Duration::default());
+    }
+}
@@ -1,6 +1,10 @@
 use super::messages::{ToClient, ToServer};
 use crate::{
-    network::{ClientEvent, ServerEvent},
+    metrics::add_to_counter,
+    network::{
+        bandwidth::{record_message, Direction},
+        ClientEvent, ServerEvent,
+    },
     player::PlayerId,
 };
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
@@ -41,18 +45,37 @@ impl super::Server for DummyServer {
             return ServerEvent::ClientConnected(PlayerId(0));
         }
         match self.to_server.try_recv() {
-            Ok(m) => ServerEvent::ClientMessage(PlayerId(0), m),
+            Ok(m) => {
+                record_message(Direction::Up, m.kind(), m.estimated_size());
+                add_to_counter(
+                    "voxel_server_messages_received_total",
+                    "Messages received by the server. The dummy network doesn't serialize the messages, so bytes can't be counted.",
+                    1,
+                );
+                ServerEvent::ClientMessage(PlayerId(0), m)
+            }
             Err(TryRecvError::Empty) => ServerEvent::NoEvent,
             Err(TryRecvError::Disconnected) => panic!("Got to somehow terminate the server :)"),
         }
     }
 
     fn send(&mut self, _: PlayerId, message: ToClient) {
+        record_message(Direction::Down, message.kind(), message.estimated_size());
+        add_to_counter(
+            "voxel_server_messages_sent_total",
+            "Messages sent by the server. The dummy network doesn't serialize the messages, so bytes can't be counted.",
+            1,
+        );
         self.to_client
             .send(message)
             .map_err(|e| log::error!("Failed to send message to client: {:?}", e))
             .ok();
     }
+
+    fn is_host(&self, _: PlayerId) -> bool {
+        // The only client is the player of the singleplayer world
+        true
+    }
 }
 
 impl super::Client for DummyClient {
@@ -0,0 +1,222 @@
+//! Emulation of a bad network, for development. The `EmulatedClient` wraps a client and delays the messages
+//! in both directions, so that prediction, interpolation and chunk streaming can be tested locally.
+//!
+//! The physics updates of the server and the inputs of the client are sent again every tick, so they are
+//! dropped when they are lost, and the jitter reorders them. The other messages must arrive, so their losses
+//! are emulated as retransmissions that cost one more round trip, and they always arrive in order.
+
+use super::messages::{ToClient, ToServer};
+use super::{Client, ClientEvent};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Number of times a message can be lost in a row before it goes through anyway
+const MAX_RETRANSMISSIONS: u32 = 10;
+
+/// The conditions of the emulated network
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkConditions {
+    /// Delay added to every message, in each direction
+    pub lag: Duration,
+    /// Maximum random variation of the delay, above or below the lag
+    pub jitter: Duration,
+    /// Probability that a message is lost, between 0 and 1
+    pub loss: f64,
+}
+
+/// Small xorshift generator, good enough to decide which messages are lost
+struct Rng(u64);
+
+impl Rng {
+    fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Self(nanos | 1)
+    }
+
+    /// Uniform random number in `[0, 1)`
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// The messages in flight in one direction, by delivery time
+struct DelayedMessages<T> {
+    messages: BTreeMap<(Instant, u64), T>,
+    next_sequence: u64,
+    /// Delivery time of the last message that must arrive in order
+    last_reliable_delivery: Option<Instant>,
+}
+
+impl<T> DelayedMessages<T> {
+    fn new() -> Self {
+        Self {
+            messages: BTreeMap::new(),
+            next_sequence: 0,
+            last_reliable_delivery: None,
+        }
+    }
+
+    /// Send a message through the emulated network, unless it is lost
+    fn push(&mut self, message: T, reliable: bool, conditions: &NetworkConditions, rng: &mut Rng) {
+        let mut delay = conditions.lag.as_secs_f64()
+            + conditions.jitter.as_secs_f64() * (2.0 * rng.next() - 1.0);
+        if reliable {
+            let mut retransmissions = 0;
+            while retransmissions < MAX_RETRANSMISSIONS && rng.next() < conditions.loss {
+                delay += 2.0 * conditions.lag.as_secs_f64();
+                retransmissions += 1;
+            }
+        } else if rng.next() < conditions.loss {
+            return;
+        }
+        let mut delivery = Instant::now() + Duration::from_secs_f64(delay.max(0.0));
+        if reliable {
+            if let Some(last) = self.last_reliable_delivery {
+                delivery = delivery.max(last);
+            }
+            self.last_reliable_delivery = Some(delivery);
+        }
+        self.messages
+            .insert((delivery, self.next_sequence), message);
+        self.next_sequence += 1;
+    }
+
+    /// Get the next message that arrived
+    fn pop_delivered(&mut self) -> Option<T> {
+        let key = *self.messages.keys().next()?;
+        if key.0 <= Instant::now() {
+            self.messages.remove(&key)
+        } else {
+            None
+        }
+    }
+}
+
+/// A client whose messages go through an emulated network
+pub struct EmulatedClient {
+    client: Box<dyn Client>,
+    conditions: NetworkConditions,
+    rng: Rng,
+    to_client: DelayedMessages<ToClient>,
+    to_server: DelayedMessages<ToServer>,
+}
+
+impl EmulatedClient {
+    pub fn new(client: Box<dyn Client>, conditions: NetworkConditions) -> Self {
+        log::info!("Emulating network conditions: {:?}", conditions);
+        Self {
+            client,
+            conditions,
+            rng: Rng::from_time(),
+            to_client: DelayedMessages::new(),
+            to_server: DelayedMessages::new(),
+        }
+    }
+
+    /// Send the messages that reached the server
+    fn flush_to_server(&mut self) {
+        while let Some(message) = self.to_server.pop_delivered() {
+            self.client.send(message);
+        }
+    }
+}
+
+impl Client for EmulatedClient {
+    fn receive_event(&mut self) -> ClientEvent {
+        self.flush_to_server();
+        loop {
+            match self.client.receive_event() {
+                ClientEvent::NoEvent => break,
+                ClientEvent::ServerMessage(message) => {
+                    let reliable = match message {
+                        ToClient::UpdatePhysics(_)
+                        | ToClient::UpdateActivities(_)
+                        | ToClient::TimeResponse(..) => false,
+                        _ => true,
+                    };
+                    self.to_client
+                        .push(message, reliable, &self.conditions, &mut self.rng);
+                }
+                event => return event,
+            }
+        }
+        match self.to_client.pop_delivered() {
+            Some(message) => ClientEvent::ServerMessage(message),
+            None => ClientEvent::NoEvent,
+        }
+    }
+
+    fn send(&mut self, message: ToServer) {
+        let reliable = match message {
+            ToServer::UpdateInput(_) | ToServer::TimeRequest(_) | ToServer::AckPhysics(_) => false,
+            _ => true,
+        };
+        self.to_server
+            .push(message, reliable, &self.conditions, &mut self.rng);
+        self.flush_to_server();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{dummy, Server, ServerEvent};
+    use crate::player::PlayerInput;
+
+    fn received_messages(server: &mut dyn Server) -> Vec<ToServer> {
+        let mut messages = Vec::new();
+        loop {
+            match server.receive_event() {
+                ServerEvent::NoEvent => break messages,
+                ServerEvent::ClientMessage(_, message) => messages.push(message),
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn reliable_messages_arrive_in_order() {
+        let (client, mut server) = dummy::new();
+        let conditions = NetworkConditions {
+            lag: Duration::from_millis(0),
+            jitter: Duration::from_millis(0),
+            loss: 1.0,
+        };
+        let mut client = EmulatedClient::new(Box::new(client), conditions);
+        client.send(ToServer::Command("first".to_owned()));
+        client.send(ToServer::UpdateInput(PlayerInput::default()));
+        client.send(ToServer::Command("second".to_owned()));
+
+        // The input was lost, but the commands were retransmitted
+        let commands = received_messages(&mut server)
+            .into_iter()
+            .map(|message| match message {
+                ToServer::Command(command) => command,
+                message => panic!("Unexpected message {:?}", message),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(commands, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn messages_are_delayed() {
+        let (client, mut server) = dummy::new();
+        let conditions = NetworkConditions {
+            lag: Duration::from_millis(50),
+            jitter: Duration::from_millis(0),
+            loss: 0.0,
+        };
+        let mut client = EmulatedClient::new(Box::new(client), conditions);
+        client.send(ToServer::Command("delayed".to_owned()));
+        assert!(received_messages(&mut server).is_empty());
+
+        std::thread::sleep(Duration::from_millis(60));
+        client.receive_event();
+        assert_eq!(received_messages(&mut server).len(), 1);
+    }
+}
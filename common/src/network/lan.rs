@@ -0,0 +1,548 @@
+//! The game network of the worlds opened to LAN, over the UDP network of `voxel_rs_network`.
+//! The integrated server accepts the players of the local network with a `LanServer` once its world is opened,
+//! and announces the world to the local network. A `LanClient` joins such a world.
+//!
+//! The messages are serialized with bincode and split into fragments that fit in a packet. The fragments are all
+//! ordered, so a message is complete when its last fragment arrives. The game data is not sent: the client uses
+//! the data it loaded itself, that must have the same blocks and items as the data of the server.
+
+use super::messages::{ToClient, ToServer};
+use super::{
+    bandwidth::{record_message, Direction},
+    dummy::DummyServer,
+    Client, ClientEvent, Server, ServerEvent,
+};
+use crate::{data::Data, metrics::add_to_counter, player::PlayerId};
+use anyhow::{Context, Result};
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use voxel_rs_network::{LanAdvertiser, LanAnnouncement, MessageDelivery};
+
+/// The id of the player hosting the integrated server
+const HOST_ID: PlayerId = PlayerId(0);
+/// Largest number of bytes of a message in a fragment, leaving room for the headers of the packet
+const FRAGMENT_SIZE: usize = 1024;
+/// Largest size of a message, so that a damaged message can't make the receiver allocate too much memory
+const MAX_MESSAGE_SIZE: u64 = 16 * 1024 * 1024;
+/// First byte of the fragments that are followed by other fragments of the same message
+const MORE_FRAGMENTS: u8 = 1;
+/// First byte of the last fragment of a message
+const LAST_FRAGMENT: u8 = 0;
+
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_MESSAGE_SIZE)
+}
+
+/// What the server sends to a LAN client
+#[derive(Debug, Serialize, Deserialize)]
+enum WireToClient {
+    /// Replaces `ToClient::GameData`, with the numbers of blocks and items so that the client can check that its
+    /// own data matches the data of the server
+    GameData {
+        blocks: u32,
+        items: u32,
+    },
+    Message(ToClient),
+}
+
+/// Serialize a message and split it into fragments
+fn encode<T: Serialize>(message: &T) -> Result<Vec<Vec<u8>>> {
+    let bytes = bincode_options()
+        .serialize(message)
+        .context("Failed to serialize message")?;
+    let num_fragments = (bytes.len() + FRAGMENT_SIZE - 1) / FRAGMENT_SIZE;
+    Ok(bytes
+        .chunks(FRAGMENT_SIZE)
+        .enumerate()
+        .map(|(i, part)| {
+            let mut fragment = Vec::with_capacity(part.len() + 1);
+            fragment.push(if i + 1 < num_fragments {
+                MORE_FRAGMENTS
+            } else {
+                LAST_FRAGMENT
+            });
+            fragment.extend_from_slice(part);
+            fragment
+        })
+        .collect())
+}
+
+/// Put the fragments of the messages back together
+#[derive(Debug, Default)]
+struct Defragmenter {
+    partial: Vec<u8>,
+}
+
+impl Defragmenter {
+    /// Add the next fragment. Return the message once its last fragment was added.
+    fn push<T: DeserializeOwned>(&mut self, fragment: &[u8]) -> Option<Result<T>> {
+        let (&flag, part) = match fragment.split_first() {
+            Some(split) => split,
+            None => return Some(Err(anyhow::anyhow!("Received an empty fragment"))),
+        };
+        self.partial.extend_from_slice(part);
+        if flag == MORE_FRAGMENTS && self.partial.len() as u64 <= MAX_MESSAGE_SIZE {
+            return None;
+        }
+        let bytes = std::mem::take(&mut self.partial);
+        Some(
+            bincode_options()
+                .deserialize(&bytes)
+                .context("Failed to deserialize message"),
+        )
+    }
+}
+
+/// The server of the players of the local network, who joined a world opened to LAN
+pub struct LanServer {
+    server: voxel_rs_network::Server<UdpSocket>,
+    advertiser: LanAdvertiser,
+    port: u16,
+    /// The ids of the connected players by address, and their addresses by id
+    ids: HashMap<SocketAddr, PlayerId>,
+    addresses: HashMap<PlayerId, SocketAddr>,
+    defragmenters: HashMap<PlayerId, Defragmenter>,
+    /// The first id of the players, and the id tried first for the next player to connect
+    first_id: u16,
+    next_id: u16,
+    events: VecDeque<ServerEvent>,
+}
+
+impl LanServer {
+    /// Listen on a free port and announce the world to the local network with some name.
+    /// The ids of the players start at `first_id`, so that they are not the ids of the local players.
+    pub fn bind(name: &str, first_id: u16) -> Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .context("Failed to bind the socket of the LAN server")?;
+        socket
+            .set_nonblocking(true)
+            .context("Failed to bind the socket of the LAN server")?;
+        let port = socket
+            .local_addr()
+            .context("Failed to get the port of the LAN server")?
+            .port();
+        let advertiser = LanAdvertiser::new(&LanAnnouncement {
+            name: name.to_owned(),
+            port,
+        })
+        .context("Failed to announce the world to the local network")?;
+        Ok(Self {
+            server: voxel_rs_network::Server::new(socket),
+            advertiser,
+            port,
+            ids: HashMap::new(),
+            addresses: HashMap::new(),
+            defragmenters: HashMap::new(),
+            first_id,
+            next_id: first_id,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// The port the server listens on
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Find an id for a new player. The ids wrap around to `first_id` after the last one,
+    /// skipping the ids of the players that are still connected.
+    fn allocate_id(&mut self) -> Option<PlayerId> {
+        let num_ids = (u16::MAX - self.first_id) as usize + 1;
+        for _ in 0..num_ids {
+            let id = PlayerId(self.next_id);
+            self.next_id = self.next_id.checked_add(1).unwrap_or(self.first_id);
+            if !self.addresses.contains_key(&id) {
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Announce the world, send the queued messages and receive the new ones
+    fn tick(&mut self) {
+        self.advertiser.tick();
+        self.server.tick();
+        let network_events: Vec<_> = self.server.get_events().collect();
+        for event in network_events {
+            match event {
+                voxel_rs_network::ServerEvent::Connected { id: address } => {
+                    let id = match self.allocate_id() {
+                        Some(id) => id,
+                        None => {
+                            log::warn!("No player id left for the player from {}", address);
+                            continue;
+                        }
+                    };
+                    log::info!("Player {} joined from {}", id, address);
+                    self.ids.insert(address, id);
+                    self.addresses.insert(id, address);
+                    self.defragmenters.insert(id, Defragmenter::default());
+                    self.events.push_back(ServerEvent::ClientConnected(id));
+                }
+                voxel_rs_network::ServerEvent::Disconnected { id: address } => {
+                    if let Some(id) = self.ids.remove(&address) {
+                        self.addresses.remove(&id);
+                        self.defragmenters.remove(&id);
+                        self.events.push_back(ServerEvent::ClientDisconnected(id));
+                    }
+                }
+                voxel_rs_network::ServerEvent::Message {
+                    source_id: address,
+                    data,
+                    ..
+                } => {
+                    let id = match self.ids.get(&address) {
+                        Some(&id) => id,
+                        None => continue,
+                    };
+                    let defragmenter = self.defragmenters.entry(id).or_default();
+                    match defragmenter.push::<ToServer>(&data) {
+                        None => {}
+                        Some(Ok(message)) => {
+                            record_message(Direction::Up, message.kind(), data.len());
+                            add_to_counter(
+                                "voxel_server_messages_received_total",
+                                "Messages received by the server.",
+                                1,
+                            );
+                            self.events
+                                .push_back(ServerEvent::ClientMessage(id, message));
+                        }
+                        Some(Err(e)) => log::warn!("Invalid message from player {}: {:?}", id, e),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Server for LanServer {
+    fn receive_event(&mut self) -> ServerEvent {
+        if self.events.is_empty() {
+            self.tick();
+        }
+        self.events.pop_front().unwrap_or(ServerEvent::NoEvent)
+    }
+
+    fn send(&mut self, client: PlayerId, message: ToClient) {
+        let address = match self.addresses.get(&client) {
+            Some(&address) => address,
+            None => return,
+        };
+        let kind = message.kind();
+        let message = match message {
+            ToClient::GameData(data) => WireToClient::GameData {
+                blocks: data.blocks.get_number_of_ids(),
+                items: data.items.get_number_of_ids(),
+            },
+            message => WireToClient::Message(message),
+        };
+        match encode(&message) {
+            Ok(fragments) => {
+                let size = fragments.iter().map(Vec::len).sum();
+                record_message(Direction::Down, kind, size);
+                add_to_counter(
+                    "voxel_server_messages_sent_total",
+                    "Messages sent by the server.",
+                    1,
+                );
+                for fragment in fragments {
+                    self.server
+                        .send_message(address, fragment, MessageDelivery::Ordered);
+                }
+            }
+            Err(e) => log::error!("Failed to send message to player {}: {:?}", client, e),
+        }
+    }
+}
+
+/// A client playing in a world opened to LAN
+pub struct LanClient {
+    client: voxel_rs_network::Client<UdpSocket>,
+    /// The data loaded by the client, given to the game instead of the data of the server
+    game_data: Data,
+    connected: bool,
+    disconnected: bool,
+    /// The messages sent before the connection was established
+    pending: Vec<ToServer>,
+    defragmenter: Defragmenter,
+    events: VecDeque<ClientEvent>,
+}
+
+impl LanClient {
+    /// Start connecting to the server at some address
+    pub fn connect(server: SocketAddr, game_data: Data) -> Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .context("Failed to bind the socket of the LAN client")?;
+        socket
+            .set_nonblocking(true)
+            .context("Failed to bind the socket of the LAN client")?;
+        let mut client = voxel_rs_network::Client::new(socket, server);
+        client.connect();
+        Ok(Self {
+            client,
+            game_data,
+            connected: false,
+            disconnected: false,
+            pending: Vec::new(),
+            defragmenter: Defragmenter::default(),
+            events: VecDeque::new(),
+        })
+    }
+
+    fn disconnect(&mut self) {
+        self.disconnected = true;
+        self.events.push_back(ClientEvent::Disconnected);
+    }
+
+    /// Send the queued messages and receive the new ones
+    fn tick(&mut self) {
+        if self.disconnected {
+            return;
+        }
+        self.client.tick();
+        if !self.connected && self.client.is_connected() {
+            self.connected = true;
+            self.events.push_back(ClientEvent::Connected);
+            for message in std::mem::take(&mut self.pending) {
+                self.send(message);
+            }
+        }
+        let messages: Vec<_> = self.client.get_messages().collect();
+        for (_, data) in messages {
+            match self.defragmenter.push::<WireToClient>(&data) {
+                None => {}
+                Some(Ok(WireToClient::GameData { blocks, items })) => {
+                    let local_blocks = self.game_data.blocks.get_number_of_ids();
+                    let local_items = self.game_data.items.get_number_of_ids();
+                    if (blocks, items) != (local_blocks, local_items) {
+                        log::error!(
+                            "The server has {} blocks and {} items, but the game data has {} blocks and {} items",
+                            blocks,
+                            items,
+                            local_blocks,
+                            local_items
+                        );
+                        self.disconnect();
+                        return;
+                    }
+                    self.events
+                        .push_back(ClientEvent::ServerMessage(ToClient::GameData(
+                            self.game_data.clone(),
+                        )));
+                }
+                Some(Ok(WireToClient::Message(message))) => {
+                    self.events.push_back(ClientEvent::ServerMessage(message))
+                }
+                Some(Err(e)) => log::warn!("Invalid message from the server: {:?}", e),
+            }
+        }
+        if let Some(message) = self.client.disconnect_message() {
+            log::warn!("Disconnected from the server: {}", message);
+            self.disconnect();
+        }
+    }
+}
+
+impl Client for LanClient {
+    fn receive_event(&mut self) -> ClientEvent {
+        if self.events.is_empty() {
+            self.tick();
+        }
+        self.events.pop_front().unwrap_or(ClientEvent::NoEvent)
+    }
+
+    fn send(&mut self, message: ToServer) {
+        if !self.connected {
+            self.pending.push(message);
+            return;
+        }
+        match encode(&message) {
+            Ok(fragments) => {
+                for fragment in fragments {
+                    self.client.send_message(fragment, MessageDelivery::Ordered);
+                }
+            }
+            Err(e) => log::error!("Failed to send message to the server: {:?}", e),
+        }
+    }
+}
+
+/// The server of a singleplayer world: the host plays through the in-memory network,
+/// and the players of the local network join through a `LanServer` once the world is opened to LAN
+pub struct IntegratedServer {
+    host: DummyServer,
+    lan: Option<LanServer>,
+}
+
+impl IntegratedServer {
+    pub fn new(host: DummyServer) -> Self {
+        Self { host, lan: None }
+    }
+}
+
+impl Server for IntegratedServer {
+    fn receive_event(&mut self) -> ServerEvent {
+        match self.host.receive_event() {
+            ServerEvent::NoEvent => match &mut self.lan {
+                Some(lan) => lan.receive_event(),
+                None => ServerEvent::NoEvent,
+            },
+            event => event,
+        }
+    }
+
+    fn send(&mut self, client: PlayerId, message: ToClient) {
+        if client == HOST_ID {
+            self.host.send(client, message);
+        } else if let Some(lan) = &mut self.lan {
+            lan.send(client, message);
+        }
+    }
+
+    fn is_host(&self, client: PlayerId) -> bool {
+        client == HOST_ID
+    }
+
+    fn open_to_lan(&mut self, name: &str) -> Result<u16> {
+        if let Some(lan) = &self.lan {
+            return Ok(lan.port());
+        }
+        let lan = LanServer::bind(name, HOST_ID.0 + 1)?;
+        let port = lan.port();
+        self.lan = Some(lan);
+        Ok(port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Chunk, ChunkPos, LightChunk};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_fragments() {
+        let mut chunk = Chunk::new(ChunkPos::from((1, -2, 3)));
+        // Many runs, so that the chunk doesn't fit in one fragment
+        for i in 0..2000 {
+            chunk.set_block_at((i % 32, (i / 32) % 32, (i * 7) % 32), (i % 5) as u16);
+        }
+        let light_chunk = LightChunk::new(chunk.pos);
+        let message = WireToClient::Message(ToClient::Chunk(
+            Arc::new(chunk.clone()),
+            Arc::new(light_chunk),
+        ));
+        let fragments = encode(&message).unwrap();
+        assert!(fragments.len() > 1);
+        assert!(fragments.iter().all(|f| f.len() <= FRAGMENT_SIZE + 1));
+
+        let mut defragmenter = Defragmenter::default();
+        let (last, first) = fragments.split_last().unwrap();
+        for fragment in first {
+            assert!(defragmenter.push::<WireToClient>(fragment).is_none());
+        }
+        match defragmenter.push::<WireToClient>(last) {
+            Some(Ok(WireToClient::Message(ToClient::Chunk(received, _)))) => {
+                assert_eq!(received.pos, chunk.pos);
+                assert!(received.blocks().eq(chunk.blocks()));
+            }
+            other => panic!("Unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_chunk_is_rejected() {
+        let chunk = Chunk::new(ChunkPos::from((0, 0, 0)));
+        let mut compressed = crate::world::CompressedChunk::from_chunk(&chunk);
+        compressed.data[0].0 -= 1;
+        let bytes = bincode_options().serialize(&compressed).unwrap();
+        assert!(bincode_options().deserialize::<Chunk>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_ids_wrap_around() {
+        let mut server = LanServer::bind("Test world", u16::MAX - 2).unwrap();
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 1));
+        server.addresses.insert(PlayerId(u16::MAX - 1), address);
+        assert_eq!(server.allocate_id(), Some(PlayerId(u16::MAX - 2)));
+        // The id of a connected player is skipped, and the ids start again after the last one
+        assert_eq!(server.allocate_id(), Some(PlayerId(u16::MAX)));
+        assert_eq!(server.allocate_id(), Some(PlayerId(u16::MAX - 2)));
+        server.addresses.insert(PlayerId(u16::MAX - 2), address);
+        server.addresses.insert(PlayerId(u16::MAX), address);
+        assert_eq!(server.allocate_id(), None);
+    }
+
+    #[test]
+    fn test_join() {
+        const PROFILE: &str = "0123456789abcdef0123456789abcdef";
+        let mut server = LanServer::bind("Test world", 1).unwrap();
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, server.port()));
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let mut client = voxel_rs_network::Client::new(socket, address);
+        client.connect();
+
+        // The message is sent once the player is connected
+        let mut sent = false;
+        let mut player = None;
+        let mut received = None;
+        for _ in 0..500 {
+            client.tick();
+            if client.is_connected() && !sent {
+                sent = true;
+                for fragment in
+                    encode(&ToServer::Join("Remote".to_owned(), PROFILE.to_owned())).unwrap()
+                {
+                    client.send_message(fragment, MessageDelivery::Ordered);
+                }
+            }
+            loop {
+                match server.receive_event() {
+                    ServerEvent::NoEvent => break,
+                    ServerEvent::ClientConnected(id) => player = Some(id),
+                    ServerEvent::ClientMessage(id, ToServer::Join(name, profile)) => {
+                        received = Some((id, name, profile))
+                    }
+                    event => panic!("Unexpected event: {:?}", event),
+                }
+            }
+            if received.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(player, Some(PlayerId(1)));
+        assert_eq!(
+            received,
+            Some((PlayerId(1), "Remote".to_owned(), PROFILE.to_owned()))
+        );
+
+        // The server answers through the same fragments
+        server.send(PlayerId(1), ToClient::ChatMessage("Welcome".to_owned()));
+        let mut defragmenter = Defragmenter::default();
+        let mut answer = None;
+        for _ in 0..500 {
+            server.receive_event();
+            client.tick();
+            for (_, data) in client.get_messages() {
+                if let Some(message) = defragmenter.push::<WireToClient>(&data) {
+                    answer = Some(message.unwrap());
+                }
+            }
+            if answer.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        match answer {
+            Some(WireToClient::Message(ToClient::ChatMessage(text))) => assert_eq!(text, "Welcome"),
+            other => panic!("Unexpected answer: {:?}", other),
+        }
+    }
+}
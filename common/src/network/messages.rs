@@ -1,40 +1,437 @@
 use crate::{
+    animation::EntityActivity,
+    block::{
+        entity::{FurnaceProgress, Sign},
+        BlockId,
+    },
+    boss_bar::BossBar,
     data::Data,
-    physics::simulation::ServerState,
+    inventory::{Inventory, InventoryWindow, ItemStack},
+    item::ItemId,
+    network::clock::ServerTime,
+    physics::snapshot::PhysicsSnapshot,
     player::PlayerId,
-    player::{PlayerInput, RenderDistance},
-    world::{Chunk, LightChunk},
+    player::{GameMode, PlayerInput, PlayerListEntry, RenderDistance, Waypoint},
+    scoreboard::{DisplayedObjective, ScoreboardDisplay},
+    skin::PlayerSkin,
+    stats::PlayerStats,
+    world::{BlockPos, Chunk, ChunkPos, FarTile, LightChunk},
+    worldgen::biome::BiomeMap,
 };
 use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::mem::size_of;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// A message sent to the server by the client
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ToServer {
     /// Update player render distance
     SetRenderDistance(RenderDistance),
     /// Update the player's input
     UpdateInput(PlayerInput),
-    /// Break a block (player pos, yaw, pitch)
+    /// Set the skin of the player, sent when joining
+    SetSkin(PlayerSkin),
+    /// Join the game (name, profile), sent first when joining.
+    /// The profile identifies the player across sessions, the name can't change until the player joins again.
+    Join(String, String),
+    /// Answer a `ToClient::Ping` with the same number
+    Pong(u32),
+    /// Ask the server for its time, to synchronize the clocks. Contains the local time of the client.
+    TimeRequest(Duration),
+    /// Acknowledge the `ToClient::UpdatePhysics` with this sequence number, the baseline of the next ones
+    AckPhysics(u32),
+    /// Watch another player, or stop watching if it is `None`
+    Spectate(Option<PlayerId>),
+    /// Start breaking a block (player pos, yaw, pitch).
+    /// The block is broken immediately in creative mode, and after its break time in survival mode.
     BreakBlock(Vector3<f64>, f64, f64),
+    /// Stop breaking the block that is currently being broken
+    StopBreaking,
     /// Select a block
     SelectBlock(Vector3<f64>, f64, f64),
-    /// Place a block
+    /// Place a block, or interact with the pointed block if it has a block entity
     PlaceBlock(Vector3<f64>, f64, f64),
-    /// Tell the server to shutdown
+    /// Select the slot of the hotbar whose item the player holds
+    SelectHotbarSlot(usize),
+    /// Click an inventory slot (inventory, slot, quick move to the other inventory)
+    ClickSlot(InventoryWindow, usize, bool),
+    /// Close the currently open container
+    CloseContainer,
+    /// Set the text of the sign at some position
+    SetSignText(BlockPos, String),
+    /// Set the command of the command block at some position
+    SetCommandBlock(BlockPos, String),
+    /// Click an item of the creative palette: take a full stack of the item if the cursor is empty,
+    /// or destroy the stack held by the cursor
+    ClickCreativePalette(ItemId),
+    /// Add a waypoint to the player's waypoints
+    AddWaypoint(Waypoint),
+    /// Remove the waypoint at some index
+    RemoveWaypoint(usize),
+    /// Ask for the statistics of the player
+    RequestStats,
+    /// Run a command, without the leading `/`
+    Command(String),
+    /// Pause or resume the game of the integrated server, while the pause menu is open or the window is not focused.
+    /// The game only pauses while the player is alone on the server.
+    SetPaused(bool),
+    /// Open the integrated server to the players of the local network. Only the host can open it.
+    OpenToLan,
+    /// Tell the server to shutdown. Only the host can stop it.
     StopServer,
 }
 
 /// A message sent to the client by the server
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ToClient {
-    /// Send the game data
+    /// Send the game data. It is not serialized: the LAN clients use the data they loaded themselves.
+    #[serde(skip)]
     GameData(Data),
     /// Send the chunk at some position
     Chunk(Arc<Chunk>, Arc<LightChunk>),
-    /// Update the whole of the physics simulation
-    // TODO: only send part of the physics simulation
-    UpdatePhysics(ServerState),
+    /// Update the light of a chunk that the client already has
+    LightChunk(Arc<LightChunk>),
+    /// Update some blocks of chunks that the client already has
+    BlockChanges(Vec<(BlockPos, BlockId)>),
+    /// Send the far tile at some position, to render the terrain beyond the render distance
+    FarTile(Arc<FarTile>),
+    /// Send the biomes of a chunk column, before the first chunk of the column
+    BiomeMap(Arc<BiomeMap>),
+    /// Update the physics simulation with the changes of the players close to the player,
+    /// to acknowledge with a `ToServer::AckPhysics`
+    UpdatePhysics(PhysicsSnapshot),
+    /// Update what the players are doing, to animate their models
+    UpdateActivities(HashMap<PlayerId, EntityActivity>),
+    /// Set the skin of another player, or remove it if it is `None`
+    UpdateSkin(PlayerId, Option<PlayerSkin>),
+    /// Ask the client to answer with a `ToServer::Pong`, to measure the round-trip time
+    Ping(u32),
+    /// Answer a `ToServer::TimeRequest` with the client time of the request and the current server time
+    TimeResponse(Duration, ServerTime),
+    /// Update the list of the connected players
+    PlayerListUpdate(Vec<PlayerListEntry>),
+    /// Set the player whose viewpoint the camera follows, or go back to the own camera if it is `None`
+    Spectate(Option<PlayerId>),
     /// Set the id of a player
     CurrentId(PlayerId),
+    /// Update the player inventory and the stack held by the cursor
+    UpdateInventory(Inventory, Option<ItemStack>),
+    /// Open the container at some position
+    OpenContainer(BlockPos, Inventory),
+    /// Update the contents of the open container at some position
+    UpdateContainer(BlockPos, Inventory),
+    /// Update the progress of the open furnace at some position
+    UpdateFurnaceProgress(BlockPos, FurnaceProgress),
+    /// Close the open container
+    CloseContainer,
+    /// Open the editor of the text of the sign at some position, with its current text
+    OpenSignEditor(BlockPos, String),
+    /// Update the sign at some position, or remove it if it is `None`
+    UpdateSign(BlockPos, Option<Sign>),
+    /// Open the editor of the command block at some position, with its current command
+    OpenCommandBlockEditor(BlockPos, String),
+    /// Update the waypoints of the player
+    UpdateWaypoints(Vec<Waypoint>),
+    /// Set the corners of the world edit selection of the player, that are not set if they are `None`
+    UpdateSelection(Option<BlockPos>, Option<BlockPos>),
+    /// Send the statistics of the player
+    UpdateStats(PlayerStats),
+    /// Set the game mode of the player
+    SetGameMode(GameMode),
+    /// Replace the objectives of the scoreboard shown on the HUD
+    ScoreboardUpdate(ScoreboardDisplay),
+    /// Show or update the bar with some id at the top of the HUD, or remove it if it is `None`
+    BossBar(String, Option<BossBar>),
+    /// The player went through a portal
+    Teleported,
+    /// The player was hurt, the screen flashes
+    Hurt,
+    /// The game was paused or resumed. The physics state doesn't change while the game is paused.
+    SetPaused(bool),
+    /// Show a message in the chat, for example the result of a command
+    ChatMessage(String),
+    /// Show a large text in the middle of the screen, with a smaller text below it. The text fades in, stays
+    /// and fades out during the given numbers of seconds.
+    Title {
+        title: String,
+        subtitle: String,
+        fade_in: f32,
+        stay: f32,
+        fade_out: f32,
+    },
+    /// Show a short text above the hotbar
+    ActionBar(String),
+}
+
+// The messages are not serialized by the in-memory network of singleplayer, so their sizes are estimated for a compact
+// binary encoding: one byte for the variant, the plain values as they are, a length before the strings and
+// the lists, and the chunks compressed with RLE. The estimates are used for the bandwidth accounting.
+
+/// Number of runs of equal values in `data`
+fn rle_runs<T: PartialEq>(data: impl IntoIterator<Item = T>) -> usize {
+    let mut data = data.into_iter();
+    match data.next() {
+        Some(first) => {
+            let mut previous = first;
+            let mut runs = 1;
+            for value in data {
+                if value != previous {
+                    runs += 1;
+                    previous = value;
+                }
+            }
+            runs
+        }
+        None => 0,
+    }
+}
+
+fn string_size(s: &str) -> usize {
+    size_of::<u32>() + s.len()
+}
+
+fn inventory_size(inventory: &Inventory) -> usize {
+    size_of::<u32>() + inventory.size() * (1 + size_of::<ItemStack>())
+}
+
+fn skin_size(skin: &PlayerSkin) -> usize {
+    size_of::<u32>() + skin.colors.len() * size_of::<(u32, u32)>()
+}
+
+impl ToServer {
+    /// Name of the variant, to tally the traffic by message type
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::SetRenderDistance(_) => "SetRenderDistance",
+            Self::UpdateInput(_) => "UpdateInput",
+            Self::SetSkin(_) => "SetSkin",
+            Self::Join(..) => "Join",
+            Self::Pong(_) => "Pong",
+            Self::TimeRequest(_) => "TimeRequest",
+            Self::AckPhysics(_) => "AckPhysics",
+            Self::Spectate(_) => "Spectate",
+            Self::BreakBlock(..) => "BreakBlock",
+            Self::StopBreaking => "StopBreaking",
+            Self::SelectBlock(..) => "SelectBlock",
+            Self::PlaceBlock(..) => "PlaceBlock",
+            Self::SelectHotbarSlot(_) => "SelectHotbarSlot",
+            Self::ClickSlot(..) => "ClickSlot",
+            Self::CloseContainer => "CloseContainer",
+            Self::SetSignText(..) => "SetSignText",
+            Self::SetCommandBlock(..) => "SetCommandBlock",
+            Self::ClickCreativePalette(_) => "ClickCreativePalette",
+            Self::AddWaypoint(_) => "AddWaypoint",
+            Self::RemoveWaypoint(_) => "RemoveWaypoint",
+            Self::RequestStats => "RequestStats",
+            Self::Command(_) => "Command",
+            Self::SetPaused(_) => "SetPaused",
+            Self::OpenToLan => "OpenToLan",
+            Self::StopServer => "StopServer",
+        }
+    }
+
+    /// Estimated size of the message on the wire, in bytes
+    pub fn estimated_size(&self) -> usize {
+        1 + match self {
+            Self::SetRenderDistance(_) => size_of::<RenderDistance>(),
+            Self::UpdateInput(_) => size_of::<PlayerInput>(),
+            Self::SetSkin(skin) => skin_size(skin),
+            Self::Join(name, profile) => string_size(name) + string_size(profile),
+            Self::Pong(_) => size_of::<u32>(),
+            Self::TimeRequest(_) => size_of::<u64>(),
+            Self::AckPhysics(_) => size_of::<u32>(),
+            Self::Spectate(_) => 1 + size_of::<PlayerId>(),
+            Self::SetPaused(_) => 1,
+            Self::BreakBlock(..) | Self::SelectBlock(..) | Self::PlaceBlock(..) => {
+                size_of::<Vector3<f64>>() + 2 * size_of::<f64>()
+            }
+            Self::StopBreaking
+            | Self::CloseContainer
+            | Self::RequestStats
+            | Self::OpenToLan
+            | Self::StopServer => 0,
+            Self::SelectHotbarSlot(_) | Self::RemoveWaypoint(_) => size_of::<u32>(),
+            Self::ClickSlot(..) => 1 + size_of::<u32>() + 1,
+            Self::ClickCreativePalette(_) => size_of::<ItemId>(),
+            Self::AddWaypoint(waypoint) => {
+                string_size(&waypoint.name) + 3 * size_of::<i64>() + size_of::<[f32; 4]>()
+            }
+            Self::SetSignText(_, text) | Self::SetCommandBlock(_, text) => {
+                size_of::<BlockPos>() + string_size(text)
+            }
+            Self::Command(command) => string_size(command),
+        }
+    }
+}
+
+impl ToClient {
+    /// Name of the variant, to tally the traffic by message type
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::GameData(_) => "GameData",
+            Self::Chunk(..) => "Chunk",
+            Self::LightChunk(_) => "LightChunk",
+            Self::BlockChanges(_) => "BlockChanges",
+            Self::FarTile(_) => "FarTile",
+            Self::BiomeMap(_) => "BiomeMap",
+            Self::UpdatePhysics(_) => "UpdatePhysics",
+            Self::UpdateActivities(_) => "UpdateActivities",
+            Self::UpdateSkin(..) => "UpdateSkin",
+            Self::Ping(_) => "Ping",
+            Self::TimeResponse(..) => "TimeResponse",
+            Self::PlayerListUpdate(_) => "PlayerListUpdate",
+            Self::Spectate(_) => "Spectate",
+            Self::CurrentId(_) => "CurrentId",
+            Self::UpdateInventory(..) => "UpdateInventory",
+            Self::OpenContainer(..) => "OpenContainer",
+            Self::UpdateContainer(..) => "UpdateContainer",
+            Self::UpdateFurnaceProgress(..) => "UpdateFurnaceProgress",
+            Self::CloseContainer => "CloseContainer",
+            Self::OpenSignEditor(..) => "OpenSignEditor",
+            Self::UpdateSign(..) => "UpdateSign",
+            Self::OpenCommandBlockEditor(..) => "OpenCommandBlockEditor",
+            Self::UpdateWaypoints(_) => "UpdateWaypoints",
+            Self::UpdateSelection(..) => "UpdateSelection",
+            Self::UpdateStats(_) => "UpdateStats",
+            Self::SetGameMode(_) => "SetGameMode",
+            Self::ScoreboardUpdate(_) => "ScoreboardUpdate",
+            Self::BossBar(..) => "BossBar",
+            Self::Teleported => "Teleported",
+            Self::Hurt => "Hurt",
+            Self::SetPaused(_) => "SetPaused",
+            Self::ChatMessage(_) => "ChatMessage",
+            Self::Title { .. } => "Title",
+            Self::ActionBar(_) => "ActionBar",
+        }
+    }
+
+    /// Estimated size of the message on the wire, in bytes
+    pub fn estimated_size(&self) -> usize {
+        // A run of the RLE-compressed chunks is a length and a value
+        const BLOCK_RUN: usize = size_of::<u16>() + size_of::<BlockId>();
+        const LIGHT_RUN: usize = size_of::<u16>() + size_of::<u8>();
+        1 + match self {
+            Self::GameData(data) => {
+                let models = (0..data.models.get_number_of_ids())
+                    .filter_map(|id| data.models.get_value_by_id(id))
+                    .map(|model| 3 * size_of::<u32>() + model.voxels.len() * (size_of::<u32>() + 1))
+                    .sum::<usize>();
+                data.texture_atlas.len() + data.emissive_atlas.len() + models
+            }
+            Self::Chunk(chunk, light_chunk) => {
+                2 * size_of::<ChunkPos>()
+                    + rle_runs(chunk.blocks()) * BLOCK_RUN
+                    + rle_runs(&light_chunk.light) * LIGHT_RUN
+            }
+            Self::LightChunk(light_chunk) => {
+                size_of::<ChunkPos>() + rle_runs(&light_chunk.light) * LIGHT_RUN
+            }
+            Self::BlockChanges(changes) => {
+                size_of::<u32>() + changes.len() * size_of::<(BlockPos, BlockId)>()
+            }
+            Self::BiomeMap(biome_map) => 2 * size_of::<i64>() + biome_map.biomes.len(),
+            Self::FarTile(tile) => {
+                2 * size_of::<i64>()
+                    + tile.heights.len() * size_of::<i32>()
+                    + tile.blocks.len() * size_of::<BlockId>()
+            }
+            Self::UpdatePhysics(snapshot) => snapshot.estimated_size(),
+            Self::UpdateActivities(activities) => {
+                size_of::<u32>() + activities.len() * (size_of::<PlayerId>() + 1)
+            }
+            Self::UpdateSkin(_, skin) => {
+                size_of::<PlayerId>() + 1 + skin.as_ref().map_or(0, skin_size)
+            }
+            Self::Ping(_) => size_of::<u32>(),
+            Self::TimeResponse(..) => 2 * size_of::<u64>(),
+            Self::Spectate(_) => 1 + size_of::<PlayerId>(),
+            Self::PlayerListUpdate(entries) => {
+                size_of::<u32>()
+                    + entries
+                        .iter()
+                        .map(|entry| {
+                            let team = entry
+                                .team
+                                .as_ref()
+                                .map_or(0, |team| string_size(&team.name) + size_of::<[f32; 4]>());
+                            size_of::<PlayerId>()
+                                + string_size(&entry.name)
+                                + 1
+                                + size_of::<u32>()
+                                + 1
+                                + team
+                        })
+                        .sum::<usize>()
+            }
+            Self::CurrentId(_) => size_of::<PlayerId>(),
+            Self::UpdateInventory(inventory, _) => {
+                inventory_size(inventory) + 1 + size_of::<ItemStack>()
+            }
+            Self::OpenContainer(_, inventory) | Self::UpdateContainer(_, inventory) => {
+                size_of::<BlockPos>() + inventory_size(inventory)
+            }
+            Self::UpdateFurnaceProgress(..) => size_of::<BlockPos>() + size_of::<FurnaceProgress>(),
+            Self::CloseContainer | Self::Teleported | Self::Hurt => 0,
+            Self::SetPaused(_) => 1,
+            Self::OpenSignEditor(_, text) | Self::OpenCommandBlockEditor(_, text) => {
+                size_of::<BlockPos>() + string_size(text)
+            }
+            Self::UpdateSign(_, sign) => {
+                size_of::<BlockPos>()
+                    + 1
+                    + sign.as_ref().map_or(0, |sign| string_size(&sign.text) + 1)
+            }
+            Self::UpdateWaypoints(waypoints) => {
+                size_of::<u32>()
+                    + waypoints
+                        .iter()
+                        .map(|waypoint| {
+                            string_size(&waypoint.name)
+                                + 3 * size_of::<i64>()
+                                + size_of::<[f32; 4]>()
+                        })
+                        .sum::<usize>()
+            }
+            Self::UpdateSelection(..) => 2 * (1 + size_of::<BlockPos>()),
+            Self::UpdateStats(stats) => {
+                let counters = stats
+                    .blocks_broken
+                    .keys()
+                    .chain(stats.blocks_placed.keys())
+                    .map(|name| string_size(name) + size_of::<u64>())
+                    .sum::<usize>();
+                2 * size_of::<u32>() + counters + 3 * size_of::<f64>()
+            }
+            Self::SetGameMode(_) => 1,
+            Self::ScoreboardUpdate(display) => {
+                let objective_size = |objective: &Option<DisplayedObjective>| {
+                    1 + objective.as_ref().map_or(0, |objective| {
+                        string_size(&objective.title)
+                            + size_of::<u32>()
+                            + objective
+                                .scores
+                                .iter()
+                                .map(|(name, _)| string_size(name) + size_of::<i64>())
+                                .sum::<usize>()
+                    })
+                };
+                objective_size(&display.sidebar) + objective_size(&display.below_name)
+            }
+            Self::BossBar(id, bar) => {
+                string_size(id)
+                    + 1
+                    + bar.as_ref().map_or(0, |bar| {
+                        string_size(&bar.title) + size_of::<f32>() + 2 * size_of::<u32>()
+                    })
+            }
+            Self::ChatMessage(message) => string_size(message),
+            Self::Title {
+                title, subtitle, ..
+            } => string_size(title) + string_size(subtitle) + 3 * size_of::<f32>(),
+            Self::ActionBar(text) => string_size(text),
+        }
+    }
 }
@@ -1,5 +1,9 @@
 use crate::player::PlayerId;
 
+pub mod bandwidth;
+pub mod clock;
+pub mod emulation;
+pub mod lan;
 pub mod messages;
 
 /// An event that the server received.
@@ -34,6 +38,14 @@ pub trait Server {
     fn receive_event(&mut self) -> ServerEvent;
     /// Send a message to a client. The message will be dropped if it can't be sent.
     fn send(&mut self, client: PlayerId, message: messages::ToClient);
+    /// Check if a client is the player hosting the server on its own machine, who can run all the commands
+    fn is_host(&self, _client: PlayerId) -> bool {
+        false
+    }
+    /// Let the players of the local network join, announcing the world with some name. Return the port they join.
+    fn open_to_lan(&mut self, _name: &str) -> anyhow::Result<u16> {
+        anyhow::bail!("Only the integrated server can be opened to LAN")
+    }
 }
 
 /// An abstraction over a network client.
@@ -7,7 +7,7 @@ use crate::{debug::send_debug_info, physics::player::PhysicsPlayer, player::Play
 use nalgebra::Vector3;
 
 // Unit vector in the `angle` direction
-fn movement_direction(yaw: f64, angle: f64) -> Vector3<f64> {
+pub(super) fn movement_direction(yaw: f64, angle: f64) -> Vector3<f64> {
     let yaw = yaw + angle;
     Vector3::new(-yaw.to_radians().sin(), 0.0, -yaw.to_radians().cos()).normalize()
 }
@@ -98,6 +98,7 @@ impl PlayerCamera for FlyingCamera {
     ) {
         // We're flying, so reset Y velocity to zero.
         player.velocity.y = 0.0;
+        player.sneaking = false;
 
         // Calculate the intended acceleration based on controls.
         let mut force = Vector3::zeros();
@@ -154,6 +155,19 @@ impl PlayerCamera for WalkingCamera {
         const JUMP_SPEED: f64 = 8.0;
         const GRAVITY_ACCELERATION: f64 = 25.0;
         const HORIZONTAL_SPEED: f64 = 7.0;
+        const SNEAKING_SPEED_FACTOR: f64 = 0.3;
+        const SPRINTING_SPEED_FACTOR: f64 = 1.4;
+        const CLIMBING_SPEED: f64 = 3.0;
+        const LADDER_SLIDING_SPEED: f64 = 2.0;
+        // Sneaking takes precedence over sprinting
+        player.sneaking = input.key_move_down;
+        let speed = if player.sneaking {
+            HORIZONTAL_SPEED * SNEAKING_SPEED_FACTOR
+        } else if input.key_sprint {
+            HORIZONTAL_SPEED * SPRINTING_SPEED_FACTOR
+        } else {
+            HORIZONTAL_SPEED
+        };
         player.velocity.x = 0.0;
         player.velocity.z = 0.0;
         let mut horizontal_velocity = Vector3::zeros();
@@ -169,8 +183,17 @@ impl PlayerCamera for WalkingCamera {
         if input.key_move_right {
             horizontal_velocity += movement_direction(input.yaw, 270.0);
         }
-        let horizontal_velocity = normalize_or_zero(horizontal_velocity) * HORIZONTAL_SPEED;
-        if player.is_on_ground(world) {
+        let horizontal_velocity = normalize_or_zero(horizontal_velocity) * speed;
+        if player.is_climbing(world) {
+            // On a ladder, the vertical movement only depends on the input, so the player never falls fast
+            player.velocity.y = if input.key_move_forward || input.key_move_up {
+                CLIMBING_SPEED
+            } else if input.key_move_down {
+                0.0
+            } else {
+                -LADDER_SLIDING_SPEED
+            };
+        } else if player.is_on_ground(world) {
             player.velocity.y = if input.key_move_up { JUMP_SPEED } else { 0.0 };
         } else {
             player.velocity.y -= GRAVITY_ACCELERATION * seconds_delta;
@@ -183,6 +206,66 @@ impl PlayerCamera for WalkingCamera {
     }
 }
 
+pub struct SwimmingCamera;
+
+impl PlayerCamera for SwimmingCamera {
+    const ACCELERATION: f64 = 10.0;
+    const MAX_SPEED: f64 = 3.0;
+
+    fn compute_movement<BC: BlockContainer>(
+        player: &mut PhysicsPlayer,
+        input: PlayerInput,
+        seconds_delta: f64,
+        world: &BC,
+    ) {
+        const HORIZONTAL_SPEED: f64 = 3.0;
+        const GRAVITY_ACCELERATION: f64 = 4.0;
+        const DRAG: f64 = 2.0;
+        const SINKING_SPEED: f64 = 1.0;
+        player.sneaking = false;
+        player.velocity.x = 0.0;
+        player.velocity.z = 0.0;
+        let mut horizontal_velocity = Vector3::zeros();
+        if input.key_move_forward {
+            horizontal_velocity += movement_direction(input.yaw, 0.0);
+        }
+        if input.key_move_left {
+            horizontal_velocity += movement_direction(input.yaw, 90.0);
+        }
+        if input.key_move_backward {
+            horizontal_velocity += movement_direction(input.yaw, 180.0);
+        }
+        if input.key_move_right {
+            horizontal_velocity += movement_direction(input.yaw, 270.0);
+        }
+        let horizontal_velocity = normalize_or_zero(horizontal_velocity) * HORIZONTAL_SPEED;
+
+        // The drag slows down the vertical movement, for example after jumping into the water
+        let mut vertical_speed = player.velocity.y * (1.0 - DRAG * seconds_delta).max(0.0);
+        if input.key_move_up && player.is_head_in_fluid(world) {
+            // Swim up. Once the head is out of the water the player sinks again,
+            // so the player bobs at the surface.
+            vertical_speed += Self::ACCELERATION * seconds_delta;
+        } else if input.key_move_down {
+            vertical_speed -= Self::ACCELERATION * seconds_delta;
+        } else {
+            vertical_speed -= GRAVITY_ACCELERATION * seconds_delta;
+            if !input.key_move_up {
+                // Sink slowly when idle
+                vertical_speed = vertical_speed.max(-SINKING_SPEED);
+            }
+        }
+        vertical_speed = vertical_speed.max(-Self::MAX_SPEED).min(Self::MAX_SPEED);
+        if vertical_speed < 0.0 && player.is_on_ground(world) {
+            vertical_speed = 0.0;
+        }
+        player.velocity.y = vertical_speed;
+
+        let expected_movement = (player.velocity + horizontal_velocity) * seconds_delta;
+        player.move_check_collision(world, expected_movement);
+    }
+}
+
 /// The default camera. It doesn't let you go inside blocks unless you are already inside blocks.
 // TODO: use better integrator (RK4 ?)
 pub fn default_camera<BC: BlockContainer>(
@@ -194,6 +277,8 @@ pub fn default_camera<BC: BlockContainer>(
     // Compute the expected movement of the player, i.e. assuming there are no collisions.
     if input.flying || player.intersect_world(world) {
         FlyingCamera::compute_movement(player, input, seconds_delta, world);
+    } else if player.is_in_fluid(world) {
+        SwimmingCamera::compute_movement(player, input, seconds_delta, world);
     } else {
         WalkingCamera::compute_movement(player, input, seconds_delta, world);
     }
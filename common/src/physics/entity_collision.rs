@@ -0,0 +1,96 @@
+//! Collisions between the entities. The boxes that overlap are pushed apart horizontally a bit every step,
+//! so that the players don't stack inside each other but can still squeeze past each other.
+//! The overlapping boxes are found with the spatial index of the entities.
+use crate::entity::SpatialIndex;
+use nalgebra::Vector3;
+use ncollide3d::bounding_volume::AABB;
+use std::collections::BTreeMap;
+
+/// Fraction of the overlap of two boxes that is resolved per second
+const PUSH_RATE: f64 = 8.0;
+/// Maximum speed at which an entity is pushed, in blocks per second
+const MAX_PUSH_SPEED: f64 = 4.0;
+
+/// Get how far every entity of the index should be pushed out of the entities it overlaps during the next
+/// `dt` seconds, sorted by keys
+pub fn push_out<K: Copy + Ord>(index: &SpatialIndex<K>, dt: f64) -> BTreeMap<K, Vector3<f64>> {
+    let aabbs: BTreeMap<K, AABB<f64>> = index.iter().copied().collect();
+    let mut pushes = BTreeMap::new();
+    for (a, b) in index.overlapping_pairs() {
+        let push = push_apart(&aabbs[&a], &aabbs[&b], dt);
+        *pushes.entry(a).or_insert_with(Vector3::zeros) += push;
+        *pushes.entry(b).or_insert_with(Vector3::zeros) -= push;
+    }
+    pushes
+}
+
+/// Get the horizontal direction and the depth of the overlap of two boxes, if they overlap.
+/// The depth is the shortest horizontal distance that separates them.
+fn overlap(a: &AABB<f64>, b: &AABB<f64>) -> Option<(Vector3<f64>, f64)> {
+    let overlap_on = |i: usize| a.maxs[i].min(b.maxs[i]) - a.mins[i].max(b.mins[i]);
+    let (x, y, z) = (overlap_on(0), overlap_on(1), overlap_on(2));
+    if x <= 0.0 || y <= 0.0 || z <= 0.0 {
+        return None;
+    }
+    let mut direction = a.center() - b.center();
+    direction.y = 0.0;
+    // The boxes that are exactly on top of each other are separated along x
+    let direction = if direction.norm() > 1e-9 {
+        direction.normalize()
+    } else {
+        Vector3::x()
+    };
+    Some((direction, x.min(z)))
+}
+
+/// Get how far `a` should move away from `b` during the next `dt` seconds. `b` moves as far in the opposite
+/// direction.
+fn push_apart(a: &AABB<f64>, b: &AABB<f64>, dt: f64) -> Vector3<f64> {
+    match overlap(a, b) {
+        Some((direction, depth)) => {
+            let distance = depth / 2.0 * (PUSH_RATE * dt).min(1.0);
+            direction * distance.min(MAX_PUSH_SPEED * dt)
+        }
+        None => Vector3::zeros(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point3;
+
+    fn player_box(x: f64, z: f64) -> AABB<f64> {
+        AABB::from_half_extents(Point3::new(x, 50.9, z), Vector3::new(0.4, 0.9, 0.4))
+    }
+
+    #[test]
+    fn test_push_out() {
+        const DT: f64 = 0.016;
+        // Two players stacked on the border of a chunk, and a third one far away
+        let mut boxes = vec![(0, player_box(-0.1, 5.0)), (1, player_box(0.1, 5.0))];
+        boxes.push((2, player_box(100.0, 5.0)));
+        assert_eq!(
+            SpatialIndex::from_entities(boxes.clone()).overlapping_pairs(),
+            vec![(0, 1)]
+        );
+
+        for _ in 0..200 {
+            let pushes = push_out(&SpatialIndex::from_entities(boxes.clone()), DT);
+            assert!(pushes
+                .values()
+                .all(|push| push.norm() <= MAX_PUSH_SPEED * DT));
+            for (key, aabb) in boxes.iter_mut() {
+                if let Some(push) = pushes.get(key) {
+                    *aabb = AABB::new(aabb.mins + push, aabb.maxs + push);
+                }
+            }
+        }
+        // They were pushed apart along x, symmetrically
+        let centers: Vec<_> = boxes.iter().map(|(_, aabb)| aabb.center()).collect();
+        assert!(centers[1].x - centers[0].x > 0.79, "{:?}", centers);
+        assert!((centers[0].x + centers[1].x).abs() < 1e-9);
+        assert_eq!(centers[0].z, 5.0);
+        assert_eq!(centers[2], Point3::new(100.0, 50.9, 5.0));
+    }
+}
@@ -1,12 +1,48 @@
-use crate::world::BlockPos;
+use crate::{
+    block::{rail::RailShape, HorizontalFace},
+    world::BlockPos,
+};
 pub use ncollide3d::bounding_volume::{BoundingVolume, AABB};
 
 pub mod camera;
+pub mod entity_collision;
 pub mod player;
 pub mod simulation;
+pub mod snapshot;
+pub mod vehicle;
 
 /// A "block container", i.e. either the client's World or the server's World.
 /// This trait allows the physics simulation to work transparently with both World structs.
 pub trait BlockContainer {
     fn is_block_full(&self, pos: BlockPos) -> bool;
+
+    /// Get the side of the cell that the ladder at `pos` is attached to, if there is a ladder
+    fn get_ladder(&self, _pos: BlockPos) -> Option<HorizontalFace> {
+        None
+    }
+
+    /// Check if the block at `pos` is a fluid that the players can swim in
+    fn is_block_fluid(&self, _pos: BlockPos) -> bool {
+        false
+    }
+
+    /// Get the number of layers of the layered block at `pos`, if there is one
+    fn get_layers(&self, _pos: BlockPos) -> Option<u8> {
+        None
+    }
+
+    /// Check if the block at `pos` is a plant, that the players walk through
+    fn is_block_plant(&self, _pos: BlockPos) -> bool {
+        false
+    }
+
+    /// Check if the block at `pos` is a portal, that the players walk into
+    fn is_block_portal(&self, _pos: BlockPos) -> bool {
+        false
+    }
+
+    /// Get the shape of the rail at `pos`, if there is one
+    fn get_rail(&self, _pos: BlockPos) -> Option<RailShape> {
+        None
+    }
 }
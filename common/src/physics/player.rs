@@ -1,28 +1,107 @@
 use nalgebra::{Isometry3, Point3, Vector3};
-use ncollide3d::bounding_volume::AABB;
+use ncollide3d::bounding_volume::{BoundingVolume, AABB};
 
 use super::BlockContainer;
-use crate::world::BlockPos;
+use crate::{
+    block::{HorizontalFace, MAX_LAYERS},
+    world::BlockPos,
+};
 
 const PLAYER_SIDE: f64 = 0.8;
 const PLAYER_HEIGHT: f64 = 1.8;
 const POSITION_OFFSET: [f64; 3] = [0.0, PLAYER_HEIGHT / 2.0, 0.0];
 const CAMERA_OFFSET: [f64; 3] = [0.0, 1.6, 0.0];
+const SNEAKING_CAMERA_OFFSET: [f64; 3] = [0.0, 1.45, 0.0];
+/// Distance below the player that is checked to know if the player is standing on something
+const GROUND_DISTANCE: f64 = 0.0021;
+/// Thickness of the collision box of the ladders
+const LADDER_THICKNESS: f64 = 1.0 / 16.0;
+/// The players on the ground walk up the obstacles that are at most this high, like the layers of snow
+const MAX_STEP_HEIGHT: f64 = 0.5;
 
-fn aabb_intersects_world<BC: BlockContainer>(world: &BC, aabb: &AABB<f64>) -> bool {
+/// Get the collision box of a ladder
+fn ladder_aabb(pos: BlockPos, attached_to: HorizontalFace) -> AABB<f64> {
+    let (x, y, z) = (pos.px as f64, pos.py as f64, pos.pz as f64);
+    let (mins, maxs) = match attached_to {
+        HorizontalFace::PosX => (
+            (x + 1.0 - LADDER_THICKNESS, y, z),
+            (x + 1.0, y + 1.0, z + 1.0),
+        ),
+        HorizontalFace::NegX => ((x, y, z), (x + LADDER_THICKNESS, y + 1.0, z + 1.0)),
+        HorizontalFace::PosZ => (
+            (x, y, z + 1.0 - LADDER_THICKNESS),
+            (x + 1.0, y + 1.0, z + 1.0),
+        ),
+        HorizontalFace::NegZ => ((x, y, z), (x + 1.0, y + 1.0, z + LADDER_THICKNESS)),
+    };
+    AABB::new(
+        Point3::new(mins.0, mins.1, mins.2),
+        Point3::new(maxs.0, maxs.1, maxs.2),
+    )
+}
+
+/// Get the collision box of a layered block
+fn layer_aabb(pos: BlockPos, layers: u8) -> AABB<f64> {
+    let (x, y, z) = (pos.px as f64, pos.py as f64, pos.pz as f64);
+    let height = layers as f64 / MAX_LAYERS as f64;
+    AABB::new(
+        Point3::new(x, y, z),
+        Point3::new(x + 1.0, y + height, z + 1.0),
+    )
+}
+
+/// Iterate over the positions of the blocks that the aabb overlaps
+fn overlapped_blocks(aabb: &AABB<f64>) -> impl Iterator<Item = BlockPos> {
     let mins = aabb.mins.map(|c| c.floor() as i64);
     let maxs = aabb.maxs.map(|c| c.ceil() as i64);
+    (mins.x..maxs.x).flat_map(move |i| {
+        (mins.y..maxs.y).flat_map(move |j| (mins.z..maxs.z).map(move |k| (i, j, k).into()))
+    })
+}
 
-    for i in mins.x..maxs.x {
-        for j in mins.y..maxs.y {
-            for k in mins.z..maxs.z {
-                if world.is_block_full((i, j, k).into()) {
-                    return true;
-                }
-            }
+fn aabb_intersects_world<BC: BlockContainer>(world: &BC, aabb: &AABB<f64>) -> bool {
+    overlapped_blocks(aabb).any(|pos| {
+        world.is_block_full(pos)
+            || world.get_ladder(pos).map_or(false, |attached_to| {
+                ladder_aabb(pos, attached_to).intersects(aabb)
+            })
+            || world
+                .get_layers(pos)
+                .map_or(false, |layers| layer_aabb(pos, layers).intersects(aabb))
+    })
+}
+
+/// Get how high the aabb must be lifted so that it is not blocked anymore, if it is at most `MAX_STEP_HEIGHT`
+fn step_height(aabb: &AABB<f64>, is_blocked: &impl Fn(&AABB<f64>) -> bool) -> Option<f64> {
+    let lifted = |height: f64| {
+        aabb.transform_by(&Isometry3::new(
+            Vector3::new(0.0, height, 0.0),
+            Vector3::zeros(),
+        ))
+    };
+    if is_blocked(&lifted(MAX_STEP_HEIGHT)) {
+        return None;
+    }
+    let mut min_height = 0.0;
+    let mut max_height = MAX_STEP_HEIGHT;
+    while max_height - min_height > 0.001 {
+        let med = (min_height + max_height) / 2.0;
+        if is_blocked(&lifted(med)) {
+            min_height = med;
+        } else {
+            max_height = med;
         }
     }
-    return false;
+    Some(max_height)
+}
+
+/// Check if there is a block right below the aabb, that it could stand on
+fn aabb_is_supported<BC: BlockContainer>(world: &BC, aabb: &AABB<f64>) -> bool {
+    let below = aabb.transform_by(&Isometry3::new(
+        Vector3::new(0.0, -GROUND_DISTANCE, 0.0),
+        Vector3::zeros(),
+    ));
+    aabb_intersects_world(world, &below)
 }
 
 /// The physics representation of a player
@@ -32,6 +111,8 @@ pub struct PhysicsPlayer {
     pub aabb: AABB<f64>,
     /// The current velocity of the player
     pub velocity: Vector3<f64>,
+    /// True if the player is sneaking: it is slower, and it can't fall off the edges of the blocks
+    pub sneaking: bool,
 }
 
 impl PhysicsPlayer {
@@ -42,10 +123,12 @@ impl PhysicsPlayer {
                 Vector3::new(PLAYER_SIDE, PLAYER_HEIGHT, PLAYER_SIDE),
             ),
             velocity: Vector3::zeros(),
+            sneaking: false,
         }
     }
 
-    /// Try to move the box in the world and stop the movement if it goes trough a block
+    /// Try to move the box in the world and stop the movement if it goes trough a block.
+    /// While sneaking on the ground, the horizontal movement also stops before the box would lose the block below it.
     /// Return the actual deplacement
     pub fn move_check_collision<BC: BlockContainer>(
         &mut self,
@@ -64,9 +147,16 @@ impl PhysicsPlayer {
         let dd = delta.zip_map(&step, |d, s| d / (s as f64));
 
         let old_pos = self.aabb;
+        // Jumping is never blocked by the edge protection, and doesn't walk up steps
+        let on_ground = delta.y <= 0.0 && self.is_on_ground(world);
+        let edge_protection = self.sneaking && on_ground;
 
         // Loop the X, Y, and Z dimension.
         for r in 0..3 {
+            let is_blocked = |aabb: &AABB<f64>| {
+                aabb_intersects_world(world, aabb)
+                    || (edge_protection && r != 1 && !aabb_is_supported(world, aabb))
+            };
             let mut dimension_delta = Vector3::zeros();
             dimension_delta[r] = dd[r];
             let mut new_pos = self.aabb;
@@ -74,7 +164,17 @@ impl PhysicsPlayer {
             for _ in 0..step[r] {
                 let mut should_break = false;
                 new_pos = new_pos.transform_by(&Isometry3::new(dimension_delta, Vector3::zeros()));
-                if aabb_intersects_world(world, &new_pos) {
+                if is_blocked(&new_pos) {
+                    if on_ground && r != 1 {
+                        if let Some(height) = step_height(&new_pos, &is_blocked) {
+                            new_pos = new_pos.transform_by(&Isometry3::new(
+                                Vector3::new(0.0, height, 0.0),
+                                Vector3::zeros(),
+                            ));
+                            self.aabb = new_pos;
+                            continue;
+                        }
+                    }
                     new_pos =
                         new_pos.transform_by(&Isometry3::new(-dimension_delta, Vector3::zeros()));
 
@@ -88,7 +188,7 @@ impl PhysicsPlayer {
                         delta_d[r] = med * dd[r].signum();
                         let pot_pos =
                             new_pos.transform_by(&Isometry3::new(delta_d, Vector3::zeros()));
-                        if aabb_intersects_world(world, &pot_pos) {
+                        if is_blocked(&pot_pos) {
                             max_d = med;
                         } else {
                             min_d = med;
@@ -114,12 +214,36 @@ impl PhysicsPlayer {
 
     /// Check if player is on ground in world.
     pub fn is_on_ground<BC: BlockContainer>(&self, world: &BC) -> bool {
-        let new_bounds = self.aabb.transform_by(&Isometry3::new(
-            Vector3::new(0.0, -0.0021, 0.0),
-            Vector3::zeros(),
-        ));
-        let would_intersect_down = aabb_intersects_world(world, &new_bounds);
-        !self.intersect_world(world) && would_intersect_down
+        !self.intersect_world(world) && aabb_is_supported(world, &self.aabb)
+    }
+
+    /// Check if the player overlaps a ladder, and can climb it
+    pub fn is_climbing<BC: BlockContainer>(&self, world: &BC) -> bool {
+        overlapped_blocks(&self.aabb).any(|pos| world.get_ladder(pos).is_some())
+    }
+
+    /// Check if the player overlaps a fluid, and swims
+    pub fn is_in_fluid<BC: BlockContainer>(&self, world: &BC) -> bool {
+        overlapped_blocks(&self.aabb).any(|pos| world.is_block_fluid(pos))
+    }
+
+    /// Get the first portal that the player overlaps, if any
+    pub fn touched_portal<BC: BlockContainer>(&self, world: &BC) -> Option<BlockPos> {
+        overlapped_blocks(&self.aabb).find(|&pos| world.is_block_portal(pos))
+    }
+
+    /// Move the player to `coords` and stop it, without checking the collisions
+    pub fn teleport(&mut self, coords: Point3<f64>) {
+        let delta = coords - self.position();
+        self.aabb = self
+            .aabb
+            .transform_by(&Isometry3::new(delta, Vector3::zeros()));
+        self.velocity = Vector3::zeros();
+    }
+
+    /// Check if the camera of the player is in a fluid
+    pub fn is_head_in_fluid<BC: BlockContainer>(&self, world: &BC) -> bool {
+        world.is_block_fluid(BlockPos::from(self.get_camera_position()))
     }
 
     /// Check if player is intersecting with the world.
@@ -132,9 +256,13 @@ impl PhysicsPlayer {
         self.aabb.center() - Vector3::from(POSITION_OFFSET)
     }
 
-    /// Get the position of the camera
+    /// Get the position of the camera, which is slightly lower while sneaking
     pub fn get_camera_position(&self) -> Point3<f64> {
-        self.position() + Vector3::from(CAMERA_OFFSET)
+        if self.sneaking {
+            self.position() + Vector3::from(SNEAKING_CAMERA_OFFSET)
+        } else {
+            self.position() + Vector3::from(CAMERA_OFFSET)
+        }
     }
 
     /// Ray trace to find the pointed block. Return the position of the block and the face (x/-x/y/-y/z/-z)
@@ -149,7 +277,16 @@ impl PhysicsPlayer {
         let mut pos = self.get_camera_position();
 
         // Check current block first
-        let was_inside = world.is_block_full(BlockPos::from(pos));
+        // The ladders, the layers, the plants and the portals can be pointed at like full blocks
+        let is_pointable = |block_pos: BlockPos| {
+            world.is_block_full(block_pos)
+                || world.get_ladder(block_pos).is_some()
+                || world.get_layers(block_pos).is_some()
+                || world.is_block_plant(block_pos)
+                || world.is_block_portal(block_pos)
+                || world.get_rail(block_pos).is_some()
+        };
+        let was_inside = is_pointable(BlockPos::from(pos));
         let dirs = [
             Vector3::new(-1.0, 0.0, 0.0),
             Vector3::new(1.0, 0.0, 0.0),
@@ -194,7 +331,7 @@ impl PhysicsPlayer {
                 max_dist -= curr_min;
                 pos += curr_min * dir;
                 let block_pos = BlockPos::from(pos);
-                if world.is_block_full(block_pos) {
+                if is_pointable(block_pos) {
                     return Some((block_pos, face));
                 }
             }
@@ -210,6 +347,7 @@ impl Default for PhysicsPlayer {
                 Vector3::new(PLAYER_SIDE / 2.0, PLAYER_HEIGHT / 2.0, PLAYER_SIDE / 2.0),
             ),
             velocity: Vector3::zeros(),
+            sneaking: false,
         }
     }
 }
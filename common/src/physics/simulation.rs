@@ -1,14 +1,16 @@
 use crate::{
+    entity::SpatialIndex,
+    network::clock::ServerTime,
     physics::camera::default_camera,
+    physics::entity_collision::push_out,
     physics::player::PhysicsPlayer,
+    physics::snapshot::{snap_to_grid, snap_vehicle_to_grid},
+    physics::vehicle::{PhysicsVehicle, VehicleId, VehicleKind},
     physics::BlockContainer,
     player::{PlayerId, PlayerInput},
 };
 use nalgebra::Point3;
-use std::{
-    collections::HashMap,
-    time::{Duration, Instant},
-};
+use std::{collections::HashMap, time::Duration};
 
 /// Input of the whole simulation.
 #[derive(Debug, Clone, Default)]
@@ -16,23 +18,73 @@ pub struct Input {
     pub(self) player_inputs: HashMap<PlayerId, PlayerInput>,
 }
 
+impl Input {
+    pub fn from_player_inputs(player_inputs: HashMap<PlayerId, PlayerInput>) -> Self {
+        Self { player_inputs }
+    }
+
+    /// Get the input of a player
+    pub fn get(&self, player_id: PlayerId) -> Option<PlayerInput> {
+        self.player_inputs.get(&player_id).copied()
+    }
+}
+
 /// Physics state of the whole simulation.
 #[derive(Debug, Clone, Default)]
 pub struct PhysicsState {
     pub players: HashMap<PlayerId, PhysicsPlayer>,
+    pub vehicles: HashMap<VehicleId, PhysicsVehicle>,
 }
 
 impl PhysicsState {
     /// Step the full physics simulation.
-    /// For now, it just moves all connected players.
+    /// It moves all connected players that don't ride a vehicle, steers the vehicles with the input of their riders
+    /// and pushes apart the players that overlap. The riders that sneak leave their vehicle.
     pub fn step_simulation<BC: BlockContainer>(&mut self, input: &Input, dt: Duration, world: &BC) {
         let seconds_delta = dt.as_secs_f64();
+        let riders = self.riders();
         for (&id, input) in input.player_inputs.iter() {
-            default_camera(self.get_player_mut(id), *input, seconds_delta, world);
+            if !riders.contains_key(&id) {
+                default_camera(self.get_player_mut(id), *input, seconds_delta, world);
+            }
         }
         // Remove players that don't exist anymore
         self.players
             .retain(|id, _| input.player_inputs.contains_key(id));
+
+        for vehicle in self.vehicles.values_mut() {
+            let rider_input = vehicle.rider.and_then(|rider| input.get(rider));
+            if rider_input.is_none() {
+                vehicle.rider = None;
+            }
+            if let (Some(rider), Some(rider_input)) = (vehicle.rider, rider_input) {
+                if rider_input.key_move_down {
+                    vehicle.rider = None;
+                    let position = vehicle.dismount_position(world);
+                    if let Some(player) = self.players.get_mut(&rider) {
+                        player.teleport(position);
+                    }
+                }
+            }
+            vehicle.steer(vehicle.rider.and(rider_input), seconds_delta, world);
+            if let Some(rider) = vehicle.rider {
+                if let Some(player) = self.players.get_mut(&rider) {
+                    vehicle.carry(player);
+                }
+            }
+        }
+
+        // The riders move with their vehicle
+        let riders = self.riders();
+        let index = SpatialIndex::from_entities(
+            self.players
+                .iter()
+                .filter(|(id, _)| !riders.contains_key(id))
+                .map(|(&id, player)| (id, player.aabb)),
+        );
+        for (id, push) in push_out(&index, seconds_delta) {
+            self.get_player_mut(id).move_check_collision(world, push);
+        }
     }
 
     pub fn get_player(&mut self, id: PlayerId) -> &PhysicsPlayer {
@@ -42,20 +94,38 @@ impl PhysicsState {
     pub fn get_player_mut(&mut self, id: PlayerId) -> &mut PhysicsPlayer {
         self.players.entry(id).or_insert(Default::default())
     }
+
+    /// Get the vehicle ridden by every rider
+    pub fn riders(&self) -> HashMap<PlayerId, VehicleId> {
+        self.vehicles
+            .iter()
+            .filter_map(|(&id, vehicle)| vehicle.rider.map(|rider| (rider, id)))
+            .collect()
+    }
+
+    /// Make `player` leave its vehicle, if it rides one
+    fn dismount(&mut self, player: PlayerId) {
+        for vehicle in self.vehicles.values_mut() {
+            if vehicle.rider == Some(player) {
+                vehicle.rider = None;
+            }
+        }
+    }
 }
 
 /// A physics state sent by the server.
 #[derive(Debug, Clone)]
 pub struct ServerState {
     pub physics_state: PhysicsState,
-    pub server_time: Instant,
+    /// Time of the state on the server clock
+    pub server_time: ServerTime,
     pub input: Input,
 }
 
 /// The client's physics simulation
 pub struct ClientPhysicsSimulation {
-    /// Previous client inputs
-    client_inputs: Vec<(Instant, PlayerInput)>,
+    /// Previous client inputs, with the estimated server time when they were applied
+    client_inputs: Vec<(ServerTime, PlayerInput)>,
     /// Last state validated by the server
     last_server_state: ServerState,
     /// Current simulation state
@@ -99,16 +169,46 @@ impl ClientPhysicsSimulation {
             .get_camera_position()
     }
 
+    /// Get the id of the client player
+    pub fn get_player_id(&self) -> PlayerId {
+        self.player_id
+    }
+
     /// Get the client player
     pub fn get_player(&mut self) -> &PhysicsPlayer {
         self.current_state.get_player(self.player_id)
     }
 
-    /// Step the simulation according to the current input and time
+    /// Get the vehicles and their ids, as predicted from the last server state
+    pub fn vehicles(&self) -> impl Iterator<Item = (VehicleId, &PhysicsVehicle)> {
+        self.current_state
+            .vehicles
+            .iter()
+            .map(|(id, vehicle)| (*id, vehicle))
+    }
+
+    /// Get the other players and their ids, as predicted from the last server state
+    pub fn other_players(&self) -> impl Iterator<Item = (PlayerId, &PhysicsPlayer)> {
+        let player_id = self.player_id;
+        self.current_state
+            .players
+            .iter()
+            .filter(move |(id, _)| **id != player_id)
+            .map(|(id, player)| (*id, player))
+    }
+
+    /// Get the camera position, the yaw and the pitch of another player, as predicted from the last server state
+    pub fn get_other_player_view(&self, id: PlayerId) -> Option<(Point3<f64>, f64, f64)> {
+        let player = self.current_state.players.get(&id)?;
+        let input = self.last_server_state.input.player_inputs.get(&id)?;
+        Some((player.get_camera_position(), input.yaw, input.pitch))
+    }
+
+    /// Step the simulation according to the current input and the estimated server time
     pub fn step_simulation<BC: BlockContainer>(
         &mut self,
         input: PlayerInput,
-        time: Instant,
+        time: ServerTime,
         world: &BC,
     ) {
         // Recompute simulation if necessary
@@ -158,17 +258,20 @@ impl ClientPhysicsSimulation {
 pub struct ServerPhysicsSimulation {
     /// The current state of the simulation
     server_state: ServerState,
+    /// Id of the next spawned vehicle
+    next_vehicle_id: u32,
 }
 
 impl ServerPhysicsSimulation {
-    /// Create a new simulation with no connected players starting at the current time
-    pub fn new() -> Self {
+    /// Create a new simulation with no connected players starting at `time`
+    pub fn new(time: ServerTime) -> Self {
         Self {
             server_state: ServerState {
                 physics_state: PhysicsState::default(),
-                server_time: Instant::now(),
+                server_time: time,
                 input: Default::default(),
             },
+            next_vehicle_id: 0,
         }
     }
 
@@ -185,8 +288,60 @@ impl ServerPhysicsSimulation {
         self.server_state.input.player_inputs.remove(&player_id);
     }
 
+    /// Move a player back to the spawn point
+    pub fn respawn_player(&mut self, player_id: PlayerId) {
+        self.server_state.physics_state.dismount(player_id);
+        self.server_state
+            .physics_state
+            .players
+            .insert(player_id, PhysicsPlayer::default());
+    }
+
+    /// Move a player to `position`, if it is in the simulation
+    pub fn teleport_player(&mut self, player_id: PlayerId, position: Point3<f64>) {
+        self.server_state.physics_state.dismount(player_id);
+        if let Some(player) = self.server_state.physics_state.players.get_mut(&player_id) {
+            player.teleport(position);
+        }
+    }
+
+    /// Spawn a vehicle whose bottom is centered on `position`
+    pub fn spawn_vehicle(
+        &mut self,
+        kind: VehicleKind,
+        position: Point3<f64>,
+        yaw: f64,
+    ) -> VehicleId {
+        let id = VehicleId(self.next_vehicle_id);
+        self.next_vehicle_id += 1;
+        self.server_state
+            .physics_state
+            .vehicles
+            .insert(id, PhysicsVehicle::new(kind, position, yaw));
+        id
+    }
+
+    /// Make `player_id` ride `vehicle_id`. Return false if the vehicle doesn't exist, if someone else rides it or
+    /// if the player already rides a vehicle.
+    pub fn mount(&mut self, player_id: PlayerId, vehicle_id: VehicleId) -> bool {
+        let state = &mut self.server_state.physics_state;
+        if state.riders().contains_key(&player_id) {
+            return false;
+        }
+        match state.vehicles.get_mut(&vehicle_id) {
+            Some(vehicle) if vehicle.rider.is_none() => {
+                vehicle.rider = Some(player_id);
+                if let Some(player) = state.players.get_mut(&player_id) {
+                    vehicle.carry(player);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Step the simulation according to the current input and time
-    pub fn step_simulation<BC: BlockContainer>(&mut self, time: Instant, world: &BC) {
+    pub fn step_simulation<BC: BlockContainer>(&mut self, time: ServerTime, world: &BC) {
         self.server_state.physics_state.step_simulation(
             &self.server_state.input,
             time - self.server_state.server_time,
@@ -195,8 +350,684 @@ impl ServerPhysicsSimulation {
         self.server_state.server_time = time;
     }
 
+    /// Move the clock of the simulation to `time` without stepping it, while the game is paused.
+    /// The next step only covers the time after `time`.
+    pub fn skip_to(&mut self, time: ServerTime) {
+        self.server_state.server_time = time;
+    }
+
+    /// Move the players to the grid of the physics snapshots, so that the clients decode exactly this state
+    pub fn snap_to_grid<BC: BlockContainer>(&mut self, world: &BC) {
+        for player in self.server_state.physics_state.players.values_mut() {
+            snap_to_grid(player, world);
+        }
+        for vehicle in self.server_state.physics_state.vehicles.values_mut() {
+            snap_vehicle_to_grid(vehicle, world);
+        }
+    }
+
     /// Get a reference to the current state of the simulation
     pub fn get_state(&self) -> &ServerState {
         &self.server_state
     }
 }
+
+/// Test harness for the client-side prediction: a scripted or random sequence of inputs is run through
+/// the client and the server simulations, connected by a simulated network with latency and jitter.
+/// Everything uses a virtual clock, so the runs are deterministic.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::HorizontalFace;
+    use crate::world::BlockPos;
+
+    /// Duration of a tick of both simulations
+    const TICK: Duration = Duration::from_millis(16);
+    /// Number of ticks of idle input after the script, so that all the messages are delivered
+    const IDLE_TICKS: u32 = 120;
+    /// Maximum distance between the client and the server positions once they converged
+    const EPSILON: f64 = 1e-6;
+
+    /// A world with full blocks below `ground_height`, or no blocks at all
+    struct FlatWorld {
+        ground_height: Option<i64>,
+    }
+
+    impl BlockContainer for FlatWorld {
+        fn is_block_full(&self, pos: BlockPos) -> bool {
+            self.ground_height.map_or(false, |height| pos.py < height)
+        }
+    }
+
+    /// Latency of the simulated network, the same in both directions
+    #[derive(Debug, Clone, Copy)]
+    struct NetworkConditions {
+        latency: Duration,
+        /// Maximum random delay added to the latency of every message. The messages are still delivered in order.
+        jitter: Duration,
+    }
+
+    /// Small deterministic random number generator (xorshift64*)
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Self {
+            Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next_u64() % n
+        }
+
+        fn bool(&mut self) -> bool {
+            self.below(2) == 0
+        }
+    }
+
+    /// One direction of the simulated network
+    struct Link<T> {
+        in_flight: Vec<(ServerTime, T)>,
+        last_arrival: Option<ServerTime>,
+    }
+
+    impl<T> Link<T> {
+        fn new() -> Self {
+            Self {
+                in_flight: Vec::new(),
+                last_arrival: None,
+            }
+        }
+
+        fn send(
+            &mut self,
+            now: ServerTime,
+            message: T,
+            conditions: NetworkConditions,
+            rng: &mut Rng,
+        ) {
+            let jitter_micros = conditions.jitter.as_micros() as u64;
+            let jitter = Duration::from_micros(rng.below(jitter_micros + 1));
+            let mut arrival = now + conditions.latency + jitter;
+            if let Some(last_arrival) = self.last_arrival {
+                arrival = arrival.max(last_arrival);
+            }
+            self.last_arrival = Some(arrival);
+            self.in_flight.push((arrival, message));
+        }
+
+        /// Get the messages that arrived at `now`, in order
+        fn receive(&mut self, now: ServerTime) -> Vec<T> {
+            let arrived = self
+                .in_flight
+                .iter()
+                .take_while(|(arrival, _)| *arrival <= now)
+                .count();
+            self.in_flight
+                .drain(..arrived)
+                .map(|(_, message)| message)
+                .collect()
+        }
+    }
+
+    /// The final positions of a run
+    struct RunResult {
+        client: Point3<f64>,
+        server: Point3<f64>,
+        /// The position computed without any network, by applying the inputs directly
+        reference: Point3<f64>,
+    }
+
+    /// Run a script of `(number of ticks, input)` through both simulations, followed by idle ticks
+    fn run<BC: BlockContainer>(
+        script: &[(u32, PlayerInput)],
+        world: &BC,
+        conditions: NetworkConditions,
+        seed: u64,
+    ) -> RunResult {
+        let id = PlayerId(0);
+        let mut rng = Rng::new(seed);
+        let idle_input = PlayerInput {
+            flying: script.last().map_or(true, |(_, input)| input.flying),
+            ..PlayerInput::default()
+        };
+        let inputs = script
+            .iter()
+            .flat_map(|&(ticks, input)| std::iter::repeat(input).take(ticks as usize))
+            .chain(std::iter::repeat(idle_input).take(IDLE_TICKS as usize));
+
+        let start = ServerTime::default();
+        let mut server = ServerPhysicsSimulation::new(start);
+        server.set_player_input(id, script.first().map_or(idle_input, |(_, input)| *input));
+        let mut client = ClientPhysicsSimulation::new(server.get_state().clone(), id);
+        let mut reference = PhysicsState::default();
+        let mut reference_input = Input::default();
+        let mut to_server = Link::new();
+        let mut to_client = Link::new();
+
+        let mut now = start;
+        for input in inputs {
+            now += TICK;
+            // Client
+            for state in to_client.receive(now) {
+                client.receive_server_update(state);
+            }
+            client.step_simulation(input, now, world);
+            to_server.send(now, input, conditions, &mut rng);
+            // Server
+            for input in to_server.receive(now) {
+                server.set_player_input(id, input);
+            }
+            server.step_simulation(now, world);
+            to_client.send(now, server.get_state().clone(), conditions, &mut rng);
+            // Reference
+            reference_input.player_inputs.insert(id, input);
+            reference.step_simulation(&reference_input, TICK, world);
+        }
+
+        RunResult {
+            client: client.get_player().position(),
+            server: server.get_state().physics_state.players[&id].position(),
+            reference: reference.get_player(id).position(),
+        }
+    }
+
+    fn flying(forward: bool, right: bool, up: bool, yaw: f64) -> PlayerInput {
+        PlayerInput {
+            key_move_forward: forward,
+            key_move_right: right,
+            key_move_up: up,
+            yaw,
+            flying: true,
+            ..PlayerInput::default()
+        }
+    }
+
+    fn random_input(rng: &mut Rng, flying: bool) -> PlayerInput {
+        PlayerInput {
+            key_move_forward: rng.bool(),
+            key_move_left: rng.bool(),
+            key_move_backward: rng.bool(),
+            key_move_right: rng.bool(),
+            key_move_up: rng.bool(),
+            key_move_down: rng.bool(),
+            key_sprint: rng.bool(),
+            yaw: rng.below(360) as f64,
+            pitch: 0.0,
+            flying,
+        }
+    }
+
+    fn assert_close(a: Point3<f64>, b: Point3<f64>, epsilon: f64, message: &str) {
+        assert!(
+            (a - b).norm() <= epsilon,
+            "{}: {:?} and {:?} are {} apart",
+            message,
+            a,
+            b,
+            (a - b).norm()
+        );
+    }
+
+    #[test]
+    fn test_skipped_time() {
+        let id = PlayerId(0);
+        let world = FlatWorld {
+            ground_height: None,
+        };
+        let mut server = ServerPhysicsSimulation::new(ServerTime::default());
+        server.set_player_input(
+            id,
+            PlayerInput {
+                flying: false,
+                ..PlayerInput::default()
+            },
+        );
+        let mut time = ServerTime::default() + TICK;
+        server.step_simulation(time, &world);
+        let paused_at = server.get_state().physics_state.players[&id].position();
+        // The player doesn't fall during the pause, only during the tick after it
+        time += Duration::from_secs(10);
+        server.skip_to(time);
+        time += TICK;
+        server.step_simulation(time, &world);
+        let resumed_at = server.get_state().physics_state.players[&id].position();
+        assert!(paused_at.y - resumed_at.y < 0.1);
+    }
+
+    #[test]
+    fn test_prediction_without_jitter() {
+        let script = [
+            (30, flying(true, false, false, 0.0)),
+            (20, flying(false, true, true, 45.0)),
+            (10, flying(false, false, false, 0.0)),
+            (40, flying(true, true, false, 270.0)),
+        ];
+        let world = FlatWorld {
+            ground_height: None,
+        };
+        for &latency in &[0, 16, 48, 160] {
+            let conditions = NetworkConditions {
+                latency: Duration::from_millis(latency),
+                jitter: Duration::from_millis(0),
+            };
+            let result = run(&script, &world, conditions, 0);
+            assert_close(result.client, result.server, EPSILON, "client and server");
+            // With a constant latency, every input lasts as long on the server as on the client
+            assert_close(
+                result.server,
+                result.reference,
+                EPSILON,
+                "server and reference",
+            );
+        }
+    }
+
+    #[test]
+    fn test_prediction_with_jitter() {
+        let script = [
+            (30, flying(true, false, false, 0.0)),
+            (20, flying(false, true, true, 45.0)),
+            (40, flying(true, true, false, 270.0)),
+        ];
+        let world = FlatWorld {
+            ground_height: None,
+        };
+        let conditions = NetworkConditions {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(40),
+        };
+        for seed in 0..8 {
+            let result = run(&script, &world, conditions, seed);
+            assert_close(result.client, result.server, EPSILON, "client and server");
+        }
+    }
+
+    /// A platform of full blocks below y = 57, that ends at x = 3
+    struct Platform;
+
+    impl BlockContainer for Platform {
+        fn is_block_full(&self, pos: BlockPos) -> bool {
+            pos.py < 57 && pos.px < 3
+        }
+    }
+
+    /// Walk towards the edge of the platform for a few seconds, and return the final position of the player
+    fn walk_off_platform(sneaking: bool) -> Point3<f64> {
+        let id = PlayerId(0);
+        let mut state = PhysicsState::default();
+        let mut input = Input::default();
+        let walking = PlayerInput {
+            flying: false,
+            ..PlayerInput::default()
+        };
+        // Land on the platform first
+        input.player_inputs.insert(id, walking);
+        for _ in 0..60 {
+            state.step_simulation(&input, TICK, &Platform);
+        }
+        let towards_edge = PlayerInput {
+            key_move_forward: true,
+            key_move_down: sneaking,
+            yaw: 270.0,
+            ..walking
+        };
+        input.player_inputs.insert(id, towards_edge);
+        for _ in 0..200 {
+            state.step_simulation(&input, TICK, &Platform);
+        }
+        state.get_player(id).position()
+    }
+
+    #[test]
+    fn test_sneaking_stops_at_edges() {
+        let position = walk_off_platform(true);
+        assert!((position.y - 57.0).abs() < 0.01, "fell to {:?}", position);
+        assert!(position.x > 2.0, "stopped too early at {:?}", position);
+        let position = walk_off_platform(false);
+        assert!(position.y < 56.0, "didn't fall: {:?}", position);
+    }
+
+    /// Ground below y = 57, with 2 layers of snow on it between x = 3 and x = 8, and 6 layers beyond x = 10
+    struct SnowField;
+
+    impl BlockContainer for SnowField {
+        fn is_block_full(&self, pos: BlockPos) -> bool {
+            pos.py < 57
+        }
+
+        fn get_layers(&self, pos: BlockPos) -> Option<u8> {
+            match (pos.px, pos.py) {
+                (3..=7, 57) => Some(2),
+                (x, 57) if x >= 10 => Some(6),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_walking_on_layers() {
+        let id = PlayerId(0);
+        let mut state = PhysicsState::default();
+        let mut input = Input::default();
+        let walking = PlayerInput {
+            flying: false,
+            ..PlayerInput::default()
+        };
+        let mut run_ticks = |player_input: PlayerInput, ticks: u32| {
+            input.player_inputs.insert(id, player_input);
+            for _ in 0..ticks {
+                state.step_simulation(&input, TICK, &SnowField);
+            }
+            state.get_player(id).position()
+        };
+        run_ticks(walking, 60);
+        let towards_layers = PlayerInput {
+            key_move_forward: true,
+            yaw: 270.0,
+            ..walking
+        };
+        // The player walks up the thin layers
+        let position = run_ticks(towards_layers, 40);
+        assert!(position.x > 4.0 && position.x < 7.0, "at {:?}", position);
+        assert!((position.y - 57.25).abs() < 0.01, "at {:?}", position);
+        // But not the thick layers
+        let position = run_ticks(towards_layers, 100);
+        assert!(position.x > 9.5 && position.x < 10.0, "at {:?}", position);
+        assert!((position.y - 57.0).abs() < 0.01, "at {:?}", position);
+    }
+
+    /// A wall at x = 2 covered with ladders on its -x side, above the ground at y = 50
+    struct LadderWall;
+
+    impl BlockContainer for LadderWall {
+        fn is_block_full(&self, pos: BlockPos) -> bool {
+            pos.py < 50 || pos.px == 2
+        }
+
+        fn get_ladder(&self, pos: BlockPos) -> Option<HorizontalFace> {
+            if pos.px == 1 && pos.py >= 50 {
+                Some(HorizontalFace::PosX)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_climbing() {
+        let id = PlayerId(0);
+        let mut state = PhysicsState::default();
+        let mut input = Input::default();
+        let walking = PlayerInput {
+            flying: false,
+            ..PlayerInput::default()
+        };
+        let mut run_ticks = |player_input: PlayerInput, ticks: u32| {
+            input.player_inputs.insert(id, player_input);
+            let start = state.get_player(id).position();
+            for _ in 0..ticks {
+                state.step_simulation(&input, TICK, &LadderWall);
+            }
+            state.get_player(id).position().y - start.y
+        };
+        // The player slides down slowly instead of falling
+        let seconds = |ticks: u32| (TICK * ticks).as_secs_f64();
+        let delta = run_ticks(walking, 30);
+        assert!((delta + 2.0 * seconds(30)).abs() < 0.01, "slid {}", delta);
+        // Climb while pushing against the wall
+        let delta = run_ticks(
+            PlayerInput {
+                key_move_forward: true,
+                yaw: 270.0,
+                ..walking
+            },
+            60,
+        );
+        assert!(
+            (delta - 3.0 * seconds(60)).abs() < 0.01,
+            "climbed {}",
+            delta
+        );
+        // Hold the position while sneaking
+        let delta = run_ticks(
+            PlayerInput {
+                key_move_down: true,
+                ..walking
+            },
+            30,
+        );
+        assert!(delta.abs() < 1e-9, "moved {} while sneaking", delta);
+    }
+
+    #[test]
+    fn test_prediction_on_ladder() {
+        let walking = PlayerInput {
+            flying: false,
+            ..PlayerInput::default()
+        };
+        let script = [
+            (20, walking),
+            (
+                40,
+                PlayerInput {
+                    key_move_forward: true,
+                    yaw: 270.0,
+                    ..walking
+                },
+            ),
+            (
+                20,
+                PlayerInput {
+                    key_move_down: true,
+                    ..walking
+                },
+            ),
+        ];
+        let conditions = NetworkConditions {
+            latency: Duration::from_millis(80),
+            jitter: Duration::from_millis(30),
+        };
+        for seed in 0..4 {
+            let result = run(&script, &LadderWall, conditions, seed);
+            assert_close(result.client, result.server, EPSILON, "client and server");
+        }
+    }
+
+    /// A pool of water between y = 40 and y = 50, above the ground
+    struct Pool;
+
+    impl BlockContainer for Pool {
+        fn is_block_full(&self, pos: BlockPos) -> bool {
+            pos.py < 40
+        }
+
+        fn is_block_fluid(&self, pos: BlockPos) -> bool {
+            pos.py >= 40 && pos.py < 50
+        }
+    }
+
+    #[test]
+    fn test_swimming() {
+        let id = PlayerId(0);
+        let mut state = PhysicsState::default();
+        let mut input = Input::default();
+        let walking = PlayerInput {
+            flying: false,
+            ..PlayerInput::default()
+        };
+        // Start underwater
+        let player = state.get_player_mut(id);
+        player.aabb = player.aabb.transform_by(&nalgebra::Isometry3::new(
+            nalgebra::Vector3::new(0.0, -12.0, 0.0),
+            nalgebra::Vector3::zeros(),
+        ));
+        // The player sinks slowly when idle
+        input.player_inputs.insert(id, walking);
+        let start = state.get_player(id).position().y;
+        for _ in 0..60 {
+            state.step_simulation(&input, TICK, &Pool);
+        }
+        let delta = state.get_player(id).position().y - start;
+        assert!(
+            delta < 0.0 && delta > -(TICK * 60).as_secs_f64() - 1e-9,
+            "sank {}",
+            delta
+        );
+        // Swim up to the surface and bob there
+        input.player_inputs.insert(
+            id,
+            PlayerInput {
+                key_move_up: true,
+                ..walking
+            },
+        );
+        for _ in 0..300 {
+            state.step_simulation(&input, TICK, &Pool);
+        }
+        let (mut above, mut below) = (false, false);
+        for _ in 0..120 {
+            state.step_simulation(&input, TICK, &Pool);
+            let camera_height = state.get_player(id).get_camera_position().y;
+            assert!(
+                (camera_height - 50.0).abs() < 1.0,
+                "camera at {}",
+                camera_height
+            );
+            above |= camera_height >= 50.0;
+            below |= camera_height < 50.0;
+        }
+        assert!(above && below, "the player doesn't bob at the surface");
+    }
+
+    #[test]
+    fn test_prediction_while_swimming() {
+        let swimming = PlayerInput {
+            flying: false,
+            ..PlayerInput::default()
+        };
+        let script = [
+            (30, swimming),
+            (
+                120,
+                PlayerInput {
+                    key_move_up: true,
+                    key_move_forward: true,
+                    ..swimming
+                },
+            ),
+            (
+                40,
+                PlayerInput {
+                    key_move_down: true,
+                    ..swimming
+                },
+            ),
+        ];
+        let conditions = NetworkConditions {
+            latency: Duration::from_millis(80),
+            jitter: Duration::from_millis(30),
+        };
+        for seed in 0..4 {
+            let result = run(&script, &Pool, conditions, seed);
+            assert_close(result.client, result.server, EPSILON, "client and server");
+        }
+    }
+
+    #[test]
+    fn test_riding_boat() {
+        let id = PlayerId(0);
+        let mut server = ServerPhysicsSimulation::new(ServerTime::default());
+        let boat = server.spawn_vehicle(VehicleKind::Boat, Point3::new(0.5, 48.0, 0.5), 0.0);
+        let idle = PlayerInput {
+            flying: false,
+            ..PlayerInput::default()
+        };
+        let mut time = ServerTime::default();
+        let mut run_ticks =
+            |server: &mut ServerPhysicsSimulation, input: PlayerInput, ticks: u32| {
+                server.set_player_input(id, input);
+                for _ in 0..ticks {
+                    time += TICK;
+                    server.step_simulation(time, &Pool);
+                }
+            };
+        run_ticks(&mut server, idle, 1);
+        assert!(server.mount(id, boat));
+        // Someone already rides the boat
+        assert!(!server.mount(PlayerId(1), boat));
+
+        // The boat goes forward with the rider on its seat
+        let forward = PlayerInput {
+            key_move_forward: true,
+            ..idle
+        };
+        run_ticks(&mut server, forward, 120);
+        let state = &server.get_state().physics_state;
+        let vehicle = &state.vehicles[&boat];
+        assert_close(
+            state.players[&id].position(),
+            vehicle.seat_position(),
+            EPSILON,
+            "rider and seat",
+        );
+        assert!(vehicle.position().z < -3.0, "at {:?}", vehicle.position());
+        assert!(vehicle.position().y > 49.0, "at {:?}", vehicle.position());
+
+        // Sneaking leaves the boat, and the rider lands beside it
+        let sneaking = PlayerInput {
+            key_move_down: true,
+            ..idle
+        };
+        run_ticks(&mut server, sneaking, 1);
+        let state = &server.get_state().physics_state;
+        let vehicle = &state.vehicles[&boat];
+        assert_eq!(vehicle.rider, None);
+        let mut offset = state.players[&id].position() - vehicle.position();
+        offset.y = 0.0;
+        assert!((offset.norm() - 1.2).abs() < 0.2, "{:?}", offset);
+    }
+
+    /// Run random inputs and network conditions, `runs` times starting at `first_seed`
+    fn fuzz(first_seed: u64, runs: u64) {
+        for seed in first_seed..first_seed + runs {
+            let mut rng = Rng::new(seed);
+            let flying = rng.bool();
+            let script = (0..1 + rng.below(20))
+                .map(|_| (1 + rng.below(30) as u32, random_input(&mut rng, flying)))
+                .collect::<Vec<_>>();
+            let world = FlatWorld {
+                ground_height: if rng.bool() { Some(50) } else { None },
+            };
+            let conditions = NetworkConditions {
+                latency: Duration::from_millis(rng.below(200)),
+                jitter: Duration::from_millis(rng.below(100)),
+            };
+            let result = run(&script, &world, conditions, seed);
+            assert_close(
+                result.client,
+                result.server,
+                EPSILON,
+                &format!("seed {} with {:?}", seed, conditions),
+            );
+        }
+    }
+
+    #[test]
+    fn fuzz_prediction() {
+        fuzz(0, 32);
+    }
+
+    /// Longer fuzzing, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn fuzz_prediction_long() {
+        fuzz(32, 2000);
+    }
+}
@@ -0,0 +1,587 @@
+//! Compact physics updates. The server sends every client a `PhysicsSnapshot` that only contains the players
+//! close to it, and only what changed since the last snapshot that the client acknowledged. The few vehicles close
+//! to the client are sent whole in every snapshot.
+//! The positions and the velocities are integers on a fine grid. The server snaps its own state to that grid
+//! after every tick, so the client decodes exactly the state of the server and the prediction stays exact.
+use crate::{
+    network::clock::ServerTime,
+    physics::player::PhysicsPlayer,
+    physics::simulation::{Input, PhysicsState, ServerState},
+    physics::vehicle::{PhysicsVehicle, VehicleId, VehicleKind},
+    physics::BlockContainer,
+    player::{PlayerId, PlayerInput},
+};
+use nalgebra::{Point3, Vector3};
+use ncollide3d::bounding_volume::AABB;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::mem::size_of;
+
+/// Number of grid steps per block of the positions
+const POSITION_STEPS: f64 = 4096.0;
+/// Number of grid steps per block per second of the velocities
+const VELOCITY_STEPS: f64 = 1024.0;
+/// Number of grid steps per degree of the yaws of the vehicles
+const YAW_STEPS: f64 = 64.0;
+/// Number of snapshots kept to be used as a baseline
+const HISTORY_SIZE: usize = 64;
+
+/// A player on the grid of the snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuantizedPlayer {
+    /// The minimum corner of the box, in grid steps
+    pub mins: [i64; 3],
+    /// The size of the box, in grid steps
+    pub size: [u32; 3],
+    pub velocity: [i32; 3],
+    pub sneaking: bool,
+}
+
+impl QuantizedPlayer {
+    /// Put the player on the grid, rounding the position of the box on each axis with `round`
+    fn with_rounding(player: &PhysicsPlayer, round: [fn(f64) -> f64; 3]) -> Self {
+        let mut mins = [0; 3];
+        let mut size = [0; 3];
+        let mut velocity = [0; 3];
+        for i in 0..3 {
+            mins[i] = round[i](player.aabb.mins[i] * POSITION_STEPS) as i64;
+            // Rounded, so that the size of a box already on the grid doesn't change with the rounding errors
+            // of its coordinates
+            size[i] = ((player.aabb.maxs[i] - player.aabb.mins[i]) * POSITION_STEPS).round() as u32;
+            velocity[i] = (player.velocity[i] * VELOCITY_STEPS).round() as i32;
+        }
+        Self {
+            mins,
+            size,
+            velocity,
+            sneaking: player.sneaking,
+        }
+    }
+
+    /// Put the player on the closest point of the grid
+    pub fn from_player(player: &PhysicsPlayer) -> Self {
+        Self::with_rounding(player, [f64::round as fn(f64) -> f64; 3])
+    }
+
+    pub fn to_player(&self) -> PhysicsPlayer {
+        let coordinate = |steps: i64| steps as f64 / POSITION_STEPS;
+        let maxs = |i: usize| coordinate(self.mins[i] + self.size[i] as i64);
+        PhysicsPlayer {
+            aabb: AABB::new(
+                Point3::new(
+                    coordinate(self.mins[0]),
+                    coordinate(self.mins[1]),
+                    coordinate(self.mins[2]),
+                ),
+                Point3::new(maxs(0), maxs(1), maxs(2)),
+            ),
+            velocity: Vector3::new(
+                self.velocity[0] as f64 / VELOCITY_STEPS,
+                self.velocity[1] as f64 / VELOCITY_STEPS,
+                self.velocity[2] as f64 / VELOCITY_STEPS,
+            ),
+            sneaking: self.sneaking,
+        }
+    }
+}
+
+/// Move the player to the grid of the snapshots.
+/// Rounding towards a close wall could move the box into it, so the closest point of the grid is only used
+/// if the box stays out of the blocks. Otherwise every axis is rounded down or up, until the box is out of the blocks.
+/// The size of a box already on the grid doesn't change, and only one side of an axis can be that close to a block,
+/// so one of the roundings keeps the box out of the blocks.
+pub fn snap_to_grid<BC: BlockContainer>(player: &mut PhysicsPlayer, world: &BC) {
+    let was_in_blocks = player.intersect_world(world);
+    let closest = QuantizedPlayer::from_player(player);
+    let roundings = (0..8).map(|mask: usize| {
+        let mut round = [f64::floor as fn(f64) -> f64; 3];
+        for (i, axis_round) in round.iter_mut().enumerate() {
+            if mask & (1 << i) != 0 {
+                *axis_round = f64::ceil;
+            }
+        }
+        QuantizedPlayer::with_rounding(player, round)
+    });
+    for candidate in std::iter::once(closest).chain(roundings) {
+        let snapped = candidate.to_player();
+        if was_in_blocks || !snapped.intersect_world(world) {
+            *player = snapped;
+            return;
+        }
+    }
+}
+
+/// A vehicle on the grid of the snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuantizedVehicle {
+    pub kind: VehicleKind,
+    pub body: QuantizedPlayer,
+    /// The yaw, in grid steps
+    pub yaw: i32,
+    pub rider: Option<PlayerId>,
+}
+
+impl QuantizedVehicle {
+    pub fn from_vehicle(vehicle: &PhysicsVehicle) -> Self {
+        Self {
+            kind: vehicle.kind,
+            body: QuantizedPlayer::from_player(&vehicle.body),
+            yaw: (vehicle.yaw * YAW_STEPS).round() as i32,
+            rider: vehicle.rider,
+        }
+    }
+
+    pub fn to_vehicle(&self) -> PhysicsVehicle {
+        PhysicsVehicle {
+            kind: self.kind,
+            body: self.body.to_player(),
+            yaw: self.yaw as f64 / YAW_STEPS,
+            rider: self.rider,
+        }
+    }
+}
+
+/// Move the vehicle to the grid of the snapshots, like `snap_to_grid`
+pub fn snap_vehicle_to_grid<BC: BlockContainer>(vehicle: &mut PhysicsVehicle, world: &BC) {
+    snap_to_grid(&mut vehicle.body, world);
+    vehicle.yaw = QuantizedVehicle::from_vehicle(vehicle).to_vehicle().yaw;
+}
+
+/// The players of a snapshot with their input
+type SnapshotPlayers = HashMap<PlayerId, (QuantizedPlayer, PlayerInput)>;
+
+/// The new state of a player in a snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PlayerUpdate {
+    /// The whole player, when it is not in the baseline
+    Full(QuantizedPlayer, PlayerInput),
+    /// The changes since the baseline, `None` for the parts that didn't change
+    Delta {
+        /// The movement of the box since the baseline, in grid steps
+        movement: Option<[i32; 3]>,
+        velocity: Option<[i32; 3]>,
+        sneaking: bool,
+        input: Option<PlayerInput>,
+    },
+}
+
+impl PlayerUpdate {
+    /// Encode `new` against `old`. Returns `None` if nothing changed.
+    fn new(
+        old: &(QuantizedPlayer, PlayerInput),
+        new: &(QuantizedPlayer, PlayerInput),
+    ) -> Option<Self> {
+        let ((old_player, old_input), (player, input)) = (old, new);
+        if old_player == player && old_input == input {
+            return None;
+        }
+        let full = Some(Self::Full(*player, *input));
+        if old_player.size != player.size {
+            return full;
+        }
+        let movement = if old_player.mins == player.mins {
+            None
+        } else {
+            // The players that move too far between two snapshots, like when they are teleported, are sent again
+            let mut movement = [0; 3];
+            for i in 0..3 {
+                movement[i] = match i32::try_from(player.mins[i] - old_player.mins[i]) {
+                    Ok(delta) => delta,
+                    Err(_) => return full,
+                };
+            }
+            Some(movement)
+        };
+        Some(Self::Delta {
+            movement,
+            velocity: Some(player.velocity).filter(|velocity| *velocity != old_player.velocity),
+            sneaking: player.sneaking,
+            input: Some(*input).filter(|input| input != old_input),
+        })
+    }
+
+    /// Apply the update to the state of the player in the baseline
+    fn apply(
+        &self,
+        old: Option<&(QuantizedPlayer, PlayerInput)>,
+    ) -> Option<(QuantizedPlayer, PlayerInput)> {
+        match self {
+            Self::Full(player, input) => Some((*player, *input)),
+            Self::Delta {
+                movement,
+                velocity,
+                sneaking,
+                input,
+            } => {
+                let (mut player, old_input) = *old?;
+                if let Some(movement) = movement {
+                    for i in 0..3 {
+                        player.mins[i] += movement[i] as i64;
+                    }
+                }
+                if let Some(velocity) = velocity {
+                    player.velocity = *velocity;
+                }
+                player.sneaking = *sneaking;
+                Some((player, input.unwrap_or(old_input)))
+            }
+        }
+    }
+
+    fn estimated_size(&self) -> usize {
+        1 + match self {
+            Self::Full(..) => size_of::<QuantizedPlayer>() + size_of::<PlayerInput>(),
+            Self::Delta {
+                movement,
+                velocity,
+                input,
+                ..
+            } => {
+                3 + movement.map_or(0, |_| size_of::<[i32; 3]>())
+                    + velocity.map_or(0, |_| size_of::<[i32; 3]>())
+                    + input.map_or(0, |_| size_of::<PlayerInput>())
+            }
+        }
+    }
+}
+
+/// A physics state sent to a client, encoded against a previous snapshot that the client acknowledged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhysicsSnapshot {
+    /// The number of the snapshot, sent back by the client in a `ToServer::AckPhysics`
+    pub sequence: u32,
+    /// The number of the snapshot the updates are relative to, `None` if the snapshot contains every player
+    pub baseline: Option<u32>,
+    pub server_time: ServerTime,
+    /// The players that changed since the baseline
+    pub updates: Vec<(PlayerId, PlayerUpdate)>,
+    /// The players of the baseline that are not in the snapshot anymore
+    pub removed: Vec<PlayerId>,
+    /// All the vehicles of the snapshot
+    pub vehicles: Vec<(VehicleId, QuantizedVehicle)>,
+}
+
+impl PhysicsSnapshot {
+    pub fn estimated_size(&self) -> usize {
+        size_of::<u32>()
+            + 1
+            + self.baseline.map_or(0, |_| size_of::<u32>())
+            + size_of::<u64>()
+            + size_of::<u32>()
+            + self
+                .updates
+                .iter()
+                .map(|(_, update)| size_of::<PlayerId>() + update.estimated_size())
+                .sum::<usize>()
+            + size_of::<u32>()
+            + self.removed.len() * size_of::<PlayerId>()
+            + size_of::<u32>()
+            + self.vehicles.len() * (size_of::<VehicleId>() + size_of::<QuantizedVehicle>())
+    }
+}
+
+/// The snapshots sent by the server to a client
+pub struct SnapshotEncoder {
+    next_sequence: u32,
+    /// The snapshots that the client didn't acknowledge yet, oldest first
+    sent: VecDeque<(u32, SnapshotPlayers)>,
+    /// The last snapshot acknowledged by the client, the baseline of the next snapshots
+    acknowledged: Option<(u32, SnapshotPlayers)>,
+}
+
+impl SnapshotEncoder {
+    pub fn new() -> Self {
+        Self {
+            next_sequence: 0,
+            sent: VecDeque::new(),
+            acknowledged: None,
+        }
+    }
+
+    /// Process a `ToServer::AckPhysics`. The acknowledgements of the snapshots older than the baseline are ignored.
+    pub fn acknowledge(&mut self, sequence: u32) {
+        if let Some(index) = self.sent.iter().position(|(sent, _)| *sent == sequence) {
+            self.acknowledged = self.sent.drain(..=index).last();
+        }
+    }
+
+    /// Encode the players of `state` for which `is_relevant` is true, against the last acknowledged snapshot.
+    /// The vehicles for which `is_vehicle_relevant` is true and the vehicles ridden by the encoded players are
+    /// encoded whole.
+    pub fn encode(
+        &mut self,
+        state: &ServerState,
+        is_relevant: impl Fn(PlayerId, &PhysicsPlayer) -> bool,
+        is_vehicle_relevant: impl Fn(&PhysicsVehicle) -> bool,
+    ) -> PhysicsSnapshot {
+        let players: SnapshotPlayers = state
+            .physics_state
+            .players
+            .iter()
+            .filter(|(id, player)| is_relevant(**id, *player))
+            .filter_map(|(&id, player)| {
+                let input = state.input.get(id)?;
+                Some((id, (QuantizedPlayer::from_player(player), input)))
+            })
+            .collect();
+        let mut vehicles: Vec<_> = state
+            .physics_state
+            .vehicles
+            .iter()
+            .filter(|(_, vehicle)| {
+                is_vehicle_relevant(vehicle)
+                    || vehicle
+                        .rider
+                        .map_or(false, |rider| players.contains_key(&rider))
+            })
+            .map(|(&id, vehicle)| (id, QuantizedVehicle::from_vehicle(vehicle)))
+            .collect();
+        vehicles.sort_by_key(|(id, _)| *id);
+
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        let (baseline, updates, removed) = match &self.acknowledged {
+            Some((baseline, baseline_players)) => (
+                Some(*baseline),
+                players
+                    .iter()
+                    .filter_map(|(&id, new)| {
+                        let update = match baseline_players.get(&id) {
+                            Some(old) => PlayerUpdate::new(old, new)?,
+                            None => PlayerUpdate::Full(new.0, new.1),
+                        };
+                        Some((id, update))
+                    })
+                    .collect(),
+                baseline_players
+                    .keys()
+                    .filter(|id| !players.contains_key(id))
+                    .copied()
+                    .collect(),
+            ),
+            None => (
+                None,
+                players
+                    .iter()
+                    .map(|(&id, &(player, input))| (id, PlayerUpdate::Full(player, input)))
+                    .collect(),
+                Vec::new(),
+            ),
+        };
+
+        self.sent.push_back((sequence, players));
+        if self.sent.len() > HISTORY_SIZE {
+            self.sent.pop_front();
+        }
+        PhysicsSnapshot {
+            sequence,
+            baseline,
+            server_time: state.server_time,
+            updates,
+            removed,
+            vehicles,
+        }
+    }
+}
+
+/// The snapshots received by the client
+pub struct SnapshotDecoder {
+    /// The last decoded snapshots, oldest first
+    received: VecDeque<(u32, SnapshotPlayers)>,
+}
+
+impl SnapshotDecoder {
+    pub fn new() -> Self {
+        Self {
+            received: VecDeque::new(),
+        }
+    }
+
+    /// Decode a snapshot. Returns `None` if it arrived after a newer snapshot, or if its baseline is unknown.
+    /// The decoded snapshots should be acknowledged with a `ToServer::AckPhysics`.
+    pub fn decode(&mut self, snapshot: PhysicsSnapshot) -> Option<ServerState> {
+        if let Some((last, _)) = self.received.back() {
+            if snapshot.sequence.wrapping_sub(*last) as i32 <= 0 {
+                return None;
+            }
+        }
+        let mut players = match snapshot.baseline {
+            Some(baseline) => {
+                let index = self
+                    .received
+                    .iter()
+                    .position(|(sequence, _)| *sequence == baseline)?;
+                // The server never goes back to an older baseline
+                self.received.drain(..index);
+                self.received[0].1.clone()
+            }
+            None => HashMap::new(),
+        };
+        for id in snapshot.removed.iter() {
+            players.remove(id);
+        }
+        for (id, update) in snapshot.updates.iter() {
+            let player = update.apply(players.get(id))?;
+            players.insert(*id, player);
+        }
+
+        let state = ServerState {
+            physics_state: PhysicsState {
+                players: players
+                    .iter()
+                    .map(|(&id, (player, _))| (id, player.to_player()))
+                    .collect(),
+                vehicles: snapshot
+                    .vehicles
+                    .iter()
+                    .map(|(id, vehicle)| (*id, vehicle.to_vehicle()))
+                    .collect(),
+            },
+            server_time: snapshot.server_time,
+            input: Input::from_player_inputs(
+                players
+                    .iter()
+                    .map(|(&id, &(_, input))| (id, input))
+                    .collect(),
+            ),
+        };
+        self.received.push_back((snapshot.sequence, players));
+        if self.received.len() > HISTORY_SIZE {
+            self.received.pop_front();
+        }
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::simulation::ServerPhysicsSimulation;
+    use crate::world::BlockPos;
+    use std::time::Duration;
+
+    /// A wall at x = 2
+    struct Wall;
+
+    impl BlockContainer for Wall {
+        fn is_block_full(&self, pos: BlockPos) -> bool {
+            pos.px >= 2
+        }
+    }
+
+    #[test]
+    fn test_snap_next_to_wall() {
+        let mut player = PhysicsPlayer::from_coords(Point3::new(0.0, 10.0, 0.0));
+        // Just in front of the wall, closer than half a grid step
+        let offset = 2.0 - player.aabb.maxs.x - 0.1 / POSITION_STEPS;
+        player.aabb = AABB::new(
+            player.aabb.mins + Vector3::new(offset, 0.0, 0.0),
+            player.aabb.maxs + Vector3::new(offset, 0.0, 0.0),
+        );
+        assert!(!player.intersect_world(&Wall));
+        snap_to_grid(&mut player, &Wall);
+        assert!(!player.intersect_world(&Wall));
+        assert!(2.0 - player.aabb.maxs.x < 2.0 / POSITION_STEPS);
+        // The snapped player is exactly on the grid
+        let snapped = player.clone();
+        snap_to_grid(&mut player, &Wall);
+        assert_eq!(snapped.aabb, player.aabb);
+        assert_eq!(
+            QuantizedPlayer::from_player(&player).to_player().aabb,
+            player.aabb
+        );
+    }
+
+    /// The size of a moving player doesn't change with the snapping
+    #[test]
+    fn test_snapped_size_is_stable() {
+        let id = PlayerId(0);
+        let mut server = ServerPhysicsSimulation::new(ServerTime::default());
+        let mut time = ServerTime::default();
+        let mut size = None;
+        for tick in 0..1000u32 {
+            server.set_player_input(
+                id,
+                PlayerInput {
+                    key_move_forward: true,
+                    key_move_left: tick % 3 == 0,
+                    key_move_up: tick % 40 == 0,
+                    // Turning, so that the box moves in every direction
+                    yaw: 90.0 + (tick % 180) as f64,
+                    ..PlayerInput::default()
+                },
+            );
+            time += Duration::from_millis(50);
+            server.step_simulation(time, &Wall);
+            server.snap_to_grid(&Wall);
+            let player = &server.get_state().physics_state.players[&id];
+            let quantized = QuantizedPlayer::from_player(player);
+            assert_eq!(
+                *size.get_or_insert(quantized.size),
+                quantized.size,
+                "tick {}",
+                tick
+            );
+        }
+    }
+
+    /// Walking players, with snapshots and acknowledgements lost on the way, are decoded exactly
+    #[test]
+    fn test_delta_encoding() {
+        let ids = [PlayerId(0), PlayerId(1), PlayerId(2)];
+        let mut server = ServerPhysicsSimulation::new(ServerTime::default());
+        let mut encoder = SnapshotEncoder::new();
+        let mut decoder = SnapshotDecoder::new();
+        let mut time = ServerTime::default();
+        let mut full_size = 0;
+        let mut delta_size = 0;
+        for tick in 0..200u32 {
+            for (i, &id) in ids.iter().enumerate() {
+                // The third player leaves for a while
+                if i == 2 && tick >= 50 && tick < 100 {
+                    server.remove(id);
+                    continue;
+                }
+                server.set_player_input(
+                    id,
+                    PlayerInput {
+                        key_move_forward: (tick / 20 + i as u32) % 2 == 0,
+                        yaw: (i * 90) as f64,
+                        ..PlayerInput::default()
+                    },
+                );
+            }
+            time += Duration::from_millis(50);
+            server.step_simulation(time, &Wall);
+            server.snap_to_grid(&Wall);
+
+            let snapshot = encoder.encode(server.get_state(), |_, _| true, |_| true);
+            if snapshot.baseline.is_some() {
+                delta_size += snapshot.estimated_size();
+            } else {
+                full_size = full_size.max(snapshot.estimated_size());
+            }
+            if tick % 7 == 3 {
+                // Lost snapshot
+                continue;
+            }
+            let sequence = snapshot.sequence;
+            let state = decoder
+                .decode(snapshot)
+                .expect("the baseline was acknowledged");
+            let expected = &server.get_state().physics_state.players;
+            assert_eq!(state.physics_state.players.len(), expected.len());
+            for (id, player) in expected.iter() {
+                let decoded = &state.physics_state.players[id];
+                assert_eq!(decoded.aabb, player.aabb);
+                assert_eq!(decoded.velocity, player.velocity);
+            }
+            if tick % 5 != 0 {
+                encoder.acknowledge(sequence);
+            }
+        }
+        // Most of the snapshots only contain the moving players
+        assert!(delta_size / 200 < full_size);
+    }
+}
@@ -0,0 +1,457 @@
+//! Vehicles: the boats and the carts that the players ride. The input of the rider steers the vehicle instead of
+//! the rider, and the rider is carried on the seat of the vehicle. The boats float on the fluids and barely slide on
+//! the ground. The carts follow the rails, keeping their momentum and speeding up down the slopes.
+//! The vehicles are part of the physics state, so the client predicts the movement of its vehicle like the movement
+//! of its player.
+use super::{camera::movement_direction, player::PhysicsPlayer, BlockContainer};
+use crate::{
+    player::{PlayerId, PlayerInput},
+    world::BlockPos,
+};
+use nalgebra::{Point3, Vector3};
+use ncollide3d::bounding_volume::AABB;
+use serde::{Deserialize, Serialize};
+
+/// Half extents of the box of a boat
+const BOAT_HALF_EXTENTS: [f64; 3] = [0.7, 0.3, 0.7];
+/// Half extents of the box of a cart, that fits between the walls of a tunnel
+const CART_HALF_EXTENTS: [f64; 3] = [0.45, 0.35, 0.45];
+/// Height of the feet of the rider above the bottom of a boat and of a cart
+const BOAT_SEAT_HEIGHT: f64 = 0.2;
+const CART_SEAT_HEIGHT: f64 = 0.3;
+/// Degrees per second that the boat turns
+const TURN_SPEED: f64 = 90.0;
+/// Acceleration of the boat going forward, in blocks per second squared. Going backward is slower.
+const THRUST: f64 = 12.0;
+const REVERSE_THRUST: f64 = 4.0;
+/// Fraction of the horizontal speed lost per second in a fluid, on the ground and in the air
+const WATER_DRAG: f64 = 1.0;
+const GROUND_DRAG: f64 = 8.0;
+const AIR_DRAG: f64 = 0.2;
+const MAX_SPEED: f64 = 8.0;
+const GRAVITY_ACCELERATION: f64 = 25.0;
+const MAX_FALL_SPEED: f64 = 30.0;
+/// Speed at which a submerged boat rises to the surface
+const FLOAT_SPEED: f64 = 2.0;
+/// Horizontal distance between the center of the vehicle and the position of a dismounting rider
+const DISMOUNT_DISTANCE: f64 = 1.2;
+/// Acceleration of a cart pushed by its rider along the direction the rider looks, in blocks per second squared
+const CART_PUSH: f64 = 6.0;
+/// Fraction of the speed of a cart lost per second on the rails
+const RAIL_DRAG: f64 = 0.1;
+const MAX_CART_SPEED: f64 = 10.0;
+/// How far a cart can be from the path of a rail of its cell to be put on it
+const RAIL_SNAP_DISTANCE: f64 = 0.75;
+/// Maximum number of rails that a cart goes through in one step
+const MAX_RAILS_PER_STEP: usize = 8;
+/// Distance between the end of the rails and a cart stopped there, so that the cart stays in the cell of the last rail
+const RAIL_END_GAP: f64 = 0.01;
+
+/// The kind of a vehicle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VehicleKind {
+    Boat,
+    Cart,
+}
+
+impl VehicleKind {
+    fn half_extents(self) -> Vector3<f64> {
+        Vector3::from(match self {
+            Self::Boat => BOAT_HALF_EXTENTS,
+            Self::Cart => CART_HALF_EXTENTS,
+        })
+    }
+
+    fn seat_height(self) -> f64 {
+        match self {
+            Self::Boat => BOAT_SEAT_HEIGHT,
+            Self::Cart => CART_SEAT_HEIGHT,
+        }
+    }
+}
+
+/// Some unique vehicle id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct VehicleId(pub u32);
+
+/// The physics representation of a vehicle
+#[derive(Debug, Clone)]
+pub struct PhysicsVehicle {
+    pub kind: VehicleKind,
+    /// The box and the velocity of the vehicle, that collides with the blocks like a player that doesn't sneak
+    pub body: PhysicsPlayer,
+    /// The direction the vehicle is facing, in degrees like the yaw of the players
+    pub yaw: f64,
+    /// The player riding the vehicle, if any
+    pub rider: Option<PlayerId>,
+}
+
+impl PhysicsVehicle {
+    /// Create a vehicle whose bottom is centered on `position`
+    pub fn new(kind: VehicleKind, position: Point3<f64>, yaw: f64) -> Self {
+        let half_extents = kind.half_extents();
+        Self {
+            kind,
+            body: PhysicsPlayer {
+                aabb: AABB::from_half_extents(
+                    position + Vector3::new(0.0, half_extents.y, 0.0),
+                    half_extents,
+                ),
+                velocity: Vector3::zeros(),
+                sneaking: false,
+            },
+            yaw,
+            rider: None,
+        }
+    }
+
+    /// Get the position of the center of the bottom of the vehicle
+    pub fn position(&self) -> Point3<f64> {
+        let aabb = &self.body.aabb;
+        Point3::new(
+            (aabb.mins.x + aabb.maxs.x) / 2.0,
+            aabb.mins.y,
+            (aabb.mins.z + aabb.maxs.z) / 2.0,
+        )
+    }
+
+    /// Get the position of the feet of the rider
+    pub fn seat_position(&self) -> Point3<f64> {
+        self.position() + Vector3::new(0.0, self.kind.seat_height(), 0.0)
+    }
+
+    /// Move the vehicle during `seconds_delta` seconds, steered by the input of its rider if it has one.
+    /// Left and right turn the boats, forward and backward push them along their direction.
+    /// The carts on the rails follow them, see `follow_rails`. Elsewhere they fall and slide like the boats on the
+    /// ground, but their rider can't steer them.
+    pub fn steer<BC: BlockContainer>(
+        &mut self,
+        input: Option<PlayerInput>,
+        seconds_delta: f64,
+        world: &BC,
+    ) {
+        if seconds_delta <= 0.0 {
+            return;
+        }
+        if self.kind == VehicleKind::Cart && self.follow_rails(input, seconds_delta, world) {
+            return;
+        }
+        let input = match self.kind {
+            VehicleKind::Boat => input.unwrap_or_default(),
+            VehicleKind::Cart => PlayerInput::default(),
+        };
+        if input.key_move_left {
+            self.yaw += TURN_SPEED * seconds_delta;
+        }
+        if input.key_move_right {
+            self.yaw -= TURN_SPEED * seconds_delta;
+        }
+        self.yaw = self.yaw.rem_euclid(360.0);
+
+        let in_fluid = self.body.is_in_fluid(world);
+        let on_ground = self.body.is_on_ground(world);
+        let mut velocity = self.body.velocity;
+        let thrust = match (input.key_move_forward, input.key_move_backward) {
+            (true, false) => THRUST,
+            (false, true) => -REVERSE_THRUST,
+            _ => 0.0,
+        };
+        // Only the boats in a fluid can be paddled forward
+        if in_fluid {
+            velocity += movement_direction(self.yaw, 0.0) * thrust * seconds_delta;
+        }
+        let drag = if in_fluid {
+            WATER_DRAG
+        } else if on_ground {
+            GROUND_DRAG
+        } else {
+            AIR_DRAG
+        };
+        let horizontal_factor = (1.0 - drag * seconds_delta).max(0.0);
+        velocity.x *= horizontal_factor;
+        velocity.z *= horizontal_factor;
+        let horizontal_speed = velocity.x.hypot(velocity.z);
+        if horizontal_speed > MAX_SPEED {
+            velocity.x *= MAX_SPEED / horizontal_speed;
+            velocity.z *= MAX_SPEED / horizontal_speed;
+        }
+
+        // The vehicle floats with its bottom in the fluid and its middle above it
+        let middle = self.body.aabb.center();
+        velocity.y = if world.is_block_fluid(BlockPos::from(middle)) {
+            FLOAT_SPEED
+        } else if in_fluid || on_ground {
+            0.0
+        } else {
+            (velocity.y - GRAVITY_ACCELERATION * seconds_delta).max(-MAX_FALL_SPEED)
+        };
+
+        let movement = self
+            .body
+            .move_check_collision(world, velocity * seconds_delta);
+        // The vehicle stops against the obstacles
+        self.body.velocity = movement / seconds_delta;
+    }
+
+    /// Move the cart along the rails during `seconds_delta` seconds. The cart keeps its speed along the rails, the
+    /// gravity pulls it down the slopes and the rider pushes it by going forward. It stops at the end of the rails.
+    /// The rails are followed without checking the collisions with the blocks.
+    /// Returns false if the cart is not on a rail.
+    fn follow_rails<BC: BlockContainer>(
+        &mut self,
+        input: Option<PlayerInput>,
+        seconds_delta: f64,
+        world: &BC,
+    ) -> bool {
+        let position = self.position();
+        let [mut start, mut end] = match rail_path_at(position, world) {
+            Some(path) => path,
+            None => return false,
+        };
+        let mut direction = (end - start).normalize();
+        let mut distance = (position - start)
+            .dot(&direction)
+            .max(0.0)
+            .min((end - start).norm());
+
+        let mut speed = self.body.velocity.dot(&direction);
+        speed -= GRAVITY_ACCELERATION * direction.y * seconds_delta;
+        if let Some(input) = input.filter(|input| input.key_move_forward) {
+            let push = movement_direction(input.yaw, 0.0).dot(&direction);
+            speed += CART_PUSH * push * seconds_delta;
+        }
+        speed *= (1.0 - RAIL_DRAG * seconds_delta).max(0.0);
+        speed = speed.max(-MAX_CART_SPEED).min(MAX_CART_SPEED);
+        // Always go from `start` towards `end`
+        if speed < 0.0 {
+            std::mem::swap(&mut start, &mut end);
+            direction = -direction;
+            distance = (end - start).norm() - distance;
+            speed = -speed;
+        }
+
+        let mut remaining = speed * seconds_delta;
+        for _ in 0..MAX_RAILS_PER_STEP {
+            let length = (end - start).norm();
+            if distance + remaining <= length {
+                distance += remaining;
+                break;
+            }
+            remaining -= length - distance;
+            match next_rail_path(end, direction, world) {
+                Some([next_start, next_end]) => {
+                    start = next_start;
+                    end = next_end;
+                    direction = (end - start).normalize();
+                    distance = 0.0;
+                }
+                None => {
+                    distance = distance.max(length - RAIL_END_GAP);
+                    speed = 0.0;
+                    break;
+                }
+            }
+        }
+
+        let translation = start + direction * distance - position;
+        self.body.aabb = AABB::new(
+            self.body.aabb.mins + translation,
+            self.body.aabb.maxs + translation,
+        );
+        self.body.velocity = direction * speed;
+        // The cart faces along the rail
+        if direction.x != 0.0 || direction.z != 0.0 {
+            self.yaw = (-direction.x)
+                .atan2(-direction.z)
+                .to_degrees()
+                .rem_euclid(360.0);
+        }
+        true
+    }
+
+    /// Put `rider` on the seat of the vehicle, moving with it
+    pub fn carry(&self, rider: &mut PhysicsPlayer) {
+        rider.teleport(self.seat_position());
+        rider.velocity = self.body.velocity;
+        rider.sneaking = false;
+    }
+
+    /// Get where a rider that leaves the vehicle can stand: beside the vehicle if there is room, or else above it
+    pub fn dismount_position<BC: BlockContainer>(&self, world: &BC) -> Point3<f64> {
+        let position = self.position();
+        let sides = [90.0, 270.0, 0.0, 180.0]
+            .iter()
+            .map(|&angle| position + movement_direction(self.yaw, angle) * DISMOUNT_DISTANCE);
+        let above = position + Vector3::new(0.0, self.kind.half_extents().y * 2.0, 0.0);
+        sides
+            .chain(std::iter::once(above))
+            .find(|&candidate| !PhysicsPlayer::from_coords(candidate).intersect_world(world))
+            .unwrap_or(above)
+    }
+}
+
+/// Get the ends of the path of the rail of `cell`, in world coordinates
+fn rail_path<BC: BlockContainer>(cell: BlockPos, world: &BC) -> Option<[Point3<f64>; 2]> {
+    let shape = world.get_rail(cell)?;
+    let corner = Vector3::new(cell.px as f64, cell.py as f64, cell.pz as f64);
+    let [a, b] = shape.path();
+    Some([Point3::from(a) + corner, Point3::from(b) + corner])
+}
+
+/// Get the path of the rail that a cart at `position` is on: the rail of its cell, or of the cell below at the top
+/// of a slope, if the cart is close enough to its path
+fn rail_path_at<BC: BlockContainer>(position: Point3<f64>, world: &BC) -> Option<[Point3<f64>; 2]> {
+    // The carts on flat rails are exactly at the bottom of the cell, or barely below after the rounding
+    let cell = BlockPos::from(position + Vector3::new(0.0, 0.01, 0.0));
+    let below = BlockPos::from((cell.px, cell.py - 1, cell.pz));
+    [cell, below]
+        .iter()
+        .filter_map(|&cell| rail_path(cell, world))
+        .find(|[start, end]| {
+            let direction = (end - start).normalize();
+            let along = (position - start)
+                .dot(&direction)
+                .max(0.0)
+                .min((end - start).norm());
+            (start + direction * along - position).norm() <= RAIL_SNAP_DISTANCE
+        })
+}
+
+/// Get the path of the rail that continues the rail ending at `end` in the horizontal `direction`, starting from
+/// `end`. The next rail is in the next cell at the same height, or in the cell below for a slope going down.
+fn next_rail_path<BC: BlockContainer>(
+    end: Point3<f64>,
+    direction: Vector3<f64>,
+    world: &BC,
+) -> Option<[Point3<f64>; 2]> {
+    let horizontal = Vector3::new(direction.x, 0.0, direction.z).normalize();
+    let cell = BlockPos::from(end + horizontal * 0.5 + Vector3::new(0.0, 0.01, 0.0));
+    let below = BlockPos::from((cell.px, cell.py - 1, cell.pz));
+    [cell, below].iter().find_map(|&cell| {
+        let [a, b] = rail_path(cell, world)?;
+        if (a - end).norm() < 1e-6 {
+            Some([a, b])
+        } else if (b - end).norm() < 1e-6 {
+            Some([b, a])
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{rail::RailShape, HorizontalFace};
+
+    /// Water below y = 10, and a wall of stone at x = 5
+    struct Lake;
+
+    impl BlockContainer for Lake {
+        fn is_block_full(&self, pos: BlockPos) -> bool {
+            pos.px == 5
+        }
+
+        fn is_block_fluid(&self, pos: BlockPos) -> bool {
+            pos.py < 10 && pos.px != 5
+        }
+    }
+
+    const DT: f64 = 0.016;
+
+    #[test]
+    fn test_boat_floats_and_sails() {
+        let mut boat = PhysicsVehicle::new(VehicleKind::Boat, Point3::new(0.5, 8.0, 0.5), 0.0);
+        for _ in 0..200 {
+            boat.steer(None, DT, &Lake);
+        }
+        // It rose to the surface, with its middle above the water
+        let height = boat.position().y;
+        assert!(height > 9.3 && height <= 10.0, "{}", height);
+        assert!(boat.body.velocity.x.abs() < 1e-9);
+
+        // Turn right to face +x, then paddle into the wall
+        let turn = PlayerInput {
+            key_move_right: true,
+            flying: false,
+            ..PlayerInput::default()
+        };
+        for _ in 0..((90.0 / TURN_SPEED / DT).round() as u32) {
+            boat.steer(Some(turn), DT, &Lake);
+        }
+        assert!((boat.yaw - 270.0).abs() < 1.0, "{}", boat.yaw);
+        let forward = PlayerInput {
+            key_move_forward: true,
+            flying: false,
+            ..PlayerInput::default()
+        };
+        for _ in 0..300 {
+            boat.steer(Some(forward), DT, &Lake);
+        }
+        let position = boat.position();
+        assert!(position.x > 4.0 && position.x < 5.0 - BOAT_HALF_EXTENTS[0] + 1e-3);
+        assert!((position.z - 0.5).abs() < 0.1, "{:?}", position);
+        assert!(boat.body.velocity.x.abs() < 0.1);
+
+        // The sides are too close to the wall, so the rider lands behind the boat
+        let dismount = boat.dismount_position(&Lake);
+        assert!(!PhysicsPlayer::from_coords(dismount).intersect_world(&Lake));
+        assert!(dismount.x < position.x - 1.0, "{:?}", dismount);
+    }
+
+    /// Ground below y = 10, with rails going along x at y = 11, down a slope to y = 10 at x = 4, along x to a turn
+    /// at x = 21 and then along z until z = 4
+    struct Track;
+
+    impl BlockContainer for Track {
+        fn is_block_full(&self, pos: BlockPos) -> bool {
+            pos.py < 10
+        }
+
+        fn get_rail(&self, pos: BlockPos) -> Option<RailShape> {
+            match (pos.px, pos.py, pos.pz) {
+                (0..=3, 11, 0) | (5..=20, 10, 0) => Some(RailShape::StraightX),
+                (4, 10, 0) => Some(RailShape::Slope(HorizontalFace::NegX)),
+                (21, 10, 0) => Some(RailShape::Curve(HorizontalFace::NegX, HorizontalFace::PosZ)),
+                (21, 10, 1..=3) => Some(RailShape::StraightZ),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_cart_follows_rails() {
+        let mut cart = PhysicsVehicle::new(VehicleKind::Cart, Point3::new(0.5, 11.0, 0.5), 0.0);
+        // The rider pushes the cart towards +x for a moment
+        let push = PlayerInput {
+            key_move_forward: true,
+            yaw: 270.0,
+            flying: false,
+            ..PlayerInput::default()
+        };
+        for _ in 0..20 {
+            cart.steer(Some(push), DT, &Track);
+        }
+        let pushed_speed = cart.body.velocity.norm();
+        assert!(pushed_speed > 1.0, "{}", pushed_speed);
+        assert!((cart.yaw - 270.0).abs() < 1e-9, "{}", cart.yaw);
+
+        // It rolls on, and goes faster after the slope
+        let mut speed_after_slope = None;
+        for _ in 0..800 {
+            cart.steer(None, DT, &Track);
+            if cart.position().x > 5.5 && speed_after_slope.is_none() {
+                speed_after_slope = Some(cart.body.velocity.norm());
+            }
+        }
+        assert!(speed_after_slope.unwrap() > pushed_speed * 2.0);
+        // Then it goes around the turn and stops at the end of the rails
+        let position = cart.position();
+        assert!(
+            (position - Point3::new(21.5, 10.0, 4.0)).norm() < 0.02,
+            "{:?}",
+            position
+        );
+        assert_eq!(cart.body.velocity, Vector3::zeros());
+    }
+}
@@ -1,15 +1,18 @@
-use crate::world::ChunkPos;
-use serde::Deserialize;
+use crate::world::{ChunkPos, ChunkPosXZ};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// The input of a player
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct PlayerInput {
     pub key_move_forward: bool,
     pub key_move_left: bool,
     pub key_move_backward: bool,
     pub key_move_right: bool,
     pub key_move_up: bool,
+    /// Flying down, or sneaking while walking
     pub key_move_down: bool,
+    pub key_sprint: bool,
     pub yaw: f64,
     pub pitch: f64,
     pub flying: bool,
@@ -24,6 +27,7 @@ impl Default for PlayerInput {
             key_move_right: false,
             key_move_up: false,
             key_move_down: false,
+            key_sprint: false,
             yaw: 0.0,
             pitch: 0.0,
             flying: true,
@@ -32,11 +36,146 @@ impl Default for PlayerInput {
 }
 
 /// Some unique player id.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct PlayerId(pub(crate) u16);
 
+impl fmt::Display for PlayerId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The game mode of a player, which decides what the player is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    Survival,
+    Creative,
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        Self::Survival
+    }
+}
+
+impl GameMode {
+    /// Whether the player can fly
+    pub fn can_fly(self) -> bool {
+        self == Self::Creative
+    }
+
+    /// Whether the blocks break as soon as the player starts breaking them
+    pub fn breaks_instantly(self) -> bool {
+        self == Self::Creative
+    }
+
+    /// Whether the player can take any item from the creative palette
+    pub fn has_infinite_items(self) -> bool {
+        self == Self::Creative
+    }
+
+    /// Whether the player can watch the other players
+    pub fn can_spectate(self) -> bool {
+        self == Self::Creative
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Survival => write!(f, "survival"),
+            Self::Creative => write!(f, "creative"),
+        }
+    }
+}
+
+impl std::str::FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "survival" => Ok(Self::Survival),
+            "creative" => Ok(Self::Creative),
+            _ => Err(format!("unknown game mode: {}", s)),
+        }
+    }
+}
+
+/// A named position that a player wants to remember
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Waypoint {
+    pub name: String,
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub color: [f32; 4],
+}
+
+/// Name of the waypoint that is automatically placed where the player last died
+pub const DEATH_WAYPOINT_NAME: &str = "Last death";
+
+/// Maximum length of the name of a player, in characters
+pub const MAX_PLAYER_NAME_LENGTH: usize = 16;
+
+/// Number of hexadecimal digits of a player profile
+pub const PROFILE_LENGTH: usize = 32;
+
+/// Check that a player profile is `PROFILE_LENGTH` lowercase hexadecimal digits.
+/// The profile is a random secret generated once by the client, that identifies the player on every server.
+/// The saves of the players are named after it, so nothing else is accepted.
+pub fn is_valid_profile(profile: &str) -> bool {
+    profile.len() == PROFILE_LENGTH
+        && profile
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+/// The team of a player, as shown in the player list and in the name tags
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamTag {
+    pub name: String,
+    /// Color of the name of the player
+    pub color: [f32; 4],
+}
+
+/// A line of the player list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerListEntry {
+    pub id: PlayerId,
+    pub name: String,
+    /// Round-trip time between the server and the player in milliseconds, once it was measured
+    pub ping: Option<u32>,
+    /// The team of the player, the players of a team are next to each other in the list
+    pub team: Option<TeamTag>,
+}
+
+/// Clean the name chosen by a player: the control characters are removed, the spaces around it are trimmed
+/// and it is cut to `MAX_PLAYER_NAME_LENGTH` characters. An empty name is replaced by `Player <id>`.
+pub fn sanitize_player_name(name: &str, id: PlayerId) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .chars()
+        .take(MAX_PLAYER_NAME_LENGTH)
+        .collect();
+    let name = name.trim_end();
+    if name.is_empty() {
+        format!("Player {}", id)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Check if a name has the form of the default name of some player, `Player <id>`
+pub fn is_default_player_name(name: &str) -> bool {
+    name.strip_prefix("Player ")
+        .map_or(false, |id| id.parse::<u16>().is_ok())
+}
+
 /// The render distance of a player
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub struct RenderDistance {
     pub x_max: u64,
     pub x_min: u64,
@@ -47,6 +186,18 @@ pub struct RenderDistance {
 }
 
 impl RenderDistance {
+    /// Limit the render distance to `max` chunks in every direction
+    pub fn capped(self, max: u64) -> Self {
+        Self {
+            x_max: self.x_max.min(max),
+            x_min: self.x_min.min(max),
+            y_max: self.y_max.min(max),
+            y_min: self.y_min.min(max),
+            z_max: self.z_max.min(max),
+            z_min: self.z_min.min(max),
+        }
+    }
+
     /// Create an iterator over the chunks in the render distance around the player pos
     pub fn iterate_around_player(self, player_chunk: ChunkPos) -> impl Iterator<Item = ChunkPos> {
         RenderDistanceIterator::new(self, player_chunk)
@@ -61,6 +212,16 @@ impl RenderDistance {
             && player_chunk.py - chunk_pos.py <= self.y_min as i64
             && player_chunk.pz - chunk_pos.pz <= self.z_min as i64
     }
+
+    /// Check if some chunks of a column can be visible to a player in `player_chunk`
+    pub fn is_column_visible(self, player_chunk: ChunkPos, column: ChunkPosXZ) -> bool {
+        let chunk_pos = ChunkPos {
+            px: column.px,
+            py: player_chunk.py,
+            pz: column.pz,
+        };
+        self.is_chunk_visible(player_chunk, chunk_pos)
+    }
 }
 
 pub struct RenderDistanceIterator {
@@ -158,3 +319,34 @@ fn get_close_chunks(render_distance: &RenderDistance) -> Vec<ChunkPos> {
     adjacent_positions.sort_by_key(|pos| origin.squared_euclidian_distance(*pos));
     adjacent_positions
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_player_name() {
+        let id = PlayerId(3);
+        assert_eq!(sanitize_player_name("  Steve\n", id), "Steve");
+        assert_eq!(sanitize_player_name(" \t ", id), "Player 3");
+        assert_eq!(
+            sanitize_player_name("A very long name that is cut", id),
+            "A very long name"
+        );
+        // No trailing space after cutting
+        assert_eq!(
+            sanitize_player_name("Fifteen letters ok", id),
+            "Fifteen letters"
+        );
+        assert!(is_default_player_name("Player 3"));
+        assert!(!is_default_player_name("Player Three"));
+    }
+
+    #[test]
+    fn test_is_valid_profile() {
+        assert!(is_valid_profile("0123456789abcdef0123456789abcdef"));
+        assert!(!is_valid_profile("0123456789ABCDEF0123456789ABCDEF"));
+        assert!(!is_valid_profile("0123456789abcdef"));
+        assert!(!is_valid_profile("../../config/server.toml........"));
+    }
+}
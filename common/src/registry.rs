@@ -1,28 +1,23 @@
 use std::collections::HashMap;
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum RegistryError {
+    #[error("key already exists in the Registry: {key}")]
     KeyAlreadyExists { key: String },
 }
 
-impl std::fmt::Display for RegistryError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        match self {
-            Self::KeyAlreadyExists { key } => {
-                write!(f, "key already exists in the Registry: {}", key)
-            }
-        }
-    }
-}
+/// The prefix of the tag names in the data files, to tell them apart from the element names
+pub const TAG_PREFIX: char = '#';
 
-impl std::error::Error for RegistryError {}
-
-/// A way to store elements by name or by id
+/// A way to store elements by name or by id.
+/// The elements can also be grouped under tags, referred to as `#tag` in place of an element name.
 #[derive(Debug, Clone)]
 pub struct Registry<T> {
     name_to_id: HashMap<String, u32>,
     id_to_name: Vec<String>,
     id_to_value: Vec<T>,
+    /// The ids of the elements of every tag, by tag name without the prefix
+    tags: HashMap<String, Vec<u32>>,
 }
 
 impl<T> Registry<T> {
@@ -42,6 +37,10 @@ impl<T> Registry<T> {
         self.name_to_id.get(name).cloned()
     }
 
+    pub fn get_name_by_id(&self, id: u32) -> Option<&String> {
+        self.id_to_name.get(id as usize)
+    }
+
     pub fn get_number_of_ids(&self) -> u32 {
         return self.id_to_name.len() as u32;
     }
@@ -52,6 +51,38 @@ impl<T> Registry<T> {
         }
         return None;
     }
+
+    /// Add the element `id` to the tag `tag`, creating the tag if it doesn't exist
+    pub fn add_to_tag(&mut self, tag: String, id: u32) {
+        let ids = self.tags.entry(tag).or_default();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    /// Get the ids of the elements of a tag, given without the prefix, or `None` if the tag doesn't exist
+    pub fn get_ids_by_tag(&self, tag: &str) -> Option<&[u32]> {
+        self.tags.get(tag).map(Vec::as_slice)
+    }
+
+    /// Get the ids that `name` refers to: the elements of the tag for a `#tag`, or the element with this name.
+    /// Returns `None` if the tag or the element doesn't exist.
+    pub fn resolve(&self, name: &str) -> Option<Vec<u32>> {
+        match name.strip_prefix(TAG_PREFIX) {
+            Some(tag) => self.get_ids_by_tag(tag).map(<[u32]>::to_vec),
+            None => self.name_to_id.get(name).map(|&id| vec![id]),
+        }
+    }
+
+    /// Check if the element `id` is the element `name` or belongs to the tag `name`, see `resolve`
+    pub fn matches(&self, name: &str, id: u32) -> bool {
+        match name.strip_prefix(TAG_PREFIX) {
+            Some(tag) => self
+                .get_ids_by_tag(tag)
+                .map_or(false, |ids| ids.contains(&id)),
+            None => self.name_to_id.get(name) == Some(&id),
+        }
+    }
 }
 
 impl<T> Default for Registry<T> {
@@ -60,6 +91,7 @@ impl<T> Default for Registry<T> {
             name_to_id: HashMap::new(),
             id_to_name: Vec::new(),
             id_to_value: Vec::new(),
+            tags: HashMap::new(),
         }
     }
 }
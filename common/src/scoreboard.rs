@@ -0,0 +1,29 @@
+//! The part of the scoreboard that the clients show: the objectives displayed on the HUD
+use serde::{Deserialize, Serialize};
+
+/// An objective as it is shown to the players
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DisplayedObjective {
+    pub title: String,
+    /// The players and their scores, from the highest to the lowest score
+    pub scores: Vec<(String, i64)>,
+}
+
+impl DisplayedObjective {
+    /// Score of the player named `name`, if it has one
+    pub fn score(&self, name: &str) -> Option<i64> {
+        self.scores
+            .iter()
+            .find(|(player, _)| player == name)
+            .map(|&(_, score)| score)
+    }
+}
+
+/// The objectives shown on the HUD of every player
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScoreboardDisplay {
+    /// The objective shown in the sidebar, with its best scores only
+    pub sidebar: Option<DisplayedObjective>,
+    /// The objective whose scores are shown below the names of the players
+    pub below_name: Option<DisplayedObjective>,
+}
@@ -0,0 +1,149 @@
+//! Player skins, which recolor the model of the players
+use crate::data::vox::VoxelModel;
+use anyhow::{bail, Result};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of colors replaced by a skin
+pub const MAX_SKIN_COLORS: usize = 64;
+
+/// Only the red, green and blue parts of the colors of the voxels are compared and replaced
+const RGB_MASK: u32 = 0x00ff_ffff;
+
+/// A recoloring of the player model: every voxel of some color is drawn with another color.
+/// The colors are in the format of the voxel models, red in the lowest byte.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerSkin {
+    /// Pairs of the original color and its replacement
+    pub colors: Vec<(u32, u32)>,
+}
+
+impl PlayerSkin {
+    /// Read a skin from an image with two rows: the first row contains the colors of the model to replace,
+    /// and the second row their replacements. The columns whose first pixel is transparent are ignored.
+    pub fn from_image(image: &RgbaImage) -> Result<Self> {
+        if image.height() != 2 {
+            bail!(
+                "The skin image must be 2 pixels high, it is {} pixels high",
+                image.height()
+            );
+        }
+        let colors: Vec<_> = (0..image.width())
+            .filter(|&x| image.get_pixel(x, 0)[3] > 0)
+            .map(|x| (pixel_color(image, x, 0), pixel_color(image, x, 1)))
+            .collect();
+        Self::new(colors)
+    }
+
+    /// Compute the skin that turns `original` into `recolored`, a copy of the same model with other colors
+    pub fn from_models(original: &VoxelModel, recolored: &VoxelModel) -> Result<Self> {
+        let size = |model: &VoxelModel| (model.size_x, model.size_y, model.size_z);
+        if size(original) != size(recolored) {
+            bail!(
+                "The skin model must have the size of the player model, {:?}, it is {:?}",
+                size(original),
+                size(recolored)
+            );
+        }
+        let mut colors: Vec<(u32, u32)> = Vec::new();
+        for i in 0..original.voxels.len() {
+            if !original.full[i] || !recolored.full[i] {
+                continue;
+            }
+            let from = original.voxels[i] & RGB_MASK;
+            let to = recolored.voxels[i] & RGB_MASK;
+            if from != to && colors.iter().all(|&(color, _)| color != from) {
+                colors.push((from, to));
+            }
+        }
+        Self::new(colors)
+    }
+
+    fn new(colors: Vec<(u32, u32)>) -> Result<Self> {
+        let skin = Self { colors };
+        if !skin.is_valid() {
+            bail!(
+                "A skin can replace at most {} colors, this one replaces {}",
+                MAX_SKIN_COLORS,
+                skin.colors.len()
+            );
+        }
+        Ok(skin)
+    }
+
+    /// Whether the skin is small enough to be accepted by the server
+    pub fn is_valid(&self) -> bool {
+        self.colors.len() <= MAX_SKIN_COLORS
+    }
+
+    /// Get a copy of `model` with the colors of the skin
+    pub fn recolor(&self, model: &VoxelModel) -> VoxelModel {
+        let mut recolored = model.clone();
+        for color in recolored.voxels.iter_mut() {
+            let rgb = *color & RGB_MASK;
+            if let Some(&(_, to)) = self
+                .colors
+                .iter()
+                .find(|&&(from, _)| from & RGB_MASK == rgb)
+            {
+                *color = (*color & !RGB_MASK) | (to & RGB_MASK);
+            }
+        }
+        recolored
+    }
+}
+
+fn pixel_color(image: &RgbaImage, x: u32, y: u32) -> u32 {
+    let [r, g, b, _] = image.get_pixel(x, y).0;
+    r as u32 | (g as u32) << 8 | (b as u32) << 16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::vox::VoxelMaterial;
+    use image::Rgba;
+
+    fn model(voxels: Vec<u32>) -> VoxelModel {
+        VoxelModel {
+            size_x: voxels.len(),
+            size_y: 1,
+            size_z: 1,
+            full: voxels.iter().map(|&color| color != 0).collect(),
+            materials: vec![VoxelMaterial::default(); voxels.len()],
+            voxels,
+        }
+    }
+
+    #[test]
+    fn test_skin_from_image() {
+        let mut image = RgbaImage::new(3, 2);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(0, 1, Rgba([0, 0, 255, 255]));
+        // Ignored, the first pixel is transparent
+        image.put_pixel(1, 1, Rgba([0, 255, 0, 255]));
+        image.put_pixel(2, 0, Rgba([0, 255, 0, 255]));
+        image.put_pixel(2, 1, Rgba([1, 2, 3, 255]));
+        let skin = PlayerSkin::from_image(&image).unwrap();
+        assert_eq!(
+            skin.colors,
+            vec![(0x0000ff, 0xff0000), (0x00ff00, 0x030201)]
+        );
+
+        assert!(PlayerSkin::from_image(&RgbaImage::new(3, 3)).is_err());
+        let too_many_colors =
+            RgbaImage::from_pixel(MAX_SKIN_COLORS as u32 + 1, 2, Rgba([1, 1, 1, 255]));
+        assert!(PlayerSkin::from_image(&too_many_colors).is_err());
+    }
+
+    #[test]
+    fn test_recolor() {
+        let original = model(vec![0xff00_00ff, 0, 0xff00_ff00, 0xff00_00ff]);
+        let recolored = model(vec![0xff11_1111, 0, 0xff00_ff00, 0xff11_1111]);
+        let skin = PlayerSkin::from_models(&original, &recolored).unwrap();
+        assert_eq!(skin.colors, vec![(0x0000ff, 0x111111)]);
+        assert_eq!(skin.recolor(&original).voxels, recolored.voxels);
+
+        assert!(PlayerSkin::from_models(&original, &model(vec![0])).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+//! Smelting recipes, used by furnaces
+use crate::item::ItemId;
+use serde::Deserialize;
+
+/// A smelting recipe. This is the data provided by the creator of the recipe.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename = "SmeltingRecipe")]
+pub struct SmeltingRecipeData {
+    /// Name of the item that is smelted, or `#tag` of the items that are smelted into the same output
+    pub input: String,
+    /// Name of the item that is produced
+    pub output: String,
+    /// Number of items produced by each smelted item
+    #[serde(default = "default_output_count")]
+    pub output_count: u32,
+    /// Number of seconds it takes to smelt one item
+    pub time: f32,
+}
+
+fn default_output_count() -> u32 {
+    1
+}
+
+/// A smelting recipe in-memory representation
+#[derive(Debug, Clone)]
+pub struct SmeltingRecipe {
+    pub input: ItemId,
+    pub output: ItemId,
+    pub output_count: u32,
+    pub time: f32,
+}
@@ -0,0 +1,33 @@
+//! Per-player statistics
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The statistics of a player. The blocks are identified by their name so that the statistics
+/// stay valid if the block ids change.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStats {
+    /// Number of blocks broken, by block name
+    #[serde(default)]
+    pub blocks_broken: BTreeMap<String, u64>,
+    /// Number of blocks placed, by block name
+    #[serde(default)]
+    pub blocks_placed: BTreeMap<String, u64>,
+    /// Distance walked on the ground, in blocks
+    #[serde(default)]
+    pub distance_walked: f64,
+    #[serde(default)]
+    pub deaths: u64,
+    /// Time spent in the game, in seconds
+    #[serde(default)]
+    pub playtime: f64,
+}
+
+impl PlayerStats {
+    pub fn add_block_broken(&mut self, block_name: &str) {
+        *self.blocks_broken.entry(block_name.to_owned()).or_insert(0) += 1;
+    }
+
+    pub fn add_block_placed(&mut self, block_name: &str) {
+        *self.blocks_placed.entry(block_name.to_owned()).or_insert(0) += 1;
+    }
+}
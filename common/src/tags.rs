@@ -0,0 +1,102 @@
+//! Tags: named groups of blocks or items, like `#logs` or `#fuel`, that the data files can refer to
+//! in place of a single block or item name
+use crate::registry::{Registry, TAG_PREFIX};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// The blocks and the items of a tag. This is the data provided by the creator of the tag.
+/// The entries are names or other `#tags` of the same kind.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename = "Tag")]
+pub struct TagData {
+    #[serde(default)]
+    pub blocks: Vec<String>,
+    #[serde(default)]
+    pub items: Vec<String>,
+}
+
+/// Add the tags to `registry`, given as the entries of every tag by tag name.
+/// The tags included in other tags are expanded. Returns the errors by tag name.
+pub fn register_tags<T>(
+    registry: &mut Registry<T>,
+    tags: &HashMap<String, Vec<String>>,
+) -> Vec<(String, String)> {
+    let mut errors = Vec::new();
+    let mut tag_names: Vec<_> = tags.keys().collect();
+    tag_names.sort();
+    for tag in tag_names {
+        let mut ids = Vec::new();
+        let mut visited = HashSet::new();
+        expand_tag(registry, tags, tag, &mut visited, &mut ids, &mut errors);
+        for id in ids {
+            registry.add_to_tag(tag.clone(), id);
+        }
+    }
+    errors
+}
+
+/// Add the ids of the elements of `tag` to `ids`, skipping the tags that were already `visited` so that the
+/// cycles of tags terminate
+fn expand_tag<T>(
+    registry: &Registry<T>,
+    tags: &HashMap<String, Vec<String>>,
+    tag: &str,
+    visited: &mut HashSet<String>,
+    ids: &mut Vec<u32>,
+    errors: &mut Vec<(String, String)>,
+) {
+    if !visited.insert(tag.to_owned()) {
+        return;
+    }
+    for entry in tags[tag].iter() {
+        match entry.strip_prefix(TAG_PREFIX) {
+            Some(included) if tags.contains_key(included) => {
+                expand_tag(registry, tags, included, visited, ids, errors)
+            }
+            Some(_) => errors.push((tag.to_owned(), format!("unknown tag {}", entry))),
+            None => match registry.get_id_by_name(entry) {
+                Some(id) => ids.push(id),
+                None => errors.push((tag.to_owned(), format!("unknown entry {}", entry))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_tags() {
+        let mut registry = Registry::default();
+        for name in ["oak", "birch", "coal", "stone"].iter() {
+            registry.register((*name).to_owned(), ()).unwrap();
+        }
+        let mut tags = HashMap::new();
+        let entries =
+            |entries: &[&str]| -> Vec<String> { entries.iter().map(|&e| e.to_owned()).collect() };
+        tags.insert("logs".to_owned(), entries(&["oak", "birch"]));
+        tags.insert("fuel".to_owned(), entries(&["#logs", "coal", "#fuel"]));
+        tags.insert(
+            "broken".to_owned(),
+            entries(&["stone", "#missing", "marble"]),
+        );
+        let errors = register_tags(&mut registry, &tags);
+
+        assert_eq!(registry.resolve("#fuel"), Some(vec![0, 1, 2]));
+        assert_eq!(registry.resolve("#logs"), Some(vec![0, 1]));
+        assert_eq!(registry.resolve("stone"), Some(vec![3]));
+        assert_eq!(registry.resolve("#missing"), None);
+        assert!(registry.matches("#fuel", 2));
+        assert!(!registry.matches("#logs", 2));
+        assert!(registry.matches("coal", 2));
+        assert_eq!(registry.resolve("#broken"), Some(vec![3]));
+        assert_eq!(
+            errors,
+            vec![
+                ("broken".to_owned(), "unknown tag #missing".to_owned()),
+                ("broken".to_owned(), "unknown entry marble".to_owned()),
+            ]
+        );
+    }
+}
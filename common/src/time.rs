@@ -49,6 +49,47 @@ impl AverageTimeCounter {
     }
 }
 
+/// Helper struct to calculate how many events happen per second, averaged over the last 10 seconds
+pub struct RateCounter {
+    events: VecDeque<(Instant, u64)>,
+    total: u64,
+}
+
+impl RateCounter {
+    pub fn new() -> Self {
+        Self {
+            events: Default::default(),
+            total: 0,
+        }
+    }
+
+    fn remove_old(&mut self) {
+        let t = Instant::now();
+        while let Some(&(prev_time, count)) = self.events.front() {
+            if (t - prev_time).as_secs() >= 10 {
+                self.events.pop_front();
+                self.total -= count;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Record that `count` events happened
+    pub fn add(&mut self, count: u64) {
+        if count > 0 {
+            self.total += count;
+            self.events.push_back((Instant::now(), count));
+        }
+        self.remove_old();
+    }
+
+    pub fn per_second(&mut self) -> f32 {
+        self.remove_old();
+        self.total as f32 / 10.0
+    }
+}
+
 /// Helper struct to calculate what parts of an operation are taking time
 pub struct BreakdownCounter {
     times: VecDeque<(Instant, Vec<Duration>)>,
@@ -1,11 +1,13 @@
 use crate::{
     block::{Block, BlockId},
     registry::Registry,
+    worldgen::biome::{Biome, BiomeMap},
 };
 use nalgebra::Point3;
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
 
 /// The position of a block in the world.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BlockPos {
     pub px: i64,
     pub py: i64,
@@ -63,13 +65,25 @@ pub trait WorldGenerator {
     /// Generate the chunk at position `pos`. The result must always be the same,
     /// independently of the previous calls to this function!
     fn generate_chunk(&mut self, pos: ChunkPos, block_registry: &Registry<Block>) -> Chunk;
+
+    /// Generate the low-resolution surface of the far tile at position `pos`. The result must always be the same,
+    /// independently of the previous calls to this function! The default implementation has no surface.
+    fn generate_far_tile(&mut self, pos: FarTilePos, _block_registry: &Registry<Block>) -> FarTile {
+        FarTile::empty(pos)
+    }
+
+    /// Generate the biomes of the chunk column at position `pos`. The result must always be the same,
+    /// independently of the previous calls to this function! The default implementation is only plains.
+    fn generate_biome_map(&mut self, pos: ChunkPosXZ) -> BiomeMap {
+        BiomeMap::uniform(pos, Biome::Plains)
+    }
 }
 
 /// Number of blocks along an axis of the chunk
 pub const CHUNK_SIZE: u32 = 32;
 
 /// Position of a chunk in the world
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ChunkPos {
     pub px: i64,
     pub py: i64,
@@ -115,7 +129,7 @@ impl From<[i64; 3]> for ChunkPos {
 }
 
 /// Chunk position but only along XZ axis
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ChunkPosXZ {
     pub px: i64,
     pub pz: i64,
@@ -158,7 +172,7 @@ impl From<ChunkPos> for ChunkPosXZ {
 }
 
 /// An RLE-compressed chunk
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompressedChunk {
     pub pos: ChunkPos,
     pub data: Vec<(u16, BlockId)>,
@@ -168,12 +182,12 @@ impl CompressedChunk {
     /// Compress `chunk` using RLE
     pub fn from_chunk(chunk: &Chunk) -> Self {
         let mut compressed_data = Vec::new();
-        let mut current_block = chunk.data[0];
+        let mut current_block = chunk.get_block_at((0, 0, 0));
         let mut current_block_count = 0;
-        for i in 0..(CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize {
-            if chunk.data[i] != current_block {
+        for block in chunk.blocks() {
+            if block != current_block {
                 compressed_data.push((current_block_count, current_block));
-                current_block = chunk.data[i];
+                current_block = block;
                 current_block_count = 0;
             }
             current_block_count += 1;
@@ -203,71 +217,329 @@ impl CompressedChunk {
             i += len;
         }
 
-        Chunk {
-            pos: self.pos,
-            data,
+        Chunk::from_blocks(self.pos, &data)
+    }
+}
+
+/// Check that the runs of an RLE-compressed chunk cover exactly the chunk, so that decompressing it can't panic
+fn check_runs<T>(data: &[(u16, T)]) -> Result<(), String> {
+    let num_blocks = data.iter().map(|&(len, _)| len as usize).sum::<usize>();
+    let chunk_volume = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+    if num_blocks == chunk_volume {
+        Ok(())
+    } else {
+        Err(format!(
+            "The compressed chunk has {} blocks instead of {}",
+            num_blocks, chunk_volume
+        ))
+    }
+}
+
+/// Number of layers of blocks in a section of a chunk
+const SECTION_HEIGHT: u32 = 8;
+/// Number of sections in a chunk
+const SECTIONS: usize = (CHUNK_SIZE / SECTION_HEIGHT) as usize;
+/// Number of blocks in a section
+const SECTION_VOLUME: usize = (CHUNK_SIZE * SECTION_HEIGHT * CHUNK_SIZE) as usize;
+/// Maximum number of blocks in the palette of a section, so that the indices fit in a byte
+const MAX_PALETTE_SIZE: usize = 256;
+
+/// The blocks of a horizontal slice of a chunk, stored according to how many different blocks it has.
+/// Most sections are only air or only stone, and most of the others only have a few different blocks.
+#[derive(Debug, Clone)]
+enum ChunkSection {
+    /// Every block of the section is the same
+    Uniform(BlockId),
+    /// The blocks are indices in a palette of at most `MAX_PALETTE_SIZE` blocks
+    Paletted {
+        palette: Vec<BlockId>,
+        indices: Box<[u8]>,
+    },
+    /// Every block, when there are too many different blocks for a palette
+    Full(Box<[BlockId]>),
+}
+
+impl ChunkSection {
+    /// Store the `SECTION_VOLUME` blocks of a section in the smallest representation
+    fn from_blocks(blocks: &[BlockId]) -> Self {
+        let mut palette = Vec::new();
+        let mut indices = Vec::with_capacity(SECTION_VOLUME);
+        for &block in blocks {
+            let index = match palette.iter().position(|&b| b == block) {
+                Some(index) => index,
+                None if palette.len() < MAX_PALETTE_SIZE => {
+                    palette.push(block);
+                    palette.len() - 1
+                }
+                None => return Self::Full(blocks.into()),
+            };
+            indices.push(index as u8);
+        }
+        if palette.len() == 1 {
+            Self::Uniform(palette[0])
+        } else {
+            Self::Paletted {
+                palette,
+                indices: indices.into_boxed_slice(),
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> BlockId {
+        match self {
+            Self::Uniform(block) => *block,
+            Self::Paletted { palette, indices } => palette[indices[index] as usize],
+            Self::Full(blocks) => blocks[index],
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn get_unchecked(&self, index: usize) -> BlockId {
+        match self {
+            Self::Uniform(block) => *block,
+            Self::Paletted { palette, indices } => {
+                *palette.get_unchecked(*indices.get_unchecked(index) as usize)
+            }
+            Self::Full(blocks) => *blocks.get_unchecked(index),
+        }
+    }
+
+    /// Set a block, switching to a representation that can store it if needed
+    fn set(&mut self, index: usize, block: BlockId) {
+        match self {
+            Self::Uniform(uniform) => {
+                if *uniform != block {
+                    let mut indices = vec![0; SECTION_VOLUME].into_boxed_slice();
+                    indices[index] = 1;
+                    *self = Self::Paletted {
+                        palette: vec![*uniform, block],
+                        indices,
+                    };
+                }
+            }
+            Self::Paletted { palette, indices } => {
+                match palette.iter().position(|&b| b == block) {
+                    Some(palette_index) => indices[index] = palette_index as u8,
+                    None if palette.len() < MAX_PALETTE_SIZE => {
+                        indices[index] = palette.len() as u8;
+                        palette.push(block);
+                    }
+                    // The palette may contain blocks that are not used anymore
+                    None => {
+                        let mut blocks = self.to_vec();
+                        blocks[index] = block;
+                        *self = Self::from_blocks(&blocks);
+                    }
+                }
+            }
+            Self::Full(blocks) => blocks[index] = block,
+        }
+    }
+
+    fn to_vec(&self) -> Vec<BlockId> {
+        match self {
+            Self::Uniform(block) => vec![*block; SECTION_VOLUME],
+            Self::Paletted { palette, indices } => indices
+                .iter()
+                .map(|&index| palette[index as usize])
+                .collect(),
+            Self::Full(blocks) => blocks.to_vec(),
         }
     }
 }
 
-/// A chunk
+/// A chunk.
+/// The blocks are stored by sections of `SECTION_HEIGHT` layers, each with the smallest representation that
+/// can store its blocks, so that the chunks of the sky and of the underground use little memory.
+/// The representations change transparently when blocks are set.
 #[derive(Debug, Clone)]
 pub struct Chunk {
     pub pos: ChunkPos,
-    pub data: Vec<BlockId>,
+    sections: Vec<ChunkSection>,
+}
+
+/// The section of a position in a chunk, and the index of the position in the section
+#[inline(always)]
+fn section_index((px, py, pz): (u32, u32, u32)) -> (usize, usize) {
+    let section = (py / SECTION_HEIGHT) as usize;
+    let index = ((px * SECTION_HEIGHT + py % SECTION_HEIGHT) * CHUNK_SIZE + pz) as usize;
+    (section, index)
 }
 
 impl Chunk {
     /// Create a new empty chunk
     pub fn new(pos: ChunkPos) -> Self {
-        let data: Vec<BlockId> = unsafe {
-            crate::collections::zero_initialized_vec(
-                (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize,
-            )
-        };
-        Self { pos, data }
+        Self {
+            pos,
+            sections: vec![ChunkSection::Uniform(0); SECTIONS],
+        }
+    }
+
+    /// Create a chunk from its blocks, ordered by x, then y, then z
+    pub fn from_blocks(pos: ChunkPos, blocks: &[BlockId]) -> Self {
+        let mut section_blocks = vec![Vec::with_capacity(SECTION_VOLUME); SECTIONS];
+        let mut blocks = blocks.iter();
+        for _ in 0..CHUNK_SIZE {
+            for py in 0..CHUNK_SIZE {
+                let section = &mut section_blocks[(py / SECTION_HEIGHT) as usize];
+                section.extend(blocks.by_ref().take(CHUNK_SIZE as usize));
+            }
+        }
+        Self {
+            pos,
+            sections: section_blocks
+                .iter()
+                .map(|blocks| ChunkSection::from_blocks(blocks))
+                .collect(),
+        }
     }
 
     /// Get block at some position
     #[inline(always)]
-    pub fn get_block_at(&self, (px, py, pz): (u32, u32, u32)) -> BlockId {
-        self.data[(px * CHUNK_SIZE * CHUNK_SIZE + py * CHUNK_SIZE + pz) as usize]
+    pub fn get_block_at(&self, pos: (u32, u32, u32)) -> BlockId {
+        let (section, index) = section_index(pos);
+        self.sections[section].get(index)
     }
 
     /// Set block at some position
     #[inline(always)]
-    pub fn set_block_at(&mut self, (px, py, pz): (u32, u32, u32), block: BlockId) {
-        self.data[(px * CHUNK_SIZE * CHUNK_SIZE + py * CHUNK_SIZE + pz) as usize] = block;
+    pub fn set_block_at(&mut self, pos: (u32, u32, u32), block: BlockId) {
+        let (section, index) = section_index(pos);
+        self.sections[section].set(index, block);
     }
 
     #[inline(always)]
-    pub unsafe fn get_block_at_unsafe(&self, (px, py, pz): (u32, u32, u32)) -> BlockId {
-        *self
-            .data
-            .get_unchecked((px * CHUNK_SIZE * CHUNK_SIZE + py * CHUNK_SIZE + pz) as usize)
+    pub unsafe fn get_block_at_unsafe(&self, pos: (u32, u32, u32)) -> BlockId {
+        let (section, index) = section_index(pos);
+        self.sections.get_unchecked(section).get_unchecked(index)
     }
 
     /// Set block at some position
     #[inline(always)]
-    pub unsafe fn set_block_at_unsafe(&mut self, (px, py, pz): (u32, u32, u32), block: BlockId) {
-        *self
-            .data
-            .get_unchecked_mut((px * CHUNK_SIZE * CHUNK_SIZE + py * CHUNK_SIZE + pz) as usize) =
-            block;
+    pub unsafe fn set_block_at_unsafe(&mut self, pos: (u32, u32, u32), block: BlockId) {
+        let (section, index) = section_index(pos);
+        self.sections.get_unchecked_mut(section).set(index, block);
     }
 
     #[inline(always)]
     pub unsafe fn fill_unsafe(&mut self, block: BlockId) {
-        for i in 0..(CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize {
-            *self.data.get_unchecked_mut(i) = block;
-        }
+        self.fill(block);
     }
 
     #[inline(always)]
     pub fn fill(&mut self, block: BlockId) {
-        for i in 0..(CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize {
-            self.data[i] = block;
+        for section in self.sections.iter_mut() {
+            *section = ChunkSection::Uniform(block);
+        }
+    }
+
+    /// Iterate over the blocks, ordered by x, then y, then z
+    pub fn blocks(&self) -> impl Iterator<Item = BlockId> + '_ {
+        (0..CHUNK_SIZE).flat_map(move |px| {
+            (0..CHUNK_SIZE)
+                .flat_map(move |py| (0..CHUNK_SIZE).map(move |pz| self.get_block_at((px, py, pz))))
+        })
+    }
+
+    /// Get the block of the chunk if all its blocks are the same, for example in the sky
+    pub fn uniform_block(&self) -> Option<BlockId> {
+        let mut blocks = self.sections.iter().map(|section| match section {
+            ChunkSection::Uniform(block) => Some(*block),
+            _ => None,
+        });
+        let first = blocks.next()??;
+        if blocks.all(|block| block == Some(first)) {
+            Some(first)
+        } else {
+            None
+        }
+    }
+
+    /// Store every section with the smallest representation again, once many blocks were set
+    pub fn compact(&mut self) {
+        for section in self.sections.iter_mut() {
+            if !matches!(section, ChunkSection::Uniform(_)) {
+                *section = ChunkSection::from_blocks(&section.to_vec());
+            }
+        }
+    }
+}
+
+/// A description of a chunk as a list of cuboids of blocks, placed in order.
+/// It is mostly useful in tests: the list is easy to generate, print and shrink,
+/// so that a failing chunk can be reduced to a few cuboids and reproduced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkBuilder {
+    pub pos: ChunkPos,
+    /// Minimum corner, inclusive maximum corner and block of every cuboid, relative to the chunk
+    pub cuboids: Vec<((u32, u32, u32), (u32, u32, u32), BlockId)>,
+}
+
+impl ChunkBuilder {
+    /// Start describing an empty chunk
+    pub fn new(pos: ChunkPos) -> Self {
+        Self {
+            pos,
+            cuboids: Vec::new(),
+        }
+    }
+
+    /// Set a single block
+    pub fn block(self, pos: (u32, u32, u32), block: BlockId) -> Self {
+        self.cuboid(pos, pos, block)
+    }
+
+    /// Fill the cuboid between two corners, in any order. The cuboid is clamped to the chunk.
+    pub fn cuboid(
+        mut self,
+        first: (u32, u32, u32),
+        second: (u32, u32, u32),
+        block: BlockId,
+    ) -> Self {
+        let clamp = |x: u32| x.min(CHUNK_SIZE - 1);
+        let min = (
+            clamp(first.0.min(second.0)),
+            clamp(first.1.min(second.1)),
+            clamp(first.2.min(second.2)),
+        );
+        let max = (
+            clamp(first.0.max(second.0)),
+            clamp(first.1.max(second.1)),
+            clamp(first.2.max(second.2)),
+        );
+        self.cuboids.push((min, max, block));
+        self
+    }
+
+    /// Create the chunk
+    pub fn build(&self) -> Chunk {
+        let mut chunk = Chunk::new(self.pos);
+        for &(min, max, block) in self.cuboids.iter() {
+            for px in min.0..=max.0 {
+                for py in min.1..=max.1 {
+                    for pz in min.2..=max.2 {
+                        chunk.set_block_at((px, py, pz), block);
+                    }
+                }
+            }
         }
+        chunk
+    }
+}
+
+// The chunks are sent to the network RLE-compressed
+impl Serialize for Chunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CompressedChunk::from_chunk(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Chunk {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let compressed = CompressedChunk::deserialize(deserializer)?;
+        check_runs(&compressed.data).map_err(D::Error::custom)?;
+        Ok(compressed.to_chunk())
     }
 }
 
@@ -301,7 +573,7 @@ impl LightChunk {
 
 /// An RLE-compressed chunk
 // TODO: merge Chunk and LightChunk implementations ? Also Compressed versions ?
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompressedLightChunk {
     pub pos: ChunkPos,
     pub data: Vec<(u16, u8)>,
@@ -352,3 +624,121 @@ impl CompressedLightChunk {
         }
     }
 }
+
+impl Serialize for LightChunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CompressedLightChunk::from_chunk(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LightChunk {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let compressed = CompressedLightChunk::deserialize(deserializer)?;
+        check_runs(&compressed.data).map_err(D::Error::custom)?;
+        Ok(compressed.to_chunk())
+    }
+}
+
+/// Number of chunks along the X and Z axes of a far tile
+pub const FAR_TILE_CHUNKS: u32 = 8;
+/// Number of samples along the X and Z axes of a far tile, not counting the samples shared with the next tiles
+pub const FAR_TILE_SAMPLES: u32 = 32;
+/// Number of blocks between two samples of a far tile
+pub const FAR_TILE_STEP: u32 = FAR_TILE_CHUNKS * CHUNK_SIZE / FAR_TILE_SAMPLES;
+/// Distance in far tiles along the X and Z axes within which the far tiles around a player are loaded
+pub const FAR_TERRAIN_DISTANCE: i64 = 4;
+
+/// Position of a far tile in the world, along the XZ axes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FarTilePos {
+    pub px: i64,
+    pub pz: i64,
+}
+
+impl FarTilePos {
+    /// Offset the current far tile position by some amount of far tiles
+    pub fn offset(self, dx: i64, dz: i64) -> Self {
+        Self {
+            px: self.px + dx,
+            pz: self.pz + dz,
+        }
+    }
+
+    /// Return true if this far tile must be loaded for a player in the far tile `player_tile`
+    pub fn is_close_to(self, player_tile: FarTilePos) -> bool {
+        (self.px - player_tile.px).abs() <= FAR_TERRAIN_DISTANCE
+            && (self.pz - player_tile.pz).abs() <= FAR_TERRAIN_DISTANCE
+    }
+
+    /// Position of the block with the minimum X and Z coordinates of the far tile, at height 0
+    pub fn min_block_pos(self) -> BlockPos {
+        let size = (FAR_TILE_CHUNKS * CHUNK_SIZE) as i64;
+        BlockPos::from((self.px * size, 0, self.pz * size))
+    }
+}
+
+impl From<ChunkPosXZ> for FarTilePos {
+    fn from(chunk_pos: ChunkPosXZ) -> Self {
+        Self {
+            px: chunk_pos.px.div_euclid(FAR_TILE_CHUNKS as i64),
+            pz: chunk_pos.pz.div_euclid(FAR_TILE_CHUNKS as i64),
+        }
+    }
+}
+
+/// The low-resolution surface of a square region of the world,
+/// to render the terrain beyond the render distance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FarTile {
+    pub pos: FarTilePos,
+    /// The height of the surface at every sample, indexed by `x * (FAR_TILE_SAMPLES + 1) + z`.
+    /// The last row and column are the first samples of the next tiles, so that the tiles can be stitched.
+    pub heights: Vec<i32>,
+    /// The block at the surface of every sample, with the same indexing. Air if there is no surface.
+    pub blocks: Vec<BlockId>,
+}
+
+impl FarTile {
+    /// Create a far tile without any surface
+    pub fn empty(pos: FarTilePos) -> Self {
+        let samples = ((FAR_TILE_SAMPLES + 1) * (FAR_TILE_SAMPLES + 1)) as usize;
+        Self {
+            pos,
+            heights: vec![0; samples],
+            blocks: vec![0; samples],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_sections() {
+        let mut chunk = Chunk::new(ChunkPos::from((0, 0, 0)));
+        assert_eq!(chunk.uniform_block(), Some(0));
+        chunk.set_block_at((3, 20, 5), 7);
+        assert_eq!(chunk.get_block_at((3, 20, 5)), 7);
+        assert_eq!(chunk.get_block_at((3, 21, 5)), 0);
+        assert_eq!(chunk.uniform_block(), None);
+        assert!(matches!(chunk.sections[2], ChunkSection::Paletted { .. }));
+
+        // More blocks than a palette can hold
+        for i in 0..300 {
+            chunk.set_block_at((i % CHUNK_SIZE, 20, i / CHUNK_SIZE), i as BlockId + 1);
+        }
+        assert!(matches!(chunk.sections[2], ChunkSection::Full(_)));
+        assert_eq!(chunk.get_block_at((4, 20, 9)), 293);
+
+        // The chunk is the same after a round trip through the RLE compression
+        let decompressed = CompressedChunk::from_chunk(&chunk).to_chunk();
+        assert!(chunk.blocks().eq(decompressed.blocks()));
+
+        chunk.fill(2);
+        chunk.set_block_at((0, 0, 0), 1);
+        chunk.set_block_at((0, 0, 0), 2);
+        chunk.compact();
+        assert_eq!(chunk.uniform_block(), Some(2));
+    }
+}
@@ -0,0 +1,417 @@
+//! Access to the blocks of a world through a cache of the last accessed chunks.
+//! The physics and the raycasts read many blocks close to each other, so most of their reads hit the same few
+//! chunks, and the cache saves looking the chunk up in the world for every block.
+//! The writes go through a `WorldViewMut`, that copies each modified chunk once instead of once per block.
+use crate::block::{rail::RailShape, BlockId, BlockShape, HorizontalFace};
+use crate::physics::BlockContainer;
+use crate::world::{BlockPos, Chunk, ChunkPos, LightChunk};
+use std::cell::Cell;
+use std::collections::HashMap;
+
+/// Number of chunks remembered by a `WorldView`, enough for a player standing at the corner of 4 chunks
+const CACHE_SIZE: usize = 4;
+
+/// The loaded chunks of a world, either the client's World or the server's World
+pub trait ChunkSource {
+    /// Get a loaded chunk and its light, or `None` if the chunk is not loaded
+    fn get_loaded_chunk(&self, pos: ChunkPos) -> Option<(&Chunk, &LightChunk)>;
+
+    /// The shape of every block id
+    fn block_shapes(&self) -> &[BlockShape];
+}
+
+/// A world whose blocks can be changed through a `WorldViewMut`
+pub trait ChunkSourceMut: ChunkSource {
+    /// Replace a loaded chunk by a modified copy. The blocks at `changed` were changed, the blocks around them
+    /// must be updated.
+    fn replace_chunk(&mut self, chunk: Chunk, changed: &[BlockPos]);
+}
+
+/// A chunk position and the chunk at this position, if it is loaded
+type CachedChunk<'a> = (ChunkPos, Option<(&'a Chunk, &'a LightChunk)>);
+
+/// A view of a world that remembers the last accessed chunks, the unloaded ones included.
+/// It borrows the world, so it lives for a short time, for example one physics step or one raycast.
+pub struct WorldView<'a, S: ChunkSource> {
+    source: &'a S,
+    /// The last accessed chunks, the most recent first
+    cache: Cell<[Option<CachedChunk<'a>>; CACHE_SIZE]>,
+    /// Number of chunk accesses, and number of chunks that were looked up in the world because they were not cached
+    accesses: Cell<usize>,
+    lookups: Cell<usize>,
+}
+
+impl<'a, S: ChunkSource> WorldView<'a, S> {
+    pub fn new(source: &'a S) -> Self {
+        Self {
+            source,
+            cache: Cell::new([None; CACHE_SIZE]),
+            accesses: Cell::new(0),
+            lookups: Cell::new(0),
+        }
+    }
+
+    /// Get the chunk at some position from the cache, or from the world if it is not cached
+    fn get_chunk(&self, pos: ChunkPos) -> Option<(&'a Chunk, &'a LightChunk)> {
+        self.accesses.set(self.accesses.get() + 1);
+        let mut cache = self.cache.get();
+        let index = cache
+            .iter()
+            .position(|cached| matches!(cached, Some((cached_pos, _)) if *cached_pos == pos));
+        let cached = match index {
+            Some(index) => cache[index].unwrap(),
+            None => {
+                self.lookups.set(self.lookups.get() + 1);
+                (pos, self.source.get_loaded_chunk(pos))
+            }
+        };
+        // Move the chunk to the front, dropping the least recently used chunk if it was not cached
+        cache[..=index.unwrap_or(CACHE_SIZE - 1)].rotate_right(1);
+        cache[0] = Some(cached);
+        self.cache.set(cache);
+        cached.1
+    }
+
+    /// Get the block at some position, or air if its chunk is not loaded
+    pub fn get_block(&self, pos: BlockPos) -> BlockId {
+        match self.get_chunk(pos.containing_chunk_pos()) {
+            None => 0,
+            Some((chunk, _)) => chunk.get_block_at(pos.pos_in_containing_chunk()),
+        }
+    }
+
+    /// Get the shape of the block at some position
+    pub fn get_shape(&self, pos: BlockPos) -> BlockShape {
+        self.source.block_shapes()[self.get_block(pos) as usize]
+    }
+
+    /// Check if the block at some position is a full block, that the players collide with
+    pub fn is_solid(&self, pos: BlockPos) -> bool {
+        self.get_shape(pos) == BlockShape::Full
+    }
+
+    /// Get the light level at some position, 0 if its chunk is not loaded
+    pub fn light_at(&self, pos: BlockPos) -> u8 {
+        match self.get_chunk(pos.containing_chunk_pos()) {
+            None => 0,
+            Some((_, light_chunk)) => light_chunk.get_light_at(pos.pos_in_containing_chunk()),
+        }
+    }
+
+    /// Number of chunk accesses since the view was created, and number of them that had to look the chunk up
+    /// in the world
+    pub fn lookup_stats(&self) -> (usize, usize) {
+        (self.accesses.get(), self.lookups.get())
+    }
+}
+
+/// A view of a world that changes its blocks. Each modified chunk is copied once, and the copies are written back
+/// to the world with the updates of the changed blocks when the view is dropped.
+pub struct WorldViewMut<'a, S: ChunkSourceMut> {
+    source: &'a mut S,
+    /// The copies of the modified chunks, with the positions of their changed blocks
+    modified: HashMap<ChunkPos, (Chunk, Vec<BlockPos>)>,
+}
+
+impl<'a, S: ChunkSourceMut> WorldViewMut<'a, S> {
+    pub fn new(source: &'a mut S) -> Self {
+        Self {
+            source,
+            modified: HashMap::new(),
+        }
+    }
+
+    /// Get the block at some position with the changes of the view, or air if its chunk is not loaded
+    pub fn get_block(&self, pos: BlockPos) -> BlockId {
+        let chunk_pos = pos.containing_chunk_pos();
+        let chunk = match self.modified.get(&chunk_pos) {
+            Some((chunk, _)) => Some(chunk),
+            None => self
+                .source
+                .get_loaded_chunk(chunk_pos)
+                .map(|(chunk, _)| chunk),
+        };
+        chunk.map_or(0, |chunk| chunk.get_block_at(pos.pos_in_containing_chunk()))
+    }
+
+    /// Set the block at some position, and queue the update of the blocks around it if it changed.
+    /// Return the previous block, or `None` if the chunk of the block is not loaded.
+    pub fn set_block_with_update(&mut self, pos: BlockPos, block: BlockId) -> Option<BlockId> {
+        let chunk_pos = pos.containing_chunk_pos();
+        if !self.modified.contains_key(&chunk_pos) {
+            let (chunk, _) = self.source.get_loaded_chunk(chunk_pos)?;
+            self.modified.insert(chunk_pos, (chunk.clone(), Vec::new()));
+        }
+        let (chunk, changed) = self.modified.get_mut(&chunk_pos).unwrap();
+        let pos_in_chunk = pos.pos_in_containing_chunk();
+        let previous_block = chunk.get_block_at(pos_in_chunk);
+        if previous_block != block {
+            chunk.set_block_at(pos_in_chunk, block);
+            changed.push(pos);
+        }
+        Some(previous_block)
+    }
+}
+
+impl<'a, S: ChunkSourceMut> Drop for WorldViewMut<'a, S> {
+    fn drop(&mut self) {
+        for (_, (chunk, changed)) in self.modified.drain() {
+            if !changed.is_empty() {
+                self.source.replace_chunk(chunk, &changed);
+            }
+        }
+    }
+}
+
+impl<'a, S: ChunkSource> BlockContainer for WorldView<'a, S> {
+    fn is_block_full(&self, pos: BlockPos) -> bool {
+        self.is_solid(pos)
+    }
+
+    fn get_ladder(&self, pos: BlockPos) -> Option<HorizontalFace> {
+        match self.get_shape(pos) {
+            BlockShape::Ladder(attached_to) => Some(attached_to),
+            _ => None,
+        }
+    }
+
+    fn is_block_fluid(&self, pos: BlockPos) -> bool {
+        self.get_shape(pos) == BlockShape::Fluid
+    }
+
+    fn get_layers(&self, pos: BlockPos) -> Option<u8> {
+        match self.get_shape(pos) {
+            BlockShape::Layer(layers) => Some(layers),
+            _ => None,
+        }
+    }
+
+    fn is_block_plant(&self, pos: BlockPos) -> bool {
+        self.get_shape(pos) == BlockShape::Plant
+    }
+
+    fn is_block_portal(&self, pos: BlockPos) -> bool {
+        self.get_shape(pos) == BlockShape::Portal
+    }
+
+    fn get_rail(&self, pos: BlockPos) -> Option<RailShape> {
+        match self.get_shape(pos) {
+            BlockShape::Rail(shape) => Some(shape),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::player::PhysicsPlayer;
+    use crate::world::CHUNK_SIZE;
+    use nalgebra::Vector3;
+    use std::time::Instant;
+
+    /// A floor of stone at y = -1, in the chunks around the origin, that counts how many chunks are looked up
+    struct Floor {
+        chunks: HashMap<ChunkPos, (Chunk, LightChunk)>,
+        lookups: Cell<usize>,
+        /// The blocks changed through a `WorldViewMut`, and number of replaced chunks
+        block_updates: Vec<BlockPos>,
+        replaced_chunks: usize,
+    }
+
+    impl Floor {
+        fn new() -> Self {
+            let mut chunks = HashMap::new();
+            for px in -1..=0 {
+                for pz in -1..=0 {
+                    let pos = ChunkPos { px, py: -1, pz };
+                    let mut chunk = Chunk::new(pos);
+                    let top = CHUNK_SIZE - 1;
+                    for x in 0..CHUNK_SIZE {
+                        for z in 0..CHUNK_SIZE {
+                            chunk.set_block_at((x, top, z), 1);
+                        }
+                    }
+                    chunks.insert(pos, (chunk, LightChunk::new(pos)));
+                }
+            }
+            Self {
+                chunks,
+                lookups: Cell::new(0),
+                block_updates: Vec::new(),
+                replaced_chunks: 0,
+            }
+        }
+    }
+
+    impl ChunkSource for Floor {
+        fn get_loaded_chunk(&self, pos: ChunkPos) -> Option<(&Chunk, &LightChunk)> {
+            self.lookups.set(self.lookups.get() + 1);
+            self.chunks
+                .get(&pos)
+                .map(|(chunk, light_chunk)| (chunk, light_chunk))
+        }
+
+        fn block_shapes(&self) -> &[BlockShape] {
+            &[BlockShape::Empty, BlockShape::Full]
+        }
+    }
+
+    impl ChunkSourceMut for Floor {
+        fn replace_chunk(&mut self, chunk: Chunk, changed: &[BlockPos]) {
+            self.chunks.get_mut(&chunk.pos).unwrap().0 = chunk;
+            self.block_updates.extend_from_slice(changed);
+            self.replaced_chunks += 1;
+        }
+    }
+
+    #[test]
+    fn test_world_view() {
+        let floor = Floor::new();
+        let view = WorldView::new(&floor);
+        assert!(view.is_solid(BlockPos::from((3, -1, 3))));
+        assert!(!view.is_solid(BlockPos::from((3, 0, 3))));
+        assert!(view.is_solid(BlockPos::from((-3, -1, -3))));
+        // Unloaded chunks are air, and are cached too
+        assert_eq!(view.get_block(BlockPos::from((100, -1, 0))), 0);
+        assert_eq!(view.get_block(BlockPos::from((100, -1, 0))), 0);
+        assert_eq!(view.light_at(BlockPos::from((3, -5, 3))), 15);
+        assert_eq!(view.light_at(BlockPos::from((3, 0, 3))), 0);
+        assert_eq!(view.lookup_stats(), (7, 4));
+        assert_eq!(floor.lookups.get(), 4);
+    }
+
+    #[test]
+    fn test_world_view_lookup_savings() {
+        // A long raycast down to the floor looks every chunk it crosses up only once
+        let floor = Floor::new();
+        let player = PhysicsPlayer::default();
+        let view = WorldView::new(&floor);
+        let dir = Vector3::new(0.3, -1.0, 0.2);
+        let direct = player.get_pointed_at(dir, 200.0, &DirectAccess(&floor));
+        let direct_lookups = floor.lookups.replace(0);
+        assert_eq!(player.get_pointed_at(dir, 200.0, &view), direct);
+        let (accesses, lookups) = view.lookup_stats();
+        assert_eq!(lookups, floor.lookups.get());
+        assert_eq!(accesses, direct_lookups);
+        assert!(lookups * 10 < direct_lookups);
+    }
+
+    #[test]
+    fn test_set_block_with_update() {
+        let mut floor = Floor::new();
+        {
+            let mut view = WorldViewMut::new(&mut floor);
+            // A wall under the floor across two chunks, and a block that doesn't change
+            for x in -4..4 {
+                assert_eq!(
+                    view.set_block_with_update(BlockPos::from((x, -2, 2)), 1),
+                    Some(0)
+                );
+            }
+            assert_eq!(
+                view.set_block_with_update(BlockPos::from((3, -1, 2)), 1),
+                Some(1)
+            );
+            assert_eq!(view.get_block(BlockPos::from((-4, -2, 2))), 1);
+            // Unloaded chunks are not changed
+            assert_eq!(
+                view.set_block_with_update(BlockPos::from((100, 0, 0)), 1),
+                None
+            );
+        }
+        assert_eq!(floor.replaced_chunks, 2);
+        assert_eq!(floor.block_updates.len(), 8);
+        let view = WorldView::new(&floor);
+        assert!(view.is_solid(BlockPos::from((-4, -2, 2))));
+        assert!(view.is_solid(BlockPos::from((3, -2, 2))));
+    }
+
+    /// Time the block accesses with and without the caches, run with `cargo test -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_world_view() {
+        let floor = Floor::new();
+        let player = PhysicsPlayer::default();
+        let directions: Vec<_> = (0..1000)
+            .map(|i| {
+                let angle = i as f64 * 0.01;
+                Vector3::new(angle.cos(), -0.5, angle.sin())
+            })
+            .collect();
+        let start = Instant::now();
+        for &dir in &directions {
+            player.get_pointed_at(dir, 50.0, &DirectAccess(&floor));
+        }
+        let direct = start.elapsed();
+        let start = Instant::now();
+        for &dir in &directions {
+            player.get_pointed_at(dir, 50.0, &WorldView::new(&floor));
+        }
+        let cached = start.elapsed();
+        println!(
+            "Raycasts: {:?}/ray without the cache, {:?}/ray with the cache",
+            direct / directions.len() as u32,
+            cached / directions.len() as u32
+        );
+
+        // Fill the layer under the floor, one chunk copy per block or one per chunk
+        let mut floor = Floor::new();
+        let positions: Vec<_> = (-(CHUNK_SIZE as i64)..CHUNK_SIZE as i64)
+            .flat_map(|x| (-(CHUNK_SIZE as i64)..CHUNK_SIZE as i64).map(move |z| (x, -2, z)))
+            .map(BlockPos::from)
+            .collect();
+        let start = Instant::now();
+        for &pos in &positions {
+            WorldViewMut::new(&mut floor).set_block_with_update(pos, 1);
+        }
+        let per_block = start.elapsed();
+        let mut floor = Floor::new();
+        let start = Instant::now();
+        {
+            let mut view = WorldViewMut::new(&mut floor);
+            for &pos in &positions {
+                view.set_block_with_update(pos, 1);
+            }
+        }
+        let batched = start.elapsed();
+        println!(
+            "Block changes: {:?}/block with a view per block, {:?}/block with one view",
+            per_block / positions.len() as u32,
+            batched / positions.len() as u32
+        );
+    }
+
+    /// Reads a `ChunkSource` without a cache, looking the chunk up for every block
+    struct DirectAccess<'a>(&'a Floor);
+
+    impl<'a> BlockContainer for DirectAccess<'a> {
+        fn is_block_full(&self, pos: BlockPos) -> bool {
+            WorldView::new(self.0).is_block_full(pos)
+        }
+
+        fn get_ladder(&self, pos: BlockPos) -> Option<HorizontalFace> {
+            WorldView::new(self.0).get_ladder(pos)
+        }
+
+        fn is_block_fluid(&self, pos: BlockPos) -> bool {
+            WorldView::new(self.0).is_block_fluid(pos)
+        }
+
+        fn get_layers(&self, pos: BlockPos) -> Option<u8> {
+            WorldView::new(self.0).get_layers(pos)
+        }
+
+        fn is_block_plant(&self, pos: BlockPos) -> bool {
+            WorldView::new(self.0).is_block_plant(pos)
+        }
+
+        fn is_block_portal(&self, pos: BlockPos) -> bool {
+            WorldView::new(self.0).is_block_portal(pos)
+        }
+
+        fn get_rail(&self, pos: BlockPos) -> Option<RailShape> {
+            WorldView::new(self.0).get_rail(pos)
+        }
+    }
+}
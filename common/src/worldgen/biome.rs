@@ -0,0 +1,200 @@
+//! Biomes, which change the colors of the grass and of the foliage
+use crate::block::BiomeTint;
+use crate::world::{ChunkPosXZ, CHUNK_SIZE};
+use crate::worldgen::perlin;
+use serde::{Deserialize, Serialize};
+
+/// Number of biomes, the length of `Biome::ALL`
+pub const BIOME_COUNT: usize = 5;
+
+/// Offset of the seeds of the climate noises, after the seeds of the ground level noises
+const CLIMATE_SEED_OFFSET: i32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Biome {
+    Plains,
+    Forest,
+    Swamp,
+    Savanna,
+    Taiga,
+}
+
+/// A color that changes with the altitude: a list of altitudes and of the colors at these altitudes,
+/// sorted by altitude. The color is interpolated between the altitudes and constant below the first one
+/// and above the last one.
+pub struct ColorRamp(&'static [(i64, [u8; 3])]);
+
+impl ColorRamp {
+    /// Get the color at altitude `y`, as red, green and blue between 0 and 1
+    pub fn color_at(&self, y: i64) -> [f32; 3] {
+        let stops = self.0;
+        let to_f32 = |c: [u8; 3]| [c[0] as f32, c[1] as f32, c[2] as f32];
+        let color = match stops.iter().position(|&(altitude, _)| y < altitude) {
+            Some(0) => to_f32(stops[0].1),
+            None => to_f32(stops[stops.len() - 1].1),
+            Some(i) => {
+                let (y0, c0) = stops[i - 1];
+                let (y1, c1) = stops[i];
+                let t = (y - y0) as f32 / (y1 - y0) as f32;
+                let (c0, c1) = (to_f32(c0), to_f32(c1));
+                [
+                    c0[0] + (c1[0] - c0[0]) * t,
+                    c0[1] + (c1[1] - c0[1]) * t,
+                    c0[2] + (c1[2] - c0[2]) * t,
+                ]
+            }
+        };
+        [color[0] / 255.0, color[1] / 255.0, color[2] / 255.0]
+    }
+}
+
+// The colors are multiplied into the textures, which are already green: they only shift their hue.
+// Everything turns paler on the mountains.
+const PLAINS_GRASS: ColorRamp = ColorRamp(&[(0, [255, 255, 255]), (96, [220, 228, 215])]);
+const PLAINS_FOLIAGE: ColorRamp = ColorRamp(&[(0, [255, 255, 255]), (96, [215, 225, 215])]);
+const FOREST_GRASS: ColorRamp = ColorRamp(&[(0, [205, 235, 195]), (96, [200, 215, 200])]);
+const FOREST_FOLIAGE: ColorRamp = ColorRamp(&[(0, [185, 225, 175]), (96, [190, 210, 190])]);
+const SWAMP_GRASS: ColorRamp = ColorRamp(&[(0, [170, 185, 135]), (32, [185, 200, 155])]);
+const SWAMP_FOLIAGE: ColorRamp = ColorRamp(&[(0, [150, 170, 120]), (32, [170, 185, 140])]);
+const SAVANNA_GRASS: ColorRamp = ColorRamp(&[(0, [255, 235, 165]), (96, [235, 225, 185])]);
+const SAVANNA_FOLIAGE: ColorRamp = ColorRamp(&[(0, [245, 240, 170]), (96, [225, 225, 185])]);
+const TAIGA_GRASS: ColorRamp = ColorRamp(&[(0, [190, 220, 210]), (96, [215, 225, 225])]);
+const TAIGA_FOLIAGE: ColorRamp = ColorRamp(&[(0, [160, 200, 190]), (96, [195, 215, 215])]);
+
+impl Biome {
+    pub const ALL: [Biome; BIOME_COUNT] = [
+        Biome::Plains,
+        Biome::Forest,
+        Biome::Swamp,
+        Biome::Savanna,
+        Biome::Taiga,
+    ];
+
+    /// Get the biome of a climate, the temperature and the humidity being between 0 and 1
+    pub fn from_climate(temperature: f32, humidity: f32) -> Self {
+        if temperature < 0.42 {
+            Biome::Taiga
+        } else if temperature > 0.58 {
+            Biome::Savanna
+        } else if humidity < 0.45 {
+            Biome::Plains
+        } else if humidity > 0.58 {
+            Biome::Swamp
+        } else {
+            Biome::Forest
+        }
+    }
+
+    /// Check if the snow piles up in this biome
+    pub fn is_cold(self) -> bool {
+        self == Biome::Taiga
+    }
+
+    /// Get the index of the biome in `Biome::ALL`
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Get the color ramp of a kind of tint in this biome
+    pub fn ramp(self, tint: BiomeTint) -> &'static ColorRamp {
+        match (self, tint) {
+            (Biome::Plains, BiomeTint::Grass) => &PLAINS_GRASS,
+            (Biome::Plains, BiomeTint::Foliage) => &PLAINS_FOLIAGE,
+            (Biome::Forest, BiomeTint::Grass) => &FOREST_GRASS,
+            (Biome::Forest, BiomeTint::Foliage) => &FOREST_FOLIAGE,
+            (Biome::Swamp, BiomeTint::Grass) => &SWAMP_GRASS,
+            (Biome::Swamp, BiomeTint::Foliage) => &SWAMP_FOLIAGE,
+            (Biome::Savanna, BiomeTint::Grass) => &SAVANNA_GRASS,
+            (Biome::Savanna, BiomeTint::Foliage) => &SAVANNA_FOLIAGE,
+            (Biome::Taiga, BiomeTint::Grass) => &TAIGA_GRASS,
+            (Biome::Taiga, BiomeTint::Foliage) => &TAIGA_FOLIAGE,
+        }
+    }
+
+    /// Get the color multiplied into the faces with some tint at altitude `y`
+    pub fn tint(self, tint: BiomeTint, y: i64) -> [f32; 3] {
+        self.ramp(tint).color_at(y)
+    }
+}
+
+/// The biome of every block column of a chunk column
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BiomeMap {
+    pub pos: ChunkPosXZ,
+    /// The biome of the column `(x, z)` is at index `x * CHUNK_SIZE + z`
+    pub biomes: Vec<Biome>,
+}
+
+impl BiomeMap {
+    /// Create a biome map with the same biome everywhere
+    pub fn uniform(pos: ChunkPosXZ, biome: Biome) -> Self {
+        Self {
+            pos,
+            biomes: vec![biome; (CHUNK_SIZE * CHUNK_SIZE) as usize],
+        }
+    }
+
+    /// Get the biome of the block column `(x, z)` of the chunk column
+    #[inline(always)]
+    pub fn get(&self, x: u32, z: u32) -> Biome {
+        self.biomes[(x * CHUNK_SIZE + z) as usize]
+    }
+}
+
+/// Generate the biomes of a chunk column from a temperature and a humidity noise,
+/// `seed` is added to the seeds of the noises
+pub fn generate_biome_map(pos: ChunkPosXZ, seed: i32) -> BiomeMap {
+    let c = CHUNK_SIZE as f32;
+    let (px, pz) = (pos.px as f32 * c, pos.pz as f32 * c);
+    let climate_noise = |seed| {
+        perlin::perlin2d(
+            px,
+            pz,
+            CHUNK_SIZE as usize,
+            1.0 / 512.0,
+            1.0 / 512.0,
+            3,
+            0.4,
+            seed,
+        )
+    };
+    let temperature = climate_noise(seed + CLIMATE_SEED_OFFSET);
+    let humidity = climate_noise(seed + CLIMATE_SEED_OFFSET + 8);
+    BiomeMap {
+        pos,
+        biomes: temperature
+            .iter()
+            .zip(humidity.iter())
+            .map(|(&t, &h)| Biome::from_climate(t, h))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_ramp() {
+        let ramp = ColorRamp(&[(0, [0, 100, 255]), (10, [255, 200, 255])]);
+        assert_eq!(ramp.color_at(-5), [0.0, 100.0 / 255.0, 1.0]);
+        assert_eq!(ramp.color_at(5), [0.5, 150.0 / 255.0, 1.0]);
+        assert_eq!(ramp.color_at(20), [1.0, 200.0 / 255.0, 1.0]);
+    }
+
+    #[test]
+    fn test_biome_index() {
+        for (i, biome) in Biome::ALL.iter().enumerate() {
+            assert_eq!(biome.index(), i);
+        }
+    }
+
+    #[test]
+    fn test_generate_biome_map() {
+        let pos = ChunkPosXZ { px: 3, pz: -7 };
+        let map = generate_biome_map(pos, 128);
+        assert_eq!(map.biomes.len(), (CHUNK_SIZE * CHUNK_SIZE) as usize);
+        // The biomes only depend on the seed and on the position
+        assert_eq!(map, generate_biome_map(pos, 128));
+    }
+}
@@ -0,0 +1,186 @@
+//! Regression tests of the world generator: a few chunk columns of known seeds are generated and compared
+//! with the hashes recorded in `goldens.ron`, so that the accidental changes of the terrain are caught.
+//! After an intentional change of the world generator, record the new hashes with
+//! `UPDATE_WORLDGEN_GOLDENS=1 cargo test -p voxel-rs-common worldgen_goldens`.
+use super::{DefaultWorldGenerator, WorldgenBlockIds};
+use crate::block::{sound::SoundMaterial, Block, BlockType};
+use crate::content::{ContentPack, DefaultContent};
+use crate::registry::Registry;
+use crate::world::{ChunkPos, ChunkPosXZ, WorldGenerator, CHUNK_SIZE};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The seeds and the chunk columns that are generated
+const SEEDS: [u32; 3] = [0, 42, 999_999];
+const COLUMNS: [(i64, i64); 2] = [(0, 0), (-3, 7)];
+/// The chunk heights of every column, around the surface
+const MIN_CHUNK_Y: i64 = -2;
+const MAX_CHUNK_Y: i64 = 3;
+
+/// The recorded generation of a chunk column
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Golden {
+    seed: u32,
+    column: (i64, i64),
+    /// Hash of the blocks of the chunks and of the biomes
+    hash: u64,
+    /// Height of the highest block of every block column, at index `x * CHUNK_SIZE + z`,
+    /// to show where the terrain changed
+    heights: Vec<i64>,
+}
+
+fn goldens_file() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/worldgen/goldens.ron")
+}
+
+/// The blocks and the tags used by the world generator. The registry doesn't depend on the data folder,
+/// so that the hashes only change with the world generator.
+pub(super) fn block_registry() -> Registry<Block> {
+    let mut registry = Registry::default();
+    let names = [
+        "air",
+        "stone",
+        "grass",
+        "dirt",
+        "dirt_grass",
+        "water",
+        "sand",
+        "leaves",
+        "wood",
+    ];
+    for name in names.iter() {
+        let block = Block {
+            name: (*name).to_owned(),
+            block_type: BlockType::Air,
+            sound_material: SoundMaterial::FALLBACK,
+        };
+        registry.register((*name).to_owned(), block).unwrap();
+    }
+    for (tag, name) in [("tree_soil", "grass"), ("leaves", "leaves")].iter() {
+        let id = registry.get_id_by_name(&(*name).to_owned()).unwrap();
+        registry.add_to_tag((*tag).to_owned(), id);
+    }
+    registry
+}
+
+/// The blocks of the default content in `block_registry()`
+pub(super) fn worldgen_blocks(blocks: &Registry<Block>) -> WorldgenBlockIds {
+    WorldgenBlockIds::resolve(&DefaultContent.worldgen_blocks(), blocks).unwrap()
+}
+
+/// 64-bit FNV-1a, which unlike the hasher of the standard library is the same on every platform and version
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+fn generate(seed: u32, (px, pz): (i64, i64), blocks: &Registry<Block>) -> Golden {
+    let mut generator = DefaultWorldGenerator::new(blocks, worldgen_blocks(blocks), seed);
+    let mut hash = Fnv::new();
+    let mut heights = vec![i64::MIN; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+    for py in MIN_CHUNK_Y..=MAX_CHUNK_Y {
+        let chunk = generator.generate_chunk(ChunkPos { px, py, pz }, blocks);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let block = chunk.get_block_at((x, y, z));
+                    hash.write(&block.to_le_bytes());
+                    if block != 0 {
+                        let height = &mut heights[(x * CHUNK_SIZE + z) as usize];
+                        *height = (*height).max(py * CHUNK_SIZE as i64 + y as i64);
+                    }
+                }
+            }
+        }
+    }
+    let biome_map = generator.generate_biome_map(ChunkPosXZ { px, pz });
+    for biome in biome_map.biomes {
+        hash.write(&[biome.index() as u8]);
+    }
+    Golden {
+        seed,
+        column: (px, pz),
+        hash: hash.0,
+        heights,
+    }
+}
+
+/// Draw where the terrain of the new generation is higher (`+`) or lower (`-`) than the recorded one
+fn heightmap_diff(golden: &Golden, generated: &Golden) -> String {
+    let mut diff = String::new();
+    for z in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            let i = (x * CHUNK_SIZE + z) as usize;
+            diff.push(match generated.heights[i].cmp(&golden.heights[i]) {
+                std::cmp::Ordering::Less => '-',
+                std::cmp::Ordering::Equal => '.',
+                std::cmp::Ordering::Greater => '+',
+            });
+        }
+        diff.push('\n');
+    }
+    diff
+}
+
+#[test]
+fn worldgen_goldens() {
+    let blocks = block_registry();
+    let generated: Vec<_> = SEEDS
+        .iter()
+        .flat_map(|&seed| COLUMNS.iter().map(move |&column| (seed, column)))
+        .map(|(seed, column)| generate(seed, column, &blocks))
+        .collect();
+    // The generation doesn't depend on the generator instance
+    assert_eq!(generated[0], generate(SEEDS[0], COLUMNS[0], &blocks));
+
+    let path = goldens_file();
+    if std::env::var_os("UPDATE_WORLDGEN_GOLDENS").is_some() {
+        let string = ron::ser::to_string(&generated).unwrap();
+        std::fs::write(&path, string).unwrap();
+        println!("Recorded the worldgen goldens in {}", path.display());
+        return;
+    }
+    let goldens = match std::fs::read_to_string(&path) {
+        Ok(goldens) => goldens,
+        Err(e) => panic!(
+            "Couldn't read the worldgen goldens {}: {}\nRecord them with UPDATE_WORLDGEN_GOLDENS=1",
+            path.display(),
+            e
+        ),
+    };
+    let goldens: Vec<Golden> = ron::de::from_str(&goldens).unwrap();
+    let mut failures = Vec::new();
+    for generated in &generated {
+        match goldens
+            .iter()
+            .find(|g| g.seed == generated.seed && g.column == generated.column)
+        {
+            None => failures.push(format!(
+                "No golden for seed {} and column {:?}",
+                generated.seed, generated.column
+            )),
+            Some(golden) if golden.hash != generated.hash => failures.push(format!(
+                "Seed {}, column {:?}: the generation changed, height difference:\n{}",
+                generated.seed,
+                generated.column,
+                heightmap_diff(golden, generated)
+            )),
+            Some(_) => (),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\nIf the change is intentional, record the new goldens with UPDATE_WORLDGEN_GOLDENS=1",
+        failures.join("\n")
+    );
+}
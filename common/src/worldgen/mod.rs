@@ -1,24 +1,75 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::world::BlockPos;
-use crate::worldgen::perlin::rand_pos_int;
 use crate::{
     block::Block,
+    content::WorldgenBlocks,
     registry::Registry,
-    world::{Chunk, ChunkPos, WorldGenerator, CHUNK_SIZE},
+    world::{Chunk, ChunkPos, ChunkPosXZ, FarTile, FarTilePos, WorldGenerator, CHUNK_SIZE},
 };
 
 use crate::debug::send_debug_info;
+use crate::worldgen::biome::{generate_biome_map, BiomeMap};
 use crate::worldgen::decorator::Decorator;
 use crate::worldgen::decorator::DecoratorPass;
-use crate::worldgen::topology::{generate_chunk_topology, HeightMap};
+use crate::worldgen::rng::ChunkRng;
+use crate::worldgen::topology::{generate_chunk_topology, generate_far_tile_surface, HeightMap};
+use anyhow::{Context, Result};
 
+pub mod biome;
 pub mod perlin;
 #[macro_use]
 pub mod decorator;
+pub mod rng;
 pub mod topology;
 
+#[cfg(test)]
+mod goldens;
+
+/// Number of noise seeds reserved for every world seed, so that the worlds of different seeds don't share noises
+const NOISE_SEEDS_PER_WORLD: i32 = 128;
+/// The decoration pass of the trees, see `ChunkRng`
+const TREE_PASS: u32 = 0;
+
+/// The ids of the `WorldgenBlocks` of the content pack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldgenBlockIds {
+    pub stone: u16,
+    pub grass: u16,
+    pub dirt_grass: u16,
+    pub dirt: u16,
+    pub water: u16,
+    pub sand: u16,
+    pub leaves: u16,
+    pub wood: u16,
+}
+
+impl WorldgenBlockIds {
+    /// Find the ids of the blocks in `block_registry`, or fail if a block doesn't exist
+    pub fn resolve(blocks: &WorldgenBlocks, block_registry: &Registry<Block>) -> Result<Self> {
+        let id = |name: &String| -> Result<u16> {
+            block_registry
+                .get_id_by_name(name)
+                .map(|id| id as u16)
+                .with_context(|| format!("unknown world generation block {}", name))
+        };
+        Ok(Self {
+            stone: id(&blocks.stone)?,
+            grass: id(&blocks.grass)?,
+            dirt_grass: id(&blocks.dirt_grass)?,
+            dirt: id(&blocks.dirt)?,
+            water: id(&blocks.water)?,
+            sand: id(&blocks.sand)?,
+            leaves: id(&blocks.leaves)?,
+            wood: id(&blocks.wood)?,
+        })
+    }
+}
+
 pub struct DefaultWorldGenerator {
+    /// The offset added to the seeds of every noise
+    seed: i32,
+    blocks: WorldgenBlockIds,
     pregenerated_chunks: HashMap<ChunkPos, Chunk>,
     pregenerated_chunks_decorator_count: HashMap<ChunkPos, u32>,
     tree_decorator: Decorator,
@@ -40,14 +91,30 @@ impl BlockToPlace {
 }
 
 impl DefaultWorldGenerator {
-    pub fn new(block_registry: &Registry<Block>) -> Self {
-        let grass_block = block_registry.get_id_by_name(&"grass".to_owned()).unwrap() as u16;
-        let leaves_block = block_registry.get_id_by_name(&"leaves".to_owned()).unwrap() as u16;
-        let wood_block = block_registry.get_id_by_name(&"wood".to_owned()).unwrap() as u16;
+    /// Create the generator of the world with seed `world_seed`, which must not be larger than a few millions.
+    /// The tags of the trees are looked up in `block_registry`.
+    pub fn new(
+        block_registry: &Registry<Block>,
+        blocks: WorldgenBlockIds,
+        world_seed: u32,
+    ) -> Self {
+        let seed = world_seed as i32 * NOISE_SEEDS_PER_WORLD;
+        let leaves_block = blocks.leaves;
+        let wood_block = blocks.wood;
+        let tagged_blocks = |tag: &str| -> HashSet<u16> {
+            block_registry
+                .get_ids_by_tag(tag)
+                .unwrap_or(&[])
+                .iter()
+                .map(|&id| id as u16)
+                .collect()
+        };
 
         let mut pass_leaves = DecoratorPass::new(leaves_block);
         let mut pass_wood = DecoratorPass::new(wood_block);
+        // The trunk replaces the leaves, and the other blocks of the #leaves tag
         pass_wood.block_whitelist.insert(leaves_block);
+        pass_wood.block_whitelist.extend(tagged_blocks("leaves"));
 
         for jj in 1..8 {
             let nl;
@@ -76,26 +143,31 @@ impl DefaultWorldGenerator {
 
         let tree_decorator = Decorator {
             number_of_try: 32,
-            block_start_whitelist: set![grass_block],
+            // The trees grow on the blocks of the #tree_soil tag
+            block_start_whitelist: tagged_blocks("tree_soil"),
             pass: vec![pass_leaves, pass_wood],
         };
         Self {
+            seed,
+            blocks,
             tree_decorator,
             pregenerated_chunks_decorator_count: HashMap::new(),
             pregenerated_chunks: HashMap::new(),
-            height_map: HeightMap::new(),
+            height_map: HeightMap::new(seed),
         }
     }
 
-    fn pregenerate_chunk(
-        chunk: &mut Chunk,
-        block_registry: &Registry<Block>,
-        height_map: &mut HeightMap,
-    ) {
-        generate_chunk_topology(chunk, block_registry, height_map);
+    /// The random numbers of the decoration pass `salt` in the chunk at `pos`, the same whatever the order in
+    /// which the chunks are generated
+    pub fn chunk_rng(&self, pos: ChunkPos, salt: u32) -> ChunkRng {
+        ChunkRng::new(self.seed, pos, salt)
     }
 
-    fn decorate_chunk(chunks: &mut Vec<Chunk>, decorator: &Decorator) {
+    fn pregenerate_chunk(chunk: &mut Chunk, blocks: &WorldgenBlockIds, height_map: &mut HeightMap) {
+        generate_chunk_topology(chunk, blocks, height_map);
+    }
+
+    fn decorate_chunk(chunks: &mut Vec<Chunk>, decorator: &Decorator, seed: i32) {
         let min_x = chunks[0].pos.px * CHUNK_SIZE as i64;
         let max_x = (chunks[0].pos.px + 3) * CHUNK_SIZE as i64;
         let min_y = chunks[0].pos.py * CHUNK_SIZE as i64;
@@ -113,35 +185,18 @@ impl DefaultWorldGenerator {
         for i in -1..=1 {
             for j in -1..=1 {
                 for k in -1..=1 {
-                    for l in 0..decorator.number_of_try as i32 {
-                        let current_chunk = &chunks[((i + 1) * 9 + (j + 1) * 3 + (k + 1)) as usize];
+                    let current_chunk = &chunks[((i + 1) * 9 + (j + 1) * 3 + (k + 1)) as usize];
+                    // The tries of a chunk only depend on its position, not on the chunk being decorated
+                    let mut rng = ChunkRng::new(seed, current_chunk.pos, TREE_PASS);
+                    for _ in 0..decorator.number_of_try {
                         let cc_pos = current_chunk.pos;
                         let cbx = cc_pos.px * chunk_size_64;
                         let cby = cc_pos.py * chunk_size_64;
                         let cbz = cc_pos.pz * chunk_size_64;
 
-                        let mut tx = rand_pos_int(
-                            cc_pos.px as i32,
-                            cc_pos.py as i32,
-                            cc_pos.pz as i32,
-                            3 * l,
-                        ) as i64;
-                        let mut ty = rand_pos_int(
-                            cc_pos.px as i32,
-                            cc_pos.py as i32,
-                            cc_pos.pz as i32,
-                            3 * l + 1,
-                        ) as i64;
-                        let mut tz = rand_pos_int(
-                            cc_pos.px as i32,
-                            cc_pos.py as i32,
-                            cc_pos.pz as i32,
-                            3 * l + 2,
-                        ) as i64;
-
-                        tx = (tx % chunk_size_64 + chunk_size_64) % chunk_size_64;
-                        ty = (ty % chunk_size_64 + chunk_size_64) % chunk_size_64;
-                        tz = (tz % chunk_size_64 + chunk_size_64) % chunk_size_64;
+                        let mut tx = rng.below(CHUNK_SIZE) as i64;
+                        let mut ty = rng.below(CHUNK_SIZE) as i64;
+                        let mut tz = rng.below(CHUNK_SIZE) as i64;
 
                         if decorator.block_start_whitelist.contains(
                             &current_chunk.get_block_at((tx as u32, ty as u32, tz as u32)),
@@ -244,7 +299,7 @@ impl DefaultWorldGenerator {
 }
 
 impl WorldGenerator for DefaultWorldGenerator {
-    fn generate_chunk(&mut self, pos: ChunkPos, block_registry: &Registry<Block>) -> Chunk {
+    fn generate_chunk(&mut self, pos: ChunkPos, _block_registry: &Registry<Block>) -> Chunk {
         let mut chunks_vec = Vec::new();
         for i in -1..=1 {
             for j in -1..=1 {
@@ -256,7 +311,7 @@ impl WorldGenerator for DefaultWorldGenerator {
                                 let mut chunk = Chunk::new(pos.offset(i, j, k));
                                 DefaultWorldGenerator::pregenerate_chunk(
                                     &mut chunk,
-                                    &block_registry,
+                                    &self.blocks,
                                     &mut self.height_map,
                                 );
                                 chunk
@@ -270,7 +325,7 @@ impl WorldGenerator for DefaultWorldGenerator {
         let decorator = &self.tree_decorator;
         let chunk_center = chunks_vec[13].clone();
 
-        DefaultWorldGenerator::decorate_chunk(&mut chunks_vec, decorator);
+        DefaultWorldGenerator::decorate_chunk(&mut chunks_vec, decorator, self.seed);
 
         let chunk_res = std::mem::replace(&mut chunks_vec[13], chunk_center);
 
@@ -299,13 +354,24 @@ impl WorldGenerator for DefaultWorldGenerator {
 
         chunk_res
     }
+
+    fn generate_far_tile(&mut self, pos: FarTilePos, _block_registry: &Registry<Block>) -> FarTile {
+        generate_far_tile_surface(pos, &self.blocks, self.seed)
+    }
+
+    fn generate_biome_map(&mut self, pos: ChunkPosXZ) -> BiomeMap {
+        generate_biome_map(pos, self.seed)
+    }
 }
 
-pub struct DebugWorldGenerator;
+/// A flat world of `stone` blocks
+pub struct DebugWorldGenerator {
+    pub stone: u16,
+}
 
 impl WorldGenerator for DebugWorldGenerator {
-    fn generate_chunk(&mut self, pos: ChunkPos, block_registry: &Registry<Block>) -> Chunk {
-        let stone = block_registry.get_id_by_name(&"stone".to_owned()).unwrap() as u16;
+    fn generate_chunk(&mut self, pos: ChunkPos, _block_registry: &Registry<Block>) -> Chunk {
+        let stone = self.stone;
         let mut c = Chunk::new(pos);
         for i in 0..CHUNK_SIZE {
             for j in 0..CHUNK_SIZE {
@@ -319,3 +385,48 @@ impl WorldGenerator for DebugWorldGenerator {
         c
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generate the chunks at `positions` in this order with a new generator, and return their blocks by position
+    fn generate(positions: &[ChunkPos], blocks: &Registry<Block>) -> HashMap<ChunkPos, Vec<u16>> {
+        let mut generator =
+            DefaultWorldGenerator::new(blocks, goldens::worldgen_blocks(blocks), 42);
+        positions
+            .iter()
+            .map(|&pos| {
+                (
+                    pos,
+                    generator.generate_chunk(pos, blocks).blocks().collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_generation_order() {
+        let blocks = goldens::block_registry();
+        let mut positions = Vec::new();
+        for px in -1..=1 {
+            for py in -1..=2 {
+                for pz in 0..=1 {
+                    positions.push(ChunkPos { px, py, pz });
+                }
+            }
+        }
+        let in_order = generate(&positions, &blocks);
+        positions.reverse();
+        assert_eq!(generate(&positions, &blocks), in_order);
+        // Every other chunk first, as the worldgen workers could
+        let (even, odd): (Vec<_>, Vec<_>) =
+            positions.iter().enumerate().partition(|(i, _)| i % 2 == 0);
+        let interleaved = odd
+            .into_iter()
+            .chain(even)
+            .map(|(_, &pos)| pos)
+            .collect::<Vec<_>>();
+        assert_eq!(generate(&interleaved, &blocks), in_order);
+    }
+}
@@ -0,0 +1,81 @@
+//! The random numbers of the decoration passes. Every chunk has its own stream of random numbers, that only
+//! depends on the seed of the world, the position of the chunk and the pass, so the decorations don't depend
+//! on the order in which the chunks are generated or on the thread that generates them.
+use crate::world::ChunkPos;
+use crate::worldgen::perlin::hash;
+
+/// A stream of random numbers for one chunk and one decoration pass
+#[derive(Debug, Clone)]
+pub struct ChunkRng {
+    pos: (i32, i32, i32),
+    /// The noise seed of the world, mixed with the pass
+    seed: i32,
+    /// Number of values already drawn
+    counter: i32,
+}
+
+impl ChunkRng {
+    /// Create the stream of the chunk at `pos` for the decoration pass `salt`, in the world whose noises are
+    /// offset by `seed`. Every pass should use its own salt so that the passes don't place their features at
+    /// the same positions.
+    pub fn new(seed: i32, pos: ChunkPos, salt: u32) -> Self {
+        Self {
+            pos: (pos.px as i32, pos.py as i32, pos.pz as i32),
+            // `hash(0) == 0`, the pass 0 uses the seed of the world as it is
+            seed: seed.wrapping_add(hash(salt as i32)),
+            counter: 0,
+        }
+    }
+
+    /// The next random integer, uniform over all the `i32`
+    pub fn next_i32(&mut self) -> i32 {
+        let (x, y, z) = self.pos;
+        let a = hash(x.wrapping_add(self.seed.wrapping_add(self.counter)));
+        let b = hash(y.wrapping_add(a));
+        self.counter = self.counter.wrapping_add(1);
+        hash(z.wrapping_add(b))
+    }
+
+    /// The next random integer in `[0, bound)`, `bound` must be positive
+    pub fn below(&mut self, bound: u32) -> u32 {
+        self.next_i32().rem_euclid(bound as i32) as u32
+    }
+
+    /// The next random number in `[0, 1)`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_i32() as u32 >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worldgen::perlin::rand_pos_int;
+
+    #[test]
+    fn test_chunk_rng() {
+        let pos = ChunkPos {
+            px: -3,
+            py: 2,
+            pz: 7,
+        };
+        let values = |seed, pos, salt| {
+            let mut rng = ChunkRng::new(seed, pos, salt);
+            (0..16).map(|_| rng.next_i32()).collect::<Vec<_>>()
+        };
+        // The stream only depends on the seed, the position and the pass
+        assert_eq!(values(128, pos, 0), values(128, pos, 0));
+        assert_ne!(values(128, pos, 0), values(256, pos, 0));
+        assert_ne!(values(128, pos, 0), values(128, pos, 1));
+        assert_ne!(values(128, pos, 0), values(128, pos.offset(1, 0, 0), 0));
+        // The pass 0 draws the same values as the position noise
+        assert_eq!(values(128, pos, 0)[5], rand_pos_int(-3, 2, 7, 128 + 5));
+
+        let mut rng = ChunkRng::new(0, pos, 2);
+        for _ in 0..1000 {
+            assert!(rng.below(32) < 32);
+            let value = rng.next_f32();
+            assert!(value >= 0.0 && value < 1.0);
+        }
+    }
+}
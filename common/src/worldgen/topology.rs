@@ -1,17 +1,21 @@
-use crate::block::Block;
-use crate::registry::Registry;
-use crate::world::{Chunk, ChunkPosXZ, CHUNK_SIZE};
+use crate::world::{
+    Chunk, ChunkPosXZ, FarTile, FarTilePos, CHUNK_SIZE, FAR_TILE_CHUNKS, FAR_TILE_SAMPLES,
+    FAR_TILE_STEP,
+};
 use crate::worldgen::perlin;
+use crate::worldgen::WorldgenBlockIds;
 use std::collections::HashMap;
 
 pub struct HeightMap {
     height_map: HashMap<ChunkPosXZ, Vec<i32>>,
+    seed: i32,
 }
 
 impl HeightMap {
-    pub fn new() -> Self {
+    pub fn new(seed: i32) -> Self {
         return Self {
             height_map: HashMap::new(),
+            seed,
         };
     }
 
@@ -19,7 +23,7 @@ impl HeightMap {
         if !self.height_map.contains_key(&pos) {
             let mut res = vec![-1; (CHUNK_SIZE * CHUNK_SIZE) as usize];
             let c = CHUNK_SIZE as f32;
-            let s = generate_ground_level((pos.px as f32) * c, (pos.pz as f32) * c);
+            let s = generate_ground_level((pos.px as f32) * c, (pos.pz as f32) * c, self.seed);
             for i in 0..(CHUNK_SIZE * CHUNK_SIZE) as usize {
                 res[i] = s[i] as i32;
             }
@@ -29,7 +33,8 @@ impl HeightMap {
     }
 }
 
-pub fn generate_ground_level(px: f32, pz: f32) -> Vec<f32> {
+/// Generate the ground level of a chunk column, `seed` is added to the seeds of the noises
+pub fn generate_ground_level(px: f32, pz: f32, seed: i32) -> Vec<f32> {
     let mut res = vec![0.0; (CHUNK_SIZE * CHUNK_SIZE) as usize];
 
     let dx1 = perlin::perlin2d(
@@ -40,7 +45,7 @@ pub fn generate_ground_level(px: f32, pz: f32) -> Vec<f32> {
         1.0 / 64.0,
         5,
         0.5,
-        0,
+        seed + 0,
     );
     let dy1 = perlin::perlin2d(
         px,
@@ -50,7 +55,7 @@ pub fn generate_ground_level(px: f32, pz: f32) -> Vec<f32> {
         1.0 / 64.0,
         5,
         0.5,
-        1,
+        seed + 1,
     );
 
     let noise1 = perlin::perlin2d_with_displacement(
@@ -64,7 +69,7 @@ pub fn generate_ground_level(px: f32, pz: f32) -> Vec<f32> {
         1.0 / 128.0,
         5,
         0.4,
-        2,
+        seed + 2,
     );
     let noise2 = perlin::perlin2d(
         px,
@@ -74,7 +79,7 @@ pub fn generate_ground_level(px: f32, pz: f32) -> Vec<f32> {
         1.0 / 256.0,
         5,
         0.3,
-        3,
+        seed + 3,
     );
 
     for i in 0..(CHUNK_SIZE * CHUNK_SIZE) as usize {
@@ -92,17 +97,15 @@ pub fn generate_ground_level(px: f32, pz: f32) -> Vec<f32> {
 /// Generate the topology of the chunk
 pub fn generate_chunk_topology(
     chunk: &mut Chunk,
-    block_registry: &Registry<Block>,
+    blocks: &WorldgenBlockIds,
     height_map: &mut HeightMap,
 ) {
-    let stone_block = block_registry.get_id_by_name(&"stone".to_owned()).unwrap() as u16;
-    let grass_block = block_registry.get_id_by_name(&"grass".to_owned()).unwrap() as u16;
-    let dirt_block = block_registry.get_id_by_name(&"dirt".to_owned()).unwrap() as u16;
-    let dirt_grass = block_registry
-        .get_id_by_name(&"dirt_grass".to_owned())
-        .unwrap() as u16;
-    let water_block = block_registry.get_id_by_name(&"water".to_owned()).unwrap() as u16;
-    let sand_block = block_registry.get_id_by_name(&"sand".to_owned()).unwrap() as u16;
+    let stone_block = blocks.stone;
+    let grass_block = blocks.grass;
+    let dirt_block = blocks.dirt;
+    let dirt_grass = blocks.dirt_grass;
+    let water_block = blocks.water;
+    let sand_block = blocks.sand;
 
     let h = height_map.get_chunk_height_map(chunk.pos.into());
 
@@ -154,3 +157,45 @@ pub fn generate_chunk_topology(
         }
     }
 }
+
+/// Generate the surface of a far tile, without the decorations.
+/// Uses the same ground level and surface blocks as `generate_chunk_topology`.
+pub fn generate_far_tile_surface(pos: FarTilePos, blocks: &WorldgenBlockIds, seed: i32) -> FarTile {
+    let grass_block = blocks.grass;
+    let water_block = blocks.water;
+    let sand_block = blocks.sand;
+
+    let mut tile = FarTile::empty(pos);
+    let samples = FAR_TILE_SAMPLES + 1;
+    let samples_per_chunk = CHUNK_SIZE / FAR_TILE_STEP;
+    let c = CHUNK_SIZE as f32;
+    // The last samples are in the first chunk column of the next tiles
+    for ci in 0..=FAR_TILE_CHUNKS {
+        for ck in 0..=FAR_TILE_CHUNKS {
+            let chunk_x = pos.px * FAR_TILE_CHUNKS as i64 + ci as i64;
+            let chunk_z = pos.pz * FAR_TILE_CHUNKS as i64 + ck as i64;
+            let ground_level = generate_ground_level(chunk_x as f32 * c, chunk_z as f32 * c, seed);
+            for si in 0..samples_per_chunk {
+                for sk in 0..samples_per_chunk {
+                    let (i, k) = (ci * samples_per_chunk + si, ck * samples_per_chunk + sk);
+                    if i >= samples || k >= samples {
+                        continue;
+                    }
+                    let hm = ground_level
+                        [(si * FAR_TILE_STEP * CHUNK_SIZE + sk * FAR_TILE_STEP) as usize]
+                        as i32;
+                    let (height, block) = if hm < 0 {
+                        (-1, water_block)
+                    } else if hm >= 1 {
+                        (hm, grass_block)
+                    } else {
+                        (hm, sand_block)
+                    };
+                    tile.heights[(i * samples + k) as usize] = height;
+                    tile.blocks[(i * samples + k) as usize] = block;
+                }
+            }
+        }
+    }
+    tile
+}
@@ -76,10 +76,11 @@ impl Sender {
                 false
             } else {
                 let idx = packet.sequence - first_sequence;
+                // Keep the packets that were not acked
                 if idx as usize >= acks.len() {
                     true
                 } else {
-                    acks[idx as usize]
+                    !acks[idx as usize]
                 }
             }
         });
@@ -131,10 +132,8 @@ impl Receiver {
             set.push(self.received_sequences[idx] >= seq && self.received[idx].is_some());
         }
         // Remove final 0s
-        while let Some(last_bit) = set.iter().by_val().last() {
-            if !last_bit {
-                set.pop().unwrap();
-            }
+        while let Some(false) = set.iter().by_val().last() {
+            set.pop();
         }
         (seq, set)
     }
@@ -1,6 +1,6 @@
 use super::channel::{Receiver, Sender};
 use super::packet::{deserialize_packet, serialize_packet};
-use super::socket::{Socket, SocketAddr};
+use super::socket::{receive_packet, send_packet, Socket, SocketAddr};
 use super::types::*;
 use std::time::Instant;
 
@@ -67,10 +67,19 @@ impl<S: Socket> Client<S> {
         }
     }
 
+    /// Get the reason of the disconnection, if the client is disconnected
+    pub fn disconnect_message(&self) -> Option<&str> {
+        if let Status::Disconnected { message } = &self.status {
+            Some(message)
+        } else {
+            None
+        }
+    }
+
     pub fn read(&mut self) {
         while let Some((packet_size, src)) = {
             self.buf.resize(MAX_PACKET_SIZE, 0);
-            self.socket.receive(&mut self.buf)
+            receive_packet(&mut self.socket, &mut self.buf)
         } {
             if src != self.server_addr {
                 continue;
@@ -182,7 +191,7 @@ impl<S: Socket> Client<S> {
                 };
                 serialize_packet(&mut self.buf, &connect_packet)
                     .expect("Failed to serialize TryConnect packet");
-                self.socket.send(&mut self.buf, self.server_addr);
+                send_packet(&mut self.socket, &self.buf, self.server_addr);
             }
             Status::ChallengeResponseSent { salts_xor, time } => {
                 // Timeout
@@ -199,7 +208,7 @@ impl<S: Socket> Client<S> {
                 };
                 serialize_packet(&mut self.buf, &connect_packet)
                     .expect("Failed to serialize ChallengeResponse packet");
-                self.socket.send(&mut self.buf, self.server_addr);
+                send_packet(&mut self.socket, &self.buf, self.server_addr);
             }
             Status::Connected {
                 last_server_packet,
@@ -242,7 +251,7 @@ impl<S: Socket> Client<S> {
                         // Send packet
                         serialize_packet(buf, &packet)
                             .expect("Failed to serialize packet to server");
-                        socket.send(buf, *server_addr);
+                        send_packet(socket, buf, *server_addr);
                         // Prepare next packet
                         packet_body.push(message);
                     } else {
@@ -273,7 +282,7 @@ impl<S: Socket> Client<S> {
                     };
                     serialize_packet(&mut self.buf, &packet)
                         .expect("Failed to serialize packet to server");
-                    self.socket.send(&mut self.buf, *server_addr);
+                    send_packet(&mut self.socket, &self.buf, *server_addr);
                 }
             }
             Status::Disconnected { .. } => {}
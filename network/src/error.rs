@@ -0,0 +1,25 @@
+use std::io;
+use thiserror::Error;
+
+/// An error of the network
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    /// The socket failed
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The socket sent only a part of a packet
+    #[error("only {sent} bytes of a packet of {size} bytes were sent")]
+    PartialSend { sent: usize, size: usize },
+    /// The content of a packet doesn't fit in `MAX_PACKET_SIZE`
+    #[error("the packet content is {size} bytes, more than the {max} bytes that fit in a packet")]
+    PacketTooLarge { size: usize, max: usize },
+    /// A received packet is shorter than its header
+    #[error("the packet is shorter than its header")]
+    PacketTooShort,
+    /// The checksum of a received packet doesn't match its content
+    #[error("the checksum of the packet is wrong")]
+    InvalidChecksum,
+    /// The content of a packet couldn't be encoded or decoded
+    #[error("couldn't encode or decode the packet")]
+    Encoding(#[from] bincode::Error),
+}
@@ -0,0 +1,192 @@
+//! Discovery of the worlds opened to the local network.
+//! A world is announced by broadcasting its name and its port every few seconds, and a listener lists the worlds
+//! whose announcement was received recently.
+use super::error::NetworkError;
+use super::packet::{deserialize_packet, serialize_packet};
+use super::socket::{receive_packet, Socket};
+use super::types::MAX_PACKET_SIZE;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// The port that the announcements are broadcast to
+pub const LAN_DISCOVERY_PORT: u16 = 4445;
+/// Time between two announcements of a world
+const ANNOUNCE_INTERVAL: Duration = Duration::from_millis(1500);
+/// A world is forgotten if it wasn't announced for this long
+const LAN_WORLD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The announcement of a world, broadcast to the local network
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LanAnnouncement {
+    /// The name of the world, shown to the other players
+    pub name: String,
+    /// The port of the server of the world, on the machine that announces it
+    pub port: u16,
+}
+
+/// Announce a world to the local network
+pub struct LanAdvertiser {
+    socket: UdpSocket,
+    announcement: Vec<u8>,
+    /// Where the announcements are sent, the broadcast address outside of the tests
+    target: SocketAddr,
+    last_announce: Option<Instant>,
+}
+
+impl LanAdvertiser {
+    pub fn new(announcement: &LanAnnouncement) -> Result<Self, NetworkError> {
+        Self::with_target(
+            announcement,
+            (Ipv4Addr::BROADCAST, LAN_DISCOVERY_PORT).into(),
+        )
+    }
+
+    fn with_target(
+        announcement: &LanAnnouncement,
+        target: SocketAddr,
+    ) -> Result<Self, NetworkError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        let mut buf = Vec::with_capacity(MAX_PACKET_SIZE);
+        serialize_packet(&mut buf, announcement)?;
+        Ok(Self {
+            socket,
+            announcement: buf,
+            target,
+            last_announce: None,
+        })
+    }
+
+    /// Broadcast the announcement if the last one is old enough
+    pub fn tick(&mut self) {
+        if self
+            .last_announce
+            .map_or(false, |time| time.elapsed() < ANNOUNCE_INTERVAL)
+        {
+            return;
+        }
+        self.last_announce = Some(Instant::now());
+        if let Err(e) = self.socket.send(&self.announcement, self.target) {
+            log::warn!("Failed to announce the world to the local network: {}", e);
+        }
+    }
+}
+
+/// List the worlds announced on the local network
+pub struct LanListener {
+    socket: UdpSocket,
+    buf: Vec<u8>,
+    /// The announced worlds, by server address, with the time of their last announcement
+    worlds: HashMap<SocketAddr, (LanAnnouncement, Instant)>,
+}
+
+impl LanListener {
+    pub fn new() -> Result<Self, NetworkError> {
+        Self::bind(LAN_DISCOVERY_PORT)
+    }
+
+    /// Listen to the announcements sent to another port than the discovery port
+    pub fn bind(port: u16) -> Result<Self, NetworkError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            buf: Vec::with_capacity(MAX_PACKET_SIZE),
+            worlds: HashMap::new(),
+        })
+    }
+
+    /// Receive the pending announcements and forget the worlds that are not announced anymore
+    pub fn poll(&mut self) {
+        loop {
+            self.buf.resize(MAX_PACKET_SIZE, 0);
+            let (size, source) = match receive_packet(&mut self.socket, &mut self.buf) {
+                Some(received) => received,
+                None => break,
+            };
+            match deserialize_packet::<LanAnnouncement>(&mut self.buf[0..size]) {
+                Ok(announcement) => {
+                    let address = SocketAddr::new(source.ip(), announcement.port);
+                    self.worlds.insert(address, (announcement, Instant::now()));
+                }
+                Err(e) => log::debug!("Invalid announcement from {}: {}", source, e),
+            }
+        }
+        self.worlds
+            .retain(|_, (_, time)| time.elapsed() < LAN_WORLD_TIMEOUT);
+    }
+
+    /// Get the port that the listener receives the announcements on
+    pub fn port(&self) -> Result<u16, NetworkError> {
+        Ok(self.socket.local_addr()?.port())
+    }
+
+    /// Get the announced worlds and the addresses of their servers
+    pub fn worlds(&self) -> impl Iterator<Item = (SocketAddr, &LanAnnouncement)> {
+        self.worlds
+            .iter()
+            .map(|(&address, (announcement, _))| (address, announcement))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Poll the listener until it lists a world, or give up after a while
+    fn poll_until_announced(listener: &mut LanListener) -> Vec<(SocketAddr, LanAnnouncement)> {
+        for _ in 0..100 {
+            listener.poll();
+            let worlds: Vec<_> = listener
+                .worlds()
+                .map(|(address, announcement)| (address, announcement.clone()))
+                .collect();
+            if !worlds.is_empty() {
+                return worlds;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        Vec::new()
+    }
+
+    #[test]
+    fn test_announcement_is_listed() {
+        let mut listener = LanListener::bind(0).unwrap();
+        let target = (Ipv4Addr::LOCALHOST, listener.port().unwrap()).into();
+        let announcement = LanAnnouncement {
+            name: "Test world".to_owned(),
+            port: 4242,
+        };
+        let mut advertiser = LanAdvertiser::with_target(&announcement, target).unwrap();
+        advertiser.tick();
+
+        let worlds = poll_until_announced(&mut listener);
+        assert_eq!(worlds.len(), 1);
+        assert_eq!(worlds[0].0.port(), 4242);
+        assert_eq!(worlds[0].1, announcement);
+    }
+
+    #[test]
+    fn test_invalid_packets_are_skipped() {
+        let mut listener = LanListener::bind(0).unwrap();
+        let target: SocketAddr = (Ipv4Addr::LOCALHOST, listener.port().unwrap()).into();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.send_to(&[1, 2, 3], target).unwrap();
+        socket.send_to(&[0; 64], target).unwrap();
+        let announcement = LanAnnouncement {
+            name: "After garbage".to_owned(),
+            port: 4343,
+        };
+        LanAdvertiser::with_target(&announcement, target)
+            .unwrap()
+            .tick();
+
+        let worlds = poll_until_announced(&mut listener);
+        assert_eq!(worlds.len(), 1);
+        assert_eq!(worlds[0].1, announcement);
+    }
+}
@@ -1,11 +1,15 @@
 mod channel;
 mod client;
+mod error;
+mod lan;
 mod packet;
 mod server;
 mod socket;
 mod types;
 
 pub use client::Client;
+pub use error::NetworkError;
+pub use lan::{LanAdvertiser, LanAnnouncement, LanListener, LAN_DISCOVERY_PORT};
 pub use server::{Server, ServerEvent};
 pub use socket::{Socket, SocketAddr};
 pub use types::MessageDelivery;
@@ -1,3 +1,4 @@
+use super::error::NetworkError;
 use super::types::*;
 use bincode::{DefaultOptions, Options};
 use crc::crc32;
@@ -9,11 +10,17 @@ lazy_static::lazy_static! {
     };
 }
 
-pub fn serialize_packet<P: Serialize>(target: &mut Vec<u8>, packet: &P) -> bincode::Result<()> {
+pub fn serialize_packet<P: Serialize>(
+    target: &mut Vec<u8>,
+    packet: &P,
+) -> Result<(), NetworkError> {
     // Resize the buffer
     let content_size = BINCODE_OPTIONS.serialized_size(packet)? as usize;
     if content_size > MAX_PACKET_CONTENT {
-        return Err(Box::new(bincode::ErrorKind::SizeLimit));
+        return Err(NetworkError::PacketTooLarge {
+            size: content_size,
+            max: MAX_PACKET_CONTENT,
+        });
     }
     target.resize(content_size as usize + HEADER_SIZE, 0);
     // Serialize the packet
@@ -30,15 +37,18 @@ pub fn serialize_packet<P: Serialize>(target: &mut Vec<u8>, packet: &P) -> binco
     Ok(())
 }
 
-pub fn deserialize_packet<P: DeserializeOwned>(source: &mut [u8]) -> bincode::Result<P> {
+pub fn deserialize_packet<P: DeserializeOwned>(source: &mut [u8]) -> Result<P, NetworkError> {
     // Check size
     let packet_size = source.len();
     if packet_size < HEADER_SIZE {
-        return Err(Box::new(bincode::ErrorKind::SizeLimit));
+        return Err(NetworkError::PacketTooShort);
     }
     let content_size = packet_size - HEADER_SIZE;
     if content_size > MAX_PACKET_CONTENT {
-        return Err(Box::new(bincode::ErrorKind::SizeLimit));
+        return Err(NetworkError::PacketTooLarge {
+            size: content_size,
+            max: MAX_PACKET_CONTENT,
+        });
     }
     // Check checksum
     let mut packet_checksum: [u8; 4] = Default::default();
@@ -52,11 +62,10 @@ pub fn deserialize_packet<P: DeserializeOwned>(source: &mut [u8]) -> bincode::Re
     }
     for i in 0..4 {
         if checksum[i] != packet_checksum[i] {
-            return Err(Box::new(bincode::ErrorKind::DeserializeAnyNotSupported));
-            // Actually, invalid checksum
+            return Err(NetworkError::InvalidChecksum);
         }
     }
-    BINCODE_OPTIONS.deserialize_from(&source[HEADER_SIZE..])
+    Ok(BINCODE_OPTIONS.deserialize_from(&source[HEADER_SIZE..])?)
 }
 
 #[test]
@@ -1,6 +1,6 @@
 use super::channel::{Receiver, Sender};
 use super::packet::{deserialize_packet, serialize_packet};
-use super::socket::{Socket, SocketAddr};
+use super::socket::{receive_packet, send_packet, Socket, SocketAddr};
 use super::types::*;
 use std::time::Instant;
 
@@ -65,13 +65,12 @@ impl<S: Socket> Server<S> {
     pub fn read(&mut self) {
         while let Some((packet_size, src)) = {
             self.buf.resize(MAX_PACKET_SIZE, 0);
-            self.socket.receive(&mut self.buf)
+            receive_packet(&mut self.socket, &mut self.buf)
         } {
             let packet = match deserialize_packet(&mut self.buf[0..packet_size]) {
                 Ok(packet) => packet,
                 Err(e) => {
-                    dbg!(&mut self.buf[0..packet_size]);
-                    dbg!(e);
+                    log::debug!("Invalid packet from {}: {}", src, e);
                     continue;
                 }
             };
@@ -220,7 +219,7 @@ impl<S: Socket> Server<S> {
                     };
                     serialize_packet(&mut self.buf, &challenge_packet)
                         .expect("Failed to serialize Challenge packet");
-                    self.socket.send(&mut self.buf, *remote);
+                    send_packet(&mut self.socket, &self.buf, *remote);
                 }
                 ClientSlot::Connected {
                     last_client_packet,
@@ -258,7 +257,7 @@ impl<S: Socket> Server<S> {
                             // Send packet
                             serialize_packet(buf, &packet)
                                 .expect("Failed to serialize packet to client");
-                            socket.send(buf, *remote);
+                            send_packet(socket, buf, *remote);
                             // Prepare next packet
                             packet_body.push(message);
                         } else {
@@ -289,7 +288,7 @@ impl<S: Socket> Server<S> {
                         };
                         serialize_packet(&mut self.buf, &packet)
                             .expect("Failed to serialize packet to client");
-                        self.socket.send(&mut self.buf, *remote);
+                        send_packet(&mut self.socket, &self.buf, *remote);
                     }
                 }
             }
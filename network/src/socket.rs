@@ -1,31 +1,52 @@
+use super::error::NetworkError;
+use std::io;
 pub use std::net::{SocketAddr, UdpSocket};
 
-// TODO: handle errors :-)
 pub trait Socket {
-    /// Receive a packet. Return the number of bytes read and the origin.
-    fn receive(&mut self, buf: &mut [u8]) -> Option<(usize, SocketAddr)>;
+    /// Receive a packet. Return the number of bytes read and the origin, or `None` if no packet is waiting.
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<(usize, SocketAddr)>, NetworkError>;
     /// Send a packet.
-    fn send(&mut self, buf: &[u8], addr: SocketAddr) -> Option<()>;
+    fn send(&mut self, buf: &[u8], addr: SocketAddr) -> Result<(), NetworkError>;
 }
 
 impl Socket for UdpSocket {
-    fn receive(&mut self, buf: &mut [u8]) -> Option<(usize, SocketAddr)> {
-        self.recv_from(buf).ok()
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<(usize, SocketAddr)>, NetworkError> {
+        match self.recv_from(buf) {
+            Ok(received) => Ok(Some(received)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+    fn send(&mut self, buf: &[u8], addr: SocketAddr) -> Result<(), NetworkError> {
+        let sent = self.send_to(buf, addr)?;
+        if sent == buf.len() {
+            Ok(())
+        } else {
+            Err(NetworkError::PartialSend {
+                sent,
+                size: buf.len(),
+            })
+        }
     }
-    fn send(&mut self, buf: &[u8], addr: SocketAddr) -> Option<()> {
-        let res = self.send_to(buf, addr);
-        match res {
-            Ok(bytes_read) => {
-                if buf.len() == bytes_read {
-                    Some(())
-                } else {
-                    None
-                }
-            }
-            Err(e) => {
-                log::warn!("Packet sending error: {:?}", e);
-                None
-            }
+}
+
+/// Receive the next packet. The errors are logged and skipped: they are about one packet, e.g. a connection
+/// reset on Windows when an ICMP message is received, and the next packets must still be read.
+pub(crate) fn receive_packet<S: Socket>(
+    socket: &mut S,
+    buf: &mut [u8],
+) -> Option<(usize, SocketAddr)> {
+    loop {
+        match socket.receive(buf) {
+            Ok(received) => return received,
+            Err(e) => log::debug!("Failed to receive a packet: {}", e),
         }
     }
 }
+
+/// Send a packet. A packet that can't be sent is logged, and is then lost like the packets lost by the network.
+pub(crate) fn send_packet<S: Socket>(socket: &mut S, buf: &[u8], addr: SocketAddr) {
+    if let Err(e) = socket.send(buf, addr) {
+        log::warn!("Failed to send a packet to {}: {}", addr, e);
+    }
+}
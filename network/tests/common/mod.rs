@@ -6,7 +6,7 @@ use rand::{
 use std::collections::{BinaryHeap, HashMap};
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
-use voxel_rs_network::Socket;
+use voxel_rs_network::{NetworkError, Socket};
 
 lazy_static::lazy_static! {
     static ref PACKET_QUEUES: Mutex<HashMap<SocketAddr, BinaryHeap<SentPacket>>> = Mutex::new(HashMap::new());
@@ -75,7 +75,7 @@ impl DummySocket {
 }
 
 impl Socket for DummySocket {
-    fn receive(&mut self, buf: &mut [u8]) -> Option<(usize, SocketAddr)> {
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<(usize, SocketAddr)>, NetworkError> {
         let mut map = PACKET_QUEUES.lock().unwrap();
         if let Some(packet_queue) = map.get_mut(&self.addr) {
             if let Some(next_message) = packet_queue.peek_mut() {
@@ -84,15 +84,15 @@ impl Socket for DummySocket {
                         let msg = std::collections::binary_heap::PeekMut::pop(next_message);
                         let data_len = msg.data.len();
                         buf[..data_len].copy_from_slice(&msg.data);
-                        Some((data_len, msg.sender))
+                        Ok(Some((data_len, msg.sender)))
                     };
                 }
             }
         }
-        None
+        Ok(None)
     }
 
-    fn send(&mut self, buf: &[u8], addr: SocketAddr) -> Option<()> {
+    fn send(&mut self, buf: &[u8], addr: SocketAddr) -> Result<(), NetworkError> {
         let mut rng = thread_rng();
         if self.packet_loss_dist.sample(&mut rng) >= self.packet_loss {
             PACKET_QUEUES
@@ -107,7 +107,7 @@ impl Socket for DummySocket {
                     data: Vec::from(buf),
                 });
         }
-        Some(())
+        Ok(())
     }
 }
 
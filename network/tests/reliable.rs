@@ -0,0 +1,66 @@
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+use voxel_rs_network::{Client, MessageDelivery, Server, ServerEvent, SocketAddr};
+
+mod common;
+use self::common::{DummySocket, DummySocketConfig};
+
+/// More messages than the reliable buffer can hold, so that the sender has to wait for the acks
+const MESSAGE_COUNT: u32 = 2500;
+
+// Server sends many ordered messages to the client through a lossy network, they must all arrive in order
+#[test]
+fn test_ordered_messages_with_loss() {
+    let config = DummySocketConfig {
+        packet_loss: 0.3,
+        latency: Duration::from_millis(10),
+        max_jitter: Duration::from_millis(20),
+    };
+    let sleep_duration = Duration::from_millis(5);
+    let client_addr = SocketAddr::from_str("127.0.0.1:44").unwrap();
+    let server_addr = SocketAddr::from_str("127.0.0.1:45").unwrap();
+    thread::spawn(move || {
+        let server_socket = DummySocket::new(server_addr, config);
+        let mut server = Server::new(server_socket);
+
+        loop {
+            server.tick();
+            let mut connected_id = None;
+            for event in server.get_events() {
+                if let ServerEvent::Connected { id } = event {
+                    connected_id = Some(id);
+                }
+            }
+            if let Some(id) = connected_id {
+                for i in 0..MESSAGE_COUNT {
+                    server.send_message(id, i.to_le_bytes().to_vec(), MessageDelivery::Ordered);
+                }
+            }
+            thread::sleep(sleep_duration);
+        }
+    });
+
+    let client_thread = thread::spawn(move || {
+        let client_socket = DummySocket::new(client_addr, config);
+        let mut client = Client::new(client_socket, server_addr);
+        client.connect();
+
+        let start = Instant::now();
+        let mut received = Vec::new();
+        while received.len() < MESSAGE_COUNT as usize && start.elapsed() < Duration::from_secs(60) {
+            client.tick();
+            for (delivery, data) in client.get_messages() {
+                assert!(matches!(delivery, MessageDelivery::Ordered));
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&data);
+                received.push(u32::from_le_bytes(bytes));
+            }
+            thread::sleep(sleep_duration);
+        }
+        received
+    });
+
+    let received = client_thread.join().unwrap();
+    assert_eq!(received, (0..MESSAGE_COUNT).collect::<Vec<_>>());
+}
@@ -0,0 +1,246 @@
+use crate::{Color, Position, Style};
+
+/// A curve mapping the progress of a transition to the progress of the animated value, both from 0.0 to 1.0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    /// Start slowly
+    EaseIn,
+    /// End slowly
+    EaseOut,
+    /// Start and end slowly
+    EaseInOut,
+}
+
+impl Easing {
+    /// Apply the curve to a progress between 0.0 and 1.0
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => t * (2.0 - t),
+            Self::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A style property that can be animated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimatedProperty {
+    Opacity,
+    Offset,
+    /// The background color, including the change to the hovered background
+    Background,
+}
+
+/// How a property moves to a new value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    /// Duration in seconds
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+/// The current values of the animated properties of a `Widget`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimatedStyle {
+    pub opacity: f32,
+    /// Offset from the computed layout, in logical pixels. The offset of the parents is already included in the layout.
+    pub offset: Position,
+    /// The background color, normal or hovered
+    pub background: Option<Color>,
+    /// Whether the cursor is over the widget
+    pub hovered: bool,
+}
+
+/// A value that can be interpolated
+trait Lerp: Copy + PartialEq {
+    fn lerp(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Lerp for Position {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        Position {
+            x: f32::lerp(from.x, to.x, t),
+            y: f32::lerp(from.y, to.y, t),
+        }
+    }
+}
+
+impl Lerp for Color {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        let mut color = from;
+        for i in 0..4 {
+            color[i] = f32::lerp(from[i], to[i], t);
+        }
+        color
+    }
+}
+
+/// A value moving towards its target
+#[derive(Debug, Clone, Copy)]
+struct Tween<T: Lerp> {
+    from: T,
+    to: T,
+    /// Seconds since the target changed
+    elapsed: f32,
+    transition: Option<Transition>,
+}
+
+impl<T: Lerp> Tween<T> {
+    fn new(value: T) -> Self {
+        Self {
+            from: value,
+            to: value,
+            elapsed: 0.0,
+            transition: None,
+        }
+    }
+
+    fn current(&self) -> T {
+        match self.transition {
+            Some(transition) if transition.duration > 0.0 => T::lerp(
+                self.from,
+                self.to,
+                transition.easing.apply(self.elapsed / transition.duration),
+            ),
+            _ => self.to,
+        }
+    }
+
+    /// Move towards a new target from the current value, or jump to it if there is no transition
+    fn set_target(&mut self, target: T, transition: Option<Transition>) {
+        if target != self.to {
+            self.from = self.current();
+            self.to = target;
+            self.elapsed = 0.0;
+        }
+        self.transition = transition;
+    }
+
+    fn tick(&mut self, dt: f32) {
+        self.elapsed += dt;
+    }
+}
+
+/// The animation state of a widget, kept between the rebuilds of the `Ui`
+pub(crate) struct WidgetAnimation {
+    opacity: Tween<f32>,
+    offset: Tween<Position>,
+    /// `None` if the widget doesn't have a background
+    background: Option<Tween<Color>>,
+    hovered: bool,
+}
+
+impl WidgetAnimation {
+    /// Start the animation of a widget that just appeared, from its initial values
+    pub(crate) fn new(style: &Style) -> Self {
+        let mut animation = Self {
+            opacity: Tween::new(style.get_initial_opacity()),
+            offset: Tween::new(style.get_initial_offset()),
+            background: None,
+            hovered: false,
+        };
+        animation.set_targets(style, false);
+        animation
+    }
+
+    /// Update the values that the properties move towards
+    pub(crate) fn set_targets(&mut self, style: &Style, hovered: bool) {
+        self.hovered = hovered;
+        self.opacity.set_target(
+            style.get_opacity(),
+            style.get_transition(AnimatedProperty::Opacity),
+        );
+        self.offset.set_target(
+            style.get_offset(),
+            style.get_transition(AnimatedProperty::Offset),
+        );
+        let background = if hovered {
+            style.get_hovered_background()
+        } else {
+            style.get_background()
+        };
+        self.background = match (self.background, background) {
+            (Some(mut tween), Some(color)) => {
+                tween.set_target(color, style.get_transition(AnimatedProperty::Background));
+                Some(tween)
+            }
+            (None, Some(color)) => Some(Tween::new(color)),
+            (_, None) => None,
+        };
+    }
+
+    pub(crate) fn tick(&mut self, dt: f32) {
+        self.opacity.tick(dt);
+        self.offset.tick(dt);
+        if let Some(background) = self.background.as_mut() {
+            background.tick(dt);
+        }
+    }
+
+    pub(crate) fn offset(&self) -> Position {
+        self.offset.current()
+    }
+
+    pub(crate) fn current(&self) -> AnimatedStyle {
+        AnimatedStyle {
+            opacity: self.opacity.current(),
+            offset: self.offset.current(),
+            background: self.background.map(|tween| tween.current()),
+            hovered: self.hovered,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_easing_bounds() {
+        for &easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ]
+        .iter()
+        {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert_eq!(easing.apply(2.0), 1.0);
+        }
+        assert_eq!(Easing::EaseIn.apply(0.5), 0.25);
+        assert_eq!(Easing::EaseOut.apply(0.5), 0.75);
+    }
+
+    #[test]
+    fn test_tween_retarget_starts_from_current_value() {
+        let transition = Transition {
+            duration: 1.0,
+            easing: Easing::Linear,
+        };
+        let mut tween = Tween::new(0.0);
+        tween.set_target(10.0, Some(transition));
+        tween.tick(0.5);
+        assert_eq!(tween.current(), 5.0);
+        // Going back starts from the middle
+        tween.set_target(0.0, Some(transition));
+        assert_eq!(tween.current(), 5.0);
+        tween.tick(0.5);
+        assert_eq!(tween.current(), 2.5);
+        tween.tick(10.0);
+        assert_eq!(tween.current(), 0.0);
+        // Without a transition, the value jumps
+        tween.set_target(3.0, None);
+        assert_eq!(tween.current(), 3.0);
+    }
+}
@@ -15,7 +15,7 @@ impl Size {
 }
 
 /// Some position.
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Position {
     pub x: f32,
     pub y: f32,
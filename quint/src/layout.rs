@@ -17,6 +17,15 @@ impl Layout {
         }
     }
 
+    /// Move this `Layout` by some position
+    pub(crate) fn offset(&self, position: crate::geometry::Position) -> Self {
+        Self {
+            x: self.x + position.x,
+            y: self.y + position.y,
+            ..*self
+        }
+    }
+
     /// Check if a position is inside this `Layout`.
     pub fn is_position_inside(&self, p: crate::geometry::Position) -> bool {
         self.x <= p.x && p.x <= self.x + self.width && self.y <= p.y && p.y <= self.y + self.height
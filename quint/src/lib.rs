@@ -1,11 +1,13 @@
+mod animation;
 mod event;
 mod geometry;
 mod layout;
 mod style;
 mod ui;
 
+pub use animation::{AnimatedProperty, AnimatedStyle, Easing, Transition};
 pub use event::{ButtonState, Event, MouseButton};
 pub use geometry::{Position, Size};
 pub use layout::Layout;
-pub use style::Style;
+pub use style::{Align, Color, Justify, Style};
 pub use ui::{Ui, Widget, WidgetTree};
@@ -1,8 +1,54 @@
+use crate::{AnimatedProperty, Easing, Position, Transition};
 use stretch::style::*;
 
+/// An RGBA color, each component between 0.0 and 1.0
+pub type Color = [f32; 4];
+
+/// Alignment along the cross axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+    /// Fill the whole cross axis
+    Stretch,
+}
+
+/// Distribution of the children along the main axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Justify {
+    Start,
+    Center,
+    End,
+    /// No space before the first child and after the last child
+    SpaceBetween,
+    /// Half as much space before the first child and after the last child as between two children
+    SpaceAround,
+    /// The same space before the first child, between the children and after the last child
+    SpaceEvenly,
+}
+
+/// A length in logical pixels on every side
+fn points_rect(pixels: f32) -> stretch::geometry::Rect<Dimension> {
+    stretch::geometry::Rect {
+        start: Dimension::Points(pixels),
+        end: Dimension::Points(pixels),
+        top: Dimension::Points(pixels),
+        bottom: Dimension::Points(pixels),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Style {
     pub(crate) style: stretch::style::Style,
+    background: Option<Color>,
+    hovered_background: Option<Color>,
+    text_color: Option<Color>,
+    opacity: f32,
+    offset: Position,
+    initial_opacity: Option<f32>,
+    initial_offset: Option<Position>,
+    transitions: Vec<(AnimatedProperty, Transition)>,
 }
 
 /// Style of a `Widget`
@@ -32,6 +78,58 @@ impl Style {
         self.style.justify_content = JustifyContent::SpaceBetween;
         self
     }
+    /// Set the alignment of the children along the cross axis
+    pub fn align_children(mut self, align: Align) -> Self {
+        self.style.align_items = match align {
+            Align::Start => AlignItems::FlexStart,
+            Align::Center => AlignItems::Center,
+            Align::End => AlignItems::FlexEnd,
+            Align::Stretch => AlignItems::Stretch,
+        };
+        self
+    }
+    /// Set the alignment of this widget along the cross axis of its parent, instead of the alignment of the parent
+    pub fn align_self(mut self, align: Align) -> Self {
+        self.style.align_self = match align {
+            Align::Start => AlignSelf::FlexStart,
+            Align::Center => AlignSelf::Center,
+            Align::End => AlignSelf::FlexEnd,
+            Align::Stretch => AlignSelf::Stretch,
+        };
+        self
+    }
+    /// Set the alignment of the lines of a wrapping container along the cross axis
+    pub fn align_lines(mut self, align: Align) -> Self {
+        self.style.align_content = match align {
+            Align::Start => AlignContent::FlexStart,
+            Align::Center => AlignContent::Center,
+            Align::End => AlignContent::FlexEnd,
+            Align::Stretch => AlignContent::Stretch,
+        };
+        self
+    }
+    /// Set the distribution of the children along the main axis
+    pub fn justify(mut self, justify: Justify) -> Self {
+        self.style.justify_content = match justify {
+            Justify::Start => JustifyContent::FlexStart,
+            Justify::Center => JustifyContent::Center,
+            Justify::End => JustifyContent::FlexEnd,
+            Justify::SpaceBetween => JustifyContent::SpaceBetween,
+            Justify::SpaceAround => JustifyContent::SpaceAround,
+            Justify::SpaceEvenly => JustifyContent::SpaceEvenly,
+        };
+        self
+    }
+    /// Set how much of the free space of the parent the widget takes along the main axis, relative to its siblings
+    pub fn grow(mut self, factor: f32) -> Self {
+        self.style.flex_grow = factor;
+        self
+    }
+    /// Set how much the widget shrinks along the main axis when the parent is too small, relative to its siblings
+    pub fn shrink(mut self, factor: f32) -> Self {
+        self.style.flex_shrink = factor;
+        self
+    }
     /// Set width relative to parent in percent (from 0.0 to 1.0)
     pub fn percent_width(mut self, width: f32) -> Self {
         self.style.size.width = Dimension::Percent(width);
@@ -60,6 +158,131 @@ impl Style {
     pub fn absolute_size(self, width: f32, height: f32) -> Self {
         self.absolute_width(width).absolute_height(height)
     }
+    /// Set the minimum width in logical pixels
+    pub fn min_width(mut self, width: f32) -> Self {
+        self.style.min_size.width = Dimension::Points(width);
+        self
+    }
+    /// Set the minimum height in logical pixels
+    pub fn min_height(mut self, height: f32) -> Self {
+        self.style.min_size.height = Dimension::Points(height);
+        self
+    }
+    /// Set the minimum size in logical pixels
+    pub fn min_size(self, width: f32, height: f32) -> Self {
+        self.min_width(width).min_height(height)
+    }
+    /// Set the maximum width in logical pixels
+    pub fn max_width(mut self, width: f32) -> Self {
+        self.style.max_size.width = Dimension::Points(width);
+        self
+    }
+    /// Set the maximum height in logical pixels
+    pub fn max_height(mut self, height: f32) -> Self {
+        self.style.max_size.height = Dimension::Points(height);
+        self
+    }
+    /// Set the maximum size in logical pixels
+    pub fn max_size(self, width: f32, height: f32) -> Self {
+        self.max_width(width).max_height(height)
+    }
+    /// Set the maximum width relative to parent in percent (from 0.0 to 1.0)
+    pub fn percent_max_width(mut self, width: f32) -> Self {
+        self.style.max_size.width = Dimension::Percent(width);
+        self
+    }
+    /// Set the maximum height relative to parent in percent (from 0.0 to 1.0)
+    pub fn percent_max_height(mut self, height: f32) -> Self {
+        self.style.max_size.height = Dimension::Percent(height);
+        self
+    }
+    /// Set the space between the border of the widget and its children, in logical pixels
+    pub fn padding(mut self, pixels: f32) -> Self {
+        self.style.padding = points_rect(pixels);
+        self
+    }
+    /// Set the space around the widget, in logical pixels
+    pub fn margin(mut self, pixels: f32) -> Self {
+        self.style.margin = points_rect(pixels);
+        self
+    }
+    /// Set the background color
+    pub fn background(mut self, color: Color) -> Self {
+        self.background = Some(color);
+        self
+    }
+    /// Set the background color when the cursor is over the widget
+    pub fn hovered_background(mut self, color: Color) -> Self {
+        self.hovered_background = Some(color);
+        self
+    }
+    /// Set the text color
+    pub fn text_color(mut self, color: Color) -> Self {
+        self.text_color = Some(color);
+        self
+    }
+    /// Set the opacity, from 0.0 to 1.0
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+    /// Move the widget and its children from their computed layout, in logical pixels
+    pub fn offset(mut self, x: f32, y: f32) -> Self {
+        self.offset = Position { x, y };
+        self
+    }
+    /// Set the opacity when the widget appears, it then transitions to the normal opacity
+    pub fn initial_opacity(mut self, opacity: f32) -> Self {
+        self.initial_opacity = Some(opacity);
+        self
+    }
+    /// Set the offset when the widget appears, it then transitions to the normal offset
+    pub fn initial_offset(mut self, x: f32, y: f32) -> Self {
+        self.initial_offset = Some(Position { x, y });
+        self
+    }
+    /// Animate the changes of a property over `duration` seconds, instead of changing it immediately
+    pub fn transition(mut self, property: AnimatedProperty, duration: f32, easing: Easing) -> Self {
+        self.transitions.retain(|(p, _)| *p != property);
+        self.transitions
+            .push((property, Transition { duration, easing }));
+        self
+    }
+    /// Get the background color, if it was set
+    pub fn get_background(&self) -> Option<Color> {
+        self.background
+    }
+    /// Get the background color when the cursor is over the widget, or the normal background if it was not set
+    pub fn get_hovered_background(&self) -> Option<Color> {
+        self.hovered_background.or(self.background)
+    }
+    /// Get the text color, if it was set
+    pub fn get_text_color(&self) -> Option<Color> {
+        self.text_color
+    }
+    /// Get the opacity
+    pub fn get_opacity(&self) -> f32 {
+        self.opacity
+    }
+    /// Get the offset from the computed layout
+    pub fn get_offset(&self) -> Position {
+        self.offset
+    }
+    /// Get the opacity when the widget appears
+    pub fn get_initial_opacity(&self) -> f32 {
+        self.initial_opacity.unwrap_or(self.opacity)
+    }
+    /// Get the offset when the widget appears
+    pub fn get_initial_offset(&self) -> Position {
+        self.initial_offset.unwrap_or(self.offset)
+    }
+    /// Get the transition of a property, if it is animated
+    pub fn get_transition(&self, property: AnimatedProperty) -> Option<Transition> {
+        self.transitions
+            .iter()
+            .find(|(p, _)| *p == property)
+            .map(|(_, transition)| *transition)
+    }
 }
 
 impl Default for Style {
@@ -68,6 +291,14 @@ impl Default for Style {
             style: stretch::style::Style {
                 ..stretch::style::Style::default()
             },
+            background: None,
+            hovered_background: None,
+            text_color: None,
+            opacity: 1.0,
+            offset: Position::default(),
+            initial_opacity: None,
+            initial_offset: None,
+            transitions: Vec::new(),
         }
     }
 }
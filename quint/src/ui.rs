@@ -1,20 +1,75 @@
-use crate::{Event, Layout, Position, Size, Style};
+use crate::animation::WidgetAnimation;
+use crate::{AnimatedStyle, Event, Layout, Position, Size, Style};
 use std::collections::HashMap;
 use stretch::{node::Node, Stretch};
 
+/// Identifies a widget between the rebuilds of the Ui: the index of its layer and its path in the tree
+type WidgetKey = (usize, Vec<usize>);
+
+struct LayerWidget<Renderer, Message> {
+    pub(self) widget: Box<dyn Widget<Renderer, Message>>,
+    pub(self) style: Style,
+    pub(self) key: WidgetKey,
+}
+
 struct UiLayer<Renderer, Message> {
     pub(self) root_node: Node,
     pub(self) stretch: Stretch,
-    pub(self) widgets: HashMap<Node, Box<dyn Widget<Renderer, Message>>>,
+    pub(self) widgets: HashMap<Node, LayerWidget<Renderer, Message>>,
+}
+
+impl<Renderer, Message> UiLayer<Renderer, Message> {
+    /// Get the layout of every node, parents before their children.
+    /// The solver places the nodes relative to their parent, so the positions of the parents are added,
+    /// as well as the animated offsets of the nodes and their parents.
+    fn absolute_layouts(
+        &self,
+        animations: &HashMap<WidgetKey, WidgetAnimation>,
+    ) -> Vec<(Node, Layout)> {
+        let mut layouts = Vec::new();
+        let mut node_stack = vec![(self.root_node, Position::default())];
+        while let Some((current_node, parent_position)) = node_stack.pop() {
+            let layout = self
+                .stretch
+                .layout(current_node)
+                .expect("Couldn't get Node layout");
+            let animated_offset = self
+                .widgets
+                .get(&current_node)
+                .and_then(|widget| animations.get(&widget.key))
+                .map(WidgetAnimation::offset)
+                .unwrap_or_default();
+            let layout = Layout::from_stretch(*layout)
+                .offset(parent_position)
+                .offset(animated_offset);
+            let position = Position {
+                x: layout.x,
+                y: layout.y,
+            };
+
+            // Push child nodes onto the stack
+            node_stack.extend(
+                self.stretch
+                    .children(current_node)
+                    .expect("Couldn't get Node children")
+                    .into_iter()
+                    .map(|child| (child, position)),
+            );
+            layouts.push((current_node, layout));
+        }
+        layouts
+    }
 }
 
 /// User interface rendered using a `Renderer` and with widgets sending messages of type `Message`.
 ///
 /// Every frame, you should first update and then you rebuild the Ui.
+/// Call `tick` every frame as well to advance the transitions of the widgets.
 /// Don't forget to set the cursor position whenever it changes.
 pub struct Ui<Renderer, Message> {
     cursor_position: Position,
     layers: Vec<UiLayer<Renderer, Message>>,
+    animations: HashMap<WidgetKey, WidgetAnimation>,
 }
 
 impl<Renderer, Message> Ui<Renderer, Message> {
@@ -22,6 +77,35 @@ impl<Renderer, Message> Ui<Renderer, Message> {
         Self {
             cursor_position: Position::default(),
             layers: Vec::new(),
+            animations: HashMap::new(),
+        }
+    }
+
+    /// Advance the transitions by `dt` seconds
+    pub fn tick(&mut self, dt: f32) {
+        self.update_animation_targets();
+        for animation in self.animations.values_mut() {
+            animation.tick(dt);
+        }
+    }
+
+    /// Move the animated properties towards the current style of the widgets, and the hovered background
+    /// of the widgets under the cursor
+    fn update_animation_targets(&mut self) {
+        let mut targets = Vec::new();
+        for layer in self.layers.iter() {
+            for (node, layout) in layer.absolute_layouts(&self.animations) {
+                if let Some(widget) = layer.widgets.get(&node) {
+                    let hovered = layout.is_position_inside(self.cursor_position);
+                    targets.push((widget.key.clone(), widget.style.clone(), hovered));
+                }
+            }
+        }
+        for (key, style, hovered) in targets {
+            self.animations
+                .entry(key)
+                .or_insert_with(|| WidgetAnimation::new(&style))
+                .set_targets(&style, hovered);
         }
     }
 
@@ -41,30 +125,13 @@ impl<Renderer, Message> Ui<Renderer, Message> {
 
     fn propagate_event(&self, event: Event, messages: &mut Vec<Message>) {
         for layer in self.layers.iter() {
-            let mut node_stack = vec![layer.root_node];
-            while let Some(current_node) = node_stack.pop() {
+            for (node, layout) in layer.absolute_layouts(&self.animations) {
                 // Update widget if it exists
-                if let Some(widget) = layer.widgets.get(&current_node) {
-                    let layout = layer
-                        .stretch
-                        .layout(current_node)
-                        .expect("Couldn't get Node layout");
-                    widget.on_event(
-                        event,
-                        Layout::from_stretch(*layout),
-                        self.cursor_position,
-                        messages,
-                    );
+                if let Some(widget) = layer.widgets.get(&node) {
+                    widget
+                        .widget
+                        .on_event(event, layout, self.cursor_position, messages);
                 }
-
-                // Push child widgets onto the stack
-                node_stack.extend(
-                    layer
-                        .stretch
-                        .children(current_node)
-                        .expect("Couldn't get Node children")
-                        .into_iter(),
-                );
             }
         }
     }
@@ -72,30 +139,46 @@ impl<Renderer, Message> Ui<Renderer, Message> {
     /// Recursively register a WidgetTree
     fn register_widget_tree(
         stretch: &mut Stretch,
-        widgets: &mut HashMap<Node, Box<dyn Widget<Renderer, Message>>>,
+        widgets: &mut HashMap<Node, LayerWidget<Renderer, Message>>,
         widget_tree: WidgetTree<Renderer, Message>,
+        key: WidgetKey,
     ) -> Node {
         let WidgetTree { root, children } = widget_tree;
         let child_nodes: Vec<Node> = children
             .into_iter()
-            .map(|child| Self::register_widget_tree(stretch, widgets, child))
+            .enumerate()
+            .map(|(i, child)| {
+                let mut child_key = key.clone();
+                child_key.1.push(i);
+                Self::register_widget_tree(stretch, widgets, child, child_key)
+            })
             .collect();
         let style = root.style();
         let node_id = stretch
-            .new_node(style.style, child_nodes)
+            .new_node(style.style.clone(), child_nodes)
             .expect("Couldn't create node");
-        widgets.insert(node_id, root);
+        widgets.insert(
+            node_id,
+            LayerWidget {
+                widget: root,
+                style,
+                key,
+            },
+        );
         node_id
     }
 
     /// Rebuild the Ui using the provided layers. The layers are rendered last-to-first.
+    /// The widgets at the same place in the trees keep their animations.
     pub fn rebuild(&mut self, layers: Vec<WidgetTree<Renderer, Message>>, dimensions: Size) {
         self.layers = layers
             .into_iter()
-            .map(|tree| {
+            .enumerate()
+            .map(|(i, tree)| {
                 let mut stretch = Stretch::new();
                 let mut widgets = HashMap::new();
-                let root_node = Self::register_widget_tree(&mut stretch, &mut widgets, tree);
+                let root_node =
+                    Self::register_widget_tree(&mut stretch, &mut widgets, tree, (i, Vec::new()));
                 stretch
                     .compute_layout(root_node, dimensions.into_stretch())
                     .expect("Couldn't compute layout");
@@ -106,33 +189,38 @@ impl<Renderer, Message> Ui<Renderer, Message> {
                 }
             })
             .collect();
+
+        // Forget the animations of the widgets that disappeared
+        let layers = &self.layers;
+        self.animations.retain(|key, _| {
+            layers
+                .iter()
+                .any(|layer| layer.widgets.values().any(|widget| widget.key == *key))
+        });
+        self.update_animation_targets();
     }
 
     /// Render the Ui using the provided `Renderer`.
     pub fn render(&self, renderer: &mut Renderer) {
         // Recursively render every widget of every layer, the last layer being rendered first
         for layer in self.layers.iter().rev() {
-            let mut render_stack = vec![layer.root_node];
-            while let Some(current_node) = render_stack.pop() {
+            for (node, layout) in layer.absolute_layouts(&self.animations) {
                 // Draw widget if it exists
-                if let Some(widget) = layer.widgets.get(&current_node) {
-                    let layout = layer
-                        .stretch
-                        .layout(current_node)
-                        .expect("Couldn't get Node layout");
-                    widget.render(
-                        renderer,
-                        self.cursor_position,
-                        Layout::from_stretch(*layout),
-                    );
+                if let Some(widget) = layer.widgets.get(&node) {
+                    let animated = self
+                        .animations
+                        .get(&widget.key)
+                        .map(WidgetAnimation::current)
+                        .unwrap_or_else(|| AnimatedStyle {
+                            opacity: 1.0,
+                            offset: Position::default(),
+                            background: None,
+                            hovered: false,
+                        });
+                    widget
+                        .widget
+                        .render(renderer, self.cursor_position, layout, &animated);
                 }
-
-                // Push child widgets onto the stack
-                let children = layer
-                    .stretch
-                    .children(current_node)
-                    .expect("Couldn't get Node children");
-                render_stack.extend(children.into_iter());
             }
         }
     }
@@ -143,8 +231,15 @@ pub trait Widget<Renderer, Message> {
     // TODO: add screen size
     /// Compute the expected style of the widget
     fn style(&self) -> Style;
-    /// Render the widget using the renderer
-    fn render(&self, _renderer: &mut Renderer, _cursor_position: Position, _layout: Layout) {}
+    /// Render the widget using the renderer, with the current values of its animated properties
+    fn render(
+        &self,
+        _renderer: &mut Renderer,
+        _cursor_position: Position,
+        _layout: Layout,
+        _animated: &AnimatedStyle,
+    ) {
+    }
     /// Process one event
     fn on_event(
         &self,
@@ -190,3 +285,254 @@ macro_rules! wt {
         WidgetTree::new(Box::new($root), vec![$children])
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Align, AnimatedProperty, Easing, Justify};
+
+    /// The layout and the animated style of every rendered widget
+    type Rendered = Vec<(u32, Layout, AnimatedStyle)>;
+
+    /// A widget that records its layout and animated style when it is rendered
+    struct TestWidget {
+        id: u32,
+        style: Style,
+    }
+
+    impl Widget<Rendered, ()> for TestWidget {
+        fn style(&self) -> Style {
+            self.style.clone()
+        }
+
+        fn render(
+            &self,
+            renderer: &mut Rendered,
+            _: Position,
+            layout: Layout,
+            animated: &AnimatedStyle,
+        ) {
+            renderer.push((self.id, layout, *animated));
+        }
+    }
+
+    fn node(
+        id: u32,
+        style: Style,
+        children: Vec<WidgetTree<Rendered, ()>>,
+    ) -> WidgetTree<Rendered, ()> {
+        WidgetTree::new(Box::new(TestWidget { id, style }), children)
+    }
+
+    /// Compute the layouts of a tree in a 200x100 screen, and return them as `[x, y, width, height]` by id
+    fn compute_layouts(tree: WidgetTree<Rendered, ()>) -> HashMap<u32, [f32; 4]> {
+        let mut ui = Ui::new();
+        ui.rebuild(
+            vec![tree],
+            Size {
+                width: 200.0,
+                height: 100.0,
+            },
+        );
+        let mut layouts = Vec::new();
+        ui.render(&mut layouts);
+        layouts
+            .into_iter()
+            .map(|(id, l, _)| (id, [l.x, l.y, l.width, l.height]))
+            .collect()
+    }
+
+    #[test]
+    fn test_wrap() {
+        let child = |id| node(id, Style::default().absolute_size(80.0, 10.0), vec![]);
+        let layouts = compute_layouts(node(
+            0,
+            Style::default()
+                .absolute_size(200.0, 100.0)
+                .wrap()
+                .align_lines(Align::Start),
+            vec![child(1), child(2), child(3)],
+        ));
+        assert_eq!(layouts[&1], [0.0, 0.0, 80.0, 10.0]);
+        assert_eq!(layouts[&2], [80.0, 0.0, 80.0, 10.0]);
+        assert_eq!(layouts[&3], [0.0, 10.0, 80.0, 10.0]);
+    }
+
+    #[test]
+    fn test_min_max_constraints() {
+        let layouts = compute_layouts(node(
+            0,
+            Style::default().absolute_size(200.0, 100.0).vertical(),
+            vec![
+                node(
+                    1,
+                    Style::default()
+                        .percent_width(1.0)
+                        .max_width(50.0)
+                        .absolute_height(10.0),
+                    vec![],
+                ),
+                node(
+                    2,
+                    Style::default()
+                        .absolute_size(10.0, 10.0)
+                        .min_width(30.0)
+                        .max_height(5.0),
+                    vec![],
+                ),
+            ],
+        ));
+        assert_eq!(layouts[&1], [0.0, 0.0, 50.0, 10.0]);
+        assert_eq!(layouts[&2], [0.0, 10.0, 30.0, 5.0]);
+    }
+
+    #[test]
+    fn test_nested_percentages_are_absolute() {
+        let layouts = compute_layouts(node(
+            0,
+            Style::default()
+                .absolute_size(200.0, 100.0)
+                .justify(Justify::End),
+            vec![node(
+                1,
+                Style::default().percent_size(0.5, 0.5).padding(10.0),
+                vec![node(2, Style::default().absolute_size(20.0, 5.0), vec![])],
+            )],
+        ));
+        assert_eq!(layouts[&1], [100.0, 0.0, 100.0, 50.0]);
+        assert_eq!(layouts[&2], [110.0, 10.0, 20.0, 5.0]);
+    }
+
+    #[test]
+    fn test_alignment() {
+        let layouts = compute_layouts(node(
+            0,
+            Style::default()
+                .absolute_size(200.0, 100.0)
+                .vertical()
+                .align_children(Align::Center),
+            vec![
+                node(1, Style::default().absolute_size(20.0, 10.0), vec![]),
+                node(
+                    2,
+                    Style::default()
+                        .absolute_size(20.0, 10.0)
+                        .align_self(Align::End),
+                    vec![],
+                ),
+                node(
+                    3,
+                    Style::default()
+                        .absolute_height(10.0)
+                        .align_self(Align::Stretch),
+                    vec![],
+                ),
+            ],
+        ));
+        assert_eq!(layouts[&1], [90.0, 0.0, 20.0, 10.0]);
+        assert_eq!(layouts[&2], [180.0, 10.0, 20.0, 10.0]);
+        assert_eq!(layouts[&3], [0.0, 20.0, 200.0, 10.0]);
+    }
+
+    #[test]
+    fn test_grow() {
+        let layouts = compute_layouts(node(
+            0,
+            Style::default().absolute_size(200.0, 100.0),
+            vec![
+                node(1, Style::default().absolute_width(50.0), vec![]),
+                node(2, Style::default().grow(1.0), vec![]),
+                node(3, Style::default().grow(3.0), vec![]),
+            ],
+        ));
+        assert_eq!(layouts[&1][2], 50.0);
+        assert_eq!(layouts[&2][0], 50.0);
+        assert_eq!(layouts[&2][2], 37.5);
+        assert_eq!(layouts[&3][0], 87.5);
+        assert_eq!(layouts[&3][2], 112.5);
+    }
+
+    fn render_ui(ui: &Ui<Rendered, ()>) -> HashMap<u32, (Layout, AnimatedStyle)> {
+        let mut rendered = Vec::new();
+        ui.render(&mut rendered);
+        rendered
+            .into_iter()
+            .map(|(id, layout, animated)| (id, (layout, animated)))
+            .collect()
+    }
+
+    const SCREEN: Size = Size {
+        width: 200.0,
+        height: 100.0,
+    };
+
+    #[test]
+    fn test_fade_in_and_slide() {
+        let tree = || {
+            node(
+                0,
+                Style::default().absolute_size(200.0, 100.0),
+                vec![node(
+                    1,
+                    Style::default()
+                        .absolute_size(20.0, 10.0)
+                        .initial_opacity(0.0)
+                        .transition(AnimatedProperty::Opacity, 1.0, Easing::Linear)
+                        .initial_offset(-50.0, 0.0)
+                        .transition(AnimatedProperty::Offset, 1.0, Easing::Linear),
+                    vec![node(2, Style::default().absolute_size(5.0, 5.0), vec![])],
+                )],
+            )
+        };
+        let mut ui = Ui::new();
+        ui.rebuild(vec![tree()], SCREEN);
+        let rendered = render_ui(&ui);
+        assert_eq!(rendered[&1].1.opacity, 0.0);
+        assert_eq!(rendered[&1].0.x, -50.0);
+
+        // The animation continues when the Ui is rebuilt, and the children move with their parent
+        ui.tick(0.5);
+        ui.rebuild(vec![tree()], SCREEN);
+        let rendered = render_ui(&ui);
+        assert_eq!(rendered[&1].1.opacity, 0.5);
+        assert_eq!(rendered[&1].0.x, -25.0);
+        assert_eq!(rendered[&2].0.x, -25.0);
+        assert_eq!(rendered[&2].1.opacity, 1.0);
+
+        ui.tick(1.0);
+        let rendered = render_ui(&ui);
+        assert_eq!(rendered[&1].1.opacity, 1.0);
+        assert_eq!(rendered[&1].0.x, 0.0);
+    }
+
+    #[test]
+    fn test_hover_transition() {
+        let mut ui = Ui::new();
+        ui.set_cursor_position(Position { x: 5.0, y: 5.0 });
+        ui.rebuild(
+            vec![node(
+                0,
+                Style::default()
+                    .absolute_size(20.0, 10.0)
+                    .background([0.0, 0.0, 0.0, 1.0])
+                    .hovered_background([1.0, 1.0, 1.0, 1.0])
+                    .transition(AnimatedProperty::Background, 1.0, Easing::Linear),
+                vec![],
+            )],
+            SCREEN,
+        );
+        let rendered = render_ui(&ui);
+        assert!(rendered[&0].1.hovered);
+        assert_eq!(rendered[&0].1.background, Some([0.0, 0.0, 0.0, 1.0]));
+
+        ui.tick(0.5);
+        assert_eq!(render_ui(&ui)[&0].1.background, Some([0.5, 0.5, 0.5, 1.0]));
+
+        // Leaving starts from the current color
+        ui.set_cursor_position(Position { x: 50.0, y: 50.0 });
+        ui.tick(0.25);
+        let rendered = render_ui(&ui);
+        assert!(!rendered[&0].1.hovered);
+        assert_eq!(rendered[&0].1.background, Some([0.375, 0.375, 0.375, 1.0]));
+    }
+}
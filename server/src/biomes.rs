@@ -0,0 +1,36 @@
+//! The biome maps of the loaded chunk columns.
+//! A biome map only takes two small noises to generate, so it is generated when it is first needed.
+use std::collections::HashMap;
+use std::sync::Arc;
+use voxel_rs_common::{
+    world::{ChunkPosXZ, WorldGenerator},
+    worldgen::biome::BiomeMap,
+};
+
+pub struct BiomeMaps {
+    maps: HashMap<ChunkPosXZ, Arc<BiomeMap>>,
+    world_generator: Box<dyn WorldGenerator + Send>,
+}
+
+impl BiomeMaps {
+    pub fn new(world_generator: Box<dyn WorldGenerator + Send>) -> Self {
+        Self {
+            maps: HashMap::new(),
+            world_generator,
+        }
+    }
+
+    /// Get the biome map of a chunk column, generating it if needed
+    pub fn get(&mut self, pos: ChunkPosXZ) -> Arc<BiomeMap> {
+        let world_generator = &mut self.world_generator;
+        self.maps
+            .entry(pos)
+            .or_insert_with(|| Arc::new(world_generator.generate_biome_map(pos)))
+            .clone()
+    }
+
+    /// Drop the biome maps of the chunk columns that are not loaded anymore
+    pub fn drop_unloaded_maps(&mut self, is_loaded: impl Fn(ChunkPosXZ) -> bool) {
+        self.maps.retain(|pos, _| is_loaded(*pos));
+    }
+}
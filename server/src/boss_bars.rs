@@ -0,0 +1,164 @@
+//! The bars shown at the top of the HUD of every player, given by id. They are set by the `/bossbar` command,
+//! and the server systems can show their own bars with `set`, `update` and `remove`.
+use std::collections::BTreeMap;
+use voxel_rs_common::boss_bar::{BossBar, BossBarColor, BossBarStyle, MAX_BOSS_BARS};
+
+/// A `/bossbar` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum BossBarCommand {
+    /// Show a bar with an id and a title
+    Add(String, String),
+    Remove(String),
+    List,
+    SetTitle(String, String),
+    SetProgress(String, f32),
+    SetColor(String, BossBarColor),
+    SetStyle(String, BossBarStyle),
+}
+
+#[derive(Debug, Default)]
+pub struct BossBars {
+    /// The shown bars, by id
+    bars: BTreeMap<String, BossBar>,
+    /// The bars that changed since they were last sent to the players, `None` if they were removed
+    changes: BTreeMap<String, Option<BossBar>>,
+}
+
+impl BossBars {
+    /// Show a bar, or replace the bar with the same id.
+    /// Return false if `MAX_BOSS_BARS` are already shown.
+    pub fn set(&mut self, id: &str, bar: BossBar) -> bool {
+        if !self.bars.contains_key(id) && self.bars.len() >= MAX_BOSS_BARS {
+            return false;
+        }
+        self.bars.insert(id.to_owned(), bar.clone());
+        self.changes.insert(id.to_owned(), Some(bar));
+        true
+    }
+
+    /// Change a shown bar. Return false if there is no bar with this id.
+    pub fn update(&mut self, id: &str, change: impl FnOnce(&mut BossBar)) -> bool {
+        match self.bars.get_mut(id) {
+            Some(bar) => {
+                change(bar);
+                bar.progress = bar.progress.max(0.0).min(1.0);
+                self.changes.insert(id.to_owned(), Some(bar.clone()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Hide a bar. Return false if there is no bar with this id.
+    pub fn remove(&mut self, id: &str) -> bool {
+        if self.bars.remove(id).is_none() {
+            return false;
+        }
+        self.changes.insert(id.to_owned(), None);
+        true
+    }
+
+    /// The shown bars, to send to a player that joins
+    pub fn bars(&self) -> impl Iterator<Item = (&String, &BossBar)> {
+        self.bars.iter()
+    }
+
+    /// The bars that changed since the last call, to send to all the players
+    pub fn take_changes(&mut self) -> Vec<(String, Option<BossBar>)> {
+        std::mem::take(&mut self.changes).into_iter().collect()
+    }
+
+    /// Run a command, and return the message for the player
+    pub fn run(&mut self, command: BossBarCommand) -> String {
+        match command {
+            BossBarCommand::Add(id, title) => {
+                if self.bars.contains_key(&id) {
+                    return format!("There is already a bar with id {}", id);
+                }
+                if self.set(&id, BossBar::new(title)) {
+                    format!("Added bar {}", id)
+                } else {
+                    format!("There can't be more than {} bars", MAX_BOSS_BARS)
+                }
+            }
+            BossBarCommand::Remove(id) => {
+                if self.remove(&id) {
+                    format!("Removed bar {}", id)
+                } else {
+                    format!("No bar with id {}", id)
+                }
+            }
+            BossBarCommand::List => {
+                if self.bars.is_empty() {
+                    return "No bars".to_owned();
+                }
+                self.bars
+                    .iter()
+                    .map(|(id, bar)| {
+                        format!("{} ({}, {:.0}%)", id, bar.title, bar.progress * 100.0)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+            BossBarCommand::SetTitle(id, title) => {
+                self.update_message(&id, |bar| bar.title = title)
+            }
+            BossBarCommand::SetProgress(id, progress) => {
+                self.update_message(&id, |bar| bar.progress = progress)
+            }
+            BossBarCommand::SetColor(id, color) => {
+                self.update_message(&id, |bar| bar.color = color)
+            }
+            BossBarCommand::SetStyle(id, style) => {
+                self.update_message(&id, |bar| bar.style = style)
+            }
+        }
+    }
+
+    fn update_message(&mut self, id: &str, change: impl FnOnce(&mut BossBar)) -> String {
+        if self.update(id, change) {
+            format!("Updated bar {}", id)
+        } else {
+            format!("No bar with id {}", id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boss_bars() {
+        let mut bars = BossBars::default();
+        bars.run(BossBarCommand::Add(
+            "boss".to_owned(),
+            "The boss".to_owned(),
+        ));
+        bars.run(BossBarCommand::SetProgress("boss".to_owned(), 1.5));
+        bars.run(BossBarCommand::SetColor(
+            "boss".to_owned(),
+            BossBarColor::Red,
+        ));
+        let changes = bars.take_changes();
+        assert_eq!(changes.len(), 1);
+        let bar = changes[0].1.as_ref().unwrap();
+        assert_eq!(bar.progress, 1.0);
+        assert_eq!(bar.color, BossBarColor::Red);
+        assert!(bars.take_changes().is_empty());
+
+        assert!(bars
+            .run(BossBarCommand::SetProgress("other".to_owned(), 0.5))
+            .contains("No bar"));
+        for i in 1..MAX_BOSS_BARS {
+            assert!(bars.set(&format!("event {}", i), BossBar::new(String::new())));
+        }
+        assert!(!bars.set("one too many", BossBar::new(String::new())));
+        assert!(bars.set("boss", BossBar::new("Replaced".to_owned())));
+
+        bars.take_changes();
+        assert!(bars.remove("boss"));
+        assert_eq!(bars.take_changes(), vec![("boss".to_owned(), None)]);
+        assert_eq!(bars.bars().count(), MAX_BOSS_BARS - 1);
+    }
+}
@@ -0,0 +1,618 @@
+//! Commands typed by the players in the chat, or run by the command blocks
+use crate::boss_bars::BossBarCommand;
+use crate::difficulty::Difficulty;
+use crate::pregen::MAX_PREGEN_RADIUS;
+use crate::save::MAX_REPAIR_RADIUS;
+use crate::scoreboard::ScoreboardCommand;
+use crate::teams::{TeamColor, TeamCommand};
+use serde::{Deserialize, Serialize};
+use voxel_rs_common::player::GameMode;
+use voxel_rs_common::world::BlockPos;
+
+/// A parsed command
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// List the available commands
+    Help,
+    /// Change the game mode of the player
+    GameMode(GameMode),
+    /// Replace the selected cuboid by some block, given by name
+    Fill(String),
+    /// Copy the selected cuboid to the clipboard of the player
+    Copy,
+    /// Paste the clipboard of the player, rotated by some number of quarter turns
+    Paste(u32),
+    /// Undo the last world edit command of the player
+    Undo,
+    /// Save the selected cuboid as a schematic in the native format
+    SaveSchematic(String),
+    /// Export the selected cuboid as a `.vox` schematic
+    ExportSchematic(String),
+    /// Load a schematic into the clipboard of the player
+    LoadSchematic(String),
+    /// Keep the chunks of the selected cuboid loaded, even when no player is close
+    ForceloadAdd,
+    /// Remove a forceloaded region, given by id
+    ForceloadRemove(u32),
+    /// List the forceloaded regions
+    ForceloadList,
+    /// Reload the values of the server config that can change without restarting the server
+    Reload,
+    /// Save the whole world now
+    SaveAll,
+    /// Check the saved chunks within some radius around the command, and generate the corrupted ones again
+    RepairRegion(u64),
+    /// Send a message to all the players
+    Say(String),
+    /// Show a title and a subtitle, which may be empty, in the middle of the screen of all the players
+    Title(String, String),
+    /// Show a text above the hotbar of all the players
+    ActionBar(String),
+    /// Replace the block at some position by some block, given by name
+    SetBlock(BlockPos, String),
+    /// Place two portals at some positions, linked to each other
+    Portal(BlockPos, BlockPos),
+    /// Watch another player, given by name, or stop watching if there is no name
+    Spectate(Option<String>),
+    /// Generate and save the chunks within some radius around the spawn, or stop if there is no radius
+    Pregen(Option<u64>),
+    /// Export a top-down map of the generated chunks to a PNG image, shaded with the slope of the terrain or not
+    ExportMap(bool),
+    /// Manage the objectives of the scoreboard and the scores of the players
+    Scoreboard(ScoreboardCommand),
+    /// Manage the teams and their members
+    Team(TeamCommand),
+    /// Manage the bars shown at the top of the HUD of every player
+    BossBar(BossBarCommand),
+    /// List the game rules with their values
+    ListGameRules,
+    /// Show the value of a game rule, given by name, or change it if there is a value
+    GameRule(String, Option<String>),
+    /// Show the difficulty of the world, or change it if there is a difficulty
+    Difficulty(Option<Difficulty>),
+}
+
+/// What the command blocks and the players are allowed to do, from the least to the most powerful
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandPermission {
+    /// No command can be run
+    Disabled,
+    /// Send messages to the players
+    Chat,
+    /// Modify the world as well
+    World,
+    /// Manage the server as well
+    Server,
+}
+
+impl Command {
+    /// The permission that a command block needs to run this command,
+    /// or `None` if only a player can run it because it uses the selection, the clipboard or the game mode
+    pub fn block_permission(&self) -> Option<CommandPermission> {
+        match self {
+            Self::Say(_)
+            | Self::Title(..)
+            | Self::ActionBar(_)
+            | Self::Scoreboard(_)
+            | Self::Team(_)
+            | Self::BossBar(_) => Some(CommandPermission::Chat),
+            Self::SetBlock(..)
+            | Self::Portal(..)
+            | Self::ListGameRules
+            | Self::GameRule(..)
+            | Self::Difficulty(_) => Some(CommandPermission::World),
+            Self::ForceloadRemove(_)
+            | Self::ForceloadList
+            | Self::Reload
+            | Self::SaveAll
+            | Self::RepairRegion(_)
+            | Self::Pregen(_)
+            | Self::ExportMap(_) => Some(CommandPermission::Server),
+            Self::Help
+            | Self::GameMode(_)
+            | Self::Fill(_)
+            | Self::Copy
+            | Self::Paste(_)
+            | Self::Undo
+            | Self::SaveSchematic(_)
+            | Self::ExportSchematic(_)
+            | Self::LoadSchematic(_)
+            | Self::ForceloadAdd
+            | Self::Spectate(_) => None,
+        }
+    }
+
+    /// The permission that a player needs to run this command
+    pub fn player_permission(&self) -> CommandPermission {
+        match self {
+            Self::Help | Self::Say(_) | Self::Spectate(_) | Self::ListGameRules => {
+                CommandPermission::Chat
+            }
+            Self::GameMode(_)
+            | Self::Fill(_)
+            | Self::Copy
+            | Self::Paste(_)
+            | Self::Undo
+            | Self::LoadSchematic(_)
+            | Self::SetBlock(..)
+            | Self::Portal(..)
+            | Self::Title(..)
+            | Self::ActionBar(_)
+            | Self::Scoreboard(_)
+            | Self::Team(_)
+            | Self::BossBar(_)
+            | Self::GameRule(..)
+            | Self::Difficulty(_) => CommandPermission::World,
+            // The schematics are written to the disk of the server
+            Self::SaveSchematic(_)
+            | Self::ExportSchematic(_)
+            | Self::ForceloadAdd
+            | Self::ForceloadRemove(_)
+            | Self::ForceloadList
+            | Self::Reload
+            | Self::SaveAll
+            | Self::RepairRegion(_)
+            | Self::Pregen(_)
+            | Self::ExportMap(_) => CommandPermission::Server,
+        }
+    }
+}
+
+/// The usage of every command, shown by `/help`
+pub const COMMAND_USAGES: &[&str] = &[
+    "/help",
+    "/gamemode <survival|creative>",
+    "/fill <block>",
+    "/copy",
+    "/paste [0|90|180|270]",
+    "/undo",
+    "/schematic <save|export|load> <name>",
+    "/forceload <add|list|remove <id>>",
+    "/reload",
+    "/save-all",
+    "/repair-region <radius>",
+    "/say <message>",
+    "/title <title> [| <subtitle>]",
+    "/actionbar <message>",
+    "/setblock <x> <y> <z> <block>",
+    "/portal <x1> <y1> <z1> <x2> <y2> <z2>",
+    "/spectate [player]",
+    "/pregen <radius|stop>",
+    "/exportmap [shaded|flat]",
+    "/scoreboard objectives <add <name> <criterion> [title]|remove <name>|list|display <slot> [name]>",
+    SCOREBOARD_PLAYERS_USAGE,
+    "/gamerule [rule] [value]",
+    "/difficulty [peaceful|easy|normal|hard]",
+    "/team <add <name> [color]|remove <name>|list|join <team> <player>|leave <player>|color <team> <color>|friendlyfire <team> <true|false>>",
+    "/bossbar <add <id> <title>|remove <id>|list|set <id> <title|progress|color|style> <value>>",
+];
+
+/// The second usage of `/scoreboard`, which `usage` can't find by the name of the command
+const SCOREBOARD_PLAYERS_USAGE: &str =
+    "/scoreboard players <set|add <objective> <value>|reset <objective>> <player>";
+
+/// Parse a command, without the leading `/`. The error is a message for the player.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or_else(|| "Empty command".to_owned())?;
+    let args = words.collect::<Vec<_>>();
+    match (name, args.as_slice()) {
+        ("help", []) => Ok(Command::Help),
+        ("gamemode", [mode]) => Ok(Command::GameMode(mode.parse()?)),
+        ("fill", [block]) => Ok(Command::Fill((*block).to_owned())),
+        ("copy", []) => Ok(Command::Copy),
+        ("paste", []) => Ok(Command::Paste(0)),
+        ("paste", [angle]) => match *angle {
+            "0" => Ok(Command::Paste(0)),
+            "90" => Ok(Command::Paste(1)),
+            "180" => Ok(Command::Paste(2)),
+            "270" => Ok(Command::Paste(3)),
+            _ => Err(format!(
+                "Invalid angle {}, it must be 0, 90, 180 or 270",
+                angle
+            )),
+        },
+        ("undo", []) => Ok(Command::Undo),
+        ("schematic", ["save", name]) => Ok(Command::SaveSchematic((*name).to_owned())),
+        ("schematic", ["export", name]) => Ok(Command::ExportSchematic((*name).to_owned())),
+        ("schematic", ["load", name]) => Ok(Command::LoadSchematic((*name).to_owned())),
+        ("reload", []) => Ok(Command::Reload),
+        ("save-all", []) => Ok(Command::SaveAll),
+        ("repair-region", [radius]) => match radius.parse() {
+            Ok(radius) if radius <= MAX_REPAIR_RADIUS => Ok(Command::RepairRegion(radius)),
+            _ => Err(format!(
+                "Invalid radius {}, it must be a number of chunks between 0 and {}",
+                radius, MAX_REPAIR_RADIUS
+            )),
+        },
+        ("say", words) if !words.is_empty() => Ok(Command::Say(words.join(" "))),
+        ("title", words) if !words.is_empty() => {
+            let line = words.join(" ");
+            let (title, subtitle) = match line.find('|') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => (line.as_str(), ""),
+            };
+            if title.is_empty() && subtitle.is_empty() {
+                return Err(format!("Usage: {}", usage(name).unwrap()));
+            }
+            Ok(Command::Title(title.to_owned(), subtitle.to_owned()))
+        }
+        ("actionbar", words) if !words.is_empty() => Ok(Command::ActionBar(words.join(" "))),
+        ("setblock", [x, y, z, block]) => Ok(Command::SetBlock(
+            parse_position(x, y, z)?,
+            (*block).to_owned(),
+        )),
+        ("portal", [x1, y1, z1, x2, y2, z2]) => {
+            match (parse_position(x1, y1, z1), parse_position(x2, y2, z2)) {
+                (Ok(from), Ok(to)) => Ok(Command::Portal(from, to)),
+                (Err(message), _) | (_, Err(message)) => Err(message),
+            }
+        }
+        ("spectate", []) => Ok(Command::Spectate(None)),
+        ("spectate", words) => Ok(Command::Spectate(Some(words.join(" ")))),
+        ("pregen", ["stop"]) => Ok(Command::Pregen(None)),
+        ("pregen", [radius]) => match radius.parse() {
+            Ok(radius) if radius <= MAX_PREGEN_RADIUS => Ok(Command::Pregen(Some(radius))),
+            _ => Err(format!(
+                "Invalid radius {}, it must be a number of chunks between 0 and {}",
+                radius, MAX_PREGEN_RADIUS
+            )),
+        },
+        ("exportmap", []) | ("exportmap", ["shaded"]) => Ok(Command::ExportMap(true)),
+        ("exportmap", ["flat"]) => Ok(Command::ExportMap(false)),
+        ("forceload", ["add"]) => Ok(Command::ForceloadAdd),
+        ("forceload", ["list"]) => Ok(Command::ForceloadList),
+        ("forceload", ["remove", id]) => match id.parse() {
+            Ok(id) => Ok(Command::ForceloadRemove(id)),
+            Err(_) => Err(format!("Invalid forceload id {}", id)),
+        },
+        ("scoreboard", ["objectives", "add", name, criterion, title @ ..]) => {
+            let title = if title.is_empty() {
+                (*name).to_owned()
+            } else {
+                title.join(" ")
+            };
+            Ok(Command::Scoreboard(ScoreboardCommand::AddObjective(
+                (*name).to_owned(),
+                criterion.parse()?,
+                title,
+            )))
+        }
+        ("scoreboard", ["objectives", "remove", name]) => Ok(Command::Scoreboard(
+            ScoreboardCommand::RemoveObjective((*name).to_owned()),
+        )),
+        ("scoreboard", ["objectives", "list"]) => {
+            Ok(Command::Scoreboard(ScoreboardCommand::ListObjectives))
+        }
+        ("scoreboard", ["objectives", "display", slot]) => Ok(Command::Scoreboard(
+            ScoreboardCommand::Display(slot.parse()?, None),
+        )),
+        ("scoreboard", ["objectives", "display", slot, name]) => Ok(Command::Scoreboard(
+            ScoreboardCommand::Display(slot.parse()?, Some((*name).to_owned())),
+        )),
+        ("scoreboard", ["players", action @ "set", objective, value, player @ ..])
+        | ("scoreboard", ["players", action @ "add", objective, value, player @ ..])
+            if !player.is_empty() =>
+        {
+            let value = value
+                .parse()
+                .map_err(|_| format!("Invalid score {}", value))?;
+            let (objective, player) = ((*objective).to_owned(), player.join(" "));
+            Ok(Command::Scoreboard(if *action == "set" {
+                ScoreboardCommand::SetScore(objective, player, value)
+            } else {
+                ScoreboardCommand::AddScore(objective, player, value)
+            }))
+        }
+        ("scoreboard", ["players", "reset", objective, player @ ..]) if !player.is_empty() => {
+            Ok(Command::Scoreboard(ScoreboardCommand::ResetScore(
+                (*objective).to_owned(),
+                player.join(" "),
+            )))
+        }
+        ("scoreboard", ["players", ..]) => Err(format!("Usage: {}", SCOREBOARD_PLAYERS_USAGE)),
+        ("gamerule", []) => Ok(Command::ListGameRules),
+        ("gamerule", [rule]) => Ok(Command::GameRule((*rule).to_owned(), None)),
+        ("gamerule", [rule, value]) => Ok(Command::GameRule(
+            (*rule).to_owned(),
+            Some((*value).to_owned()),
+        )),
+        ("difficulty", []) => Ok(Command::Difficulty(None)),
+        ("difficulty", [difficulty]) => Ok(Command::Difficulty(Some(difficulty.parse()?))),
+        ("team", ["add", name]) => Ok(Command::Team(TeamCommand::Add(
+            (*name).to_owned(),
+            TeamColor::White,
+        ))),
+        ("team", ["add", name, color]) => Ok(Command::Team(TeamCommand::Add(
+            (*name).to_owned(),
+            color.parse()?,
+        ))),
+        ("team", ["remove", name]) => Ok(Command::Team(TeamCommand::Remove((*name).to_owned()))),
+        ("team", ["list"]) => Ok(Command::Team(TeamCommand::List)),
+        ("team", ["join", team, player @ ..]) if !player.is_empty() => Ok(Command::Team(
+            TeamCommand::Join((*team).to_owned(), player.join(" ")),
+        )),
+        ("team", ["leave", player @ ..]) if !player.is_empty() => {
+            Ok(Command::Team(TeamCommand::Leave(player.join(" "))))
+        }
+        ("team", ["color", team, color]) => Ok(Command::Team(TeamCommand::Color(
+            (*team).to_owned(),
+            color.parse()?,
+        ))),
+        ("team", ["friendlyfire", team, value]) => match *value {
+            "true" => Ok(Command::Team(TeamCommand::FriendlyFire(
+                (*team).to_owned(),
+                true,
+            ))),
+            "false" => Ok(Command::Team(TeamCommand::FriendlyFire(
+                (*team).to_owned(),
+                false,
+            ))),
+            _ => Err(format!("Invalid value {}, it must be true or false", value)),
+        },
+        ("bossbar", ["add", id, title @ ..]) if !title.is_empty() => Ok(Command::BossBar(
+            BossBarCommand::Add((*id).to_owned(), title.join(" ")),
+        )),
+        ("bossbar", ["remove", id]) => {
+            Ok(Command::BossBar(BossBarCommand::Remove((*id).to_owned())))
+        }
+        ("bossbar", ["list"]) => Ok(Command::BossBar(BossBarCommand::List)),
+        ("bossbar", ["set", id, "title", title @ ..]) if !title.is_empty() => Ok(Command::BossBar(
+            BossBarCommand::SetTitle((*id).to_owned(), title.join(" ")),
+        )),
+        ("bossbar", ["set", id, "progress", progress]) => match progress.parse::<f32>() {
+            Ok(progress) if (0.0..=1.0).contains(&progress) => Ok(Command::BossBar(
+                BossBarCommand::SetProgress((*id).to_owned(), progress),
+            )),
+            _ => Err(format!(
+                "Invalid progress {}, it must be between 0 and 1",
+                progress
+            )),
+        },
+        ("bossbar", ["set", id, "color", color]) => Ok(Command::BossBar(BossBarCommand::SetColor(
+            (*id).to_owned(),
+            color.parse()?,
+        ))),
+        ("bossbar", ["set", id, "style", style]) => Ok(Command::BossBar(BossBarCommand::SetStyle(
+            (*id).to_owned(),
+            style.parse()?,
+        ))),
+        // Wrong arguments
+        _ => match usage(name) {
+            Some(usage) => Err(format!("Usage: {}", usage)),
+            None => Err(format!("Unknown command /{}, type /help for help", name)),
+        },
+    }
+}
+
+fn parse_position(x: &str, y: &str, z: &str) -> Result<BlockPos, String> {
+    match (x.parse(), y.parse(), z.parse()) {
+        (Ok(px), Ok(py), Ok(pz)) => Ok(BlockPos { px, py, pz }),
+        _ => Err(format!("Invalid position {} {} {}", x, y, z)),
+    }
+}
+
+fn usage(name: &str) -> Option<&'static str> {
+    COMMAND_USAGES
+        .iter()
+        .find(|usage| usage[1..].split(' ').next() == Some(name))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoreboard::{Criterion, DisplaySlot};
+    use voxel_rs_common::boss_bar::BossBarStyle;
+
+    #[test]
+    fn test_parse_block_commands() {
+        match parse_command("say  Hello   world") {
+            Ok(Command::Say(message)) => assert_eq!(message, "Hello world"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("title Wave 2 | Get ready") {
+            Ok(Command::Title(title, subtitle)) => {
+                assert_eq!(title, "Wave 2");
+                assert_eq!(subtitle, "Get ready");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("title | Only a subtitle") {
+            Ok(Command::Title(title, subtitle)) => {
+                assert_eq!(title, "");
+                assert_eq!(subtitle, "Only a subtitle");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_command("title |").is_err());
+        assert!(parse_command("actionbar").is_err());
+        match parse_command("setblock 1 -2 3 stone") {
+            Ok(Command::SetBlock(pos, block)) => {
+                assert_eq!(
+                    pos,
+                    BlockPos {
+                        px: 1,
+                        py: -2,
+                        pz: 3
+                    }
+                );
+                assert_eq!(block, "stone");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_command("setblock 1 a 3 stone").is_err());
+        assert!(parse_command("say").is_err());
+        match parse_command("portal 0 50 0 100 60 -100") {
+            Ok(Command::Portal(from, to)) => {
+                assert_eq!(from, BlockPos::from((0, 50, 0)));
+                assert_eq!(to, BlockPos::from((100, 60, -100)));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_command("portal 0 50 0 100 b 0").is_err());
+        assert!(parse_command("portal 0 50 0").is_err());
+    }
+
+    #[test]
+    fn test_parse_pregen() {
+        match parse_command("pregen 16") {
+            Ok(Command::Pregen(Some(radius))) => assert_eq!(radius, 16),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("pregen stop") {
+            Ok(Command::Pregen(None)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_command(&format!("pregen {}", MAX_PREGEN_RADIUS + 1)).is_err());
+        assert!(parse_command("pregen -1").is_err());
+    }
+
+    #[test]
+    fn test_parse_repair_region() {
+        match parse_command("repair-region 8") {
+            Ok(Command::RepairRegion(radius)) => assert_eq!(radius, 8),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_command(&format!("repair-region {}", MAX_REPAIR_RADIUS + 1)).is_err());
+        assert!(parse_command("repair-region").is_err());
+    }
+
+    #[test]
+    fn test_player_permission() {
+        let permission = |line: &str| parse_command(line).unwrap().player_permission();
+        assert_eq!(permission("help"), CommandPermission::Chat);
+        assert_eq!(permission("gamemode creative"), CommandPermission::World);
+        assert_eq!(permission("difficulty hard"), CommandPermission::World);
+        assert_eq!(
+            permission("schematic save house"),
+            CommandPermission::Server
+        );
+        assert_eq!(permission("reload"), CommandPermission::Server);
+        assert_eq!(permission("repair-region 4"), CommandPermission::Server);
+    }
+
+    #[test]
+    fn test_parse_spectate() {
+        match parse_command("spectate Player 2") {
+            Ok(Command::Spectate(Some(name))) => assert_eq!(name, "Player 2"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("spectate") {
+            Ok(Command::Spectate(None)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_scoreboard() {
+        match parse_command("scoreboard objectives add mined blocks_broken Blocks mined") {
+            Ok(Command::Scoreboard(ScoreboardCommand::AddObjective(name, criterion, title))) => {
+                assert_eq!(name, "mined");
+                assert_eq!(criterion, Criterion::BlocksBroken);
+                assert_eq!(title, "Blocks mined");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("scoreboard players add points -3 Player 2") {
+            Ok(Command::Scoreboard(ScoreboardCommand::AddScore(objective, player, value))) => {
+                assert_eq!(objective, "points");
+                assert_eq!(player, "Player 2");
+                assert_eq!(value, -3);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("scoreboard objectives display sidebar") {
+            Ok(Command::Scoreboard(ScoreboardCommand::Display(DisplaySlot::Sidebar, None))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_command("scoreboard objectives add points score").is_err());
+        assert!(parse_command("scoreboard objectives display top points").is_err());
+        assert!(parse_command("scoreboard players set points 1").is_err());
+        assert!(parse_command("scoreboard players set points many Player").is_err());
+    }
+
+    #[test]
+    fn test_parse_team() {
+        match parse_command("team add red red") {
+            Ok(Command::Team(TeamCommand::Add(name, color))) => {
+                assert_eq!(name, "red");
+                assert_eq!(color, TeamColor::Red);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("team join red Player 2") {
+            Ok(Command::Team(TeamCommand::Join(team, player))) => {
+                assert_eq!(team, "red");
+                assert_eq!(player, "Player 2");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("team friendlyfire red false") {
+            Ok(Command::Team(TeamCommand::FriendlyFire(team, false))) => assert_eq!(team, "red"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_command("team add red black").is_err());
+        assert!(parse_command("team friendlyfire red maybe").is_err());
+        assert!(parse_command("team leave").is_err());
+    }
+
+    #[test]
+    fn test_parse_boss_bar() {
+        match parse_command("bossbar add event Event starts soon") {
+            Ok(Command::BossBar(BossBarCommand::Add(id, title))) => {
+                assert_eq!(id, "event");
+                assert_eq!(title, "Event starts soon");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("bossbar set event progress 0.25") {
+            Ok(Command::BossBar(BossBarCommand::SetProgress(id, progress))) => {
+                assert_eq!(id, "event");
+                assert_eq!(progress, 0.25);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("bossbar set event style notched_10") {
+            Ok(Command::BossBar(BossBarCommand::SetStyle(_, BossBarStyle::Notched(10)))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_command("bossbar add event").is_err());
+        assert!(parse_command("bossbar set event progress 2").is_err());
+        assert!(parse_command("bossbar set event color black").is_err());
+        assert!(parse_command("bossbar set event speed 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_difficulty() {
+        match parse_command("difficulty peaceful") {
+            Ok(Command::Difficulty(Some(Difficulty::Peaceful))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_command("difficulty") {
+            Ok(Command::Difficulty(None)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse_command("difficulty extreme").is_err());
+        assert!(parse_command("difficulty hard now").is_err());
+    }
+
+    #[test]
+    fn test_block_permissions() {
+        let permission = |line: &str| parse_command(line).unwrap().block_permission();
+        assert_eq!(permission("say hi"), Some(CommandPermission::Chat));
+        assert_eq!(
+            permission("setblock 0 0 0 stone"),
+            Some(CommandPermission::World)
+        );
+        assert_eq!(permission("save-all"), Some(CommandPermission::Server));
+        assert_eq!(
+            permission("gamerule keep_inventory false"),
+            Some(CommandPermission::World)
+        );
+        assert_eq!(permission("copy"), None);
+        assert!(CommandPermission::Disabled < CommandPermission::Chat);
+        assert!(CommandPermission::World < CommandPermission::Server);
+    }
+}
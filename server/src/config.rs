@@ -0,0 +1,241 @@
+//! The server configuration, read from `config/server.toml`
+use crate::command::CommandPermission;
+use crate::pregen::MAX_PREGEN_RADIUS;
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use voxel_rs_common::player::is_valid_profile;
+
+/// Folder containing the configuration files
+const CONFIG_FOLDER: &str = "config";
+/// File containing the server configuration
+const CONFIG_FILE: &str = "config/server.toml";
+/// Largest accepted world seed, so that the noise seeds derived from it can't overflow
+pub const MAX_WORLD_SEED: u32 = 1_000_000;
+
+/// The server configuration.
+/// The MOTD, the autosave interval, the backup settings and the permissions can be changed with `/reload`,
+/// the other values need a restart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Maximum number of players connected at the same time
+    pub max_players: u32,
+    /// Maximum render distance in chunks, in every direction, that a player can request
+    pub view_distance_cap: u64,
+    /// Number of iterations of the server loop per second
+    pub tick_rate: u32,
+    /// Seed of the world generator
+    pub world_seed: u32,
+    /// Message sent to the players when they join
+    pub motd: String,
+    /// Number of seconds between two autosaves, 0 to disable autosaving
+    pub autosave_interval: u64,
+    /// Number of seconds between two backups of the save folder, 0 to disable backups
+    pub backup_interval: u64,
+    /// Number of backups to keep, the oldest ones are deleted
+    pub backups_kept: usize,
+    /// Serve the metrics in the Prometheus text format over HTTP
+    pub metrics_enabled: bool,
+    /// Address of the metrics endpoint
+    pub metrics_address: String,
+    /// Number of threads simulating the regions of the world, 0 for one per core
+    pub simulation_threads: usize,
+    /// What the command blocks are allowed to do: disabled, chat, world or server
+    pub command_block_permission: CommandPermission,
+    /// What the players are allowed to do, except the operators: disabled, chat, world or server
+    pub player_permission: CommandPermission,
+    /// Profiles of the players that can run all the commands, like the host of a singleplayer world.
+    /// The profile of a player is logged when they join, it is the `player_profile` of their client settings.
+    /// The last name of an operator is reserved, the other players can't take it.
+    pub operators: Vec<String>,
+    /// Radius in chunks around the spawn that is generated and saved when the server starts, 0 to disable it
+    pub pregen_radius: u64,
+    /// Only report what the upgrade of an old save would change, and stop without modifying the save
+    pub migration_dry_run: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            max_players: 8,
+            view_distance_cap: 32,
+            tick_rate: 60,
+            world_seed: 0,
+            motd: "Welcome to voxel-rs!".to_owned(),
+            autosave_interval: 300,
+            backup_interval: 0,
+            backups_kept: 5,
+            metrics_enabled: false,
+            metrics_address: "127.0.0.1:9100".to_owned(),
+            simulation_threads: 0,
+            command_block_permission: CommandPermission::World,
+            player_permission: CommandPermission::Chat,
+            operators: Vec::new(),
+            pregen_radius: 0,
+            migration_dry_run: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Check that the values make sense, the error explains how to fix the file
+    fn validate(&self) -> Result<()> {
+        if self.max_players == 0 {
+            bail!("max_players must be at least 1");
+        }
+        if self.view_distance_cap == 0 {
+            bail!("view_distance_cap must be at least 1 chunk");
+        }
+        if self.tick_rate == 0 || self.tick_rate > 1000 {
+            bail!(
+                "tick_rate must be between 1 and 1000, got {}",
+                self.tick_rate
+            );
+        }
+        if self.world_seed > MAX_WORLD_SEED {
+            bail!(
+                "world_seed must be between 0 and {}, got {}",
+                MAX_WORLD_SEED,
+                self.world_seed
+            );
+        }
+        if self.metrics_enabled
+            && self
+                .metrics_address
+                .parse::<std::net::SocketAddr>()
+                .is_err()
+        {
+            bail!(
+                "metrics_address must be an IP address and a port, like 127.0.0.1:9100, got {}",
+                self.metrics_address
+            );
+        }
+        if self.pregen_radius > MAX_PREGEN_RADIUS {
+            bail!(
+                "pregen_radius must be at most {} chunks, got {}",
+                MAX_PREGEN_RADIUS,
+                self.pregen_radius
+            );
+        }
+        if self.backup_interval > 0 && self.backups_kept == 0 {
+            bail!("backups_kept must be at least 1 when backups are enabled");
+        }
+        if let Some(operator) = self
+            .operators
+            .iter()
+            .find(|operator| !is_valid_profile(operator))
+        {
+            bail!(
+                "operators must be player profiles, as logged when the players join, got {}",
+                operator
+            );
+        }
+        Ok(())
+    }
+
+    /// Copy the values that can be changed without restarting the server from `other`,
+    /// and return the names of the values that changed but need a restart
+    pub fn reload_from(&mut self, other: ServerConfig) -> Vec<&'static str> {
+        let mut restart_needed = Vec::new();
+        if self.max_players != other.max_players {
+            restart_needed.push("max_players");
+        }
+        if self.view_distance_cap != other.view_distance_cap {
+            restart_needed.push("view_distance_cap");
+        }
+        if self.tick_rate != other.tick_rate {
+            restart_needed.push("tick_rate");
+        }
+        if self.world_seed != other.world_seed {
+            restart_needed.push("world_seed");
+        }
+        if self.metrics_enabled != other.metrics_enabled
+            || self.metrics_address != other.metrics_address
+        {
+            restart_needed.push("metrics");
+        }
+        if self.simulation_threads != other.simulation_threads {
+            restart_needed.push("simulation_threads");
+        }
+        if self.pregen_radius != other.pregen_radius {
+            restart_needed.push("pregen_radius");
+        }
+        if self.migration_dry_run != other.migration_dry_run {
+            restart_needed.push("migration_dry_run");
+        }
+        self.motd = other.motd;
+        self.autosave_interval = other.autosave_interval;
+        self.backup_interval = other.backup_interval;
+        self.backups_kept = other.backups_kept;
+        self.command_block_permission = other.command_block_permission;
+        self.player_permission = other.player_permission;
+        self.operators = other.operators;
+        restart_needed
+    }
+}
+
+/// Load the server configuration, or create the file with the default configuration if it doesn't exist
+pub fn load_config() -> Result<ServerConfig> {
+    info!("Reading server configuration from {}", CONFIG_FILE);
+    if !Path::new(CONFIG_FILE).is_file() {
+        let config = ServerConfig::default();
+        std::fs::create_dir_all(CONFIG_FOLDER)
+            .context(format!("Failed to create folder {}", CONFIG_FOLDER))?;
+        let string = toml::ser::to_string(&config).context("Failed to serialize server config")?;
+        std::fs::write(CONFIG_FILE, string).context(format!(
+            "Failed to write server config file {}",
+            CONFIG_FILE
+        ))?;
+        return Ok(config);
+    }
+    let string = std::fs::read_to_string(CONFIG_FILE)
+        .context(format!("Failed to read server config file {}", CONFIG_FILE))?;
+    let config: ServerConfig = toml::de::from_str(&string).context(format!(
+        "Failed to parse server config file {}",
+        CONFIG_FILE
+    ))?;
+    config
+        .validate()
+        .context(format!("Invalid server config file {}", CONFIG_FILE))?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: ServerConfig =
+            toml::de::from_str("motd = \"Hello\"\ntick_rate = 20\n").unwrap();
+        assert_eq!(config.motd, "Hello");
+        assert_eq!(config.tick_rate, 20);
+        assert_eq!(config.max_players, ServerConfig::default().max_players);
+        assert!(config.validate().is_ok());
+
+        assert!(toml::de::from_str::<ServerConfig>("unknown_value = 1\n").is_err());
+        let config: ServerConfig = toml::de::from_str("tick_rate = 0\n").unwrap();
+        assert!(config.validate().is_err());
+        // The operators are profiles, not names
+        let config: ServerConfig = toml::de::from_str("operators = [\"Admin\"]\n").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_reload() {
+        const OPERATOR: &str = "0123456789abcdef0123456789abcdef";
+        let mut config = ServerConfig::default();
+        let new_config = ServerConfig {
+            motd: "New motd".to_owned(),
+            operators: vec![OPERATOR.to_owned()],
+            world_seed: 42,
+            ..ServerConfig::default()
+        };
+        assert_eq!(config.reload_from(new_config), vec!["world_seed"]);
+        assert_eq!(config.motd, "New motd");
+        assert_eq!(config.operators, vec![OPERATOR.to_owned()]);
+        assert_eq!(config.world_seed, 0);
+    }
+}
@@ -0,0 +1,71 @@
+//! The difficulty of the world, saved with the game rules and changed with the `/difficulty` command.
+//! There are no mobs and no hunger yet, so the difficulty doesn't change anything in the game.
+use serde::{Deserialize, Serialize};
+
+/// Appended to the output of the `/difficulty` command
+pub const DIFFICULTY_NOTE: &str = "(it has no effect yet: there are no mobs and no hunger)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    /// No hostile mobs and no hunger
+    Peaceful,
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+impl Difficulty {
+    const ALL: [Difficulty; 4] = [Self::Peaceful, Self::Easy, Self::Normal, Self::Hard];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Peaceful => "peaceful",
+            Self::Easy => "easy",
+            Self::Normal => "normal",
+            Self::Hard => "hard",
+        }
+    }
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|difficulty| difficulty.name() == s)
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "Unknown difficulty {}, it must be peaceful, easy, normal or hard",
+                    s
+                )
+            })
+    }
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_difficulty() {
+        for difficulty in Difficulty::ALL.iter() {
+            assert_eq!(difficulty.to_string().parse(), Ok(*difficulty));
+        }
+        assert!("extreme".parse::<Difficulty>().is_err());
+    }
+}
@@ -0,0 +1,99 @@
+//! Generation of the far tiles, the low-resolution terrain that the clients render beyond their render distance
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use voxel_rs_common::worker::{Worker, WorkerState};
+use voxel_rs_common::{
+    block::Block,
+    registry::Registry,
+    world::{FarTile, FarTilePos, WorldGenerator, FAR_TERRAIN_DISTANCE},
+};
+
+static FAR_TERRAIN_QUEUE_SIZE: usize = 10;
+
+/// The far tiles around the players
+pub struct FarTerrain {
+    /// The generated far tiles
+    tiles: HashMap<FarTilePos, Arc<FarTile>>,
+    /// The far tiles in the generation queue
+    queue: HashSet<FarTilePos>,
+    worker: FarTerrainWorker,
+}
+
+impl FarTerrain {
+    pub fn new(
+        block_registry: Registry<Block>,
+        world_generator: Box<dyn WorldGenerator + Send>,
+    ) -> Self {
+        Self {
+            tiles: HashMap::new(),
+            queue: HashSet::new(),
+            worker: Worker::new(
+                FarTerrainState {
+                    block_registry,
+                    world_generator,
+                },
+                FAR_TERRAIN_QUEUE_SIZE,
+                "Far terrain".into(),
+            ),
+        }
+    }
+
+    /// Fetch the new far tiles from the worker
+    pub fn get_new_tiles(&mut self) {
+        while let Some(tile) = self.worker.get_result() {
+            self.queue.remove(&tile.pos);
+            self.tiles.insert(tile.pos, Arc::new(tile));
+        }
+    }
+
+    /// Get a far tile if it was generated, or start generating it if it is not in the queue yet
+    pub fn get_tile(&mut self, pos: FarTilePos) -> Option<Arc<FarTile>> {
+        if let Some(tile) = self.tiles.get(&pos) {
+            return Some(tile.clone());
+        }
+        if !self.queue.contains(&pos) && self.worker.enqueue(pos).is_ok() {
+            self.queue.insert(pos);
+        }
+        None
+    }
+
+    /// Drop the far tiles that are not close to any player
+    pub fn drop_far_tiles(&mut self, player_tiles: &[FarTilePos]) {
+        self.tiles.retain(|pos, _| {
+            player_tiles
+                .iter()
+                .any(|player_tile| pos.is_close_to(*player_tile))
+        });
+    }
+
+    /// Number of generated far tiles
+    pub fn num_tiles(&self) -> usize {
+        self.tiles.len()
+    }
+}
+
+/// Get the far tiles that must be sent to a player in the far tile `player_tile`, the closest first
+pub fn close_tiles(player_tile: FarTilePos) -> Vec<FarTilePos> {
+    let mut tiles = Vec::new();
+    for i in -FAR_TERRAIN_DISTANCE..=FAR_TERRAIN_DISTANCE {
+        for k in -FAR_TERRAIN_DISTANCE..=FAR_TERRAIN_DISTANCE {
+            tiles.push(player_tile.offset(i, k));
+        }
+    }
+    tiles.sort_by_key(|pos| (pos.px - player_tile.px).pow(2) + (pos.pz - player_tile.pz).pow(2));
+    tiles
+}
+
+pub struct FarTerrainState {
+    block_registry: Registry<Block>,
+    world_generator: Box<dyn WorldGenerator + Send>,
+}
+
+impl WorkerState<FarTilePos, FarTile> for FarTerrainState {
+    fn compute(&mut self, pos: FarTilePos) -> FarTile {
+        self.world_generator
+            .generate_far_tile(pos, &self.block_registry)
+    }
+}
+
+pub type FarTerrainWorker = Worker<FarTilePos, FarTile, FarTerrainState>;
@@ -0,0 +1,151 @@
+//! The game rules: settings of the world that change how the game plays, saved with the world and changed
+//! with the `/gamerule` command while the server runs.
+use crate::difficulty::Difficulty;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// File containing the game rules of the world
+const GAME_RULES_FILE: &str = "save/gamerules.ron";
+/// Highest accepted random tick speed, the random ticks of every loaded chunk run every tick
+const MAX_RANDOM_TICK_SPEED: u32 = 1000;
+
+/// The name and the description of every game rule, shown by `/gamerule`
+pub const GAME_RULES: &[(&str, &str)] = &[
+    (
+        "keep_inventory",
+        "whether the players keep their inventory when they die",
+    ),
+    (
+        "random_tick_speed",
+        "number of random blocks of every loaded chunk that are ticked every tick",
+    ),
+    (
+        "block_update_budget",
+        "number of blocks that the leaf decay can visit every tick",
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameRules {
+    pub keep_inventory: bool,
+    pub random_tick_speed: u32,
+    pub block_update_budget: u32,
+    /// Changed with the `/difficulty` command, it is not a game rule of `/gamerule`
+    pub difficulty: Difficulty,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            keep_inventory: true,
+            random_tick_speed: 3,
+            block_update_budget: 4096,
+            difficulty: Difficulty::default(),
+        }
+    }
+}
+
+impl GameRules {
+    /// Load the game rules of the world, logging the errors
+    pub fn load() -> Self {
+        if !std::path::Path::new(GAME_RULES_FILE).is_file() {
+            return Self::default();
+        }
+        let result = std::fs::read_to_string(GAME_RULES_FILE)
+            .context(format!(
+                "Failed to read game rules file {}",
+                GAME_RULES_FILE
+            ))
+            .and_then(|string| {
+                ron::de::from_str::<GameRules>(&string).context(format!(
+                    "Failed to parse game rules file {}",
+                    GAME_RULES_FILE
+                ))
+            });
+        match result {
+            Ok(rules) => rules,
+            Err(e) => {
+                warn!("{:?}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save the game rules of the world
+    pub fn save(&self) -> Result<()> {
+        info!("Saving game rules");
+        std::fs::create_dir_all("save").context("Failed to create folder save")?;
+        let string = ron::ser::to_string_pretty(self, Default::default())
+            .context("Failed to serialize game rules")?;
+        std::fs::write(GAME_RULES_FILE, string).context(format!(
+            "Failed to write game rules file {}",
+            GAME_RULES_FILE
+        ))
+    }
+
+    /// The value of a game rule, or `None` if there is no game rule with this name
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "keep_inventory" => Some(self.keep_inventory.to_string()),
+            "random_tick_speed" => Some(self.random_tick_speed.to_string()),
+            "block_update_budget" => Some(self.block_update_budget.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Change the value of a game rule. The error is a message for the player.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("Invalid value {} for {}", value, name);
+        match name {
+            "keep_inventory" => self.keep_inventory = value.parse().map_err(|_| invalid())?,
+            "random_tick_speed" => match value.parse() {
+                Ok(speed) if speed <= MAX_RANDOM_TICK_SPEED => self.random_tick_speed = speed,
+                _ => {
+                    return Err(format!(
+                        "{}, it must be between 0 and {}",
+                        invalid(),
+                        MAX_RANDOM_TICK_SPEED
+                    ))
+                }
+            },
+            "block_update_budget" => match value.parse() {
+                Ok(budget) if budget > 0 => self.block_update_budget = budget,
+                _ => return Err(format!("{}, it must be at least 1", invalid())),
+            },
+            _ => return Err(format!("Unknown game rule {}", name)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_rules() {
+        let mut rules = GameRules::default();
+        for (name, _) in GAME_RULES {
+            let value = rules.get(name).unwrap();
+            assert_eq!(rules.set(name, &value), Ok(()));
+        }
+        assert_eq!(rules, GameRules::default());
+
+        assert_eq!(rules.set("keep_inventory", "false"), Ok(()));
+        assert!(!rules.keep_inventory);
+        assert!(rules.set("keep_inventory", "no").is_err());
+        assert_eq!(rules.set("random_tick_speed", "0"), Ok(()));
+        assert_eq!(rules.get("random_tick_speed"), Some("0".to_owned()));
+        assert!(rules.set("random_tick_speed", "-1").is_err());
+        assert!(rules.set("block_update_budget", "0").is_err());
+        assert!(rules.set("do_weather", "true").is_err());
+        assert_eq!(rules.get("do_weather"), None);
+
+        // The rules missing from an old save keep their default value
+        let rules: GameRules = ron::de::from_str("(keep_inventory: false)").unwrap();
+        assert_eq!(rules.random_tick_speed, 3);
+        assert_eq!(rules.difficulty, Difficulty::Normal);
+    }
+}
@@ -0,0 +1,97 @@
+//! Interest management: what is relevant to each player.
+//! A player only receives the movements and the activities of the players in the chunks it can see from its
+//! viewpoint, so that the traffic of a player doesn't grow with the number of players on the server.
+//! The world has a single dimension, so the relevance only depends on the distance.
+use nalgebra::Point3;
+use std::collections::{HashMap, HashSet};
+use voxel_rs_common::entity::SpatialIndex;
+use voxel_rs_common::physics::{player::PhysicsPlayer, AABB};
+use voxel_rs_common::player::{PlayerId, RenderDistance};
+use voxel_rs_common::world::{BlockPos, ChunkPos, CHUNK_SIZE};
+
+/// What is relevant to a player, computed every tick
+#[derive(Debug, Clone)]
+pub struct Interest {
+    /// The chunk of the camera, of the watched player while spectating
+    viewpoint_chunk: ChunkPos,
+    render_distance: RenderDistance,
+    /// The players whose movements and activities are sent to the player, including the player
+    players: HashSet<PlayerId>,
+}
+
+impl Interest {
+    /// Compute what is relevant to the player `id`, who may be watching the player `spectating`.
+    /// The players in sight are looked up in `player_index`, the spatial index of the physics players.
+    /// Returns `None` if the player is not in the physics simulation yet.
+    pub fn compute(
+        id: PlayerId,
+        spectating: Option<PlayerId>,
+        render_distance: RenderDistance,
+        physics_players: &HashMap<PlayerId, PhysicsPlayer>,
+        player_index: &SpatialIndex<PlayerId>,
+    ) -> Option<Self> {
+        let viewpoint = spectating
+            .and_then(|target| physics_players.get(&target))
+            .or_else(|| physics_players.get(&id))?;
+        let viewpoint_chunk =
+            BlockPos::from(viewpoint.get_camera_position()).containing_chunk_pos();
+        let mut players: HashSet<_> = player_index
+            .entities_in_aabb(&visible_region(viewpoint_chunk, render_distance))
+            .into_iter()
+            .filter(|other_id| {
+                physics_players.get(other_id).map_or(false, |other| {
+                    render_distance.is_chunk_visible(
+                        viewpoint_chunk,
+                        BlockPos::from(other.position()).containing_chunk_pos(),
+                    )
+                })
+            })
+            .collect();
+        // The player and the watched player are always relevant
+        for always_relevant in std::iter::once(id).chain(spectating) {
+            if physics_players.contains_key(&always_relevant) {
+                players.insert(always_relevant);
+            }
+        }
+        Some(Self {
+            viewpoint_chunk,
+            render_distance,
+            players,
+        })
+    }
+
+    /// The chunk that the chunks are sent around
+    pub fn viewpoint_chunk(&self) -> ChunkPos {
+        self.viewpoint_chunk
+    }
+
+    pub fn is_player_relevant(&self, id: PlayerId) -> bool {
+        self.players.contains(&id)
+    }
+
+    pub fn is_chunk_relevant(&self, pos: ChunkPos) -> bool {
+        self.render_distance
+            .is_chunk_visible(self.viewpoint_chunk, pos)
+    }
+}
+
+/// The region of the chunks that a player in `viewpoint_chunk` can see, in blocks
+fn visible_region(viewpoint_chunk: ChunkPos, render_distance: RenderDistance) -> AABB<f64> {
+    let size = CHUNK_SIZE as i64;
+    let corner = |px: i64, py: i64, pz: i64| {
+        Point3::new((px * size) as f64, (py * size) as f64, (pz * size) as f64)
+    };
+    let ChunkPos { px, py, pz } = viewpoint_chunk;
+    AABB::new(
+        corner(
+            px - render_distance.x_min as i64,
+            py - render_distance.y_min as i64,
+            pz - render_distance.z_min as i64,
+        ),
+        corner(
+            px + render_distance.x_max as i64 + 1,
+            py + render_distance.y_max as i64 + 1,
+            pz + render_distance.z_max as i64 + 1,
+        ),
+    )
+}
@@ -0,0 +1,163 @@
+//! Decay of the leaves: when the blocks around a decaying block change, it checks that a block sustaining it,
+//! like the wood of a tree, is still close enough. Otherwise it disappears, and so do the decaying blocks next to it,
+//! so that the leaves of a chopped tree vanish.
+use crate::world::World;
+use std::collections::{HashSet, VecDeque};
+use voxel_rs_common::{
+    block::{Block, BlockId},
+    registry::Registry,
+    world::BlockPos,
+};
+
+/// Maximum number of steps through the decaying blocks to reach a sustaining block
+const SUSTAIN_DISTANCE: u32 = 4;
+
+const NEIGHBORS: [(i64, i64, i64); 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+fn neighbor(pos: BlockPos, (dx, dy, dz): (i64, i64, i64)) -> BlockPos {
+    BlockPos::from((pos.px + dx, pos.py + dy, pos.pz + dz))
+}
+
+pub struct LeafDecay {
+    /// Whether every block id decays
+    decays: Vec<bool>,
+    /// Whether every block id sustains the decaying blocks
+    sustains: Vec<bool>,
+    /// The decaying blocks that must be checked, in order
+    pending: VecDeque<BlockPos>,
+    pending_set: HashSet<BlockPos>,
+}
+
+impl LeafDecay {
+    pub fn new(blocks: &Registry<Block>) -> Self {
+        let flags = |flag: fn(&Block) -> bool| -> Vec<bool> {
+            (0..blocks.get_number_of_ids())
+                .map(|id| flag(blocks.get_value_by_id(id).unwrap()))
+                .collect()
+        };
+        Self {
+            decays: flags(Block::decays),
+            sustains: flags(Block::sustains_decaying),
+            pending: VecDeque::new(),
+            pending_set: HashSet::new(),
+        }
+    }
+
+    fn decays(&self, block: BlockId) -> bool {
+        self.decays.get(block as usize).cloned().unwrap_or(false)
+    }
+
+    fn sustains(&self, block: BlockId) -> bool {
+        self.sustains.get(block as usize).cloned().unwrap_or(false)
+    }
+
+    /// Check the decaying blocks next to the blocks that changed
+    pub fn add_block_updates(&mut self, world: &World, updates: Vec<BlockPos>) {
+        for pos in updates {
+            for &direction in NEIGHBORS.iter() {
+                let neighbor = neighbor(pos, direction);
+                if self.decays(world.get_block(neighbor)) && self.pending_set.insert(neighbor) {
+                    self.pending.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    /// Check the pending decaying blocks, and return the ones that must disappear.
+    /// The searches stop after visiting `max_visited_blocks` blocks, the remaining checks wait for the next ticks.
+    pub fn tick(&mut self, world: &World, max_visited_blocks: usize) -> Vec<(BlockPos, BlockId)> {
+        let mut changes = Vec::new();
+        let mut visited = 0;
+        while visited < max_visited_blocks {
+            let pos = match self.pending.pop_front() {
+                Some(pos) => pos,
+                None => break,
+            };
+            self.pending_set.remove(&pos);
+            // The block may have changed since it was added, or its chunk may be unloaded
+            if !self.decays(world.get_block(pos)) {
+                continue;
+            }
+            let (sustained, visited_blocks) = self.is_sustained(pos, |pos| world.get_block(pos));
+            visited += visited_blocks;
+            if !sustained {
+                // TODO: drop the items of the decayed blocks in the world
+                changes.push((pos, 0));
+            }
+        }
+        changes
+    }
+
+    /// Search a sustaining block close enough to `pos` through the decaying blocks.
+    /// Return whether there is one, and the number of visited blocks.
+    fn is_sustained(
+        &self,
+        pos: BlockPos,
+        get_block: impl Fn(BlockPos) -> BlockId,
+    ) -> (bool, usize) {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(pos);
+        queue.push_back((pos, 0));
+        while let Some((pos, distance)) = queue.pop_front() {
+            for &direction in NEIGHBORS.iter() {
+                let neighbor = neighbor(pos, direction);
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let block = get_block(neighbor);
+                if self.sustains(block) {
+                    return (true, visited.len());
+                }
+                if distance + 1 < SUSTAIN_DISTANCE && self.decays(block) {
+                    queue.push_back((neighbor, distance + 1));
+                }
+            }
+        }
+        (false, visited.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const LEAVES: BlockId = 1;
+    const WOOD: BlockId = 2;
+
+    fn leaf_decay() -> LeafDecay {
+        LeafDecay {
+            decays: vec![false, true, false],
+            sustains: vec![false, false, true],
+            pending: VecDeque::new(),
+            pending_set: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_sustain_distance() {
+        let decay = leaf_decay();
+        // A line of leaves ending with a piece of wood
+        let mut blocks = HashMap::new();
+        for x in 0..6 {
+            blocks.insert(BlockPos::from((x, 0, 0)), LEAVES);
+        }
+        blocks.insert(BlockPos::from((6, 0, 0)), WOOD);
+        let get_block = |pos| blocks.get(&pos).cloned().unwrap_or(0);
+        assert!(decay.is_sustained(BlockPos::from((5, 0, 0)), get_block).0);
+        assert!(decay.is_sustained(BlockPos::from((2, 0, 0)), get_block).0);
+        assert!(!decay.is_sustained(BlockPos::from((1, 0, 0)), get_block).0);
+        // Without the wood, nothing is sustained
+        blocks.remove(&BlockPos::from((6, 0, 0)));
+        let get_block = |pos| blocks.get(&pos).cloned().unwrap_or(0);
+        assert!(!decay.is_sustained(BlockPos::from((5, 0, 0)), get_block).0);
+    }
+}
@@ -1,28 +1,95 @@
-use crate::world::World;
-use anyhow::Result;
+use crate::biomes::BiomeMaps;
+use crate::boss_bars::BossBars;
+use crate::command::{parse_command, Command, CommandPermission, COMMAND_USAGES};
+use crate::config::ServerConfig;
+use crate::difficulty::DIFFICULTY_NOTE;
+use crate::far_terrain::FarTerrain;
+use crate::game_rules::{GameRules, GAME_RULES};
+use crate::interest::Interest;
+use crate::leaf_decay::LeafDecay;
+use crate::player_save::{adopt_legacy_host_save, load_player, save_player, PlayerSave};
+use crate::portal::{PortalDwell, PORTAL_BLOCK};
+use crate::pregen::Pregen;
+use crate::rails::RailShaping;
+use crate::random_tick::RandomTicks;
+use crate::save::RegionRepair;
+use crate::scheduler::{BlockTask, Tick};
+use crate::scoreboard::Scoreboard;
+use crate::teams::Teams;
+use crate::tickets::{ChunkRegion, ChunkTickets, TicketKind, MAX_FORCELOAD_CHUNKS};
+use crate::world::{ChunkUpdate, ChunkVersions, World};
+use crate::world_edit::{volume, BlockChanges, Clipboard, EditHistory, Selection, MAX_EDIT_VOLUME};
+use anyhow::{bail, Context, Result};
 use log::info;
 use nalgebra::{Point3, Vector3};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::time::Instant;
-use voxel_rs_common::block::BlockId;
+use std::time::{Duration, Instant};
+use voxel_rs_common::animation::EntityActivity;
+use voxel_rs_common::block::entity::{BlockEntity, BlockEntityKind, Portal, Sign};
+use voxel_rs_common::block::{Block, BlockId, BlockShape, BlockType, HorizontalFace, MAX_LAYERS};
+use voxel_rs_common::inventory::{
+    Inventory, InventoryWindow, ItemStack, HOTBAR_SIZE, MAX_STACK_SIZE,
+};
+use voxel_rs_common::item::{mining_speed, Item, ItemTool};
+use voxel_rs_common::metrics::{observe_histogram, set_gauge};
 use voxel_rs_common::physics::player::PhysicsPlayer;
-use voxel_rs_common::time::BreakdownCounter;
+use voxel_rs_common::skin::PlayerSkin;
+use voxel_rs_common::stats::PlayerStats;
+use voxel_rs_common::time::{BreakdownCounter, RateCounter};
 use voxel_rs_common::{
-    data::load_data,
-    debug::{send_debug_info, send_perf_breakdown},
+    content::DefaultContent,
+    data::{compute_block_colors, load_data, Data},
+    debug::{
+        add_chunk_stat, replace_chunk_stats, send_debug_info, send_perf_breakdown, ChunkMetric,
+    },
+    entity::SpatialIndex,
     network::{
+        clock::ServerClock,
         messages::{ToClient, ToServer},
         Server, ServerEvent,
     },
     physics::simulation::ServerPhysicsSimulation,
-    player::{CloseChunks, RenderDistance},
-    world::{BlockPos, ChunkPos},
+    physics::snapshot::SnapshotEncoder,
+    physics::vehicle::VehicleKind,
+    physics::BlockContainer,
+    player::{
+        is_default_player_name, is_valid_profile, sanitize_player_name, CloseChunks, GameMode,
+        PlayerId, PlayerListEntry, RenderDistance, Waypoint, DEATH_WAYPOINT_NAME,
+    },
+    registry::Registry,
+    world::{BlockPos, ChunkPos, ChunkPosXZ, FarTilePos},
+    world_view::{WorldView, WorldViewMut},
     worldgen::DefaultWorldGenerator,
 };
 
+mod biomes;
+mod boss_bars;
+mod command;
+mod config;
+mod difficulty;
+mod far_terrain;
+mod game_rules;
+mod interest;
+mod leaf_decay;
 mod light;
+mod map_export;
+mod metrics;
+mod migration;
+mod player_save;
+mod portal;
+mod pregen;
+mod rails;
+mod random_tick;
+mod regions;
+mod save;
+mod scheduler;
+mod schematic;
+mod scoreboard;
+mod teams;
+mod tickets;
 mod world;
+mod world_edit;
 mod worldgen;
 
 // TODO: refactor
@@ -37,10 +104,55 @@ const D: [[i64; 3]; 6] = [
 
 /// The data that the server stores for every player.
 pub struct PlayerData {
-    loaded_chunks: HashMap<ChunkPos, u64>,
+    loaded_chunks: HashMap<ChunkPos, ChunkVersions>,
+    /// The far tiles that were sent to the player
+    far_tiles: HashSet<FarTilePos>,
+    /// The chunk columns whose biome map was sent to the player
+    biome_maps: HashSet<ChunkPosXZ>,
     render_distance: RenderDistance,
     close_chunks: CloseChunks,
     block_to_place: BlockId,
+    inventory: Inventory,
+    /// The slot of the hotbar whose item the player holds
+    selected_slot: usize,
+    /// The stack that the player is moving around in the inventory screen
+    cursor_stack: Option<ItemStack>,
+    /// The position of the container the player has currently opened
+    open_container: Option<BlockPos>,
+    waypoints: Vec<Waypoint>,
+    stats: PlayerStats,
+    /// The position of the player at the previous tick, used to compute the distance walked
+    last_position: Option<Point3<f64>>,
+    game_mode: GameMode,
+    /// The block that the player is breaking in survival mode, and for how many seconds
+    breaking: Option<(BlockPos, f32)>,
+    /// When the player last started breaking a block or hit another player
+    last_attack: Option<Instant>,
+    /// What the player is doing, sent to the clients to animate the player
+    activity: EntityActivity,
+    selection: Selection,
+    clipboard: Option<Clipboard>,
+    edit_history: EditHistory,
+    /// The skin sent by the client, relayed to the other players
+    skin: Option<PlayerSkin>,
+    /// The name shown to the other players, chosen when joining
+    name: String,
+    /// The random secret sent by the client that identifies the player across sessions, the player is saved under it
+    profile: String,
+    /// The last measured round-trip time, in milliseconds
+    ping: Option<u32>,
+    /// The number of the last ping sent to the player, and when it was sent
+    pending_ping: Option<(u32, Instant)>,
+    /// The player whose viewpoint the player is watching. The chunks are sent around that player.
+    spectating: Option<PlayerId>,
+    /// The physics snapshots sent to the player
+    snapshots: SnapshotEncoder,
+    /// What is relevant to the player, `None` until the player is in the physics simulation
+    interest: Option<Interest>,
+    /// How long the player has been standing in a portal
+    portal_dwell: PortalDwell,
+    /// Whether the client asked to pause the game, that only pauses while the player is alone on the server
+    wants_pause: bool,
 }
 
 impl Default for PlayerData {
@@ -49,58 +161,394 @@ impl Default for PlayerData {
         let close_chunks = CloseChunks::new(&render_distance);
         Self {
             loaded_chunks: Default::default(),
+            far_tiles: Default::default(),
+            biome_maps: Default::default(),
             render_distance,
             close_chunks,
             block_to_place: 1,
+            inventory: Inventory::new(PLAYER_INVENTORY_SIZE),
+            selected_slot: 0,
+            cursor_stack: None,
+            open_container: None,
+            waypoints: Vec::new(),
+            stats: PlayerStats::default(),
+            last_position: None,
+            game_mode: GameMode::default(),
+            breaking: None,
+            last_attack: None,
+            activity: EntityActivity::default(),
+            selection: Selection::default(),
+            clipboard: None,
+            edit_history: EditHistory::default(),
+            skin: None,
+            name: String::new(),
+            profile: String::new(),
+            ping: None,
+            pending_ping: None,
+            spectating: None,
+            snapshots: SnapshotEncoder::new(),
+            interest: None,
+            portal_dwell: PortalDwell::default(),
+            wants_pause: false,
+        }
+    }
+}
+
+impl PlayerData {
+    /// Get the part of the player data that is saved to disk
+    fn to_save(&self) -> PlayerSave {
+        PlayerSave {
+            name: self.name.clone(),
+            waypoints: self.waypoints.clone(),
+            stats: self.stats.clone(),
+            game_mode: self.game_mode,
+            inventory: Some(self.inventory.clone()),
+            cursor_stack: self.cursor_stack,
         }
     }
+
+    /// Get the tool behavior of the held item, if it is a tool
+    fn held_tool(&self, item_registry: &Registry<Item>) -> Option<ItemTool> {
+        let stack = self.inventory.get(self.selected_slot)?;
+        item_registry.get_value_by_id(stack.item)?.tool()
+    }
+
+    /// Get how many times faster the player breaks `block` than its break time, with the held item
+    fn mining_speed(
+        &self,
+        block: BlockId,
+        item_registry: &Registry<Item>,
+        block_registry: &Registry<Block>,
+    ) -> f32 {
+        let held = self
+            .inventory
+            .get(self.selected_slot)
+            .and_then(|stack| item_registry.get_value_by_id(stack.item));
+        mining_speed(held, block, block_registry)
+    }
 }
 
+/// Number of slots in the player inventory
+const PLAYER_INVENTORY_SIZE: usize = 36;
+/// Number of every item given to the new players
+const STARTING_ITEMS: u32 = 16;
+/// Players that fall below this height die
+const VOID_HEIGHT: f64 = -256.0;
+/// Maximum number of chunks saved per tick during an autosave, to avoid hitches
+const AUTOSAVE_CHUNKS_PER_TICK: usize = 4;
+/// Maximum number of saved chunks checked per tick by `/repair-region`, to avoid hitches
+const REPAIR_CHUNKS_PER_TICK: usize = 8;
+/// How long the attack animation is played when a player hits a block, and the time between two hits on players
+const ATTACK_DURATION: Duration = Duration::from_millis(500);
+/// How far a player can hit the other players
+const PLAYER_HIT_DISTANCE: f64 = 4.0;
+/// Minimum horizontal distance moved in one tick for a player to be walking
+const WALKING_DISTANCE: f64 = 0.01;
+/// Color of the waypoint placed where the player last died
+const DEATH_WAYPOINT_COLOR: [f32; 4] = [0.8, 0.1, 0.1, 1.0];
+/// How often the players are pinged and the player list is sent
+const PLAYER_LIST_INTERVAL: Duration = Duration::from_secs(1);
+/// How many seconds the titles of `/title` fade in, stay and fade out
+const TITLE_TIMES: (f32, f32, f32) = (0.5, 3.5, 1.0);
+
 /// Start a new server instance.
-pub fn launch_server(mut server: Box<dyn Server>) -> Result<()> {
+pub fn launch_server(server: Box<dyn Server>) -> Result<()> {
+    let game_data = load_data("data".into(), &DefaultContent)?;
+    launch_server_with_data(server, game_data)
+}
+
+/// Launch the server with data that was already loaded, for example by the client of an integrated server
+pub fn launch_server_with_data(mut server: Box<dyn Server>, game_data: Data) -> Result<()> {
     info!("Starting server");
 
     let mut server_timing = BreakdownCounter::new();
 
-    // Load data
-    let game_data = load_data("data".into())?;
+    let mut config = config::load_config()?;
+    info!("Server config: {:?}", config);
+    if config.metrics_enabled {
+        metrics::start_metrics_endpoint(&config.metrics_address)?;
+    }
+
+    let report = migration::migrate_save(
+        &game_data.blocks,
+        &game_data.items,
+        config.migration_dry_run,
+        config.backups_kept,
+    )
+    .context("Failed to upgrade the save")?;
+    if !report.is_empty() {
+        for line in report.lines() {
+            info!("Save upgrade: {}", line);
+        }
+        if config.migration_dry_run {
+            bail!("Dry run of the save upgrade, the save was not modified. Disable migration_dry_run to upgrade it");
+        }
+    }
+
+    // Used to convert between blocks and the colors of .vox schematics
+    let block_colors = compute_block_colors(&game_data.meshes, &game_data.texture_atlas);
 
     let mut world = World::new(
         game_data.blocks.clone(),
-        Box::new(DefaultWorldGenerator::new(&game_data.blocks.clone())),
+        Box::new(DefaultWorldGenerator::new(
+            &game_data.blocks,
+            game_data.worldgen_blocks,
+            config.world_seed,
+        )),
+    );
+    let mut far_terrain = FarTerrain::new(
+        game_data.blocks.clone(),
+        Box::new(DefaultWorldGenerator::new(
+            &game_data.blocks,
+            game_data.worldgen_blocks,
+            config.world_seed,
+        )),
     );
+    let mut biome_maps = BiomeMaps::new(Box::new(DefaultWorldGenerator::new(
+        &game_data.blocks,
+        game_data.worldgen_blocks,
+        config.world_seed,
+    )));
+    let mut random_ticks = RandomTicks::new(&game_data.blocks);
+    let mut leaf_decay = LeafDecay::new(&game_data.blocks);
+    let mut rail_shaping = RailShaping::new(&game_data.blocks);
+    let spawn_chunk = BlockPos::from(PhysicsPlayer::default().position()).containing_chunk_pos();
+    let mut tickets = ChunkTickets::new(spawn_chunk);
+    tickets.load_forceloads();
+    let mut scoreboard = Scoreboard::load();
+    let mut teams = Teams::load();
+    let mut boss_bars = BossBars::default();
+    let mut game_rules = GameRules::load();
+    let mut pregen = if config.pregen_radius > 0 {
+        info!(
+            "Pre-generating the chunks within {} chunks of the spawn",
+            config.pregen_radius
+        );
+        Some(Pregen::new(spawn_chunk, config.pregen_radius, None))
+    } else {
+        None
+    };
+    let mut region_repair: Option<RegionRepair> = None;
     let mut players = HashMap::new();
-    let mut physics_simulation = ServerPhysicsSimulation::new();
+    // Players rejected when connecting or joining, their messages are ignored
+    let mut rejected_players = HashSet::new();
+    // Players that are connected but didn't send their name and profile yet
+    let mut joining = HashSet::new();
+    // The server timestamps are measured on this clock, that the clients synchronize with
+    let server_clock = ServerClock::new();
+    let mut physics_simulation = ServerPhysicsSimulation::new(server_clock.now());
     let mut close_chunks_merged = Vec::new();
+    let region_pool = regions::RegionPool::new(config.simulation_threads)?;
+    let mut num_regions = 0;
+    let mut last_tick = Instant::now();
+    let mut blocks_changed = RateCounter::new();
+    let mut chunk_updates = RateCounter::new();
+    let tick_duration = Duration::from_secs_f64(1.0 / config.tick_rate as f64);
+    let mut next_tick = Instant::now();
+    let mut last_autosave = Instant::now();
+    let mut last_backup = Instant::now();
+    let mut last_player_list = Instant::now();
+    let mut next_ping: u32 = 0;
+    let mut ticks = RateCounter::new();
+    // While the game is paused, the messages are handled and the chunks are sent but the world doesn't change
+    let mut game_paused = false;
 
     info!("Server initialized successfully! Starting server loop");
     loop {
         server_timing.start_frame();
+        let tick_start = Instant::now();
 
         // Handle messages
         loop {
             match server.receive_event() {
                 ServerEvent::NoEvent => break,
                 ServerEvent::ClientConnected(id) => {
+                    if players.len() + joining.len() >= config.max_players as usize {
+                        info!("Client {} rejected, the server is full", id);
+                        server.send(
+                            id,
+                            ToClient::ChatMessage(format!(
+                                "The server is full ({} players)",
+                                config.max_players
+                            )),
+                        );
+                        rejected_players.insert(id);
+                        continue;
+                    }
                     info!("Client connected to the server!");
-                    physics_simulation.set_player_input(id, Default::default());
-                    players.insert(id, PlayerData::default());
                     server.send(id, ToClient::GameData(game_data.clone()));
                     server.send(id, ToClient::CurrentId(id));
+                    joining.insert(id);
                 }
                 ServerEvent::ClientDisconnected(id) => {
+                    if rejected_players.remove(&id) || joining.remove(&id) {
+                        continue;
+                    }
                     physics_simulation.remove(id);
-                    players.remove(&id);
+                    tickets.remove_ticket(TicketKind::Player(id));
+                    if let Some(player) = players.remove(&id) {
+                        save_player_data(id, &player);
+                        // The players watching this player go back to their own camera
+                        let spectators: Vec<_> = players
+                            .iter()
+                            .filter(|(_, other)| other.spectating == Some(id))
+                            .map(|(&other_id, _)| other_id)
+                            .collect();
+                        for other_id in spectators {
+                            let message =
+                                set_spectating(&mut *server, &mut players, other_id, None);
+                            server.send(other_id, ToClient::ChatMessage(message));
+                        }
+                        if player.skin.is_some() {
+                            for &other_id in players.keys() {
+                                server.send(other_id, ToClient::UpdateSkin(id, None));
+                            }
+                        }
+                    }
+                }
+                ServerEvent::ClientMessage(id, _) if rejected_players.contains(&id) => {}
+                ServerEvent::ClientMessage(id, ToServer::Join(name, profile))
+                    if joining.contains(&id) =>
+                {
+                    joining.remove(&id);
+                    if !is_valid_profile(&profile) {
+                        info!("Client {} rejected, its profile is invalid", id);
+                        server.send(
+                            id,
+                            ToClient::ChatMessage("Your player profile is invalid".to_owned()),
+                        );
+                        rejected_players.insert(id);
+                        continue;
+                    }
+                    if players.values().any(|player| player.profile == profile) {
+                        info!("Client {} rejected, its profile is already playing", id);
+                        server.send(
+                            id,
+                            ToClient::ChatMessage(
+                                "You are already playing on this server".to_owned(),
+                            ),
+                        );
+                        rejected_players.insert(id);
+                        continue;
+                    }
+                    if server.is_host(id) {
+                        adopt_legacy_host_save(&profile);
+                    }
+                    physics_simulation.set_player_input(id, Default::default());
+                    let mut player_data = PlayerData::default();
+                    player_data.name =
+                        match choose_player_name(&name, id, &profile, &players, &config) {
+                            Ok(name) => name,
+                            Err(message) => {
+                                let name = sanitize_player_name("", id);
+                                server.send(
+                                    id,
+                                    ToClient::ChatMessage(format!(
+                                        "{}, you are named {}",
+                                        message, name
+                                    )),
+                                );
+                                name
+                            }
+                        };
+                    let save = match load_player(&profile) {
+                        Ok(save) => save.unwrap_or_default(),
+                        Err(e) => {
+                            // Don't give the starting items again to a player whose save is broken
+                            log::warn!("{:?}", e);
+                            PlayerSave {
+                                inventory: Some(Inventory::new(PLAYER_INVENTORY_SIZE)),
+                                ..PlayerSave::default()
+                            }
+                        }
+                    };
+                    player_data.profile = profile;
+                    player_data.waypoints = save.waypoints;
+                    player_data.stats = save.stats;
+                    player_data.game_mode = save.game_mode;
+                    match save.inventory {
+                        Some(inventory) => player_data.inventory = inventory,
+                        None => {
+                            for item in 0..game_data.items.get_number_of_ids() {
+                                player_data
+                                    .inventory
+                                    .insert(ItemStack::new(item, STARTING_ITEMS));
+                            }
+                        }
+                    }
+                    player_data.cursor_stack = save.cursor_stack;
+                    send_inventory(&mut *server, id, &player_data);
+                    server.send(id, ToClient::UpdateWaypoints(player_data.waypoints.clone()));
+                    server.send(id, ToClient::SetGameMode(player_data.game_mode));
+                    server.send(id, ToClient::ScoreboardUpdate(scoreboard.display()));
+                    for (bar_id, bar) in boss_bars.bars() {
+                        server.send(id, ToClient::BossBar(bar_id.clone(), Some(bar.clone())));
+                    }
+                    if !config.motd.is_empty() {
+                        server.send(id, ToClient::ChatMessage(config.motd.clone()));
+                    }
+                    for (&other_id, other) in players.iter() {
+                        if let Some(skin) = &other.skin {
+                            server.send(id, ToClient::UpdateSkin(other_id, Some(skin.clone())));
+                        }
+                    }
+                    info!(
+                        "Player {} joined as {} with profile {}",
+                        id, player_data.name, player_data.profile
+                    );
+                    players.insert(id, player_data);
                 }
+                // The players that didn't join yet, or that were rejected, can't play
+                ServerEvent::ClientMessage(id, _) if !players.contains_key(&id) => {}
                 ServerEvent::ClientMessage(id, message) => match message {
-                    ToServer::UpdateInput(input) => {
-                        assert!(players.contains_key(&id));
+                    ToServer::UpdateInput(mut input) => {
+                        let player = players.get(&id).unwrap();
+                        if !player.game_mode.can_fly() {
+                            input.flying = false;
+                        }
                         physics_simulation.set_player_input(id, input);
                     }
+                    ToServer::SetSkin(skin) => {
+                        if !skin.is_valid() {
+                            log::warn!("Player {} sent an invalid skin, ignoring it", id);
+                            continue;
+                        }
+                        for &other_id in players.keys().filter(|&&other_id| other_id != id) {
+                            server.send(other_id, ToClient::UpdateSkin(id, Some(skin.clone())));
+                        }
+                        players.get_mut(&id).unwrap().skin = Some(skin);
+                    }
+                    ToServer::Join(..) => {
+                        log::warn!("Player {} tried to join again, ignoring it", id);
+                    }
+                    ToServer::Pong(number) => {
+                        let player = players.get_mut(&id).unwrap();
+                        if let Some((sent_number, sent_at)) = player.pending_ping {
+                            if sent_number == number {
+                                player.ping = Some(sent_at.elapsed().as_millis() as u32);
+                                player.pending_ping = None;
+                            }
+                        }
+                    }
+                    ToServer::AckPhysics(sequence) => {
+                        players
+                            .get_mut(&id)
+                            .unwrap()
+                            .snapshots
+                            .acknowledge(sequence);
+                    }
+                    ToServer::TimeRequest(client_time) => {
+                        server.send(id, ToClient::TimeResponse(client_time, server_clock.now()));
+                    }
+                    ToServer::Spectate(target) => {
+                        let message = set_spectating(&mut *server, &mut players, id, target);
+                        server.send(id, ToClient::ChatMessage(message));
+                    }
                     ToServer::SetRenderDistance(render_distance) => {
                         assert!(players.contains_key(&id));
                         players.entry(id).and_modify(move |player_data| {
-                            player_data.render_distance = render_distance
+                            player_data.render_distance =
+                                render_distance.capped(config.view_distance_cap)
                         });
                     }
                     ToServer::BreakBlock(player_pos, yaw, pitch) => {
@@ -109,18 +557,63 @@ pub fn launch_server(mut server: Box<dyn Server>) -> Result<()> {
                         let y = yaw.to_radians();
                         let p = pitch.to_radians();
                         let dir = Vector3::new(-y.sin() * p.cos(), p.sin(), -y.cos() * p.cos());
+                        // Hit the pointed player, if no block is in front of them
+                        let player_index = SpatialIndex::from_entities(
+                            physics_simulation
+                                .get_state()
+                                .physics_state
+                                .players
+                                .iter()
+                                .filter(|(&other, _)| other != id)
+                                .map(|(&other, data)| (other, data.aabb)),
+                        );
+                        let hit_player = player_index
+                            .raycast_entities(
+                                physics_player.get_camera_position(),
+                                dir,
+                                PLAYER_HIT_DISTANCE,
+                            )
+                            .first()
+                            .copied()
+                            .filter(|&(_, distance)| {
+                                physics_player
+                                    .get_pointed_at(dir, distance, &WorldView::new(&world))
+                                    .is_none()
+                            });
+                        if let Some((target, _)) = hit_player {
+                            hit(&mut *server, &mut players, &teams, id, target);
+                            continue;
+                        }
                         // TODO: don't hardcode max dist
                         if let Some((block, _face)) =
-                            physics_player.get_pointed_at(dir, 10.0, &world)
+                            physics_player.get_pointed_at(dir, 10.0, &WorldView::new(&world))
                         {
-                            let chunk_pos = block.containing_chunk_pos();
-                            if let Some(chunk) = world.get_chunk(chunk_pos) {
-                                let mut new_chunk = (*chunk).clone();
-                                new_chunk.set_block_at(block.pos_in_containing_chunk(), 0);
-                                world.set_chunk(Arc::new(new_chunk));
+                            let player = players.get_mut(&id).unwrap();
+                            if player.held_tool(&game_data.items) == Some(ItemTool::Selection) {
+                                if player.selection.first != Some(block) {
+                                    player.selection.first = Some(block);
+                                    send_selection(&mut *server, id, player);
+                                }
+                            } else if player.game_mode.breaks_instantly() {
+                                player.last_attack = Some(Instant::now());
+                                if break_block(
+                                    &mut *server,
+                                    &mut world,
+                                    &mut players,
+                                    &game_data.blocks,
+                                    id,
+                                    block,
+                                ) {
+                                    blocks_changed.add(1);
+                                }
+                            } else {
+                                player.breaking = Some((block, 0.0));
                             }
                         }
                     }
+                    ToServer::StopBreaking => {
+                        players.get_mut(&id).unwrap().breaking = None;
+                    }
                     ToServer::SelectBlock(player_pos, yaw, pitch) => {
                         // TODO: check player pos and block
                         let physics_player = PhysicsPlayer::from_coords(Point3::from(player_pos));
@@ -129,7 +622,7 @@ pub fn launch_server(mut server: Box<dyn Server>) -> Result<()> {
                         let dir = Vector3::new(-y.sin() * p.cos(), p.sin(), -y.cos() * p.cos());
                         // TODO: don't hardcode max dist
                         if let Some((block, _face)) =
-                            physics_player.get_pointed_at(dir, 10.0, &world)
+                            physics_player.get_pointed_at(dir, 10.0, &WorldView::new(&world))
                         {
                             // TODO: careful with more complicated blocks
                             players.get_mut(&id).unwrap().block_to_place = world.get_block(block);
@@ -141,26 +634,470 @@ pub fn launch_server(mut server: Box<dyn Server>) -> Result<()> {
                         let y = yaw.to_radians();
                         let p = pitch.to_radians();
                         let dir = Vector3::new(-y.sin() * p.cos(), p.sin(), -y.cos() * p.cos());
+                        // Ride the clicked vehicle, if no block is in front of it
+                        let vehicle_index = SpatialIndex::from_entities(
+                            physics_simulation
+                                .get_state()
+                                .physics_state
+                                .vehicles
+                                .iter()
+                                .map(|(&vehicle, data)| (vehicle, data.body.aabb)),
+                        );
+                        let clicked_vehicle = vehicle_index
+                            .raycast_entities(physics_player.get_camera_position(), dir, 10.0)
+                            .first()
+                            .copied()
+                            .filter(|&(_, distance)| {
+                                physics_player
+                                    .get_pointed_at(dir, distance, &WorldView::new(&world))
+                                    .is_none()
+                            });
+                        if let Some((vehicle, _)) = clicked_vehicle {
+                            physics_simulation.mount(id, vehicle);
+                            continue;
+                        }
                         // TODO: don't hardcode max dist
                         if let Some((mut block, face)) =
-                            physics_player.get_pointed_at(dir, 10.0, &world)
+                            physics_player.get_pointed_at(dir, 10.0, &WorldView::new(&world))
                         {
-                            block.px += D[face][0];
-                            block.py += D[face][1];
-                            block.pz += D[face][2];
+                            let player = players.get_mut(&id).unwrap();
+                            match player.held_tool(&game_data.items) {
+                                Some(ItemTool::Selection) => {
+                                    player.selection.second = Some(block);
+                                    send_selection(&mut *server, id, player);
+                                    continue;
+                                }
+                                Some(tool @ ItemTool::Boat) | Some(tool @ ItemTool::Cart) => {
+                                    // The boats go on top of the clicked block, the carts in the clicked rail
+                                    let above = BlockPos::from((block.px, block.py + 1, block.pz));
+                                    let spawn = match tool {
+                                        ItemTool::Boat if world.get_block(above) == 0 => {
+                                            Some((VehicleKind::Boat, above))
+                                        }
+                                        ItemTool::Cart if world.get_rail(block).is_some() => {
+                                            Some((VehicleKind::Cart, block))
+                                        }
+                                        _ => None,
+                                    };
+                                    if let Some((kind, pos)) = spawn {
+                                        physics_simulation.spawn_vehicle(
+                                            kind,
+                                            Point3::new(
+                                                pos.px as f64 + 0.5,
+                                                pos.py as f64,
+                                                pos.pz as f64 + 0.5,
+                                            ),
+                                            yaw,
+                                        );
+                                        consume_held_item(&mut *server, player, id);
+                                    }
+                                    continue;
+                                }
+                                Some(tool @ ItemTool::Hoe) | Some(tool @ ItemTool::Fertilizer) => {
+                                    if use_farming_tool(
+                                        &mut *server,
+                                        &mut world,
+                                        &mut players,
+                                        &game_data.blocks,
+                                        id,
+                                        block,
+                                        tool,
+                                    ) {
+                                        blocks_changed.add(1);
+                                    }
+                                    continue;
+                                }
+                                None => (),
+                            }
+                            // Interact with the block entity if there is one
+                            if let Some(block_entity) = world.get_block_entity(block) {
+                                match block_entity {
+                                    BlockEntity::Sign(sign) => server.send(
+                                        id,
+                                        ToClient::OpenSignEditor(block, sign.text.clone()),
+                                    ),
+                                    BlockEntity::CommandBlock(command_block) => {
+                                        let message = if player_permission(
+                                            &*server, &config, id, player,
+                                        ) >= command_block_edit_permission(&config)
+                                        {
+                                            ToClient::OpenCommandBlockEditor(
+                                                block,
+                                                command_block.command.clone(),
+                                            )
+                                        } else {
+                                            ToClient::ChatMessage(
+                                                "You don't have the permission to edit command blocks"
+                                                    .to_owned(),
+                                            )
+                                        };
+                                        server.send(id, message);
+                                    }
+                                    _ => (),
+                                }
+                                if let Some(inventory) = block_entity.inventory() {
+                                    let player = players.get_mut(&id).unwrap();
+                                    player.open_container = Some(block);
+                                    server.send(
+                                        id,
+                                        ToClient::OpenContainer(block, inventory.clone()),
+                                    );
+                                    if let BlockEntity::Furnace(furnace) = block_entity {
+                                        server.send(
+                                            id,
+                                            ToClient::UpdateFurnaceProgress(
+                                                block,
+                                                furnace.progress(),
+                                            ),
+                                        );
+                                    }
+                                }
+                                continue;
+                            }
+                            // Layers of the same kind pile up when placed on top of each other
+                            let stacked_layer = if D[face][1] == 1 {
+                                let block_to_place = players.get(&id).unwrap().block_to_place;
+                                let placed =
+                                    game_data.blocks.get_value_by_id(block_to_place as u32);
+                                let clicked = game_data
+                                    .blocks
+                                    .get_value_by_id(world.get_block(block) as u32);
+                                match (placed, clicked) {
+                                    (Some(placed), Some(clicked)) => match clicked.shape() {
+                                        BlockShape::Layer(layers)
+                                            if layers < MAX_LAYERS
+                                                && placed
+                                                    .variant_with_layers(1, &game_data.blocks)
+                                                    == clicked.variant_with_layers(
+                                                        1,
+                                                        &game_data.blocks,
+                                                    ) =>
+                                        {
+                                            clicked
+                                                .variant_with_layers(layers + 1, &game_data.blocks)
+                                        }
+                                        _ => None,
+                                    },
+                                    _ => None,
+                                }
+                            } else {
+                                None
+                            };
+                            if stacked_layer.is_none() {
+                                block.px += D[face][0];
+                                block.py += D[face][1];
+                                block.pz += D[face][2];
+                            }
                             let chunk_pos = block.containing_chunk_pos();
                             if let Some(chunk) = world.get_chunk(chunk_pos) {
                                 let mut new_chunk = (*chunk).clone();
-                                new_chunk.set_block_at(
-                                    block.pos_in_containing_chunk(),
-                                    players.get(&id).unwrap().block_to_place,
-                                );
+                                let mut block_to_place = players.get(&id).unwrap().block_to_place;
+                                // Ladders are attached to the side of the block that was clicked
+                                if let Some(block_data) =
+                                    game_data.blocks.get_value_by_id(block_to_place as u32)
+                                {
+                                    // Crops are only planted on fertile blocks
+                                    if block_data.crop_stage().is_some() {
+                                        let below =
+                                            BlockPos::from((block.px, block.py - 1, block.pz));
+                                        let is_fertile = game_data
+                                            .blocks
+                                            .get_value_by_id(world.get_block(below) as u32)
+                                            .map_or(false, |below| below.is_fertile());
+                                        if !is_fertile {
+                                            continue;
+                                        }
+                                    }
+                                    if let BlockShape::Ladder(_) = block_data.shape() {
+                                        match HorizontalFace::from_direction(
+                                            -D[face][0],
+                                            -D[face][2],
+                                        ) {
+                                            Some(side) => {
+                                                block_to_place = block_data.variant_attached_to(
+                                                    block_to_place,
+                                                    side,
+                                                    &game_data.blocks,
+                                                )
+                                            }
+                                            None => continue,
+                                        }
+                                    }
+                                    // Torches stand on the block below or are attached to the side that was clicked,
+                                    // they can't hang from a ceiling
+                                    if block_data.is_torch() {
+                                        let side = if D[face][1] == 1 {
+                                            None
+                                        } else {
+                                            match HorizontalFace::from_direction(
+                                                -D[face][0],
+                                                -D[face][2],
+                                            ) {
+                                                Some(side) => Some(side),
+                                                None => continue,
+                                            }
+                                        };
+                                        match block_data.torch_variant(side, &game_data.blocks) {
+                                            Some(variant) => block_to_place = variant,
+                                            None => continue,
+                                        }
+                                    }
+                                }
+                                if let Some(layer) = stacked_layer {
+                                    block_to_place = layer;
+                                }
+                                new_chunk
+                                    .set_block_at(block.pos_in_containing_chunk(), block_to_place);
                                 world.set_chunk(Arc::new(new_chunk));
+                                blocks_changed.add(1);
+                                if let Some(placed_block) =
+                                    game_data.blocks.get_value_by_id(block_to_place as u32)
+                                {
+                                    let player = players.get_mut(&id).unwrap();
+                                    player.stats.add_block_placed(&placed_block.name);
+                                }
+                                let block_entity_kind = game_data
+                                    .blocks
+                                    .get_value_by_id(block_to_place as u32)
+                                    .and_then(|block| block.block_entity_kind());
+                                match block_entity_kind {
+                                    Some(kind) => {
+                                        let mut block_entity = kind.create();
+                                        // Signs face the player that placed them, who writes their text
+                                        if let BlockEntity::Sign(sign) = &mut block_entity {
+                                            sign.facing = HorizontalFace::closest_to_direction(
+                                                -dir.x, -dir.z,
+                                            );
+                                            send_sign(
+                                                &mut *server,
+                                                &players,
+                                                block,
+                                                Some(sign.clone()),
+                                            );
+                                            server.send(
+                                                id,
+                                                ToClient::OpenSignEditor(block, String::new()),
+                                            );
+                                        }
+                                        world.set_block_entity(block, block_entity);
+                                    }
+                                    None => {
+                                        if let Some(BlockEntity::Sign(_)) =
+                                            world.remove_block_entity(block)
+                                        {
+                                            send_sign(&mut *server, &players, block, None);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    ToServer::SelectHotbarSlot(slot) => {
+                        if slot < HOTBAR_SIZE {
+                            players.get_mut(&id).unwrap().selected_slot = slot;
+                        }
+                    }
+                    ToServer::ClickSlot(window, slot, quick_move) => {
+                        let player = players.get_mut(&id).unwrap();
+                        let container = match player.open_container {
+                            Some(pos) => world
+                                .get_block_entity_mut(pos)
+                                .and_then(|block_entity| block_entity.inventory_mut())
+                                .map(|inventory| (pos, inventory)),
+                            None => None,
+                        };
+                        // The server is the only one modifying the inventories, so the last click wins if
+                        // multiple players have the same container open. The updated inventories are sent
+                        // back to all the viewers, and the clients don't modify their inventories locally.
+                        let mut updated_container = None;
+                        match (window, container) {
+                            (InventoryWindow::Player, Some((pos, container))) if quick_move => {
+                                player.inventory.quick_move(slot, container);
+                                updated_container = Some((pos, container.clone()));
                             }
+                            (InventoryWindow::Player, _) => {
+                                player.inventory.click_slot(slot, &mut player.cursor_stack);
+                            }
+                            (InventoryWindow::Container, Some((pos, container))) => {
+                                if quick_move {
+                                    container.quick_move(slot, &mut player.inventory);
+                                } else {
+                                    container.click_slot(slot, &mut player.cursor_stack);
+                                }
+                                updated_container = Some((pos, container.clone()));
+                            }
+                            // The container was closed or broken in the meantime
+                            (InventoryWindow::Container, None) => (),
+                        }
+                        send_inventory(&mut *server, id, player);
+                        if let Some((pos, container)) = updated_container {
+                            wake_furnace(&mut world, pos);
+                            send_to_viewers(
+                                &mut *server,
+                                &players,
+                                pos,
+                                ToClient::UpdateContainer(pos, container),
+                            );
                         }
                     }
+                    ToServer::SetSignText(pos, text) => {
+                        // Only the players that see the sign can edit it
+                        let player = players.get(&id).unwrap();
+                        if !player
+                            .loaded_chunks
+                            .contains_key(&pos.containing_chunk_pos())
+                        {
+                            continue;
+                        }
+                        if let Some(BlockEntity::Sign(sign)) = world.get_block_entity_mut(pos) {
+                            sign.set_text(&text);
+                            let sign = sign.clone();
+                            send_sign(&mut *server, &players, pos, Some(sign));
+                        }
+                    }
+                    ToServer::SetCommandBlock(pos, command) => {
+                        let player = players.get(&id).unwrap();
+                        if player_permission(&*server, &config, id, player)
+                            < command_block_edit_permission(&config)
+                            || !player
+                                .loaded_chunks
+                                .contains_key(&pos.containing_chunk_pos())
+                        {
+                            continue;
+                        }
+                        if let Some(BlockEntity::CommandBlock(command_block)) =
+                            world.get_block_entity_mut(pos)
+                        {
+                            command_block.set_command(&command);
+                            let message = if command_block.command.is_empty() {
+                                "Command block cleared".to_owned()
+                            } else {
+                                format!("Command block set to /{}", command_block.command)
+                            };
+                            server.send(id, ToClient::ChatMessage(message));
+                            start_command_block(&mut world, pos, config.tick_rate);
+                        }
+                    }
+                    ToServer::ClickCreativePalette(item) => {
+                        let player = players.get_mut(&id).unwrap();
+                        if !player.game_mode.has_infinite_items() {
+                            continue;
+                        }
+                        player.cursor_stack = match player.cursor_stack {
+                            Some(_) => None,
+                            None if item < game_data.items.get_number_of_ids() => {
+                                Some(ItemStack::new(item, MAX_STACK_SIZE))
+                            }
+                            None => None,
+                        };
+                        send_inventory(&mut *server, id, player);
+                    }
+                    ToServer::CloseContainer => {
+                        let player = players.get_mut(&id).unwrap();
+                        player.open_container = None;
+                        // Put the stack held by the cursor back in the inventory
+                        if let Some(stack) = player.cursor_stack.take() {
+                            player.cursor_stack = player.inventory.insert(stack);
+                        }
+                        send_inventory(&mut *server, id, player);
+                    }
+                    ToServer::AddWaypoint(waypoint) => {
+                        let player = players.get_mut(&id).unwrap();
+                        player.waypoints.push(waypoint);
+                        save_player_data(id, player);
+                        server.send(id, ToClient::UpdateWaypoints(player.waypoints.clone()));
+                    }
+                    ToServer::RemoveWaypoint(index) => {
+                        let player = players.get_mut(&id).unwrap();
+                        if index < player.waypoints.len() {
+                            player.waypoints.remove(index);
+                            save_player_data(id, player);
+                        }
+                        server.send(id, ToClient::UpdateWaypoints(player.waypoints.clone()));
+                    }
+                    ToServer::RequestStats => {
+                        let player = players.get(&id).unwrap();
+                        server.send(id, ToClient::UpdateStats(player.stats.clone()));
+                    }
+                    ToServer::Command(line) => {
+                        let message = match parse_command(&line) {
+                            Ok(command)
+                                if command.player_permission()
+                                    > player_permission(&*server, &config, id, &players[&id]) =>
+                            {
+                                format!(
+                                    "You don't have the permission to run this command, it needs the {:?} permission",
+                                    command.player_permission()
+                                )
+                            }
+                            Ok(command) => {
+                                // The player is not in the physics simulation until its first step
+                                let position = physics_simulation
+                                    .get_state()
+                                    .physics_state
+                                    .players
+                                    .get(&id)
+                                    .map(|player| player.position())
+                                    .unwrap_or_else(|| PhysicsPlayer::default().position());
+                                run_command(
+                                    &mut *server,
+                                    &mut world,
+                                    &mut players,
+                                    &game_data.blocks,
+                                    &block_colors,
+                                    &mut tickets,
+                                    &mut scoreboard,
+                                    &mut teams,
+                                    &mut boss_bars,
+                                    &mut game_rules,
+                                    &mut config,
+                                    &mut pregen,
+                                    &mut region_repair,
+                                    &mut blocks_changed,
+                                    spawn_chunk,
+                                    Some(id),
+                                    BlockPos::from(position),
+                                    command,
+                                )
+                            }
+                            Err(message) => message,
+                        };
+                        for line in message.lines() {
+                            server.send(id, ToClient::ChatMessage(line.to_owned()));
+                        }
+                    }
+                    ToServer::SetPaused(paused) => {
+                        players.get_mut(&id).unwrap().wants_pause = paused;
+                    }
+                    ToServer::OpenToLan => {
+                        let message = if !server.is_host(id) {
+                            "Only the host can open the world to LAN".to_owned()
+                        } else {
+                            let name = format!("{}'s world", players[&id].name);
+                            match server.open_to_lan(&name) {
+                                Ok(port) => {
+                                    info!("Opened the world to LAN on port {}", port);
+                                    format!("The world is open to LAN on port {}", port)
+                                }
+                                Err(e) => format!("Failed to open the world to LAN: {:#}", e),
+                            }
+                        };
+                        server.send(id, ToClient::ChatMessage(message));
+                    }
                     ToServer::StopServer => {
+                        if !server.is_host(id) {
+                            log::warn!("Player {} tried to stop the server", id);
+                            continue;
+                        }
                         log::info!("Shutting down server.");
+                        save_all(
+                            &mut world,
+                            &players,
+                            &tickets,
+                            &scoreboard,
+                            &teams,
+                            &game_rules,
+                        );
                         return Ok(());
                     }
                 },
@@ -176,21 +1113,487 @@ pub fn launch_server(mut server: Box<dyn Server>) -> Result<()> {
         world.get_new_light_chunks();
         server_timing.record_part("Receive lighted chunks");
 
-        // Tick game
-        physics_simulation.step_simulation(Instant::now(), &world);
-        server_timing.record_part("Update physics");
+        let now = Instant::now();
+        let dt = (now - last_tick).as_secs_f32();
+        last_tick = now;
 
-        // Send physics updates to players
-        for (&player, _) in players.iter() {
-            server.send(
-                player,
-                ToClient::UpdatePhysics((*physics_simulation.get_state()).clone()),
+        // Pause the game while the only player asks for it
+        let should_pause = players.len() == 1 && players.values().all(|player| player.wants_pause);
+        if should_pause != game_paused {
+            game_paused = should_pause;
+            info!("Game {}", if game_paused { "paused" } else { "resumed" });
+            for &id in players.keys() {
+                server.send(id, ToClient::SetPaused(game_paused));
+            }
+        }
+
+        if game_paused {
+            // The physics don't catch up with the pause when the game resumes
+            physics_simulation.skip_to(server_clock.now());
+        } else {
+            // Tick game
+            let world_view = WorldView::new(&world);
+            physics_simulation.step_simulation(server_clock.now(), &world_view);
+            physics_simulation.snap_to_grid(&world_view);
+            server_timing.record_part("Update physics");
+
+            // Kill the players that fell into the void
+            // TODO: add other ways to die
+            let dead_players = physics_simulation
+                .get_state()
+                .physics_state
+                .players
+                .iter()
+                .filter(|(_, player)| player.position().y < VOID_HEIGHT)
+                .map(|(&id, player)| (id, player.position()))
+                .collect::<Vec<_>>();
+            for (id, position) in dead_players {
+                if let Some(player) = players.get_mut(&id) {
+                    // Only keep the last death position
+                    player
+                        .waypoints
+                        .retain(|waypoint| waypoint.name != DEATH_WAYPOINT_NAME);
+                    player.waypoints.push(Waypoint {
+                        name: DEATH_WAYPOINT_NAME.to_owned(),
+                        x: position.x.floor() as i64,
+                        y: position.y.floor() as i64,
+                        z: position.z.floor() as i64,
+                        color: DEATH_WAYPOINT_COLOR,
+                    });
+                    player.stats.deaths += 1;
+                    player.last_position = None;
+                    if !game_rules.keep_inventory {
+                        player.inventory = Inventory::new(player.inventory.size());
+                        player.cursor_stack = None;
+                        send_inventory(&mut *server, id, player);
+                    }
+                    save_player_data(id, player);
+                    server.send(id, ToClient::UpdateWaypoints(player.waypoints.clone()));
+                    server.send(id, ToClient::Hurt);
+                }
+                physics_simulation.respawn_player(id);
+            }
+            server_timing.record_part("Kill players");
+
+            // Teleport the players that stood in a portal long enough to the linked portal
+            let touched_portals = physics_simulation
+                .get_state()
+                .physics_state
+                .players
+                .iter()
+                .map(|(&id, player)| (id, player.touched_portal(&world)))
+                .collect::<Vec<_>>();
+            for (id, portal) in touched_portals {
+                let player = match players.get_mut(&id) {
+                    Some(player) => player,
+                    None => continue,
+                };
+                let portal = match player.portal_dwell.update(portal, dt) {
+                    Some(portal) => portal,
+                    None => continue,
+                };
+                if let Some(BlockEntity::Portal(Portal {
+                    destination: Some(destination),
+                })) = world.get_block_entity(portal)
+                {
+                    let destination = Point3::new(
+                        destination.px as f64 + 0.5,
+                        destination.py as f64,
+                        destination.pz as f64 + 0.5,
+                    );
+                    physics_simulation.teleport_player(id, destination);
+                    // The distance walked doesn't count the teleportation
+                    player.last_position = None;
+                    server.send(id, ToClient::Teleported);
+                }
+            }
+            server_timing.record_part("Portals");
+
+            // The block tasks due on this tick, in the order they were scheduled
+            let block_tasks = world.advance_block_tasks();
+
+            // Tick the furnaces that have a `TickFurnace` task, region by region in parallel.
+            // The changes that leave a region are applied once all the regions were ticked.
+            let furnaces: HashSet<BlockPos> = block_tasks
+                .iter()
+                .filter(|(_, task)| *task == BlockTask::TickFurnace)
+                .map(|(pos, _)| *pos)
+                .collect();
+            let regions = regions::partition_regions(world.loaded_chunk_positions());
+            num_regions = regions.len();
+            let region_events = region_pool.tick_block_entities(
+                regions::block_entities_by_region(&regions, world.block_entities_mut_at(&furnaces)),
+                dt,
+                &game_data.smelting_recipes,
+                &game_data.items,
+            );
+            let mut furnace_activity_changes = Vec::new();
+            for event in region_events {
+                match event {
+                    regions::RegionEvent::ContainerChanged(pos, inventory) => send_to_viewers(
+                        &mut *server,
+                        &players,
+                        pos,
+                        ToClient::UpdateContainer(pos, inventory),
+                    ),
+                    regions::RegionEvent::FurnaceProgressChanged(pos, progress) => send_to_viewers(
+                        &mut *server,
+                        &players,
+                        pos,
+                        ToClient::UpdateFurnaceProgress(pos, progress),
+                    ),
+                    regions::RegionEvent::FurnaceActivityChanged(pos, is_active) => {
+                        furnace_activity_changes.push((pos, is_active))
+                    }
+                }
+            }
+            // Swap the furnace blocks to change their texture and light
+            let mut world_view = WorldViewMut::new(&mut world);
+            for (pos, is_active) in furnace_activity_changes {
+                let kind = game_data
+                    .blocks
+                    .get_value_by_id(world_view.get_block(pos) as u32)
+                    .and_then(|block| block.block_entity_kind());
+                if let Some(BlockEntityKind::Furnace {
+                    inactive_block,
+                    active_block,
+                }) = kind
+                {
+                    let new_block = if is_active {
+                        active_block
+                    } else {
+                        inactive_block
+                    };
+                    if let Some(new_block) = game_data.blocks.get_id_by_name(new_block) {
+                        world_view.set_block_with_update(pos, new_block as BlockId);
+                    }
+                }
+            }
+            // Write the swapped blocks to the world
+            drop(world_view);
+            // The furnaces with nothing left to do stop ticking until their inventory changes
+            for &pos in &furnaces {
+                if let Some(BlockEntity::Furnace(furnace)) = world.get_block_entity(pos) {
+                    if furnace.is_idle(&game_data.smelting_recipes, &game_data.items) {
+                        world.cancel_block_task(pos, BlockTask::TickFurnace);
+                    }
+                }
+            }
+            server_timing.record_part("Tick block entities");
+
+            // Run the other block tasks
+            let mut commands_to_run = Vec::new();
+            for (pos, task) in block_tasks {
+                match task {
+                    // Already ticked with the other furnaces
+                    BlockTask::TickFurnace => (),
+                    BlockTask::StartCommandBlock => {
+                        start_command_block(&mut world, pos, config.tick_rate)
+                    }
+                    BlockTask::RunCommandBlock => {
+                        if let Some(BlockEntity::CommandBlock(command_block)) =
+                            world.get_block_entity(pos)
+                        {
+                            commands_to_run.push((pos, command_block.command.clone()));
+                        }
+                    }
+                }
+            }
+            for (pos, command) in commands_to_run {
+                run_block_command(
+                    &mut *server,
+                    &mut world,
+                    &mut players,
+                    &game_data.blocks,
+                    &block_colors,
+                    &mut tickets,
+                    &mut scoreboard,
+                    &mut teams,
+                    &mut boss_bars,
+                    &mut game_rules,
+                    &mut config,
+                    &mut pregen,
+                    &mut region_repair,
+                    &mut blocks_changed,
+                    spawn_chunk,
+                    pos,
+                    &command,
+                );
+            }
+            server_timing.record_part("Block tasks");
+
+            let random_changes = random_ticks.tick(
+                &world,
+                &mut biome_maps,
+                &game_data.blocks,
+                game_rules.random_tick_speed,
+            );
+            if !random_changes.is_empty() {
+                let changed = apply_block_changes(
+                    &mut *server,
+                    &mut world,
+                    &mut players,
+                    &game_data.blocks,
+                    &random_changes,
+                );
+                blocks_changed.add(changed.len() as u64);
+            }
+            server_timing.record_part("Random ticks");
+
+            // Connect the rails next to the blocks that changed to the rails around them
+            let block_updates = world.take_block_updates();
+            rail_shaping.add_block_updates(&world, &block_updates);
+            let rail_changes = rail_shaping.tick(&world);
+            if !rail_changes.is_empty() {
+                let changed = apply_block_changes(
+                    &mut *server,
+                    &mut world,
+                    &mut players,
+                    &game_data.blocks,
+                    &rail_changes,
+                );
+                blocks_changed.add(changed.len() as u64);
+            }
+            server_timing.record_part("Rail shapes");
+
+            // Decay the leaves next to the blocks that changed, if they are too far from a tree now
+            leaf_decay.add_block_updates(&world, block_updates);
+            let decay_changes = leaf_decay.tick(&world, game_rules.block_update_budget as usize);
+            if !decay_changes.is_empty() {
+                let changed = apply_block_changes(
+                    &mut *server,
+                    &mut world,
+                    &mut players,
+                    &game_data.blocks,
+                    &decay_changes,
+                );
+                blocks_changed.add(changed.len() as u64);
+            }
+            server_timing.record_part("Leaf decay");
+
+            // Update player statistics
+            for (id, data) in players.iter_mut() {
+                let player = match physics_simulation.get_state().physics_state.players.get(id) {
+                    Some(player) => player,
+                    None => continue,
+                };
+                let position = player.position();
+                let mut moved = 0.0;
+                if let Some(last_position) = data.last_position {
+                    let delta = position - last_position;
+                    moved = (delta.x * delta.x + delta.z * delta.z).sqrt();
+                    if player.is_on_ground(&world) {
+                        data.stats.distance_walked += moved;
+                    }
+                }
+                let attacking = data.breaking.is_some()
+                    || data
+                        .last_attack
+                        .map_or(false, |time| time.elapsed() < ATTACK_DURATION);
+                data.activity = if attacking {
+                    EntityActivity::Attacking
+                } else if moved > WALKING_DISTANCE {
+                    EntityActivity::Walking
+                } else {
+                    EntityActivity::Idle
+                };
+                data.last_position = Some(position);
+                data.stats.playtime += dt as f64;
+            }
+            server_timing.record_part("Update player statistics");
+
+            // Break the blocks that the players have been breaking for long enough
+            let mut broken_blocks = Vec::new();
+            for (&id, data) in players.iter_mut() {
+                if let Some((pos, time)) = data.breaking {
+                    let block_id = world.get_block(pos);
+                    let time = time
+                        + dt * data.mining_speed(block_id, &game_data.items, &game_data.blocks);
+                    data.breaking = Some((pos, time));
+                    match game_data.blocks.get_value_by_id(block_id as u32) {
+                        // The block was already broken
+                        Some(Block {
+                            block_type: BlockType::Air,
+                            ..
+                        })
+                        | None => data.breaking = None,
+                        Some(block) => {
+                            if time >= block.break_time() {
+                                broken_blocks.push((id, pos));
+                                data.breaking = None;
+                            }
+                        }
+                    }
+                }
+            }
+            for (id, pos) in broken_blocks {
+                if break_block(
+                    &mut *server,
+                    &mut world,
+                    &mut players,
+                    &game_data.blocks,
+                    id,
+                    pos,
+                ) {
+                    blocks_changed.add(1);
+                    wear_held_item(&mut *server, &mut players, &game_data.items, id);
+                }
+            }
+            server_timing.record_part("Break blocks");
+
+            // Follow the statistics in the scoreboard, and send it again if what the players see changed
+            for data in players.values() {
+                scoreboard.update_stats(&data.name, &data.stats);
+            }
+            if scoreboard.take_display_changed() {
+                let display = scoreboard.display();
+                for &id in players.keys() {
+                    server.send(id, ToClient::ScoreboardUpdate(display.clone()));
+                }
+            }
+            server_timing.record_part("Update the scoreboard");
+
+            for (bar_id, bar) in boss_bars.take_changes() {
+                for &id in players.keys() {
+                    server.send(id, ToClient::BossBar(bar_id.clone(), bar.clone()));
+                }
+            }
+        }
+
+        // Update what is relevant to each player
+        let physics_state = physics_simulation.get_state();
+        let player_index = SpatialIndex::from_entities(
+            physics_state
+                .physics_state
+                .players
+                .iter()
+                .map(|(&id, player)| (id, player.aabb)),
+        );
+        for (&id, data) in players.iter_mut() {
+            data.interest = Interest::compute(
+                id,
+                data.spectating,
+                data.render_distance,
+                &physics_state.physics_state.players,
+                &player_index,
             );
         }
+
+        // Send physics updates to players, with only the players that are relevant to them
+        let activities: HashMap<_, _> = players
+            .iter()
+            .map(|(&id, data)| (id, data.activity))
+            .collect();
+        for (&player, data) in players.iter_mut() {
+            let interest = match &data.interest {
+                Some(interest) => interest,
+                None => continue,
+            };
+            let snapshot = data.snapshots.encode(
+                physics_state,
+                |id, _| interest.is_player_relevant(id),
+                |vehicle| {
+                    interest.is_chunk_relevant(
+                        BlockPos::from(vehicle.position()).containing_chunk_pos(),
+                    )
+                },
+            );
+            let relevant_activities = activities
+                .iter()
+                .filter(|(id, _)| interest.is_player_relevant(**id))
+                .map(|(&id, &activity)| (id, activity))
+                .collect();
+            server.send(player, ToClient::UpdatePhysics(snapshot));
+            server.send(player, ToClient::UpdateActivities(relevant_activities));
+        }
         server_timing.record_part("Send physics updates to players");
 
-        // Send chunks to players
-        let mut player_positions = Vec::new();
+        // Ping the players and send them the player list. A ping that isn't answered before the next one is lost.
+        if last_player_list.elapsed() >= PLAYER_LIST_INTERVAL {
+            last_player_list = Instant::now();
+            let mut entries: Vec<_> = players
+                .iter()
+                .map(|(&id, player)| PlayerListEntry {
+                    id,
+                    name: player.name.clone(),
+                    ping: player.ping,
+                    team: teams.tag(&player.name),
+                })
+                .collect();
+            // The players of a team are next to each other, the players without a team are last
+            entries.sort_by_key(|entry| {
+                let team = entry.team.as_ref().map(|team| team.name.clone());
+                (team.is_none(), team, entry.name.clone())
+            });
+            for (&id, player) in players.iter_mut() {
+                player.pending_ping = Some((next_ping, Instant::now()));
+                server.send(id, ToClient::Ping(next_ping));
+                server.send(id, ToClient::PlayerListUpdate(entries.clone()));
+            }
+            next_ping = next_ping.wrapping_add(1);
+        }
+
+        // Send chunks to players, around the player they are watching if they are spectating
+        for (player, data) in players.iter_mut() {
+            let player_chunk = match &data.interest {
+                Some(interest) => interest.viewpoint_chunk(),
+                None => continue,
+            };
+            tickets.set_ticket(
+                TicketKind::Player(*player),
+                ChunkRegion::around_player(player_chunk, data.render_distance),
+            );
+            // Send new chunks
+            let updates = world.send_chunks_to_player(player_chunk, data);
+            chunk_updates.add(updates.len() as u64);
+            for update in updates {
+                match update {
+                    ChunkUpdate::Full(chunk, light_chunk) => {
+                        let chunk_pos = chunk.pos;
+                        // The biomes of the column come before its first chunk
+                        let column = ChunkPosXZ::from(chunk_pos);
+                        if data.biome_maps.insert(column) {
+                            server.send(*player, ToClient::BiomeMap(biome_maps.get(column)));
+                        }
+                        let message = ToClient::Chunk(chunk, light_chunk);
+                        add_chunk_stat(
+                            ChunkMetric::BytesSent,
+                            chunk_pos,
+                            message.estimated_size() as f64,
+                        );
+                        server.send(*player, message);
+                        // The signs of the chunk come with it
+                        for (pos, block_entity) in world.block_entities_in_chunk(chunk_pos) {
+                            if let BlockEntity::Sign(sign) = block_entity {
+                                server.send(*player, ToClient::UpdateSign(pos, Some(sign.clone())));
+                            }
+                        }
+                    }
+                    ChunkUpdate::Light(light_chunk) => {
+                        let chunk_pos = light_chunk.pos;
+                        let message = ToClient::LightChunk(light_chunk);
+                        add_chunk_stat(
+                            ChunkMetric::BytesSent,
+                            chunk_pos,
+                            message.estimated_size() as f64,
+                        );
+                        server.send(*player, message);
+                    }
+                }
+            }
+            // Drop chunks that are too far away
+            let render_distance = data.render_distance;
+            let interest = data.interest.as_ref().unwrap();
+            data.loaded_chunks
+                .retain(|chunk_pos, _| interest.is_chunk_relevant(*chunk_pos));
+            data.biome_maps
+                .retain(|column| render_distance.is_column_visible(player_chunk, *column));
+        }
+        server_timing.record_part("Send chunks to players");
+
+        // Send far tiles to players
+        far_terrain.get_new_tiles();
+        let mut player_tiles = Vec::new();
         for (player, data) in players.iter_mut() {
             let player_pos = BlockPos::from(
                 physics_simulation
@@ -201,19 +1604,21 @@ pub fn launch_server(mut server: Box<dyn Server>) -> Result<()> {
                     .unwrap()
                     .get_camera_position(),
             );
-            let player_chunk = player_pos.containing_chunk_pos();
-            player_positions.push((player_chunk, data.render_distance));
-            // Send new chunks
-            let updates = world.send_chunks_to_player(player_chunk, data);
-            for (chunk, light_chunk) in updates {
-                server.send(*player, ToClient::Chunk(chunk, light_chunk));
+            let player_tile = FarTilePos::from(ChunkPosXZ::from(player_pos.containing_chunk_pos()));
+            player_tiles.push(player_tile);
+            // The client drops the far tiles that are too far away by itself
+            data.far_tiles.retain(|pos| pos.is_close_to(player_tile));
+            for pos in far_terrain::close_tiles(player_tile) {
+                if !data.far_tiles.contains(&pos) {
+                    if let Some(tile) = far_terrain.get_tile(pos) {
+                        data.far_tiles.insert(pos);
+                        server.send(*player, ToClient::FarTile(tile));
+                    }
+                }
             }
-            // Drop chunks that are too far away
-            let render_distance = data.render_distance;
-            data.loaded_chunks
-                .retain(|chunk_pos, _| render_distance.is_chunk_visible(player_chunk, *chunk_pos));
         }
-        server_timing.record_part("Send chunks to players");
+        far_terrain.drop_far_tiles(&player_tiles);
+        server_timing.record_part("Send far tiles to players");
 
         // Compute close chunks
         for (_, data) in players.iter_mut() {
@@ -237,9 +1642,11 @@ pub fn launch_server(mut server: Box<dyn Server>) -> Result<()> {
             })
             .collect::<Vec<_>>();
         voxel_rs_common::collections::merge_arrays(&mut close_chunks_merged, &all_close_chunks[..]);
+        // The chunks of the other tickets come after the chunks close to the players
         let close_chunks = close_chunks_merged
             .iter()
             .map(|&ccp| ccp.pos)
+            .chain(tickets.non_player_chunks())
             .collect::<Vec<_>>();
         server_timing.record_part("Compute close chunks");
 
@@ -251,19 +1658,153 @@ pub fn launch_server(mut server: Box<dyn Server>) -> Result<()> {
         world.enqueue_chunks_for_worldgen(&close_chunks);
         server_timing.record_part("Send chunks to worldgen worker");
 
-        // Drop chunks that are far from all players
-        world.drop_far_chunks(&player_positions);
-        server_timing.record_part("Drop far chunks");
+        // Pre-generate chunks with the room left in the worldgen queue
+        if let Some(current) = &mut pregen {
+            current.update(&mut world);
+            let message = if current.is_done() {
+                Some(current.summary())
+            } else {
+                current.poll_report()
+            };
+            if let Some(message) = message {
+                info!("{}", message);
+                if let Some(issuer) = current.issuer.filter(|id| players.contains_key(id)) {
+                    server.send(issuer, ToClient::ChatMessage(message));
+                }
+            }
+            if current.is_done() {
+                pregen = None;
+            }
+        }
+        server_timing.record_part("Pre-generate chunks");
+
+        // Check a few chunks of the current `/repair-region`. The corrupted chunks that are loaded are saved
+        // again, the others will be generated again.
+        if let Some(repair) = &mut region_repair {
+            for pos in repair.check_chunks(REPAIR_CHUNKS_PER_TICK) {
+                if world.resave_chunk(pos) {
+                    repair.resaved += 1;
+                }
+            }
+            if repair.is_done() {
+                let message = repair.summary();
+                info!("{}", message);
+                if let Some(issuer) = repair.issuer.filter(|id| players.contains_key(id)) {
+                    server.send(issuer, ToClient::ChatMessage(message));
+                }
+                region_repair = None;
+            }
+        }
+        server_timing.record_part("Repair region");
+
+        // Drop chunks that are not covered by any ticket
+        world.drop_unticketed_chunks(&tickets);
+        biome_maps.drop_unloaded_maps(|column| world.is_column_loaded(column));
+        server_timing.record_part("Drop unticketed chunks");
+
+        // The block entities and the players of every chunk, for the heatmap of the debug overlay
+        let mut entities = HashMap::new();
+        let player_positions = physics_simulation
+            .get_state()
+            .physics_state
+            .players
+            .values()
+            .map(|player| BlockPos::from(player.position()));
+        for pos in world.block_entity_positions().chain(player_positions) {
+            *entities.entry(pos.containing_chunk_pos()).or_insert(0.0) += 1.0;
+        }
+        replace_chunk_stats(ChunkMetric::Entities, entities.into_iter().collect());
 
         send_debug_info(
             "Chunks",
             "server",
             format!(
-                "Server loaded chunks = {}\nServer loaded chunk columns = {}\n",
+                "Server loaded chunks = {}\nServer loaded chunk columns = {}\nServer far tiles = {}\nForceloaded regions = {}\nModified chunks = {}\nScheduled block tasks = {}\n",
                 world.num_loaded_chunks(),
                 world.num_loaded_chunk_columns(),
+                far_terrain.num_tiles(),
+                tickets.forceloads().len(),
+                world.num_dirty_chunks(),
+                world.num_block_tasks(),
             ),
         );
+        send_debug_info(
+            "Chunks",
+            "serverrates",
+            format!(
+                "Blocks changed per second = {:.1}\nChunk updates sent per second = {:.1}\n",
+                blocks_changed.per_second(),
+                chunk_updates.per_second(),
+            ),
+        );
+
+        // Autosave, the chunks are saved incrementally over the next ticks
+        if config.autosave_interval > 0
+            && last_autosave.elapsed() >= Duration::from_secs(config.autosave_interval)
+        {
+            last_autosave = Instant::now();
+            for (&id, player) in players.iter() {
+                save_player_data(id, player);
+            }
+            save_forceloads(&tickets);
+            save_scoreboard(&scoreboard);
+            save_teams(&teams);
+            save_game_rules(&game_rules);
+            world.start_autosave();
+        }
+        world.save_queued_chunks(AUTOSAVE_CHUNKS_PER_TICK);
+        server_timing.record_part("Autosave");
+
+        // Backup, once the chunks are saved. The save folder is zipped on another thread.
+        if config.backup_interval > 0
+            && last_backup.elapsed() >= Duration::from_secs(config.backup_interval)
+            && !world.is_autosaving()
+        {
+            last_backup = Instant::now();
+            save_all(
+                &mut world,
+                &players,
+                &tickets,
+                &scoreboard,
+                &teams,
+                &game_rules,
+            );
+            let backups_kept = config.backups_kept;
+            std::thread::spawn(move || {
+                if let Err(e) = save::create_backup(backups_kept) {
+                    log::error!("Failed to create backup: {:?}", e);
+                }
+            });
+        }
+        server_timing.record_part("Backup");
+
+        // Metrics
+        ticks.add(1);
+        set_gauge(
+            "voxel_server_ticks_per_second",
+            "Ticks per second, averaged over the last 10 seconds",
+            ticks.per_second() as f64,
+        );
+        set_gauge(
+            "voxel_server_loaded_chunks",
+            "Chunks loaded by the server",
+            world.num_loaded_chunks() as f64,
+        );
+        set_gauge(
+            "voxel_server_regions",
+            "Independent regions of loaded chunks, simulated in parallel",
+            num_regions as f64,
+        );
+        set_gauge(
+            "voxel_server_players",
+            "Connected players",
+            players.len() as f64,
+        );
+        observe_histogram(
+            "voxel_server_tick_seconds",
+            "Duration of the server ticks, without the wait for the next tick",
+            tick_start.elapsed().as_secs_f64(),
+        );
 
         // Nothing else to do for now :-)
         send_perf_breakdown(
@@ -272,6 +1813,1043 @@ pub fn launch_server(mut server: Box<dyn Server>) -> Result<()> {
             "Server main loop",
             server_timing.extract_part_averages(),
         );
+
+        // Wait for the next tick
+        next_tick += tick_duration;
+        let now = Instant::now();
+        if next_tick > now {
+            std::thread::sleep(next_tick - now);
+        } else {
+            // The server is late, don't try to catch up
+            next_tick = now;
+        }
+    }
+}
+
+/// Hurt the player `target` hit by the player `attacker`, unless the attacker hit recently or their team
+/// prevents it
+fn hit(
+    server: &mut dyn Server,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    teams: &Teams,
+    attacker: PlayerId,
+    target: PlayerId,
+) {
+    let target_name = match players.get(&target) {
+        Some(player) => player.name.clone(),
+        None => return,
+    };
+    let player = players.get_mut(&attacker).unwrap();
+    if player
+        .last_attack
+        .map_or(false, |time| time.elapsed() < ATTACK_DURATION)
+    {
+        return;
+    }
+    player.last_attack = Some(Instant::now());
+    if teams.can_hurt(&player.name, &target_name) {
+        server.send(target, ToClient::Hurt);
+    }
+}
+
+/// Send the inventory of a player to its client
+fn send_inventory(server: &mut dyn Server, id: PlayerId, player: &PlayerData) {
+    server.send(
+        id,
+        ToClient::UpdateInventory(player.inventory.clone(), player.cursor_stack),
+    );
+}
+
+/// Break the block at `pos`, giving the contents of its block entity to the player `id` that broke it.
+/// Return false if the chunk of the block is not loaded, or if the contents don't fit in the inventory of the player.
+fn break_block(
+    server: &mut dyn Server,
+    world: &mut World,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    blocks: &Registry<Block>,
+    id: PlayerId,
+    pos: BlockPos,
+) -> bool {
+    if world.get_chunk(pos.containing_chunk_pos()).is_none() {
+        return false;
+    }
+    // The items can't be dropped in the world, so a container only breaks if all its contents fit
+    if let Some(inventory) = world.get_block_entity(pos).and_then(BlockEntity::inventory) {
+        let mut player_inventory = players[&id].inventory.clone();
+        if inventory
+            .slots()
+            .flatten()
+            .any(|stack| player_inventory.insert(*stack).is_some())
+        {
+            server.send(
+                id,
+                ToClient::ActionBar(
+                    "Your inventory is too full to hold the contents of this container".to_owned(),
+                ),
+            );
+            return false;
+        }
+    }
+    let broken_block = world.get_block(pos);
+    world.set_block(pos, 0);
+    if let Some(broken_block) = blocks.get_value_by_id(broken_block as u32) {
+        let player = players.get_mut(&id).unwrap();
+        player.stats.add_block_broken(&broken_block.name);
+    }
+    if let Some(block_entity) = world.remove_block_entity(pos) {
+        close_container(server, players, pos);
+        if let BlockEntity::Sign(_) = block_entity {
+            send_sign(server, players, pos, None);
+        }
+        // Give the contents of the container to the player that broke it, they were checked to fit
+        // TODO: drop the items in the world instead
+        if let Some(inventory) = block_entity.inventory() {
+            let player = players.get_mut(&id).unwrap();
+            for stack in inventory.slots().flatten() {
+                let remainder = player.inventory.insert(*stack);
+                debug_assert!(remainder.is_none());
+            }
+            send_inventory(server, id, player);
+        }
+    }
+    true
+}
+
+/// Use the held item of the player once, if it wears out and the player doesn't have infinite items
+fn wear_held_item(
+    server: &mut dyn Server,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    items: &Registry<Item>,
+    id: PlayerId,
+) {
+    let player = players.get_mut(&id).unwrap();
+    if player.game_mode.has_infinite_items() {
+        return;
+    }
+    if let Some(stack) = player.inventory.get(player.selected_slot) {
+        let durability = items
+            .get_value_by_id(stack.item)
+            .map_or(0, Item::durability);
+        if durability > 0 {
+            player
+                .inventory
+                .set(player.selected_slot, stack.wear(durability));
+            send_inventory(server, id, player);
+        }
+    }
+}
+
+/// Number of growth stages that one use of the fertilizer adds to a crop
+const FERTILIZER_STAGES: u8 = 2;
+
+/// Till the block at `pos` with a hoe, or make the crop at `pos` grow with the fertilizer.
+/// Return true if the block changed.
+fn use_farming_tool(
+    server: &mut dyn Server,
+    world: &mut World,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    blocks: &Registry<Block>,
+    id: PlayerId,
+    pos: BlockPos,
+    tool: ItemTool,
+) -> bool {
+    let clicked = match blocks.get_value_by_id(world.get_block(pos) as u32) {
+        Some(clicked) => clicked,
+        None => return false,
+    };
+    let new_block = match tool {
+        // Only the blocks with nothing on top can be tilled
+        ItemTool::Hoe => clicked
+            .tilled_into()
+            .and_then(|name| blocks.get_id_by_name(name))
+            .filter(|_| world.get_block(BlockPos::from((pos.px, pos.py + 1, pos.pz))) == 0)
+            .map(|tilled| tilled as BlockId),
+        ItemTool::Fertilizer => {
+            let (stage, _) = match clicked.crop_stage() {
+                Some(stage) => stage,
+                None => return false,
+            };
+            (1..=FERTILIZER_STAGES)
+                .rev()
+                .find_map(|grown| clicked.variant_with_stage(stage + grown, blocks))
+        }
+        ItemTool::Selection | ItemTool::Boat | ItemTool::Cart => None,
+    };
+    let new_block = match new_block {
+        Some(new_block) => new_block,
+        None => return false,
+    };
+    apply_block_changes(server, world, players, blocks, &[(pos, new_block)]);
+    // The fertilizer is used up
+    if tool == ItemTool::Fertilizer {
+        consume_held_item(server, players.get_mut(&id).unwrap(), id);
+    }
+    true
+}
+
+/// Remove one of the held items of the player, unless it has infinite items
+fn consume_held_item(server: &mut dyn Server, player: &mut PlayerData, id: PlayerId) {
+    if player.game_mode.has_infinite_items() {
+        return;
+    }
+    if let Some(mut stack) = player.inventory.get(player.selected_slot) {
+        stack.count -= 1;
+        player.inventory.set(
+            player.selected_slot,
+            Some(stack).filter(|stack| stack.count > 0),
+        );
+        send_inventory(server, id, player);
+    }
+}
+
+/// Apply many block changes at once, updating the block entities and sending the changes to the players
+/// that have the modified chunks. Return the previous value of the blocks that changed.
+fn apply_block_changes(
+    server: &mut dyn Server,
+    world: &mut World,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    blocks: &Registry<Block>,
+    changes: &[(BlockPos, BlockId)],
+) -> BlockChanges {
+    let previous_blocks = world.set_blocks(changes);
+
+    // Update the block entities
+    // TODO: save the block entities in the undo history
+    let mut changed_chunks = HashSet::new();
+    let mut new_blocks = Vec::with_capacity(previous_blocks.len());
+    let mut sign_changes = Vec::new();
+    for &(pos, _) in previous_blocks.iter() {
+        let mut had_sign = false;
+        if let Some(block_entity) = world.remove_block_entity(pos) {
+            close_container(server, players, pos);
+            had_sign = matches!(block_entity, BlockEntity::Sign(_));
+        }
+        let block = world.get_block(pos);
+        let block_entity_kind = blocks
+            .get_value_by_id(block as u32)
+            .and_then(|block| block.block_entity_kind());
+        let mut new_sign = None;
+        if let Some(kind) = block_entity_kind {
+            let block_entity = kind.create();
+            if let BlockEntity::Sign(sign) = &block_entity {
+                new_sign = Some(sign.clone());
+            }
+            world.set_block_entity(pos, block_entity);
+        }
+        if had_sign || new_sign.is_some() {
+            sign_changes.push((pos, new_sign));
+        }
+        changed_chunks.insert(pos.containing_chunk_pos());
+        new_blocks.push((pos, block));
+    }
+
+    // Send the changes to the players that have the chunks, so that they don't need the whole chunks again
+    for (&id, player) in players.iter_mut() {
+        let player_changes = new_blocks
+            .iter()
+            .filter(|(pos, _)| {
+                player
+                    .loaded_chunks
+                    .contains_key(&pos.containing_chunk_pos())
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for chunk_pos in changed_chunks.iter() {
+            if let Some(versions) = player.loaded_chunks.get_mut(chunk_pos) {
+                versions.blocks = world.get_chunk_versions(*chunk_pos).unwrap().blocks;
+            }
+        }
+        if !player_changes.is_empty() {
+            server.send(id, ToClient::BlockChanges(player_changes));
+        }
+    }
+    for (pos, sign) in sign_changes {
+        send_sign(server, players, pos, sign);
+    }
+
+    previous_blocks
+}
+
+/// Replace the block at `pos` by the block called `block_name`. Return the previous block.
+fn set_block_by_name(
+    server: &mut dyn Server,
+    world: &mut World,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    blocks: &Registry<Block>,
+    pos: BlockPos,
+    block_name: &str,
+) -> Result<BlockChanges, String> {
+    let block = blocks
+        .get_id_by_name(block_name)
+        .ok_or_else(|| format!("Unknown block {}", block_name))?;
+    if world.get_chunk(pos.containing_chunk_pos()).is_none() {
+        return Err("The chunk of the block is not loaded".to_owned());
+    }
+    Ok(apply_block_changes(
+        server,
+        world,
+        players,
+        blocks,
+        &[(pos, block as BlockId)],
+    ))
+}
+
+/// Place a portal at `from` and one at `to`, and link them to each other
+fn link_portals(
+    server: &mut dyn Server,
+    world: &mut World,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    blocks: &Registry<Block>,
+    from: BlockPos,
+    to: BlockPos,
+) -> Result<BlockChanges, String> {
+    let block = blocks
+        .get_id_by_name(PORTAL_BLOCK)
+        .ok_or_else(|| format!("Unknown block {}", PORTAL_BLOCK))?;
+    if from == to {
+        return Err("A portal can't be linked to itself".to_owned());
+    }
+    if [from, to]
+        .iter()
+        .any(|pos| world.get_chunk(pos.containing_chunk_pos()).is_none())
+    {
+        return Err("The chunks of the portals are not loaded".to_owned());
+    }
+    let previous_blocks = apply_block_changes(
+        server,
+        world,
+        players,
+        blocks,
+        &[(from, block as BlockId), (to, block as BlockId)],
+    );
+    for &(pos, destination) in &[(from, to), (to, from)] {
+        if let Some(BlockEntity::Portal(portal)) = world.get_block_entity_mut(pos) {
+            portal.destination = Some(destination);
+        }
+    }
+    Ok(previous_blocks)
+}
+
+/// Schedule the runs of the command of the command block at `pos`, replacing its previous schedule
+fn start_command_block(world: &mut World, pos: BlockPos, tick_rate: u32) {
+    world.cancel_block_tasks(pos);
+    let interval = match world.get_block_entity(pos) {
+        Some(BlockEntity::CommandBlock(command_block)) if command_block.is_active() => {
+            (command_block.interval() * tick_rate as f32).round() as Tick
+        }
+        _ => return,
+    };
+    world.schedule_repeating_block_task(pos, interval, BlockTask::RunCommandBlock);
+}
+
+/// Run the command of the command block at `pos` if the config allows it, logging the result
+fn run_block_command(
+    server: &mut dyn Server,
+    world: &mut World,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    blocks: &Registry<Block>,
+    block_colors: &[[u8; 4]],
+    tickets: &mut ChunkTickets,
+    scoreboard: &mut Scoreboard,
+    teams: &mut Teams,
+    boss_bars: &mut BossBars,
+    game_rules: &mut GameRules,
+    config: &mut ServerConfig,
+    pregen: &mut Option<Pregen>,
+    region_repair: &mut Option<RegionRepair>,
+    blocks_changed: &mut RateCounter,
+    spawn_chunk: ChunkPos,
+    pos: BlockPos,
+    line: &str,
+) {
+    let command = match parse_command(line) {
+        Ok(command) => command,
+        Err(message) => {
+            log::warn!("Command block at {:?}: {}", pos, message);
+            return;
+        }
+    };
+    match command.block_permission() {
+        None => {
+            log::warn!(
+                "Command block at {:?}: /{} can only be run by a player",
+                pos,
+                line
+            );
+            return;
+        }
+        Some(permission) if permission > config.command_block_permission => {
+            log::warn!(
+                "Command block at {:?}: /{} needs the {:?} permission, command_block_permission is {:?}",
+                pos,
+                line,
+                permission,
+                config.command_block_permission
+            );
+            return;
+        }
+        Some(_) => (),
+    }
+    let message = run_command(
+        server,
+        world,
+        players,
+        blocks,
+        block_colors,
+        tickets,
+        scoreboard,
+        teams,
+        boss_bars,
+        game_rules,
+        config,
+        pregen,
+        region_repair,
+        blocks_changed,
+        spawn_chunk,
+        None,
+        pos,
+        command,
+    );
+    if !message.is_empty() {
+        log::debug!("Command block at {:?}: {}", pos, message);
+    }
+}
+
+/// Run a command for the player `issuer`, or for a command block if there is no issuer. The permission must have
+/// been checked. The command runs at `pos`, the position of the player or of the command block.
+/// Return the message for the issuer, with a line per chat message, empty if the command already told the players.
+fn run_command(
+    server: &mut dyn Server,
+    world: &mut World,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    blocks: &Registry<Block>,
+    block_colors: &[[u8; 4]],
+    tickets: &mut ChunkTickets,
+    scoreboard: &mut Scoreboard,
+    teams: &mut Teams,
+    boss_bars: &mut BossBars,
+    game_rules: &mut GameRules,
+    config: &mut ServerConfig,
+    pregen: &mut Option<Pregen>,
+    region_repair: &mut Option<RegionRepair>,
+    blocks_changed: &mut RateCounter,
+    spawn_chunk: ChunkPos,
+    issuer: Option<PlayerId>,
+    pos: BlockPos,
+    command: Command,
+) -> String {
+    match (command, issuer) {
+        (Command::Help, _) => COMMAND_USAGES.join("\n"),
+        (Command::GameMode(game_mode), Some(id)) => {
+            let player = players.get_mut(&id).unwrap();
+            player.game_mode = game_mode;
+            player.breaking = None;
+            save_player_data(id, player);
+            if !game_mode.can_spectate() && player.spectating.is_some() {
+                set_spectating(server, players, id, None);
+            }
+            server.send(id, ToClient::SetGameMode(game_mode));
+            format!("Game mode set to {}", game_mode)
+        }
+        (Command::Fill(block_name), Some(id)) => {
+            let player = players.get(&id).unwrap();
+            match (
+                player.selection.bounds(),
+                blocks.get_id_by_name(&block_name),
+            ) {
+                (None, _) => "Select a cuboid with the selection tool first".to_owned(),
+                (_, None) => format!("Unknown block {}", block_name),
+                (Some((min, max)), _) if volume(min, max) > MAX_EDIT_VOLUME => format!(
+                    "The selection is too big: {} blocks, the maximum is {}",
+                    volume(min, max),
+                    MAX_EDIT_VOLUME
+                ),
+                (Some((min, max)), Some(block)) => {
+                    let changes = world_edit::fill(min, max, block as BlockId);
+                    let previous_blocks =
+                        apply_block_changes(server, world, players, blocks, &changes);
+                    let count = record_edit(players, blocks_changed, issuer, previous_blocks);
+                    format!("{} blocks changed", count)
+                }
+            }
+        }
+        (Command::Copy, Some(id)) => {
+            let player = players.get_mut(&id).unwrap();
+            match player.selection.bounds() {
+                None => "Select a cuboid with the selection tool first".to_owned(),
+                Some((min, max)) if volume(min, max) > MAX_EDIT_VOLUME => format!(
+                    "The selection is too big: {} blocks, the maximum is {}",
+                    volume(min, max),
+                    MAX_EDIT_VOLUME
+                ),
+                Some((min, max)) => {
+                    player.clipboard = Some(Clipboard::copy(world, min, max, pos));
+                    format!("{} blocks copied", volume(min, max))
+                }
+            }
+        }
+        (Command::Paste(quarter_turns), Some(id)) => {
+            let changes = players[&id]
+                .clipboard
+                .as_ref()
+                .map(|clipboard| clipboard.rotated(quarter_turns).paste(pos));
+            match changes {
+                None => "The clipboard is empty, use /copy first".to_owned(),
+                Some(changes) => {
+                    let previous_blocks =
+                        apply_block_changes(server, world, players, blocks, &changes);
+                    let count = record_edit(players, blocks_changed, issuer, previous_blocks);
+                    format!("{} blocks changed", count)
+                }
+            }
+        }
+        (Command::Undo, Some(id)) => match players.get_mut(&id).unwrap().edit_history.pop() {
+            None => "Nothing to undo".to_owned(),
+            Some(changes) => {
+                let previous_blocks = apply_block_changes(server, world, players, blocks, &changes);
+                blocks_changed.add(previous_blocks.len() as u64);
+                format!("{} blocks restored", previous_blocks.len())
+            }
+        },
+        (Command::SaveSchematic(name), Some(id)) => {
+            export_selection(world, &players[&id], &name, |clipboard| {
+                schematic::save_schematic(&name, clipboard, blocks)
+            })
+        }
+        (Command::ExportSchematic(name), Some(id)) => {
+            export_selection(world, &players[&id], &name, |clipboard| {
+                schematic::export_vox(&name, clipboard, block_colors)
+            })
+        }
+        (Command::LoadSchematic(name), Some(id)) => {
+            match schematic::load_schematic(&name, blocks, block_colors) {
+                Ok(clipboard) => {
+                    let (sx, sy, sz) = clipboard.size;
+                    players.get_mut(&id).unwrap().clipboard = Some(clipboard);
+                    format!(
+                        "Schematic {} ({}x{}x{}) loaded to the clipboard, use /paste to place it",
+                        name, sx, sy, sz
+                    )
+                }
+                Err(e) => format!("{:#}", e),
+            }
+        }
+        (Command::ForceloadAdd, Some(id)) => match players[&id].selection.bounds() {
+            None => "Select a cuboid with the selection tool first".to_owned(),
+            Some((min, max)) => {
+                let region = ChunkRegion::containing_blocks(min, max);
+                if region.num_chunks() > MAX_FORCELOAD_CHUNKS {
+                    format!(
+                        "The selection covers {} chunks, the maximum is {}",
+                        region.num_chunks(),
+                        MAX_FORCELOAD_CHUNKS
+                    )
+                } else {
+                    let ticket_id = tickets.add_forceload(region);
+                    save_forceloads(tickets);
+                    format!(
+                        "Forceloaded {} chunks with id {}",
+                        region.num_chunks(),
+                        ticket_id
+                    )
+                }
+            }
+        },
+        (Command::ForceloadRemove(ticket_id), _) => {
+            if tickets.remove_forceload(ticket_id) {
+                save_forceloads(tickets);
+                format!("Removed forceload {}", ticket_id)
+            } else {
+                format!("No forceload with id {}", ticket_id)
+            }
+        }
+        (Command::ForceloadList, _) => {
+            let forceloads = tickets.forceloads();
+            if forceloads.is_empty() {
+                "No forceloaded chunks".to_owned()
+            } else {
+                forceloads
+                    .iter()
+                    .map(|(ticket_id, region)| {
+                        format!("{}: chunks {:?} to {:?}", ticket_id, region.min, region.max)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+        (Command::Reload, _) => reload_config(config),
+        (Command::SaveAll, _) => {
+            save_all(world, players, tickets, scoreboard, teams, game_rules);
+            "World saved".to_owned()
+        }
+        (Command::RepairRegion(radius), _) => {
+            start_region_repair(region_repair, pos.containing_chunk_pos(), radius, issuer)
+        }
+        (Command::Pregen(radius), _) => start_pregen(pregen, spawn_chunk, radius, issuer),
+        (Command::ExportMap(shaded), _) => start_map_export(world, block_colors, shaded),
+        (Command::Say(message), _) => {
+            broadcast(server, players, message);
+            String::new()
+        }
+        (Command::Title(title, subtitle), _) => {
+            broadcast_title(server, players, title, subtitle);
+            String::new()
+        }
+        (Command::ActionBar(text), _) => {
+            for &id in players.keys() {
+                server.send(id, ToClient::ActionBar(text.clone()));
+            }
+            String::new()
+        }
+        (Command::Scoreboard(command), _) => scoreboard.run(command),
+        (Command::Team(command), _) => teams.run(command),
+        (Command::BossBar(command), _) => boss_bars.run(command),
+        (Command::ListGameRules, _) => GAME_RULES
+            .iter()
+            .map(|(name, description)| {
+                format!(
+                    "{} = {}: {}",
+                    name,
+                    game_rules.get(name).unwrap(),
+                    description
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        (Command::GameRule(name, value), _) => run_game_rule(game_rules, &name, value),
+        (Command::Difficulty(None), _) => format!(
+            "The difficulty is {} {}",
+            game_rules.difficulty, DIFFICULTY_NOTE
+        ),
+        (Command::Difficulty(Some(difficulty)), _) => {
+            game_rules.difficulty = difficulty;
+            let message = format!(
+                "The difficulty was set to {} {}",
+                difficulty, DIFFICULTY_NOTE
+            );
+            broadcast(server, players, message);
+            String::new()
+        }
+        (Command::Spectate(name), Some(id)) => {
+            let target = name.map(|name| {
+                players
+                    .iter()
+                    .find(|(_, player)| player.name.eq_ignore_ascii_case(&name))
+                    .map(|(&target, _)| target)
+                    .ok_or(name)
+            });
+            match target {
+                None => set_spectating(server, players, id, None),
+                Some(Ok(target)) => set_spectating(server, players, id, Some(target)),
+                Some(Err(name)) => format!("No player named {}", name),
+            }
+        }
+        (Command::SetBlock(block_pos, block_name), _) => {
+            match set_block_by_name(server, world, players, blocks, block_pos, &block_name) {
+                Ok(previous_blocks) => {
+                    record_edit(players, blocks_changed, issuer, previous_blocks);
+                    "Block set".to_owned()
+                }
+                Err(message) => message,
+            }
+        }
+        (Command::Portal(from, to), _) => {
+            match link_portals(server, world, players, blocks, from, to) {
+                Ok(previous_blocks) => {
+                    record_edit(players, blocks_changed, issuer, previous_blocks);
+                    "Portals linked".to_owned()
+                }
+                Err(message) => message,
+            }
+        }
+        // The command blocks can't run the commands that use the selection, the clipboard or the game mode
+        (_, None) => "This command can only be run by a player".to_owned(),
+    }
+}
+
+/// Count the blocks changed by a command, and add them to the edit history of the player who ran it.
+/// Return the number of blocks.
+fn record_edit(
+    players: &mut HashMap<PlayerId, PlayerData>,
+    blocks_changed: &mut RateCounter,
+    issuer: Option<PlayerId>,
+    previous_blocks: BlockChanges,
+) -> usize {
+    let count = previous_blocks.len();
+    blocks_changed.add(count as u64);
+    if let Some(id) = issuer {
+        players
+            .get_mut(&id)
+            .unwrap()
+            .edit_history
+            .push(previous_blocks);
+    }
+    count
+}
+
+/// Start checking the saved chunks around `center` and setting the corrupted ones aside, replacing the current
+/// repair. Return the message for the player.
+fn start_region_repair(
+    region_repair: &mut Option<RegionRepair>,
+    center: ChunkPos,
+    radius: u64,
+    issuer: Option<PlayerId>,
+) -> String {
+    match RegionRepair::new(center, radius, issuer) {
+        Ok(repair) => {
+            *region_repair = Some(repair);
+            format!("Checking the saved chunks within {} chunks", radius)
+        }
+        Err(e) => format!("Failed to repair the region: {:#}", e),
+    }
+}
+
+/// Export the map of the world on another thread, and return the message for the player
+fn start_map_export(world: &World, block_colors: &[[u8; 4]], shaded: bool) -> String {
+    let loaded_chunks = world
+        .loaded_chunk_positions()
+        .filter_map(|pos| world.get_chunk(pos))
+        .collect();
+    let block_colors = block_colors.to_vec();
+    std::thread::spawn(move || {
+        match map_export::export_map(loaded_chunks, &block_colors, shaded) {
+            Ok((width, height)) => info!(
+                "Exported a map of {}x{} pixels to {}",
+                width,
+                height,
+                map_export::MAP_FILE
+            ),
+            Err(e) => log::error!("Failed to export the map: {:?}", e),
+        }
+    });
+    format!("Exporting the map to {}", map_export::MAP_FILE)
+}
+
+/// Start pre-generating the chunks within `radius` chunks of the spawn, replacing the current pre-generation,
+/// or stop it if there is no radius. Return the message for the player.
+fn start_pregen(
+    pregen: &mut Option<Pregen>,
+    spawn_chunk: ChunkPos,
+    radius: Option<u64>,
+    issuer: Option<PlayerId>,
+) -> String {
+    let previous = std::mem::replace(
+        pregen,
+        radius.map(|radius| Pregen::new(spawn_chunk, radius, issuer)),
+    );
+    match (radius, previous) {
+        (Some(radius), _) => format!(
+            "Pre-generating the chunks within {} chunks of the spawn",
+            radius
+        ),
+        (None, Some(previous)) => format!("Pre-generation stopped, {}", previous.progress()),
+        (None, None) => "No pre-generation is running".to_owned(),
+    }
+}
+
+/// Reload the server config from its file, and return the message for the player
+fn reload_config(config: &mut ServerConfig) -> String {
+    match config::load_config() {
+        Ok(new_config) => {
+            let restart_needed = config.reload_from(new_config);
+            if restart_needed.is_empty() {
+                "Server config reloaded".to_owned()
+            } else {
+                format!(
+                    "Server config reloaded, restart the server to apply {}",
+                    restart_needed.join(", ")
+                )
+            }
+        }
+        Err(e) => format!("{:#}", e),
+    }
+}
+
+/// Make a player watch another player, or go back to its own camera if `target` is `None`,
+/// and return the message for the player. Only the players that can spectate can watch the other players.
+fn set_spectating(
+    server: &mut dyn Server,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    id: PlayerId,
+    target: Option<PlayerId>,
+) -> String {
+    if let Some(target) = target {
+        if target == id {
+            return "You can't spectate yourself".to_owned();
+        }
+        if !players[&id].game_mode.can_spectate() {
+            return "Only the players in creative mode can spectate".to_owned();
+        }
+        if !players.contains_key(&target) {
+            return "This player is not connected".to_owned();
+        }
+    }
+    players.get_mut(&id).unwrap().spectating = target;
+    server.send(id, ToClient::Spectate(target));
+    match target {
+        Some(target) => format!("Spectating {}", players[&target].name),
+        None => "Stopped spectating".to_owned(),
+    }
+}
+
+/// Send a chat message to all the players
+fn broadcast(server: &mut dyn Server, players: &HashMap<PlayerId, PlayerData>, message: String) {
+    for &id in players.keys() {
+        server.send(id, ToClient::ChatMessage(message.clone()));
+    }
+}
+
+/// Show a title in the middle of the screen of all the players
+fn broadcast_title(
+    server: &mut dyn Server,
+    players: &HashMap<PlayerId, PlayerData>,
+    title: String,
+    subtitle: String,
+) {
+    let (fade_in, stay, fade_out) = TITLE_TIMES;
+    for &id in players.keys() {
+        server.send(
+            id,
+            ToClient::Title {
+                title: title.clone(),
+                subtitle: subtitle.clone(),
+                fade_in,
+                stay,
+                fade_out,
+            },
+        );
+    }
+}
+
+/// Export the cuboid selected by a player with `export`, and return the message for the player
+fn export_selection(
+    world: &World,
+    player: &PlayerData,
+    name: &str,
+    export: impl FnOnce(&Clipboard) -> anyhow::Result<()>,
+) -> String {
+    match player.selection.bounds() {
+        None => "Select a cuboid with the selection tool first".to_owned(),
+        Some((min, max)) if volume(min, max) > MAX_EDIT_VOLUME => format!(
+            "The selection is too big: {} blocks, the maximum is {}",
+            volume(min, max),
+            MAX_EDIT_VOLUME
+        ),
+        Some((min, max)) => match export(&Clipboard::copy(world, min, max, min)) {
+            Ok(()) => format!("Schematic {} saved", name),
+            Err(e) => format!("{:#}", e),
+        },
+    }
+}
+
+/// Tell a player which cuboid is selected
+fn send_selection(server: &mut dyn Server, id: PlayerId, player: &PlayerData) {
+    let format_pos = |pos: Option<BlockPos>| match pos {
+        Some(pos) => format!("({}, {}, {})", pos.px, pos.py, pos.pz),
+        None => "not set".to_owned(),
+    };
+    let mut message = format!(
+        "Selection: first corner {}, second corner {}",
+        format_pos(player.selection.first),
+        format_pos(player.selection.second)
+    );
+    if let Some((min, max)) = player.selection.bounds() {
+        message += &format!(", {} blocks", volume(min, max));
+    }
+    server.send(id, ToClient::ChatMessage(message));
+    server.send(
+        id,
+        ToClient::UpdateSelection(player.selection.first, player.selection.second),
+    );
+}
+
+/// Choose the name of a joining player from the name they asked for. The name is refused if a connected player
+/// or an operator already has it, or if it is the default name of another player, the message says why.
+fn choose_player_name(
+    asked_name: &str,
+    id: PlayerId,
+    profile: &str,
+    players: &HashMap<PlayerId, PlayerData>,
+    config: &ServerConfig,
+) -> Result<String, String> {
+    let name = sanitize_player_name(asked_name, id);
+    if name == sanitize_player_name("", id) {
+        return Ok(name);
+    }
+    if is_default_player_name(&name) {
+        return Err(format!(
+            "The name {} is reserved for the players without a name",
+            name
+        ));
+    }
+    if players
+        .values()
+        .any(|player| player.name.eq_ignore_ascii_case(&name))
+    {
+        return Err(format!(
+            "The name {} is already used by another player",
+            name
+        ));
+    }
+    let operator_name = |operator: &String| match load_player(operator) {
+        Ok(Some(save)) => save.name,
+        _ => String::new(),
+    };
+    if config
+        .operators
+        .iter()
+        .filter(|&operator| operator != profile)
+        .any(|operator| operator_name(operator).eq_ignore_ascii_case(&name))
+    {
+        return Err(format!("The name {} is reserved for an operator", name));
+    }
+    Ok(name)
+}
+
+/// The permission of a player. The host of the server and the operators, listed by profile, can run all the commands.
+fn player_permission(
+    server: &dyn Server,
+    config: &ServerConfig,
+    id: PlayerId,
+    player: &PlayerData,
+) -> CommandPermission {
+    if server.is_host(id)
+        || config
+            .operators
+            .iter()
+            .any(|profile| *profile == player.profile)
+    {
+        CommandPermission::Server
+    } else {
+        config.player_permission
+    }
+}
+
+/// The permission that a player needs to edit the command blocks. The command blocks run their commands with
+/// the permission of the config, so a player can't use them to run the commands that they can't run themselves.
+fn command_block_edit_permission(config: &ServerConfig) -> CommandPermission {
+    config
+        .command_block_permission
+        .max(CommandPermission::World)
+}
+
+/// Tick the furnace at position `pos` every tick, until it has nothing left to do
+fn wake_furnace(world: &mut World, pos: BlockPos) {
+    if let Some(BlockEntity::Furnace(_)) = world.get_block_entity(pos) {
+        if !world.has_block_task(pos, BlockTask::TickFurnace) {
+            world.schedule_repeating_block_task(pos, 1, BlockTask::TickFurnace);
+        }
+    }
+}
+
+/// Save the data of a player, logging the errors
+fn save_player_data(id: PlayerId, player: &PlayerData) {
+    info!("Saving player {} ({})", id, player.name);
+    if let Err(e) = save_player(&player.profile, &player.to_save()) {
+        log::error!("Failed to save player {}: {:?}", id, e);
+    }
+}
+
+/// Save the players, the forceloaded regions, the scoreboard, the teams, the game rules
+/// and the modified chunks now
+fn save_all(
+    world: &mut World,
+    players: &HashMap<PlayerId, PlayerData>,
+    tickets: &ChunkTickets,
+    scoreboard: &Scoreboard,
+    teams: &Teams,
+    game_rules: &GameRules,
+) {
+    for (&id, player) in players.iter() {
+        save_player_data(id, player);
+    }
+    save_forceloads(tickets);
+    save_scoreboard(scoreboard);
+    save_teams(teams);
+    save_game_rules(game_rules);
+    world.save_all_chunks();
+}
+
+fn save_forceloads(tickets: &ChunkTickets) {
+    if let Err(e) = tickets.save_forceloads() {
+        log::error!("Failed to save the forceloaded regions: {:?}", e);
+    }
+}
+
+fn save_scoreboard(scoreboard: &Scoreboard) {
+    if let Err(e) = scoreboard.save() {
+        log::error!("Failed to save the scoreboard: {:?}", e);
+    }
+}
+
+fn save_teams(teams: &Teams) {
+    if let Err(e) = teams.save() {
+        log::error!("Failed to save the teams: {:?}", e);
+    }
+}
+
+fn save_game_rules(game_rules: &GameRules) {
+    if let Err(e) = game_rules.save() {
+        log::error!("Failed to save the game rules: {:?}", e);
+    }
+}
+
+/// Show the value of a game rule, or change it if there is a value. Return the message for the player.
+fn run_game_rule(game_rules: &mut GameRules, name: &str, value: Option<String>) -> String {
+    match value {
+        None => match game_rules.get(name) {
+            Some(value) => format!("{} = {}", name, value),
+            None => format!("Unknown game rule {}", name),
+        },
+        Some(value) => match game_rules.set(name, &value) {
+            Ok(()) => format!("Game rule {} set to {}", name, value),
+            Err(message) => message,
+        },
+    }
+}
+
+/// Send a message to all the players that have the container at `pos` open
+fn send_to_viewers(
+    server: &mut dyn Server,
+    players: &HashMap<PlayerId, PlayerData>,
+    pos: BlockPos,
+    message: ToClient,
+) {
+    for (&id, player) in players.iter() {
+        if player.open_container == Some(pos) {
+            server.send(id, message.clone());
+        }
+    }
+}
+
+/// Send the new state of the sign at `pos` to all the players that have its chunk
+fn send_sign(
+    server: &mut dyn Server,
+    players: &HashMap<PlayerId, PlayerData>,
+    pos: BlockPos,
+    sign: Option<Sign>,
+) {
+    for (&id, player) in players.iter() {
+        if player
+            .loaded_chunks
+            .contains_key(&pos.containing_chunk_pos())
+        {
+            server.send(id, ToClient::UpdateSign(pos, sign.clone()));
+        }
+    }
+}
+
+/// Close the container at `pos` for all the players that have it open
+fn close_container(
+    server: &mut dyn Server,
+    players: &mut HashMap<PlayerId, PlayerData>,
+    pos: BlockPos,
+) {
+    for (&id, player) in players.iter_mut() {
+        if player.open_container == Some(pos) {
+            player.open_container = None;
+            server.send(id, ToClient::CloseContainer);
+        }
     }
 }
 
@@ -2,6 +2,8 @@ use std::sync::Arc;
 use voxel_rs_common::world::{Chunk, CHUNK_SIZE};
 
 mod sunlight;
+#[cfg(test)]
+mod tests;
 pub mod worker;
 
 /// This data structure contains the y position of the highest opaque block
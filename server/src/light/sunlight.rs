@@ -2,8 +2,6 @@ use super::HighestOpaqueBlock;
 use std::sync::Arc;
 use voxel_rs_common::world::{Chunk, CHUNK_SIZE};
 
-// TODO : Add block that are source of light
-
 pub struct LightData {
     pub light_level: [u8; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize],
 }
@@ -16,11 +14,14 @@ impl LightData {
     }
 }
 
-/// Take a 3x3x3 chunks bloc and 3x3 HighestOpaqueBlock and compute the light by using a BFS
+/// Take a 3x3x3 chunks bloc and 3x3 HighestOpaqueBlock and compute the light by using a BFS.
+/// `block_light_levels` contains the light level emitted by each block id.
 pub fn compute_light(
     chunks: Vec<Option<Arc<Chunk>>>,
     highest_opaque_blocks: Vec<Arc<HighestOpaqueBlock>>,
+    block_light_levels: &[u8],
     queue: &mut FastBFSQueue,
+    light_sources: &mut Vec<(usize, usize, usize, u8)>,
     light_data: &mut [u8],
     opaque: &mut [bool],
 ) -> LightData {
@@ -28,6 +29,7 @@ pub fn compute_light(
     assert!(opaque.len() >= (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE * 27) as usize);
     let mut res = LightData::new();
     queue.clear();
+    light_sources.clear();
 
     const MAX_LIGHT: u32 = 15;
 
@@ -36,10 +38,8 @@ pub fn compute_light(
     let csize = CHUNK_SIZE as usize;
 
     let mut transparent_count = 0;
-    let c = chunks[9 + 3 + 1].clone().unwrap();
+    assert!(chunks[9 + 3 + 1].is_some(), "No middle chunk");
     unsafe {
-        let y0 = c.pos.py; // Center chunk height
-
         'triple_loop: for cx in [1, 0, 2].iter() {
             for cy in [1, 0, 2].iter() {
                 for cz in [1, 0, 2].iter() {
@@ -70,29 +70,18 @@ pub fn compute_light(
                     }
                     // Then we fill the BFS queue
                     match chunk {
+                        // The light doesn't propagate into unloaded chunks, and doesn't come from them either:
+                        // it pauses at the border, and the chunk is lit again when the neighbour is loaded.
+                        // Otherwise the light of a chunk would depend on the chunks that were loaded before it.
                         None => {
                             for i in i_range {
-                                for k in j_range.clone() {
-                                    for j in k_range.clone() {
+                                for j in j_range.clone() {
+                                    for k in k_range.clone() {
                                         let s = (*cx * csize + i as usize) * csize * csize * 9
                                             + (*cy * csize + j as usize) * csize * 3
                                             + (*cz * csize + k as usize);
-                                        *opaque.get_unchecked_mut(s) = false;
-                                        if (y0 + *cy as i64 - 1) * CHUNK_SIZE as i64 + j as i64
-                                            > *highest_opaque_block
-                                                .y
-                                                .get_unchecked((i * CHUNK_SIZE + k) as usize)
-                                        {
-                                            *light_data.get_unchecked_mut(s) = 15;
-                                            queue.push((
-                                                *cx * csize + i as usize,
-                                                *cy * csize + j as usize,
-                                                *cz * csize + k as usize,
-                                                15,
-                                            ));
-                                        } else {
-                                            *light_data.get_unchecked_mut(s) = 0;
-                                        }
+                                        *opaque.get_unchecked_mut(s) = true;
+                                        *light_data.get_unchecked_mut(s) = 0;
                                     }
                                 }
                             }
@@ -104,9 +93,20 @@ pub fn compute_light(
                                         let s = (*cx * csize + i as usize) * csize * csize * 9
                                             + (*cy * csize + j as usize) * csize * 3
                                             + (*cz * csize + k as usize);
-                                        if c.get_block_at_unsafe((i, j, k)) != 0 {
+                                        let block = c.get_block_at_unsafe((i, j, k));
+                                        if block != 0 {
                                             // TODO : replace by is opaque
                                             *opaque.get_unchecked_mut(s) = true;
+                                            let block_light =
+                                                *block_light_levels.get_unchecked(block as usize);
+                                            if block_light > 0 {
+                                                light_sources.push((
+                                                    *cx * csize + i as usize,
+                                                    *cy * csize + j as usize,
+                                                    *cz * csize + k as usize,
+                                                    block_light,
+                                                ));
+                                            }
                                         } else {
                                             *opaque.get_unchecked_mut(s) = false;
                                             if c.pos.py * CHUNK_SIZE as i64 + j as i64
@@ -137,13 +137,26 @@ pub fn compute_light(
             }
         }
 
+        // Add the light sources after the sunlight, brightest first
+        light_sources.sort_unstable_by(|a, b| b.3.cmp(&a.3));
+        for source in light_sources.iter() {
+            queue.push(*source);
+        }
+
         const MIN_VAL: isize = CHUNK_SIZE as isize - MAX_LIGHT as isize + 1;
         const MAX_VAL: isize = 2 * CHUNK_SIZE as isize + MAX_LIGHT as isize;
         const DX: [isize; 6] = [1, -1, 0, 0, 0, 0];
         const DY: [isize; 6] = [0, 0, 1, -1, 0, 0];
         const DZ: [isize; 6] = [0, 0, 0, 0, 1, -1];
 
-        while !queue.is_empty() && transparent_count > 0 {
+        // If every transparent block of the center chunk is in the sunlight, it is already fully lit.
+        // With only sunlight, the BFS lights every block with its final value the first time, so it can stop
+        // once all the blocks are lit. Light sources have different levels, so a block can be lit by a dim
+        // source before a brighter one: the BFS must then run until the queue is empty.
+        let needs_propagation = transparent_count > 0;
+        let has_light_sources = !light_sources.is_empty();
+        while !queue.is_empty() && needs_propagation && (transparent_count > 0 || has_light_sources)
+        {
             let (x, y, z, ll) = *queue.pop();
             for i in 0..6 {
                 let (nx, ny, nz) = (x as isize + DX[i], y as isize + DY[i], z as isize + DZ[i]);
@@ -162,11 +175,14 @@ pub fn compute_light(
                     }
                     let ref_light = light_data.get_unchecked_mut(s as usize);
                     if *ref_light < ll - 1 {
+                        // With several light sources a block can be lit more than once
+                        let was_dark = *ref_light == 0;
                         *ref_light = ll - 1;
                         if ll > 1 {
                             queue.push((nx as usize, ny as usize, nz as usize, ll - 1));
                         }
-                        if nx as usize / csize == 1
+                        if was_dark
+                            && nx as usize / csize == 1
                             && ny as usize / csize == 1
                             && nz as usize / csize == 1
                             && !*opaque.get_unchecked(
@@ -0,0 +1,304 @@
+//! Property-based tests of the light engine: random chunks with random light sources must satisfy the invariants
+//! of light propagation. The chunks are described with `ChunkBuilder`s, so that proptest can shrink a failing
+//! world to a few cuboids.
+use super::sunlight::{compute_light, FastBFSQueue};
+use super::HighestOpaqueBlock;
+use proptest::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use voxel_rs_common::block::BlockId;
+use voxel_rs_common::world::{Chunk, ChunkBuilder, ChunkPos, ChunkPosXZ, CHUNK_SIZE};
+
+/// Light level emitted by every block id: air, stone, torch, dim lamp
+const BLOCK_LIGHT_LEVELS: [u8; 4] = [0, 0, 14, 7];
+const STONE: BlockId = 1;
+const TORCH: BlockId = 2;
+const LAMP: BlockId = 3;
+
+/// The reusable buffers of the light computation. They are kept between computations like in the light worker,
+/// so that the tests also catch state leaking from one computation to the next.
+struct LightEngine {
+    queue: FastBFSQueue,
+    light_sources: Vec<(usize, usize, usize, u8)>,
+    light_data: Vec<u8>,
+    opaque: Vec<bool>,
+}
+
+impl LightEngine {
+    fn new() -> Self {
+        let size = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE * 27) as usize;
+        Self {
+            queue: FastBFSQueue::new(),
+            light_sources: Vec::new(),
+            light_data: vec![0; size],
+            opaque: vec![false; size],
+        }
+    }
+
+    /// Compute the light of the chunk `pos` in `world`. The chunks missing from the world are not loaded.
+    fn compute(&mut self, world: &TestWorld, pos: ChunkPos) -> Vec<u8> {
+        let mut chunks = Vec::new();
+        let mut highest_opaque_blocks = Vec::new();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    chunks.push(world.chunks.get(&pos.offset(dx, dy, dz)).cloned());
+                }
+            }
+            for dz in -1..=1 {
+                highest_opaque_blocks
+                    .push(world.highest_opaque_block(pos.offset(dx, 0, dz).into()));
+            }
+        }
+        compute_light(
+            chunks,
+            highest_opaque_blocks,
+            &BLOCK_LIGHT_LEVELS,
+            &mut self.queue,
+            &mut self.light_sources,
+            &mut self.light_data,
+            &mut self.opaque,
+        )
+        .light_level
+        .to_vec()
+    }
+}
+
+struct TestWorld {
+    chunks: HashMap<ChunkPos, Arc<Chunk>>,
+}
+
+impl TestWorld {
+    fn new(builders: &[ChunkBuilder]) -> Self {
+        Self {
+            chunks: builders
+                .iter()
+                .map(|builder| (builder.pos, Arc::new(builder.build())))
+                .collect(),
+        }
+    }
+
+    /// The highest opaque block of a column, over all its loaded chunks like on the server
+    fn highest_opaque_block(&self, column: ChunkPosXZ) -> Arc<HighestOpaqueBlock> {
+        let mut hob = HighestOpaqueBlock::new();
+        for (pos, chunk) in self.chunks.iter() {
+            if ChunkPosXZ::from(*pos) == column {
+                hob.merge(&HighestOpaqueBlock::from_chunk(chunk));
+            }
+        }
+        Arc::new(hob)
+    }
+
+    fn get_block(&self, pos: ChunkPos, (x, y, z): (u32, u32, u32)) -> BlockId {
+        self.chunks
+            .get(&pos)
+            .map_or(0, |chunk| chunk.get_block_at((x, y, z)))
+    }
+}
+
+fn index((x, y, z): (u32, u32, u32)) -> usize {
+    (x * CHUNK_SIZE * CHUNK_SIZE + y * CHUNK_SIZE + z) as usize
+}
+
+/// Check the invariants of the light of a single chunk
+fn check_chunk_light(world: &TestWorld, pos: ChunkPos, light: &[u8]) -> Result<(), TestCaseError> {
+    let hob = world.highest_opaque_block(pos.into());
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                if world.get_block(pos, (x, y, z)) != 0 {
+                    continue;
+                }
+                let level = light[index((x, y, z))];
+                prop_assert!(level <= 15, "light {} at {:?}", level, (x, y, z));
+                // Sunlight
+                let height = pos.py * CHUNK_SIZE as i64 + y as i64;
+                if height > hob.y[(x * CHUNK_SIZE + z) as usize] {
+                    prop_assert_eq!(level, 15, "no sunlight at {:?}", (x, y, z));
+                }
+                // Attenuation: the light changes by at most 1 between neighbouring transparent blocks
+                for &(nx, ny, nz) in &[(x + 1, y, z), (x, y + 1, z), (x, y, z + 1)] {
+                    if nx < CHUNK_SIZE
+                        && ny < CHUNK_SIZE
+                        && nz < CHUNK_SIZE
+                        && world.get_block(pos, (nx, ny, nz)) == 0
+                    {
+                        let neighbour_level = light[index((nx, ny, nz))];
+                        prop_assert!(
+                            (level as i32 - neighbour_level as i32).abs() <= 1,
+                            "light {} at {:?} but {} at {:?}",
+                            level,
+                            (x, y, z),
+                            neighbour_level,
+                            (nx, ny, nz)
+                        );
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A random cuboid of stone or a random light source
+fn cuboid_strategy() -> impl Strategy<Value = ((u32, u32, u32), (u32, u32, u32), BlockId)> {
+    let corner = (0..CHUNK_SIZE, 0..CHUNK_SIZE, 0..CHUNK_SIZE);
+    prop_oneof![
+        3 => (corner.clone(), corner.clone()).prop_map(|(min, max)| (min, max, STONE)),
+        1 => (corner, prop_oneof![Just(TORCH), Just(LAMP)]).prop_map(|(pos, block)| (pos, pos, block)),
+    ]
+}
+
+/// Random chunks with x in `x_range`, and y and z in `-1..=1`
+fn world_strategy(
+    x_range: std::ops::RangeInclusive<i64>,
+) -> impl Strategy<Value = Vec<ChunkBuilder>> {
+    let mut positions = Vec::new();
+    for px in x_range {
+        for py in -1..=1 {
+            for pz in -1..=1 {
+                positions.push(ChunkPos::from((px, py, pz)));
+            }
+        }
+    }
+    let num_positions = positions.len();
+    prop::collection::vec(
+        (
+            0..num_positions,
+            prop::collection::vec(cuboid_strategy(), 0..6),
+        ),
+        1..12,
+    )
+    .prop_map(move |chunks| {
+        chunks
+            .into_iter()
+            .map(|(position, cuboids)| {
+                cuboids.into_iter().fold(
+                    ChunkBuilder::new(positions[position]),
+                    |builder, (first, second, block)| builder.cuboid(first, second, block),
+                )
+            })
+            .collect()
+    })
+}
+
+/// The same world where the center chunk is always loaded, so that its light can be computed
+fn with_center_chunk(mut builders: Vec<ChunkBuilder>) -> Vec<ChunkBuilder> {
+    let center = ChunkPos::from((0, 0, 0));
+    if builders.iter().all(|builder| builder.pos != center) {
+        builders.push(ChunkBuilder::new(center));
+    }
+    builders
+}
+
+fn merge_builders(builders: Vec<ChunkBuilder>) -> Vec<ChunkBuilder> {
+    // The same position can be generated several times, keep the cuboids of all of them
+    let mut merged: Vec<ChunkBuilder> = Vec::new();
+    for builder in builders {
+        match merged.iter_mut().find(|other| other.pos == builder.pos) {
+            Some(other) => other.cuboids.extend(builder.cuboids),
+            None => merged.push(builder),
+        }
+    }
+    merged
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(24))]
+
+    #[test]
+    fn light_invariants(builders in world_strategy(-1..=1)) {
+        let builders = merge_builders(with_center_chunk(builders));
+        let world = TestWorld::new(&builders);
+        let center = ChunkPos::from((0, 0, 0));
+        let light = LightEngine::new().compute(&world, center);
+        check_chunk_light(&world, center, &light)?;
+    }
+
+    #[test]
+    fn light_source_removal_is_reversible(
+        builders in world_strategy(-1..=1),
+        source in (0..CHUNK_SIZE, 0..CHUNK_SIZE, 0..CHUNK_SIZE),
+    ) {
+        let mut builders = merge_builders(with_center_chunk(builders));
+        let center = ChunkPos::from((0, 0, 0));
+        let center_index = builders.iter().position(|builder| builder.pos == center).unwrap();
+        builders[center_index] = builders[center_index].clone().block(source, TORCH);
+        let mut without_source = builders.clone();
+        without_source[center_index] = without_source[center_index].clone().block(source, 0);
+
+        let mut engine = LightEngine::new();
+        let with_source = TestWorld::new(&builders);
+        let original_light = engine.compute(&with_source, center);
+        let removed_light = engine.compute(&TestWorld::new(&without_source), center);
+        check_chunk_light(&TestWorld::new(&without_source), center, &removed_light)?;
+        let readded_light = engine.compute(&with_source, center);
+        prop_assert_eq!(original_light, readded_light);
+    }
+
+    #[test]
+    fn light_is_consistent_across_chunks(builders in world_strategy(-1..=2)) {
+        let mut builders = merge_builders(with_center_chunk(builders));
+        let east = ChunkPos::from((1, 0, 0));
+        if builders.iter().all(|builder| builder.pos != east) {
+            builders.push(ChunkBuilder::new(east));
+        }
+        let world = TestWorld::new(&builders);
+        let center = ChunkPos::from((0, 0, 0));
+        let mut engine = LightEngine::new();
+        let center_light = engine.compute(&world, center);
+        let east_light = engine.compute(&world, east);
+        check_chunk_light(&world, east, &east_light)?;
+        // The light changes by at most 1 across the border between the chunks
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let west_side = (CHUNK_SIZE - 1, y, z);
+                let east_side = (0, y, z);
+                if world.get_block(center, west_side) == 0 && world.get_block(east, east_side) == 0 {
+                    let west_level = center_light[index(west_side)];
+                    let east_level = east_light[index(east_side)];
+                    prop_assert!(
+                        (west_level as i32 - east_level as i32).abs() <= 1,
+                        "light {} and {} across the border at y = {}, z = {}",
+                        west_level,
+                        east_level,
+                        y,
+                        z
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_dim_source_does_not_hide_bright_source() {
+    // A corridor closed on all sides, with a dim lamp close to the torch
+    let center = ChunkPos::from((0, 0, 0));
+    let builder = ChunkBuilder::new(center)
+        .cuboid((0, 0, 0), (31, 31, 31), STONE)
+        .cuboid((4, 4, 2), (4, 4, 12), 0)
+        .block((4, 4, 4), TORCH)
+        .block((4, 4, 8), LAMP);
+    let world = TestWorld::new(&[builder]);
+    let light = LightEngine::new().compute(&world, center);
+    assert_eq!(light[index((4, 4, 5))], 13);
+    assert_eq!(light[index((4, 4, 7))], 11);
+    assert_eq!(light[index((4, 4, 12))], 6);
+}
+
+#[test]
+fn test_light_pauses_at_unloaded_chunks() {
+    // A chunk below a stone roof, next to a chunk that is not loaded yet
+    let center = ChunkPos::from((0, 0, 0));
+    let east = ChunkPos::from((1, 0, 0));
+    let roofed = ChunkBuilder::new(center).cuboid((0, 31, 0), (31, 31, 31), STONE);
+    let mut engine = LightEngine::new();
+    let light = engine.compute(&TestWorld::new(&[roofed.clone()]), center);
+    assert_eq!(light[index((31, 4, 4))], 0);
+    // The sunlight enters from the side once the neighbour is loaded
+    let world = TestWorld::new(&[roofed, ChunkBuilder::new(east)]);
+    let light = engine.compute(&world, center);
+    assert_eq!(light[index((31, 4, 4))], 14);
+    assert_eq!(light[index((30, 4, 4))], 13);
+}
@@ -2,16 +2,19 @@ use super::sunlight::{compute_light, FastBFSQueue};
 use super::HighestOpaqueBlock;
 use std::sync::Arc;
 use voxel_rs_common::{
+    block::Block,
     collections::zero_initialized_vec,
+    debug::{add_chunk_stat, ChunkMetric},
+    registry::Registry,
     worker::{Worker, WorkerState},
     world::{Chunk, LightChunk, CHUNK_SIZE},
 };
 
 static LIGHTING_QUEUE_SIZE: usize = 20;
 
-pub fn start_lighting_worker() -> ChunkLightingWorker {
+pub fn start_lighting_worker(block_registry: &Registry<Block>) -> ChunkLightingWorker {
     Worker::new(
-        ChunkLightingState::new(),
+        ChunkLightingState::new(block_registry),
         LIGHTING_QUEUE_SIZE,
         "Light".into(),
     )
@@ -24,15 +27,22 @@ pub struct ChunkLightingData {
 }
 
 pub struct ChunkLightingState {
+    /// The light level emitted by each block id
+    block_light_levels: Vec<u8>,
     queue_reuse: FastBFSQueue,
+    light_sources_reuse: Vec<(usize, usize, usize, u8)>,
     light_data_reuse: Vec<u8>,
     opaque_reuse: Vec<bool>,
 }
 
 impl ChunkLightingState {
-    pub(self) fn new() -> Self {
+    pub(self) fn new(block_registry: &Registry<Block>) -> Self {
         Self {
+            block_light_levels: (0..block_registry.get_number_of_ids())
+                .map(|id| block_registry.get_value_by_id(id).unwrap().light_level())
+                .collect(),
             queue_reuse: FastBFSQueue::new(),
+            light_sources_reuse: Vec::new(),
             light_data_reuse: unsafe {
                 zero_initialized_vec((CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE * 27) as usize)
             },
@@ -49,11 +59,14 @@ impl WorkerState<ChunkLightingData, Arc<LightChunk>> for ChunkLightingState {
             .as_ref()
             .expect("No middle chunk")
             .pos;
+        add_chunk_stat(ChunkMetric::LightUpdates, pos, 1.0);
         Arc::new(LightChunk {
             light: compute_light(
                 data.chunks,
                 data.highest_opaque_blocks,
+                &self.block_light_levels,
                 &mut self.queue_reuse,
+                &mut self.light_sources_reuse,
                 &mut self.light_data_reuse,
                 &mut self.opaque_reuse,
             )
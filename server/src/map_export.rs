@@ -0,0 +1,178 @@
+//! Export of a top-down map of the world to a PNG image: every block column has the color of its highest
+//! visible block, optionally shaded with the slope of the terrain. The map covers the loaded chunks and the
+//! saved chunks. It is written one row of chunks at a time, so only one row of the map is in memory.
+use crate::save::{load_chunk, saved_chunk_positions};
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use voxel_rs_common::block::BlockId;
+use voxel_rs_common::world::{Chunk, ChunkPos, CHUNK_SIZE};
+
+/// File of the exported map
+pub const MAP_FILE: &str = "save/map.png";
+/// Largest width or height of the map, in chunks
+const MAX_MAP_CHUNKS: i64 = 1024;
+/// Brightness of the columns lower and higher than the column to their north, when the map is shaded
+const SHADOW: f32 = 0.8;
+const HIGHLIGHT: f32 = 1.15;
+
+/// The highest visible block of every block column of a chunk, at index `x * CHUNK_SIZE + z`,
+/// with its height in the world. The blocks without a color are not visible.
+fn chunk_surface(chunk: &Chunk, block_colors: &[[u8; 4]]) -> Vec<Option<(i64, BlockId)>> {
+    let mut surface = vec![None; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+    for x in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            surface[(x * CHUNK_SIZE + z) as usize] = (0..CHUNK_SIZE).rev().find_map(|y| {
+                let block = chunk.get_block_at((x, y, z));
+                let visible = block_colors.get(block as usize).map_or(false, |c| c[3] > 0);
+                if visible {
+                    Some((chunk.pos.py * CHUNK_SIZE as i64 + y as i64, block))
+                } else {
+                    None
+                }
+            });
+        }
+    }
+    surface
+}
+
+/// The color of a column of height `height`, shaded if the height of the column to its north is given
+fn column_color(color: [u8; 4], height: i64, north_height: Option<i64>) -> [u8; 4] {
+    let brightness = match north_height {
+        Some(north) if north > height => SHADOW,
+        Some(north) if north < height => HIGHLIGHT,
+        _ => 1.0,
+    };
+    let shade = |c: u8| (c as f32 * brightness).min(255.0) as u8;
+    [shade(color[0]), shade(color[1]), shade(color[2]), 255]
+}
+
+/// Export the map of the world to `MAP_FILE`. The loaded chunks are used instead of their saved version,
+/// which may be older. Return the size of the map in pixels.
+pub fn export_map(
+    loaded_chunks: Vec<Arc<Chunk>>,
+    block_colors: &[[u8; 4]],
+    shaded: bool,
+) -> Result<(u32, u32)> {
+    let loaded_chunks: HashMap<ChunkPos, Arc<Chunk>> = loaded_chunks
+        .into_iter()
+        .map(|chunk| (chunk.pos, chunk))
+        .collect();
+    // The chunks of every row of chunks, by position of the row along z
+    let mut rows: BTreeMap<i64, Vec<ChunkPos>> = BTreeMap::new();
+    let saved = saved_chunk_positions()?;
+    for &pos in loaded_chunks.keys().chain(saved.iter()) {
+        rows.entry(pos.pz).or_default().push(pos);
+    }
+    for row in rows.values_mut() {
+        row.sort_by_key(|pos| (pos.px, pos.py));
+        row.dedup();
+    }
+    let (min_z, max_z) = match (rows.keys().next(), rows.keys().next_back()) {
+        (Some(&min_z), Some(&max_z)) => (min_z, max_z),
+        _ => bail!("No chunks to export"),
+    };
+    let min_x = rows.values().flatten().map(|pos| pos.px).min().unwrap();
+    let max_x = rows.values().flatten().map(|pos| pos.px).max().unwrap();
+    let (width_chunks, height_chunks) = (max_x - min_x + 1, max_z - min_z + 1);
+    if width_chunks > MAX_MAP_CHUNKS || height_chunks > MAX_MAP_CHUNKS {
+        bail!(
+            "The map would be {} by {} chunks, the largest map is {} by {} chunks",
+            width_chunks,
+            height_chunks,
+            MAX_MAP_CHUNKS,
+            MAX_MAP_CHUNKS
+        );
+    }
+    let size = CHUNK_SIZE as usize;
+    let width = width_chunks as usize * size;
+    let height = height_chunks as usize * size;
+
+    std::fs::create_dir_all("save").context("Failed to create folder save")?;
+    let file = File::create(MAP_FILE).context(format!("Failed to create file {}", MAP_FILE))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .context("Failed to write the header of the map")?;
+    let mut stream = writer.stream_writer();
+    // The heights of the last pixel row, to shade the first pixel row of the next row of chunks
+    let mut north_heights: Vec<Option<i64>> = vec![None; width];
+    for pz in min_z..=max_z {
+        // The surface of the row of chunks, at index `z * width + x`
+        let mut surface: Vec<Option<(i64, BlockId)>> = vec![None; width * size];
+        for &pos in rows.get(&pz).map_or(&[][..], |row| row.as_slice()) {
+            let chunk = match loaded_chunks.get(&pos) {
+                Some(chunk) => chunk.clone(),
+                None => match load_chunk(pos) {
+                    Some(loaded) => Arc::new(loaded.chunk),
+                    None => continue,
+                },
+            };
+            let x0 = (pos.px - min_x) as usize * size;
+            for (i, column) in chunk_surface(&chunk, block_colors).into_iter().enumerate() {
+                let (x, z) = (x0 + i / size, i % size);
+                let pixel = &mut surface[z * width + x];
+                if let Some((column_height, _)) = column {
+                    if pixel.map_or(true, |(pixel_height, _)| column_height > pixel_height) {
+                        *pixel = column;
+                    }
+                }
+            }
+        }
+        let mut pixels = Vec::with_capacity(width * size * 4);
+        for z in 0..size {
+            for x in 0..width {
+                let color = match surface[z * width + x] {
+                    Some((column_height, block)) => {
+                        let north = if shaded { north_heights[x] } else { None };
+                        column_color(block_colors[block as usize], column_height, north)
+                    }
+                    None => [0, 0, 0, 0],
+                };
+                pixels.extend_from_slice(&color);
+                north_heights[x] = surface[z * width + x].map(|(column_height, _)| column_height);
+            }
+        }
+        stream
+            .write_all(&pixels)
+            .context("Failed to write the map")?;
+    }
+    stream.finish().context("Failed to write the map")?;
+    Ok((width as u32, height as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_surface() {
+        // Air, stone and a ladder without a color
+        let colors = [[0, 0, 0, 0], [100, 100, 100, 255], [0, 0, 0, 0]];
+        let mut chunk = Chunk::new(ChunkPos {
+            px: 0,
+            py: 2,
+            pz: 0,
+        });
+        chunk.set_block_at((1, 3, 2), 1);
+        chunk.set_block_at((1, 5, 2), 1);
+        chunk.set_block_at((1, 9, 2), 2);
+        let surface = chunk_surface(&chunk, &colors);
+        assert_eq!(surface[(CHUNK_SIZE + 2) as usize], Some((69, 1)));
+        assert_eq!(surface[0], None);
+    }
+
+    #[test]
+    fn test_column_color() {
+        let color = [100, 200, 250, 255];
+        assert_eq!(column_color(color, 10, None), color);
+        assert_eq!(column_color(color, 10, Some(10)), color);
+        assert_eq!(column_color(color, 10, Some(12)), [80, 160, 200, 255]);
+        // The highlight saturates
+        assert_eq!(column_color(color, 12, Some(10))[2], 255);
+    }
+}
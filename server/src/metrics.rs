@@ -0,0 +1,58 @@
+//! A small HTTP endpoint serving the metrics in the Prometheus text format, for the server operators
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use voxel_rs_common::metrics::render_prometheus;
+
+/// True once the endpoint is started. The metrics are global, so one endpoint serves all the servers of the process.
+static ENDPOINT_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Start serving the metrics on `address`, at the path `/metrics`, if it is not started yet
+pub fn start_metrics_endpoint(address: &str) -> Result<()> {
+    if ENDPOINT_STARTED.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let listener = TcpListener::bind(address).context(format!(
+        "Failed to bind the metrics endpoint to {}",
+        address
+    ))?;
+    ENDPOINT_STARTED.store(true, Ordering::SeqCst);
+    info!("Serving metrics on http://{}/metrics", address);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream
+                .context("Failed to accept metrics connection")
+                .and_then(handle_connection);
+            if let Err(e) = result {
+                warn!("{:?}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream) -> Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut request_line)
+        .context("Failed to read metrics request")?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", render_prometheus())
+    } else {
+        (
+            "404 Not Found",
+            "Not found, the metrics are at /metrics\n".to_owned(),
+        )
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+    .context("Failed to send metrics response")
+}
@@ -0,0 +1,384 @@
+//! Upgrades of the saves written by older versions of the server.
+//! The save manifest records the version of the save format and the names of the block and item ids.
+//! When the server starts, the migrations from the version of the save to `SAVE_VERSION` run in order
+//! on every chunk and player file, and the block and item ids are remapped by name if the data folder changed.
+use crate::player_save::{read_player_file, write_player_file, PlayerSave, PLAYERS_FOLDER};
+use crate::save::{
+    chunk_files, create_backup, list_files, read_chunk_file, write_atomically, write_chunk_file,
+    ChunkSave, SAVE_FOLDER,
+};
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use voxel_rs_common::inventory::{Inventory, ItemStack};
+use voxel_rs_common::registry::Registry;
+
+/// Version of the save format written by this server
+pub const SAVE_VERSION: u32 = 1;
+/// File containing the save manifest
+const MANIFEST_FILE: &str = "save/manifest.ron";
+
+/// The version of a save, and what its ids mean
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveManifest {
+    pub version: u32,
+    /// The name of every block id
+    pub blocks: Vec<String>,
+    /// The name of every item id
+    pub items: Vec<String>,
+}
+
+impl SaveManifest {
+    /// The manifest of a save written with the current data
+    pub fn current<B, I>(blocks: &Registry<B>, items: &Registry<I>) -> Self {
+        Self {
+            version: SAVE_VERSION,
+            blocks: registry_names(blocks),
+            items: registry_names(items),
+        }
+    }
+}
+
+fn registry_names<T>(registry: &Registry<T>) -> Vec<String> {
+    (0..registry.get_number_of_ids())
+        .map(|id| registry.get_name_by_id(id).unwrap().clone())
+        .collect()
+}
+
+/// An upgrade of the save format from `from_version` to the next version
+struct Migration {
+    from_version: u32,
+    description: &'static str,
+    /// Upgrade a chunk, and return true if it changed
+    migrate_chunk: fn(&mut ChunkSave) -> bool,
+    /// Upgrade the data of a player, and return true if it changed
+    migrate_player: fn(&mut PlayerSave) -> bool,
+}
+
+/// The migrations, ordered by version
+const MIGRATIONS: &[Migration] = &[Migration {
+    from_version: 0,
+    description: "Record the block and item names in the save manifest",
+    migrate_chunk: chunk_unchanged,
+    migrate_player: player_unchanged,
+}];
+
+fn chunk_unchanged(_: &mut ChunkSave) -> bool {
+    false
+}
+
+fn player_unchanged(_: &mut PlayerSave) -> bool {
+    false
+}
+
+/// What an upgrade of the save changes
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// The descriptions of the migrations that run
+    pub migrations: Vec<&'static str>,
+    /// The block and item names whose id changed, or that don't exist anymore
+    pub remapped_names: Vec<String>,
+    pub missing_names: Vec<String>,
+    pub chunks_changed: usize,
+    pub players_changed: usize,
+}
+
+impl MigrationReport {
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
+            && self.remapped_names.is_empty()
+            && self.missing_names.is_empty()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for description in &self.migrations {
+            lines.push(format!("Migration: {}", description));
+        }
+        if !self.remapped_names.is_empty() {
+            lines.push(format!("New ids for {}", self.remapped_names.join(", ")));
+        }
+        if !self.missing_names.is_empty() {
+            lines.push(format!(
+                "Removed from the world, they don't exist anymore: {}",
+                self.missing_names.join(", ")
+            ));
+        }
+        lines.push(format!(
+            "{} chunk files and {} player files changed",
+            self.chunks_changed, self.players_changed
+        ));
+        lines
+    }
+}
+
+/// The new id of every old id, `None` if its name doesn't exist anymore
+fn id_remap(
+    old_names: &[String],
+    new_id: impl Fn(&String) -> Option<u32>,
+    report: &mut MigrationReport,
+) -> Vec<Option<u32>> {
+    old_names
+        .iter()
+        .enumerate()
+        .map(|(old_id, name)| {
+            let id = new_id(name);
+            match id {
+                None => report.missing_names.push(name.clone()),
+                Some(id) if id != old_id as u32 => report.remapped_names.push(name.clone()),
+                Some(_) => (),
+            }
+            id
+        })
+        .collect()
+}
+
+/// Change the block and item ids of a chunk, the missing blocks are replaced by air and the missing items are removed.
+/// Return true if the chunk changed.
+fn remap_chunk(save: &mut ChunkSave, blocks: &[Option<u32>], items: &[Option<u32>]) -> bool {
+    let mut changed = false;
+    for (_, block) in save.blocks.iter_mut() {
+        let new_block = blocks.get(*block as usize).cloned().flatten().unwrap_or(0) as u16;
+        changed |= new_block != *block;
+        *block = new_block;
+    }
+    for (_, block_entity) in save.block_entities.iter_mut() {
+        if let Some(inventory) = block_entity.inventory_mut() {
+            changed |= remap_inventory(inventory, items);
+        }
+    }
+    changed
+}
+
+/// Change the item ids of the inventory and the cursor stack of a player, the missing items are removed.
+/// Return true if the player changed.
+fn remap_player(save: &mut PlayerSave, items: &[Option<u32>]) -> bool {
+    let mut changed = false;
+    if let Some(inventory) = &mut save.inventory {
+        changed |= remap_inventory(inventory, items);
+    }
+    if let Some(stack) = save.cursor_stack {
+        let new_stack = remap_stack(stack, items);
+        changed |= new_stack != Some(stack);
+        save.cursor_stack = new_stack;
+    }
+    changed
+}
+
+/// Change the item ids of an inventory, the missing items are removed. Return true if the inventory changed.
+fn remap_inventory(inventory: &mut Inventory, items: &[Option<u32>]) -> bool {
+    let mut changed = false;
+    for slot in 0..inventory.size() {
+        if let Some(stack) = inventory.get(slot) {
+            let new_stack = remap_stack(stack, items);
+            changed |= new_stack != Some(stack);
+            inventory.set(slot, new_stack);
+        }
+    }
+    changed
+}
+
+fn remap_stack(stack: ItemStack, items: &[Option<u32>]) -> Option<ItemStack> {
+    let item = items.get(stack.item as usize).cloned().flatten()?;
+    Some(ItemStack { item, ..stack })
+}
+
+/// Upgrade the save to the current version and data before the server loads it.
+/// In a dry run, only report what would change and leave the save untouched.
+/// The save is backed up before it is modified.
+pub fn migrate_save<B, I>(
+    blocks: &Registry<B>,
+    items: &Registry<I>,
+    dry_run: bool,
+    backups_kept: usize,
+) -> Result<MigrationReport> {
+    let current = SaveManifest::current(blocks, items);
+    let chunks = chunk_files()?;
+    let players = list_files(Path::new(PLAYERS_FOLDER))?;
+    let manifest = if Path::new(MANIFEST_FILE).is_file() {
+        let string = std::fs::read_to_string(MANIFEST_FILE)
+            .context(format!("Failed to read save manifest {}", MANIFEST_FILE))?;
+        ron::de::from_str(&string)
+            .context(format!("Failed to parse save manifest {}", MANIFEST_FILE))?
+    } else if chunks.is_empty() && players.is_empty() {
+        // A new world
+        current.clone()
+    } else {
+        // The saves older than the manifest used the same ids as the current data
+        SaveManifest {
+            version: 0,
+            ..current.clone()
+        }
+    };
+    if manifest.version > SAVE_VERSION {
+        bail!(
+            "The save has version {}, but this server only supports versions up to {}",
+            manifest.version,
+            SAVE_VERSION
+        );
+    }
+
+    let mut report = MigrationReport::default();
+    let migrations: Vec<_> = MIGRATIONS
+        .iter()
+        .filter(|migration| migration.from_version >= manifest.version)
+        .collect();
+    report.migrations = migrations.iter().map(|m| m.description).collect();
+    let block_remap = id_remap(
+        &manifest.blocks,
+        |name| blocks.get_id_by_name(name),
+        &mut report,
+    );
+    let item_remap = id_remap(
+        &manifest.items,
+        |name| items.get_id_by_name(name),
+        &mut report,
+    );
+    if report.is_empty() {
+        if !dry_run && !Path::new(MANIFEST_FILE).is_file() {
+            write_manifest(&current)?;
+        }
+        return Ok(report);
+    }
+
+    if !dry_run {
+        info!("Upgrading the save, backing it up first");
+        create_backup(backups_kept).context("Failed to back up the save before upgrading it")?;
+    }
+    let remap_ids = !report.remapped_names.is_empty() || !report.missing_names.is_empty();
+    for path in chunks {
+        let mut save = match read_chunk_file(&path) {
+            Ok(save) => save,
+            Err(e) => {
+                warn!("{:?}", e);
+                continue;
+            }
+        };
+        let mut changed = false;
+        for migration in &migrations {
+            changed |= (migration.migrate_chunk)(&mut save);
+        }
+        if remap_ids {
+            changed |= remap_chunk(&mut save, &block_remap, &item_remap);
+        }
+        if changed {
+            report.chunks_changed += 1;
+            if !dry_run {
+                write_chunk_file(&path, &mut save)?;
+            }
+        }
+    }
+    for path in players {
+        let mut save = match read_player_file(&path) {
+            Ok(save) => save,
+            Err(e) => {
+                warn!("{:?}", e);
+                continue;
+            }
+        };
+        let mut changed = false;
+        for migration in &migrations {
+            changed |= (migration.migrate_player)(&mut save);
+        }
+        if remap_ids {
+            changed |= remap_player(&mut save, &item_remap);
+        }
+        if changed {
+            report.players_changed += 1;
+            if !dry_run {
+                write_player_file(&path, &save)?;
+            }
+        }
+    }
+    if !dry_run {
+        write_manifest(&current)?;
+    }
+    Ok(report)
+}
+
+fn write_manifest(manifest: &SaveManifest) -> Result<()> {
+    std::fs::create_dir_all(SAVE_FOLDER)
+        .context(format!("Failed to create folder {}", SAVE_FOLDER))?;
+    let string = ron::ser::to_string_pretty(manifest, Default::default())
+        .context("Failed to serialize save manifest")?;
+    write_atomically(Path::new(MANIFEST_FILE), &string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use voxel_rs_common::block::entity::BlockEntity;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (version, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.from_version, version as u32);
+        }
+        assert_eq!(MIGRATIONS.len() as u32, SAVE_VERSION);
+    }
+
+    #[test]
+    fn test_remap_chunk() {
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|&name| name.to_owned())
+                .collect::<Vec<_>>()
+        };
+        let new_blocks = names(&["air", "stone", "dirt"]);
+        let new_id = |name: &String| {
+            new_blocks
+                .iter()
+                .position(|n| n == name)
+                .map(|id| id as u32)
+        };
+        let mut report = MigrationReport::default();
+        let block_remap = id_remap(&names(&["air", "dirt", "marble"]), new_id, &mut report);
+        let item_remap = vec![Some(1), None];
+        assert_eq!(block_remap, vec![Some(0), Some(2), None]);
+        assert_eq!(report.remapped_names, vec!["dirt"]);
+        assert_eq!(report.missing_names, vec!["marble"]);
+
+        let mut inventory = Inventory::new(2);
+        inventory.set(0, Some(ItemStack::new(0, 3)));
+        inventory.set(1, Some(ItemStack::new(1, 5)));
+        let mut save = ChunkSave {
+            blocks: vec![(10, 1), (20, 2), (5, 0)],
+            block_entities: vec![((0, 0, 0), BlockEntity::Container(inventory))],
+            block_tasks: Vec::new(),
+            checksum: None,
+        };
+        assert!(remap_chunk(&mut save, &block_remap, &item_remap));
+        assert_eq!(save.blocks, vec![(10, 2), (20, 0), (5, 0)]);
+        let inventory = save.block_entities[0].1.inventory().unwrap();
+        assert_eq!(inventory.get(0), Some(ItemStack::new(1, 3)));
+        assert_eq!(inventory.get(1), None);
+        // Remapping with the same ids changes nothing
+        assert!(!remap_chunk(
+            &mut save,
+            &[Some(0), Some(1), Some(2)],
+            &[Some(0), Some(1)]
+        ));
+    }
+
+    #[test]
+    fn test_remap_player() {
+        let item_remap = vec![Some(1), None];
+        let mut inventory = Inventory::new(2);
+        inventory.set(0, Some(ItemStack::new(0, 3)));
+        inventory.set(1, Some(ItemStack::new(1, 5)));
+        let mut save = PlayerSave {
+            inventory: Some(inventory),
+            cursor_stack: Some(ItemStack::new(1, 2)),
+            ..PlayerSave::default()
+        };
+        assert!(remap_player(&mut save, &item_remap));
+        let inventory = save.inventory.as_ref().unwrap();
+        assert_eq!(inventory.get(0), Some(ItemStack::new(1, 3)));
+        assert_eq!(inventory.get(1), None);
+        assert_eq!(save.cursor_stack, None);
+        // A player without a saved inventory doesn't change
+        assert!(!remap_player(&mut PlayerSave::default(), &item_remap));
+    }
+}
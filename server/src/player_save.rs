@@ -0,0 +1,89 @@
+//! Persistence of the per-player data. The players are saved under their profile,
+//! the random secret that their client sends when joining.
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use voxel_rs_common::inventory::{Inventory, ItemStack};
+use voxel_rs_common::player::{GameMode, Waypoint};
+use voxel_rs_common::stats::PlayerStats;
+
+/// Folder containing the data of every player
+pub const PLAYERS_FOLDER: &str = "save/players";
+/// Before the profiles, the players were saved under their id. Only the id of the host was stable.
+const LEGACY_HOST_FILE: &str = "0.ron";
+
+/// The part of the player data that is saved to disk
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PlayerSave {
+    /// The last name of the player, reserved for the operators
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub waypoints: Vec<Waypoint>,
+    #[serde(default)]
+    pub stats: PlayerStats,
+    #[serde(default)]
+    pub game_mode: GameMode,
+    /// `None` if the inventory was never saved, then the player gets the starting items
+    #[serde(default)]
+    pub inventory: Option<Inventory>,
+    /// The stack that the player was moving around in the inventory screen
+    #[serde(default)]
+    pub cursor_stack: Option<ItemStack>,
+}
+
+/// The file of a player, the profile must be valid
+fn player_file(profile: &str) -> PathBuf {
+    PathBuf::from(PLAYERS_FOLDER).join(format!("{}.ron", profile))
+}
+
+/// Give the save of the host from before the profiles to the profile of the host, if the profile has no save yet.
+/// The saves of the other players can't be matched with their profile, they are left as they are.
+pub fn adopt_legacy_host_save(profile: &str) {
+    let legacy_path = PathBuf::from(PLAYERS_FOLDER).join(LEGACY_HOST_FILE);
+    let path = player_file(profile);
+    if !legacy_path.is_file() || path.exists() {
+        return;
+    }
+    match std::fs::rename(&legacy_path, &path) {
+        Ok(()) => info!(
+            "Moved the save of the host {} to {}",
+            legacy_path.display(),
+            path.display()
+        ),
+        Err(e) => warn!(
+            "Failed to move the save of the host {}: {:?}",
+            legacy_path.display(),
+            e
+        ),
+    }
+}
+
+/// Load the saved data of a player, or `None` if the player was never saved
+pub fn load_player(profile: &str) -> Result<Option<PlayerSave>> {
+    let path = player_file(profile);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    read_player_file(&path).map(Some)
+}
+
+pub fn read_player_file(path: &Path) -> Result<PlayerSave> {
+    let string = std::fs::read_to_string(path)
+        .context(format!("Failed to read player file {}", path.display()))?;
+    ron::de::from_str(&string).context(format!("Failed to parse player file {}", path.display()))
+}
+
+pub fn write_player_file(path: &Path, save: &PlayerSave) -> Result<()> {
+    let string = ron::ser::to_string_pretty(save, Default::default())
+        .context("Failed to serialize player data")?;
+    std::fs::write(path, string).context(format!("Failed to write player file {}", path.display()))
+}
+
+/// Save the data of a player
+pub fn save_player(profile: &str, save: &PlayerSave) -> Result<()> {
+    std::fs::create_dir_all(PLAYERS_FOLDER)
+        .context(format!("Failed to create folder {}", PLAYERS_FOLDER))?;
+    write_player_file(&player_file(profile), save)
+}
@@ -0,0 +1,76 @@
+//! Portals: linked pairs of portal blocks. A player that stands in a portal for a moment is teleported to the
+//! linked portal. It must then leave the portal blocks before it can go through a portal again, so that it
+//! doesn't bounce back and forth between the two portals.
+use voxel_rs_common::world::BlockPos;
+
+/// The block placed by the `/portal` command
+pub const PORTAL_BLOCK: &str = "portal";
+/// Seconds that a player must stand in a portal before it is teleported
+pub const PORTAL_DWELL_TIME: f32 = 2.0;
+
+/// Where a player is with respect to the portals
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PortalDwell {
+    /// The player is not in a portal
+    Outside,
+    /// The player is in the portal at this position, since this many seconds
+    Entering(BlockPos, f32),
+    /// The player was teleported and didn't leave the portal blocks yet
+    Arrived,
+}
+
+impl Default for PortalDwell {
+    fn default() -> Self {
+        Self::Outside
+    }
+}
+
+impl PortalDwell {
+    /// Advance by `dt` seconds, `portal` being the portal the player is in.
+    /// Return the portal to teleport the player through, if it stood in it long enough.
+    pub fn update(&mut self, portal: Option<BlockPos>, dt: f32) -> Option<BlockPos> {
+        *self = match (*self, portal) {
+            (_, None) => Self::Outside,
+            (Self::Arrived, Some(_)) => Self::Arrived,
+            (Self::Entering(entered, time), Some(pos)) if entered == pos => {
+                let time = time + dt;
+                if time >= PORTAL_DWELL_TIME {
+                    *self = Self::Arrived;
+                    return Some(pos);
+                }
+                Self::Entering(pos, time)
+            }
+            (_, Some(pos)) => Self::Entering(pos, 0.0),
+        };
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_portal_dwell() {
+        let portal = BlockPos::from((3, 50, 3));
+        let other_portal = BlockPos::from((4, 50, 3));
+        let mut dwell = PortalDwell::default();
+        assert_eq!(dwell.update(Some(portal), 0.5), None);
+        assert_eq!(dwell.update(Some(portal), PORTAL_DWELL_TIME / 2.0), None);
+        // Moving to another portal block starts over
+        assert_eq!(dwell.update(Some(other_portal), 0.5), None);
+        assert_eq!(
+            dwell.update(Some(other_portal), PORTAL_DWELL_TIME / 2.0),
+            None
+        );
+        assert_eq!(
+            dwell.update(Some(other_portal), PORTAL_DWELL_TIME / 2.0),
+            Some(other_portal)
+        );
+        assert_eq!(dwell, PortalDwell::Arrived);
+        // Standing in the destination portal doesn't teleport back
+        assert_eq!(dwell.update(Some(portal), PORTAL_DWELL_TIME * 2.0), None);
+        assert_eq!(dwell.update(None, 0.1), None);
+        assert_eq!(dwell, PortalDwell::Outside);
+    }
+}
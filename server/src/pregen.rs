@@ -0,0 +1,154 @@
+//! Pre-generation of the chunks around the spawn with `/pregen`, so that the terrain is already saved
+//! when the players arrive. The chunks go through the worldgen worker after the chunks that the players need.
+use crate::world::World;
+use std::time::{Duration, Instant};
+use voxel_rs_common::player::PlayerId;
+use voxel_rs_common::world::ChunkPos;
+
+/// Lowest chunk height that is pre-generated, below the surface of the default terrain
+const MIN_CHUNK_Y: i64 = -2;
+/// Highest chunk height that is pre-generated, above the surface of the default terrain
+const MAX_CHUNK_Y: i64 = 4;
+/// Maximum number of pre-generated chunks in the worldgen queue, so that the chunks of the players still fit
+const MAX_CHUNKS_IN_QUEUE: usize = 8;
+/// Time between two progress reports
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// Largest radius in chunks accepted by `/pregen`
+pub const MAX_PREGEN_RADIUS: u64 = 64;
+
+pub struct Pregen {
+    /// The chunks that were not sent to the worldgen worker yet, the closest to the center last
+    remaining: Vec<ChunkPos>,
+    /// The chunks in the worldgen queue
+    generating: Vec<ChunkPos>,
+    total: usize,
+    start: Instant,
+    last_report: Instant,
+    /// The player who started the pre-generation, who receives the progress reports too
+    pub issuer: Option<PlayerId>,
+}
+
+impl Pregen {
+    /// Pre-generate the chunk columns within `radius` chunks of `center`, horizontally
+    pub fn new(center: ChunkPos, radius: u64, issuer: Option<PlayerId>) -> Self {
+        let r = radius as i64;
+        let mut remaining = Vec::new();
+        for px in center.px - r..=center.px + r {
+            for pz in center.pz - r..=center.pz + r {
+                for py in MIN_CHUNK_Y..=MAX_CHUNK_Y {
+                    remaining.push(ChunkPos { px, py, pz });
+                }
+            }
+        }
+        // The closest chunks are generated first
+        let horizontal_distance = |pos: &ChunkPos| {
+            let (dx, dz) = (pos.px - center.px, pos.pz - center.pz);
+            dx * dx + dz * dz
+        };
+        remaining.sort_by_key(|pos| std::cmp::Reverse(horizontal_distance(pos)));
+        let now = Instant::now();
+        Self {
+            total: remaining.len(),
+            remaining,
+            generating: Vec::new(),
+            start: now,
+            last_report: now,
+            issuer,
+        }
+    }
+
+    /// Forget the chunks that the worldgen worker finished, and send it the next chunks
+    pub fn update(&mut self, world: &mut World) {
+        self.generating
+            .retain(|&pos| world.is_in_worldgen_queue(pos));
+        while self.generating.len() < MAX_CHUNKS_IN_QUEUE {
+            let pos = match self.remaining.last() {
+                Some(&pos) => pos,
+                None => break,
+            };
+            if !world.enqueue_chunk_for_pregen(pos) {
+                // The worldgen queue is full
+                break;
+            }
+            self.remaining.pop();
+            if world.is_in_worldgen_queue(pos) {
+                self.generating.push(pos);
+            }
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining.is_empty() && self.generating.is_empty()
+    }
+
+    /// Number of chunks that are generated and saved
+    pub fn num_done(&self) -> usize {
+        self.total - self.remaining.len() - self.generating.len()
+    }
+
+    /// Describe the progress, with an estimation of the remaining time
+    pub fn progress(&self) -> String {
+        let done = self.num_done();
+        let mut message = format!(
+            "Pre-generation: {}% ({}/{} chunks)",
+            done * 100 / self.total.max(1),
+            done,
+            self.total
+        );
+        if done > 0 && done < self.total {
+            let left = self
+                .start
+                .elapsed()
+                .mul_f64((self.total - done) as f64 / done as f64);
+            message += &format!(", about {} s left", left.as_secs());
+        }
+        message
+    }
+
+    /// Describe the progress if the last report is old enough
+    pub fn poll_report(&mut self) -> Option<String> {
+        if self.last_report.elapsed() < REPORT_INTERVAL {
+            return None;
+        }
+        self.last_report = Instant::now();
+        Some(self.progress())
+    }
+
+    /// Describe the finished pre-generation
+    pub fn summary(&self) -> String {
+        format!(
+            "Pre-generation finished: {} chunks in {} s",
+            self.total,
+            self.start.elapsed().as_secs()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_chunks_first() {
+        let center = ChunkPos {
+            px: 10,
+            py: 0,
+            pz: -3,
+        };
+        let pregen = Pregen::new(center, 2, None);
+        let columns = 5 * 5;
+        let heights = (MAX_CHUNK_Y - MIN_CHUNK_Y + 1) as usize;
+        assert_eq!(pregen.remaining.len(), columns * heights);
+        // The chunks of the center column are at the end, the corners at the beginning
+        for pos in &pregen.remaining[pregen.remaining.len() - heights..] {
+            assert_eq!((pos.px, pos.pz), (center.px, center.pz));
+        }
+        let first = pregen.remaining[0];
+        assert_eq!(
+            ((first.px - center.px).abs(), (first.pz - center.pz).abs()),
+            (2, 2)
+        );
+        assert_eq!(pregen.num_done(), 0);
+        assert!(!pregen.is_done());
+    }
+}
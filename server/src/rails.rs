@@ -0,0 +1,103 @@
+//! Shapes of the rails: when the blocks around a rail change, the rail takes the shape that connects it to the
+//! rails around it, see `RailShape::from_neighbors`. The shape only depends on where the rails are, so the rails
+//! reshaped by a tick don't change again when their neighbors are checked on the next tick.
+use crate::world::World;
+use std::collections::{HashMap, HashSet, VecDeque};
+use voxel_rs_common::{
+    block::{rail::RailShape, Block, BlockId, HorizontalFace},
+    registry::Registry,
+    world::BlockPos,
+};
+
+const SIDES: [HorizontalFace; 4] = [
+    HorizontalFace::PosX,
+    HorizontalFace::NegX,
+    HorizontalFace::PosZ,
+    HorizontalFace::NegZ,
+];
+
+/// Get the position next to the side `side` of `pos`, `dy` blocks higher
+fn neighbor(pos: BlockPos, side: HorizontalFace, dy: i64) -> BlockPos {
+    let (dx, dz) = match side {
+        HorizontalFace::PosX => (1, 0),
+        HorizontalFace::NegX => (-1, 0),
+        HorizontalFace::PosZ => (0, 1),
+        HorizontalFace::NegZ => (0, -1),
+    };
+    BlockPos::from((pos.px + dx, pos.py + dy, pos.pz + dz))
+}
+
+pub struct RailShaping {
+    /// The kind of rail and the shape of the block ids that are rails
+    rails: HashMap<BlockId, (String, RailShape)>,
+    /// The block id of every shape of every kind of rail
+    variants: HashMap<(String, RailShape), BlockId>,
+    /// The rails that must be checked, in order
+    pending: VecDeque<BlockPos>,
+    pending_set: HashSet<BlockPos>,
+}
+
+impl RailShaping {
+    pub fn new(blocks: &Registry<Block>) -> Self {
+        let rails: HashMap<_, _> = (0..blocks.get_number_of_ids())
+            .filter_map(|id| {
+                let (rail, shape) = blocks.get_value_by_id(id).unwrap().rail()?;
+                Some((id as BlockId, (rail.to_owned(), shape)))
+            })
+            .collect();
+        let variants = rails
+            .iter()
+            .map(|(&id, (rail, shape))| ((rail.clone(), *shape), id))
+            .collect();
+        Self {
+            rails,
+            variants,
+            pending: VecDeque::new(),
+            pending_set: HashSet::new(),
+        }
+    }
+
+    fn is_rail(&self, block: BlockId) -> bool {
+        self.rails.contains_key(&block)
+    }
+
+    fn add_pending(&mut self, world: &World, pos: BlockPos) {
+        if self.is_rail(world.get_block(pos)) && self.pending_set.insert(pos) {
+            self.pending.push_back(pos);
+        }
+    }
+
+    /// Check the rails that changed, and the rails next to the blocks that changed or one block higher or lower
+    pub fn add_block_updates(&mut self, world: &World, updates: &[BlockPos]) {
+        for &pos in updates {
+            self.add_pending(world, pos);
+            for &side in SIDES.iter() {
+                for dy in -1..=1 {
+                    self.add_pending(world, neighbor(pos, side, dy));
+                }
+            }
+        }
+    }
+
+    /// Check the pending rails, and return the ones that must change shape
+    pub fn tick(&mut self, world: &World) -> Vec<(BlockPos, BlockId)> {
+        let mut changes = Vec::new();
+        while let Some(pos) = self.pending.pop_front() {
+            self.pending_set.remove(&pos);
+            // The block may have changed since it was added, or its chunk may be unloaded
+            let (rail, shape) = match self.rails.get(&world.get_block(pos)) {
+                Some(rail) => rail,
+                None => continue,
+            };
+            let new_shape = RailShape::from_neighbors(|side, dy| {
+                self.is_rail(world.get_block(neighbor(pos, side, dy)))
+            });
+            if let Some(new_shape) = new_shape.filter(|new_shape| new_shape != shape) {
+                if let Some(&variant) = self.variants.get(&(rail.clone(), new_shape)) {
+                    changes.push((pos, variant));
+                }
+            }
+        }
+        changes
+    }
+}
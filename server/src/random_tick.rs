@@ -0,0 +1,143 @@
+//! Random ticks: every tick, a few random blocks of every loaded chunk get a chance to change.
+//! This way the slow processes, like the snow piling up or melting and the crops growing, happen at random places over time.
+use crate::biomes::BiomeMaps;
+use crate::world::World;
+use std::time::{SystemTime, UNIX_EPOCH};
+use voxel_rs_common::{
+    block::{Block, BlockId, BlockShape},
+    registry::Registry,
+    world::{BlockPos, ChunkPosXZ, CHUNK_SIZE},
+};
+
+/// The snow piles up in the cold biomes until it has this many layers
+const MAX_ACCUMULATED_LAYERS: u8 = 3;
+
+/// Small xorshift generator, good enough to pick the ticked blocks
+struct Rng(u64);
+
+impl Rng {
+    fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Self(nanos | 1)
+    }
+
+    /// Uniform random number in `[0, bound)`
+    fn below(&mut self, bound: u32) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        ((self.0 >> 32) % bound as u64) as u32
+    }
+}
+
+pub struct RandomTicks {
+    rng: Rng,
+    /// The single layer that piles up in the cold biomes, if the content has one
+    accumulating_layer: Option<BlockId>,
+}
+
+impl RandomTicks {
+    pub fn new(blocks: &Registry<Block>) -> Self {
+        let accumulating_layer = (0..blocks.get_number_of_ids())
+            .find(|&id| {
+                let block = blocks.get_value_by_id(id).unwrap();
+                block.accumulates() && block.shape() == BlockShape::Layer(1)
+            })
+            .map(|id| id as BlockId);
+        Self {
+            rng: Rng::from_time(),
+            accumulating_layer,
+        }
+    }
+
+    /// Tick `ticks_per_chunk` random blocks of every loaded chunk, and return the blocks that must change
+    pub fn tick(
+        &mut self,
+        world: &World,
+        biome_maps: &mut BiomeMaps,
+        blocks: &Registry<Block>,
+        ticks_per_chunk: u32,
+    ) -> Vec<(BlockPos, BlockId)> {
+        let size = CHUNK_SIZE as i64;
+        let mut changes = Vec::new();
+        for chunk_pos in world.loaded_chunk_positions() {
+            for _ in 0..ticks_per_chunk {
+                let pos = BlockPos::from((
+                    chunk_pos.px * size + self.rng.below(CHUNK_SIZE) as i64,
+                    chunk_pos.py * size + self.rng.below(CHUNK_SIZE) as i64,
+                    chunk_pos.pz * size + self.rng.below(CHUNK_SIZE) as i64,
+                ));
+                changes.extend(self.tick_snow(world, biome_maps, blocks, pos));
+                changes.extend(self.tick_crop(world, blocks, pos));
+            }
+        }
+        changes
+    }
+
+    /// Under the open sky, pile up snow in the cold biomes and melt it elsewhere.
+    /// There is no weather yet, so it only depends on the biome.
+    fn tick_snow(
+        &self,
+        world: &World,
+        biome_maps: &mut BiomeMaps,
+        blocks: &Registry<Block>,
+        pos: BlockPos,
+    ) -> Option<(BlockPos, BlockId)> {
+        if world.highest_block(pos) != Some(pos.py) {
+            return None;
+        }
+        let block = blocks.get_value_by_id(world.get_block(pos) as u32)?;
+        let (x, _, z) = pos.pos_in_containing_chunk();
+        let is_cold = biome_maps
+            .get(ChunkPosXZ::from(pos.containing_chunk_pos()))
+            .get(x, z)
+            .is_cold();
+        match block.shape() {
+            BlockShape::Layer(layers) if block.accumulates() => {
+                let layers = if !is_cold {
+                    layers - 1
+                } else if layers < MAX_ACCUMULATED_LAYERS {
+                    layers + 1
+                } else {
+                    return None;
+                };
+                if layers == 0 {
+                    Some((pos, 0))
+                } else {
+                    block
+                        .variant_with_layers(layers, blocks)
+                        .map(|layer| (pos, layer))
+                }
+            }
+            BlockShape::Full if is_cold => {
+                let above = BlockPos::from((pos.px, pos.py + 1, pos.pz));
+                self.accumulating_layer.map(|layer| (above, layer))
+            }
+            _ => None,
+        }
+    }
+
+    /// Grow the crops planted on a fertile block, if there is enough light above them
+    fn tick_crop(
+        &self,
+        world: &World,
+        blocks: &Registry<Block>,
+        pos: BlockPos,
+    ) -> Option<(BlockPos, BlockId)> {
+        let block = blocks.get_value_by_id(world.get_block(pos) as u32)?;
+        let (stage, min_light) = block.crop_stage()?;
+        let below = BlockPos::from((pos.px, pos.py - 1, pos.pz));
+        let above = BlockPos::from((pos.px, pos.py + 1, pos.pz));
+        let is_fertile = blocks
+            .get_value_by_id(world.get_block(below) as u32)
+            .map_or(false, |below| below.is_fertile());
+        if !is_fertile || world.get_light(above) < min_light {
+            return None;
+        }
+        block
+            .variant_with_stage(stage + 1, blocks)
+            .map(|next_stage| (pos, next_stage))
+    }
+}
@@ -0,0 +1,190 @@
+//! Partition of the loaded chunks into independent regions, simulated in parallel.
+//! Two chunks are in the same region if they are at most `REGION_MARGIN` chunks apart, so nothing that happens
+//! in a region during a tick can reach another region. What a region changes outside of its block entities
+//! (the blocks of the world, the messages to the players) is returned as events and applied after all the
+//! regions were ticked, so interactions between regions are deferred to the next tick.
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use voxel_rs_common::{
+    block::entity::{BlockEntity, FurnaceProgress},
+    inventory::Inventory,
+    item::Item,
+    registry::Registry,
+    smelting::SmeltingRecipe,
+    world::{BlockPos, ChunkPos},
+};
+
+/// Chunks closer than this, in every direction, are in the same region
+const REGION_MARGIN: i64 = 1;
+
+/// Group the chunks into regions: the connected components of the chunks, two chunks being connected if they
+/// are at most `REGION_MARGIN` chunks apart in every direction
+pub fn partition_regions(chunks: impl IntoIterator<Item = ChunkPos>) -> Vec<Vec<ChunkPos>> {
+    let mut remaining: HashSet<ChunkPos> = chunks.into_iter().collect();
+    let mut regions = Vec::new();
+    while let Some(&start) = remaining.iter().next() {
+        remaining.remove(&start);
+        let mut region = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some(pos) = queue.pop_front() {
+            region.push(pos);
+            for dx in -REGION_MARGIN..=REGION_MARGIN {
+                for dy in -REGION_MARGIN..=REGION_MARGIN {
+                    for dz in -REGION_MARGIN..=REGION_MARGIN {
+                        let neighbor = pos.offset(dx, dy, dz);
+                        if remaining.remove(&neighbor) {
+                            queue.push_back(neighbor);
+                        }
+                    }
+                }
+            }
+        }
+        regions.push(region);
+    }
+    regions
+}
+
+/// Something that a region wants to change outside of itself
+#[derive(Debug, Clone)]
+pub enum RegionEvent {
+    /// The inventory of a container changed
+    ContainerChanged(BlockPos, Inventory),
+    /// The progress of a furnace changed
+    FurnaceProgressChanged(BlockPos, FurnaceProgress),
+    /// A furnace started or stopped burning, its block must be swapped
+    FurnaceActivityChanged(BlockPos, bool),
+}
+
+/// The threads that tick the regions
+pub struct RegionPool {
+    pool: rayon::ThreadPool,
+}
+
+impl RegionPool {
+    /// Create the pool, with one thread per core if `threads` is 0
+    pub fn new(threads: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("region-{}", i))
+            .build()
+            .context("Failed to create the region thread pool")?;
+        log::info!(
+            "Simulating the regions on {} threads",
+            pool.current_num_threads()
+        );
+        Ok(Self { pool })
+    }
+
+    /// Tick the block entities of every region in parallel, and return the events of all the regions
+    pub fn tick_block_entities(
+        &self,
+        regions: Vec<Vec<(BlockPos, &mut BlockEntity)>>,
+        dt: f32,
+        recipes: &[SmeltingRecipe],
+        items: &Registry<Item>,
+    ) -> Vec<RegionEvent> {
+        self.pool.install(|| {
+            regions
+                .into_par_iter()
+                .flat_map_iter(|block_entities| {
+                    tick_region_block_entities(block_entities, dt, recipes, items)
+                })
+                .collect()
+        })
+    }
+}
+
+/// Group the block entities by region. The block entities outside of the regions are put in an extra region.
+pub fn block_entities_by_region<'a>(
+    regions: &[Vec<ChunkPos>],
+    block_entities: impl Iterator<Item = (BlockPos, &'a mut BlockEntity)>,
+) -> Vec<Vec<(BlockPos, &'a mut BlockEntity)>> {
+    let region_of_chunk: HashMap<ChunkPos, usize> = regions
+        .iter()
+        .enumerate()
+        .flat_map(|(i, region)| region.iter().map(move |&pos| (pos, i)))
+        .collect();
+    let mut grouped: Vec<Vec<_>> = (0..=regions.len()).map(|_| Vec::new()).collect();
+    for (pos, block_entity) in block_entities {
+        let region = region_of_chunk
+            .get(&pos.containing_chunk_pos())
+            .copied()
+            .unwrap_or(regions.len());
+        grouped[region].push((pos, block_entity));
+    }
+    grouped.retain(|block_entities| !block_entities.is_empty());
+    grouped
+}
+
+fn tick_region_block_entities(
+    block_entities: Vec<(BlockPos, &mut BlockEntity)>,
+    dt: f32,
+    recipes: &[SmeltingRecipe],
+    items: &Registry<Item>,
+) -> Vec<RegionEvent> {
+    let mut events = Vec::new();
+    for (pos, block_entity) in block_entities {
+        match block_entity {
+            BlockEntity::Furnace(furnace) => {
+                let was_active = furnace.is_active();
+                let old_progress = furnace.progress();
+                if furnace.tick(dt, recipes, items) {
+                    events.push(RegionEvent::ContainerChanged(
+                        pos,
+                        furnace.inventory.clone(),
+                    ));
+                }
+                if furnace.progress() != old_progress {
+                    events.push(RegionEvent::FurnaceProgressChanged(pos, furnace.progress()));
+                }
+                if furnace.is_active() != was_active {
+                    events.push(RegionEvent::FurnaceActivityChanged(
+                        pos,
+                        furnace.is_active(),
+                    ));
+                }
+            }
+            // The command blocks run from the block tasks
+            BlockEntity::Container(_)
+            | BlockEntity::Sign(_)
+            | BlockEntity::CommandBlock(_)
+            | BlockEntity::Portal(_) => (),
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(px: i64, py: i64, pz: i64) -> ChunkPos {
+        ChunkPos { px, py, pz }
+    }
+
+    #[test]
+    fn test_partition_regions() {
+        let mut regions = partition_regions(vec![
+            chunk(0, 0, 0),
+            chunk(1, 1, 0),
+            chunk(2, 1, 1),
+            chunk(10, 0, 0),
+            chunk(12, 0, 0),
+        ]);
+        for region in regions.iter_mut() {
+            region.sort_by_key(|pos| (pos.px, pos.py, pos.pz));
+        }
+        regions.sort_by_key(|region| (region[0].px, region.len()));
+        assert_eq!(
+            regions,
+            vec![
+                vec![chunk(0, 0, 0), chunk(1, 1, 0), chunk(2, 1, 1)],
+                vec![chunk(10, 0, 0)],
+                vec![chunk(12, 0, 0)],
+            ]
+        );
+    }
+}
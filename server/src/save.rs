@@ -0,0 +1,457 @@
+//! Persistence of the chunks, and backups of the save folder
+use crate::scheduler::{BlockTask, PendingTask};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use voxel_rs_common::block::entity::BlockEntity;
+use voxel_rs_common::player::PlayerId;
+use voxel_rs_common::world::{BlockPos, Chunk, ChunkPos, CompressedChunk, CHUNK_SIZE};
+
+/// Folder containing the whole save of the world
+pub const SAVE_FOLDER: &str = "save";
+/// Folder containing the modified chunks
+const CHUNKS_FOLDER: &str = "save/chunks";
+/// Folder containing the zipped backups of the save folder
+const BACKUPS_FOLDER: &str = "backups";
+
+/// The part of a chunk that is saved to disk.
+/// Block and item ids are saved as is, the save manifest records their names to remap them if the data folder changes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChunkSave {
+    /// The RLE-compressed blocks, as numbers of blocks and block ids
+    pub blocks: Vec<(u16, u16)>,
+    /// The block entities of the chunk, with their absolute position
+    pub block_entities: Vec<((i64, i64, i64), BlockEntity)>,
+    /// The pending block tasks of the chunk, with the absolute position of their block.
+    /// The chunks saved before the scheduler existed don't have any.
+    #[serde(default)]
+    pub block_tasks: Vec<PendingTask<((i64, i64, i64), BlockTask)>>,
+    /// The checksum of the other fields, set when the chunk is written.
+    /// The chunks saved before the checksums existed don't have one, and are not checked.
+    #[serde(default)]
+    pub checksum: Option<u64>,
+}
+
+/// A chunk loaded from the save, with the state attached to its blocks
+pub struct LoadedChunk {
+    pub chunk: Chunk,
+    pub block_entities: Vec<(BlockPos, BlockEntity)>,
+    pub block_tasks: Vec<PendingTask<(BlockPos, BlockTask)>>,
+}
+
+fn chunk_file(pos: ChunkPos) -> PathBuf {
+    PathBuf::from(CHUNKS_FOLDER).join(format!("{}_{}_{}.ron", pos.px, pos.py, pos.pz))
+}
+
+/// Write a file through a temporary file, so that a backup never contains a partially written file
+pub fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, contents)
+        .context(format!("Failed to write file {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path).context(format!("Failed to replace file {}", path.display()))
+}
+
+/// List the files of the saved chunks
+pub fn chunk_files() -> Result<Vec<PathBuf>> {
+    list_files(Path::new(CHUNKS_FOLDER))
+}
+
+/// List the positions of the saved chunks, from the names of their files
+pub fn saved_chunk_positions() -> Result<Vec<ChunkPos>> {
+    Ok(chunk_files()?
+        .iter()
+        .filter_map(|path| {
+            let stem = path.file_stem()?.to_str()?;
+            let mut coords = stem.split('_').map(|coord| coord.parse::<i64>().ok());
+            match (coords.next(), coords.next(), coords.next(), coords.next()) {
+                (Some(Some(px)), Some(Some(py)), Some(Some(pz)), None) => {
+                    Some(ChunkPos { px, py, pz })
+                }
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// List the `.ron` files of a folder, or nothing if the folder doesn't exist
+pub fn list_files(folder: &Path) -> Result<Vec<PathBuf>> {
+    if !folder.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in
+        std::fs::read_dir(folder).context(format!("Failed to read folder {}", folder.display()))?
+    {
+        let path = entry
+            .context(format!("Failed to read folder {}", folder.display()))?
+            .path();
+        if path.extension().map_or(false, |ext| ext == "ron") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The FNV-1a hash of the serialized fields of a chunk save, apart from the checksum
+fn chunk_checksum(save: &ChunkSave) -> Result<u64> {
+    let fields = (&save.blocks, &save.block_entities, &save.block_tasks);
+    let string = ron::ser::to_string(&fields).context("Failed to serialize chunk")?;
+    Ok(string.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    }))
+}
+
+/// Why a chunk file couldn't be read
+#[derive(Debug, thiserror::Error)]
+pub enum ChunkFileError {
+    /// The file couldn't be read, it is not known to be damaged
+    #[error("Failed to read chunk file {}", .path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        error: std::io::Error,
+    },
+    /// The contents of the file are damaged
+    #[error("Chunk file {} is corrupted, {reason}", .path.display())]
+    Corrupted { path: PathBuf, reason: String },
+}
+
+/// Read a chunk file, checking that it was not damaged since it was written
+pub fn read_chunk_file(path: &Path) -> Result<ChunkSave, ChunkFileError> {
+    let corrupted = |reason| ChunkFileError::Corrupted {
+        path: path.to_owned(),
+        reason,
+    };
+    let string = std::fs::read_to_string(path).map_err(|error| ChunkFileError::Io {
+        path: path.to_owned(),
+        error,
+    })?;
+    let save: ChunkSave =
+        ron::de::from_str(&string).map_err(|e| corrupted(format!("it can't be parsed: {}", e)))?;
+    if let Some(checksum) = save.checksum {
+        let expected = chunk_checksum(&save).map_err(|e| corrupted(format!("{:#}", e)))?;
+        if checksum != expected {
+            return Err(corrupted("its checksum is wrong".to_owned()));
+        }
+    }
+    let num_blocks = save.blocks.iter().map(|&(len, _)| len as u64).sum::<u64>();
+    let chunk_volume = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as u64;
+    if num_blocks != chunk_volume {
+        return Err(corrupted(format!(
+            "it has {} blocks instead of {}",
+            num_blocks, chunk_volume
+        )));
+    }
+    Ok(save)
+}
+
+/// Write a chunk file, with the checksum of the chunk
+pub fn write_chunk_file(path: &Path, save: &mut ChunkSave) -> Result<()> {
+    save.checksum = Some(chunk_checksum(save)?);
+    let string = ron::ser::to_string(save).context("Failed to serialize chunk")?;
+    write_atomically(path, &string)
+}
+
+/// Move a corrupted chunk file to a `.corrupt` file next to it, so that the chunk is generated again
+/// but the damaged data can still be recovered by hand
+fn set_aside_corrupted_chunk(path: &Path) {
+    let corrupt_path = path.with_extension("corrupt");
+    match std::fs::rename(path, &corrupt_path) {
+        Ok(()) => warn!(
+            "Moved corrupted chunk file {} to {}, the chunk will be generated again",
+            path.display(),
+            corrupt_path.display()
+        ),
+        Err(e) => log::error!(
+            "Failed to move corrupted chunk file {}: {:?}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// Save a chunk, its block entities and its pending block tasks
+pub fn save_chunk(
+    chunk: &Chunk,
+    block_entities: Vec<(BlockPos, BlockEntity)>,
+    block_tasks: Vec<PendingTask<(BlockPos, BlockTask)>>,
+) -> Result<()> {
+    let mut save = ChunkSave {
+        blocks: CompressedChunk::from_chunk(chunk).data,
+        block_entities: block_entities
+            .into_iter()
+            .map(|(pos, block_entity)| ((pos.px, pos.py, pos.pz), block_entity))
+            .collect(),
+        block_tasks: block_tasks
+            .into_iter()
+            .map(|pending| PendingTask {
+                task: (
+                    (pending.task.0.px, pending.task.0.py, pending.task.0.pz),
+                    pending.task.1,
+                ),
+                delay: pending.delay,
+                interval: pending.interval,
+            })
+            .collect(),
+        checksum: None,
+    };
+    std::fs::create_dir_all(CHUNKS_FOLDER)
+        .context(format!("Failed to create folder {}", CHUNKS_FOLDER))?;
+    write_chunk_file(&chunk_file(chunk.pos), &mut save)
+}
+
+/// Load a saved chunk, or return `None` if the chunk was never saved.
+/// The errors are logged, and the chunk is then generated again. The corrupted chunk files are set aside,
+/// the files that couldn't be read are left in place.
+pub fn load_chunk(pos: ChunkPos) -> Option<LoadedChunk> {
+    let path = chunk_file(pos);
+    if !path.is_file() {
+        return None;
+    }
+    match read_chunk_file(&path) {
+        Ok(save) => {
+            let chunk = CompressedChunk {
+                pos,
+                data: save.blocks,
+            }
+            .to_chunk();
+            let block_entities = save
+                .block_entities
+                .into_iter()
+                .map(|(pos, block_entity)| (BlockPos::from(pos), block_entity))
+                .collect();
+            let block_tasks = save
+                .block_tasks
+                .into_iter()
+                .map(|pending| PendingTask {
+                    task: (BlockPos::from(pending.task.0), pending.task.1),
+                    delay: pending.delay,
+                    interval: pending.interval,
+                })
+                .collect();
+            Some(LoadedChunk {
+                chunk,
+                block_entities,
+                block_tasks,
+            })
+        }
+        Err(e @ ChunkFileError::Corrupted { .. }) => {
+            warn!("{}", e);
+            set_aside_corrupted_chunk(&path);
+            None
+        }
+        Err(e) => {
+            log::error!("{:?}", anyhow::Error::from(e));
+            None
+        }
+    }
+}
+
+/// The largest radius in chunks of `/repair-region`
+pub const MAX_REPAIR_RADIUS: u64 = 64;
+
+/// A `/repair-region` in progress. The saved chunks of the region are checked a few at a time with
+/// `check_chunks`, so that reading them doesn't stall the server.
+pub struct RegionRepair {
+    /// The saved chunks that were not checked yet
+    remaining: Vec<ChunkPos>,
+    checked: usize,
+    corrupted: usize,
+    /// Number of corrupted chunks that were saved again from the loaded world
+    pub resaved: usize,
+    /// The player who started the repair, who receives the summary
+    pub issuer: Option<PlayerId>,
+}
+
+impl RegionRepair {
+    /// Repair the saved chunks within `radius` chunks of `center` horizontally
+    pub fn new(center: ChunkPos, radius: u64, issuer: Option<PlayerId>) -> Result<Self> {
+        let radius = radius as i64;
+        let remaining = saved_chunk_positions()?
+            .into_iter()
+            .filter(|pos| {
+                (pos.px - center.px).abs() <= radius && (pos.pz - center.pz).abs() <= radius
+            })
+            .collect();
+        Ok(Self {
+            remaining,
+            checked: 0,
+            corrupted: 0,
+            resaved: 0,
+            issuer,
+        })
+    }
+
+    /// Check at most `max_chunks` chunks, set the corrupted ones aside and return their positions.
+    /// The chunks that can't be read are not corrupted, they are left in place.
+    pub fn check_chunks(&mut self, max_chunks: usize) -> Vec<ChunkPos> {
+        let mut corrupted = Vec::new();
+        for _ in 0..max_chunks {
+            let pos = match self.remaining.pop() {
+                Some(pos) => pos,
+                None => break,
+            };
+            self.checked += 1;
+            let path = chunk_file(pos);
+            match read_chunk_file(&path) {
+                Ok(_) => {}
+                Err(e @ ChunkFileError::Corrupted { .. }) => {
+                    warn!("{}", e);
+                    set_aside_corrupted_chunk(&path);
+                    corrupted.push(pos);
+                }
+                Err(e) => warn!("{:?}", anyhow::Error::from(e)),
+            }
+        }
+        self.corrupted += corrupted.len();
+        corrupted
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    /// Describe the finished repair
+    pub fn summary(&self) -> String {
+        format!(
+            "Checked {} chunks, {} were corrupted: {} were saved again from the loaded world, \
+             the others will be generated again. The damaged files were kept as .corrupt files.",
+            self.checked, self.corrupted, self.resaved
+        )
+    }
+}
+
+/// Zip the save folder into the backups folder, and delete the oldest backups to keep only `backups_kept` of them
+pub fn create_backup(backups_kept: usize) -> Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let path = PathBuf::from(BACKUPS_FOLDER).join(format!("save-{}.zip", timestamp));
+    info!("Creating backup {}", path.display());
+    std::fs::create_dir_all(BACKUPS_FOLDER)
+        .context(format!("Failed to create folder {}", BACKUPS_FOLDER))?;
+    let file =
+        File::create(&path).context(format!("Failed to create backup {}", path.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    std::fs::create_dir_all(SAVE_FOLDER)
+        .context(format!("Failed to create folder {}", SAVE_FOLDER))?;
+    let mut folders = vec![PathBuf::from(SAVE_FOLDER)];
+    while let Some(folder) = folders.pop() {
+        let entries = std::fs::read_dir(&folder)
+            .context(format!("Failed to read folder {}", folder.display()))?;
+        for entry in entries {
+            let entry_path = entry
+                .context(format!("Failed to read folder {}", folder.display()))?
+                .path();
+            if entry_path.is_dir() {
+                folders.push(entry_path);
+            } else if entry_path.extension().map_or(true, |ext| ext != "tmp") {
+                let contents = std::fs::read(&entry_path)
+                    .context(format!("Failed to read file {}", entry_path.display()))?;
+                // Zip files always use / as the separator
+                let name = entry_path
+                    .iter()
+                    .map(|part| part.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                zip.start_file(name, options)
+                    .context(format!("Failed to write backup {}", path.display()))?;
+                zip.write_all(&contents)
+                    .context(format!("Failed to write backup {}", path.display()))?;
+            }
+        }
+    }
+    zip.finish()
+        .context(format!("Failed to write backup {}", path.display()))?;
+    rotate_backups(backups_kept)
+}
+
+/// Delete the oldest backups to keep only `backups_kept` of them
+fn rotate_backups(backups_kept: usize) -> Result<()> {
+    let mut backups = std::fs::read_dir(BACKUPS_FOLDER)
+        .context(format!("Failed to read folder {}", BACKUPS_FOLDER))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let timestamp = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix("save-")?
+                .strip_suffix(".zip")?
+                .parse::<u64>()
+                .ok()?;
+            Some((timestamp, path))
+        })
+        .collect::<Vec<_>>();
+    backups.sort();
+    let num_deleted = backups.len().saturating_sub(backups_kept);
+    for (_, path) in backups.into_iter().take(num_deleted) {
+        info!("Deleting old backup {}", path.display());
+        std::fs::remove_file(&path)
+            .context(format!("Failed to delete backup {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_checksum() {
+        let save = ChunkSave {
+            blocks: vec![(100, 1), (200, 0)],
+            block_entities: Vec::new(),
+            block_tasks: Vec::new(),
+            checksum: None,
+        };
+        let checksum = chunk_checksum(&save).unwrap();
+        // The checksum doesn't depend on itself
+        let mut damaged = ChunkSave {
+            checksum: Some(checksum),
+            ..save
+        };
+        assert_eq!(chunk_checksum(&damaged).unwrap(), checksum);
+        damaged.blocks[1] = (200, 2);
+        assert_ne!(chunk_checksum(&damaged).unwrap(), checksum);
+    }
+
+    #[test]
+    fn test_read_chunk_file_errors() {
+        let folder = std::env::temp_dir().join(format!("voxel-rs-chunks-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        // A file that can't be read is not corrupted
+        let missing = folder.join("missing.ron");
+        assert!(matches!(
+            read_chunk_file(&missing),
+            Err(ChunkFileError::Io { .. })
+        ));
+        let garbage = folder.join("garbage.ron");
+        std::fs::write(&garbage, "not a chunk").unwrap();
+        assert!(matches!(
+            read_chunk_file(&garbage),
+            Err(ChunkFileError::Corrupted { .. })
+        ));
+        let mut save = ChunkSave {
+            blocks: vec![(100, 1), (200, 0)],
+            block_entities: Vec::new(),
+            block_tasks: Vec::new(),
+            checksum: None,
+        };
+        let too_few_blocks = folder.join("too_few_blocks.ron");
+        write_chunk_file(&too_few_blocks, &mut save).unwrap();
+        assert!(matches!(
+            read_chunk_file(&too_few_blocks),
+            Err(ChunkFileError::Corrupted { .. })
+        ));
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
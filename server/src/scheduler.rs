@@ -0,0 +1,221 @@
+//! Delayed and repeating game events, run on a given server tick.
+//! The tasks due on the same tick run in the order they were scheduled, so that the simulation doesn't depend
+//! on the iteration order of a map. The pending block tasks are saved with the chunk of their block, with the
+//! number of ticks they still have to wait, so that they resume when the chunk is loaded again.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use voxel_rs_common::world::BlockPos;
+
+/// Number of server ticks since the scheduler was created
+pub type Tick = u64;
+
+/// A task that is waiting for its tick
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingTask<T> {
+    pub task: T,
+    /// Ticks left before the task runs
+    pub delay: Tick,
+    /// Ticks between two runs of the task, if it repeats
+    pub interval: Option<Tick>,
+}
+
+/// Run tasks after a number of ticks, once or repeatedly
+#[derive(Debug, Clone)]
+pub struct Scheduler<T> {
+    current_tick: Tick,
+    /// The sequence number of the next scheduled task, to order the tasks due on the same tick
+    next_sequence: u64,
+    queue: BTreeMap<(Tick, u64), (T, Option<Tick>)>,
+}
+
+impl<T> Scheduler<T> {
+    pub fn new() -> Self {
+        Self {
+            current_tick: 0,
+            next_sequence: 0,
+            queue: BTreeMap::new(),
+        }
+    }
+
+    /// Number of pending tasks
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn insert(&mut self, due_tick: Tick, task: T, interval: Option<Tick>) {
+        self.queue
+            .insert((due_tick, self.next_sequence), (task, interval));
+        self.next_sequence += 1;
+    }
+
+    /// Run `task` once, `ticks` ticks from now. A task is never run on the tick it is scheduled.
+    pub fn schedule_in(&mut self, ticks: Tick, task: T) {
+        self.insert(self.current_tick + ticks.max(1), task, None);
+    }
+
+    /// Run `task` every `interval` ticks, the first time `interval` ticks from now
+    pub fn schedule_repeating(&mut self, interval: Tick, task: T) {
+        let interval = interval.max(1);
+        self.insert(self.current_tick + interval, task, Some(interval));
+    }
+
+    /// Add back a task that was saved from `pending`
+    pub fn restore(&mut self, pending: PendingTask<T>) {
+        self.insert(
+            self.current_tick + pending.delay.max(1),
+            pending.task,
+            pending.interval.map(|interval| interval.max(1)),
+        );
+    }
+
+    /// Remove the pending tasks that match `predicate`, including the repeating ones
+    pub fn cancel(&mut self, mut predicate: impl FnMut(&T) -> bool) {
+        self.queue.retain(|_, (task, _)| !predicate(task));
+    }
+
+    /// Iterate over the pending tasks that match `predicate`, in the order they will run
+    pub fn pending<'a>(
+        &'a self,
+        mut predicate: impl FnMut(&T) -> bool + 'a,
+    ) -> impl Iterator<Item = PendingTask<&'a T>> + 'a {
+        let current_tick = self.current_tick;
+        self.queue
+            .iter()
+            .filter(move |(_, (task, _))| predicate(task))
+            .map(move |(&(due_tick, _), (task, interval))| PendingTask {
+                task,
+                delay: due_tick - current_tick,
+                interval: *interval,
+            })
+    }
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Scheduler<T> {
+    /// Advance to the next tick and return the tasks due on it, in order.
+    /// The repeating tasks are scheduled again for their next run.
+    pub fn advance(&mut self) -> Vec<T> {
+        self.current_tick += 1;
+        let not_due = self.queue.split_off(&(self.current_tick + 1, 0));
+        let due = std::mem::replace(&mut self.queue, not_due);
+        let mut tasks = Vec::with_capacity(due.len());
+        for ((due_tick, _), (task, interval)) in due {
+            if let Some(interval) = interval {
+                self.insert(due_tick + interval, task.clone(), Some(interval));
+            }
+            tasks.push(task);
+        }
+        tasks
+    }
+}
+
+/// A task about a block, saved with the chunk of the block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockTask {
+    /// Schedule the runs of the command of the command block, for the command blocks that don't have them yet
+    StartCommandBlock,
+    /// Run the command of the command block
+    RunCommandBlock,
+    /// Tick the furnace. It repeats every tick while the furnace is burning fuel or can start smelting.
+    TickFurnace,
+}
+
+/// The scheduler of the block tasks of the loaded chunks
+pub type BlockScheduler = Scheduler<(BlockPos, BlockTask)>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_tick_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule_in(2, "b");
+        scheduler.schedule_in(1, "a");
+        scheduler.schedule_in(2, "c");
+        scheduler.schedule_in(0, "d");
+        assert_eq!(scheduler.advance(), vec!["a", "d"]);
+        assert_eq!(scheduler.advance(), vec!["b", "c"]);
+        assert!(scheduler.advance().is_empty());
+        assert_eq!(scheduler.len(), 0);
+    }
+
+    #[test]
+    fn test_repeating() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule_repeating(2, "repeat");
+        scheduler.schedule_in(4, "once");
+        let runs: Vec<_> = (0..6).map(|_| scheduler.advance()).collect();
+        assert_eq!(
+            runs,
+            vec![
+                vec![],
+                vec!["repeat"],
+                vec![],
+                vec!["once", "repeat"],
+                vec![],
+                vec!["repeat"],
+            ]
+        );
+        scheduler.cancel(|&task| task == "repeat");
+        assert_eq!(scheduler.len(), 0);
+    }
+
+    #[test]
+    fn test_take_and_restore() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule_in(5, (1, "late"));
+        scheduler.schedule_repeating(3, (1, "repeat"));
+        scheduler.schedule_in(3, (2, "other"));
+        scheduler.advance();
+        let taken: Vec<_> = scheduler
+            .pending(|&(chunk, _)| chunk == 1)
+            .map(|pending| PendingTask {
+                task: *pending.task,
+                delay: pending.delay,
+                interval: pending.interval,
+            })
+            .collect();
+        scheduler.cancel(|&(chunk, _)| chunk == 1);
+        assert_eq!(
+            taken,
+            vec![
+                PendingTask {
+                    task: (1, "repeat"),
+                    delay: 2,
+                    interval: Some(3),
+                },
+                PendingTask {
+                    task: (1, "late"),
+                    delay: 4,
+                    interval: None,
+                },
+            ]
+        );
+        assert_eq!(scheduler.len(), 1);
+
+        // The delays resume where they stopped
+        for _ in 0..10 {
+            scheduler.advance();
+        }
+        for pending in taken {
+            scheduler.restore(pending);
+        }
+        let runs: Vec<_> = (0..5).map(|_| scheduler.advance()).collect();
+        assert_eq!(
+            runs,
+            vec![
+                vec![],
+                vec![(1, "repeat")],
+                vec![],
+                vec![(1, "late")],
+                vec![(1, "repeat")],
+            ]
+        );
+    }
+}
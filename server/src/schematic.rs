@@ -0,0 +1,183 @@
+//! Import and export of clipboards as schematic files, to share builds between worlds
+use crate::world_edit::Clipboard;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use voxel_rs_common::block::{Block, BlockId};
+use voxel_rs_common::data::vox::{load_voxel_model, save_voxel_model, VoxelMaterial, VoxelModel};
+use voxel_rs_common::registry::Registry;
+
+/// Folder containing the schematics, shared by all the worlds
+const SCHEMATICS_FOLDER: &str = "schematics";
+/// Maximum length of the name of a schematic
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The native schematic format
+#[derive(Debug, Serialize, Deserialize)]
+struct Schematic {
+    /// Size of the cuboid along each axis
+    size: (i64, i64, i64),
+    /// The names of the blocks, so that the schematic doesn't depend on the block ids of a world
+    palette: Vec<String>,
+    /// The RLE-compressed indices in the palette of the blocks, in the order of the clipboard
+    blocks: Vec<(u32, u16)>,
+}
+
+/// Get the path of the file of a schematic, checking that the name cannot escape the schematics folder
+fn schematic_file(name: &str, extension: &str) -> Result<PathBuf> {
+    if name.is_empty()
+        || name.len() > MAX_NAME_LENGTH
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        bail!(
+            "Invalid schematic name {}, only letters, digits, _ and - are allowed",
+            name
+        );
+    }
+    Ok(PathBuf::from(SCHEMATICS_FOLDER).join(format!("{}.{}", name, extension)))
+}
+
+/// Save a clipboard in the native format, in `schematics/<name>.ron`
+pub fn save_schematic(name: &str, clipboard: &Clipboard, blocks: &Registry<Block>) -> Result<()> {
+    let path = schematic_file(name, "ron")?;
+    let mut palette: Vec<String> = Vec::new();
+    let mut compressed_blocks: Vec<(u32, u16)> = Vec::new();
+    for &block in clipboard.blocks.iter() {
+        let name = blocks
+            .get_value_by_id(block as u32)
+            .map(|block| block.name.clone())
+            .with_context(|| format!("Unknown block id {}", block))?;
+        let index = match palette.iter().position(|n| *n == name) {
+            Some(index) => index as u16,
+            None => {
+                palette.push(name);
+                (palette.len() - 1) as u16
+            }
+        };
+        match compressed_blocks.last_mut() {
+            Some((count, last)) if *last == index => *count += 1,
+            _ => compressed_blocks.push((1, index)),
+        }
+    }
+    let schematic = Schematic {
+        size: clipboard.size,
+        palette,
+        blocks: compressed_blocks,
+    };
+    std::fs::create_dir_all(SCHEMATICS_FOLDER)
+        .context(format!("Failed to create folder {}", SCHEMATICS_FOLDER))?;
+    let string = ron::ser::to_string(&schematic).context("Failed to serialize schematic")?;
+    std::fs::write(&path, string)
+        .context(format!("Failed to write schematic file {}", path.display()))
+}
+
+/// Export a clipboard to a `.vox` file, in `schematics/<name>.vox`, using the color of every block
+pub fn export_vox(name: &str, clipboard: &Clipboard, block_colors: &[[u8; 4]]) -> Result<()> {
+    let path = schematic_file(name, "vox")?;
+    let model = VoxelModel {
+        size_x: clipboard.size.0 as usize,
+        size_y: clipboard.size.1 as usize,
+        size_z: clipboard.size.2 as usize,
+        voxels: clipboard
+            .blocks
+            .iter()
+            .map(|&block| u32::from_le_bytes(block_colors[block as usize]))
+            .collect(),
+        full: clipboard.blocks.iter().map(|&block| block != 0).collect(),
+        materials: vec![VoxelMaterial::default(); clipboard.blocks.len()],
+    };
+    std::fs::create_dir_all(SCHEMATICS_FOLDER)
+        .context(format!("Failed to create folder {}", SCHEMATICS_FOLDER))?;
+    save_voxel_model(path.to_str().unwrap(), &model)
+}
+
+/// Load a schematic into a clipboard, from the native format if `schematics/<name>.ron` exists,
+/// or else from `schematics/<name>.vox`. The voxels of `.vox` files become the block with the closest color.
+/// The minimum corner of the clipboard is at the position of the player.
+pub fn load_schematic(
+    name: &str,
+    blocks: &Registry<Block>,
+    block_colors: &[[u8; 4]],
+) -> Result<Clipboard> {
+    let native_path = schematic_file(name, "ron")?;
+    let vox_path = schematic_file(name, "vox")?;
+    if native_path.is_file() {
+        let string = std::fs::read_to_string(&native_path).context(format!(
+            "Failed to read schematic file {}",
+            native_path.display()
+        ))?;
+        let schematic: Schematic = ron::de::from_str(&string).context(format!(
+            "Failed to parse schematic file {}",
+            native_path.display()
+        ))?;
+        let palette = schematic
+            .palette
+            .iter()
+            .map(|name| {
+                blocks
+                    .get_id_by_name(name)
+                    .map(|id| id as BlockId)
+                    .with_context(|| format!("Unknown block {} in the schematic", name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut clipboard_blocks = Vec::new();
+        for &(count, index) in schematic.blocks.iter() {
+            let block = *palette
+                .get(index as usize)
+                .context("Invalid palette index in the schematic")?;
+            clipboard_blocks.extend(std::iter::repeat(block).take(count as usize));
+        }
+        let (sx, sy, sz) = schematic.size;
+        if sx <= 0 || sy <= 0 || sz <= 0 || clipboard_blocks.len() as i64 != sx * sy * sz {
+            bail!("The size of the schematic doesn't match its number of blocks");
+        }
+        Ok(Clipboard {
+            offset: (0, 0, 0),
+            size: schematic.size,
+            blocks: clipboard_blocks,
+        })
+    } else if vox_path.is_file() {
+        let model = load_voxel_model(vox_path.to_str().unwrap())?;
+        let clipboard_blocks = model
+            .voxels
+            .iter()
+            .zip(model.full.iter())
+            .map(|(&color, &full)| {
+                if full {
+                    closest_block(color.to_le_bytes(), block_colors)
+                } else {
+                    0
+                }
+            })
+            .collect();
+        Ok(Clipboard {
+            offset: (0, 0, 0),
+            size: (
+                model.size_x as i64,
+                model.size_y as i64,
+                model.size_z as i64,
+            ),
+            blocks: clipboard_blocks,
+        })
+    } else {
+        bail!("No schematic named {}", name)
+    }
+}
+
+/// Find the non-air block whose color is the closest to `color`
+fn closest_block(color: [u8; 4], block_colors: &[[u8; 4]]) -> BlockId {
+    let distance = |block_color: &[u8; 4]| -> i32 {
+        (0..3)
+            .map(|i| (color[i] as i32 - block_color[i] as i32).pow(2))
+            .sum()
+    };
+    block_colors
+        .iter()
+        .enumerate()
+        .filter(|(_, block_color)| block_color[3] > 0)
+        .min_by_key(|(_, block_color)| distance(block_color))
+        .map(|(id, _)| id as BlockId)
+        .unwrap_or(0)
+}
@@ -0,0 +1,402 @@
+//! The scoreboard: objectives with a score per player, shown on the HUD of the players.
+//! The scores of the custom objectives are only changed by the `/scoreboard` command, the scores of the other
+//! objectives follow the statistics of the players.
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use voxel_rs_common::scoreboard::{DisplayedObjective, ScoreboardDisplay};
+use voxel_rs_common::stats::PlayerStats;
+
+/// File containing the objectives and the scores
+const SCOREBOARD_FILE: &str = "save/scoreboard.ron";
+/// Maximum number of scores shown in the sidebar
+pub const MAX_SIDEBAR_SCORES: usize = 15;
+/// Maximum length of the name of an objective, in characters
+const MAX_OBJECTIVE_NAME_LENGTH: usize = 16;
+
+/// What the scores of an objective count
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Criterion {
+    /// Only set by the commands
+    Custom,
+    BlocksBroken,
+    BlocksPlaced,
+    Deaths,
+}
+
+impl Criterion {
+    /// The score of a player for this criterion, if it follows the statistics
+    fn score_from_stats(self, stats: &PlayerStats) -> Option<i64> {
+        match self {
+            Self::Custom => None,
+            Self::BlocksBroken => Some(stats.blocks_broken.values().sum::<u64>() as i64),
+            Self::BlocksPlaced => Some(stats.blocks_placed.values().sum::<u64>() as i64),
+            Self::Deaths => Some(stats.deaths as i64),
+        }
+    }
+}
+
+impl std::str::FromStr for Criterion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "custom" => Ok(Self::Custom),
+            "blocks_broken" => Ok(Self::BlocksBroken),
+            "blocks_placed" => Ok(Self::BlocksPlaced),
+            "deaths" => Ok(Self::Deaths),
+            _ => Err(format!(
+                "Unknown criterion {}, it must be custom, blocks_broken, blocks_placed or deaths",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Criterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Custom => "custom",
+            Self::BlocksBroken => "blocks_broken",
+            Self::BlocksPlaced => "blocks_placed",
+            Self::Deaths => "deaths",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where an objective is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplaySlot {
+    Sidebar,
+    BelowName,
+}
+
+impl std::str::FromStr for DisplaySlot {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sidebar" => Ok(Self::Sidebar),
+            "below_name" => Ok(Self::BelowName),
+            _ => Err(format!(
+                "Unknown display slot {}, it must be sidebar or below_name",
+                s
+            )),
+        }
+    }
+}
+
+/// A `/scoreboard` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScoreboardCommand {
+    /// Create an objective with a name, a criterion and a title
+    AddObjective(String, Criterion, String),
+    RemoveObjective(String),
+    ListObjectives,
+    /// Show an objective in a slot, or clear the slot if there is no objective
+    Display(DisplaySlot, Option<String>),
+    /// Set the score of a player, given by name, for an objective
+    SetScore(String, String, i64),
+    /// Add to the score of a player, given by name, for an objective
+    AddScore(String, String, i64),
+    /// Remove the score of a player, given by name, from an objective
+    ResetScore(String, String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Objective {
+    title: String,
+    criterion: Criterion,
+    /// The score of every player, by player name
+    scores: BTreeMap<String, i64>,
+}
+
+impl Objective {
+    fn displayed(&self, max_scores: usize) -> DisplayedObjective {
+        let mut scores = self
+            .scores
+            .iter()
+            .map(|(name, &score)| (name.clone(), score))
+            .collect::<Vec<_>>();
+        // The order of the names is kept for the equal scores, the sort is stable
+        scores.sort_by(|(_, a), (_, b)| b.cmp(a));
+        scores.truncate(max_scores);
+        DisplayedObjective {
+            title: self.title.clone(),
+            scores,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Scoreboard {
+    /// The objectives, by name
+    objectives: BTreeMap<String, Objective>,
+    /// Names of the displayed objectives
+    sidebar: Option<String>,
+    below_name: Option<String>,
+    /// Whether the displayed objectives changed since they were last sent to the players
+    #[serde(skip)]
+    display_changed: bool,
+}
+
+impl Scoreboard {
+    /// Load the scoreboard that was saved, logging the errors
+    pub fn load() -> Self {
+        if !std::path::Path::new(SCOREBOARD_FILE).is_file() {
+            return Self::default();
+        }
+        let result = std::fs::read_to_string(SCOREBOARD_FILE)
+            .context(format!(
+                "Failed to read scoreboard file {}",
+                SCOREBOARD_FILE
+            ))
+            .and_then(|string| {
+                ron::de::from_str::<Scoreboard>(&string).context(format!(
+                    "Failed to parse scoreboard file {}",
+                    SCOREBOARD_FILE
+                ))
+            });
+        match result {
+            Ok(scoreboard) => scoreboard,
+            Err(e) => {
+                warn!("{:?}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save the scoreboard
+    pub fn save(&self) -> Result<()> {
+        info!("Saving scoreboard");
+        std::fs::create_dir_all("save").context("Failed to create folder save")?;
+        let string = ron::ser::to_string_pretty(self, Default::default())
+            .context("Failed to serialize scoreboard")?;
+        std::fs::write(SCOREBOARD_FILE, string).context(format!(
+            "Failed to write scoreboard file {}",
+            SCOREBOARD_FILE
+        ))
+    }
+
+    /// Run a command, and return the message for the player
+    pub fn run(&mut self, command: ScoreboardCommand) -> String {
+        match command {
+            ScoreboardCommand::AddObjective(name, criterion, title) => {
+                if name.chars().count() > MAX_OBJECTIVE_NAME_LENGTH {
+                    return format!(
+                        "The name of an objective can't be longer than {} characters",
+                        MAX_OBJECTIVE_NAME_LENGTH
+                    );
+                }
+                if self.objectives.contains_key(&name) {
+                    return format!("There is already an objective named {}", name);
+                }
+                self.objectives.insert(
+                    name.clone(),
+                    Objective {
+                        title,
+                        criterion,
+                        scores: BTreeMap::new(),
+                    },
+                );
+                format!("Added objective {}", name)
+            }
+            ScoreboardCommand::RemoveObjective(name) => {
+                if self.objectives.remove(&name).is_none() {
+                    return format!("No objective named {}", name);
+                }
+                for slot in vec![&mut self.sidebar, &mut self.below_name] {
+                    if slot.as_deref() == Some(name.as_str()) {
+                        *slot = None;
+                        self.display_changed = true;
+                    }
+                }
+                format!("Removed objective {}", name)
+            }
+            ScoreboardCommand::ListObjectives => {
+                if self.objectives.is_empty() {
+                    return "No objectives".to_owned();
+                }
+                self.objectives
+                    .iter()
+                    .map(|(name, objective)| {
+                        format!("{} ({}, {})", name, objective.title, objective.criterion)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+            ScoreboardCommand::Display(slot, name) => {
+                if let Some(name) = &name {
+                    if !self.objectives.contains_key(name) {
+                        return format!("No objective named {}", name);
+                    }
+                }
+                let message = match &name {
+                    Some(name) => format!("Showing objective {}", name),
+                    None => "Display slot cleared".to_owned(),
+                };
+                match slot {
+                    DisplaySlot::Sidebar => self.sidebar = name,
+                    DisplaySlot::BelowName => self.below_name = name,
+                }
+                self.display_changed = true;
+                message
+            }
+            ScoreboardCommand::SetScore(objective, player, score) => {
+                self.change_score(&objective, &player, |_| score)
+            }
+            ScoreboardCommand::AddScore(objective, player, amount) => {
+                self.change_score(&objective, &player, |score| score.saturating_add(amount))
+            }
+            ScoreboardCommand::ResetScore(objective, player) => {
+                let removed = match self.objectives.get_mut(&objective) {
+                    Some(o) => o.scores.remove(&player).is_some(),
+                    None => return format!("No objective named {}", objective),
+                };
+                if !removed {
+                    return format!("{} has no score for {}", player, objective);
+                }
+                self.mark_changed(&objective);
+                format!("Reset the score of {} for {}", player, objective)
+            }
+        }
+    }
+
+    /// Change the score of a player for an objective, and return the message for the player
+    fn change_score(
+        &mut self,
+        objective: &str,
+        player: &str,
+        change: impl FnOnce(i64) -> i64,
+    ) -> String {
+        let score = match self.objectives.get_mut(objective) {
+            Some(o) if o.criterion != Criterion::Custom => {
+                return format!(
+                    "The scores of {} follow the {} statistic, they can't be changed",
+                    objective, o.criterion
+                );
+            }
+            Some(o) => {
+                let score = o.scores.entry(player.to_owned()).or_insert(0);
+                *score = change(*score);
+                *score
+            }
+            None => return format!("No objective named {}", objective),
+        };
+        self.mark_changed(objective);
+        format!("Set the score of {} for {} to {}", player, objective, score)
+    }
+
+    /// Update the scores of a player for the objectives that follow the statistics
+    pub fn update_stats(&mut self, player: &str, stats: &PlayerStats) {
+        let mut changed = Vec::new();
+        for (name, objective) in self.objectives.iter_mut() {
+            if let Some(score) = objective.criterion.score_from_stats(stats) {
+                if objective.scores.get(player) != Some(&score) {
+                    objective.scores.insert(player.to_owned(), score);
+                    changed.push(name.clone());
+                }
+            }
+        }
+        for name in changed {
+            self.mark_changed(&name);
+        }
+    }
+
+    fn mark_changed(&mut self, objective: &str) {
+        if self.sidebar.as_deref() == Some(objective)
+            || self.below_name.as_deref() == Some(objective)
+        {
+            self.display_changed = true;
+        }
+    }
+
+    /// Return true if the displayed objectives changed since the last call
+    pub fn take_display_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.display_changed, false)
+    }
+
+    /// The displayed objectives, to send to the players
+    pub fn display(&self) -> ScoreboardDisplay {
+        let displayed = |name: &Option<String>, max_scores| {
+            name.as_ref()
+                .and_then(|name| self.objectives.get(name))
+                .map(|objective| objective.displayed(max_scores))
+        };
+        ScoreboardDisplay {
+            sidebar: displayed(&self.sidebar, MAX_SIDEBAR_SCORES),
+            below_name: displayed(&self.below_name, usize::MAX),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scoreboard() {
+        let mut scoreboard = Scoreboard::default();
+        scoreboard.run(ScoreboardCommand::AddObjective(
+            "points".to_owned(),
+            Criterion::Custom,
+            "Points".to_owned(),
+        ));
+        scoreboard.run(ScoreboardCommand::AddObjective(
+            "mined".to_owned(),
+            Criterion::BlocksBroken,
+            "Blocks mined".to_owned(),
+        ));
+        scoreboard.run(ScoreboardCommand::Display(
+            DisplaySlot::Sidebar,
+            Some("points".to_owned()),
+        ));
+        assert!(scoreboard.take_display_changed());
+
+        scoreboard.run(ScoreboardCommand::SetScore(
+            "points".to_owned(),
+            "Alice".to_owned(),
+            3,
+        ));
+        scoreboard.run(ScoreboardCommand::AddScore(
+            "points".to_owned(),
+            "Bob".to_owned(),
+            5,
+        ));
+        assert!(scoreboard.take_display_changed());
+        assert_eq!(
+            scoreboard.display().sidebar.unwrap().scores,
+            vec![("Bob".to_owned(), 5), ("Alice".to_owned(), 3)]
+        );
+
+        // The statistics only change the objectives that follow them, the hidden ones don't need a new display
+        let mut stats = PlayerStats::default();
+        stats.add_block_broken("stone");
+        stats.add_block_broken("dirt");
+        scoreboard.update_stats("Alice", &stats);
+        assert!(!scoreboard.take_display_changed());
+        scoreboard.run(ScoreboardCommand::Display(
+            DisplaySlot::BelowName,
+            Some("mined".to_owned()),
+        ));
+        let below_name = scoreboard.display().below_name.unwrap();
+        assert_eq!(below_name.score("Alice"), Some(2));
+        assert_eq!(below_name.score("Bob"), None);
+        assert!(scoreboard
+            .run(ScoreboardCommand::SetScore(
+                "mined".to_owned(),
+                "Bob".to_owned(),
+                1
+            ))
+            .contains("can't be changed"));
+
+        // Removing a displayed objective clears its slot
+        scoreboard.take_display_changed();
+        scoreboard.run(ScoreboardCommand::RemoveObjective("points".to_owned()));
+        assert!(scoreboard.take_display_changed());
+        assert_eq!(scoreboard.display().sidebar, None);
+    }
+}
@@ -0,0 +1,310 @@
+//! The teams of the players, given by name. A team colors the names of its members and decides whether they
+//! can hurt each other.
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use voxel_rs_common::player::TeamTag;
+
+/// File containing the teams and their members
+const TEAMS_FILE: &str = "save/teams.ron";
+/// Maximum length of the name of a team, in characters
+const MAX_TEAM_NAME_LENGTH: usize = 16;
+
+/// The color of the names of the members of a team
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TeamColor {
+    White,
+    Gray,
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Aqua,
+    Blue,
+    Purple,
+    Pink,
+}
+
+impl TeamColor {
+    const ALL: [TeamColor; 10] = [
+        Self::White,
+        Self::Gray,
+        Self::Red,
+        Self::Orange,
+        Self::Yellow,
+        Self::Green,
+        Self::Aqua,
+        Self::Blue,
+        Self::Purple,
+        Self::Pink,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::White => "white",
+            Self::Gray => "gray",
+            Self::Red => "red",
+            Self::Orange => "orange",
+            Self::Yellow => "yellow",
+            Self::Green => "green",
+            Self::Aqua => "aqua",
+            Self::Blue => "blue",
+            Self::Purple => "purple",
+            Self::Pink => "pink",
+        }
+    }
+
+    pub fn rgba(self) -> [f32; 4] {
+        match self {
+            Self::White => [1.0, 1.0, 1.0, 1.0],
+            Self::Gray => [0.6, 0.6, 0.6, 1.0],
+            Self::Red => [1.0, 0.33, 0.33, 1.0],
+            Self::Orange => [1.0, 0.65, 0.2, 1.0],
+            Self::Yellow => [1.0, 1.0, 0.33, 1.0],
+            Self::Green => [0.33, 1.0, 0.33, 1.0],
+            Self::Aqua => [0.33, 1.0, 1.0, 1.0],
+            Self::Blue => [0.4, 0.5, 1.0, 1.0],
+            Self::Purple => [0.7, 0.4, 1.0, 1.0],
+            Self::Pink => [1.0, 0.5, 0.8, 1.0],
+        }
+    }
+}
+
+impl std::str::FromStr for TeamColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|color| color.name() == s)
+            .cloned()
+            .ok_or_else(|| {
+                let names = Self::ALL
+                    .iter()
+                    .map(|color| color.name())
+                    .collect::<Vec<_>>();
+                format!("Unknown color {}, it must be {}", s, names.join(", "))
+            })
+    }
+}
+
+impl std::fmt::Display for TeamColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A `/team` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum TeamCommand {
+    /// Create a team with a name and a color
+    Add(String, TeamColor),
+    Remove(String),
+    List,
+    /// Put a player, given by name, in a team, removing them from their previous team
+    Join(String, String),
+    /// Remove a player, given by name, from their team
+    Leave(String),
+    /// Change the color of a team
+    Color(String, TeamColor),
+    /// Allow or prevent the members of a team from hurting each other
+    FriendlyFire(String, bool),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Team {
+    color: TeamColor,
+    friendly_fire: bool,
+    /// Names of the players in the team
+    members: BTreeSet<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Teams {
+    /// The teams, by name
+    teams: BTreeMap<String, Team>,
+}
+
+impl Teams {
+    /// Load the teams that were saved, logging the errors
+    pub fn load() -> Self {
+        if !std::path::Path::new(TEAMS_FILE).is_file() {
+            return Self::default();
+        }
+        let result = std::fs::read_to_string(TEAMS_FILE)
+            .context(format!("Failed to read teams file {}", TEAMS_FILE))
+            .and_then(|string| {
+                ron::de::from_str::<Teams>(&string)
+                    .context(format!("Failed to parse teams file {}", TEAMS_FILE))
+            });
+        match result {
+            Ok(teams) => teams,
+            Err(e) => {
+                warn!("{:?}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save the teams
+    pub fn save(&self) -> Result<()> {
+        info!("Saving teams");
+        std::fs::create_dir_all("save").context("Failed to create folder save")?;
+        let string = ron::ser::to_string_pretty(self, Default::default())
+            .context("Failed to serialize teams")?;
+        std::fs::write(TEAMS_FILE, string)
+            .context(format!("Failed to write teams file {}", TEAMS_FILE))
+    }
+
+    /// Run a command, and return the message for the player
+    pub fn run(&mut self, command: TeamCommand) -> String {
+        match command {
+            TeamCommand::Add(name, color) => {
+                if name.chars().count() > MAX_TEAM_NAME_LENGTH {
+                    return format!(
+                        "The name of a team can't be longer than {} characters",
+                        MAX_TEAM_NAME_LENGTH
+                    );
+                }
+                if self.teams.contains_key(&name) {
+                    return format!("There is already a team named {}", name);
+                }
+                self.teams.insert(
+                    name.clone(),
+                    Team {
+                        color,
+                        friendly_fire: true,
+                        members: BTreeSet::new(),
+                    },
+                );
+                format!("Added team {}", name)
+            }
+            TeamCommand::Remove(name) => match self.teams.remove(&name) {
+                Some(_) => format!("Removed team {}", name),
+                None => format!("No team named {}", name),
+            },
+            TeamCommand::List => {
+                if self.teams.is_empty() {
+                    return "No teams".to_owned();
+                }
+                self.teams
+                    .iter()
+                    .map(|(name, team)| {
+                        format!("{} ({}, {} players)", name, team.color, team.members.len())
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+            TeamCommand::Join(name, player) => {
+                if !self.teams.contains_key(&name) {
+                    return format!("No team named {}", name);
+                }
+                self.leave(&player);
+                self.teams
+                    .get_mut(&name)
+                    .unwrap()
+                    .members
+                    .insert(player.clone());
+                format!("{} joined team {}", player, name)
+            }
+            TeamCommand::Leave(player) => match self.leave(&player) {
+                Some(name) => format!("{} left team {}", player, name),
+                None => format!("{} is not in a team", player),
+            },
+            TeamCommand::Color(name, color) => match self.teams.get_mut(&name) {
+                Some(team) => {
+                    team.color = color;
+                    format!("The color of team {} is now {}", name, color)
+                }
+                None => format!("No team named {}", name),
+            },
+            TeamCommand::FriendlyFire(name, friendly_fire) => match self.teams.get_mut(&name) {
+                Some(team) => {
+                    team.friendly_fire = friendly_fire;
+                    let state = if friendly_fire { "enabled" } else { "disabled" };
+                    format!("Friendly fire {} for team {}", state, name)
+                }
+                None => format!("No team named {}", name),
+            },
+        }
+    }
+
+    /// Remove a player from their team, and return the name of the team
+    fn leave(&mut self, player: &str) -> Option<String> {
+        let (name, team) = self
+            .teams
+            .iter_mut()
+            .find(|(_, team)| team.members.contains(player))?;
+        team.members.remove(player);
+        Some(name.clone())
+    }
+
+    fn team_of(&self, player: &str) -> Option<(&String, &Team)> {
+        self.teams
+            .iter()
+            .find(|(_, team)| team.members.contains(player))
+    }
+
+    /// The team of a player, as shown to the other players
+    pub fn tag(&self, player: &str) -> Option<TeamTag> {
+        self.team_of(player).map(|(name, team)| TeamTag {
+            name: name.clone(),
+            color: team.color.rgba(),
+        })
+    }
+
+    /// Whether the player named `attacker` can hurt the player named `target`: the members of a team can't hurt
+    /// each other unless friendly fire is enabled for it.
+    pub fn can_hurt(&self, attacker: &str, target: &str) -> bool {
+        match (self.team_of(attacker), self.team_of(target)) {
+            (Some((attacker_team, team)), Some((target_team, _)))
+                if attacker_team == target_team =>
+            {
+                team.friendly_fire
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_teams() {
+        let mut teams = Teams::default();
+        teams.run(TeamCommand::Add("red".to_owned(), TeamColor::Red));
+        teams.run(TeamCommand::Add("blue".to_owned(), TeamColor::Blue));
+        teams.run(TeamCommand::Join("red".to_owned(), "Alice".to_owned()));
+        teams.run(TeamCommand::Join("red".to_owned(), "Bob".to_owned()));
+        teams.run(TeamCommand::Join("blue".to_owned(), "Carol".to_owned()));
+        assert_eq!(
+            teams.tag("Alice"),
+            Some(TeamTag {
+                name: "red".to_owned(),
+                color: TeamColor::Red.rgba(),
+            })
+        );
+        assert_eq!(teams.tag("Dave"), None);
+        assert!(teams.can_hurt("Alice", "Bob"));
+        assert!(teams.can_hurt("Alice", "Carol"));
+
+        teams.run(TeamCommand::FriendlyFire("red".to_owned(), false));
+        assert!(!teams.can_hurt("Alice", "Bob"));
+        assert!(teams.can_hurt("Alice", "Carol"));
+        assert!(teams.can_hurt("Alice", "Dave"));
+
+        // Joining another team leaves the previous one
+        teams.run(TeamCommand::Join("blue".to_owned(), "Bob".to_owned()));
+        assert!(teams.can_hurt("Alice", "Bob"));
+        assert_eq!(teams.tag("Bob").unwrap().name, "blue");
+        assert!(teams
+            .run(TeamCommand::Join("green".to_owned(), "Bob".to_owned()))
+            .contains("No team"));
+        assert_eq!("aqua".parse(), Ok(TeamColor::Aqua));
+        assert!("black".parse::<TeamColor>().is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! Chunk loading tickets, that decide which chunks stay loaded.
+//! A chunk is loaded as long as at least one ticket covers it, and unloaded as soon as no ticket covers it.
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use voxel_rs_common::player::{PlayerId, RenderDistance};
+use voxel_rs_common::world::{BlockPos, ChunkPos};
+
+/// File containing the forceloaded regions of the world
+const FORCELOAD_FILE: &str = "save/forceload.ron";
+/// Distance in chunks around the spawn chunk that is always loaded
+const SPAWN_RADIUS: i64 = 2;
+/// Maximum number of chunks that a single forceload ticket can keep loaded
+pub const MAX_FORCELOAD_CHUNKS: u64 = 256;
+
+/// The reason why a ticket keeps chunks loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TicketKind {
+    /// The render distance of a player
+    Player(PlayerId),
+    /// The area around the spawn point
+    Spawn,
+    /// A forceloaded region, created with the `/forceload` command
+    Forceload(u32),
+}
+
+/// A cuboid of chunks, inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRegion {
+    pub min: (i64, i64, i64),
+    pub max: (i64, i64, i64),
+}
+
+impl ChunkRegion {
+    /// The chunks within the render distance of a player in the chunk `player_chunk`
+    pub fn around_player(player_chunk: ChunkPos, render_distance: RenderDistance) -> Self {
+        Self {
+            min: (
+                player_chunk.px - render_distance.x_min as i64,
+                player_chunk.py - render_distance.y_min as i64,
+                player_chunk.pz - render_distance.z_min as i64,
+            ),
+            max: (
+                player_chunk.px + render_distance.x_max as i64,
+                player_chunk.py + render_distance.y_max as i64,
+                player_chunk.pz + render_distance.z_max as i64,
+            ),
+        }
+    }
+
+    /// The chunks containing the cuboid of blocks between `first` and `second`
+    pub fn containing_blocks(first: BlockPos, second: BlockPos) -> Self {
+        let (first, second) = (first.containing_chunk_pos(), second.containing_chunk_pos());
+        Self {
+            min: (
+                first.px.min(second.px),
+                first.py.min(second.py),
+                first.pz.min(second.pz),
+            ),
+            max: (
+                first.px.max(second.px),
+                first.py.max(second.py),
+                first.pz.max(second.pz),
+            ),
+        }
+    }
+
+    pub fn contains(&self, pos: ChunkPos) -> bool {
+        self.min.0 <= pos.px
+            && pos.px <= self.max.0
+            && self.min.1 <= pos.py
+            && pos.py <= self.max.1
+            && self.min.2 <= pos.pz
+            && pos.pz <= self.max.2
+    }
+
+    /// Number of chunks in the region
+    pub fn num_chunks(&self) -> u64 {
+        ((self.max.0 - self.min.0 + 1)
+            * (self.max.1 - self.min.1 + 1)
+            * (self.max.2 - self.min.2 + 1)) as u64
+    }
+
+    /// Iterate over the chunks of the region
+    pub fn chunks(self) -> impl Iterator<Item = ChunkPos> {
+        (self.min.0..=self.max.0).flat_map(move |px| {
+            (self.min.1..=self.max.1).flat_map(move |py| {
+                (self.min.2..=self.max.2).map(move |pz| ChunkPos::from((px, py, pz)))
+            })
+        })
+    }
+}
+
+/// The part of the tickets that is saved to disk
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ForceloadSave {
+    regions: Vec<ChunkRegion>,
+}
+
+/// All the chunk loading tickets of the world
+#[derive(Debug, Default)]
+pub struct ChunkTickets {
+    tickets: HashMap<TicketKind, ChunkRegion>,
+    next_forceload_id: u32,
+}
+
+impl ChunkTickets {
+    /// Create the tickets with a spawn ticket around `spawn_chunk`
+    pub fn new(spawn_chunk: ChunkPos) -> Self {
+        let mut tickets = Self::default();
+        tickets.set_ticket(
+            TicketKind::Spawn,
+            ChunkRegion {
+                min: (
+                    spawn_chunk.px - SPAWN_RADIUS,
+                    spawn_chunk.py - SPAWN_RADIUS,
+                    spawn_chunk.pz - SPAWN_RADIUS,
+                ),
+                max: (
+                    spawn_chunk.px + SPAWN_RADIUS,
+                    spawn_chunk.py + SPAWN_RADIUS,
+                    spawn_chunk.pz + SPAWN_RADIUS,
+                ),
+            },
+        );
+        tickets
+    }
+
+    /// Add a ticket, or move it if there is already a ticket of the same kind
+    pub fn set_ticket(&mut self, kind: TicketKind, region: ChunkRegion) {
+        self.tickets.insert(kind, region);
+    }
+
+    pub fn remove_ticket(&mut self, kind: TicketKind) {
+        self.tickets.remove(&kind);
+    }
+
+    /// Return true if some ticket keeps the chunk `pos` loaded
+    pub fn is_chunk_ticketed(&self, pos: ChunkPos) -> bool {
+        self.tickets.values().any(|region| region.contains(pos))
+    }
+
+    /// Get the chunks that are kept loaded by the tickets that don't belong to players,
+    /// so that they are generated even when no player is close
+    pub fn non_player_chunks(&self) -> Vec<ChunkPos> {
+        let mut chunks = self
+            .tickets
+            .iter()
+            .filter(|(kind, _)| match kind {
+                TicketKind::Player(_) => false,
+                _ => true,
+            })
+            .flat_map(|(_, region)| region.chunks())
+            .collect::<Vec<_>>();
+        chunks.sort_by_key(|pos| (pos.px, pos.py, pos.pz));
+        chunks.dedup();
+        chunks
+    }
+
+    /// Add a forceload ticket, and return its id
+    pub fn add_forceload(&mut self, region: ChunkRegion) -> u32 {
+        let id = self.next_forceload_id;
+        self.next_forceload_id += 1;
+        self.set_ticket(TicketKind::Forceload(id), region);
+        id
+    }
+
+    /// Remove a forceload ticket, return false if there is no forceload ticket with this id
+    pub fn remove_forceload(&mut self, id: u32) -> bool {
+        self.tickets.remove(&TicketKind::Forceload(id)).is_some()
+    }
+
+    /// Get the forceload tickets, sorted by id
+    pub fn forceloads(&self) -> Vec<(u32, ChunkRegion)> {
+        let mut forceloads = self
+            .tickets
+            .iter()
+            .filter_map(|(kind, region)| match kind {
+                TicketKind::Forceload(id) => Some((*id, *region)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        forceloads.sort_by_key(|(id, _)| *id);
+        forceloads
+    }
+
+    /// Load the forceload tickets that were saved, logging the errors
+    pub fn load_forceloads(&mut self) {
+        if !std::path::Path::new(FORCELOAD_FILE).is_file() {
+            return;
+        }
+        let result = std::fs::read_to_string(FORCELOAD_FILE)
+            .context(format!("Failed to read forceload file {}", FORCELOAD_FILE))
+            .and_then(|string| {
+                ron::de::from_str::<ForceloadSave>(&string)
+                    .context(format!("Failed to parse forceload file {}", FORCELOAD_FILE))
+            });
+        match result {
+            Ok(save) => {
+                for region in save.regions {
+                    self.add_forceload(region);
+                }
+            }
+            Err(e) => warn!("{:?}", e),
+        }
+    }
+
+    /// Save the forceload tickets
+    pub fn save_forceloads(&self) -> Result<()> {
+        info!("Saving forceloaded regions");
+        let save = ForceloadSave {
+            regions: self
+                .forceloads()
+                .into_iter()
+                .map(|(_, region)| region)
+                .collect(),
+        };
+        std::fs::create_dir_all("save").context("Failed to create folder save")?;
+        let string = ron::ser::to_string_pretty(&save, Default::default())
+            .context("Failed to serialize forceloaded regions")?;
+        std::fs::write(FORCELOAD_FILE, string)
+            .context(format!("Failed to write forceload file {}", FORCELOAD_FILE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(min: (i64, i64, i64), max: (i64, i64, i64)) -> ChunkRegion {
+        ChunkRegion { min, max }
+    }
+
+    #[test]
+    fn test_chunk_lifetime() {
+        let mut tickets = ChunkTickets::new(ChunkPos::from((0, 0, 0)));
+        let far_chunk = ChunkPos::from((100, 0, 0));
+        assert!(tickets.is_chunk_ticketed(ChunkPos::from((SPAWN_RADIUS, 0, -SPAWN_RADIUS))));
+        assert!(!tickets.is_chunk_ticketed(far_chunk));
+
+        // Setting a ticket again moves it
+        let render_distance = RenderDistance::default();
+        tickets.set_ticket(
+            TicketKind::Spawn,
+            ChunkRegion::around_player(far_chunk, render_distance),
+        );
+        assert!(tickets.is_chunk_ticketed(far_chunk));
+        assert!(!tickets.is_chunk_ticketed(ChunkPos::from((0, 0, 0))));
+        tickets.remove_ticket(TicketKind::Spawn);
+        assert!(!tickets.is_chunk_ticketed(far_chunk));
+
+        // Forceloaded chunks stay loaded until the forceload is removed
+        let id = tickets.add_forceload(region((100, 0, 0), (101, 0, 0)));
+        assert!(tickets.is_chunk_ticketed(far_chunk));
+        assert_eq!(
+            tickets.forceloads(),
+            vec![(id, region((100, 0, 0), (101, 0, 0)))]
+        );
+        assert!(tickets.remove_forceload(id));
+        assert!(!tickets.remove_forceload(id));
+        assert!(!tickets.is_chunk_ticketed(far_chunk));
+    }
+
+    #[test]
+    fn test_non_player_chunks() {
+        let mut tickets = ChunkTickets::default();
+        tickets.add_forceload(region((0, 0, 0), (1, 0, 0)));
+        tickets.add_forceload(region((1, 0, 0), (2, 0, 0)));
+        assert_eq!(
+            tickets.non_player_chunks(),
+            vec![
+                ChunkPos::from((0, 0, 0)),
+                ChunkPos::from((1, 0, 0)),
+                ChunkPos::from((2, 0, 0)),
+            ]
+        );
+        assert_eq!(region((0, 0, 0), (1, 2, 3)).num_chunks(), 24);
+    }
+}
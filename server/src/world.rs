@@ -1,6 +1,9 @@
 use crate::{
     light::worker::{start_lighting_worker, ChunkLightingData, ChunkLightingWorker},
     light::HighestOpaqueBlock,
+    save::save_chunk,
+    scheduler::{BlockScheduler, BlockTask, PendingTask, Tick},
+    tickets::ChunkTickets,
     worldgen::{start_worldgen_worker, WorldGenerationWorker},
 };
 use lazy_static::lazy_static;
@@ -9,11 +12,14 @@ use std::{
     sync::Arc,
 };
 use voxel_rs_common::{
-    block::{Block, BlockId},
+    block::{
+        block_shapes, entity::BlockEntity, rail::RailShape, Block, BlockId, BlockShape,
+        HorizontalFace,
+    },
     physics::BlockContainer,
-    player::RenderDistance,
     registry::Registry,
-    world::{BlockPos, Chunk, ChunkPos, ChunkPosXZ, LightChunk, WorldGenerator},
+    world::{BlockPos, Chunk, ChunkPos, ChunkPosXZ, LightChunk, WorldGenerator, CHUNK_SIZE},
+    world_view::{ChunkSource, ChunkSourceMut, WorldViewMut},
 };
 
 lazy_static! {
@@ -30,14 +36,26 @@ pub struct World {
     chunks: HashMap<ChunkPos, ServerChunk>,
     /// The chunk columns
     chunk_columns: HashMap<ChunkPosXZ, ServerChunkColumn>,
+    /// The block entities of the loaded chunks
+    block_entities: HashMap<BlockPos, BlockEntity>,
+    /// The pending block tasks of the loaded chunks
+    block_tasks: BlockScheduler,
     /// The next chunk version. When the chunk version changes, we know we must send the updated chunk to the clients.
     next_chunk_version: u64,
     /// The chunks in the worldgen queue
     worldgen_queue: HashSet<ChunkPos>,
+    /// The chunks in the worldgen queue for `/pregen`, which are saved once generated
+    pregen_chunks: HashSet<ChunkPos>,
+    /// The chunks that the current autosave still has to save
+    save_queue: Vec<ChunkPos>,
     /// The worldgen worker
     worldgen_worker: WorldGenerationWorker,
     /// The light worker
     light_worker: ChunkLightingWorker,
+    /// The shape of every block, for the physics
+    block_shapes: Vec<BlockShape>,
+    /// The positions of the blocks that changed since the last call to `take_block_updates`
+    block_updates: Vec<BlockPos>,
 }
 
 impl World {
@@ -48,10 +66,16 @@ impl World {
         Self {
             chunks: HashMap::default(),
             chunk_columns: HashMap::default(),
+            block_entities: HashMap::default(),
+            block_tasks: BlockScheduler::new(),
             next_chunk_version: 0,
             worldgen_queue: HashSet::default(),
+            pregen_chunks: HashSet::default(),
+            save_queue: Vec::new(),
+            light_worker: start_lighting_worker(&block_registry),
+            block_shapes: block_shapes(&block_registry),
+            block_updates: Vec::new(),
             worldgen_worker: start_worldgen_worker(block_registry, world_generator),
-            light_worker: start_lighting_worker(),
         }
     }
 
@@ -72,6 +96,159 @@ impl World {
         }
     }
 
+    /// Get the light level at position `pos`, 0 if its chunk is not loaded
+    pub fn get_light(&self, pos: BlockPos) -> u8 {
+        match self.chunks.get(&pos.containing_chunk_pos()) {
+            None => 0,
+            Some(server_chunk) => server_chunk
+                .light_chunk
+                .get_light_at(pos.pos_in_containing_chunk()),
+        }
+    }
+
+    /// Replace many blocks at once, modifying each chunk only once. The blocks whose chunk is not loaded are skipped.
+    /// Block entities are not modified. Return the previous value of the blocks that changed.
+    pub fn set_blocks(&mut self, changes: &[(BlockPos, BlockId)]) -> Vec<(BlockPos, BlockId)> {
+        let mut view = WorldViewMut::new(self);
+        changes
+            .iter()
+            .filter_map(
+                |&(pos, block)| match view.set_block_with_update(pos, block) {
+                    Some(previous_block) if previous_block != block => Some((pos, previous_block)),
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+
+    /// Get the versions of the blocks and of the light of a loaded chunk
+    pub fn get_chunk_versions(&self, pos: ChunkPos) -> Option<ChunkVersions> {
+        self.chunks.get(&pos).map(|server_chunk| ChunkVersions {
+            blocks: server_chunk.version,
+            light: server_chunk.light_version,
+        })
+    }
+
+    /// Replace the block at position `pos`, if its chunk is loaded. Block entities are not modified.
+    pub fn set_block(&mut self, pos: BlockPos, block: BlockId) {
+        if let Some(chunk) = self.get_chunk(pos.containing_chunk_pos()) {
+            let mut new_chunk = (*chunk).clone();
+            new_chunk.set_block_at(pos.pos_in_containing_chunk(), block);
+            self.set_chunk(Arc::new(new_chunk));
+            self.block_updates.push(pos);
+        }
+    }
+
+    /// Take the positions of the blocks that changed since the last call, so that the blocks around them can react
+    pub fn take_block_updates(&mut self) -> Vec<BlockPos> {
+        std::mem::take(&mut self.block_updates)
+    }
+
+    /// Return the block entity at position `pos`, if there is one
+    pub fn get_block_entity(&self, pos: BlockPos) -> Option<&BlockEntity> {
+        self.block_entities.get(&pos)
+    }
+
+    /// Return the block entity at position `pos`, if there is one
+    pub fn get_block_entity_mut(&mut self, pos: BlockPos) -> Option<&mut BlockEntity> {
+        self.mark_dirty(pos.containing_chunk_pos());
+        self.block_entities.get_mut(&pos)
+    }
+
+    /// Set the block entity at position `pos`, replacing the previous one and cancelling its block tasks
+    pub fn set_block_entity(&mut self, pos: BlockPos, block_entity: BlockEntity) {
+        self.cancel_block_tasks(pos);
+        self.block_entities.insert(pos, block_entity);
+    }
+
+    /// Remove the block entity at position `pos`, returning it. Its block tasks are cancelled.
+    pub fn remove_block_entity(&mut self, pos: BlockPos) -> Option<BlockEntity> {
+        self.cancel_block_tasks(pos);
+        self.block_entities.remove(&pos)
+    }
+
+    /// Run the block task `task` of the block at position `pos` every `interval` ticks
+    pub fn schedule_repeating_block_task(
+        &mut self,
+        pos: BlockPos,
+        interval: Tick,
+        task: BlockTask,
+    ) {
+        self.mark_dirty(pos.containing_chunk_pos());
+        self.block_tasks.schedule_repeating(interval, (pos, task));
+    }
+
+    /// Cancel the block tasks of the block at position `pos`
+    pub fn cancel_block_tasks(&mut self, pos: BlockPos) {
+        self.mark_dirty(pos.containing_chunk_pos());
+        self.block_tasks.cancel(|&(task_pos, _)| task_pos == pos);
+    }
+
+    /// Cancel the pending `task` of the block at position `pos`
+    pub fn cancel_block_task(&mut self, pos: BlockPos, task: BlockTask) {
+        self.mark_dirty(pos.containing_chunk_pos());
+        self.block_tasks
+            .cancel(|&(task_pos, pending)| task_pos == pos && pending == task);
+    }
+
+    /// Check if the block at position `pos` has a pending `task`
+    pub fn has_block_task(&self, pos: BlockPos, task: BlockTask) -> bool {
+        self.block_tasks
+            .pending(|&(task_pos, pending)| task_pos == pos && pending == task)
+            .next()
+            .is_some()
+    }
+
+    /// Advance the block tasks to the next tick, and return the tasks to run now in order
+    pub fn advance_block_tasks(&mut self) -> Vec<(BlockPos, BlockTask)> {
+        self.block_tasks.advance()
+    }
+
+    /// Number of pending block tasks in the loaded chunks
+    pub fn num_block_tasks(&self) -> usize {
+        self.block_tasks.len()
+    }
+
+    /// Iterate over the block entities of the chunk at position `pos`
+    pub fn block_entities_in_chunk(
+        &self,
+        pos: ChunkPos,
+    ) -> impl Iterator<Item = (BlockPos, &BlockEntity)> {
+        self.block_entities
+            .iter()
+            .filter(move |(block_pos, _)| block_pos.containing_chunk_pos() == pos)
+            .map(|(block_pos, block_entity)| (*block_pos, block_entity))
+    }
+
+    /// Iterate over the positions of the block entities of the loaded chunks
+    pub fn block_entity_positions(&self) -> impl Iterator<Item = BlockPos> + '_ {
+        self.block_entities.keys().copied()
+    }
+
+    /// Iterate over the block entities at `positions`. Their chunks are considered modified.
+    pub fn block_entities_mut_at<'a>(
+        &'a mut self,
+        positions: &'a HashSet<BlockPos>,
+    ) -> impl Iterator<Item = (BlockPos, &'a mut BlockEntity)> {
+        let chunks = &mut self.chunks;
+        self.block_entities
+            .iter_mut()
+            .filter(move |(pos, _)| positions.contains(pos))
+            .map(move |(pos, block_entity)| {
+                if let Some(server_chunk) = chunks.get_mut(&pos.containing_chunk_pos()) {
+                    server_chunk.dirty = true;
+                }
+                (*pos, block_entity)
+            })
+    }
+
+    /// Remember that a chunk must be saved
+    fn mark_dirty(&mut self, pos: ChunkPos) {
+        if let Some(server_chunk) = self.chunks.get_mut(&pos) {
+            server_chunk.dirty = true;
+        }
+    }
+
     /// Update the highest opaque block in the column, and mark relevant chunks for a light update.
     /// To be called after every chunk loading or modification.
     fn update_chunk_column(&mut self, pos: ChunkPos) {
@@ -83,13 +260,18 @@ impl World {
         column.highest_opaque_blocks.insert(pos.py, hob);
 
         // TODO: don't update entire column, try to be more clever
+        self.update_column_hob(column_pos);
+    }
 
-        // Update column HOB
-        let mut column_hob = HighestOpaqueBlock::new();
-        for (_, chunk_hob) in column.highest_opaque_blocks.iter() {
-            column_hob.merge(chunk_hob);
+    /// Merge the highest opaque blocks of the chunks of a column, and mark the neighbouring columns for a light update
+    fn update_column_hob(&mut self, column_pos: ChunkPosXZ) {
+        if let Some(column) = self.chunk_columns.get_mut(&column_pos) {
+            let mut column_hob = HighestOpaqueBlock::new();
+            for (_, chunk_hob) in column.highest_opaque_blocks.iter() {
+                column_hob.merge(chunk_hob);
+            }
+            column.highest_opaque_block = Arc::new(column_hob);
         }
-        column.highest_opaque_block = Arc::new(column_hob);
 
         for i in -1..=1 {
             for k in -1..=1 {
@@ -118,11 +300,14 @@ impl World {
             chunk: chunk.clone(),
             light_chunk: Arc::new(LightChunk::new(pos)),
             version: 0,
+            light_version: 0,
             is_in_light_queue: false,
             needs_light_update: true,
+            dirty: true,
         });
         server_chunk.chunk = chunk;
         server_chunk.needs_light_update = true;
+        server_chunk.dirty = true;
         server_chunk.version = self.next_chunk_version;
         self.next_chunk_version += 1;
 
@@ -140,13 +325,43 @@ impl World {
         self.update_chunk_column(pos);
     }
 
-    /// Fetch the new chunk meshes from the worldgen worker
+    /// Fetch the new chunks from the worldgen worker, either loaded from the save or generated
     pub fn get_new_generated_chunks(&mut self) {
         // TODO: maybe don't update all the light column every time
         // TODO: if there are multiple chunks in the same column this may save time
-        while let Some(chunk) = self.worldgen_worker.get_result() {
-            self.worldgen_queue.remove(&chunk.pos);
-            self.set_chunk(Arc::new(chunk));
+        while let Some(generated) = self.worldgen_worker.get_result() {
+            let pos = generated.chunk.pos;
+            self.worldgen_queue.remove(&pos);
+            let pregenerated = self.pregen_chunks.remove(&pos);
+            self.set_chunk(Arc::new(generated.chunk));
+            // The command blocks and the furnaces saved before the block tasks existed start their runs again.
+            // The idle furnaces stop ticking after their first tick.
+            for (block_pos, block_entity) in &generated.block_entities {
+                let has_task = generated
+                    .block_tasks
+                    .iter()
+                    .any(|pending| pending.task.0 == *block_pos);
+                match block_entity {
+                    BlockEntity::CommandBlock(command_block)
+                        if command_block.is_active() && !has_task =>
+                    {
+                        self.block_tasks
+                            .schedule_in(1, (*block_pos, BlockTask::StartCommandBlock));
+                    }
+                    BlockEntity::Furnace(_) if !has_task => {
+                        self.block_tasks
+                            .schedule_repeating(1, (*block_pos, BlockTask::TickFurnace));
+                    }
+                    _ => (),
+                }
+            }
+            self.block_entities.extend(generated.block_entities);
+            for pending in generated.block_tasks {
+                self.block_tasks.restore(pending);
+            }
+            // Generated chunks can be generated again, so they are only saved once they are modified,
+            // unless they are pre-generated
+            self.chunks.get_mut(&pos).unwrap().dirty = pregenerated && !generated.from_save;
         }
     }
 
@@ -156,7 +371,7 @@ impl World {
             if let Some(mut server_chunk) = self.chunks.get_mut(&light_chunk.pos) {
                 server_chunk.light_chunk = light_chunk;
                 server_chunk.is_in_light_queue = false;
-                server_chunk.version = self.next_chunk_version;
+                server_chunk.light_version = self.next_chunk_version;
                 self.next_chunk_version += 1;
             }
         }
@@ -236,23 +451,46 @@ impl World {
         }
     }
 
-    /// Drop far chunks
-    pub fn drop_far_chunks(&mut self, player_positions: &[(ChunkPos, RenderDistance)]) {
+    /// Send a chunk to the worldgen worker for `/pregen`, to save it once it is generated.
+    /// Return false if the worldgen queue is full.
+    pub fn enqueue_chunk_for_pregen(&mut self, pos: ChunkPos) -> bool {
+        if let Some(server_chunk) = self.chunks.get_mut(&pos) {
+            // Already loaded, it will be saved with the next autosave or when it is unloaded
+            server_chunk.dirty = true;
+            return true;
+        }
+        if !self.worldgen_queue.contains(&pos) {
+            if self.worldgen_worker.enqueue(pos).is_err() {
+                return false;
+            }
+            self.worldgen_queue.insert(pos);
+        }
+        self.pregen_chunks.insert(pos);
+        true
+    }
+
+    pub fn is_in_worldgen_queue(&self, pos: ChunkPos) -> bool {
+        self.worldgen_queue.contains(&pos)
+    }
+
+    /// Drop the chunks that are not covered by any ticket
+    pub fn drop_unticketed_chunks(&mut self, tickets: &ChunkTickets) {
         let loaded_chunks = self.chunks.keys().cloned().collect::<Vec<_>>();
-        'chunks: for chunk_pos in loaded_chunks {
-            for (player_chunk, render_distance) in player_positions {
-                if render_distance.is_chunk_visible(*player_chunk, chunk_pos) {
-                    continue 'chunks;
-                }
+        for chunk_pos in loaded_chunks {
+            if !tickets.is_chunk_ticketed(chunk_pos) {
+                self.unload_chunk(chunk_pos);
             }
-            self.unload_chunk(chunk_pos);
         }
     }
 
-    /// Unload chunk
-    // TODO: persist to disk
+    /// Unload chunk, saving it first if it was modified
     fn unload_chunk(&mut self, pos: ChunkPos) {
+        self.save_chunk_if_dirty(pos);
         self.chunks.remove(&pos);
+        self.block_entities
+            .retain(|block_pos, _| block_pos.containing_chunk_pos() != pos);
+        self.block_tasks
+            .cancel(|(block_pos, _)| block_pos.containing_chunk_pos() == pos);
         let column_pos = ChunkPosXZ::from(pos);
         let col = self
             .chunk_columns
@@ -263,6 +501,9 @@ impl World {
         if col.loaded_chunks.len() == 0 {
             self.chunk_columns.remove(&column_pos);
         }
+        // The light pauses at unloaded chunks, so the neighbours are lit again without this chunk.
+        // This way the light only depends on the loaded chunks, and not on the order they were loaded in.
+        self.update_column_hob(column_pos);
     }
 
     /// Get chunks to send to a player this frame, and update the `PlayerData` accordingly. Start generating some chunks if necessary
@@ -270,21 +511,28 @@ impl World {
         &mut self,
         player_chunk: ChunkPos,
         data: &mut super::PlayerData,
-    ) -> Vec<(Arc<Chunk>, Arc<LightChunk>)> {
+    ) -> Vec<ChunkUpdate> {
         const MAX_CHUNKS: usize = 20;
         let mut updates = Vec::new();
         for pos in data.close_chunks.get_close_chunks() {
             let pos = pos.offset_by_pos(player_chunk);
             if let Some(server_chunk) = self.chunks.get(&pos) {
                 // Send the chunk to the player
-                let loaded = data.loaded_chunks.insert(pos, server_chunk.version);
-                if let Some(old_client_version) = loaded {
-                    if old_client_version < server_chunk.version {
-                        updates
-                            .push((server_chunk.chunk.clone(), server_chunk.light_chunk.clone()));
+                let versions = ChunkVersions {
+                    blocks: server_chunk.version,
+                    light: server_chunk.light_version,
+                };
+                match data.loaded_chunks.insert(pos, versions) {
+                    Some(old_versions) if old_versions.blocks >= versions.blocks => {
+                        // Only send the light if the client already has the blocks
+                        if old_versions.light < versions.light {
+                            updates.push(ChunkUpdate::Light(server_chunk.light_chunk.clone()));
+                        }
                     }
-                } else {
-                    updates.push((server_chunk.chunk.clone(), server_chunk.light_chunk.clone()));
+                    _ => updates.push(ChunkUpdate::Full(
+                        server_chunk.chunk.clone(),
+                        server_chunk.light_chunk.clone(),
+                    )),
                 }
                 if updates.len() == MAX_CHUNKS {
                     break;
@@ -300,11 +548,110 @@ impl World {
         updates
     }
 
+    /// Save a chunk if it was modified since it was last saved
+    fn save_chunk_if_dirty(&mut self, pos: ChunkPos) {
+        let server_chunk = match self.chunks.get_mut(&pos) {
+            Some(server_chunk) if server_chunk.dirty => server_chunk,
+            _ => return,
+        };
+        server_chunk.dirty = false;
+        let block_entities = self
+            .block_entities
+            .iter()
+            .filter(|(block_pos, _)| block_pos.containing_chunk_pos() == pos)
+            .map(|(block_pos, block_entity)| (*block_pos, block_entity.clone()))
+            .collect();
+        let block_tasks = self
+            .block_tasks
+            .pending(|(block_pos, _)| block_pos.containing_chunk_pos() == pos)
+            .map(|pending| PendingTask {
+                task: *pending.task,
+                delay: pending.delay,
+                interval: pending.interval,
+            })
+            .collect();
+        if let Err(e) = save_chunk(&server_chunk.chunk, block_entities, block_tasks) {
+            log::error!("Failed to save chunk {:?}: {:?}", pos, e);
+        }
+    }
+
+    /// Start saving the modified chunks, a few at a time with `save_queued_chunks`
+    pub fn start_autosave(&mut self) {
+        self.save_queue = self
+            .chunks
+            .iter()
+            .filter(|(_, server_chunk)| server_chunk.dirty)
+            .map(|(pos, _)| *pos)
+            .collect();
+    }
+
+    /// Save at most `max_chunks` chunks of the current autosave
+    pub fn save_queued_chunks(&mut self, max_chunks: usize) {
+        for _ in 0..max_chunks {
+            match self.save_queue.pop() {
+                Some(pos) => self.save_chunk_if_dirty(pos),
+                None => break,
+            }
+        }
+    }
+
+    /// Return true if the current autosave didn't save all its chunks yet
+    pub fn is_autosaving(&self) -> bool {
+        !self.save_queue.is_empty()
+    }
+
+    /// Save all the modified chunks now
+    pub fn save_all_chunks(&mut self) {
+        self.save_queue.clear();
+        let loaded_chunks = self.chunks.keys().cloned().collect::<Vec<_>>();
+        for pos in loaded_chunks {
+            self.save_chunk_if_dirty(pos);
+        }
+    }
+
+    /// Save a chunk again if it is loaded, for example because its file was damaged. Return false if it is not loaded.
+    pub fn resave_chunk(&mut self, pos: ChunkPos) -> bool {
+        if !self.chunks.contains_key(&pos) {
+            return false;
+        }
+        self.mark_dirty(pos);
+        self.save_chunk_if_dirty(pos);
+        true
+    }
+
+    /// Number of loaded chunks that were modified since they were last saved
+    pub fn num_dirty_chunks(&self) -> usize {
+        self.chunks
+            .values()
+            .filter(|server_chunk| server_chunk.dirty)
+            .count()
+    }
+
+    /// Positions of the loaded chunks
+    pub fn loaded_chunk_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
     /// Number of loaded chunks
     pub fn num_loaded_chunks(&self) -> usize {
         self.chunks.len()
     }
 
+    /// Get the height of the highest non-empty block of the loaded chunks in the column of `pos`,
+    /// if the column is loaded
+    pub fn highest_block(&self, pos: BlockPos) -> Option<i64> {
+        let column = self
+            .chunk_columns
+            .get(&ChunkPosXZ::from(pos.containing_chunk_pos()))?;
+        let (x, _, z) = pos.pos_in_containing_chunk();
+        Some(column.highest_opaque_block.y[(x * CHUNK_SIZE + z) as usize])
+    }
+
+    /// Check if some chunks of a column are loaded
+    pub fn is_column_loaded(&self, pos: ChunkPosXZ) -> bool {
+        self.chunk_columns.contains_key(&pos)
+    }
+
     /// Number of loaded chunk columns
     pub fn num_loaded_chunk_columns(&self) -> usize {
         self.chunk_columns.len()
@@ -313,26 +660,93 @@ impl World {
 
 impl BlockContainer for World {
     fn is_block_full(&self, pos: BlockPos) -> bool {
-        // TODO: use BlockRegistry
-        match self.chunks.get(&pos.containing_chunk_pos()) {
-            None => false,
-            Some(chunk) => chunk.chunk.get_block_at(pos.pos_in_containing_chunk()) != 0,
+        self.block_shapes[self.get_block(pos) as usize] == BlockShape::Full
+    }
+
+    fn get_ladder(&self, pos: BlockPos) -> Option<HorizontalFace> {
+        match self.block_shapes[self.get_block(pos) as usize] {
+            BlockShape::Ladder(attached_to) => Some(attached_to),
+            _ => None,
+        }
+    }
+
+    fn is_block_fluid(&self, pos: BlockPos) -> bool {
+        self.block_shapes[self.get_block(pos) as usize] == BlockShape::Fluid
+    }
+
+    fn get_layers(&self, pos: BlockPos) -> Option<u8> {
+        match self.block_shapes[self.get_block(pos) as usize] {
+            BlockShape::Layer(layers) => Some(layers),
+            _ => None,
+        }
+    }
+
+    fn is_block_plant(&self, pos: BlockPos) -> bool {
+        self.block_shapes[self.get_block(pos) as usize] == BlockShape::Plant
+    }
+
+    fn is_block_portal(&self, pos: BlockPos) -> bool {
+        self.block_shapes[self.get_block(pos) as usize] == BlockShape::Portal
+    }
+
+    fn get_rail(&self, pos: BlockPos) -> Option<RailShape> {
+        match self.block_shapes[self.get_block(pos) as usize] {
+            BlockShape::Rail(shape) => Some(shape),
+            _ => None,
         }
     }
 }
 
+impl ChunkSource for World {
+    fn get_loaded_chunk(&self, pos: ChunkPos) -> Option<(&Chunk, &LightChunk)> {
+        self.chunks
+            .get(&pos)
+            .map(|server_chunk| (&*server_chunk.chunk, &*server_chunk.light_chunk))
+    }
+
+    fn block_shapes(&self) -> &[BlockShape] {
+        &self.block_shapes
+    }
+}
+
+impl ChunkSourceMut for World {
+    fn replace_chunk(&mut self, chunk: Chunk, changed: &[BlockPos]) {
+        self.set_chunk(Arc::new(chunk));
+        self.block_updates.extend_from_slice(changed);
+    }
+}
+
+/// The versions of a chunk, to know what must be sent to the clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkVersions {
+    pub blocks: u64,
+    pub light: u64,
+}
+
+/// An update of a chunk that must be sent to a player
+pub enum ChunkUpdate {
+    /// The whole chunk, for the chunks that the player doesn't have yet
+    Full(Arc<Chunk>, Arc<LightChunk>),
+    /// Only the light, for the chunks whose blocks the player already has
+    Light(Arc<LightChunk>),
+}
+
 /// The data for each chunk stored by the server
 struct ServerChunk {
     /// The chunk itself
     pub chunk: Arc<Chunk>,
     /// The light chunk
     pub light_chunk: Arc<LightChunk>,
-    /// The current chunk version
+    /// The current chunk version, which changes every time the blocks of the chunk change
     pub version: u64,
+    /// The current light version, which changes every time the light of the chunk changes
+    pub light_version: u64,
     /// True if the chunk is in the light queue
     pub is_in_light_queue: bool,
     /// True if the chunk needs a light update, for example before it never had one or because it changed.
     pub needs_light_update: bool,
+    /// True if the chunk was modified since it was last saved
+    pub dirty: bool,
 }
 
 /// The data for each chunk column stored by the server
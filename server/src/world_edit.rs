@@ -0,0 +1,148 @@
+//! Bulk editing of the world with the world edit commands
+use crate::world::World;
+use std::collections::VecDeque;
+use voxel_rs_common::{block::BlockId, world::BlockPos};
+
+/// Maximum number of blocks that a single world edit command can modify
+pub const MAX_EDIT_VOLUME: u64 = 1 << 20;
+/// Number of edits that can be undone
+const UNDO_HISTORY_SIZE: usize = 16;
+
+/// A list of block changes, to apply or to undo
+pub type BlockChanges = Vec<(BlockPos, BlockId)>;
+
+/// The two corners of the cuboid selected with the selection tool
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Selection {
+    pub first: Option<BlockPos>,
+    pub second: Option<BlockPos>,
+}
+
+impl Selection {
+    /// Get the minimum and maximum corners of the selected cuboid, if both corners were selected
+    pub fn bounds(&self) -> Option<(BlockPos, BlockPos)> {
+        let (first, second) = (self.first?, self.second?);
+        Some((
+            BlockPos::from((
+                first.px.min(second.px),
+                first.py.min(second.py),
+                first.pz.min(second.pz),
+            )),
+            BlockPos::from((
+                first.px.max(second.px),
+                first.py.max(second.py),
+                first.pz.max(second.pz),
+            )),
+        ))
+    }
+}
+
+/// Number of blocks in the cuboid between `min` and `max`, inclusive
+pub fn volume(min: BlockPos, max: BlockPos) -> u64 {
+    ((max.px - min.px + 1) * (max.py - min.py + 1) * (max.pz - min.pz + 1)) as u64
+}
+
+/// Iterate over the positions of the cuboid between `min` and `max`, inclusive
+fn cuboid(min: BlockPos, max: BlockPos) -> impl Iterator<Item = BlockPos> {
+    (min.px..=max.px).flat_map(move |px| {
+        (min.py..=max.py)
+            .flat_map(move |py| (min.pz..=max.pz).map(move |pz| BlockPos::from((px, py, pz))))
+    })
+}
+
+/// Get the changes that replace the cuboid between `min` and `max` by `block`
+pub fn fill(min: BlockPos, max: BlockPos, block: BlockId) -> BlockChanges {
+    cuboid(min, max).map(|pos| (pos, block)).collect()
+}
+
+/// A copied cuboid of blocks
+#[derive(Debug, Clone)]
+pub struct Clipboard {
+    /// Position of the minimum corner of the cuboid, relative to the player that copied it
+    pub offset: (i64, i64, i64),
+    /// Size of the cuboid along each axis
+    pub size: (i64, i64, i64),
+    /// The blocks, indexed by `(x * size.1 + y) * size.2 + z`
+    pub blocks: Vec<BlockId>,
+}
+
+impl Clipboard {
+    /// Copy the cuboid between `min` and `max`, relative to the position `origin` of the player
+    pub fn copy(world: &World, min: BlockPos, max: BlockPos, origin: BlockPos) -> Self {
+        Self {
+            offset: (min.px - origin.px, min.py - origin.py, min.pz - origin.pz),
+            size: (
+                max.px - min.px + 1,
+                max.py - min.py + 1,
+                max.pz - min.pz + 1,
+            ),
+            blocks: cuboid(min, max).map(|pos| world.get_block(pos)).collect(),
+        }
+    }
+
+    /// Get a copy of the clipboard rotated by `quarter_turns` quarter turns around the vertical axis of the player
+    pub fn rotated(&self, quarter_turns: u32) -> Self {
+        let mut clipboard = self.clone();
+        for _ in 0..quarter_turns % 4 {
+            let (ox, oy, oz) = clipboard.offset;
+            let (sx, sy, sz) = clipboard.size;
+            // The block at (x, y, z) moves to (-z, y, x)
+            let mut blocks = vec![0; clipboard.blocks.len()];
+            for x in 0..sx {
+                for y in 0..sy {
+                    for z in 0..sz {
+                        let new_index = ((sz - 1 - z) * sy + y) * sx + x;
+                        blocks[new_index as usize] =
+                            clipboard.blocks[((x * sy + y) * sz + z) as usize];
+                    }
+                }
+            }
+            clipboard = Self {
+                offset: (-(oz + sz - 1), oy, ox),
+                size: (sz, sy, sx),
+                blocks,
+            };
+        }
+        clipboard
+    }
+
+    /// Get the changes that paste the clipboard relative to the position `origin` of the player
+    pub fn paste(&self, origin: BlockPos) -> BlockChanges {
+        let min = BlockPos::from((
+            origin.px + self.offset.0,
+            origin.py + self.offset.1,
+            origin.pz + self.offset.2,
+        ));
+        let max = BlockPos::from((
+            min.px + self.size.0 - 1,
+            min.py + self.size.1 - 1,
+            min.pz + self.size.2 - 1,
+        ));
+        cuboid(min, max).zip(self.blocks.iter().cloned()).collect()
+    }
+}
+
+/// The last edits of a player, to undo them. The oldest edits are forgotten.
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    /// The previous blocks of every edit, the most recent last
+    edits: VecDeque<BlockChanges>,
+}
+
+impl EditHistory {
+    /// Remember the previous blocks of an edit
+    pub fn push(&mut self, previous_blocks: BlockChanges) {
+        if previous_blocks.is_empty() {
+            return;
+        }
+        if self.edits.len() == UNDO_HISTORY_SIZE {
+            self.edits.pop_front();
+        }
+        self.edits.push_back(previous_blocks);
+    }
+
+    /// Get the changes that undo the last edit
+    pub fn pop(&mut self) -> Option<BlockChanges> {
+        self.edits.pop_back()
+    }
+}
@@ -1,8 +1,10 @@
+use crate::save::load_chunk;
+use crate::scheduler::{BlockTask, PendingTask};
 use voxel_rs_common::worker::{Worker, WorkerState};
 use voxel_rs_common::{
-    block::Block,
+    block::{entity::BlockEntity, Block},
     registry::Registry,
-    world::{Chunk, ChunkPos, WorldGenerator},
+    world::{BlockPos, Chunk, ChunkPos, WorldGenerator},
 };
 
 static WORLDGEN_QUEUE_SIZE: usize = 20;
@@ -35,11 +37,39 @@ impl WorldGenerationState {
     }
 }
 
-impl WorkerState<ChunkPos, Chunk> for WorldGenerationState {
-    fn compute(&mut self, pos: ChunkPos) -> Chunk {
-        self.world_generator
-            .generate_chunk(pos, &self.block_registry)
+/// A chunk that was loaded from the save, or generated if it was never saved
+pub struct GeneratedChunk {
+    pub chunk: Chunk,
+    pub block_entities: Vec<(BlockPos, BlockEntity)>,
+    pub block_tasks: Vec<PendingTask<(BlockPos, BlockTask)>>,
+    /// True if the chunk was loaded from the save
+    pub from_save: bool,
+}
+
+impl WorkerState<ChunkPos, GeneratedChunk> for WorldGenerationState {
+    fn compute(&mut self, pos: ChunkPos) -> GeneratedChunk {
+        match load_chunk(pos) {
+            Some(loaded) => GeneratedChunk {
+                chunk: loaded.chunk,
+                block_entities: loaded.block_entities,
+                block_tasks: loaded.block_tasks,
+                from_save: true,
+            },
+            None => {
+                let mut chunk = self
+                    .world_generator
+                    .generate_chunk(pos, &self.block_registry);
+                // The generators set the blocks one by one, the palettes may have unused blocks
+                chunk.compact();
+                GeneratedChunk {
+                    chunk,
+                    block_entities: Vec::new(),
+                    block_tasks: Vec::new(),
+                    from_save: false,
+                }
+            }
+        }
     }
 }
 
-pub type WorldGenerationWorker = Worker<ChunkPos, Chunk, WorldGenerationState>;
+pub type WorldGenerationWorker = Worker<ChunkPos, GeneratedChunk, WorldGenerationState>;